@@ -1,6 +1,37 @@
 use std::path::PathBuf;
-use zatboard::config::CoordinatorConfig;
+use std::time::Duration;
+use tokio::sync::watch;
+use zatboard::backup;
+use zatboard::config::{ConfigWatcher, CoordinatorConfig};
 use zatboard::coordinator::Coordinator;
+use zatboard::daemon;
+use zatboard::status;
+use zatboard::zingo_wrapper::ZingoClient;
+
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = sigterm.recv() => {},
+                }
+            }
+            Err(e) => {
+                eprintln!("⚠️  Could not install SIGTERM handler: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -15,6 +46,57 @@ async fn main() {
         }
     };
 
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--restore") {
+        let Some(archive) = args.get(position + 1) else {
+            eprintln!("Usage: zatboard-coordinator [--restore <backup.tar>] [--sessions]");
+            std::process::exit(1);
+        };
+        let known = Coordinator::state_files(&config.storage.database_file);
+        match backup::restore(&PathBuf::from(archive), &config.storage.data_dir, &known) {
+            Ok(manifest) => println!(
+                "♻️  Restored {} files from backup created at {}",
+                manifest.files.len(),
+                manifest.created_at
+            ),
+            Err(e) => {
+                eprintln!("Error restoring backup: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args.iter().any(|arg| arg == "--sessions") {
+        match Coordinator::stored_sessions(&config.storage.data_dir, config.sessions.store) {
+            Ok(snapshot) => {
+                for session in &snapshot.sessions {
+                    println!(
+                        "{} session={} reply={} created={} last_seen={} expires_at={} authenticated={} commands={}",
+                        session.user_id,
+                        session.session_id,
+                        session.reply_address,
+                        session.session_start,
+                        session.last_activity,
+                        session.expires_at,
+                        session.is_authenticated,
+                        session.commands_issued
+                    );
+                }
+                println!(
+                    "{} verified users, {} sessions, {} pending challenges",
+                    snapshot.verified_users.len(),
+                    snapshot.sessions.len(),
+                    snapshot.challenges.values().map(Vec::len).sum::<usize>()
+                );
+                return;
+            }
+            Err(e) => {
+                eprintln!("Error reading sessions: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     println!("Configuration loaded from: {}", config_path.display());
     println!("Data directory: {}", config.storage.data_dir.display());
     println!(
@@ -23,13 +105,19 @@ async fn main() {
     );
     println!("Fees enabled: {}", config.fees.enabled);
 
-    let mut coordinator = Coordinator::new_with_options(
-        3600,
+    let wallet = ZingoClient::new(
         config.storage.data_dir.clone(),
         config.network.zingo_server.clone(),
+    )
+    .with_retry(config.retry.clone());
+    let mut coordinator = Coordinator::with_wallet(
+        config.sessions.timeout_secs,
+        config.storage.data_dir.clone(),
+        wallet,
         config.storage.database_file.clone(),
         config.storage.cache_ttl_secs,
     );
+    coordinator.apply_config(&config);
 
     if config.api.enable_json_rpc {
         println!(
@@ -37,7 +125,7 @@ async fn main() {
             config.api.bind_address, config.api.bind_port
         );
         let rpc_coordinator = Coordinator::new_with_options(
-            3600,
+            config.sessions.timeout_secs,
             config.storage.data_dir.clone(),
             config.network.zingo_server.clone(),
             config.storage.database_file.clone(),
@@ -56,31 +144,41 @@ async fn main() {
         });
     }
 
-    println!("Coordinator ready. Aggressive polling enabled for low latency...");
+    let (stop_tx, stop_rx) = watch::channel(false);
 
-    loop {
-        match coordinator.poll_for_new_messages() {
-            Ok(messages) => {
-                if messages.is_empty() {
-                    std::thread::sleep(std::time::Duration::from_secs(5));
-                    continue;
-                }
-
-                for message in messages {
-                    match coordinator.process_and_respond(&message) {
-                        Ok(()) => println!("📤 Message processed successfully"),
-                        Err(e) => eprintln!("❌ Error processing message: {}", e),
-                    }
-                }
+    if config.status.enabled {
+        let bound = config
+            .status
+            .socket_addr()
+            .and_then(|addr| status::bind(addr, coordinator.status_handle(), stop_rx.clone()));
+        match bound {
+            Ok((addr, server)) => {
+                println!("Status endpoint listening on http://{}", addr);
+                tokio::spawn(server);
             }
             Err(e) => {
-                eprintln!("⚠️  Error polling messages: {}", e);
-                std::thread::sleep(std::time::Duration::from_secs(5));
+                eprintln!("Error starting status endpoint: {}", e);
+                std::process::exit(1);
             }
         }
+    }
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        println!("🛑 Shutdown requested, finishing in-flight work...");
+        let _ = stop_tx.send(true);
+    });
+
+    println!("Coordinator ready. Aggressive polling enabled for low latency...");
 
-        std::thread::sleep(std::time::Duration::from_secs(
-            config.network.polling_interval_secs,
-        ));
+    let polling_interval = Duration::from_secs(config.network.polling_interval_secs);
+    let config_watcher = ConfigWatcher::new(config_path);
+    match daemon::run_with_reload(coordinator, polling_interval, stop_rx, Some(config_watcher))
+        .await
+    {
+        Ok(()) => println!("👋 Coordinator state flushed, exiting"),
+        Err(e) => {
+            eprintln!("❌ Error flushing state on shutdown: {}", e);
+            std::process::exit(1);
+        }
     }
 }
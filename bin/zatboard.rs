@@ -1,16 +1,31 @@
 use std::env;
 use std::fs;
 use std::path::PathBuf;
-use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
-use zatboard::message::Message;
+use zatboard::builder::{self, MessageBuilder};
+use zatboard::commands::{BATCH_PREFIX, BATCH_SEPARATOR};
+use zatboard::e2e::{self, E2eKey};
+use zatboard::fee;
+use zatboard::handshake::{self, Features};
+use zatboard::identity::{IdentityKey, IDENTITY_KEY_FILE};
+use zatboard::memo_decoder::MAX_MEMO_SIZE;
+use zatboard::message::{self, Message};
+use zatboard::priority::MAX_PRIORITY;
+use zatboard::response::{self, Ack, Envelope, Status};
+use zatboard::shard;
+use zatboard::signing;
+use zatboard::tokenizer;
+use zatboard::tx_status::TxStatus;
 use zatboard::zingo_wrapper::ZingoClient;
 
 struct CliConfig {
     data_dir: PathBuf,
     server: String,
+    json: bool,
+    max_fee: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
@@ -19,6 +34,22 @@ struct ClientState {
     reply_address: Option<String>,
     conversation_id: Option<String>,
     participant_id: Option<String>,
+    #[serde(default)]
+    coordinator_key: Option<String>,
+    #[serde(default)]
+    shard_routes: BTreeMap<String, String>,
+    #[serde(default)]
+    compression: Option<String>,
+    #[serde(default)]
+    features: Option<Features>,
+    #[serde(default)]
+    sent: Vec<SentCommand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct SentCommand {
+    txids: Vec<String>,
+    command: String,
 }
 
 enum UserCommand {
@@ -32,13 +63,28 @@ enum UserCommand {
     Auth {
         coordinator: String,
         challenge: String,
-        signature: String,
     },
     Command {
         coordinator: String,
         memo: String,
+        expires_in: Option<u64>,
+        priority: Option<u8>,
+    },
+    Guest {
+        coordinator: String,
+        reply_address: String,
+        memo: String,
+    },
+    Rekey {
+        coordinator: String,
+        new_reply_address: Option<String>,
     },
+    Keygen,
     Poll,
+    Threads,
+    Status {
+        txid: Option<String>,
+    },
 }
 
 impl CliConfig {
@@ -48,8 +94,17 @@ impl CliConfig {
             .unwrap_or_else(|_| PathBuf::from("./client_data"));
         let server =
             env::var("ZATBOARD_SERVER").unwrap_or_else(|_| "http://127.0.0.1:9067".to_string());
+        let json = env::var("ZATBOARD_WIRE").is_ok_and(|wire| wire == "json");
+        let max_fee = env::var("ZATBOARD_MAX_FEE")
+            .ok()
+            .and_then(|fee| fee.parse().ok());
 
-        Self { data_dir, server }
+        Self {
+            data_dir,
+            server,
+            json,
+            max_fee,
+        }
     }
 }
 
@@ -78,30 +133,10 @@ fn save_client_state(data_dir: &Path, state: &ClientState) -> Result<(), String>
     fs::write(state_path, raw).map_err(|e| format!("Failed to write client state: {}", e))
 }
 
-fn poll_with_retry(
-    client: &ZingoClient,
-    attempts: u8,
-    delay_ms: u64,
-) -> Result<Vec<Message>, String> {
-    let mut last_error = None;
-
-    for attempt in 1..=attempts.max(1) {
-        match client.poll_once() {
-            Ok(messages) => return Ok(messages),
-            Err(e) => {
-                last_error = Some(e);
-                if attempt < attempts.max(1) {
-                    std::thread::sleep(Duration::from_millis(delay_ms));
-                }
-            }
-        }
-    }
-
-    Err(last_error.unwrap_or_else(|| "Polling failed".to_string()))
-}
+const MAX_SENT_COMMANDS: usize = 50;
 
 fn usage() -> &'static str {
-    "ZatBoard User CLI\n\nCommands:\n  zatboard connect <coordinator_address>\n  zatboard register <coordinator_address> <reply_address>\n  zatboard auth <coordinator_address> <challenge> <signature>\n  zatboard command <coordinator_address> <memo_command>\n  zatboard poll\n\nEnvironment:\n  ZATBOARD_DATA_DIR  default ./client_data\n  ZATBOARD_SERVER    default http://127.0.0.1:9067"
+    "ZatBoard User CLI\n\nCommands:\n  zatboard keygen\n  zatboard connect <coordinator_address>\n  zatboard register <coordinator_address> <reply_address>\n  zatboard auth <coordinator_address> <challenge>\n  zatboard command [--expires <secs>] [--priority <0-9>] <coordinator_address> <memo_command>\n  zatboard guest <coordinator_address> <reply_address> <read_command>\n  zatboard rekey <coordinator_address> reply <new_reply_address>\n  zatboard rekey <coordinator_address> key\n  zatboard poll\n  zatboard threads\n  zatboard status [txid]\n\nEnvironment:\n  ZATBOARD_DATA_DIR  default ./client_data\n  ZATBOARD_SERVER    default http://127.0.0.1:9067\n  ZATBOARD_WIRE      compact (default) or json"
 }
 
 fn parse_cli(args: &[String]) -> Result<UserCommand, String> {
@@ -130,41 +165,105 @@ fn parse_cli(args: &[String]) -> Result<UserCommand, String> {
             })
         }
         "auth" => {
-            if args.len() != 5 {
-                return Err(
-                    "Usage: zatboard auth <coordinator_address> <challenge> <signature>"
-                        .to_string(),
-                );
+            if args.len() != 4 {
+                return Err("Usage: zatboard auth <coordinator_address> <challenge>".to_string());
             }
             Ok(UserCommand::Auth {
                 coordinator: args[2].clone(),
                 challenge: args[3].clone(),
-                signature: args[4].clone(),
             })
         }
         "command" => {
-            if args.len() < 4 {
+            let usage = "Usage: zatboard command [--expires <secs>] [--priority <0-9>] <coordinator_address> <memo_command>";
+            let mut rest = &args[2..];
+            let mut expires_in = None;
+            let mut priority = None;
+            while let [flag, value, remaining @ ..] = rest {
+                match flag.as_str() {
+                    "--expires" => {
+                        expires_in = Some(value.parse::<u64>().map_err(|_| usage.to_string())?)
+                    }
+                    "--priority" => {
+                        priority = Some(
+                            value
+                                .parse::<u8>()
+                                .ok()
+                                .filter(|p| *p <= MAX_PRIORITY)
+                                .ok_or_else(|| usage.to_string())?,
+                        )
+                    }
+                    _ => break,
+                }
+                rest = remaining;
+            }
+            if rest.len() < 2 {
+                return Err(usage.to_string());
+            }
+            Ok(UserCommand::Command {
+                coordinator: rest[0].clone(),
+                memo: builder::command_memo(&rest[1..]),
+                expires_in,
+                priority,
+            })
+        }
+        "guest" => {
+            if args.len() < 5 {
                 return Err(
-                    "Usage: zatboard command <coordinator_address> <memo_command>".to_string(),
+                    "Usage: zatboard guest <coordinator_address> <reply_address> <read_command>"
+                        .to_string(),
                 );
             }
-            Ok(UserCommand::Command {
+            Ok(UserCommand::Guest {
                 coordinator: args[2].clone(),
-                memo: args[3..].join(" "),
+                reply_address: args[3].clone(),
+                memo: builder::command_memo(&args[4..]),
             })
         }
+        "rekey" => match args.get(3).map(String::as_str) {
+            Some("reply") if args.len() == 5 => Ok(UserCommand::Rekey {
+                coordinator: args[2].clone(),
+                new_reply_address: Some(args[4].clone()),
+            }),
+            Some("key") if args.len() == 4 => Ok(UserCommand::Rekey {
+                coordinator: args[2].clone(),
+                new_reply_address: None,
+            }),
+            _ => Err(
+                "Usage: zatboard rekey <coordinator_address> reply <new_reply_address> | key"
+                    .to_string(),
+            ),
+        },
+        "keygen" => {
+            if args.len() != 2 {
+                return Err("Usage: zatboard keygen".to_string());
+            }
+            Ok(UserCommand::Keygen)
+        }
         "poll" => {
             if args.len() != 2 {
                 return Err("Usage: zatboard poll".to_string());
             }
             Ok(UserCommand::Poll)
         }
+        "threads" => {
+            if args.len() != 2 {
+                return Err("Usage: zatboard threads".to_string());
+            }
+            Ok(UserCommand::Threads)
+        }
+        "status" => match args.len() {
+            2 => Ok(UserCommand::Status { txid: None }),
+            3 => Ok(UserCommand::Status {
+                txid: Some(args[2].clone()),
+            }),
+            _ => Err("Usage: zatboard status [txid]".to_string()),
+        },
         _ => Err(usage().to_string()),
     }
 }
 
-fn sender_address(client: &ZingoClient) -> Result<String, String> {
-    let addresses = client.get_addresses()?;
+async fn sender_address(client: &ZingoClient) -> Result<String, String> {
+    let addresses = client.get_addresses_async().await?;
     addresses
         .into_iter()
         .find(|addr| !addr.trim().is_empty())
@@ -173,34 +272,384 @@ fn sender_address(client: &ZingoClient) -> Result<String, String> {
         })
 }
 
-fn build_register_memo(reply_address: &str) -> String {
-    format!("REGISTER:{}", reply_address)
+fn load_identity(data_dir: &Path) -> Result<IdentityKey, String> {
+    fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create client data dir: {}", e))?;
+    IdentityKey::load_or_create(data_dir.join(IDENTITY_KEY_FILE))
+}
+
+fn next_identity_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(format!("{}.next", IDENTITY_KEY_FILE))
+}
+
+fn promote_next_identity(data_dir: &Path) -> Result<(), String> {
+    let current = data_dir.join(IDENTITY_KEY_FILE);
+    fs::rename(
+        &current,
+        data_dir.join(format!("{}.prev", IDENTITY_KEY_FILE)),
+    )
+    .map_err(|e| format!("Failed to keep previous identity key: {}", e))?;
+    fs::rename(next_identity_path(data_dir), &current)
+        .map_err(|e| format!("Failed to install new identity key: {}", e))
+}
+
+fn signing_identity(state: &ClientState, data_dir: &Path) -> Result<Option<IdentityKey>, String> {
+    match &state.reply_address {
+        Some(_) if data_dir.join(IDENTITY_KEY_FILE).exists() => Ok(Some(load_identity(data_dir)?)),
+        _ => Ok(None),
+    }
+}
+
+fn sealing_key(state: &ClientState, data_dir: &Path) -> Result<Option<(E2eKey, String)>, String> {
+    match (&state.reply_address, &state.coordinator_key) {
+        (Some(_), Some(coordinator_key)) if data_dir.join(IDENTITY_KEY_FILE).exists() => Ok(Some(
+            (load_identity(data_dir)?.e2e_key(), coordinator_key.clone()),
+        )),
+        _ => Ok(None),
+    }
+}
+
+fn open_reply(state: &ClientState, data_dir: &Path, message: Message) -> Message {
+    if !e2e::is_encrypted(&message.memo_text) {
+        return message;
+    }
+    let opened = sealing_key(state, data_dir).and_then(|seal| match seal {
+        Some((key, coordinator_key)) => key.open(&coordinator_key, &message.memo_text),
+        None => Err("no coordinator key known yet".to_string()),
+    });
+    let memo_text = opened.unwrap_or_else(|e| format!("<cannot decrypt reply: {}>", e));
+    Message {
+        memo_text,
+        ..message
+    }
+}
+
+fn offered_coordinator_key(state: &ClientState, message: &Message, body: &str) -> Option<String> {
+    if state.coordinator.as_deref() != Some(message.sender_address.as_str()) {
+        return None;
+    }
+    if !["Registration successful!", "Already registered!"]
+        .iter()
+        .any(|prefix| body.starts_with(prefix))
+    {
+        return None;
+    }
+    body.split("CoordKey: ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+}
+
+fn check_reply_signature(state: &mut ClientState, message: &Message) -> (Message, &'static str) {
+    let (body, signature) = signing::split_signature(&message.memo_text);
+    let unsigned = Message {
+        memo_text: body.to_string(),
+        ..message.clone()
+    };
+    if signature.is_none() {
+        return (unsigned, "unsigned");
+    }
+
+    let recipient = state
+        .reply_address
+        .clone()
+        .unwrap_or_else(|| message.recipient_address.clone());
+    if state.coordinator_key.is_none() {
+        state.coordinator_key = offered_coordinator_key(state, message, body)
+            .filter(|key| signing::verify(key, &recipient, &message.memo_text).is_ok());
+    }
+    match &state.coordinator_key {
+        Some(key) => match signing::verify(key, &recipient, &message.memo_text) {
+            Ok(_) => (unsigned, "verified"),
+            Err(_) => (unsigned, "SIGNATURE INVALID"),
+        },
+        None => (unsigned, "unverified"),
+    }
 }
 
-fn build_auth_memo(challenge: &str) -> String {
-    format!("AUTH:{}", challenge)
+fn read_reply(
+    state: &mut ClientState,
+    data_dir: &Path,
+    message: &Message,
+) -> (Message, &'static str) {
+    let (msg, trust) = check_reply_signature(state, message);
+    let msg = open_reply(state, data_dir, msg).take_reply_ref();
+    learn_compression(state, &msg);
+    (msg, trust)
 }
 
-fn send_user_message(
+fn record_sent(state: &mut ClientState, command: &str, outputs: &[String]) {
+    let txids: Vec<String> = outputs
+        .iter()
+        .filter_map(|output| ZingoClient::parse_txid(output).ok())
+        .collect();
+    if txids.is_empty() {
+        return;
+    }
+    state.sent.push(SentCommand {
+        txids,
+        command: command.to_string(),
+    });
+    let excess = state.sent.len().saturating_sub(MAX_SENT_COMMANDS);
+    state.sent.drain(..excess);
+}
+
+fn render_statuses(sent: &[SentCommand], statuses: &BTreeMap<String, TxStatus>) -> String {
+    let mut lines = Vec::new();
+    for entry in sent {
+        lines.push(format!("> {}", entry.command));
+        for txid in &entry.txids {
+            let status = statuses.get(txid).copied().unwrap_or(TxStatus::Unknown);
+            lines.push(format!("  {} {}", txid, status.describe()));
+        }
+    }
+    lines.join("\n")
+}
+
+fn render_threads(sent: &[SentCommand], replies: &[(Message, &str)]) -> String {
+    let mut lines = Vec::new();
+    let mut threaded = vec![false; replies.len()];
+    for entry in sent {
+        let answers: Vec<usize> = (0..replies.len())
+            .filter(|&i| {
+                replies[i]
+                    .0
+                    .in_reply_to
+                    .as_deref()
+                    .is_some_and(|reference| {
+                        entry.txids.iter().any(|txid| txid.starts_with(reference))
+                    })
+            })
+            .collect();
+        lines.push(format!(
+            "> {} [{}]",
+            entry.command,
+            entry
+                .txids
+                .first()
+                .map_or("?", |txid| response::reply_ref(txid))
+        ));
+        if answers.is_empty() {
+            lines.push("  (no reply yet)".to_string());
+        }
+        for i in answers {
+            threaded[i] = true;
+            let (reply, trust) = &replies[i];
+            for (n, line) in render_message(reply).lines().enumerate() {
+                match n {
+                    0 => lines.push(format!("  ({}) {}", trust, line)),
+                    _ => lines.push(format!("    {}", line)),
+                }
+            }
+        }
+    }
+
+    let others: Vec<String> = (0..replies.len())
+        .filter(|&i| !threaded[i])
+        .map(|i| {
+            let (reply, trust) = &replies[i];
+            format!("  ({}) {}", trust, render_message(reply))
+        })
+        .collect();
+    if !others.is_empty() {
+        lines.push("Other messages:".to_string());
+        lines.extend(others);
+    }
+    if lines.is_empty() {
+        return "No threads yet.".to_string();
+    }
+    lines.join("\n")
+}
+
+fn render_message(message: &Message) -> String {
+    if let Some(ack) = Ack::decode(&message.memo_text) {
+        return format!("[ack] {}", ack.describe());
+    }
+    match Envelope::decode(&message.memo_text) {
+        Some(envelope) => {
+            let outcome = if envelope.status == Status::Ok {
+                "ok".to_string()
+            } else {
+                format!("error {}", envelope.status.code())
+            };
+            format!(
+                "[{}] {}{}\n{}",
+                outcome,
+                envelope.command,
+                if envelope.more { " (continued)" } else { "" },
+                envelope.payload
+            )
+        }
+        None => message.to_string(),
+    }
+}
+
+fn retransmit_hint(state: &ClientState, message: &Message) -> Option<String> {
+    let status = match Ack::decode(&message.memo_text) {
+        Some(Ack::Rejected(status)) => status,
+        Some(_) => return None,
+        None => Envelope::decode(&message.memo_text)?.status,
+    };
+    if status != Status::Corrupted {
+        return None;
+    }
+    let reference = message.in_reply_to.as_deref()?;
+    let sent = state
+        .sent
+        .iter()
+        .rev()
+        .find(|entry| entry.txids.iter().any(|txid| txid.starts_with(reference)))?;
+    Some(format!(
+        "`{}` arrived damaged and was not run; send it again",
+        sent.command
+    ))
+}
+
+fn learn_redirect(state: &mut ClientState, message: &Message) -> Option<String> {
+    let payload = match Envelope::decode(&message.memo_text) {
+        Some(envelope) => envelope.payload,
+        None => message.memo_text.clone(),
+    };
+    let (address, path) = shard::parse_redirect(&payload)?;
+    let prefix = shard::top_level(&path)?.to_string();
+    state.shard_routes.insert(prefix.clone(), address.clone());
+    Some(format!(
+        "{} is served by {}; commands under it will be sent there",
+        prefix, address
+    ))
+}
+
+fn learn_compression(state: &mut ClientState, message: &Message) {
+    let codec = message
+        .memo_text
+        .split("Compress: ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next());
+    if codec == Some(message::COMPRESSION_CODEC) {
+        state.compression = codec.map(str::to_string);
+    }
+}
+
+fn learn_features<'a>(state: &mut ClientState, replies: impl IntoIterator<Item = &'a Message>) {
+    let mut threads: BTreeMap<Option<&str>, (String, String)> = BTreeMap::new();
+    for reply in replies {
+        let (command, payload) = match Envelope::decode(&reply.memo_text) {
+            Some(envelope) => (envelope.command, envelope.payload),
+            None => (String::new(), reply.memo_text.clone()),
+        };
+        let thread = threads
+            .entry(reply.in_reply_to.as_deref())
+            .or_insert_with(|| (command, String::new()));
+        thread.1.push_str(&payload);
+    }
+    for (command, text) in threads.values() {
+        let caps_reply = command == "caps" && text.starts_with(handshake::CAPS_PREFIX);
+        if !caps_reply && !text.contains("Registration successful!") {
+            continue;
+        }
+        if let Some(mut features) = Features::parse(text) {
+            if features.supports_compression(message::COMPRESSION_CODEC) {
+                state.compression = Some(message::COMPRESSION_CODEC.to_string());
+            }
+            if features.commands.is_empty() {
+                if let Some(known) = &state.features {
+                    features.commands = known.commands.clone();
+                }
+            }
+            state.features = Some(features);
+        }
+    }
+}
+
+fn coordinator_offers(state: &ClientState, check: impl Fn(&Features) -> bool) -> bool {
+    state.features.as_ref().is_none_or(check)
+}
+
+fn check_features(state: &ClientState, memo: &str) -> Result<(), String> {
+    let Some(features) = &state.features else {
+        return Ok(());
+    };
+    features.negotiate()?;
+    let commands = match memo.trim_start().strip_prefix(BATCH_PREFIX) {
+        Some(batch) => tokenizer::split_unquoted(batch, BATCH_SEPARATOR),
+        None => vec![memo],
+    };
+    for command in commands {
+        let Ok((name, _)) = tokenizer::split_first(command) else {
+            continue;
+        };
+        if !name.is_empty() && !features.supports_command(&name) {
+            return Err(format!(
+                "The coordinator does not offer {}; it supports: {}. Send caps again if it was upgraded",
+                name,
+                features.commands.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn route_command<'a>(state: &'a ClientState, coordinator: &'a str, memo: &str) -> &'a str {
+    memo.split_whitespace()
+        .find(|token| token.starts_with('/'))
+        .and_then(shard::top_level)
+        .and_then(|prefix| state.shard_routes.get(prefix))
+        .map(String::as_str)
+        .unwrap_or(coordinator)
+}
+
+async fn send_user_message(
     client: &ZingoClient,
-    from: String,
-    coordinator: &str,
-    memo: String,
-    signature: Option<String>,
-) -> Result<String, String> {
-    let mut message = Message::new(from, coordinator.to_string(), memo);
-    message.signature = signature;
-    client.send_memo(coordinator, 0, &message.memo_text)
+    state: &ClientState,
+    max_fee: Option<u64>,
+    message: MessageBuilder<'_>,
+) -> Result<Vec<String>, String> {
+    let recipient = message.recipient().to_string();
+    let mut message = message.compress(state.compression.is_some());
+    if let Some(features) = state.features.as_ref().filter(|f| f.max_memo > 0) {
+        message = message.max_memo_len(features.max_memo.min(MAX_MEMO_SIZE));
+    }
+    let memos = message.build_memos()?;
+    if let Some(features) = state.features.as_ref().filter(|f| f.max_fragments > 0) {
+        if memos.len() > features.max_fragments {
+            return Err(format!(
+                "Message too long: needs {} memos but the coordinator accepts at most {}",
+                memos.len(),
+                features.max_fragments
+            ));
+        }
+    }
+    let fees = memos
+        .iter()
+        .map(|memo| fee::check_fee(&[memo.len()], max_fee))
+        .collect::<Result<Vec<u64>, String>>()?;
+    println!(
+        "Estimated network fee: {} zatoshis for {} memo(s)",
+        fees.iter().sum::<u64>(),
+        memos.len()
+    );
+    let mut txids = Vec::with_capacity(memos.len());
+    for memo in &memos {
+        txids.push(client.send_memo_async(&recipient, 0, memo, max_fee).await?);
+    }
+    Ok(txids)
 }
 
-fn run() -> Result<(), String> {
+async fn run() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
     let command = parse_cli(&args)?;
     let config = CliConfig::from_env();
+    let max_fee = config.max_fee;
     let client = ZingoClient::new(config.data_dir, config.server);
     let mut state = load_client_state(client.data_dir.as_path())?;
+    let json =
+        config.json && coordinator_offers(&state, |f| f.supports_format(handshake::FORMAT_JSON));
 
     match command {
+        UserCommand::Keygen => {
+            let identity = load_identity(client.data_dir.as_path())?;
+            println!("Identity public key: {}", identity.public_key_hex());
+            Ok(())
+        }
         UserCommand::Connect { coordinator } => {
             state.coordinator = Some(coordinator.clone());
             save_client_state(client.data_dir.as_path(), &state)?;
@@ -211,61 +660,208 @@ fn run() -> Result<(), String> {
             coordinator,
             reply_address,
         } => {
-            let sender = sender_address(&client)?;
-            let result = send_user_message(
-                &client,
-                sender,
-                &coordinator,
-                build_register_memo(&reply_address),
-                None,
-            )?;
+            let sender = sender_address(&client).await?;
+            let identity = load_identity(client.data_dir.as_path())?;
+            let public_key = identity.public_key_hex();
+            let message = MessageBuilder::new(sender, coordinator.as_str())
+                .register(&reply_address, Some(&public_key));
+            let result = send_user_message(&client, &state, max_fee, message.json(json)).await?;
 
             state.coordinator = Some(coordinator);
             state.reply_address = Some(reply_address);
             save_client_state(client.data_dir.as_path(), &state)?;
 
-            println!("{}", result.trim());
+            println!("{}", result.join("\n").trim());
             Ok(())
         }
         UserCommand::Auth {
             coordinator,
             challenge,
-            signature,
         } => {
-            let sender = sender_address(&client)?;
-            let result = send_user_message(
-                &client,
-                sender,
-                &coordinator,
-                build_auth_memo(&challenge),
-                Some(signature),
-            )?;
-            println!("{}", result.trim());
+            let reply_address = state
+                .reply_address
+                .clone()
+                .ok_or("No reply address saved. Run zatboard register first")?;
+            let identity = load_identity(client.data_dir.as_path())?;
+            let signature = identity.sign_auth(&reply_address, &challenge);
+            let sender = sender_address(&client).await?;
+            let message = MessageBuilder::new(sender, coordinator)
+                .auth(&challenge, &signature)
+                .reply_to(&reply_address);
+            let result = send_user_message(&client, &state, max_fee, message.json(json)).await?;
+            println!("{}", result.join("\n").trim());
             Ok(())
         }
-        UserCommand::Command { coordinator, memo } => {
-            let sender = sender_address(&client)?;
-            let result =
-                send_user_message(&client, sender, &coordinator, memo, Some("sig".to_string()))?;
-            println!("{}", result.trim());
+        UserCommand::Command {
+            coordinator,
+            memo,
+            expires_in,
+            priority,
+        } => {
+            check_features(&state, &memo)?;
+            let sender = sender_address(&client).await?;
+            let target = route_command(&state, &coordinator, &memo);
+            if target != coordinator {
+                println!("Following shard redirect to {}", target);
+            }
+            let identity = signing_identity(&state, client.data_dir.as_path())?;
+            let seal = if target == coordinator
+                && coordinator_offers(&state, Features::supports_encryption)
+            {
+                sealing_key(&state, client.data_dir.as_path())?
+            } else {
+                None
+            };
+            let mut message = MessageBuilder::new(sender, target).command(&memo);
+            if let Some(reply_address) = &state.reply_address {
+                message = message.reply_to(reply_address);
+            }
+            if let (Some(identity), Some(reply_address)) = (&identity, &state.reply_address) {
+                message = message.signed_by(identity, reply_address);
+            }
+            if let Some((key, coordinator_key)) = &seal {
+                message = message.encrypt_to(key, coordinator_key);
+            }
+            if let Some(secs) = expires_in {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                message = message.expires_at(now + secs);
+            }
+            if let Some(priority) = priority {
+                message = message.priority(priority);
+            }
+            let result = send_user_message(&client, &state, max_fee, message.json(json)).await?;
+            println!("{}", result.join("\n").trim());
+            record_sent(&mut state, &memo, &result);
+            save_client_state(client.data_dir.as_path(), &state)
+        }
+        UserCommand::Guest {
+            coordinator,
+            reply_address,
+            memo,
+        } => {
+            let sender = sender_address(&client).await?;
+            let message = MessageBuilder::new(sender, coordinator).guest(&reply_address, &memo);
+            let result = send_user_message(&client, &state, max_fee, message.json(json)).await?;
+            println!("{}", result.join("\n").trim());
+            Ok(())
+        }
+        UserCommand::Rekey {
+            coordinator,
+            new_reply_address,
+        } => {
+            if state.reply_address.is_none() {
+                return Err("No reply address saved. Run zatboard register first".to_string());
+            }
+            let data_dir = client.data_dir.as_path();
+            let words = match &new_reply_address {
+                Some(reply_address) => ["rekey", "reply", reply_address.as_str()].map(String::from),
+                None => {
+                    load_identity(data_dir)?;
+                    let next = IdentityKey::load_or_create(next_identity_path(data_dir))?;
+                    [
+                        "rekey".to_string(),
+                        "key".to_string(),
+                        next.public_key_hex(),
+                    ]
+                }
+            };
+            let sender = sender_address(&client).await?;
+            let identity = signing_identity(&state, data_dir)?;
+            let mut message = MessageBuilder::new(sender, coordinator).command_words(&words);
+            if let (Some(identity), Some(reply_address)) = (&identity, &state.reply_address) {
+                message = message.signed_by(identity, reply_address);
+            }
+            let result = send_user_message(&client, &state, max_fee, message.json(json)).await?;
+
+            match new_reply_address {
+                Some(reply_address) => {
+                    state.reply_address = Some(reply_address);
+                    save_client_state(data_dir, &state)?;
+                }
+                None => {
+                    promote_next_identity(data_dir)?;
+                    println!(
+                        "Now signing with {}; the old key is kept as {}.prev",
+                        load_identity(data_dir)?.public_key_hex(),
+                        IDENTITY_KEY_FILE
+                    );
+                }
+            }
+            println!("{}", result.join("\n").trim());
             Ok(())
         }
         UserCommand::Poll => {
             println!("Polling for new messages...");
-            let messages = poll_with_retry(&client, 3, 500)?;
+            let messages = client.poll_once_async().await?;
             if messages.is_empty() {
                 println!("No new messages.");
             }
-            for msg in messages {
-                println!("{}", msg);
+            let known = state.clone();
+            let replies: Vec<(Message, &str)> = messages
+                .iter()
+                .map(|msg| read_reply(&mut state, client.data_dir.as_path(), msg))
+                .collect();
+            learn_features(&mut state, replies.iter().map(|(msg, _)| msg));
+            for (msg, trust) in replies {
+                let thread = msg
+                    .in_reply_to
+                    .as_ref()
+                    .map(|reference| format!(" re {}", reference))
+                    .unwrap_or_default();
+                println!("({}{}) {}", trust, thread, render_message(&msg));
+                if let Some(note) = learn_redirect(&mut state, &msg) {
+                    println!("  -> {}", note);
+                }
+                if let Some(note) = retransmit_hint(&state, &msg) {
+                    println!("  -> {}", note);
+                }
+            }
+            if state != known {
+                save_client_state(client.data_dir.as_path(), &state)?;
+            }
+            Ok(())
+        }
+        UserCommand::Threads => {
+            let messages = client.poll_once_async().await?;
+            let known = state.clone();
+            let replies: Vec<(Message, &str)> = messages
+                .iter()
+                .map(|msg| read_reply(&mut state, client.data_dir.as_path(), msg))
+                .collect();
+            learn_features(&mut state, replies.iter().map(|(msg, _)| msg));
+            println!("{}", render_threads(&state.sent, &replies));
+            if state != known {
+                save_client_state(client.data_dir.as_path(), &state)?;
             }
             Ok(())
         }
+        UserCommand::Status { txid } => {
+            let sent = match txid {
+                Some(txid) => vec![SentCommand {
+                    txids: vec![txid],
+                    command: "transaction".to_string(),
+                }],
+                None => state.sent.clone(),
+            };
+            let txids: Vec<String> = sent.iter().flat_map(|entry| entry.txids.clone()).collect();
+            if txids.is_empty() {
+                println!("No sent commands to check.");
+                return Ok(());
+            }
+            let statuses = client.get_tx_statuses_async(&txids).await?;
+            let statuses: BTreeMap<String, TxStatus> = txids.into_iter().zip(statuses).collect();
+            println!("{}", render_statuses(&sent, &statuses));
+            Ok(())
+        }
     }
 }
 
-fn main() {
-    if let Err(e) = run() {
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
         eprintln!("{}", e);
         std::process::exit(1);
     }
@@ -304,7 +900,6 @@ mod tests {
             "auth".to_string(),
             "zs1coord".to_string(),
             "challenge".to_string(),
-            "signature".to_string(),
         ];
 
         let cmd = parse_cli(&args).unwrap();
@@ -312,16 +907,52 @@ mod tests {
             UserCommand::Auth {
                 coordinator,
                 challenge,
-                signature,
             } => {
                 assert_eq!(coordinator, "zs1coord");
                 assert_eq!(challenge, "challenge");
-                assert_eq!(signature, "signature");
             }
             _ => panic!("Expected auth command"),
         }
     }
 
+    #[test]
+    fn test_parse_command_expiry_and_priority() {
+        let args = |words: &[&str]| {
+            ["zatboard", "command"]
+                .iter()
+                .chain(words)
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        match parse_cli(&args(&[
+            "--priority",
+            "9",
+            "--expires",
+            "300",
+            "zs1coord",
+            "ls",
+            "/",
+        ]))
+        .unwrap()
+        {
+            UserCommand::Command {
+                coordinator,
+                memo,
+                expires_in,
+                priority,
+            } => {
+                assert_eq!(coordinator, "zs1coord");
+                assert_eq!(memo, "ls /");
+                assert_eq!((expires_in, priority), (Some(300), Some(9)));
+            }
+            _ => panic!("Expected command variant"),
+        }
+        assert!(parse_cli(&args(&["--priority", "10", "zs1coord", "ls"])).is_err());
+        assert!(parse_cli(&args(&["--expires", "soon", "zs1coord", "ls"])).is_err());
+        assert!(parse_cli(&args(&["--expires", "60", "zs1coord"])).is_err());
+    }
+
     #[test]
     fn test_parse_command_with_spaces() {
         let args = vec![
@@ -336,12 +967,68 @@ mod tests {
 
         let cmd = parse_cli(&args).unwrap();
         match cmd {
-            UserCommand::Command { coordinator, memo } => {
+            UserCommand::Command {
+                coordinator,
+                memo,
+                expires_in,
+                priority,
+            } => {
                 assert_eq!(coordinator, "zs1coord");
                 assert_eq!(memo, "chat /lobby hello world");
+                assert_eq!((expires_in, priority), (None, None));
             }
             _ => panic!("Expected command variant"),
         }
+
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            builder::command_memo(&words(&["cp", "/my notes.txt", "/backup"])),
+            "cp \"/my notes.txt\" /backup"
+        );
+        assert_eq!(
+            builder::command_memo(&words(&["touch '/a b.txt' hi"])),
+            "touch '/a b.txt' hi"
+        );
+    }
+
+    #[test]
+    fn test_parse_rekey_command() {
+        let args: Vec<String> = ["zatboard", "rekey", "zs1coord", "reply", "zs1new"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        match parse_cli(&args).unwrap() {
+            UserCommand::Rekey {
+                coordinator,
+                new_reply_address,
+            } => {
+                assert_eq!(coordinator, "zs1coord");
+                assert_eq!(new_reply_address.as_deref(), Some("zs1new"));
+            }
+            _ => panic!("Expected rekey command"),
+        }
+        assert!(parse_cli(&args[..4]).is_err());
+        let key_args: Vec<String> = ["zatboard", "rekey", "zs1coord", "key"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert!(matches!(
+            parse_cli(&key_args).unwrap(),
+            UserCommand::Rekey {
+                new_reply_address: None,
+                ..
+            }
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let old = load_identity(dir.path()).unwrap().public_key_hex();
+        let next = IdentityKey::load_or_create(next_identity_path(dir.path()))
+            .unwrap()
+            .public_key_hex();
+        promote_next_identity(dir.path()).unwrap();
+        assert_eq!(load_identity(dir.path()).unwrap().public_key_hex(), next);
+        assert_ne!(old, next);
+        assert!(!next_identity_path(dir.path()).exists());
     }
 
     #[test]
@@ -349,6 +1036,42 @@ mod tests {
         let args = vec!["zatboard".to_string(), "poll".to_string()];
         let cmd = parse_cli(&args).unwrap();
         assert!(matches!(cmd, UserCommand::Poll));
+        let args = vec!["zatboard".to_string(), "threads".to_string()];
+        assert!(matches!(parse_cli(&args).unwrap(), UserCommand::Threads));
+        let args = vec!["zatboard".to_string(), "status".to_string()];
+        assert!(matches!(
+            parse_cli(&args).unwrap(),
+            UserCommand::Status { txid: None }
+        ));
+    }
+
+    #[test]
+    fn test_status_lists_each_sent_transaction() {
+        let sent = vec![
+            SentCommand {
+                txids: vec!["aa11".to_string(), "bb22".to_string()],
+                command: "touch /big.txt".to_string(),
+            },
+            SentCommand {
+                txids: vec!["cc33".to_string()],
+                command: "ls /".to_string(),
+            },
+        ];
+        let statuses = BTreeMap::from([
+            (
+                "aa11".to_string(),
+                TxStatus::Confirmed {
+                    height: 90,
+                    confirmations: 11,
+                },
+            ),
+            ("bb22".to_string(), TxStatus::Mined { height: 99 }),
+            ("cc33".to_string(), TxStatus::Pending),
+        ]);
+        assert_eq!(
+            render_statuses(&sent, &statuses),
+            "> touch /big.txt\n  aa11 confirmed (11)\n  bb22 mined at 99\n> ls /\n  cc33 pending"
+        );
     }
 
     #[test]
@@ -360,14 +1083,336 @@ mod tests {
 
     #[test]
     fn test_build_register_memo() {
-        let memo = build_register_memo("zs1reply");
-        assert_eq!(memo, "REGISTER:zs1reply");
+        let memo = builder::register_memo("zs1reply", Some("ab12"));
+        assert_eq!(memo, "REGISTER:zs1reply ab12");
+    }
+
+    #[test]
+    fn test_parse_guest_command() {
+        let args = vec![
+            "zatboard".to_string(),
+            "guest".to_string(),
+            "zs1coord".to_string(),
+            "zs1me".to_string(),
+            "cat".to_string(),
+            "/rules.txt".to_string(),
+        ];
+        match parse_cli(&args).unwrap() {
+            UserCommand::Guest {
+                coordinator,
+                reply_address,
+                memo,
+            } => {
+                assert_eq!(coordinator, "zs1coord");
+                assert_eq!(
+                    builder::guest_memo(&reply_address, &memo),
+                    "GUEST:zs1me cat /rules.txt"
+                );
+            }
+            _ => panic!("Expected guest command"),
+        }
+        assert!(parse_cli(&args[..4]).is_err());
+    }
+
+    #[test]
+    fn test_commands_are_signed_once_registered() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = ClientState::default();
+        assert!(signing_identity(&state, dir.path()).unwrap().is_none());
+
+        let identity = load_identity(dir.path()).unwrap();
+        assert!(signing_identity(&state, dir.path()).unwrap().is_none());
+        state.reply_address = Some("zs1reply".to_string());
+        let signer = signing_identity(&state, dir.path()).unwrap().unwrap();
+        let signed = MessageBuilder::new("zs1me", "zs1coord")
+            .command("ls /")
+            .signed_by(&signer, "zs1reply")
+            .build()
+            .memo_text;
+        assert_eq!(
+            zatboard::identity::verify_command(&identity.public_key_hex(), "zs1reply", &signed)
+                .unwrap()
+                .0,
+            "ls /"
+        );
+        assert!(matches!(
+            parse_cli(&["zatboard".to_string(), "keygen".to_string()]),
+            Ok(UserCommand::Keygen)
+        ));
     }
 
     #[test]
     fn test_build_auth_memo() {
-        let memo = build_auth_memo("challenge");
-        assert_eq!(memo, "AUTH:challenge");
+        let memo = builder::auth_memo("challenge", "abcd");
+        assert_eq!(memo, "AUTH:challenge abcd");
+    }
+
+    #[test]
+    fn test_render_enveloped_reply() {
+        let reply = Message::new(
+            "zs1coordinator".to_string(),
+            "zs1reply".to_string(),
+            "ZB1 404 . cat /a.txt\nFile not found: /a.txt".to_string(),
+        );
+        assert_eq!(
+            render_message(&reply),
+            "[error 404] cat /a.txt\nFile not found: /a.txt"
+        );
+
+        let plain = Message::new(
+            "zs1coordinator".to_string(),
+            "zs1reply".to_string(),
+            "watch: created /a.txt".to_string(),
+        );
+        assert_eq!(render_message(&plain), plain.to_string());
+    }
+
+    #[test]
+    fn test_reply_signatures_are_checked() {
+        let signer = signing::ResponseSigner::generate();
+        let key = signer.public_key_hex();
+        let mut state = ClientState {
+            coordinator: Some("zs1coordinator".to_string()),
+            reply_address: Some("zs1reply".to_string()),
+            ..ClientState::default()
+        };
+        let reply =
+            |memo: String| Message::new("zs1coordinator".to_string(), "zs1reply".to_string(), memo);
+
+        let impostor = signing::ResponseSigner::generate();
+        let offer = |signer: &signing::ResponseSigner, text: &str| {
+            signer.sign(
+                "zs1reply",
+                &format!("{} CoordKey: {}", text, signer.public_key_hex()),
+            )
+        };
+        let from_elsewhere = Message::new(
+            "zs1attacker".to_string(),
+            "zs1reply".to_string(),
+            offer(&impostor, "Registration successful!"),
+        );
+        assert_eq!(
+            check_reply_signature(&mut state, &from_elsewhere).1,
+            "unverified"
+        );
+        check_reply_signature(&mut state, &reply(offer(&impostor, "chat: CoordKey")));
+        let forged = reply(signer.sign(
+            "zs1reply",
+            &format!(
+                "Registration successful! CoordKey: {}",
+                impostor.public_key_hex()
+            ),
+        ));
+        check_reply_signature(&mut state, &forged);
+        assert_eq!(state.coordinator_key, None);
+
+        let registered = reply(signer.sign(
+            "zs1reply",
+            &format!("Registration successful! CoordKey: {}", key),
+        ));
+        let (_, trust) = check_reply_signature(&mut state, &registered);
+        assert_eq!(trust, "verified");
+        assert_eq!(state.coordinator_key, Some(key));
+
+        let (body, trust) =
+            check_reply_signature(&mut state, &reply(signer.sign("zs1reply", "hi")));
+        assert_eq!((body.memo_text.as_str(), trust), ("hi", "verified"));
+
+        let spoofed = signing::ResponseSigner::generate().sign("zs1reply", "hi");
+        assert_eq!(
+            check_reply_signature(&mut state, &reply(spoofed)).1,
+            "SIGNATURE INVALID"
+        );
+        assert_eq!(
+            check_reply_signature(&mut state, &reply("hi".to_string())).1,
+            "unsigned"
+        );
+    }
+
+    #[test]
+    fn test_redirects_are_learned_and_followed() {
+        let mut state = ClientState::default();
+        let reply = Message::new(
+            "zs1coordinator".to_string(),
+            "zs1reply".to_string(),
+            "ZB1 200 . ls /video\nREDIRECT:zs1shard2:/video".to_string(),
+        );
+        assert_eq!(
+            learn_redirect(&mut state, &reply).unwrap(),
+            "/video is served by zs1shard2; commands under it will be sent there"
+        );
+        assert_eq!(
+            route_command(&state, "zs1primary", "cat /video/a.txt"),
+            "zs1shard2"
+        );
+        assert_eq!(
+            route_command(&state, "zs1primary", "cat /docs/a.txt"),
+            "zs1primary"
+        );
+        assert_eq!(route_command(&state, "zs1primary", "help"), "zs1primary");
+
+        let plain = Message::new(
+            "zs1coordinator".to_string(),
+            "zs1reply".to_string(),
+            "ZB1 200 . ls /\n(empty directory)".to_string(),
+        );
+        assert_eq!(learn_redirect(&mut state, &plain), None);
+    }
+
+    #[test]
+    fn test_threads_group_replies_under_their_commands() {
+        let mut state = ClientState::default();
+        record_sent(
+            &mut state,
+            "ls /",
+            &[r#"{"txids": ["aaaa1111bbbb2222cccc"]}"#.to_string()],
+        );
+        record_sent(
+            &mut state,
+            "cat /a.txt",
+            &["{\"txids\": [\"dddd3333eeee4444ffff\"]}".to_string()],
+        );
+        record_sent(&mut state, "rm /b", &["error: no funds".to_string()]);
+        assert_eq!(state.sent.len(), 2);
+
+        let reply = |memo: &str| {
+            Message::new(
+                "zs1coordinator".to_string(),
+                "zs1reply".to_string(),
+                memo.to_string(),
+            )
+            .take_reply_ref()
+        };
+        let replies = vec![
+            (
+                reply("RE:aaaa1111bbbb2222\nZB1 200 . ls /\na.txt"),
+                "verified",
+            ),
+            (reply("watch: created /a.txt"), "unsigned"),
+        ];
+        assert_eq!(
+            render_threads(&state.sent, &replies),
+            "> ls / [aaaa1111bbbb2222]\n  (verified) [ok] ls /\n    a.txt\n\
+             > cat /a.txt [dddd3333eeee4444]\n  (no reply yet)\n\
+             Other messages:\n  (unsigned) Message from zs1coordinator to zs1reply: watch: created /a.txt"
+        );
+        assert_eq!(render_threads(&[], &[]), "No threads yet.");
+
+        let acked = vec![(reply("RE:dddd3333eeee4444\nZBACK done 2"), "verified")];
+        assert!(render_threads(&state.sent, &acked).contains(
+            "> cat /a.txt [dddd3333eeee4444]\n  (verified) [ack] ran; 2 reply memos on the way"
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_commands_get_a_resend_hint() {
+        let mut state = ClientState::default();
+        record_sent(
+            &mut state,
+            "touch /a.txt hello",
+            &[r#"{"txids": ["aaaa1111bbbb2222cccc"]}"#.to_string()],
+        );
+        let reply = |memo: &str| {
+            Message::new(
+                "zs1coordinator".to_string(),
+                "zs1reply".to_string(),
+                memo.to_string(),
+            )
+            .take_reply_ref()
+        };
+
+        let damaged =
+            reply("RE:aaaa1111bbbb2222\nZB1 422 . touch /a.txt\nCorrupted memo: checksum mismatch");
+        assert_eq!(
+            retransmit_hint(&state, &damaged).unwrap(),
+            "`touch /a.txt hello` arrived damaged and was not run; send it again"
+        );
+        assert!(
+            retransmit_hint(&state, &reply("RE:aaaa1111bbbb2222\nZB1 200 . ls /\nok")).is_none()
+        );
+        assert!(retransmit_hint(&state, &reply("ZB1 422 . ?\nCorrupted memo")).is_none());
+        assert!(retransmit_hint(&state, &reply("RE:aaaa1111bbbb2222\nZBACK nack 422")).is_some());
+        assert!(retransmit_hint(&state, &reply("RE:aaaa1111bbbb2222\nZBACK seen")).is_none());
+    }
+
+    #[test]
+    fn test_compression_support_is_learned_from_registration() {
+        let mut state = ClientState::default();
+        let reply = |memo: &str| {
+            Message::new(
+                "zs1coordinator".to_string(),
+                "zs1reply".to_string(),
+                memo.to_string(),
+            )
+        };
+        learn_compression(&mut state, &reply("Registration successful! CoordKey: ab"));
+        assert_eq!(state.compression, None);
+        learn_compression(&mut state, &reply("Compress: brotli CoordKey: ab"));
+        assert_eq!(state.compression, None);
+        learn_compression(
+            &mut state,
+            &reply("Registration successful! Compress: zstd CoordKey: ab"),
+        );
+        assert_eq!(state.compression.as_deref(), Some("zstd"));
+    }
+
+    #[test]
+    fn test_features_are_learned_and_checked_before_sending() {
+        let mut state = ClientState::default();
+        assert!(check_features(&state, "anything /").is_ok());
+        assert!(coordinator_offers(&state, Features::supports_encryption));
+
+        let reply = |memo: &str| {
+            Message::new(
+                "zs1coordinator".to_string(),
+                "zs1reply".to_string(),
+                memo.to_string(),
+            )
+        };
+        learn_features(&mut state, [&reply("cat /notes.txt\nCaps: v=9 cmds=rm")]);
+        assert_eq!(state.features, None);
+        let mut pages = [
+            reply("ZB1 200 + REGISTER:zs1reply\nRegistration successful! ConvID: c PartID: p Caps: v=1 memo=400 frags=4 zip=zstd enc= fmt=zm1"),
+            reply("ZB1 200 . REGISTER:zs1reply\n Compress: zstd"),
+        ];
+        for page in pages.iter_mut() {
+            page.in_reply_to = Some("aaaa1111bbbb2222".to_string());
+        }
+        learn_features(&mut state, &pages);
+        let features = state.features.clone().unwrap();
+        assert_eq!((features.max_memo, features.max_fragments), (400, 4));
+        assert!(check_features(&state, "rm /x").is_ok());
+
+        learn_features(
+            &mut state,
+            [&reply("ZB1 200 . cat /caps.txt\nCaps: v=1 cmds=rm")],
+        );
+        assert!(state.features.as_ref().unwrap().commands.is_empty());
+        let mut caps = [
+            reply("ZB1 200 + caps\nCaps: v=1 memo=400 frags=4 zip=zstd enc= fmt=zm1 cmds=ls,c"),
+            reply("ZB1 200 . caps\nat"),
+        ];
+        for page in caps.iter_mut() {
+            page.in_reply_to = Some("cccc3333dddd4444".to_string());
+        }
+        learn_features(&mut state, &caps);
+        learn_features(&mut state, &pages);
+        assert_eq!(state.features.as_ref().unwrap().commands, vec!["ls", "cat"]);
+        assert_eq!(state.compression.as_deref(), Some("zstd"));
+        assert!(!coordinator_offers(&state, Features::supports_encryption));
+        assert!(!coordinator_offers(&state, |f| f.supports_format(handshake::FORMAT_JSON)));
+
+        assert!(check_features(&state, "ls /").is_ok());
+        assert!(check_features(&state, r#"batch cat "/a;b"; ls /"#).is_ok());
+        assert!(check_features(&state, "cat /a; rm /x").is_ok());
+        assert!(check_features(&state, "batch ls /; rm /x")
+            .unwrap_err()
+            .starts_with("The coordinator does not offer rm; it supports: ls, cat."));
+
+        state.features.as_mut().unwrap().protocols = vec![2];
+        assert!(check_features(&state, "ls /")
+            .unwrap_err()
+            .contains("only speaks up to v1"));
     }
 
     #[test]
@@ -384,6 +1429,14 @@ mod tests {
             reply_address: Some("zs1reply".to_string()),
             conversation_id: None,
             participant_id: None,
+            coordinator_key: None,
+            shard_routes: BTreeMap::from([("/video".to_string(), "zs1shard2".to_string())]),
+            compression: Some("zstd".to_string()),
+            features: Features::parse("Caps: v=1 memo=512 cmds=ls"),
+            sent: vec![SentCommand {
+                txids: vec!["aaaa1111".to_string()],
+                command: "ls /".to_string(),
+            }],
         };
 
         save_client_state(temp_dir.path(), &state).unwrap();
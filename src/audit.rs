@@ -0,0 +1,382 @@
+use crate::events::{Event, Subscriber};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 3;
+const MAX_FIELD_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    #[serde(default)]
+    pub seq: u64,
+    pub timestamp: u64,
+    pub sender: String,
+    pub txid: Option<String>,
+    pub command: String,
+    pub ok: bool,
+    pub result: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        timestamp: u64,
+        sender: &str,
+        txid: Option<&str>,
+        command: &str,
+        result: &Result<String, String>,
+    ) -> Self {
+        let (ok, text) = match result {
+            Ok(response) => (true, response.as_str()),
+            Err(e) => (false, e.as_str()),
+        };
+
+        AuditEntry {
+            seq: 0,
+            timestamp,
+            sender: sender.to_string(),
+            txid: txid.map(|t| t.to_string()),
+            command: truncate(command),
+            ok,
+            result: truncate(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthAuditEntry {
+    #[serde(default)]
+    pub seq: u64,
+    pub timestamp: u64,
+    pub kind: String,
+    pub user: String,
+    pub detail: String,
+}
+
+impl AuthAuditEntry {
+    pub fn from_event(timestamp: u64, event: &Event) -> Option<Self> {
+        let (user, detail) = match event {
+            Event::UserRegistered {
+                user,
+                reply_address,
+            } => (user, format!("reply={}", reply_address)),
+            Event::ChallengeIssued {
+                user,
+                reply_address,
+            } => (user, format!("reply={}", reply_address)),
+            Event::AuthSucceeded { user } => (user, String::new()),
+            Event::AuthFailed { user, reason } => (user, truncate(reason)),
+            Event::SessionExpired { user, session_id } => (user, format!("session={}", session_id)),
+            Event::CommandDelegated {
+                user,
+                issuer,
+                command,
+                path,
+                ok,
+            } => (
+                user,
+                format!(
+                    "issuer={} path={} ok={} command={}",
+                    issuer, path, ok, command
+                ),
+            ),
+            _ => return None,
+        };
+        Some(AuthAuditEntry {
+            seq: 0,
+            timestamp,
+            kind: event.name().to_string(),
+            user: user.clone(),
+            detail,
+        })
+    }
+}
+
+pub trait LogEntry: Serialize + DeserializeOwned + Clone {
+    fn seq(&self) -> u64;
+
+    fn with_seq(self, seq: u64) -> Self;
+}
+
+impl LogEntry for AuditEntry {
+    fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    fn with_seq(self, seq: u64) -> Self {
+        AuditEntry { seq, ..self }
+    }
+}
+
+impl LogEntry for AuthAuditEntry {
+    fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    fn with_seq(self, seq: u64) -> Self {
+        AuthAuditEntry { seq, ..self }
+    }
+}
+
+fn truncate(value: &str) -> String {
+    let first_line = value.lines().next().unwrap_or("");
+    if first_line.chars().count() > MAX_FIELD_CHARS || first_line.len() < value.len() {
+        let short: String = first_line.chars().take(MAX_FIELD_CHARS).collect();
+        format!("{}…", short)
+    } else {
+        first_line.to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog<E = AuditEntry> {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    next_seq: Arc<AtomicU64>,
+    entries: PhantomData<E>,
+}
+
+pub type AuthAuditLog = AuditLog<AuthAuditEntry>;
+
+impl<E: LogEntry> AuditLog<E> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let log = AuditLog {
+            path: path.as_ref().to_path_buf(),
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_files: DEFAULT_MAX_FILES,
+            next_seq: Arc::new(AtomicU64::new(1)),
+            entries: PhantomData,
+        };
+        let last_seq = log.recent(1).first().map(LogEntry::seq).unwrap_or(0);
+        log.next_seq.store(last_seq + 1, Ordering::SeqCst);
+        log
+    }
+
+    pub fn with_rotation(mut self, max_bytes: u64, max_files: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.max_files = max_files;
+        self
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate_if_needed(&self) -> Result<(), String> {
+        let size = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(()),
+        };
+        if size < self.max_bytes {
+            return Ok(());
+        }
+
+        if self.max_files == 0 {
+            return std::fs::remove_file(&self.path)
+                .map_err(|e| format!("Failed to truncate audit log: {}", e));
+        }
+
+        let _ = std::fs::remove_file(self.rotated_path(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(index + 1))
+                    .map_err(|e| format!("Failed to rotate audit log: {}", e))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))
+            .map_err(|e| format!("Failed to rotate audit log: {}", e))
+    }
+
+    pub fn append(&self, entry: &E) -> Result<(), String> {
+        self.rotate_if_needed()?;
+
+        let entry = entry
+            .clone()
+            .with_seq(self.next_seq.fetch_add(1, Ordering::SeqCst));
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| format!("Failed to serialize audit entry: {}", e))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open audit log: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write audit log: {}", e))
+    }
+
+    fn read_file(path: &Path) -> Vec<E> {
+        std::fs::read_to_string(path)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<E>(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn recent(&self, limit: usize) -> Vec<E> {
+        let mut entries = Self::read_file(&self.path);
+        let mut index = 1;
+        while entries.len() < limit && index <= self.max_files {
+            let mut older = Self::read_file(&self.rotated_path(index));
+            if older.is_empty() {
+                break;
+            }
+            older.extend(entries);
+            entries = older;
+            index += 1;
+        }
+
+        let skip = entries.len().saturating_sub(limit);
+        entries.split_off(skip)
+    }
+
+    pub fn all(&self) -> Vec<E> {
+        let mut entries = Vec::new();
+        for index in (1..=self.max_files).rev() {
+            entries.extend(Self::read_file(&self.rotated_path(index)));
+        }
+        entries.extend(Self::read_file(&self.path));
+        entries
+    }
+}
+
+impl Subscriber for AuditLog<AuditEntry> {
+    fn on_event(&mut self, event: &Event) {
+        if let Event::MessageProcessed {
+            sender,
+            txid,
+            memo,
+            result,
+        } = event
+        {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let entry = AuditEntry::new(timestamp, sender, txid.as_deref(), memo, result);
+            if let Err(e) = self.append(&entry) {
+                eprintln!("⚠️  Failed to write audit log: {}", e);
+            }
+        }
+    }
+}
+
+impl Subscriber for AuditLog<AuthAuditEntry> {
+    fn on_event(&mut self, event: &Event) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if let Some(entry) = AuthAuditEntry::from_event(timestamp, event) {
+            if let Err(e) = self.append(&entry) {
+                eprintln!("⚠️  Failed to write auth audit log: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, command: &str) -> AuditEntry {
+        AuditEntry::new(
+            timestamp,
+            "zs1user",
+            Some("tx1"),
+            command,
+            &Ok("done".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_append_and_recent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(temp_dir.path().join("audit.log"));
+        assert!(log.recent(5).is_empty());
+
+        log.append(&entry(1, "ls /")).unwrap();
+        log.append(&AuditEntry::new(
+            2,
+            "zs1user",
+            None,
+            "rm /x",
+            &Err("File not found\ndetails".to_string()),
+        ))
+        .unwrap();
+
+        let recent = log.recent(5);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].command, "ls /");
+        assert!(recent[0].ok);
+        assert!(!recent[1].ok);
+        assert_eq!(recent[1].result, "File not found…");
+        assert_eq!(log.recent(1)[0].timestamp, 2);
+        assert_eq!(log.recent(1)[0].seq, 2);
+
+        let reopened = AuditLog::new(temp_dir.path().join("audit.log"));
+        reopened.append(&entry(3, "pwd")).unwrap();
+        let seqs: Vec<u64> = reopened.all().iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_rotation_keeps_bounded_history() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("audit.log");
+        let log = AuditLog::new(&path).with_rotation(200, 2);
+
+        for i in 0..20 {
+            log.append(&entry(i, &format!("cat /file{}", i))).unwrap();
+        }
+
+        assert!(log.rotated_path(1).exists());
+        assert!(log.rotated_path(2).exists());
+        assert!(!log.rotated_path(3).exists());
+
+        let recent = log.recent(3);
+        let stamps: Vec<u64> = recent.iter().map(|e| e.timestamp).collect();
+        assert_eq!(stamps, vec![17, 18, 19]);
+        assert!(log.recent(100).len() < 20);
+    }
+
+    #[test]
+    fn test_auth_log_keeps_only_auth_events() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut log = AuthAuditLog::new(temp_dir.path().join("auth_audit.log"));
+
+        log.on_event(&Event::ChallengeIssued {
+            user: "zs1user".to_string(),
+            reply_address: "zs1reply".to_string(),
+        });
+        log.on_event(&Event::MessageProcessed {
+            sender: "zs1user".to_string(),
+            txid: None,
+            memo: "ls /".to_string(),
+            result: Ok("done".to_string()),
+        });
+        log.on_event(&Event::AuthFailed {
+            user: "zs1user".to_string(),
+            reason: "Authentication failed: bad signature".to_string(),
+        });
+
+        let entries = log.all();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, "challenge_issued");
+        assert_eq!(entries[0].detail, "reply=zs1reply");
+        assert_eq!(entries[1].seq, 2);
+        assert_eq!(entries[1].kind, "auth_failed");
+        assert!(entries[1].detail.contains("bad signature"));
+    }
+}
@@ -1,15 +1,107 @@
+use crate::challenge;
 use crate::message::Message;
-use crate::user_session::SessionManager;
-use sha2::{Digest, Sha256};
+use crate::user_session::{SessionManager, UserSession};
+use std::collections::HashSet;
+
+pub const BAN_WILDCARD: char = '*';
+pub const MIN_BAN_PREFIX_LEN: usize = 6;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BanList {
+    entries: HashSet<String>,
+}
+
+impl BanList {
+    pub fn new(entries: HashSet<String>) -> Self {
+        BanList { entries }
+    }
+
+    pub fn entries(&self) -> &HashSet<String> {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn parse_pattern(pattern: &str) -> Result<Option<&str>, String> {
+        match pattern.strip_suffix(BAN_WILDCARD) {
+            None if pattern.contains(BAN_WILDCARD) => Err(format!(
+                "Invalid ban pattern: {} may only end with {}",
+                pattern, BAN_WILDCARD
+            )),
+            None => Ok(None),
+            Some(prefix) if prefix.contains(BAN_WILDCARD) => Err(format!(
+                "Invalid ban pattern: {} may only end with {}",
+                pattern, BAN_WILDCARD
+            )),
+            Some(prefix) if prefix.chars().count() < MIN_BAN_PREFIX_LEN => Err(format!(
+                "Invalid ban pattern: prefixes need at least {} characters",
+                MIN_BAN_PREFIX_LEN
+            )),
+            Some(prefix) => Ok(Some(prefix)),
+        }
+    }
+
+    pub fn insert(&mut self, pattern: &str) -> Result<bool, String> {
+        Self::parse_pattern(pattern)?;
+        Ok(self.entries.insert(pattern.to_string()))
+    }
+
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        self.entries.remove(pattern)
+    }
+
+    pub fn pattern_matches(pattern: &str, address: &str) -> bool {
+        match pattern.strip_suffix(BAN_WILDCARD) {
+            Some(prefix) => address.starts_with(prefix),
+            None => pattern == address,
+        }
+    }
+
+    pub fn matching(&self, address: &str) -> Option<&str> {
+        if let Some(exact) = self.entries.get(address) {
+            return Some(exact);
+        }
+        self.entries
+            .iter()
+            .find(|pattern| {
+                pattern.ends_with(BAN_WILDCARD) && Self::pattern_matches(pattern, address)
+            })
+            .map(String::as_str)
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.matching(address).is_some()
+    }
+
+    pub fn sorted(&self) -> Vec<&str> {
+        let mut entries: Vec<&str> = self.entries.iter().map(String::as_str).collect();
+        entries.sort();
+        entries
+    }
+}
 
 pub struct AuthenticationFlow {
     pub session_manager: SessionManager,
+    challenge_secret: Vec<u8>,
 }
 
 impl AuthenticationFlow {
     pub fn new(session_timeout: u64) -> Self {
         AuthenticationFlow {
             session_manager: SessionManager::new(session_timeout),
+            challenge_secret: challenge::random_secret(),
+        }
+    }
+
+    pub fn set_challenge_secret(&mut self, secret: Option<&str>) {
+        if let Some(secret) = secret {
+            self.challenge_secret = secret.as_bytes().to_vec();
         }
     }
 
@@ -73,16 +165,11 @@ impl AuthenticationFlow {
             .unwrap()
             .as_secs();
 
-        let mut hasher = Sha256::new();
-        hasher.update(user_id.as_bytes());
-        hasher.update(timestamp.to_string().as_bytes());
-        hasher.update(b"zatboard_challenge");
-
-        format!("{:x}", hasher.finalize())[..16].to_string()
+        challenge::mint(&self.challenge_secret, user_id, timestamp)
     }
 
-    pub fn cleanup_expired_sessions(&mut self) {
-        self.session_manager.cleanup_expired_sessions();
+    pub fn cleanup_expired_sessions(&mut self) -> Vec<UserSession> {
+        self.session_manager.cleanup_expired_sessions()
     }
 }
 
@@ -99,6 +186,37 @@ mod tests {
 
         assert!(challenge.starts_with("AUTH_CHALLENGE:"));
         assert!(!auth.is_user_authenticated("zs1user123"));
+
+        auth.set_challenge_secret(Some("operator-secret"));
+        let challenge =
+            auth.initiate_authentication("zs1user123".to_string(), "zs1reply456".to_string());
+        let value = challenge.strip_prefix("AUTH_CHALLENGE:").unwrap();
+        assert!(
+            challenge::verify(b"operator-secret", "zs1user123", value, 600, u64::MAX / 2).is_err()
+        );
+        let now = value.split('-').nth(1).unwrap().parse::<u64>().unwrap();
+        assert!(challenge::verify(b"operator-secret", "zs1user123", value, 600, now).is_ok());
+    }
+
+    #[test]
+    fn test_ban_list_matches_addresses_and_prefixes() {
+        let mut bans = BanList::default();
+        assert!(bans.insert("zs1exact").unwrap());
+        assert!(!bans.insert("zs1exact").unwrap());
+        assert!(bans.insert("zs1spam*").unwrap());
+        assert!(bans.insert("zs1*").is_err());
+        assert!(bans.insert("zs1sp*m*").is_err());
+        assert!(bans.insert("zs1s*am").is_err());
+
+        assert!(bans.contains("zs1exact"));
+        assert!(!bans.contains("zs1exactly"));
+        assert_eq!(bans.matching("zs1spammer42"), Some("zs1spam*"));
+        assert!(!bans.contains("zs1friend"));
+        assert_eq!(bans.sorted(), vec!["zs1exact", "zs1spam*"]);
+
+        assert!(bans.remove("zs1spam*"));
+        assert!(!bans.contains("zs1spammer42"));
+        assert_eq!(bans.len(), 1);
     }
 
     #[test]
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+pub const BACKUP_VERSION: u32 = 1;
+const BACKUP_MANIFEST: &str = "backup.json";
+const BACKUPS_DIR: &str = "backups";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: u64,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    pub files: usize,
+    pub bytes: usize,
+}
+
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none()
+}
+
+pub fn backups_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(BACKUPS_DIR)
+}
+
+pub fn create(data_dir: &Path, files: &[String], now: u64) -> Result<BackupReport, String> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut included = Vec::new();
+
+    for name in files {
+        let path = data_dir.join(name);
+        if !path.exists() {
+            continue;
+        }
+        let contents =
+            std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o600);
+        header.set_mtime(now);
+        header.set_size(contents.len() as u64);
+        builder
+            .append_data(&mut header, name, contents.as_slice())
+            .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+        included.push(name.clone());
+    }
+
+    let manifest = BackupManifest {
+        version: BACKUP_VERSION,
+        created_at: now,
+        files: included,
+    };
+    let manifest_json = serde_json::to_vec(&manifest)
+        .map_err(|e| format!("Failed to serialize backup manifest: {}", e))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o600);
+    header.set_mtime(now);
+    header.set_size(manifest_json.len() as u64);
+    builder
+        .append_data(&mut header, BACKUP_MANIFEST, manifest_json.as_slice())
+        .map_err(|e| format!("Failed to add manifest to backup: {}", e))?;
+
+    let archive = builder
+        .into_inner()
+        .map_err(|e| format!("Failed to finish backup: {}", e))?;
+
+    let dir = backups_dir(data_dir);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create backups directory: {}", e))?;
+    let path = dir.join(format!("backup-{}.tar", now));
+    let tmp_path = path.with_extension("tar.tmp");
+    std::fs::write(&tmp_path, &archive).map_err(|e| format!("Failed to write backup: {}", e))?;
+    std::fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to replace backup: {}", e))?;
+
+    Ok(BackupReport {
+        path,
+        files: manifest.files.len(),
+        bytes: archive.len(),
+    })
+}
+
+pub fn restore(
+    archive_path: &Path,
+    data_dir: &Path,
+    known: &[String],
+) -> Result<BackupManifest, String> {
+    let data = std::fs::read(archive_path)
+        .map_err(|e| format!("Failed to read backup {}: {}", archive_path.display(), e))?;
+
+    let mut archive = tar::Archive::new(data.as_slice());
+    let mut manifest: Option<BackupManifest> = None;
+    let mut contents: HashMap<String, Vec<u8>> = HashMap::new();
+
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("Invalid backup: {}", e))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("Invalid backup entry: {}", e))?;
+        let name = entry
+            .path()
+            .map_err(|e| format!("Invalid backup path: {}", e))?
+            .to_string_lossy()
+            .to_string();
+        if !is_plain_name(&name) {
+            return Err(format!("Unsafe path in backup: {}", name));
+        }
+
+        let mut bytes = Vec::new();
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("Failed to read {} from backup: {}", name, e))?;
+        if name == BACKUP_MANIFEST {
+            manifest = Some(
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| format!("Invalid backup manifest: {}", e))?,
+            );
+        } else {
+            contents.insert(name, bytes);
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| "Backup has no manifest".to_string())?;
+    if manifest.version > BACKUP_VERSION {
+        return Err(format!(
+            "Backup version {} is newer than supported version {}",
+            manifest.version, BACKUP_VERSION
+        ));
+    }
+    for name in &manifest.files {
+        if !known.contains(name) {
+            return Err(format!("Backup contains unexpected file: {}", name));
+        }
+        if !contents.contains_key(name) {
+            return Err(format!("Backup is missing {}", name));
+        }
+    }
+
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    for name in known {
+        let path = data_dir.join(name);
+        match contents.get(name).filter(|_| manifest.files.contains(name)) {
+            Some(bytes) => {
+                let tmp_path = data_dir.join(format!("{}.restore", name));
+                std::fs::write(&tmp_path, bytes)
+                    .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+                std::fs::rename(&tmp_path, &path)
+                    .map_err(|e| format!("Failed to replace {}: {}", name, e))?;
+            }
+            None if path.exists() => std::fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove stale {}: {}", name, e))?,
+            None => {}
+        }
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known() -> Vec<String> {
+        vec![
+            "state.json".to_string(),
+            "fs.db".to_string(),
+            "chat.json".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_backup_roundtrip_replaces_state() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("state.json"), "{\"users\":1}").unwrap();
+        std::fs::write(source.path().join("fs.db"), [0u8, 1, 2, 255]).unwrap();
+
+        let report = create(source.path(), &known(), 42).unwrap();
+        assert_eq!(report.files, 2);
+        assert!(report.path.ends_with("backups/backup-42.tar"));
+
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("chat.json"), "stale").unwrap();
+        std::fs::write(target.path().join("state.json"), "old").unwrap();
+
+        let manifest = restore(&report.path, target.path(), &known()).unwrap();
+        assert_eq!(manifest.version, BACKUP_VERSION);
+        assert_eq!(manifest.created_at, 42);
+        assert_eq!(
+            std::fs::read_to_string(target.path().join("state.json")).unwrap(),
+            "{\"users\":1}"
+        );
+        assert_eq!(
+            std::fs::read(target.path().join("fs.db")).unwrap(),
+            vec![0u8, 1, 2, 255]
+        );
+        assert!(!target.path().join("chat.json").exists());
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("state.json"), "{}").unwrap();
+        let report = create(dir.path(), &known(), 1).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        assert!(restore(&report.path, target.path(), &["fs.db".to_string()])
+            .unwrap_err()
+            .contains("unexpected file"));
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let manifest = b"{\"version\":99,\"created_at\":0,\"files\":[]}";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        builder
+            .append_data(&mut header, BACKUP_MANIFEST, &manifest[..])
+            .unwrap();
+        let future = dir.path().join("future.tar");
+        std::fs::write(&future, builder.into_inner().unwrap()).unwrap();
+        assert!(restore(&future, target.path(), &known())
+            .unwrap_err()
+            .contains("newer than supported"));
+
+        assert!(!is_plain_name("../state.json"));
+        assert!(!is_plain_name("nested/state.json"));
+        assert!(is_plain_name("state.json"));
+    }
+}
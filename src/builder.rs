@@ -0,0 +1,305 @@
+use crate::e2e::{self, E2eKey};
+use crate::guest::GUEST_PREFIX;
+use crate::identity::IdentityKey;
+use crate::memo_decoder::{self, MAX_MEMO_SIZE};
+use crate::message::Message;
+use crate::ownership::PROVE_PREFIX;
+use crate::tokenizer;
+
+pub const REGISTER_PREFIX: &str = "REGISTER:";
+pub const AUTH_PREFIX: &str = "AUTH:";
+
+pub fn register_memo(reply_address: &str, public_key: Option<&str>) -> String {
+    match public_key {
+        Some(public_key) => format!("{}{} {}", REGISTER_PREFIX, reply_address, public_key),
+        None => format!("{}{}", REGISTER_PREFIX, reply_address),
+    }
+}
+
+pub fn auth_memo(challenge: &str, signature: &str) -> String {
+    format!("{}{} {}", AUTH_PREFIX, challenge, signature)
+}
+
+pub fn guest_memo(reply_address: &str, command: &str) -> String {
+    format!("{}{} {}", GUEST_PREFIX, reply_address, command)
+}
+
+pub fn prove_memo(code: &str) -> String {
+    format!("{}{}", PROVE_PREFIX, code)
+}
+
+pub fn command_memo<S: AsRef<str>>(words: &[S]) -> String {
+    match words {
+        [memo] => memo.as_ref().to_string(),
+        _ => words
+            .iter()
+            .map(|word| tokenizer::quote(word.as_ref()))
+            .collect::<Vec<String>>()
+            .join(" "),
+    }
+}
+
+pub struct MessageBuilder<'a> {
+    sender: String,
+    recipient: String,
+    memo: String,
+    signer: Option<(&'a IdentityKey, &'a str)>,
+    seal: Option<(&'a E2eKey, &'a str)>,
+    reply_to: Option<String>,
+    expires_at: Option<u64>,
+    priority: Option<u8>,
+    compress: bool,
+    json: bool,
+    checksum: bool,
+    max_memo_len: usize,
+}
+
+impl<'a> MessageBuilder<'a> {
+    pub fn new(sender: impl Into<String>, recipient: impl Into<String>) -> Self {
+        MessageBuilder {
+            sender: sender.into(),
+            recipient: recipient.into(),
+            memo: String::new(),
+            signer: None,
+            seal: None,
+            reply_to: None,
+            expires_at: None,
+            priority: None,
+            compress: false,
+            json: false,
+            checksum: true,
+            max_memo_len: MAX_MEMO_SIZE,
+        }
+    }
+
+    pub fn recipient(&self) -> &str {
+        &self.recipient
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = memo.into();
+        self
+    }
+
+    pub fn register(self, reply_address: &str, public_key: Option<&str>) -> Self {
+        self.memo(register_memo(reply_address, public_key))
+    }
+
+    pub fn auth(self, challenge: &str, signature: &str) -> Self {
+        self.memo(auth_memo(challenge, signature))
+    }
+
+    pub fn guest(self, reply_address: &str, command: &str) -> Self {
+        self.memo(guest_memo(reply_address, command))
+    }
+
+    pub fn prove(self, code: &str) -> Self {
+        self.memo(prove_memo(code))
+    }
+
+    pub fn command(self, command: &str) -> Self {
+        self.memo(command)
+    }
+
+    pub fn command_words<S: AsRef<str>>(self, words: &[S]) -> Self {
+        self.memo(command_memo(words))
+    }
+
+    pub fn batch<S: AsRef<str>>(self, commands: &[S]) -> Self {
+        let commands: Vec<String> = commands
+            .iter()
+            .map(|command| memo_decoder::sanitize_text(command.as_ref()))
+            .collect();
+        self.memo(memo_decoder::encode_batch(&commands))
+    }
+
+    pub fn reply_to(mut self, reply_address: &str) -> Self {
+        self.reply_to = Some(reply_address.to_string());
+        self
+    }
+
+    pub fn signed_by(mut self, identity: &'a IdentityKey, reply_address: &'a str) -> Self {
+        self.signer = Some((identity, reply_address));
+        self.reply_to(reply_address)
+    }
+
+    pub fn encrypt_to(mut self, key: &'a E2eKey, peer_public_key_hex: &'a str) -> Self {
+        self.seal = Some((key, peer_public_key_hex));
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn max_memo_len(mut self, max_memo_len: usize) -> Self {
+        self.max_memo_len = max_memo_len;
+        self
+    }
+
+    pub fn build(&self) -> Message {
+        let memo = memo_decoder::sanitize_text(&self.memo);
+        let memo = match self.signer {
+            Some((identity, reply_address)) => identity.sign_command(reply_address, &memo),
+            None => memo,
+        };
+        let mut message = Message::new(self.sender.clone(), self.recipient.clone(), memo);
+        message.expires_at = self.expires_at;
+        message.priority = self.priority;
+        message.reply_to = self.reply_to.clone();
+        message
+    }
+
+    pub fn build_memos(&self) -> Result<Vec<String>, String> {
+        let mut message = self.build();
+        message.memo_text = if self.json {
+            message.to_json_memo()?
+        } else {
+            message.compact_if_smaller(self.compress)
+        };
+
+        let mut max_len = self.max_memo_len;
+        if self.checksum {
+            max_len = max_len.saturating_sub(memo_decoder::CHECKSUM_OVERHEAD);
+        }
+        if self.seal.is_some() {
+            max_len = e2e::plaintext_budget(max_len);
+        }
+
+        message
+            .split_for_memo(MAX_MEMO_SIZE.saturating_sub(max_len))?
+            .into_iter()
+            .map(|fragment| {
+                let memo = match self.seal {
+                    Some((key, peer)) => key.seal(peer, &fragment.memo_text)?,
+                    None => fragment.memo_text,
+                };
+                Ok(if self.checksum {
+                    memo_decoder::append_checksum(&memo)
+                } else {
+                    memo
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity;
+    use crate::memo_decoder::{ChecksumConfig, Fragment};
+
+    #[test]
+    fn test_memo_templates() {
+        assert_eq!(
+            register_memo("zs1reply", Some("ab12")),
+            "REGISTER:zs1reply ab12"
+        );
+        assert_eq!(register_memo("zs1reply", None), "REGISTER:zs1reply");
+        assert_eq!(auth_memo("challenge", "abcd"), "AUTH:challenge abcd");
+        assert_eq!(
+            guest_memo("zs1me", "cat /rules.txt"),
+            "GUEST:zs1me cat /rules.txt"
+        );
+        assert_eq!(prove_memo("00ff"), "PROVE:00ff");
+        assert_eq!(
+            command_memo(&["cp", "/my notes.txt", "/backup"]),
+            "cp \"/my notes.txt\" /backup"
+        );
+        assert_eq!(command_memo(&["ls /docs"]), "ls /docs");
+        let batch = MessageBuilder::new("zs1me", "zs1coord")
+            .batch(&["touch /cafe\u{301}.txt", "ls /"])
+            .build();
+        assert_eq!(
+            memo_decoder::decode_batch(&batch.memo_text),
+            Some(Ok(vec!["touch /café.txt".to_string(), "ls /".to_string()]))
+        );
+
+        let plain = MessageBuilder::new("zs1me", "zs1coord")
+            .register("zs1reply", None)
+            .checksum(false)
+            .build_memos()
+            .unwrap();
+        assert_eq!(plain, vec!["REGISTER:zs1reply"]);
+
+        let json = MessageBuilder::new("zs1me", "zs1coord")
+            .command("ls /")
+            .expires_at(1700000600)
+            .json(true)
+            .checksum(false)
+            .build_memos()
+            .unwrap();
+        assert_eq!(
+            json,
+            vec![r#"ZBJ:{"expires_at":1700000600,"memo":"ls /","v":1}"#]
+        );
+    }
+
+    #[test]
+    fn test_builder_signs_fragments_and_seals_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = IdentityKey::load_or_create(dir.path().join("me.key")).unwrap();
+        let coordinator = IdentityKey::load_or_create(dir.path().join("coord.key")).unwrap();
+        let coordinator_public = coordinator.public_key_hex();
+        let key = identity.e2e_key();
+
+        let content = "a line of text\n".repeat(80);
+        let builder = MessageBuilder::new("zs1me", "zs1coord")
+            .command_words(&["touch", "/notes.txt", &content])
+            .signed_by(&identity, "zs1reply")
+            .priority(7);
+        let message = builder.build();
+        assert_eq!(message.priority, Some(7));
+        assert_eq!(message.reply_to.as_deref(), Some("zs1reply"));
+        assert_eq!(
+            identity::verify_command(&identity.public_key_hex(), "zs1reply", &message.memo_text)
+                .unwrap()
+                .0,
+            command_memo(&["touch", "/notes.txt", &content])
+        );
+
+        let memos = builder
+            .encrypt_to(&key, &coordinator_public)
+            .build_memos()
+            .unwrap();
+        assert!(memos.len() > 1);
+        let mut parts = Vec::new();
+        for memo in &memos {
+            assert!(memo.len() <= MAX_MEMO_SIZE, "{}", memo.len());
+            let body = memo_decoder::verify_checksum(memo, &ChecksumConfig::default()).unwrap();
+            let opened = coordinator
+                .e2e_key()
+                .open(&identity.public_key_hex(), body)
+                .unwrap();
+            let fragment = Fragment::parse(&opened).unwrap().unwrap();
+            parts.push(fragment.body.to_string());
+        }
+        let wire = Message::new("zs1me".to_string(), "zs1coord".to_string(), parts.concat());
+        let decoded = wire.decode_memo().unwrap().unwrap();
+        assert_eq!(decoded.memo_text, message.memo_text);
+        assert_eq!(decoded.priority, Some(7));
+        assert_eq!(decoded.reply_to.as_deref(), Some("zs1reply"));
+    }
+}
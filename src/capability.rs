@@ -0,0 +1,427 @@
+use crate::commands::CommandClass;
+use crate::crypto;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const CAPABILITY_PREFIX: &str = "CAP:";
+pub const MAX_TOKENS_PER_ISSUER: usize = 50;
+pub const MAX_TTL_SECS: u64 = 30 * 24 * 3600;
+pub const DELEGABLE_COMMANDS: &[&str] = &[
+    "ls", "stat", "du", "cat", "head", "tail", "diff", "mkdir", "rm", "touch", "write", "cp", "ln",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapabilityOp {
+    Read,
+    Write,
+}
+
+impl CapabilityOp {
+    pub fn parse(name: &str) -> Result<CapabilityOp, String> {
+        match name {
+            "read" => Ok(CapabilityOp::Read),
+            "write" => Ok(CapabilityOp::Write),
+            other => Err(format!("Invalid capability: {}. Use read or write", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CapabilityOp::Read => "read",
+            CapabilityOp::Write => "write",
+        }
+    }
+
+    fn allows(&self, class: CommandClass) -> bool {
+        match self {
+            CapabilityOp::Read => class == CommandClass::Read,
+            CapabilityOp::Write => matches!(
+                class,
+                CommandClass::Read | CommandClass::Write | CommandClass::Upload
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Authority {
+    Own,
+    Delegated { issuer: String, path: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Capability {
+    pub issuer: String,
+    pub op: CapabilityOp,
+    pub path: String,
+    pub expires_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grantee: Option<String>,
+}
+
+impl Capability {
+    fn covers(&self, path: &str) -> bool {
+        self.path == "/"
+            || path == self.path
+            || path
+                .strip_prefix(self.path.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    fn permits(&self, class: CommandClass, paths: &[&str], now: u64) -> bool {
+        now < self.expires_at
+            && self.op.allows(class)
+            && !paths.is_empty()
+            && paths
+                .iter()
+                .all(|path| path.starts_with('/') && self.covers(path))
+    }
+
+    pub fn describe(&self) -> String {
+        let grantee = match &self.grantee {
+            Some(grantee) => format!(" for {}", grantee),
+            None => String::new(),
+        };
+        format!(
+            "{} under {}{} until {}",
+            self.op.as_str(),
+            self.path,
+            grantee,
+            self.expires_at
+        )
+    }
+}
+
+pub fn parse_ttl(ttl: &str) -> Result<u64, String> {
+    let (digits, unit) = match ttl.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => (&ttl[..i], unit),
+        _ => (ttl, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => {
+            return Err(format!(
+                "Invalid ttl: {}. Use seconds or a m/h/d suffix",
+                ttl
+            ))
+        }
+    };
+    let ttl_secs = digits
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid ttl: {}. Use seconds or a m/h/d suffix", ttl))?
+        .saturating_mul(multiplier);
+    if ttl_secs == 0 || ttl_secs > MAX_TTL_SECS {
+        return Err(format!("Invalid ttl: must be 1-{} seconds", MAX_TTL_SECS));
+    }
+    Ok(ttl_secs)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapabilityRequest<'a> {
+    pub token: &'a str,
+    pub reply_address: &'a str,
+    pub command: &'a str,
+}
+
+pub fn parse(memo: &str) -> Option<Result<CapabilityRequest<'_>, String>> {
+    let rest = memo.strip_prefix(CAPABILITY_PREFIX)?;
+    let mut parts = rest.trim_start().splitn(3, char::is_whitespace);
+    let parsed = match (parts.next(), parts.next(), parts.next()) {
+        (Some(token), Some(reply_address), Some(command))
+            if !token.is_empty() && !reply_address.is_empty() && !command.trim().is_empty() =>
+        {
+            Ok(CapabilityRequest {
+                token,
+                reply_address,
+                command: command.trim(),
+            })
+        }
+        _ => {
+            Err("Invalid capability format. Use CAP:<token> <reply_address> <command>".to_string())
+        }
+    };
+    Some(parsed)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+    tokens: HashMap<String, Capability>,
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Capabilities::new());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read capabilities: {}", e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse capabilities: {}", e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize capabilities: {}", e))?;
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write capabilities: {}", e))?;
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| format!("Failed to replace capabilities: {}", e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.tokens
+            .get(token)
+            .is_some_and(|capability| capability.grantee.is_none())
+    }
+
+    pub fn issue(&mut self, capability: Capability) -> Result<String, String> {
+        let issued = self
+            .tokens
+            .values()
+            .filter(|existing| existing.issuer == capability.issuer)
+            .count();
+        if issued >= MAX_TOKENS_PER_ISSUER {
+            return Err(format!(
+                "Rate limit: {} capability tokens already issued; revoke some first",
+                issued
+            ));
+        }
+
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let token = crypto::to_hex(&bytes);
+        self.tokens.insert(token.clone(), capability);
+        Ok(token)
+    }
+
+    pub fn issued_by(&self, issuer: &str) -> Vec<(&str, &Capability)> {
+        let mut tokens: Vec<(&str, &Capability)> = self
+            .tokens
+            .iter()
+            .filter(|(_, capability)| capability.issuer == issuer)
+            .map(|(token, capability)| (token.as_str(), capability))
+            .collect();
+        tokens.sort_by_key(|(_, capability)| capability.expires_at);
+        tokens
+    }
+
+    pub fn revoke(&mut self, issuer: &str, token: &str) -> Result<(), String> {
+        match self.tokens.get(token) {
+            Some(capability) if capability.issuer == issuer => {
+                self.tokens.remove(token);
+                Ok(())
+            }
+            _ => Err(format!("No such capability token: {}", token)),
+        }
+    }
+
+    pub fn revoke_issuer(&mut self, issuer: &str) -> usize {
+        let before = self.tokens.len();
+        self.tokens
+            .retain(|_, capability| capability.issuer != issuer);
+        before - self.tokens.len()
+    }
+
+    pub fn authorize(
+        &self,
+        token: &str,
+        class: CommandClass,
+        paths: &[&str],
+        now: u64,
+    ) -> Result<&Capability, String> {
+        let capability = self
+            .tokens
+            .get(token)
+            .filter(|capability| capability.grantee.is_none())
+            .ok_or_else(|| "Authentication failed: unknown capability token".to_string())?;
+        if now >= capability.expires_at {
+            return Err("Authentication failed: capability token expired".to_string());
+        }
+        if !capability.op.allows(class) {
+            return Err(format!(
+                "Permission denied: token only grants {} under {}",
+                capability.op.as_str(),
+                capability.path
+            ));
+        }
+        if paths.is_empty()
+            || paths
+                .iter()
+                .any(|path| !path.starts_with('/') || !capability.covers(path))
+        {
+            return Err(format!(
+                "Permission denied: token only covers {}",
+                capability.path
+            ));
+        }
+        Ok(capability)
+    }
+
+    pub fn delegated_to(&self, grantee: &str) -> Vec<(&str, &Capability)> {
+        let mut delegations: Vec<(&str, &Capability)> = self
+            .tokens
+            .iter()
+            .filter(|(_, capability)| capability.grantee.as_deref() == Some(grantee))
+            .map(|(id, capability)| (id.as_str(), capability))
+            .collect();
+        delegations.sort_by_key(|(_, capability)| capability.expires_at);
+        delegations
+    }
+
+    pub fn delegation_for(
+        &self,
+        grantee: &str,
+        class: CommandClass,
+        paths: &[&str],
+        now: u64,
+    ) -> Option<&Capability> {
+        self.tokens.values().find(|capability| {
+            capability.grantee.as_deref() == Some(grantee) && capability.permits(class, paths, now)
+        })
+    }
+
+    pub fn prune(&mut self, now: u64) -> usize {
+        let before = self.tokens.len();
+        self.tokens
+            .retain(|_, capability| now < capability.expires_at);
+        before - self.tokens.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_writer(expires_at: u64) -> Capability {
+        Capability {
+            issuer: "zs1owner".to_string(),
+            op: CapabilityOp::Write,
+            path: "/board/general".to_string(),
+            expires_at,
+            grantee: None,
+        }
+    }
+
+    #[test]
+    fn test_tokens_are_scoped_to_paths_ops_and_time() {
+        let mut capabilities = Capabilities::new();
+        let token = capabilities.issue(board_writer(200)).unwrap();
+        assert_eq!(token.len(), 32);
+
+        let allowed =
+            capabilities.authorize(&token, CommandClass::Write, &["/board/general/a.txt"], 100);
+        assert_eq!(allowed.unwrap().issuer, "zs1owner");
+        assert!(capabilities
+            .authorize(&token, CommandClass::Read, &["/board/general"], 100)
+            .is_ok());
+        assert!(capabilities
+            .authorize(&token, CommandClass::Write, &["/board/generalx"], 100)
+            .unwrap_err()
+            .starts_with("Permission denied"));
+        assert!(capabilities
+            .authorize(&token, CommandClass::Write, &[], 100)
+            .is_err());
+        assert!(capabilities
+            .authorize(&token, CommandClass::Write, &["board/general/a.txt"], 100)
+            .is_err());
+        assert!(capabilities
+            .authorize(&token, CommandClass::Chat, &["/board/general"], 100)
+            .is_err());
+        assert!(capabilities
+            .authorize(&token, CommandClass::Write, &["/board/general/a.txt"], 200)
+            .unwrap_err()
+            .contains("expired"));
+        assert!(capabilities
+            .authorize("nope", CommandClass::Read, &["/board/general"], 100)
+            .unwrap_err()
+            .starts_with("Authentication failed"));
+
+        assert!(capabilities.revoke("zs1other", &token).is_err());
+        capabilities.revoke("zs1owner", &token).unwrap();
+        assert!(capabilities.is_empty());
+    }
+
+    #[test]
+    fn test_parse_capability_memo() {
+        assert_eq!(
+            parse("CAP:abc zs1me touch /board/general/hi.txt hello")
+                .unwrap()
+                .unwrap(),
+            CapabilityRequest {
+                token: "abc",
+                reply_address: "zs1me",
+                command: "touch /board/general/hi.txt hello",
+            }
+        );
+        assert!(parse("CAP:abc zs1me").unwrap().is_err());
+        assert!(parse("ls /").is_none());
+
+        let mut capabilities = Capabilities::new();
+        capabilities.issue(board_writer(50)).unwrap();
+        capabilities.issue(board_writer(500)).unwrap();
+        assert_eq!(capabilities.prune(100), 1);
+        assert_eq!(capabilities.issued_by("zs1owner").len(), 1);
+        assert_eq!(parse_ttl("24h").unwrap(), 86400);
+        assert_eq!(parse_ttl("90").unwrap(), 90);
+        assert!(parse_ttl("0").is_err());
+        assert!(parse_ttl("31d").is_err());
+        assert!(parse_ttl("5w").is_err());
+    }
+
+    #[test]
+    fn test_delegations_bind_to_a_grantee() {
+        let mut capabilities = Capabilities::new();
+        let id = capabilities
+            .issue(Capability {
+                grantee: Some("zs1friend".to_string()),
+                ..board_writer(200)
+            })
+            .unwrap();
+        assert!(!capabilities.contains(&id));
+        assert!(capabilities
+            .authorize(&id, CommandClass::Write, &["/board/general/a.txt"], 100)
+            .is_err());
+
+        let found = capabilities.delegation_for(
+            "zs1friend",
+            CommandClass::Write,
+            &["/board/general/a.txt"],
+            100,
+        );
+        assert_eq!(found.unwrap().issuer, "zs1owner");
+        assert!(found.unwrap().describe().contains("for zs1friend"));
+        assert!(capabilities
+            .delegation_for("zs1other", CommandClass::Write, &["/board/general"], 100)
+            .is_none());
+        assert!(capabilities
+            .delegation_for("zs1friend", CommandClass::Write, &["/elsewhere"], 100)
+            .is_none());
+        assert!(capabilities
+            .delegation_for("zs1friend", CommandClass::Write, &["/board/general"], 200)
+            .is_none());
+        assert_eq!(capabilities.delegated_to("zs1friend").len(), 1);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::crypto;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const MAC_HEX_LEN: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ChallengeConfig {
+    pub ttl_secs: u64,
+    pub max_outstanding: usize,
+    pub secret: Option<String>,
+}
+
+impl Default for ChallengeConfig {
+    fn default() -> Self {
+        ChallengeConfig {
+            ttl_secs: 600,
+            max_outstanding: 3,
+            secret: None,
+        }
+    }
+}
+
+pub fn random_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+fn challenge_mac(secret: &[u8], user: &str, nonce: &str, timestamp: u64) -> String {
+    let data = format!("{}|{}|{}", user, nonce, timestamp);
+    crypto::hmac_sha256_hex(secret, data.as_bytes())[..MAC_HEX_LEN].to_string()
+}
+
+pub fn mint(secret: &[u8], user: &str, timestamp: u64) -> String {
+    let mut nonce = [0u8; 8];
+    OsRng.fill_bytes(&mut nonce);
+    let nonce = crypto::to_hex(&nonce);
+    let mac = challenge_mac(secret, user, &nonce, timestamp);
+    format!("{}-{}-{}", nonce, timestamp, mac)
+}
+
+pub fn verify(
+    secret: &[u8],
+    user: &str,
+    value: &str,
+    ttl_secs: u64,
+    now: u64,
+) -> Result<u64, String> {
+    let invalid = || "Invalid signature or challenge.".to_string();
+    let mut parts = value.splitn(3, '-');
+    let (Some(nonce), Some(timestamp), Some(mac)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    let timestamp = timestamp.parse::<u64>().map_err(|_| invalid())?;
+    if mac.len() != MAC_HEX_LEN || challenge_mac(secret, user, nonce, timestamp) != mac {
+        return Err(invalid());
+    }
+    if timestamp > now || is_lapsed(timestamp, ttl_secs, now) {
+        return Err("Challenge expired; send REGISTER again for a new one.".to_string());
+    }
+    Ok(timestamp)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingChallenge {
+    pub value: String,
+    pub issued_at: u64,
+}
+
+impl PendingChallenge {
+    fn is_expired(&self, ttl_secs: u64, now: u64) -> bool {
+        is_lapsed(self.issued_at, ttl_secs, now)
+    }
+}
+
+fn is_lapsed(issued_at: u64, ttl_secs: u64, now: u64) -> bool {
+    ttl_secs > 0 && now.saturating_sub(issued_at) >= ttl_secs
+}
+
+#[derive(Debug, Default)]
+pub struct ChallengeStore {
+    pending: HashMap<String, Vec<PendingChallenge>>,
+    spent: HashMap<String, u64>,
+    issued: HashMap<String, Vec<u64>>,
+}
+
+impl ChallengeStore {
+    pub fn new(
+        pending: HashMap<String, Vec<PendingChallenge>>,
+        spent: HashMap<String, u64>,
+        issued: HashMap<String, Vec<u64>>,
+    ) -> Self {
+        ChallengeStore {
+            pending,
+            spent,
+            issued,
+        }
+    }
+
+    pub fn migrate_legacy(&mut self, legacy: &HashMap<String, String>, now: u64) {
+        for (user, value) in legacy {
+            self.pending
+                .entry(user.clone())
+                .or_default()
+                .push(PendingChallenge {
+                    value: value.clone(),
+                    issued_at: now,
+                });
+        }
+    }
+
+    pub fn pending(&self) -> &HashMap<String, Vec<PendingChallenge>> {
+        &self.pending
+    }
+
+    pub fn spent(&self) -> &HashMap<String, u64> {
+        &self.spent
+    }
+
+    pub fn issued(&self) -> &HashMap<String, Vec<u64>> {
+        &self.issued
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn outstanding(&self, user: &str) -> usize {
+        self.pending.get(user).map_or(0, Vec::len)
+    }
+
+    pub fn latest(&self, user: &str) -> Option<&str> {
+        self.pending
+            .get(user)
+            .and_then(|challenges| challenges.last())
+            .map(|challenge| challenge.value.as_str())
+    }
+
+    pub fn issue(
+        &mut self,
+        user: &str,
+        value: &str,
+        config: &ChallengeConfig,
+        now: u64,
+    ) -> Result<(), String> {
+        let outstanding = match config.secret {
+            Some(_) => {
+                let issued = self.issued.entry(user.to_string()).or_default();
+                issued.retain(|&issued_at| !is_lapsed(issued_at, config.ttl_secs, now));
+                issued.len()
+            }
+            None => {
+                let challenges = self.pending.entry(user.to_string()).or_default();
+                challenges.retain(|challenge| !challenge.is_expired(config.ttl_secs, now));
+                challenges.len()
+            }
+        };
+        if config.max_outstanding > 0 && outstanding >= config.max_outstanding {
+            return Err(format!(
+                "Rate limit: {} challenges already outstanding; use one or wait for it to expire",
+                outstanding
+            ));
+        }
+        match config.secret {
+            Some(_) => self.issued.entry(user.to_string()).or_default().push(now),
+            None => self
+                .pending
+                .entry(user.to_string())
+                .or_default()
+                .push(PendingChallenge {
+                    value: value.to_string(),
+                    issued_at: now,
+                }),
+        }
+        Ok(())
+    }
+
+    pub fn consume(
+        &mut self,
+        user: &str,
+        value: &str,
+        config: &ChallengeConfig,
+        now: u64,
+    ) -> Result<(), String> {
+        let position = self.pending.get(user).and_then(|challenges| {
+            challenges
+                .iter()
+                .position(|challenge| challenge.value == value)
+        });
+        let Some(position) = position else {
+            return self.consume_stateless(user, value, config, now);
+        };
+        let challenges = self.pending.get_mut(user).expect("position found above");
+        let challenge = challenges.remove(position);
+        if challenges.is_empty() {
+            self.pending.remove(user);
+        }
+        self.spent
+            .insert(challenge.value.clone(), challenge.issued_at);
+
+        if challenge.is_expired(config.ttl_secs, now) {
+            return Err("Challenge expired; send REGISTER again for a new one.".to_string());
+        }
+        Ok(())
+    }
+
+    fn consume_stateless(
+        &mut self,
+        user: &str,
+        value: &str,
+        config: &ChallengeConfig,
+        now: u64,
+    ) -> Result<(), String> {
+        let secret = config
+            .secret
+            .as_deref()
+            .ok_or_else(|| "Invalid signature or challenge.".to_string())?;
+        if self.spent.contains_key(value) {
+            return Err("Invalid signature or challenge.".to_string());
+        }
+        let issued_at = verify(secret.as_bytes(), user, value, config.ttl_secs, now)?;
+        self.spent.insert(value.to_string(), issued_at);
+        if let Some(issued) = self.issued.get_mut(user) {
+            if let Some(position) = issued.iter().position(|&at| at == issued_at) {
+                issued.remove(position);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_user(&mut self, user: &str) {
+        self.pending.remove(user);
+        self.issued.remove(user);
+    }
+
+    pub fn retain_users<F: FnMut(&str) -> bool>(&mut self, mut keep: F) {
+        self.pending.retain(|user, _| keep(user));
+        self.issued.retain(|user, _| keep(user));
+    }
+
+    pub fn prune(&mut self, config: &ChallengeConfig, now: u64) -> usize {
+        let before = self.len();
+        for challenges in self.pending.values_mut() {
+            challenges.retain(|challenge| !challenge.is_expired(config.ttl_secs, now));
+        }
+        self.pending.retain(|_, challenges| !challenges.is_empty());
+        for issued in self.issued.values_mut() {
+            issued.retain(|&issued_at| !is_lapsed(issued_at, config.ttl_secs, now));
+        }
+        self.issued.retain(|_, issued| !issued.is_empty());
+        self.spent
+            .retain(|_, issued_at| !is_lapsed(*issued_at, config.ttl_secs, now));
+        before - self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenges_are_single_use_and_expire() {
+        let config = ChallengeConfig {
+            ttl_secs: 60,
+            max_outstanding: 3,
+            ..ChallengeConfig::default()
+        };
+        let mut store = ChallengeStore::default();
+        store.issue("zs1alice", "c1", &config, 100).unwrap();
+        store.issue("zs1alice", "c2", &config, 100).unwrap();
+
+        assert!(store.consume("zs1alice", "c1", &config, 110).is_ok());
+        assert!(store.consume("zs1alice", "c1", &config, 110).is_err());
+        assert!(store.consume("zs1bob", "c2", &config, 110).is_err());
+        assert_eq!(store.latest("zs1alice"), Some("c2"));
+
+        assert!(store
+            .consume("zs1alice", "c2", &config, 160)
+            .unwrap_err()
+            .starts_with("Challenge expired"));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_outstanding_limit_and_prune() {
+        let config = ChallengeConfig {
+            ttl_secs: 60,
+            max_outstanding: 2,
+            ..ChallengeConfig::default()
+        };
+        let mut store = ChallengeStore::default();
+        store.issue("zs1alice", "c1", &config, 100).unwrap();
+        store.issue("zs1alice", "c2", &config, 120).unwrap();
+        assert!(store
+            .issue("zs1alice", "c3", &config, 130)
+            .unwrap_err()
+            .starts_with("Rate limit"));
+        store.issue("zs1bob", "b1", &config, 130).unwrap();
+
+        store.issue("zs1alice", "c3", &config, 165).unwrap();
+        assert_eq!(store.outstanding("zs1alice"), 2);
+
+        assert_eq!(store.prune(&config, 190), 2);
+        assert_eq!(store.outstanding("zs1alice"), 1);
+        assert_eq!(store.outstanding("zs1bob"), 0);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_minted_challenges_verify_without_pending_state() {
+        let config = ChallengeConfig {
+            ttl_secs: 60,
+            secret: Some("operator-secret".to_string()),
+            ..ChallengeConfig::default()
+        };
+        let secret = config.secret.as_deref().unwrap().as_bytes();
+        let value = mint(secret, "zs1alice", 100);
+        assert_ne!(value, mint(secret, "zs1alice", 100));
+        assert!(verify(secret, "zs1alice", &value, 60, 110).is_ok());
+        assert!(verify(secret, "zs1bob", &value, 60, 110).is_err());
+        assert!(verify(b"other-secret", "zs1alice", &value, 60, 110).is_err());
+        assert!(verify(secret, "zs1alice", &value, 60, 160)
+            .unwrap_err()
+            .starts_with("Challenge expired"));
+
+        let mut store = ChallengeStore::default();
+        store.issue("zs1alice", &value, &config, 100).unwrap();
+        assert!(store.is_empty());
+        assert!(store.consume("zs1alice", &value, &config, 110).is_ok());
+        assert!(store.consume("zs1alice", &value, &config, 110).is_err());
+
+        let mut restarted =
+            ChallengeStore::new(HashMap::new(), store.spent().clone(), HashMap::new());
+        assert!(restarted
+            .consume("zs1alice", &value, &config, 120)
+            .unwrap_err()
+            .starts_with("Invalid signature or challenge"));
+        assert!(restarted
+            .consume("zs1alice", &value, &ChallengeConfig::default(), 110)
+            .is_err());
+        assert_eq!(restarted.prune(&config, 200), 0);
+        assert!(restarted.spent().is_empty());
+    }
+
+    #[test]
+    fn test_stateless_challenges_still_limit_outstanding() {
+        let config = ChallengeConfig {
+            ttl_secs: 60,
+            max_outstanding: 2,
+            secret: Some("operator-secret".to_string()),
+        };
+        let secret = b"operator-secret";
+        let mut store = ChallengeStore::default();
+        let first = mint(secret, "zs1alice", 100);
+        store.issue("zs1alice", &first, &config, 100).unwrap();
+        store
+            .issue("zs1alice", &mint(secret, "zs1alice", 100), &config, 100)
+            .unwrap();
+        assert!(store
+            .issue("zs1alice", "c3", &config, 110)
+            .unwrap_err()
+            .starts_with("Rate limit"));
+
+        let mut restarted = ChallengeStore::new(
+            HashMap::new(),
+            store.spent().clone(),
+            store.issued().clone(),
+        );
+        assert!(restarted
+            .issue("zs1alice", "c3", &config, 110)
+            .unwrap_err()
+            .starts_with("Rate limit"));
+
+        store.consume("zs1alice", &first, &config, 110).unwrap();
+        store.issue("zs1alice", "c3", &config, 110).unwrap();
+        assert!(store.is_empty());
+        store.prune(&config, 200);
+        store.issue("zs1alice", "c4", &config, 200).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+const MAX_ROOM_HISTORY: usize = 200;
+const MAX_ROOM_NAME_LENGTH: usize = 32;
+const MAX_MESSAGE_LENGTH: usize = 400;
+pub const DEFAULT_READ_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatMessage {
+    pub id: u64,
+    pub author: String,
+    pub timestamp: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRoom {
+    pub name: String,
+    pub created_by: String,
+    pub created_at: u64,
+    next_id: u64,
+    messages: VecDeque<ChatMessage>,
+}
+
+impl ChatRoom {
+    pub fn messages_since(&self, since: Option<u64>, limit: usize) -> Vec<&ChatMessage> {
+        let matching: Vec<&ChatMessage> = self
+            .messages
+            .iter()
+            .filter(|message| since.is_none_or(|since| message.id > since))
+            .collect();
+        let skip = matching.len().saturating_sub(limit);
+        matching.into_iter().skip(skip).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ChatRooms {
+    rooms: HashMap<String, ChatRoom>,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn validate_room_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_ROOM_NAME_LENGTH {
+        return Err(format!(
+            "Room name must be 1-{} characters",
+            MAX_ROOM_NAME_LENGTH
+        ));
+    }
+
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("Room name may only contain letters, digits, '-' and '_'".to_string());
+    }
+
+    Ok(())
+}
+
+impl ChatRooms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(ChatRooms::new());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read chat rooms: {}", e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse chat rooms: {}", e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize chat rooms: {}", e))?;
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write chat rooms: {}", e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace chat rooms: {}", e))
+    }
+
+    pub fn create(&mut self, name: &str, creator: &str) -> Result<(), String> {
+        validate_room_name(name)?;
+        if self.rooms.contains_key(name) {
+            return Err(format!("Room already exists: {}", name));
+        }
+
+        self.rooms.insert(
+            name.to_string(),
+            ChatRoom {
+                name: name.to_string(),
+                created_by: creator.to_string(),
+                created_at: now(),
+                next_id: 1,
+                messages: VecDeque::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn post(&mut self, name: &str, author: &str, text: &str) -> Result<u64, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Message cannot be empty".to_string());
+        }
+        if text.chars().count() > MAX_MESSAGE_LENGTH {
+            return Err(format!(
+                "Message too long (max {} characters)",
+                MAX_MESSAGE_LENGTH
+            ));
+        }
+
+        let room = self
+            .rooms
+            .get_mut(name)
+            .ok_or_else(|| format!("Room not found: {}", name))?;
+
+        let id = room.next_id;
+        room.next_id += 1;
+        room.messages.push_back(ChatMessage {
+            id,
+            author: author.to_string(),
+            timestamp: now(),
+            text: text.to_string(),
+        });
+        while room.messages.len() > MAX_ROOM_HISTORY {
+            room.messages.pop_front();
+        }
+
+        Ok(id)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ChatRoom> {
+        self.rooms.get(name)
+    }
+
+    pub fn room_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.rooms.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_post_read() {
+        let mut rooms = ChatRooms::new();
+        rooms.create("general", "zs1alice").unwrap();
+        assert!(rooms.create("general", "zs1bob").is_err());
+        assert!(rooms.create("bad name", "zs1bob").is_err());
+        assert!(rooms.post("missing", "alice", "hi").is_err());
+        assert!(rooms.post("general", "alice", "   ").is_err());
+
+        assert_eq!(rooms.post("general", "alice", "hello").unwrap(), 1);
+        assert_eq!(rooms.post("general", "bob", "hey").unwrap(), 2);
+
+        let room = rooms.get("general").unwrap();
+        let texts: Vec<&str> = room
+            .messages_since(None, 10)
+            .iter()
+            .map(|message| message.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["hello", "hey"]);
+        assert_eq!(room.messages_since(Some(1), 10)[0].author, "bob");
+        assert_eq!(room.messages_since(None, 1)[0].id, 2);
+    }
+
+    #[test]
+    fn test_history_is_bounded_and_persists() {
+        let mut rooms = ChatRooms::new();
+        rooms.create("busy", "zs1alice").unwrap();
+        for i in 0..MAX_ROOM_HISTORY + 5 {
+            rooms.post("busy", "alice", &format!("msg {}", i)).unwrap();
+        }
+        let room = rooms.get("busy").unwrap();
+        assert_eq!(room.len(), MAX_ROOM_HISTORY);
+        assert_eq!(
+            room.messages_since(None, 1)[0].id,
+            (MAX_ROOM_HISTORY + 5) as u64
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("chat_rooms.json");
+        rooms.save(&path).unwrap();
+        let mut loaded = ChatRooms::load(&path).unwrap();
+        assert_eq!(loaded.room_names(), vec!["busy"]);
+        assert_eq!(
+            loaded.post("busy", "bob", "next").unwrap(),
+            (MAX_ROOM_HISTORY + 6) as u64
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use crate::coordinator::Coordinator;
+use crate::message::Message;
+use crate::roles::Role;
+use crate::tokenizer;
+use crate::wallet::WalletBackend;
+use crate::zingo_wrapper::ZingoClient;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const BATCH_PREFIX: &str = "batch ";
+pub const BATCH_SEPARATOR: char = ';';
+pub const MAX_BATCH_COMMANDS: usize = 10;
+const PATH_OPERANDS: &[&str] = &["path", "file", "folder", "src", "dest"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandClass {
+    Read,
+    Write,
+    Upload,
+    Chat,
+}
+
+impl CommandClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandClass::Read => "read",
+            CommandClass::Write => "write",
+            CommandClass::Upload => "upload",
+            CommandClass::Chat => "chat",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandSpec {
+    pub name: String,
+    pub args: String,
+    pub summary: String,
+    pub role: Role,
+    pub min_args: usize,
+    pub cacheable: bool,
+    pub class: CommandClass,
+}
+
+impl CommandSpec {
+    pub fn new(name: &str, args: &str, summary: &str) -> Self {
+        CommandSpec {
+            name: name.to_string(),
+            args: args.to_string(),
+            summary: summary.to_string(),
+            role: Role::Member,
+            min_args: args
+                .split_whitespace()
+                .filter(|token| token.starts_with('<'))
+                .count(),
+            cacheable: false,
+            class: CommandClass::Read,
+        }
+    }
+
+    pub fn admin(mut self) -> Self {
+        self.role = Role::Admin;
+        self
+    }
+
+    pub fn moderator(mut self) -> Self {
+        self.role = Role::Moderator;
+        self
+    }
+
+    pub fn cacheable(mut self) -> Self {
+        self.cacheable = true;
+        self
+    }
+
+    pub fn class(mut self, class: CommandClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+
+    pub fn path_operands(&self, args: &str) -> Result<Vec<String>, String> {
+        let tokens = tokenizer::tokenize(args)?;
+        let (slots, operands) = match (
+            self.args.split_once(" > "),
+            tokens.iter().position(|token| token == ">"),
+        ) {
+            (Some((_, target)), Some(at)) => (target, &tokens[at + 1..]),
+            (Some(_), None) => return Ok(Vec::new()),
+            (None, _) => (self.args.as_str(), tokens.as_slice()),
+        };
+        Ok(slots
+            .split_whitespace()
+            .filter(|slot| !slot.starts_with("[--"))
+            .zip(operands.iter().filter(|token| !token.starts_with("--")))
+            .filter(|(slot, _)| PATH_OPERANDS.contains(&slot.trim_matches(['<', '>', '[', ']'])))
+            .map(|(_, token)| token.clone())
+            .collect())
+    }
+
+    pub fn check_args(&self, args: &str) -> Result<(), String> {
+        if tokenizer::tokenize(args)?.len() < self.min_args {
+            return Err(format!(
+                "Invalid {} format. Use: {}",
+                self.name,
+                self.usage()
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub trait CommandHandler<W: WalletBackend = ZingoClient>: Send + Sync {
+    fn spec(&self) -> &CommandSpec;
+
+    fn execute(
+        &self,
+        coordinator: &mut Coordinator<W>,
+        user_id: &str,
+        args: &str,
+    ) -> Result<String, String>;
+}
+
+pub trait MessageInterceptor<W: WalletBackend = ZingoClient>: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn intercept(
+        &self,
+        coordinator: &mut Coordinator<W>,
+        message: &Message,
+    ) -> Option<Result<String, String>>;
+}
+
+pub type CommandFn<W = ZingoClient> = fn(&mut Coordinator<W>, &str, &str) -> Result<String, String>;
+
+pub struct FnCommand<W: WalletBackend = ZingoClient> {
+    spec: CommandSpec,
+    handler: CommandFn<W>,
+}
+
+impl<W: WalletBackend> FnCommand<W> {
+    pub fn new(spec: CommandSpec, handler: CommandFn<W>) -> Self {
+        FnCommand { spec, handler }
+    }
+}
+
+impl<W: WalletBackend> CommandHandler<W> for FnCommand<W> {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn execute(
+        &self,
+        coordinator: &mut Coordinator<W>,
+        user_id: &str,
+        args: &str,
+    ) -> Result<String, String> {
+        (self.handler)(coordinator, user_id, args)
+    }
+}
+
+pub struct CommandRegistry<W: WalletBackend = ZingoClient> {
+    handlers: HashMap<String, Arc<dyn CommandHandler<W>>>,
+    order: Vec<String>,
+    aliases: HashMap<String, String>,
+}
+
+impl<W: WalletBackend> Clone for CommandRegistry<W> {
+    fn clone(&self) -> Self {
+        CommandRegistry {
+            handlers: self.handlers.clone(),
+            order: self.order.clone(),
+            aliases: self.aliases.clone(),
+        }
+    }
+}
+
+impl<W: WalletBackend> Default for CommandRegistry<W> {
+    fn default() -> Self {
+        CommandRegistry {
+            handlers: HashMap::new(),
+            order: Vec::new(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+impl<W: WalletBackend> CommandRegistry<W> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, handler: Arc<dyn CommandHandler<W>>) -> Result<(), String> {
+        let name = handler.spec().name.clone();
+        if name.split_whitespace().count() > 2 || name.trim() != name || name.is_empty() {
+            return Err(format!("Invalid command name: '{}'", name));
+        }
+        if self.handlers.contains_key(&name) {
+            return Err(format!("Command already registered: {}", name));
+        }
+
+        self.order.push(name.clone());
+        self.handlers.insert(name, handler);
+        Ok(())
+    }
+
+    pub fn add(&mut self, spec: CommandSpec, handler: CommandFn<W>) {
+        let name = spec.name.clone();
+        if let Err(e) = self.register(Arc::new(FnCommand::new(spec, handler))) {
+            panic!("Failed to register built-in command {}: {}", name, e);
+        }
+    }
+
+    pub fn add_alias(&mut self, alias: &str, target: &str) -> Result<(), String> {
+        let alias = alias.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let target = target.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if alias.is_empty() || alias.split(' ').count() > 2 {
+            return Err(format!("Invalid alias name: '{}'", alias));
+        }
+        if self.handlers.contains_key(&alias) {
+            return Err(format!("Alias {} would shadow a built-in command", alias));
+        }
+        if !self.handlers.contains_key(&target) {
+            return Err(format!(
+                "Alias {} points to unknown command: {}",
+                alias, target
+            ));
+        }
+
+        self.aliases.insert(alias, target);
+        Ok(())
+    }
+
+    pub fn clear_aliases(&mut self) {
+        self.aliases.clear();
+    }
+
+    pub fn alias_target(&self, alias: &str) -> Option<&str> {
+        self.aliases.get(alias).map(String::as_str)
+    }
+
+    pub fn aliases(&self) -> Vec<(&str, &str)> {
+        let mut aliases: Vec<(&str, &str)> = self
+            .aliases
+            .iter()
+            .map(|(alias, target)| (alias.as_str(), target.as_str()))
+            .collect();
+        aliases.sort();
+        aliases
+    }
+
+    pub fn resolve<'a>(&self, input: &'a str) -> Option<(Arc<dyn CommandHandler<W>>, &'a str)> {
+        let mut words = input.split_whitespace();
+        let first = words.next()?;
+        let candidates = match words.next() {
+            Some(second) => vec![format!("{} {}", first, second), first.to_string()],
+            None => vec![first.to_string()],
+        };
+
+        for name in candidates {
+            let handler = self.handlers.get(&name).or_else(|| {
+                self.aliases
+                    .get(&name)
+                    .and_then(|target| self.handlers.get(target))
+            });
+            if let Some(handler) = handler {
+                let args = Self::strip_name(input, &name);
+                return Some((Arc::clone(handler), args));
+            }
+        }
+        None
+    }
+
+    pub fn split_batch<'a>(&self, input: &'a str) -> Option<Vec<&'a str>> {
+        let input = input.trim_start().strip_prefix(BATCH_PREFIX)?;
+        let commands: Vec<&str> = tokenizer::split_unquoted(input, BATCH_SEPARATOR)
+            .into_iter()
+            .map(str::trim)
+            .filter(|command| !command.is_empty())
+            .collect();
+        if commands.len() < 2
+            || commands
+                .iter()
+                .any(|command| self.resolve(command).is_none())
+        {
+            return None;
+        }
+        Some(commands)
+    }
+
+    fn strip_name<'a>(input: &'a str, name: &str) -> &'a str {
+        name.split(' ').fold(input.trim_start(), |rest, word| {
+            rest[word.len()..].trim_start()
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CommandSpec> {
+        self.handlers.get(name).map(|handler| handler.spec())
+    }
+
+    pub fn specs(&self) -> Vec<&CommandSpec> {
+        self.order
+            .iter()
+            .filter_map(|name| self.handlers.get(name))
+            .map(|handler| handler.spec())
+            .collect()
+    }
+
+    pub fn top_level_names(&self, role: Role) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for spec in self.specs() {
+            if spec.role > role {
+                continue;
+            }
+            let top = spec.name.split(' ').next().unwrap_or_default().to_string();
+            if !names.contains(&top) {
+                names.push(top);
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_args(_: &mut Coordinator, _: &str, args: &str) -> Result<String, String> {
+        Ok(args.to_string())
+    }
+
+    #[test]
+    fn test_spec_schema() {
+        let spec = CommandSpec::new("grant", "<read|write> <user> <path>", "Grant access");
+        assert_eq!(spec.min_args, 3);
+        assert_eq!(spec.usage(), "grant <read|write> <user> <path>");
+        assert_eq!(
+            spec.check_args("read zs1bob").unwrap_err(),
+            "Invalid grant format. Use: grant <read|write> <user> <path>"
+        );
+        assert!(spec.check_args("read zs1bob /a b").is_ok());
+        assert_eq!(CommandSpec::new("ls", "[path]", "List").min_args, 0);
+    }
+
+    #[test]
+    fn test_path_operands_follow_spec_positions() {
+        let touch = CommandSpec::new("touch", "[--overwrite] <path> [content]", "Create");
+        assert_eq!(
+            touch.path_operands("--overwrite /a.txt /b").unwrap(),
+            vec!["/a.txt"]
+        );
+        let cp = CommandSpec::new("cp", "<src> <dest>", "Copy");
+        assert_eq!(cp.path_operands("\"/a b\" /c").unwrap(), vec!["/a b", "/c"]);
+        let grant = CommandSpec::new("grant", "<read|write> <user> <path>", "Grant");
+        assert_eq!(
+            grant.path_operands("read /bob /docs").unwrap(),
+            vec!["/docs"]
+        );
+        let echo = CommandSpec::new("echo", "<content> > <file>", "Write");
+        assert_eq!(
+            echo.path_operands("/video/a b > /docs/c").unwrap(),
+            vec!["/docs/c"]
+        );
+        assert!(echo.path_operands("\"a > b\"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_prefers_two_word_commands() {
+        let mut registry = CommandRegistry::new();
+        registry.add(
+            CommandSpec::new("chat", "<folder> <message>", "Chat"),
+            echo_args,
+        );
+        registry.add(
+            CommandSpec::new("chat create", "<room>", "Create"),
+            echo_args,
+        );
+        registry.add(CommandSpec::new("gc", "", "Collect").admin(), echo_args);
+        registry.add(
+            CommandSpec::new("hide", "<path>", "Hide").moderator(),
+            echo_args,
+        );
+
+        let (handler, args) = registry.resolve("chat create  lobby").unwrap();
+        assert_eq!(handler.spec().name, "chat create");
+        assert_eq!(args, "lobby");
+
+        let (handler, args) = registry.resolve("chat /lobby hi there").unwrap();
+        assert_eq!(handler.spec().name, "chat");
+        assert_eq!(args, "/lobby hi there");
+
+        let (handler, args) = registry.resolve("gc").unwrap();
+        assert_eq!(handler.spec().name, "gc");
+        assert_eq!(args, "");
+
+        assert!(registry.resolve("chatter").is_none());
+        assert!(registry.resolve("").is_none());
+        assert_eq!(registry.top_level_names(Role::Member), vec!["chat"]);
+        assert_eq!(
+            registry.top_level_names(Role::Moderator),
+            vec!["chat", "hide"]
+        );
+        assert_eq!(
+            registry.top_level_names(Role::Admin),
+            vec!["chat", "gc", "hide"]
+        );
+    }
+
+    #[test]
+    fn test_aliases_resolve_to_commands() {
+        let mut registry = CommandRegistry::new();
+        registry.add(CommandSpec::new("ls", "[path]", "List"), echo_args);
+        registry.add(
+            CommandSpec::new("chat post", "<room> <message>", "Post"),
+            echo_args,
+        );
+        registry.add_alias("dir", "ls").unwrap();
+        registry.add_alias("say", "chat  post").unwrap();
+
+        let (handler, args) = registry.resolve("dir /docs").unwrap();
+        assert_eq!(handler.spec().name, "ls");
+        assert_eq!(args, "/docs");
+        let (handler, args) = registry.resolve("say lobby hello").unwrap();
+        assert_eq!(handler.spec().name, "chat post");
+        assert_eq!(args, "lobby hello");
+
+        assert!(registry.add_alias("ls", "chat post").is_err());
+        assert!(registry.add_alias("del", "rm").is_err());
+        assert_eq!(
+            registry.aliases(),
+            vec![("dir", "ls"), ("say", "chat post")]
+        );
+
+        registry.clear_aliases();
+        assert!(registry.resolve("dir /docs").is_none());
+    }
+
+    #[test]
+    fn test_split_batch_requires_known_commands() {
+        let mut registry = CommandRegistry::new();
+        registry.add(CommandSpec::new("ls", "[path]", "List"), echo_args);
+        registry.add(CommandSpec::new("mkdir", "<path>", "Make"), echo_args);
+        registry.add(
+            CommandSpec::new("chat post", "<room> <message>", "Post"),
+            echo_args,
+        );
+
+        assert_eq!(
+            registry.split_batch("batch mkdir /a; ls /a;"),
+            Some(vec!["mkdir /a", "ls /a"])
+        );
+        assert_eq!(registry.split_batch("mkdir /a; ls /a"), None);
+        assert_eq!(registry.split_batch("chat post lobby hi; ls /"), None);
+        assert_eq!(
+            registry.split_batch("batch chat post lobby \"hi; ls /\"; ls /"),
+            Some(vec!["chat post lobby \"hi; ls /\"", "ls /"])
+        );
+        assert_eq!(
+            registry.split_batch("batch chat post lobby hi; there"),
+            None
+        );
+        assert_eq!(registry.split_batch("batch ls /;"), None);
+        assert_eq!(registry.split_batch("batch ls /"), None);
+    }
+
+    #[test]
+    fn test_register_rejects_duplicates() {
+        let mut registry = CommandRegistry::new();
+        let spec = CommandSpec::new("ls", "[path]", "List");
+        registry
+            .register(Arc::new(FnCommand::new(spec.clone(), echo_args)))
+            .unwrap();
+        assert!(registry
+            .register(Arc::new(FnCommand::new(spec, echo_args)))
+            .is_err());
+        assert!(registry
+            .register(Arc::new(FnCommand::new(
+                CommandSpec::new("a b c", "", "Too deep"),
+                echo_args
+            )))
+            .is_err());
+    }
+}
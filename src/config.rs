@@ -1,4 +1,27 @@
+use crate::challenge::ChallengeConfig;
+use crate::commands::CommandClass;
+use crate::confirm::ConfirmationConfig;
+use crate::conversation::ConversationConfig;
+use crate::federation::FederationConfig;
+use crate::filesystem::FsLimits;
+use crate::guest::GuestConfig;
+use crate::health::HealthConfig;
+use crate::lockout::LockoutConfig;
+use crate::memo_decoder::{ChecksumConfig, FragmentConfig};
+use crate::ownership::OwnershipConfig;
+use crate::policy::PolicyConfig;
+use crate::priority::PriorityConfig;
+use crate::quota::QuotaConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::response::ResponseConfig;
+use crate::retry::RetryConfig;
+use crate::scheduler::SchedulerConfig;
+use crate::shard::ShardConfig;
+use crate::signing::SigningConfig;
+use crate::status::StatusConfig;
+use crate::user_session::SessionConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,20 +31,78 @@ pub struct CoordinatorConfig {
     pub fees: FeeConfig,
     pub api: ApiConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub limits: FsLimits,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub federation: FederationConfig,
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub conversations: ConversationConfig,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub responses: ResponseConfig,
+    #[serde(default)]
+    pub signing: SigningConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub sessions: SessionConfig,
+    #[serde(default)]
+    pub challenges: ChallengeConfig,
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    #[serde(default)]
+    pub confirmations: ConfirmationConfig,
+    #[serde(default)]
+    pub ownership: OwnershipConfig,
+    #[serde(default)]
+    pub fragments: FragmentConfig,
+    #[serde(default)]
+    pub checksums: ChecksumConfig,
+    #[serde(default)]
+    pub shards: ShardConfig,
+    #[serde(default)]
+    pub guests: GuestConfig,
+    #[serde(default)]
+    pub priority: PriorityConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct NetworkConfig {
     pub zingo_server: String,
     pub coordinator_address: Option<String>,
     pub polling_interval_secs: u64,
+    #[serde(default)]
+    pub max_fee_zatoshis: Option<u64>,
+    #[serde(default = "default_max_outputs_per_tx")]
+    pub max_outputs_per_tx: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn default_max_outputs_per_tx() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StorageConfig {
     pub data_dir: PathBuf,
     pub database_file: String,
     pub cache_ttl_secs: u64,
+    #[serde(default)]
+    pub encryption_secret: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,9 +111,26 @@ pub struct FeeConfig {
     pub per_command_zatoshi: u64,
     pub chat_message_zatoshi: u64,
     pub file_upload_zatoshi: u64,
+    #[serde(default)]
+    pub read_zatoshi: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl FeeConfig {
+    pub fn required_for(&self, class: CommandClass) -> u64 {
+        if !self.enabled {
+            return 0;
+        }
+
+        match class {
+            CommandClass::Read => self.read_zatoshi,
+            CommandClass::Write => self.per_command_zatoshi,
+            CommandClass::Upload => self.file_upload_zatoshi,
+            CommandClass::Chat => self.chat_message_zatoshi,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ApiConfig {
     pub enable_json_rpc: bool,
     pub bind_address: String,
@@ -46,6 +144,11 @@ pub struct LoggingConfig {
     pub enable_console: bool,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdminConfig {
+    pub addresses: Vec<String>,
+}
+
 impl Default for CoordinatorConfig {
     fn default() -> Self {
         CoordinatorConfig {
@@ -53,17 +156,21 @@ impl Default for CoordinatorConfig {
                 zingo_server: "http://localhost:9067".to_string(),
                 coordinator_address: None,
                 polling_interval_secs: 1,
+                max_fee_zatoshis: None,
+                max_outputs_per_tx: default_max_outputs_per_tx(),
             },
             storage: StorageConfig {
                 data_dir: PathBuf::from("./coordinator_data"),
                 database_file: "filesystem.db".to_string(),
                 cache_ttl_secs: 10,
+                encryption_secret: None,
             },
             fees: FeeConfig {
                 enabled: false,
                 per_command_zatoshi: 1000,
                 chat_message_zatoshi: 500,
                 file_upload_zatoshi: 5000,
+                read_zatoshi: 0,
             },
             api: ApiConfig {
                 enable_json_rpc: true,
@@ -75,6 +182,30 @@ impl Default for CoordinatorConfig {
                 log_file: Some(PathBuf::from("coordinator.log")),
                 enable_console: true,
             },
+            limits: FsLimits::default(),
+            admin: AdminConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            federation: FederationConfig::default(),
+            quotas: QuotaConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            status: StatusConfig::default(),
+            conversations: ConversationConfig::default(),
+            aliases: BTreeMap::new(),
+            responses: ResponseConfig::default(),
+            signing: SigningConfig::default(),
+            policy: PolicyConfig::default(),
+            health: HealthConfig::default(),
+            sessions: SessionConfig::default(),
+            challenges: ChallengeConfig::default(),
+            lockout: LockoutConfig::default(),
+            confirmations: ConfirmationConfig::default(),
+            ownership: OwnershipConfig::default(),
+            fragments: FragmentConfig::default(),
+            checksums: ChecksumConfig::default(),
+            shards: ShardConfig::default(),
+            guests: GuestConfig::default(),
+            priority: PriorityConfig::default(),
+            retry: RetryConfig::default(),
         }
     }
 }
@@ -94,6 +225,25 @@ impl CoordinatorConfig {
         toml::from_str(&content).map_err(|e| format!("Failed to parse config file: {}", e))
     }
 
+    pub fn restart_required(&self, next: &CoordinatorConfig) -> Vec<&'static str> {
+        let mut sections = Vec::new();
+        if self.network != next.network {
+            sections.push("network");
+        }
+        if self.storage.data_dir != next.storage.data_dir
+            || self.storage.database_file != next.storage.database_file
+        {
+            sections.push("storage");
+        }
+        if self.api != next.api {
+            sections.push("api");
+        }
+        if self.status != next.status {
+            sections.push("status");
+        }
+        sections
+    }
+
     pub fn save_to_file(&self, path: &PathBuf) -> Result<(), String> {
         let content = toml::to_string_pretty(self)
             .map_err(|e| format!("Failed to serialize config: {}", e))?;
@@ -106,3 +256,72 @@ impl CoordinatorConfig {
         std::fs::write(path, content).map_err(|e| format!("Failed to write config file: {}", e))
     }
 }
+
+pub struct ConfigWatcher {
+    path: PathBuf,
+    contents: Option<String>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let contents = std::fs::read_to_string(&path).ok();
+        ConfigWatcher { path, contents }
+    }
+
+    pub fn poll(&mut self) -> Option<Result<CoordinatorConfig, String>> {
+        let contents = std::fs::read_to_string(&self.path).ok()?;
+        if self.contents.as_deref() == Some(contents.as_str()) {
+            return None;
+        }
+
+        let parsed =
+            toml::from_str(&contents).map_err(|e| format!("Failed to parse config file: {}", e));
+        self.contents = Some(contents);
+        Some(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher_reports_each_edit_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("coordinator.toml");
+        let config = CoordinatorConfig::default();
+        config.save_to_file(&path).unwrap();
+
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(watcher.poll().is_none());
+
+        let edited = CoordinatorConfig {
+            fees: FeeConfig {
+                enabled: true,
+                ..config.fees.clone()
+            },
+            ..config.clone()
+        };
+        edited.save_to_file(&path).unwrap();
+        assert!(watcher.poll().unwrap().unwrap().fees.enabled);
+        assert!(watcher.poll().is_none());
+
+        std::fs::write(&path, "[network\n").unwrap();
+        assert!(watcher.poll().unwrap().is_err());
+        assert!(watcher.poll().is_none());
+    }
+
+    #[test]
+    fn test_restart_required_sections() {
+        let current = CoordinatorConfig::default();
+        let mut next = current.clone();
+        next.fees.enabled = true;
+        next.admin.addresses.push("zs1admin".to_string());
+        next.sessions.timeout_secs = 60;
+        assert!(current.restart_required(&next).is_empty());
+
+        next.network.polling_interval_secs = 5;
+        next.storage.database_file = "other.db".to_string();
+        assert_eq!(current.restart_required(&next), vec!["network", "storage"]);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::message::Message;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ConfirmationConfig {
+    pub enabled: bool,
+    pub ttl_secs: u64,
+    pub commands: Vec<String>,
+}
+
+impl Default for ConfirmationConfig {
+    fn default() -> Self {
+        ConfirmationConfig {
+            enabled: false,
+            ttl_secs: 300,
+            commands: vec!["rm".to_string(), "admin".to_string()],
+        }
+    }
+}
+
+impl ConfirmationConfig {
+    pub fn requires(&self, command: &str) -> bool {
+        self.enabled
+            && self.commands.iter().any(|prefix| {
+                command == prefix
+                    || command
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with(' '))
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingConfirmation {
+    code: String,
+    message: Message,
+    issued_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Confirmations {
+    pending: HashMap<String, PendingConfirmation>,
+}
+
+impl Confirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn issue(&mut self, user: &str, message: &Message, now: u64) -> String {
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        self.pending.insert(
+            user.to_string(),
+            PendingConfirmation {
+                code: code.clone(),
+                message: message.clone(),
+                issued_at: now,
+            },
+        );
+        code
+    }
+
+    pub fn confirm(
+        &mut self,
+        user: &str,
+        code: &str,
+        config: &ConfirmationConfig,
+        now: u64,
+    ) -> Result<Message, String> {
+        let pending = self.pending.remove(user).ok_or_else(|| {
+            "Invalid confirmation: nothing is waiting to be confirmed".to_string()
+        })?;
+        if now.saturating_sub(pending.issued_at) >= config.ttl_secs {
+            return Err(
+                "Invalid confirmation: code expired; send the command again for a new one"
+                    .to_string(),
+            );
+        }
+        if pending.code != code {
+            return Err(
+                "Invalid confirmation: wrong code; send the command again for a new one"
+                    .to_string(),
+            );
+        }
+        Ok(pending.message)
+    }
+
+    pub fn remove_user(&mut self, user: &str) {
+        self.pending.remove(user);
+    }
+
+    pub fn prune(&mut self, config: &ConfirmationConfig, now: u64) -> usize {
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, pending| now.saturating_sub(pending.issued_at) < config.ttl_secs);
+        before - self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rm_message() -> Message {
+        Message::new(
+            "zs1alice".to_string(),
+            "zs1coordinator".to_string(),
+            "rm -r /docs".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_requires_matches_whole_command_words() {
+        let config = ConfirmationConfig {
+            enabled: true,
+            ..ConfirmationConfig::default()
+        };
+        assert!(config.requires("rm"));
+        assert!(config.requires("admin ban"));
+        assert!(!config.requires("rmdir"));
+        assert!(!config.requires("ls"));
+        assert!(!ConfirmationConfig::default().requires("rm"));
+    }
+
+    #[test]
+    fn test_codes_are_single_use_and_expire() {
+        let config = ConfirmationConfig::default();
+        let mut confirmations = Confirmations::new();
+        let code = confirmations.issue("zs1alice", &rm_message(), 100);
+        assert_eq!(code.len(), 6);
+
+        assert!(confirmations
+            .confirm("zs1bob", &code, &config, 110)
+            .is_err());
+        let message = confirmations
+            .confirm("zs1alice", &code, &config, 110)
+            .unwrap();
+        assert_eq!(message.memo_text, "rm -r /docs");
+        assert!(confirmations
+            .confirm("zs1alice", &code, &config, 110)
+            .is_err());
+
+        confirmations.issue("zs1alice", &rm_message(), 100);
+        assert!(confirmations
+            .confirm("zs1alice", "nope", &config, 110)
+            .unwrap_err()
+            .contains("wrong code"));
+        assert!(confirmations.is_empty());
+
+        let code = confirmations.issue("zs1alice", &rm_message(), 100);
+        assert!(confirmations
+            .confirm("zs1alice", &code, &config, 400)
+            .unwrap_err()
+            .contains("expired"));
+
+        confirmations.issue("zs1alice", &rm_message(), 100);
+        assert_eq!(confirmations.prune(&config, 400), 1);
+    }
+}
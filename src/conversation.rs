@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+const CONVERSATION_PREFIX: &str = "CONV";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ConversationConfig {
+    pub max_idle_secs: u64,
+}
+
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        ConversationConfig {
+            max_idle_secs: 30 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Conversation {
+    pub id: String,
+    pub participant_id: String,
+    pub user: String,
+    pub reply_address: String,
+    pub created_at: u64,
+    pub last_used: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routed {
+    pub conversation_id: String,
+    pub user: String,
+    pub command: String,
+}
+
+pub fn participant_id(user: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(user.as_bytes());
+    hasher.update(b"zatboard_participant");
+    format!(
+        "P{}",
+        &format!("{:x}", hasher.finalize())[..6].to_uppercase()
+    )
+}
+
+fn split_prefix(memo: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = memo.splitn(3, ':');
+    let conversation_id = parts.next()?;
+    let participant_id = parts.next()?;
+    let command = parts.next()?;
+
+    let numbered = conversation_id
+        .strip_prefix(CONVERSATION_PREFIX)
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()));
+    let participant = participant_id.len() == 7
+        && participant_id.starts_with('P')
+        && participant_id[1..]
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase());
+    if numbered && participant {
+        Some((conversation_id, participant_id, command))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConversationRegistry {
+    counter: u32,
+    conversations: HashMap<String, Conversation>,
+    by_user: HashMap<String, String>,
+}
+
+impl ConversationRegistry {
+    pub fn new(counter: u32) -> Self {
+        ConversationRegistry {
+            counter,
+            ..Default::default()
+        }
+    }
+
+    pub fn restore(counter: u32, conversations: Vec<Conversation>) -> Self {
+        let mut registry = Self::new(counter);
+        for conversation in conversations {
+            registry.insert(conversation);
+        }
+        registry
+    }
+
+    pub fn migrate_legacy(
+        &mut self,
+        conversation_users: &HashMap<String, String>,
+        reply_addresses: &HashMap<String, String>,
+        now: u64,
+    ) -> usize {
+        let mut migrated = 0;
+        for (id, user) in conversation_users {
+            if self.conversations.contains_key(id) || self.by_user.contains_key(user) {
+                continue;
+            }
+            self.insert(Conversation {
+                id: id.clone(),
+                participant_id: participant_id(user),
+                user: user.clone(),
+                reply_address: reply_addresses.get(user).cloned().unwrap_or_default(),
+                created_at: now,
+                last_used: now,
+            });
+            migrated += 1;
+        }
+        migrated
+    }
+
+    fn insert(&mut self, conversation: Conversation) {
+        self.by_user
+            .insert(conversation.user.clone(), conversation.id.clone());
+        self.conversations
+            .insert(conversation.id.clone(), conversation);
+    }
+
+    pub fn counter(&self) -> u32 {
+        self.counter
+    }
+
+    pub fn len(&self) -> usize {
+        self.conversations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conversations.is_empty()
+    }
+
+    pub fn conversations(&self) -> Vec<Conversation> {
+        let mut conversations: Vec<Conversation> = self.conversations.values().cloned().collect();
+        conversations.sort_by(|a, b| a.id.cmp(&b.id));
+        conversations
+    }
+
+    pub fn for_user(&self, user: &str) -> Option<&Conversation> {
+        self.by_user
+            .get(user)
+            .and_then(|id| self.conversations.get(id))
+    }
+
+    pub fn user_for_participant(&self, participant: &str) -> Option<&str> {
+        self.conversations
+            .values()
+            .find(|conversation| conversation.participant_id == participant)
+            .map(|conversation| conversation.user.as_str())
+    }
+
+    pub fn open(&mut self, user: &str, reply_address: &str, now: u64) -> Conversation {
+        if let Some(id) = self.by_user.get(user).cloned() {
+            if let Some(conversation) = self.conversations.get_mut(&id) {
+                conversation.reply_address = reply_address.to_string();
+                conversation.last_used = now;
+                return conversation.clone();
+            }
+        }
+
+        self.counter += 1;
+        let conversation = Conversation {
+            id: format!("{}{:04}", CONVERSATION_PREFIX, self.counter),
+            participant_id: participant_id(user),
+            user: user.to_string(),
+            reply_address: reply_address.to_string(),
+            created_at: now,
+            last_used: now,
+        };
+        self.insert(conversation.clone());
+        conversation
+    }
+
+    pub fn route(&mut self, memo: &str, now: u64) -> Result<Option<Routed>, String> {
+        let Some((conversation_id, participant, command)) = split_prefix(memo) else {
+            return Ok(None);
+        };
+
+        let conversation = self
+            .conversations
+            .get_mut(conversation_id)
+            .ok_or_else(|| format!("Unknown or retired conversation: {}", conversation_id))?;
+        if conversation.participant_id != participant {
+            return Err(format!(
+                "Participant {} does not belong to conversation {}",
+                participant, conversation_id
+            ));
+        }
+
+        conversation.last_used = now;
+        Ok(Some(Routed {
+            conversation_id: conversation.id.clone(),
+            user: conversation.user.clone(),
+            command: command.to_string(),
+        }))
+    }
+
+    pub fn retire_user(&mut self, user: &str) -> Option<Conversation> {
+        let id = self.by_user.remove(user)?;
+        self.conversations.remove(&id)
+    }
+
+    pub fn retire_stale(&mut self, now: u64, max_idle_secs: u64) -> Vec<Conversation> {
+        if max_idle_secs == 0 {
+            return Vec::new();
+        }
+
+        let stale: Vec<String> = self
+            .conversations
+            .values()
+            .filter(|conversation| now.saturating_sub(conversation.last_used) >= max_idle_secs)
+            .map(|conversation| conversation.user.clone())
+            .collect();
+        stale
+            .iter()
+            .filter_map(|user| self.retire_user(user))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_and_route() {
+        let mut registry = ConversationRegistry::new(1000);
+        let alice = registry.open("zs1alice", "zs1alicereply", 10);
+        assert_eq!(alice.id, "CONV1001");
+        assert_eq!(alice.participant_id, participant_id("zs1alice"));
+        assert_eq!(registry.open("zs1alice", "zs1newreply", 20).id, "CONV1001");
+        assert_eq!(
+            registry.for_user("zs1alice").unwrap().reply_address,
+            "zs1newreply"
+        );
+        let bob = registry.open("zs1bob", "zs1bobreply", 20);
+
+        let routed = registry
+            .route(&format!("CONV1001:{}:ls /", alice.participant_id), 30)
+            .unwrap()
+            .unwrap();
+        assert_eq!(routed.user, "zs1alice");
+        assert_eq!(routed.command, "ls /");
+        assert_eq!(registry.for_user("zs1alice").unwrap().last_used, 30);
+
+        assert!(registry
+            .route(&format!("CONV1001:{}:ls /", bob.participant_id), 30)
+            .is_err());
+        assert!(registry
+            .route(&format!("CONV9999:{}:ls /", bob.participant_id), 30)
+            .is_err());
+        assert_eq!(registry.route("ls /", 30), Ok(None));
+        assert_eq!(registry.route("chat post lobby: hi: there", 30), Ok(None));
+        assert_eq!(
+            registry.user_for_participant(&bob.participant_id),
+            Some("zs1bob")
+        );
+    }
+
+    #[test]
+    fn test_retire_stale_and_migrate() {
+        let mut registry = ConversationRegistry::new(1000);
+        registry.open("zs1alice", "zs1alicereply", 0);
+        registry.open("zs1bob", "zs1bobreply", 90);
+
+        let retired = registry.retire_stale(100, 50);
+        assert_eq!(retired.len(), 1);
+        assert_eq!(retired[0].user, "zs1alice");
+        assert!(registry.for_user("zs1alice").is_none());
+        assert!(registry.retire_stale(100, 0).is_empty());
+
+        let restored = ConversationRegistry::restore(registry.counter(), registry.conversations());
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored.counter(), 1002);
+
+        let mut legacy = ConversationRegistry::new(1005);
+        let conversations = HashMap::from([("CONV1003".to_string(), "zs1carol".to_string())]);
+        let replies = HashMap::from([("zs1carol".to_string(), "zs1carolreply".to_string())]);
+        assert_eq!(legacy.migrate_legacy(&conversations, &replies, 5), 1);
+        assert_eq!(
+            legacy.for_user("zs1carol").unwrap().reply_address,
+            "zs1carolreply"
+        );
+        assert_eq!(legacy.migrate_legacy(&conversations, &replies, 5), 0);
+    }
+}
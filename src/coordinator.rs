@@ -1,32 +1,248 @@
-use crate::auth::AuthenticationFlow;
-use crate::filesystem::FileSystem;
-use crate::message::Message;
+use crate::audit::{AuditLog, AuthAuditLog};
+use crate::auth::{AuthenticationFlow, BanList};
+use crate::backup;
+use crate::capability::{
+    self, Authority, Capabilities, Capability, CapabilityOp, CapabilityRequest,
+};
+use crate::challenge::{ChallengeConfig, ChallengeStore};
+use crate::chat::{self, ChatRooms};
+use crate::commands::{
+    self, CommandClass, CommandHandler, CommandRegistry, CommandSpec, MessageInterceptor,
+};
+use crate::config::{CoordinatorConfig, FeeConfig};
+use crate::confirm::{ConfirmationConfig, Confirmations};
+use crate::conversation::{self, ConversationConfig, ConversationRegistry};
+use crate::crypto::{self, NodeKey};
+use crate::cursor;
+use crate::e2e;
+use crate::events::{Event, EventBus, EventHook, Subscriber};
+use crate::federation::{Federation, FederationOp, FEDERATION_PREFIX};
+use crate::filesystem::{EntryFilter, FileNode, FileSystem, ListOptions, Principal, SortKey};
+use crate::guest::{self, GuestConfig, GUEST_USER};
+use crate::handshake::{self, Features};
+use crate::health::{HealthConfig, HealthReport, Probe};
+use crate::identity;
+use crate::lockout::{AuthLockouts, LockoutConfig};
+use crate::memo_decoder::{self, ChecksumConfig, Fragment, FragmentConfig, Reassembler};
+use crate::message::{self, Message};
+use crate::metrics::Metrics;
+use crate::moderation::Reports;
+use crate::outbound::{self, FlushReport, FundsGate, OutboundQueue};
+use crate::ownership::{self, OwnershipConfig, Probes};
+use crate::policy::{self, PolicyConfig, PolicyContext};
+use crate::priority::{MessageQueue, PriorityConfig};
+use crate::processed::{ProcessedEntry, ProcessedLog};
+use crate::profile::Profiles;
+use crate::quota::QuotaTracker;
+use crate::rate_limit::RateLimiter;
+use crate::relay::{self, RelayLog, RelayStatus};
+use crate::response::{self, Ack, ResponseConfig, Status};
+use crate::roles::Role;
+use crate::scheduler::{Job, Scheduler, SchedulerConfig};
+use crate::session_store::{self, AuthSnapshot, SessionBackend, SessionStore, SqliteSessionStore};
+use crate::shard::ShardMap;
+use crate::signing::{self, ResponseSigner, SigningConfig};
+use crate::state::CoordinatorState;
+use crate::status::{SharedStatus, StatusSnapshot};
+use crate::tokenizer;
+use crate::tx_status::TxWatcher;
+use crate::user_session::UserSession;
+use crate::wallet::WalletBackend;
+use crate::watch::{WatchEvent, WatchRegistry};
 use crate::zingo_wrapper::ZingoClient;
 use serde_json::{json, Value};
-use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, SystemTime};
 use warp::Filter;
 
 const MAX_PROCESSED_TXIDS: usize = 5000;
 const MAX_RESPONSE_CACHE: usize = 1000;
+const STATE_FILE: &str = "coordinator_state.json";
+const CHAT_ROOMS_FILE: &str = "chat_rooms.json";
+const OUTBOUND_FILE: &str = "outbound_queue.json";
+const AUDIT_FILE: &str = "audit.log";
+const AUTH_AUDIT_FILE: &str = "auth_audit.log";
+const METRICS_FILE: &str = "metrics.prom";
+const PROCESSED_FILE: &str = "processed.json";
+const REPORTS_FILE: &str = "reports.json";
+const RELAYS_FILE: &str = "relays.json";
+const PROFILES_FILE: &str = "profiles.json";
+const CAPABILITIES_FILE: &str = "capabilities.json";
+const SIGNING_KEY_FILE: &str = "coordinator_signing.key";
+const DEFAULT_AUDIT_LIMIT: usize = 20;
+const DEFAULT_INBOX_LIMIT: usize = 10;
+const DEFAULT_RECEIPTS_LIMIT: usize = 10;
+const DEFAULT_LS_LIMIT: usize = 50;
+const DEFAULT_CAT_LEN: usize = 400;
+const DEFAULT_HEAD_LINES: usize = 10;
+
+struct LsOptions {
+    path: String,
+    offset: usize,
+    limit: usize,
+    show_hash: bool,
+    list: ListOptions,
+}
+
+impl LsOptions {
+    fn parse(args: &str) -> Result<Self, String> {
+        let usage = "Invalid ls format. Use: ls [--hash] [--sort=name|mtime|size|owner] [--reverse] [--files|--dirs] [--owner=<user>] <path> [offset] [limit]";
+        let mut show_hash = false;
+        let mut list = ListOptions::default();
+        let mut positional = Vec::new();
+
+        let tokens = tokenizer::tokenize(args)?;
+        for token in &tokens {
+            let Some(flag) = token.strip_prefix("--") else {
+                positional.push(token.as_str());
+                continue;
+            };
+
+            match flag.split_once('=') {
+                Some(("sort", key)) => {
+                    list.sort_by = match key {
+                        "name" => SortKey::Name,
+                        "mtime" => SortKey::Modified,
+                        "size" => SortKey::Size,
+                        "owner" => SortKey::Owner,
+                        _ => return Err(format!("Unknown sort key: {}", key)),
+                    }
+                }
+                Some(("owner", owner)) if !owner.is_empty() => list.owner = Some(owner.to_string()),
+                Some(_) => return Err(format!("Unknown ls flag: --{}", flag)),
+                None => match flag {
+                    "hash" => show_hash = true,
+                    "reverse" => list.reverse = true,
+                    "files" => list.filter = EntryFilter::FilesOnly,
+                    "dirs" => list.filter = EntryFilter::DirsOnly,
+                    _ => return Err(format!("Unknown ls flag: --{}", flag)),
+                },
+            }
+        }
+
+        if positional.len() > 3 {
+            return Err(usage.to_string());
+        }
+
+        let offset = match positional.get(1) {
+            Some(value) => value.parse::<usize>().map_err(|_| usage.to_string())?,
+            None => 0,
+        };
+        let limit = match positional.get(2) {
+            Some(value) => value.parse::<usize>().map_err(|_| usage.to_string())?,
+            None => DEFAULT_LS_LIMIT,
+        };
+        if limit == 0 {
+            return Err(usage.to_string());
+        }
+
+        Ok(LsOptions {
+            path: positional.first().copied().unwrap_or("/").to_string(),
+            offset,
+            limit,
+            show_hash,
+            list,
+        })
+    }
+
+    fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.show_hash {
+            flags.push_str("--hash ");
+        }
+        match self.list.sort_by {
+            SortKey::Name => {}
+            SortKey::Modified => flags.push_str("--sort=mtime "),
+            SortKey::Size => flags.push_str("--sort=size "),
+            SortKey::Owner => flags.push_str("--sort=owner "),
+        }
+        if self.list.reverse {
+            flags.push_str("--reverse ");
+        }
+        match self.list.filter {
+            EntryFilter::All => {}
+            EntryFilter::FilesOnly => flags.push_str("--files "),
+            EntryFilter::DirsOnly => flags.push_str("--dirs "),
+        }
+        if let Some(owner) = &self.list.owner {
+            flags.push_str(&format!("--owner={} ", owner));
+        }
+        flags
+    }
+}
 
-pub struct Coordinator {
+pub struct Coordinator<W: WalletBackend = ZingoClient> {
     auth_flow: AuthenticationFlow,
     verified_users: HashMap<String, String>,
-    pending_challenges: HashMap<String, String>,
+    user_keys: HashMap<String, String>,
+    challenges: ChallengeStore,
+    challenge_config: ChallengeConfig,
+    lockouts: AuthLockouts,
+    lockout_config: LockoutConfig,
+    confirmations: Confirmations,
+    confirmation_config: ConfirmationConfig,
+    probes: Probes,
+    ownership: OwnershipConfig,
+    reassembler: Reassembler,
+    fragment_config: FragmentConfig,
+    checksums: ChecksumConfig,
+    confirmed: bool,
     session_mappings: HashMap<String, String>,
-    conversation_mappings: HashMap<String, String>,
-    user_conversations: HashMap<String, String>,
-    participant_mappings: HashMap<String, String>,
-    conversation_counter: u32,
+    current_session: Option<String>,
+    session_store: Option<Box<dyn SessionStore>>,
+    conversations: ConversationRegistry,
+    conversation_config: ConversationConfig,
+    responses: ResponseConfig,
+    signer: ResponseSigner,
+    signing: SigningConfig,
+    policy: PolicyConfig,
+    health: HealthConfig,
+    last_health: Option<HealthReport>,
+    applied_config: CoordinatorConfig,
+    shards: ShardMap,
+    guests: GuestConfig,
+    priority: PriorityConfig,
+    inbox: MessageQueue,
     pub filesystem: FileSystem,
-    zingo_client: ZingoClient,
+    wallet: W,
+    data_dir: PathBuf,
     db_path: PathBuf,
+    state_path: PathBuf,
     response_cache: HashMap<String, (String, SystemTime)>,
     cache_duration: Duration,
     processed_txids: HashSet<String>,
+    admin_addresses: HashSet<String>,
+    roles: HashMap<String, Role>,
+    at_rest_secret: Option<String>,
+    watches: WatchRegistry,
+    rate_limiter: RateLimiter,
+    chat_rooms: ChatRooms,
+    commands: CommandRegistry<W>,
+    bans: BanList,
+    fees: FeeConfig,
+    outbound: Arc<Mutex<OutboundQueue>>,
+    funds: FundsGate,
+    tx_watcher: TxWatcher,
+    audit_log: AuditLog,
+    auth_log: AuthAuditLog,
+    metrics: Metrics,
+    processed: ProcessedLog,
+    federation: Federation,
+    interceptors: Vec<Arc<dyn MessageInterceptor<W>>>,
+    events: EventBus,
+    reports: Reports,
+    quotas: QuotaTracker,
+    relays: RelayLog,
+    profiles: Profiles,
+    capabilities: Capabilities,
+    scheduler: Scheduler,
+    started_at: u64,
+    sync_height: Option<u64>,
+    last_sync_at: Option<u64>,
+    last_poll_error: Option<String>,
+    status: SharedStatus,
 }
 
 impl Coordinator {
@@ -47,7 +263,65 @@ impl Coordinator {
         database_file: String,
         cache_ttl_secs: u64,
     ) -> Self {
-        let db_path = zingo_data_dir.join(database_file);
+        let wallet = ZingoClient::new(zingo_data_dir.clone(), zingo_server);
+        Coordinator::with_wallet(
+            session_timeout,
+            zingo_data_dir,
+            wallet,
+            database_file,
+            cache_ttl_secs,
+        )
+    }
+
+    pub fn state_files(database_file: &str) -> Vec<String> {
+        [
+            STATE_FILE,
+            database_file,
+            CHAT_ROOMS_FILE,
+            OUTBOUND_FILE,
+            PROCESSED_FILE,
+            REPORTS_FILE,
+            RELAYS_FILE,
+            PROFILES_FILE,
+            CAPABILITIES_FILE,
+            session_store::SESSION_DB_FILE,
+            SIGNING_KEY_FILE,
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+    }
+
+    pub fn stored_sessions(
+        data_dir: &std::path::Path,
+        backend: SessionBackend,
+    ) -> Result<AuthSnapshot, String> {
+        match backend {
+            SessionBackend::Sqlite => {
+                SqliteSessionStore::open(data_dir.join(session_store::SESSION_DB_FILE))?.load()
+            }
+            SessionBackend::State => {
+                let state = CoordinatorState::load(data_dir.join(STATE_FILE))?;
+                Ok(AuthSnapshot {
+                    verified_users: state.verified_users,
+                    session_mappings: state.session_mappings,
+                    sessions: state.sessions,
+                    challenges: state.challenges,
+                })
+            }
+        }
+    }
+}
+
+impl<W: WalletBackend> Coordinator<W> {
+    pub fn with_wallet(
+        session_timeout: u64,
+        data_dir: PathBuf,
+        wallet: W,
+        database_file: String,
+        cache_ttl_secs: u64,
+    ) -> Self {
+        let db_path = data_dir.join(database_file);
 
         let filesystem = FileSystem::load_from_db(&db_path, "coordinator".to_string())
             .unwrap_or_else(|e| {
@@ -55,45 +329,306 @@ impl Coordinator {
                 FileSystem::new("coordinator".to_string())
             });
 
+        let state_path = data_dir.join(STATE_FILE);
+        let state = CoordinatorState::load(&state_path).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load coordinator state: {}", e);
+            CoordinatorState::default()
+        });
+
+        let chat_rooms = ChatRooms::load(data_dir.join(CHAT_ROOMS_FILE)).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load chat rooms: {}", e);
+            ChatRooms::new()
+        });
+
+        let audit_log = AuditLog::new(data_dir.join(AUDIT_FILE));
+        let auth_log = AuthAuditLog::new(data_dir.join(AUTH_AUDIT_FILE));
+        let reports = Reports::load(data_dir.join(REPORTS_FILE)).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load moderation reports: {}", e);
+            Reports::new()
+        });
+        let relays = RelayLog::load(data_dir.join(RELAYS_FILE)).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load relayed messages: {}", e);
+            RelayLog::new()
+        });
+        let profiles = Profiles::load(data_dir.join(PROFILES_FILE)).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load user profiles: {}", e);
+            Profiles::new()
+        });
+        let capabilities =
+            Capabilities::load(data_dir.join(CAPABILITIES_FILE)).unwrap_or_else(|e| {
+                eprintln!("Warning: Could not load capability tokens: {}", e);
+                Capabilities::new()
+            });
+        let signer = ResponseSigner::load_or_create(data_dir.join(SIGNING_KEY_FILE))
+            .unwrap_or_else(|e| {
+                eprintln!("Warning: Using a temporary reply signing key: {}", e);
+                ResponseSigner::generate()
+            });
+        let processed_path = data_dir.join(PROCESSED_FILE);
+        let processed = ProcessedLog::load(&processed_path).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load processed message log: {}", e);
+            let _ = std::fs::rename(
+                &processed_path,
+                processed_path.with_extension("json.corrupt"),
+            );
+            ProcessedLog::load(&processed_path).expect("fresh processed message log")
+        });
+        let outbound_path = data_dir.join(OUTBOUND_FILE);
+        let mut outbound = OutboundQueue::load(&outbound_path).unwrap_or_else(|e| {
+            eprintln!("Warning: Could not load outbound queue: {}", e);
+            let _ = std::fs::rename(&outbound_path, outbound_path.with_extension("json.corrupt"));
+            OutboundQueue::load(&outbound_path).expect("fresh outbound queue")
+        });
+        outbound.set_max_outputs(CoordinatorConfig::default().network.max_outputs_per_tx);
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let scheduler = Scheduler::new(&SchedulerConfig::default(), now);
+
+        let mut conversations =
+            ConversationRegistry::restore(state.conversation_counter, state.conversations);
+        conversations.migrate_legacy(&state.conversation_mappings, &state.verified_users, now);
+
+        let mut challenges = ChallengeStore::new(
+            state.challenges,
+            state.spent_challenges,
+            state.issued_challenges,
+        );
+        challenges.migrate_legacy(&state.pending_challenges, now);
+
+        let mut auth_flow = AuthenticationFlow::new(session_timeout);
+        for mut session in state.sessions {
+            if session.session_id.is_empty() {
+                session.session_id = state
+                    .session_mappings
+                    .iter()
+                    .find(|(_, reply_address)| **reply_address == session.reply_address)
+                    .map(|(session_id, _)| session_id.clone())
+                    .unwrap_or_default();
+            }
+            auth_flow.session_manager.restore_session(session);
+        }
+
         Coordinator {
-            auth_flow: AuthenticationFlow::new(session_timeout),
-            verified_users: HashMap::new(),
-            pending_challenges: HashMap::new(),
-            session_mappings: HashMap::new(),
-            conversation_mappings: HashMap::new(),
-            user_conversations: HashMap::new(),
-            participant_mappings: HashMap::new(),
-            conversation_counter: 1000,
+            auth_flow,
+            verified_users: state.verified_users,
+            user_keys: state.user_keys,
+            challenges,
+            challenge_config: ChallengeConfig::default(),
+            lockouts: AuthLockouts::new(),
+            lockout_config: LockoutConfig::default(),
+            confirmations: Confirmations::new(),
+            confirmation_config: ConfirmationConfig::default(),
+            probes: Probes::new(),
+            ownership: OwnershipConfig::default(),
+            reassembler: Reassembler::new(),
+            fragment_config: FragmentConfig::default(),
+            checksums: ChecksumConfig::default(),
+            confirmed: false,
+            session_mappings: state.session_mappings,
+            current_session: None,
+            session_store: None,
+            conversations,
+            conversation_config: ConversationConfig::default(),
+            responses: ResponseConfig::default(),
+            signer,
+            signing: SigningConfig::default(),
+            policy: PolicyConfig::default(),
+            health: HealthConfig::default(),
+            last_health: None,
+            applied_config: CoordinatorConfig::default(),
+            shards: ShardMap::new(),
+            guests: GuestConfig::default(),
+            priority: PriorityConfig::default(),
+            inbox: MessageQueue::new(),
             filesystem,
-            zingo_client: ZingoClient::new(zingo_data_dir, zingo_server),
+            wallet,
+            data_dir,
             db_path,
+            state_path,
             response_cache: HashMap::new(),
             cache_duration: Duration::from_secs(cache_ttl_secs.max(1)),
             processed_txids: HashSet::new(),
+            admin_addresses: HashSet::new(),
+            roles: state.roles,
+            at_rest_secret: None,
+            watches: WatchRegistry::new(),
+            rate_limiter: RateLimiter::new(Default::default()),
+            chat_rooms,
+            commands: Self::builtin_commands(),
+            bans: BanList::new(state.banned_users),
+            federation: Federation::new(
+                state.federation_seq,
+                state.federation_seen,
+                state.federation_log,
+            ),
+            quotas: QuotaTracker::new(state.quota_usage),
+            interceptors: Vec::new(),
+            events: EventBus::new(),
+            reports,
+            relays,
+            profiles,
+            capabilities,
+            scheduler,
+            started_at: now,
+            sync_height: None,
+            last_sync_at: None,
+            last_poll_error: None,
+            status: Arc::new(Mutex::new(StatusSnapshot::default())),
+            fees: CoordinatorConfig::default().fees,
+            audit_log,
+            auth_log,
+            metrics: Metrics::new(),
+            processed,
+            outbound: Arc::new(Mutex::new(outbound)),
+            funds: FundsGate::default(),
+            tx_watcher: TxWatcher::new(),
         }
     }
 
-    fn generate_conversation_id(&mut self) -> String {
-        self.conversation_counter += 1;
-        format!("CONV{:04}", self.conversation_counter)
+    pub fn register_command(&mut self, handler: Arc<dyn CommandHandler<W>>) -> Result<(), String> {
+        self.commands.register(handler)
     }
 
-    fn generate_participant_id(&self, user_address: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(user_address.as_bytes());
-        hasher.update(b"zatboard_participant");
-        format!(
-            "P{}",
-            &format!("{:x}", hasher.finalize())[..6].to_uppercase()
-        )
+    pub fn add_interceptor(&mut self, interceptor: Arc<dyn MessageInterceptor<W>>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub fn subscribe(&mut self, hook: Arc<dyn EventHook>) {
+        self.events.subscribe(hook);
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.metrics.on_event(&event);
+        self.audit_log.on_event(&event);
+        self.auth_log.on_event(&event);
+        if let Some((actor, path, change)) = event.as_file_change() {
+            self.notify_watchers(actor, path, change);
+        }
+        self.events.publish(&event);
+    }
+
+    pub fn apply_config(&mut self, config: &CoordinatorConfig) {
+        self.filesystem.limits = config.limits.clone();
+        self.admin_addresses = config.admin.addresses.iter().cloned().collect();
+        self.at_rest_secret = config.storage.encryption_secret.clone();
+        self.rate_limiter.set_config(config.rate_limit.clone());
+        self.fees = config.fees.clone();
+        let mut queue = self.lock_outbound();
+        queue.set_max_fee(config.network.max_fee_zatoshis);
+        queue.set_max_outputs(config.network.max_outputs_per_tx);
+        drop(queue);
+        self.federation.set_config(config.federation.clone());
+        self.quotas.set_config(config.quotas.clone());
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.scheduler.set_config(&config.scheduler, now);
+        self.conversation_config = config.conversations.clone();
+        self.responses = config.responses.clone();
+        self.signing = config.signing.clone();
+        self.policy = config.policy.clone();
+        self.health = config.health.clone();
+        self.guests = config.guests.clone();
+        self.challenge_config = config.challenges.clone();
+        self.auth_flow
+            .set_challenge_secret(config.challenges.secret.as_deref());
+        self.lockout_config = config.lockout.clone();
+        self.confirmation_config = config.confirmations.clone();
+        self.ownership = config.ownership.clone();
+        self.fragment_config = config.fragments.clone();
+        self.checksums = config.checksums.clone();
+        self.priority = config.priority.clone();
+        self.auth_flow
+            .session_manager
+            .set_timeout(config.sessions.timeout_secs);
+        self.auth_flow
+            .session_manager
+            .set_max_per_user(config.sessions.max_per_user);
+        self.set_session_backend(config.sessions.store);
+        self.cache_duration = Duration::from_secs(config.storage.cache_ttl_secs.max(1));
+
+        self.commands.clear_aliases();
+        for (alias, target) in &config.aliases {
+            if let Err(e) = self.commands.add_alias(alias, target) {
+                eprintln!("Warning: Ignoring command alias: {}", e);
+            }
+        }
+        self.shards.clear();
+        for (prefix, address) in &config.shards.routes {
+            if let Err(e) = self.shards.add_route(prefix, address) {
+                eprintln!("Warning: Ignoring shard route: {}", e);
+            }
+        }
+        self.applied_config = config.clone();
+    }
+
+    pub fn reload_config(&mut self, config: &CoordinatorConfig) -> Vec<&'static str> {
+        let restart_required = self.applied_config.restart_required(config);
+        self.apply_config(config);
+        restart_required
+    }
+
+    fn node_key(&self, owner: &str) -> Option<NodeKey> {
+        self.at_rest_secret
+            .as_deref()
+            .map(|secret| crypto::derive_node_key(secret, owner))
+    }
+
+    fn read_key(&self, user_id: &str, node: &FileNode) -> Result<Option<NodeKey>, String> {
+        if !node.encrypted {
+            return Ok(None);
+        }
+        if !self
+            .filesystem
+            .principal(user_id)
+            .can_decrypt(&node.permissions)
+        {
+            return Err(
+                "Permission denied: encrypted files are readable by their owner and granted readers"
+                    .to_string(),
+            );
+        }
+        Ok(self.node_key(&node.permissions.owner))
+    }
+
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        self.role_of(user_id) == Role::Admin
+    }
+
+    pub fn role_of(&self, user_id: &str) -> Role {
+        if self.admin_addresses.contains(user_id) {
+            return Role::Admin;
+        }
+        self.roles.get(user_id).copied().unwrap_or(Role::Member)
+    }
+
+    fn can_run(&self, role: Role, spec: &CommandSpec) -> bool {
+        match role {
+            Role::Guest => {
+                spec.role <= Role::Member
+                    && spec.class == CommandClass::Read
+                    && self.guests.allows(&spec.name)
+            }
+            role => role >= spec.role,
+        }
     }
 
     fn truncate_for_log(value: &str, max_chars: usize) -> String {
         value.chars().take(max_chars).collect()
     }
 
-    fn get_cached_response(&self, command: &str) -> Option<String> {
-        if let Some((response, timestamp)) = self.response_cache.get(command) {
+    fn cache_key(user_id: &str, command: &str) -> String {
+        format!("{}|{}", user_id, command)
+    }
+
+    fn get_cached_response(&self, user_id: &str, command: &str) -> Option<String> {
+        let key = Self::cache_key(user_id, command);
+        if let Some((response, timestamp)) = self.response_cache.get(&key) {
             if timestamp.elapsed().unwrap() < self.cache_duration {
                 return Some(response.clone());
             }
@@ -101,17 +636,12 @@ impl Coordinator {
         None
     }
 
-    fn cache_response(&mut self, command: &str, response: &str) {
-        if command.starts_with("ls ")
-            || command.starts_with("cat ")
-            || command.starts_with("history ")
-        {
-            self.prune_response_cache();
-            self.response_cache.insert(
-                command.to_string(),
-                (response.to_string(), SystemTime::now()),
-            );
-        }
+    fn cache_response(&mut self, user_id: &str, command: &str, response: &str) {
+        self.prune_response_cache();
+        self.response_cache.insert(
+            Self::cache_key(user_id, command),
+            (response.to_string(), SystemTime::now()),
+        );
     }
 
     fn prune_response_cache(&mut self) {
@@ -144,763 +674,9101 @@ impl Coordinator {
         }
     }
 
-    fn save_filesystem(&self) -> Result<(), String> {
-        self.filesystem.save_to_db(&self.db_path)
+    fn auth_snapshot(&self) -> AuthSnapshot {
+        AuthSnapshot {
+            verified_users: self.verified_users.clone(),
+            session_mappings: self.session_mappings.clone(),
+            sessions: self.auth_flow.session_manager.sessions(),
+            challenges: self.challenges.pending().clone(),
+        }
     }
 
-    pub fn send_response(&mut self, user_id: &str, response: &str) -> Result<(), String> {
-        if let Some(reply_address) = self.get_reply_address(user_id) {
-            let reply_preview = Self::truncate_for_log(&reply_address, 8);
-            let response_preview = Self::truncate_for_log(response, 50);
-            println!(
-                "📤 Sending response to {}: {}",
-                reply_preview, response_preview
-            );
-            match self.zingo_client.send_memo(&reply_address, 0, response) {
-                Ok(_result) => {
-                    println!("✅ Response sent successfully");
-                    Ok(())
-                }
-                Err(e) => {
-                    println!("❌ Send failed: {}", e);
-                    Err(format!("Failed to send response: {}", e))
+    fn restore_auth(&mut self, snapshot: AuthSnapshot) {
+        self.verified_users = snapshot.verified_users;
+        self.session_mappings = snapshot.session_mappings;
+        self.challenges = ChallengeStore::new(
+            snapshot.challenges,
+            self.challenges.spent().clone(),
+            self.challenges.issued().clone(),
+        );
+        self.auth_flow.session_manager.clear();
+        for session in snapshot.sessions {
+            self.auth_flow.session_manager.restore_session(session);
+        }
+    }
+
+    fn set_session_backend(&mut self, backend: SessionBackend) {
+        match (backend, self.session_store.is_some()) {
+            (SessionBackend::Sqlite, false) => {
+                let path = self
+                    .state_path
+                    .with_file_name(session_store::SESSION_DB_FILE);
+                let opened = SqliteSessionStore::open(&path).and_then(|store| {
+                    let snapshot = store.load()?;
+                    Ok((store, snapshot))
+                });
+                match opened {
+                    Ok((store, snapshot)) => {
+                        if !snapshot.is_empty() {
+                            self.restore_auth(snapshot);
+                        }
+                        println!("🗄️  Storing sessions in {}", store.location());
+                        self.session_store = Some(Box::new(store));
+                        self.save_state();
+                    }
+                    Err(e) => eprintln!("Warning: Keeping sessions in the state file: {}", e),
                 }
             }
-        } else {
-            Err("No reply address found for user".to_string())
+            (SessionBackend::State, true) => {
+                self.session_store = None;
+                self.save_state();
+            }
+            _ => {}
         }
     }
 
-    pub fn process_and_respond(&mut self, message: &Message) -> Result<(), String> {
-        let response = self.process_incoming_message(message)?;
-        self.send_response(&message.sender_address, &response)?;
-        Ok(())
+    fn save_state(&self) {
+        let auth = self.auth_snapshot();
+        let auth = match &self.session_store {
+            Some(store) => match store.save(&auth) {
+                Ok(()) => AuthSnapshot::default(),
+                Err(e) => {
+                    eprintln!("Warning: Failed to persist sessions: {}", e);
+                    auth
+                }
+            },
+            None => auth,
+        };
+        let state = CoordinatorState {
+            verified_users: auth.verified_users,
+            user_keys: self.user_keys.clone(),
+            pending_challenges: HashMap::new(),
+            challenges: auth.challenges,
+            spent_challenges: self.challenges.spent().clone(),
+            issued_challenges: self.challenges.issued().clone(),
+            session_mappings: auth.session_mappings,
+            conversation_mappings: HashMap::new(),
+            user_conversations: HashMap::new(),
+            participant_mappings: HashMap::new(),
+            conversation_counter: self.conversations.counter(),
+            conversations: self.conversations.conversations(),
+            sessions: auth.sessions,
+            banned_users: self.bans.entries().clone(),
+            roles: self.roles.clone(),
+            federation_seq: self.federation.next_seq(),
+            federation_seen: self.federation.last_seen().clone(),
+            federation_log: self.federation.log().clone(),
+            quota_usage: self.quotas.usage().clone(),
+        };
+
+        if let Err(e) = state.save(&self.state_path) {
+            eprintln!("Warning: Failed to persist coordinator state: {}", e);
+        }
     }
 
-    fn handle_authenticated_command(&mut self, message: &Message) -> Result<String, String> {
-        if let Some(cached) = self.get_cached_response(&message.memo_text) {
-            return Ok(cached);
+    pub fn shutdown(&mut self) -> Result<(), String> {
+        let report = self.flush_outbound();
+        let pending = self.pending_outbound();
+        if pending > 0 {
+            println!(
+                "📦 {} replies left in the outbound queue for next start",
+                pending
+            );
         }
+        println!("📤 Flushed {} queued replies before shutdown", report.sent);
 
-        let user_id = &message.sender_address;
+        let mut errors = Vec::new();
+        if let Err(e) = self.flush_state() {
+            errors.push(e);
+        }
+        if let Err(e) = self.export_metrics() {
+            errors.push(e);
+        }
 
-        let result = if message.memo_text.starts_with("chmod ") {
-            let parts: Vec<&str> = message.memo_text.splitn(3, ' ').collect();
-            if parts.len() >= 3 {
-                let permissions = parts[1];
-                let path = parts[2];
-                self.handle_chmod_command(user_id, path, permissions)
-            } else {
-                Err("Invalid chmod format. Use: chmod <permissions> <path>".to_string())
-            }
-        } else if message.memo_text.starts_with("chown ") {
-            let parts: Vec<&str> = message.memo_text.splitn(3, ' ').collect();
-            if parts.len() >= 3 {
-                let new_owner = parts[1];
-                let path = parts[2];
-                self.handle_chown_command(user_id, path, new_owner)
-            } else {
-                Err("Invalid chown format. Use: chown <user> <path>".to_string())
-            }
-        } else if message.memo_text.starts_with("grant ") {
-            let parts: Vec<&str> = message.memo_text.splitn(4, ' ').collect();
-            if parts.len() >= 4 {
-                let permission_type = parts[1];
-                let target_user = parts[2];
-                let path = parts[3];
-                self.handle_grant_command(user_id, path, target_user, permission_type)
-            } else {
-                Err("Invalid grant format. Use: grant <read|write> <user> <path>".to_string())
-            }
-        } else if message.memo_text.starts_with("ls ") {
-            let path = message.memo_text.strip_prefix("ls ").unwrap_or("/");
-            self.handle_ls_command(user_id, path)
-        } else if message.memo_text.starts_with("cat ") {
-            let path = message.memo_text.strip_prefix("cat ").unwrap();
-            self.handle_cat_command(user_id, path)
-        } else if message.memo_text.starts_with("mkdir ") {
-            let path = message.memo_text.strip_prefix("mkdir ").unwrap();
-            self.handle_mkdir_command(user_id, path)
-        } else if message.memo_text.starts_with("rm ") {
-            let path = message.memo_text.strip_prefix("rm ").unwrap();
-            self.handle_rm_command(user_id, path)
-        } else if message.memo_text.contains(" > ") {
-            self.handle_echo_command(user_id, &message.memo_text)
-        } else if message.memo_text.starts_with("touch ") {
-            let parts: Vec<&str> = message.memo_text.splitn(3, ' ').collect();
-            if parts.len() >= 2 {
-                let path = parts[1];
-                let content = if parts.len() == 3 { parts[2] } else { "" };
-                self.handle_touch_command(user_id, path, content)
-            } else {
-                Err("Invalid touch command".to_string())
-            }
-        } else if message.memo_text.starts_with("permissions ") {
-            let path = message.memo_text.strip_prefix("permissions ").unwrap();
-            self.handle_permissions_command(user_id, path)
-        } else if message.memo_text.starts_with("chat ") {
-            let parts: Vec<&str> = message.memo_text.splitn(3, ' ').collect();
-            if parts.len() >= 3 {
-                let folder = parts[1];
-                let chat_message = parts[2].trim_matches('"');
-                self.handle_chat_command(user_id, folder, chat_message)
-            } else {
-                Err("Invalid chat format. Use: chat <folder> \"message\"".to_string())
-            }
-        } else if message.memo_text.starts_with("history ") {
-            let folder = message.memo_text.strip_prefix("history ").unwrap();
-            self.handle_history_command(user_id, folder)
+        if errors.is_empty() {
+            Ok(())
         } else {
-            Err("Unknown command. Try: ls, cat, mkdir, rm, echo, touch, chmod, chown, grant, chat, history".to_string())
-        };
-
-        if let Ok(ref response) = result {
-            self.cache_response(&message.memo_text, response);
+            Err(errors.join("; "))
         }
-
-        result
     }
 
-    fn handle_permissions_command(&self, user_id: &str, path: &str) -> Result<String, String> {
-        let node = self
-            .filesystem
-            .resolve_path(path)
-            .ok_or_else(|| format!("Path not found: {}", path))?;
-
-        if !node.permissions.can_read(user_id) {
-            return Err("Permission denied: cannot view permissions".to_string());
+    fn flush_state(&self) -> Result<(), String> {
+        self.save_state();
+        let mut errors = Vec::new();
+        if let Err(e) = self.save_filesystem() {
+            errors.push(e);
+        }
+        if let Err(e) = self.save_chat_rooms() {
+            errors.push(e);
         }
 
-        let mut result = format!("Permissions for {}:\n", path);
-        result.push_str(&format!("Owner: {}\n", node.permissions.owner));
-        result.push_str(&format!("Public read: {}\n", node.permissions.public_read));
-        result.push_str(&format!(
-            "Public write: {}\n",
-            node.permissions.public_write
-        ));
-        result.push_str(&format!("Read users: {:?}\n", node.permissions.read_users));
-        result.push_str(&format!("Write users: {:?}", node.permissions.write_users));
-
-        Ok(result)
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
     }
 
-    fn handle_chmod_command(
-        &mut self,
-        user_id: &str,
-        path: &str,
-        permissions: &str,
-    ) -> Result<String, String> {
-        let node = self
-            .filesystem
-            .resolve_path_mut(path)
-            .ok_or_else(|| format!("Path not found: {}", path))?;
+    pub fn run_due_jobs(&mut self) -> usize {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.run_jobs_at(now)
+    }
 
-        if node.permissions.owner != user_id {
-            return Err("Permission denied: only owner can change permissions".to_string());
+    fn run_jobs_at(&mut self, now: u64) -> usize {
+        let due = self.scheduler.due(now);
+        for job in &due {
+            let result = self.run_job(*job);
+            if let Err(e) = &result {
+                eprintln!("⚠️  Scheduled job {} failed: {}", job.as_str(), e);
+            }
+            self.scheduler.record(*job, now, result);
         }
+        due.len()
+    }
 
-        match permissions {
-            "public" => {
-                node.permissions.public_read = true;
-                node.permissions.public_write = false;
+    fn run_job(&mut self, job: Job) -> Result<String, String> {
+        match job {
+            Job::SessionCleanup => {
+                self.cleanup_expired_sessions();
+                let expired = self.prune_challenges();
+                let tokens = self.prune_capabilities()?;
+                self.prune_lockouts();
+                self.prune_confirmations();
+                self.prune_probes();
+                self.prune_fragments();
+                Ok(format!(
+                    "{} sessions active, {} challenges expired, {} capability tokens expired",
+                    self.session_mappings.len(),
+                    expired,
+                    tokens
+                ))
             }
-            "private" => {
-                node.permissions.public_read = false;
-                node.permissions.public_write = false;
+            Job::Gc => self.handle_gc_command(),
+            Job::StateFlush => self.flush_state().map(|_| "state saved".to_string()),
+            Job::QuotaPrune => {
+                self.prune_quotas();
+                Ok(format!("{} users tracked", self.quotas.usage().len()))
             }
-            "open" => {
-                node.permissions.public_read = true;
-                node.permissions.public_write = true;
+            Job::ConversationRetire => {
+                let retired = self.retire_stale_conversations();
+                Ok(format!("{} conversations retired", retired))
+            }
+            Job::HealthCheck => {
+                let report = self.run_health_check()?;
+                match report.hold_reason() {
+                    Some(reason) => Err(format!("replies held: {}", reason)),
+                    None => Ok(format!("health {}", report.state().as_str())),
+                }
+            }
+            Job::TxWatch => {
+                let changes = self.refresh_receipts()?;
+                Ok(format!("{} reply transactions changed state", changes))
             }
-            _ => return Err("Invalid permissions. Use: public, private, or open".to_string()),
         }
+    }
 
-        self.save_filesystem()?;
-        Ok(format!("Permissions updated for {}", path))
+    fn save_filesystem(&self) -> Result<(), String> {
+        self.filesystem.save_to_db(&self.db_path)
     }
 
-    fn handle_chown_command(
-        &mut self,
-        user_id: &str,
-        path: &str,
-        new_owner: &str,
-    ) -> Result<String, String> {
-        let node = self
-            .filesystem
-            .resolve_path_mut(path)
-            .ok_or_else(|| format!("Path not found: {}", path))?;
+    pub fn send_response(&mut self, user_id: &str, response: &str) -> Result<(), String> {
+        let reply_address = self
+            .get_reply_address(user_id)
+            .ok_or_else(|| "No reply address found for user".to_string())?;
 
-        if node.permissions.owner != user_id {
-            return Err("Permission denied: only owner can change ownership".to_string());
+        let memos = memo_decoder::split_into_memos_within(response, self.memo_budget());
+        self.queue_reply_memos(&reply_address, memos, None)
+            .map(|_| ())
+    }
+
+    fn memo_budget(&self) -> usize {
+        if self.signing.enabled {
+            memo_decoder::MAX_MEMO_SIZE - signing::SIGNATURE_OVERHEAD
+        } else {
+            memo_decoder::MAX_MEMO_SIZE
         }
+    }
 
-        node.permissions.owner = new_owner.to_string();
-        node.permissions.read_users.clear();
-        node.permissions.write_users.clear();
-        node.permissions.read_users.push(new_owner.to_string());
-        node.permissions.write_users.push(new_owner.to_string());
+    fn admit_reply(&mut self) -> Result<(), String> {
+        if !self.health.enabled {
+            return Ok(());
+        }
+        let queued = self.pending_outbound();
+        self.funds
+            .admit(queued, 1)
+            .inspect_err(|_| self.metrics.record_refused(1))
+    }
 
-        self.save_filesystem()?;
-        Ok(format!(
-            "Ownership of {} transferred to {}",
-            path, new_owner
-        ))
+    fn queue_reply_memos(
+        &self,
+        address: &str,
+        memos: Vec<String>,
+        reply_to: Option<&str>,
+    ) -> Result<Vec<u64>, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut queue = self.lock_outbound();
+        memos
+            .iter()
+            .map(|memo| {
+                let memo = if self.signing.enabled {
+                    self.signer.sign(address, memo)
+                } else {
+                    memo.clone()
+                };
+                queue.enqueue_reply(address, &memo, reply_to, now)
+            })
+            .collect()
     }
 
-    fn handle_grant_command(
+    fn send_reply(
         &mut self,
-        user_id: &str,
-        path: &str,
-        target_user: &str,
-        permission_type: &str,
-    ) -> Result<String, String> {
-        let node = self
-            .filesystem
-            .resolve_path_mut(path)
-            .ok_or_else(|| format!("Path not found: {}", path))?;
-
-        if node.permissions.owner != user_id {
-            return Err("Permission denied: only owner can grant permissions".to_string());
-        }
-
-        match permission_type {
-            "read" => {
-                node.permissions
-                    .add_read_permission(target_user.to_string());
-                self.save_filesystem()?;
-                Ok(format!(
-                    "Read permission granted to {} for {}",
-                    target_user, path
-                ))
+        message: &Message,
+        result: &Result<String, String>,
+        sealed: bool,
+    ) -> Result<(), String> {
+        let (reply_address, command) = self
+            .reply_route(message)
+            .ok_or_else(|| "No reply address found for user".to_string())?;
+        let peer_key = if sealed {
+            Some(
+                self.user_keys
+                    .get(&message.sender_address)
+                    .cloned()
+                    .ok_or_else(|| "No public key registered to encrypt the reply".to_string())?,
+            )
+        } else {
+            None
+        };
+        let thread_header = message
+            .txid
+            .as_deref()
+            .filter(|_| self.responses.threading)
+            .map(response::reply_ref_header);
+        let budget = match peer_key {
+            Some(_) => e2e::plaintext_budget(self.memo_budget()),
+            None => self.memo_budget(),
+        } - thread_header.as_ref().map_or(0, String::len);
+        let mut memos = if self.responses.envelope {
+            response::envelope_memos(command, result, budget)
+        } else {
+            match result {
+                Ok(response) | Err(response) => {
+                    memo_decoder::split_into_memos_within(response, budget)
+                }
             }
-            "write" => {
-                node.permissions
-                    .add_write_permission(target_user.to_string());
-                self.save_filesystem()?;
-                Ok(format!(
-                    "Write permission granted to {} for {}",
-                    target_user, path
-                ))
+        };
+        if let Some(header) = thread_header {
+            memos = memos
+                .into_iter()
+                .map(|memo| format!("{}{}", header, memo))
+                .collect();
+        }
+        if let Some(peer_key) = peer_key {
+            let key = self.signer.e2e_key();
+            memos = memos
+                .iter()
+                .map(|memo| key.seal(&peer_key, memo))
+                .collect::<Result<_, _>>()?;
+        }
+        if result.is_ok() {
+            if let Err(e) = self.send_ack(message, Ack::Done(memos.len()), sealed) {
+                eprintln!(
+                    "⚠️  Failed to acknowledge {}: {}",
+                    message.sender_address, e
+                );
             }
-            _ => Err("Invalid permission type. Use: read or write".to_string()),
         }
+        self.queue_reply_memos(&reply_address, memos, message.txid.as_deref())
+            .map(|_| ())
     }
 
-    fn handle_ls_command(&self, user_id: &str, path: &str) -> Result<String, String> {
-        let node = self
-            .filesystem
-            .resolve_path(path)
-            .ok_or_else(|| format!("Path not found: {}", path))?;
-
-        if !node.permissions.can_read(user_id) {
-            return Err("Permission denied: cannot read directory".to_string());
+    fn send_ack(&mut self, message: &Message, ack: Ack, sealed: bool) -> Result<(), String> {
+        let Some(txid) = message.txid.as_deref().filter(|_| self.responses.acks) else {
+            return Ok(());
+        };
+        if message.memo_text.starts_with(FEDERATION_PREFIX)
+            || self.is_banned(&message.sender_address)
+            || self.rate_limiter.is_throttled(&message.sender_address)
+        {
+            return Ok(());
         }
+        let Some((reply_address, _)) = self.reply_route(message) else {
+            return Ok(());
+        };
+        let mut memo = format!("{}{}", response::reply_ref_header(txid), ack.encode());
+        if let Some(peer_key) = self
+            .user_keys
+            .get(&message.sender_address)
+            .filter(|_| sealed)
+        {
+            memo = self.signer.e2e_key().seal(peer_key, &memo)?;
+        }
+        self.queue_reply_memos(&reply_address, vec![memo], Some(txid))
+            .map(|_| ())
+    }
 
-        if node.file_type != crate::filesystem::FileType::Directory {
-            return Err("Not a directory".to_string());
+    fn ack_seen(&mut self, message: &Message, opened: &Message) {
+        if let Some(Ok(fragment)) = Fragment::parse(&opened.memo_text) {
+            if fragment.index > 1 {
+                return;
+            }
+        }
+        let decoded = opened
+            .decode_memo()
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| opened.clone());
+        let decoded = self.resolve_reply_to(&decoded);
+        let sealed = e2e::is_encrypted(&message.memo_text);
+        if let Err(e) = self.send_ack(&decoded, Ack::Seen, sealed) {
+            eprintln!(
+                "⚠️  Failed to acknowledge {}: {}",
+                message.sender_address, e
+            );
         }
+    }
 
-        let listing = node.list_children();
-        if listing.is_empty() {
-            Ok("(empty directory)".to_string())
-        } else {
-            Ok(listing.join("  "))
+    fn reply_route<'a>(&self, message: &'a Message) -> Option<(String, &'a str)> {
+        match guest::parse(&message.memo_text) {
+            Some(Ok(request)) if self.guests.enabled => {
+                Some((request.reply_address.to_string(), request.command))
+            }
+            Some(_) => None,
+            None => match capability::parse(&message.memo_text) {
+                Some(Ok(request)) if self.capabilities.contains(request.token) => {
+                    Some((request.reply_address.to_string(), request.command))
+                }
+                Some(_) => None,
+                None => self
+                    .command_session(&message.sender_address, &message.memo_text)
+                    .and_then(|session_id| self.auth_flow.session_manager.session(&session_id))
+                    .map(|session| session.reply_address.clone())
+                    .or_else(|| self.signed_reply_to(message))
+                    .or_else(|| self.get_reply_address(&message.sender_address))
+                    .map(|address| (address, message.memo_text.as_str())),
+            },
         }
     }
 
-    fn handle_cat_command(&self, user_id: &str, path: &str) -> Result<String, String> {
-        let node = self
-            .filesystem
-            .resolve_path(path)
-            .ok_or_else(|| format!("File not found: {}", path))?;
+    fn signed_reply_to(&self, message: &Message) -> Option<String> {
+        let reply_to = message.reply_to.as_ref()?;
+        let public_key = self.user_keys.get(&message.sender_address)?;
+        identity::verify_command(public_key, reply_to, &message.memo_text).ok()?;
+        Some(reply_to.clone())
+    }
 
-        if !node.permissions.can_read(user_id) {
-            return Err("Permission denied: cannot read file".to_string());
+    fn resolve_reply_to(&self, message: &Message) -> Message {
+        let message = message.clone().take_reply_to();
+        let Some(reply_to) = message.reply_to.as_deref() else {
+            return message;
+        };
+        if self.user_keys.contains_key(&message.sender_address) {
+            return message;
         }
-
-        if node.file_type != crate::filesystem::FileType::File {
-            return Err("Not a file".to_string());
+        let owner = self.user_keys.iter().find(|(user, public_key)| {
+            let known = self.verified_users.get(*user).map(String::as_str) == Some(reply_to)
+                || self
+                    .auth_flow
+                    .session_manager
+                    .sessions_for(user)
+                    .iter()
+                    .any(|session| session.reply_address == reply_to);
+            known && identity::verify_command(public_key, reply_to, &message.memo_text).is_ok()
+        });
+        match owner {
+            Some((user, _)) => Message {
+                sender_address: user.clone(),
+                ..message.clone()
+            },
+            None => message,
         }
-
-        Ok(node
-            .content
-            .clone()
-            .unwrap_or_else(|| "(empty file)".to_string()))
     }
 
-    fn handle_mkdir_command(&mut self, user_id: &str, path: &str) -> Result<String, String> {
-        match self.filesystem.create_directory(path, user_id.to_string()) {
-            Ok(()) => {
-                let response = format!("Directory created: {}", path);
-
-                if let Err(e) = self.save_filesystem() {
-                    eprintln!("Warning: Failed to persist filesystem: {}", e);
-                }
+    fn lock_outbound(&self) -> MutexGuard<'_, OutboundQueue> {
+        self.outbound
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 
-                Ok(response)
-            }
-            Err(e) => Err(e),
-        }
+    pub fn outbound_queue(&self) -> Arc<Mutex<OutboundQueue>> {
+        Arc::clone(&self.outbound)
     }
 
-    fn handle_touch_command(
-        &mut self,
-        user_id: &str,
-        path: &str,
-        content: &str,
-    ) -> Result<String, String> {
-        match self
-            .filesystem
-            .create_file(path, content.to_string(), user_id.to_string())
-        {
-            Ok(()) => {
-                self.save_filesystem()?;
-                Ok(format!("File created: {}", path))
-            }
-            Err(e) => Err(e),
-        }
+    pub fn wallet(&self) -> W {
+        self.wallet.clone()
     }
 
-    fn handle_rm_command(&mut self, user_id: &str, path: &str) -> Result<String, String> {
-        match self.filesystem.remove(path, user_id) {
-            Ok(()) => {
-                self.save_filesystem()?;
-                Ok(format!("Directory removed: {}", path))
-            }
-            Err(e) => Err(e),
-        }
+    pub fn flush_outbound(&mut self) -> FlushReport {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let (batch, max_fee, max_outputs) = {
+            let queue = self.lock_outbound();
+            (queue.due(now), queue.max_fee(), queue.max_outputs())
+        };
+        let results = OutboundQueue::send_grouped(&batch, max_outputs, |items| {
+            outbound::deliver(&self.wallet, items, max_fee)
+        });
+        let report = self.lock_outbound().record_results(now, results);
+        self.record_flush(&report);
+        report
     }
 
-    fn handle_echo_command(&mut self, user_id: &str, command: &str) -> Result<String, String> {
-        let parts: Vec<&str> = command.splitn(2, " > ").collect();
-        if parts.len() != 2 {
-            return Err("Invalid echo format. Use: echo \"content\" > <file>".to_string());
+    pub fn refresh_receipts(&mut self) -> Result<usize, String> {
+        let txids = self.lock_outbound().unconfirmed_txids();
+        for txid in txids {
+            self.tx_watcher.watch(&txid);
         }
+        let changes = self.tx_watcher.poll(&self.wallet)?;
+        let mut queue = self.lock_outbound();
+        for (txid, status) in &changes {
+            println!("⛓️  Reply transaction {} is {}", txid, status.describe());
+            queue.update_status(txid, *status)?;
+        }
+        Ok(changes.len())
+    }
 
-        let echo_part = parts[0].trim();
-        let file_path = parts[1].trim();
-
-        if !echo_part.starts_with("echo ") {
-            return Err("Command must start with 'echo'".to_string());
+    pub fn run_health_check(&mut self) -> Result<HealthReport, String> {
+        if !self.health.enabled {
+            self.lock_outbound().resume();
+            return Err("Health checks are disabled".to_string());
         }
 
-        let content_part = echo_part.strip_prefix("echo ").unwrap().trim();
-        let content = if content_part.starts_with('"') && content_part.ends_with('"') {
-            content_part[1..content_part.len() - 1].to_string()
-        } else {
-            content_part.to_string()
+        let probe = Probe {
+            height: self.wallet.height(),
+            balance: self.wallet.balance(),
+            last_sync_at: self.last_sync_at,
         };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.funds.observe(probe.balance.clone());
+        let report = HealthReport::assess(&self.health, &probe, now);
+        self.apply_health(&report);
+        Ok(report)
+    }
 
-        if let Some(file_node) = self.filesystem.resolve_path_mut(file_path) {
-            if file_node.file_type == crate::filesystem::FileType::File {
-                if file_node.permissions.can_write(user_id) {
-                    file_node.update_content(content)?;
-                    self.save_filesystem()?;
-                    Ok(format!("File updated: {}", file_path))
-                } else {
-                    Err("Permission denied: cannot write to file".to_string())
+    fn apply_health(&mut self, report: &HealthReport) {
+        let mut queue = self.lock_outbound();
+        match report.hold_reason() {
+            Some(reason) => {
+                if queue.held().is_none() {
+                    eprintln!("⚠️  Holding outbound replies: {}", reason);
                 }
-            } else {
-                Err("Cannot write to directory".to_string())
+                queue.hold(&reason);
             }
-        } else {
-            match self
-                .filesystem
-                .create_file(file_path, content, user_id.to_string())
-            {
-                Ok(()) => {
-                    self.save_filesystem()?;
-                    Ok(format!("File created: {}", file_path))
+            None => {
+                if queue.held().is_some() {
+                    println!("✅ Health restored, resuming outbound replies");
                 }
-                Err(e) => Err(e),
+                queue.resume();
             }
         }
+        drop(queue);
+        self.last_health = Some(report.clone());
     }
 
-    fn handle_chat_command(
-        &mut self,
-        user_id: &str,
-        folder_path: &str,
-        message: &str,
-    ) -> Result<String, String> {
-        let folder_node = self
-            .filesystem
-            .resolve_path(folder_path)
-            .ok_or_else(|| format!("Folder not found: {}", folder_path))?;
+    fn handle_health_command(&mut self) -> Result<String, String> {
+        let report = self.run_health_check()?;
+        let queue = self.lock_outbound();
+        let outbound = match queue.held() {
+            Some(_) => format!("Outbound: held, {} replies queued", queue.len()),
+            None => format!("Outbound: sending, {} replies queued", queue.len()),
+        };
+        let funds = match self.funds.alert() {
+            Some(alert) => format!("\nFunds: refusing new replies. {}", alert),
+            None => String::new(),
+        };
+        Ok(format!("{}\n{}{}", report.describe(), outbound, funds))
+    }
 
-        if folder_node.file_type != crate::filesystem::FileType::Directory {
-            return Err("Can only chat in directories".to_string());
-        }
+    pub fn record_flush(&mut self, report: &FlushReport) {
+        self.metrics.record_replies(report.sent, report.failed);
+    }
 
-        if !folder_node.permissions.can_read(user_id) {
-            return Err("Permission denied: cannot access chatroom".to_string());
-        }
+    pub fn record_poll_error(&mut self, error: &str) {
+        self.metrics.record_zingo_error();
+        self.last_poll_error = Some(error.to_string());
+    }
 
-        let timestamp = std::time::SystemTime::now()
+    pub fn record_sync(&mut self, height: Option<u64>) {
+        let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-
-        let chat_entry = format!(
-            "[{}] {}: {}",
-            timestamp,
-            self.get_user_display_name(user_id),
-            message
-        );
-
-        let chat_log_path = format!("{}/.chat_log", folder_path.trim_end_matches('/'));
-
-        if let Some(chat_file) = self.filesystem.resolve_path_mut(&chat_log_path) {
-            let current_content = chat_file.content.clone().unwrap_or_default();
-            let new_content = if current_content.is_empty() {
-                chat_entry
-            } else {
-                format!("{}\n{}", current_content, chat_entry)
-            };
-            chat_file.update_content(new_content)?;
-        } else {
-            self.filesystem
-                .create_file(&chat_log_path, chat_entry, "coordinator".to_string())?;
+        self.last_sync_at = Some(now);
+        self.last_poll_error = None;
+        if height.is_some() {
+            self.sync_height = height;
         }
+    }
 
-        self.save_filesystem()?;
+    pub fn status_handle(&self) -> SharedStatus {
+        Arc::clone(&self.status)
+    }
 
-        Ok(format!("Message sent to chatroom: {}", folder_path))
+    pub fn status_snapshot(&self) -> StatusSnapshot {
+        StatusSnapshot {
+            started_at: self.started_at,
+            updated_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            sync_height: self.sync_height,
+            last_sync_at: self.last_sync_at,
+            last_poll_error: self.last_poll_error.clone(),
+            inbound_depth: self.pending_messages(),
+            outbound_depth: self.pending_outbound(),
+            health: self
+                .last_health
+                .as_ref()
+                .map(|report| report.state().as_str().to_string()),
+            verified_users: self.verified_users.len(),
+            sessions: self.session_mappings.len(),
+            metrics: self.metrics.to_json(),
+        }
     }
 
-    fn handle_history_command(&self, user_id: &str, folder_path: &str) -> Result<String, String> {
-        let folder_node = self
-            .filesystem
-            .resolve_path(folder_path)
-            .ok_or_else(|| format!("Folder not found: {}", folder_path))?;
+    pub fn publish_status(&self) {
+        let snapshot = self.status_snapshot();
+        *self
+            .status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = snapshot;
+    }
 
-        if !folder_node.permissions.can_read(user_id) {
-            return Err("Permission denied: cannot access chatroom".to_string());
-        }
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
 
-        let chat_log_path = format!("{}/.chat_log", folder_path.trim_end_matches('/'));
+    pub fn export_metrics(&self) -> Result<(), String> {
+        let path = self.data_dir.join(METRICS_FILE);
+        let tmp_path = path.with_extension("prom.tmp");
+        std::fs::write(&tmp_path, self.metrics.to_prometheus())
+            .map_err(|e| format!("Failed to write metrics: {}", e))?;
+        std::fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to replace metrics: {}", e))
+    }
 
-        if let Some(chat_file) = self.filesystem.resolve_path(&chat_log_path) {
-            Ok(chat_file
-                .content
-                .clone()
-                .unwrap_or_else(|| "No chat history".to_string()))
-        } else {
-            Ok("No chat history in this folder yet. Start chatting!".to_string())
-        }
+    pub fn pending_outbound(&self) -> usize {
+        self.lock_outbound().len()
     }
 
-    fn get_user_display_name(&self, user_id: &str) -> String {
-        if user_id.len() > 8 {
-            user_id[user_id.len() - 8..].to_string()
-        } else {
-            user_id.to_string()
+    pub fn enqueue_messages(&mut self, messages: Vec<Message>) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for message in self.filter_new_messages(messages) {
+            let opened = self.unwrap_memo(&message).ok();
+            if let Some(ref opened) = opened {
+                self.ack_seen(&message, opened);
+            }
+            let fields = opened
+                .map(Message::with_envelope_fields)
+                .unwrap_or_else(|| message.clone());
+            let message = Message {
+                expires_at: fields.expires_at,
+                priority: fields.priority,
+                ..message
+            };
+            self.inbox.push(message, now);
         }
     }
 
-    fn generate_session_id(&self, user_address: &str) -> String {
-        let timestamp = std::time::SystemTime::now()
+    pub fn next_message_batch(&mut self) -> Vec<Message> {
+        let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
+        self.inbox.next_batch(&self.priority, now)
+    }
 
-        let mut hasher = Sha256::new();
-        hasher.update(user_address.as_bytes());
-        hasher.update(timestamp.to_string().as_bytes());
-        hasher.update(b"zatboard_session");
-
-        format!("{:x}", hasher.finalize())[..16].to_string()
+    pub fn pending_messages(&self) -> usize {
+        self.inbox.len()
     }
 
-    fn handle_authentication(&mut self, message: &Message) -> Result<String, String> {
-        let parts: Vec<&str> = message.memo_text.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            return Err("Invalid auth format. Use AUTH:<signed_challenge>".to_string());
+    pub fn process_and_respond(&mut self, message: &Message) -> Result<(), String> {
+        let key = message.idempotency_key();
+        let sealed = e2e::is_encrypted(&message.memo_text);
+
+        if let Some(ref key) = key {
+            if let Some(entry) = self.processed.get(key).cloned() {
+                if entry.replied {
+                    println!("↩️  Skipping already processed message {}", key);
+                    return Ok(());
+                }
+                println!("🔁 Resending stored reply for {}", key);
+                return self.respond(message, Some(key), entry.result(), sealed);
+            }
         }
-
-        let provided_challenge = parts[1];
-
-        if let Some(expected_challenge) = self.pending_challenges.get(&message.sender_address) {
-            if expected_challenge == provided_challenge && message.signature.is_some() {
-                let session_id = self.generate_session_id(&message.sender_address);
-
-                let reply_address = self
-                    .auth_flow
-                    .session_manager
-                    .get_reply_address(&message.sender_address)
-                    .unwrap_or_else(|| message.sender_address.clone());
-
-                self.verified_users
-                    .insert(message.sender_address.clone(), reply_address.clone());
-                self.session_mappings
-                    .insert(session_id.clone(), reply_address);
-                self.pending_challenges.remove(&message.sender_address);
-
-                return Ok(format!(
-                    "Authentication successful. Session ID: {}",
-                    session_id
-                ));
+        if let Err(e) = self.admit_reply() {
+            if let Some(ref key) = key {
+                self.processed_txids.remove(key);
             }
+            return Err(e);
         }
 
-        Err("Authentication failed. Invalid signature or challenge.".to_string())
-    }
-
-    pub fn get_reply_address_by_session(&self, session_id: &str) -> Option<String> {
-        self.session_mappings.get(session_id).cloned()
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let opened = self.unwrap_memo(message);
+        let (message, result) = match opened.and_then(|opened| self.reassemble(&opened, now)) {
+            Ok(Some(whole)) => {
+                let whole = self.resolve_reply_to(&whole);
+                let result = self.process_incoming_message(&whole);
+                (whole, result)
+            }
+            Ok(None) => {
+                if let Some(ref key) = key {
+                    let buffered = Ok("Fragment buffered".to_string());
+                    let entry = ProcessedEntry::new(key, &message.sender_address, &buffered, now);
+                    if let Err(e) = self
+                        .processed
+                        .record(entry)
+                        .and_then(|_| self.processed.mark_replied(key))
+                    {
+                        eprintln!("⚠️  Failed to record processed message: {}", e);
+                    }
+                }
+                return Ok(());
+            }
+            Err(e) => (message.clone(), Err(e)),
+        };
+        if let Some(ref key) = key {
+            let entry = ProcessedEntry::new(key, &message.sender_address, &result, now);
+            if let Err(e) = self.processed.record(entry) {
+                eprintln!("⚠️  Failed to record processed message: {}", e);
+            }
+        }
+        self.respond(&message, key.as_deref(), result, sealed)
     }
 
-    pub fn get_all_sessions(&self) -> &HashMap<String, String> {
-        &self.session_mappings
+    fn unwrap_memo(&self, message: &Message) -> Result<Message, String> {
+        let memo_text = memo_decoder::verify_checksum(&message.memo_text, &self.checksums)?;
+        self.open_memo(&Message {
+            memo_text: memo_text.to_string(),
+            ..message.clone()
+        })
     }
 
-    pub fn cleanup_expired_sessions(&mut self) {
-        self.auth_flow.cleanup_expired_sessions();
-        let active_addresses: HashSet<String> = self
-            .auth_flow
-            .session_manager
-            .active_reply_addresses()
-            .into_iter()
-            .collect();
-
-        self.session_mappings
-            .retain(|_, reply_address| active_addresses.contains(reply_address));
-
-        self.verified_users
-            .retain(|_, reply_address| active_addresses.contains(reply_address));
-
-        self.pending_challenges
-            .retain(|user, _| self.auth_flow.session_manager.get_session(user).is_some());
-    }
-
-    fn parse_command_with_ids(&self, memo_text: &str) -> Option<(String, String, String)> {
-        let parts: Vec<&str> = memo_text.splitn(3, ':').collect();
-        if parts.len() == 3 {
-            let conv_id = parts[0];
-            let part_id = parts[1];
-            let command = parts[2];
-
-            if let Some(user_address) = self.conversation_mappings.get(conv_id) {
-                if let Some(mapped_address) = self.participant_mappings.get(part_id) {
-                    if user_address == mapped_address {
-                        return Some((
-                            user_address.clone(),
-                            conv_id.to_string(),
-                            command.to_string(),
-                        ));
-                    }
-                }
-            }
+    fn open_memo(&self, message: &Message) -> Result<Message, String> {
+        if !e2e::is_encrypted(&message.memo_text) {
+            return Ok(message.clone());
         }
-        None
+        let public_key = self.user_keys.get(&message.sender_address).ok_or_else(|| {
+            "Invalid encrypted memo: register a public key before sending encrypted memos"
+                .to_string()
+        })?;
+        let memo_text = self.signer.e2e_key().open(public_key, &message.memo_text)?;
+        Ok(Message {
+            memo_text,
+            ..message.clone()
+        })
     }
 
-    pub fn process_incoming_message(&mut self, message: &Message) -> Result<String, String> {
-        if message.memo_text.starts_with("REGISTER:") {
-            return self.handle_registration(message);
+    fn reassemble(&mut self, message: &Message, now: u64) -> Result<Option<Message>, String> {
+        let Some(fragment) = Fragment::parse(&message.memo_text) else {
+            return Ok(Some(
+                message.decode_memo()?.unwrap_or_else(|| message.clone()),
+            ));
+        };
+        let fragment = fragment?;
+        if self.is_banned(&message.sender_address) {
+            return Err("Access denied: this address is banned".to_string());
         }
+        let payload = self.reassembler.accept(
+            &message.sender_address,
+            &fragment,
+            &self.fragment_config,
+            now,
+        )?;
+        let Some(memo_text) = payload else {
+            return Ok(None);
+        };
+        let whole = Message {
+            memo_text,
+            ..message.clone()
+        };
+        Ok(Some(whole.decode_memo()?.unwrap_or(whole)))
+    }
 
-        if message.memo_text.starts_with("AUTH:") {
-            return self.handle_authentication(message);
+    fn respond(
+        &mut self,
+        message: &Message,
+        key: Option<&str>,
+        result: Result<String, String>,
+        sealed: bool,
+    ) -> Result<(), String> {
+        if message.memo_text.starts_with(FEDERATION_PREFIX) {
+            if let Some(key) = key {
+                let _ = self.processed.mark_replied(key);
+            }
+            return result.map(|applied| println!("🔗 {}", applied));
         }
 
-        if let Some((user_address, _conv_id, command)) =
-            self.parse_command_with_ids(&message.memo_text)
-        {
-            if self.verified_users.contains_key(&user_address) {
-                let synthetic_message = Message {
-                    sender_address: user_address,
-                    recipient_address: message.recipient_address.clone(),
-                    memo_text: command,
-                    signature: Some("conv_id_auth".to_string()),
-                    txid: message.txid.clone(),
-                    timestamp: message.timestamp,
-                };
-                return self.handle_authenticated_command(&synthetic_message);
+        let sender = &message.sender_address;
+        let sent = match result {
+            Ok(_) => self.send_reply(message, &result, sealed),
+            Err(_) if self.rate_limiter.take_notice(sender) => {
+                self.send_reply(message, &result, sealed)
+            }
+            Err(_)
+                if self.responses.envelope
+                    && !self.rate_limiter.is_throttled(sender)
+                    && self.reply_route(message).is_some() =>
+            {
+                self.send_reply(message, &result, sealed)
+            }
+            Err(ref e) => self.send_ack(message, Ack::Rejected(Status::classify(e)), sealed),
+        };
+
+        if let Some(key) = key {
+            if sent.is_ok() || self.reply_route(message).is_none() {
+                if let Err(e) = self.processed.mark_replied(key) {
+                    eprintln!("⚠️  Failed to record reply for {}: {}", key, e);
+                }
             } else {
-                return Err("Invalid conversation ID - user not registered".to_string());
+                self.processed_txids.remove(key);
             }
         }
 
-        if self.verify_sender_identity(message) {
-            self.handle_authenticated_command(message)
-        } else {
-            Err("Authentication required. Send REGISTER:<reply_address> first.".to_string())
-        }
+        sent?;
+        result?;
+        self.flush_notifications();
+        Ok(())
     }
 
-    fn handle_registration(&mut self, message: &Message) -> Result<String, String> {
-        let parts: Vec<&str> = message.memo_text.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            return Err("Invalid registration format. Use REGISTER:<reply_address>".to_string());
+    pub fn flush_notifications(&mut self) {
+        for notification in self.watches.drain() {
+            if let Err(e) = self.send_response(&notification.user_id, &notification.memo) {
+                eprintln!("⚠️  Failed to deliver watch notification: {}", e);
+            }
         }
+    }
 
-        let reply_address = parts[1].to_string();
+    fn notify_watchers(&mut self, actor: &str, path: &str, event: WatchEvent) {
+        let parent_path = match path.trim_end_matches('/').rsplit_once('/') {
+            Some(("", _)) | None => "/",
+            Some((parent, _)) => parent,
+        };
+        let memo = format!(
+            "watch: {} {} by {}",
+            event.as_str(),
+            path,
+            self.get_user_display_name(actor)
+        );
 
-        if self.verified_users.contains_key(&message.sender_address) {
-            let _conv_id = self
-                .user_conversations
-                .get(&message.sender_address)
-                .unwrap();
-            let _part_id = self.generate_participant_id(&message.sender_address);
-            return Ok("Already registered!".to_string());
+        for subscriber in self.watches.subscribers(path) {
+            if subscriber == actor {
+                continue;
+            }
+            let visible = self
+                .filesystem
+                .resolve_path(parent_path)
+                .is_some_and(|parent| parent.permissions.can_read(&subscriber));
+            if visible {
+                self.watches.enqueue(&subscriber, memo.clone());
+            }
         }
+    }
 
-        let conversation_id = self.generate_conversation_id();
-        let participant_id = self.generate_participant_id(&message.sender_address);
-
-        self.verified_users
-            .insert(message.sender_address.clone(), reply_address.clone());
-        self.conversation_mappings
-            .insert(conversation_id.clone(), message.sender_address.clone());
-        self.user_conversations
-            .insert(message.sender_address.clone(), conversation_id.clone());
-        self.participant_mappings
-            .insert(participant_id.clone(), message.sender_address.clone());
-
-        let challenge = self
-            .auth_flow
-            .initiate_authentication(message.sender_address.clone(), reply_address.clone());
-        let challenge_value = challenge
-            .strip_prefix("AUTH_CHALLENGE:")
-            .unwrap_or("")
-            .to_string();
-        self.pending_challenges
-            .insert(message.sender_address.clone(), challenge_value.clone());
+    fn builtin_commands() -> CommandRegistry<W> {
+        let mut registry = CommandRegistry::new();
 
-        let sender_preview = Self::truncate_for_log(&message.sender_address, 12);
-        let reply_preview = Self::truncate_for_log(&reply_address, 12);
-        println!(
-            "✅ New user registered: {} -> {}",
-            sender_preview, reply_preview
+        registry.add(
+            CommandSpec::new("help", "[command]", "Show available commands"),
+            |c, user_id, args| c.handle_help_command(user_id, args.trim()),
         );
-
-        println!(
-            "   ConvID: {} | PartID: {}",
-            conversation_id, participant_id
+        registry.add(
+            CommandSpec::new(
+                "ls",
+                "[--hash] [--sort=name|mtime|size|owner] [--reverse] [--files|--dirs] [--owner=<user>] [path] [offset] [limit]",
+                "List a directory",
+            )
+            .cacheable(),
+            |c, user_id, args| {
+                LsOptions::parse(args).and_then(|options| c.handle_ls_command(user_id, &options))
+            },
+        );
+        registry.add(
+            CommandSpec::new("stat", "<path>", "Show file or directory metadata"),
+            |c, user_id, args| {
+                tokenizer::single(args, "stat <path>")
+                    .and_then(|path| c.handle_stat_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("du", "<path>", "Show recursive disk usage"),
+            |c, user_id, args| {
+                tokenizer::single(args, "du <path>")
+                    .and_then(|path| c.handle_du_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("cat", "<path> [offset] [len]", "Read a file").cacheable(),
+            |c, user_id, args| {
+                let parts = tokenizer::tokenize(args)?;
+                match parts.as_slice() {
+                    [path] => c.handle_cat_command(user_id, path),
+                    [path, offset, rest @ ..] if rest.len() <= 1 => {
+                        let range = match (offset.parse::<usize>(), rest.first()) {
+                            (Ok(offset), None) => Ok((offset, DEFAULT_CAT_LEN)),
+                            (Ok(offset), Some(len)) => len
+                                .parse::<usize>()
+                                .map(|len| (offset, len))
+                                .map_err(|_| "Invalid length".to_string()),
+                            _ => Err("Invalid offset".to_string()),
+                        };
+                        range
+                            .map_err(|e| format!("{}. Use: cat <path> [offset] [len]", e))
+                            .and_then(|(offset, len)| {
+                                c.handle_cat_range_command(user_id, path, offset, len)
+                            })
+                    }
+                    _ => Err("Invalid cat format. Use: cat <path> [offset] [len]".to_string()),
+                }
+            },
+        );
+        registry.add(
+            CommandSpec::new("head", "<path> [lines]", "Read the first lines of a file"),
+            |c, user_id, args| c.handle_head_command(user_id, args, false),
+        );
+        registry.add(
+            CommandSpec::new("tail", "<path> [lines]", "Read the last lines of a file"),
+            |c, user_id, args| c.handle_head_command(user_id, args, true),
+        );
+        registry.add(
+            CommandSpec::new("mkdir", "<path>", "Create a directory").class(CommandClass::Write),
+            |c, user_id, args| {
+                tokenizer::single(args, "mkdir <path>")
+                    .and_then(|path| c.handle_mkdir_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("rm", "<path>", "Remove a file or directory")
+                .class(CommandClass::Write),
+            |c, user_id, args| {
+                tokenizer::single(args, "rm <path>")
+                    .and_then(|path| c.handle_rm_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("echo", "<content> > <file>", "Write content to a file")
+                .class(CommandClass::Upload),
+            |c, user_id, args| c.handle_echo_command(user_id, &format!("echo {}", args)),
+        );
+        registry.add(
+            CommandSpec::new("touch", "[--overwrite] <path> [content]", "Create a file")
+                .class(CommandClass::Write),
+            |c, user_id, args| {
+                let (overwrite, args) = match args.strip_prefix("--overwrite ") {
+                    Some(rest) => (true, rest.trim_start()),
+                    None => (false, args),
+                };
+                let (path, content) = tokenizer::split_first(args)?;
+                if path.is_empty() || path == "--overwrite" {
+                    return Err(
+                        "Invalid touch format. Use: touch [--overwrite] <path> [content]"
+                            .to_string(),
+                    );
+                }
+                c.handle_touch_command(user_id, &path, &tokenizer::text(content), overwrite)
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "write",
+                "<path> <content>",
+                "Replace an existing file's content",
+            )
+            .class(CommandClass::Upload),
+            |c, user_id, args| {
+                let (path, content) = tokenizer::split_first(args)?;
+                c.handle_write_command(user_id, &path, &tokenizer::text(content))
+            },
+        );
+        registry.add(
+            CommandSpec::new("cp", "<src> <dest>", "Copy a file or directory")
+                .class(CommandClass::Write),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [src, dest] => c.handle_cp_command(user_id, src, dest),
+                _ => Err("Invalid cp format. Use: cp <src> <dest>".to_string()),
+            },
+        );
+        registry.add(
+            CommandSpec::new("ln", "<src> <dest>", "Link a file under another path")
+                .class(CommandClass::Write),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [src, dest] => c.handle_ln_command(user_id, src, dest),
+                _ => Err("Invalid ln format. Use: ln <src> <dest>".to_string()),
+            },
+        );
+        registry.add(
+            CommandSpec::new("diff", "<path> <v1> <v2>", "Compare two file versions"),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [path, from, to] => match (from.parse::<u32>(), to.parse::<u32>()) {
+                    (Ok(from), Ok(to)) => c.handle_diff_command(user_id, path, from, to),
+                    _ => Err("Invalid version number. Use: diff <path> <v1> <v2>".to_string()),
+                },
+                _ => Err("Invalid diff format. Use: diff <path> <v1> <v2>".to_string()),
+            },
+        );
+        registry.add(
+            CommandSpec::new("encrypt", "<path>", "Encrypt a file at rest")
+                .class(CommandClass::Write),
+            |c, user_id, args| {
+                tokenizer::single(args, "encrypt <path>")
+                    .and_then(|path| c.handle_encrypt_command(user_id, &path, true))
+            },
+        );
+        registry.add(
+            CommandSpec::new("decrypt", "<path>", "Remove at-rest encryption")
+                .class(CommandClass::Write),
+            |c, user_id, args| {
+                tokenizer::single(args, "decrypt <path>")
+                    .and_then(|path| c.handle_encrypt_command(user_id, &path, false))
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "chmod",
+                "<public|private|open> <path>",
+                "Change permissions",
+            )
+            .class(CommandClass::Write),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [permissions, path] => c.handle_chmod_command(user_id, path, permissions),
+                _ => {
+                    Err("Invalid chmod format. Use: chmod <public|private|open> <path>".to_string())
+                }
+            },
+        );
+        registry.add(
+            CommandSpec::new("chown", "<user> <path>", "Transfer ownership")
+                .class(CommandClass::Write),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [new_owner, path] => c.handle_chown_command(user_id, path, new_owner),
+                _ => Err("Invalid chown format. Use: chown <user> <path>".to_string()),
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "grant",
+                "<read|write> <user> <path>",
+                "Grant access to a user",
+            )
+            .class(CommandClass::Write),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [access, target, path] => c.handle_grant_command(user_id, path, target, access),
+                _ => Err("Invalid grant format. Use: grant <read|write> <user> <path>".to_string()),
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "token issue",
+                "<read|write> <path> <ttl_secs>",
+                "Issue a capability token scoped to a path you own",
+            ),
+            |c, user_id, args| match tokenizer::tokenize(args)?.as_slice() {
+                [op, path, ttl] => match ttl.parse::<u64>() {
+                    Ok(ttl) => {
+                        c.handle_token_issue_command(user_id, CapabilityOp::parse(op)?, path, ttl)
+                    }
+                    Err(_) => Err(
+                        "Invalid token ttl. Use: token issue <read|write> <path> <ttl_secs>"
+                            .to_string(),
+                    ),
+                },
+                _ => Err(
+                    "Invalid token issue format. Use: token issue <read|write> <path> <ttl_secs>"
+                        .to_string(),
+                ),
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "delegate",
+                "<read|write> <user> <path> <ttl>",
+                "Let a registered user access a path you own for a while (ttl like 3600, 30m, 24h, 7d)",
+            ),
+            |c, user_id, args| c.handle_delegate_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new(
+                "delegations",
+                "",
+                "List access others have delegated to you",
+            ),
+            |c, user_id, _| c.handle_delegations_command(user_id),
+        );
+        registry.add(
+            CommandSpec::new("token list", "", "List capability tokens you have issued"),
+            |c, user_id, _| c.handle_token_list_command(user_id),
+        );
+        registry.add(
+            CommandSpec::new("token revoke", "<token>", "Revoke a capability token"),
+            |c, user_id, args| c.handle_token_revoke_command(user_id, args.trim()),
+        );
+        registry.add(
+            CommandSpec::new("permissions", "<path>", "Show permissions for a path"),
+            |c, user_id, args| {
+                tokenizer::single(args, "permissions <path>")
+                    .and_then(|path| c.handle_permissions_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("quota", "", "Show your remaining command allowance"),
+            |c, user_id, _| c.handle_quota_command(user_id),
+        );
+        registry.add(
+            CommandSpec::new("report", "<path> <reason>", "Report content to moderators"),
+            |c, user_id, args| c.handle_report_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new(
+                "msg",
+                "<user_or_session> <text>",
+                "Send a private message without revealing reply addresses",
+            )
+            .class(CommandClass::Chat),
+            |c, user_id, args| c.handle_msg_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new("profile", "", "Show your public profile"),
+            |c, user_id, _| c.handle_profile_command(user_id),
+        );
+        registry.add(
+            CommandSpec::new(
+                "profile set",
+                "<name|bio|key> [value]",
+                "Set or clear a profile field",
+            )
+            .class(CommandClass::Write),
+            |c, user_id, args| c.handle_profile_set_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new(
+                "whois",
+                "<participant|session|name>",
+                "Look up someone's public profile",
+            ),
+            |c, _, args| c.handle_whois_command(args.trim()),
+        );
+        registry.add(
+            CommandSpec::new("renew", "", "Extend your authenticated session"),
+            |c, user_id, _| c.handle_renew_command(user_id),
+        );
+        registry.add(
+            CommandSpec::new(
+                "rekey reply",
+                "<new_reply_address>",
+                "Move this session to a new reply address",
+            ),
+            |c, user_id, args| c.handle_rekey_reply_command(user_id, args.trim()),
+        );
+        registry.add(
+            CommandSpec::new(
+                "rekey key",
+                "<new_public_key>",
+                "Replace your registered public key",
+            ),
+            |c, user_id, args| c.handle_rekey_key_command(user_id, args.trim()),
+        );
+        registry.add(
+            CommandSpec::new(
+                "confirm",
+                "<code>",
+                "Run the command waiting on a confirmation code",
+            ),
+            |c, user_id, args| c.handle_confirm_command(user_id, args.trim()),
+        );
+        registry.add(
+            CommandSpec::new(
+                "logout",
+                "[all]",
+                "End this session, or all of them and your registration",
+            ),
+            |c, user_id, args| c.handle_logout_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new(
+                "caps",
+                "",
+                "List protocol features and the commands this coordinator accepts",
+            )
+            .cacheable(),
+            |c, _, _| Ok(c.features().encode()),
+        );
+        registry.add(
+            CommandSpec::new("pubkey", "", "Show the key that signs coordinator replies")
+                .cacheable(),
+            |c, _, _| Ok(c.handle_pubkey_command()),
+        );
+        registry.add(
+            CommandSpec::new(
+                "shards",
+                "",
+                "Show which coordinator serves each top-level directory",
+            )
+            .cacheable(),
+            |c, _, _| Ok(c.handle_shards_command()),
+        );
+        registry.add(
+            CommandSpec::new("inbox", "[n]", "Show delivery status of messages you sent"),
+            |c, user_id, args| c.handle_inbox_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new(
+                "receipts",
+                "[n]",
+                "Show transaction ids of replies sent to you",
+            ),
+            |c, user_id, args| c.handle_receipts_command(user_id, args),
+        );
+        registry.add(
+            CommandSpec::new("watch", "<path>", "Get notified about changes under a path")
+                .class(CommandClass::Write),
+            |c, user_id, args| {
+                tokenizer::single(args, "watch <path>")
+                    .and_then(|path| c.handle_watch_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("unwatch", "<path>", "Stop watching a path")
+                .class(CommandClass::Write),
+            |c, user_id, args| {
+                tokenizer::single(args, "unwatch <path>")
+                    .and_then(|path| c.handle_unwatch_command(user_id, &path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("chat", "<folder> <message>", "Post to a folder chat log")
+                .class(CommandClass::Chat),
+            |c, user_id, args| {
+                let (folder, message) = tokenizer::split_first(args)?;
+                if message.trim().is_empty() {
+                    return Err("Invalid chat format. Use: chat <folder> \"message\"".to_string());
+                }
+                c.handle_chat_command(user_id, &folder, &tokenizer::text(message))
+            },
+        );
+        registry.add(
+            CommandSpec::new("chat create", "<room>", "Create a chat room")
+                .class(CommandClass::Chat),
+            |c, user_id, args| c.handle_chat_create_command(user_id, args.trim()),
+        );
+        registry.add(
+            CommandSpec::new("chat post", "<room> <text>", "Post to a chat room")
+                .class(CommandClass::Chat),
+            |c, user_id, args| {
+                let (room, text) = args.split_once(' ').unwrap_or((args, ""));
+                c.handle_chat_post_command(user_id, room, text)
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "chat read",
+                "<room> [since|cursor=<cursor>]",
+                "Read recent chat room messages",
+            ),
+            |c, _, args| {
+                let (room, since) = match args.split_once(' ') {
+                    Some((room, since)) => (room, Some(since)),
+                    None => (args, None),
+                };
+                c.handle_chat_read_command(room, since)
+            },
+        );
+        registry.add(
+            CommandSpec::new("chat rooms", "", "List chat rooms"),
+            |c, _, _| c.handle_chat_rooms_command(),
+        );
+        registry.add(
+            CommandSpec::new("history", "<folder>", "Show a folder chat log").cacheable(),
+            |c, user_id, args| {
+                tokenizer::single(args, "history <folder>")
+                    .and_then(|folder| c.handle_history_command(user_id, &folder))
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin export",
+                "<path>",
+                "Export a subtree as a tar archive",
+            )
+            .admin(),
+            |c, _, args| {
+                tokenizer::single(args, "admin export <path>")
+                    .and_then(|path| c.handle_export_command(&path))
+            },
+        );
+        registry.add(
+            CommandSpec::new("admin backup", "", "Archive all coordinator state").admin(),
+            |c, _, _| c.handle_backup_command(),
+        );
+        registry.add(
+            CommandSpec::new("admin import", "<archive> <dest>", "Import a tar archive").admin(),
+            |c, _, args| match tokenizer::tokenize(args)?.as_slice() {
+                [archive_name, dest] => c.handle_import_command(archive_name, dest),
+                _ => Err(
+                    "Invalid admin import format. Use: admin import <archive> <dest>".to_string(),
+                ),
+            },
+        );
+        registry.add(
+            CommandSpec::new("admin freeze", "[path]", "Make a subtree read-only").admin(),
+            |c, _, args| {
+                Self::optional_path(args, "admin freeze [path]")
+                    .and_then(|path| c.handle_freeze_command(&path, true))
+            },
+        );
+        registry.add(
+            CommandSpec::new("admin hide", "<path>", "Hide a path from non-admins").moderator(),
+            |c, _, args| {
+                tokenizer::single(args, "admin hide <path>")
+                    .and_then(|path| c.handle_hide_command(&path, true))
+            },
+        );
+        registry.add(
+            CommandSpec::new("admin unhide", "<path>", "Make a hidden path visible again")
+                .moderator(),
+            |c, _, args| {
+                tokenizer::single(args, "admin unhide <path>")
+                    .and_then(|path| c.handle_hide_command(&path, false))
+            },
+        );
+        registry.add(
+            CommandSpec::new("admin reports", "", "List open content reports").moderator(),
+            |c, _, _| c.handle_admin_reports_command(),
+        );
+        registry.add(
+            CommandSpec::new("admin unfreeze", "[path]", "Lift a read-only freeze").admin(),
+            |c, _, args| {
+                Self::optional_path(args, "admin unfreeze [path]")
+                    .and_then(|path| c.handle_freeze_command(&path, false))
+            },
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin expire",
+                "<path> <seconds>",
+                "Expire a node after a delay (0 clears)",
+            )
+            .admin(),
+            |c, _, args| match tokenizer::tokenize(args)?.as_slice() {
+                [path, seconds] => match seconds.parse::<u64>() {
+                    Ok(seconds) => c.handle_expire_command(path, seconds),
+                    Err(_) => Err("Invalid expiry. Use: admin expire <path> <seconds>".to_string()),
+                },
+                _ => Err(
+                    "Invalid admin expire format. Use: admin expire <path> <seconds>".to_string(),
+                ),
+            },
+        );
+        registry.add(
+            CommandSpec::new("admin gc", "", "Prune expired nodes and stale history").admin(),
+            |c, _, _| c.handle_gc_command(),
+        );
+        registry.add(
+            CommandSpec::new("admin policy", "", "Show the anti-spam policy pipeline").admin(),
+            |c, _, _| Ok(c.policy.describe()),
+        );
+        registry.add(
+            CommandSpec::new("admin health", "", "Check wallet, balance and sync health").admin(),
+            |c, _, _| c.handle_health_command(),
+        );
+        registry.add(
+            CommandSpec::new("admin jobs", "", "Show scheduled maintenance jobs").admin(),
+            |c, _, _| Ok(c.scheduler.summary()),
+        );
+        registry.add(
+            CommandSpec::new("admin users", "", "List registered users").admin(),
+            |c, _, _| c.handle_admin_users_command(),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin sessions",
+                "[address]",
+                "List sessions with their age, expiry and command count",
+            )
+            .admin(),
+            |c, _, args| c.handle_admin_sessions_command(args.trim()),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin ban",
+                "<address|prefix*>",
+                "Ban an address or address prefix and end matching sessions",
+            )
+            .moderator(),
+            |c, user_id, args| c.handle_ban_command(user_id, args.trim()),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin revoke",
+                "<session|address>",
+                "End a session and drop its verified status",
+            )
+            .admin(),
+            |c, _, args| c.handle_revoke_command(args.trim()),
+        );
+        registry.add(
+            CommandSpec::new("admin unban", "<address|prefix*>", "Lift a ban").moderator(),
+            |c, _, args| c.handle_unban_command(args.trim()),
+        );
+        registry.add(
+            CommandSpec::new("admin bans", "", "List banned addresses and prefixes").moderator(),
+            |c, _, _| c.handle_bans_command(),
+        );
+        registry.add(
+            CommandSpec::new("admin stats", "", "Show coordinator statistics").admin(),
+            |c, _, _| c.handle_admin_stats_command(),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin audit",
+                "[n|cursor=<cursor>]",
+                "Show recent processed commands",
+            )
+            .admin(),
+            |c, _, args| c.handle_admin_audit_command(args),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin authlog",
+                "[address|event] [n]",
+                "Show registrations, challenges, logins and session expiries",
+            )
+            .admin(),
+            |c, _, args| c.handle_admin_authlog_command(args),
+        );
+        registry.add(
+            CommandSpec::new("admin metrics", "", "Show coordinator metrics").admin(),
+            |c, _, _| c.handle_admin_metrics_command(),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin federation",
+                "",
+                "Show federation peers and sync state",
+            )
+            .admin(),
+            |c, _, _| c.handle_admin_federation_command(),
+        );
+        registry.add(
+            CommandSpec::new("admin roles", "", "List users with a non-default role").admin(),
+            |c, _, _| c.handle_admin_roles_command(),
+        );
+        registry.add(
+            CommandSpec::new(
+                "admin role",
+                "<address> <role>",
+                "Set a user's role (guest, member, moderator, admin)",
+            )
+            .admin(),
+            |c, _, args| match args.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                [address, role] => c.handle_set_role_command(address, Role::parse(role)?),
+                _ => Err("Invalid admin role format. Use: admin role <address> <role>".to_string()),
+            },
         );
 
-        Ok(format!(
-            "Registration successful! ConvID: {} PartID: {} AUTH_CHALLENGE:{} - Save these for future commands.",
-            conversation_id,
-            participant_id,
-            challenge_value
-        ))
+        registry
     }
 
-    fn verify_sender_identity(&self, message: &Message) -> bool {
-        self.verified_users.contains_key(&message.sender_address) && message.signature.is_some()
+    fn handle_help_command(&self, user_id: &str, topic: &str) -> Result<String, String> {
+        let role = self.role_of(user_id);
+        let visible: Vec<&CommandSpec> = self
+            .commands
+            .specs()
+            .into_iter()
+            .filter(|spec| self.can_run(role, spec))
+            .collect();
+
+        if topic.is_empty() {
+            let usages: Vec<String> = visible.iter().map(|spec| spec.usage()).collect();
+            let aliases: Vec<String> = self
+                .commands
+                .aliases()
+                .into_iter()
+                .filter(|(_, target)| visible.iter().any(|spec| spec.name == *target))
+                .map(|(alias, target)| format!("{} -> {}", alias, target))
+                .collect();
+            let aliases = if aliases.is_empty() {
+                String::new()
+            } else {
+                format!("\nAliases: {}", aliases.join(", "))
+            };
+            return Ok(format!(
+                "Commands:\n{}{}\nSend: help <command> for details",
+                usages.join("\n"),
+                aliases
+            ));
+        }
+
+        let topic = topic.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let topic = match self.commands.alias_target(&topic) {
+            Some(target) => target.to_string(),
+            None => topic,
+        };
+        let matching: Vec<String> = visible
+            .iter()
+            .filter(|spec| {
+                spec.name == topic
+                    || spec
+                        .name
+                        .strip_prefix(topic.as_str())
+                        .is_some_and(|rest| rest.starts_with(' '))
+            })
+            .map(|spec| format!("{} - {}", spec.usage(), spec.summary))
+            .collect();
+
+        if matching.is_empty() {
+            Err(format!("No help for: {}. Send: help", topic))
+        } else {
+            Ok(matching.join("\n"))
+        }
     }
 
-    pub fn get_reply_address(&self, user_id: &str) -> Option<String> {
-        self.verified_users.get(user_id).cloned()
+    fn path_or_root(args: &str) -> &str {
+        match args.trim() {
+            "" => "/",
+            path => path,
+        }
     }
 
-    pub fn is_user_verified(&self, user_id: &str) -> bool {
-        self.verified_users.contains_key(user_id)
+    fn optional_path(args: &str, usage: &str) -> Result<String, String> {
+        match args.trim() {
+            "" => Ok("/".to_string()),
+            _ => tokenizer::single(args, usage),
+        }
     }
 
-    pub fn poll_for_new_messages(&mut self) -> Result<Vec<Message>, String> {
-        let all_messages = self.zingo_client.poll_once()?;
-        self.prune_processed_txids();
+    fn handle_authenticated_command(&mut self, message: &Message) -> Result<String, String> {
+        let user_id = &message.sender_address;
 
-        let mut new_messages = Vec::new();
-        let mut _processed_count = 0;
+        if self.is_banned(user_id) {
+            return Err("Access denied: this address is banned".to_string());
+        }
 
-        for msg in all_messages {
-            if let Some(ref txid) = msg.txid {
-                if self.processed_txids.contains(txid) {
-                    _processed_count += 1;
-                    continue;
-                } else {
-                    self.processed_txids.insert(txid.clone());
-                    new_messages.push(msg);
-                }
-            } else {
-                new_messages.push(msg);
+        if let Some(batch) = memo_decoder::decode_batch(&message.memo_text) {
+            return self.handle_batch(message, &batch?);
+        }
+        if let Some(batch) = self.commands.split_batch(&message.memo_text) {
+            let batch: Vec<String> = batch.into_iter().map(str::to_string).collect();
+            return self.handle_batch(message, &batch);
+        }
+
+        let (handler, args) = match self.commands.resolve(&message.memo_text) {
+            Some(resolved) => resolved,
+            None => {
+                return Err(format!(
+                    "Unknown command. Try: {}",
+                    self.commands.top_level_names(Role::Member).join(", ")
+                ))
             }
+        };
+        let spec = handler.spec();
+
+        let role = self.role_of(user_id);
+        if !self.can_run(role, spec) {
+            return Err(match spec.role {
+                Role::Admin => "Permission denied: admin only".to_string(),
+                Role::Moderator => "Permission denied: moderators only".to_string(),
+                _ => format!(
+                    "Permission denied: your role ({}) may only run {}",
+                    role.as_str(),
+                    self.guests.commands.join(", ")
+                ),
+            });
+        }
+        if !matches!(spec.name.as_str(), "renew" | "logout")
+            && self
+                .current_session
+                .as_deref()
+                .is_some_and(|session_id| self.auth_flow.session_manager.is_stale(session_id))
+        {
+            return Err(
+                "Authentication required: session expired. Send a signed renew, or AUTH with a new challenge."
+                    .to_string(),
+            );
+        }
+        spec.check_args(args)?;
+        if let Some(redirect) = self.shard_redirect(spec, args)? {
+            return Ok(redirect);
+        }
+        self.check_payment(user_id, spec, message.amount_zatoshis)?;
+        if !self.confirmed && self.confirmation_config.requires(&spec.name) {
+            return self.request_confirmation(message, &spec.name);
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let metered = spec.role <= Role::Member && role != Role::Admin;
+        if metered {
+            self.quotas.check(user_id, spec.class, now)?;
         }
 
-        if !new_messages.is_empty() {
-            println!("📨 Found {} new messages", new_messages.len());
+        if let Some(cached) = self.get_cached_response(user_id, &message.memo_text) {
+            return Ok(cached);
         }
 
-        Ok(new_messages)
-    }
+        let started = std::time::Instant::now();
+        let authority = self.authority(user_id, spec, args);
+        if let Authority::Delegated { issuer, .. } = &authority {
+            self.filesystem.delegation = Some(Principal {
+                user: user_id.to_string(),
+                issuer: Some(issuer.clone()),
+            });
+        }
+        let result = handler.execute(self, user_id, args);
+        self.filesystem.delegation = None;
+        if let Authority::Delegated { issuer, path } = authority {
+            self.emit(Event::CommandDelegated {
+                user: user_id.clone(),
+                issuer,
+                command: format!("{} {}", spec.name, args),
+                path,
+                ok: result.is_ok(),
+            });
+        }
+        self.emit(Event::CommandCompleted {
+            user: user_id.clone(),
+            command: spec.name.clone(),
+            elapsed_micros: started.elapsed().as_micros().min(u64::MAX as u128) as u64,
+            ok: result.is_ok(),
+        });
+        if metered && result.is_ok() {
+            self.quotas.record(user_id, spec.class, now);
+        }
 
-    pub async fn start_json_rpc_server(
-        &self,
-        bind_address: String,
-        port: u16,
-    ) -> Result<(), String> {
-        let coordinator_data = self.get_coordinator_status();
+        if result.is_ok() && spec.class != CommandClass::Read && spec.role <= Role::Member {
+            let command = if args.is_empty() {
+                spec.name.clone()
+            } else {
+                format!("{} {}", spec.name, args)
+            };
+            self.replicate(FederationOp::Command {
+                user: user_id.clone(),
+                command,
+            });
+        }
+        if let Ok(ref response) = result {
+            if spec.cacheable {
+                self.cache_response(user_id, &message.memo_text, response);
+            }
+        }
 
-        let status_route = warp::path("status")
-            .and(warp::get())
-            .map(move || warp::reply::json(&coordinator_data));
+        result
+    }
 
-        let filesystem_route = warp::path("filesystem")
-            .and(warp::path::param::<String>())
-            .and(warp::get())
-            .map(move |path: String| {
-                let response = json!({
-                    "path": path,
-                    "type": "directory",
-                    "children": ["file1.txt", "folder1/"],
-                    "message": "JSON-RPC filesystem query"
-                });
-                warp::reply::json(&response)
-            });
+    fn handle_guest_command(&mut self, command: &str, paid: u64) -> Result<String, String> {
+        if !self.guests.enabled {
+            return Err(
+                "Authentication required: guest access is disabled. Send REGISTER:<reply_address> first."
+                    .to_string(),
+            );
+        }
 
-        let chat_route = warp::path("chat")
-            .and(warp::path::param::<String>())
-            .and(warp::get())
-            .map(move |folder: String| {
-                let response = json!({
-                    "folder": folder,
-                    "history": [
-                        {"timestamp": 1640995200, "user": "user123", "message": "Hello!"},
-                        {"timestamp": 1640995260, "user": "user456", "message": "Hi there!"}
-                    ],
-                    "message": "JSON-RPC chat history"
+        let (handler, args) = self.commands.resolve(command).ok_or_else(|| {
+            format!(
+                "Unknown command. Guests may run: {}",
+                self.guests.commands.join(", ")
+            )
+        })?;
+        let spec = handler.spec();
+        if !self.can_run(Role::Guest, spec) {
+            return Err(format!(
+                "Permission denied: guests may only run {}. Register for full access.",
+                self.guests.commands.join(", ")
+            ));
+        }
+        spec.check_args(args)?;
+        if let Some(redirect) = self.shard_redirect(spec, args)? {
+            return Ok(redirect);
+        }
+        self.check_payment(GUEST_USER, spec, paid)?;
+        handler.execute(self, GUEST_USER, args)
+    }
+
+    fn handle_capability_command(
+        &mut self,
+        request: &CapabilityRequest,
+        paid: u64,
+    ) -> Result<String, String> {
+        let (handler, args) = self
+            .commands
+            .resolve(request.command)
+            .ok_or_else(|| "Unknown command".to_string())?;
+        let spec = handler.spec();
+        if !capability::DELEGABLE_COMMANDS.contains(&spec.name.as_str()) {
+            return Err(format!(
+                "Permission denied: capability tokens cannot run {}",
+                spec.name
+            ));
+        }
+        spec.check_args(args)?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let paths = spec.path_operands(args)?;
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let issuer = self
+            .capabilities
+            .authorize(request.token, spec.class, &paths, now)?
+            .issuer
+            .clone();
+        if self.is_banned(&issuer) {
+            return Err("Access denied: the token's issuer is banned".to_string());
+        }
+        if let Some(redirect) = self.shards.redirect_for(&paths)? {
+            return Ok(redirect);
+        }
+        self.check_payment(&issuer, spec, paid)?;
+        handler.execute(self, &issuer, args)
+    }
+
+    fn issue_capability(
+        &mut self,
+        user_id: &str,
+        op: CapabilityOp,
+        path: &str,
+        ttl_secs: u64,
+        grantee: Option<String>,
+    ) -> Result<(String, String), String> {
+        if ttl_secs == 0 || ttl_secs > capability::MAX_TTL_SECS {
+            return Err(format!(
+                "Invalid token ttl: must be 1-{} seconds",
+                capability::MAX_TTL_SECS
+            ));
+        }
+        let node = self
+            .visible_node(user_id, path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        if node.permissions.owner != user_id && !self.is_admin(user_id) {
+            return Err(
+                "Permission denied: only the owner can delegate access to a path".to_string(),
+            );
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let capability = Capability {
+            issuer: user_id.to_string(),
+            op,
+            path: Self::path_or_root(path.trim_end_matches('/')).to_string(),
+            expires_at: now + ttl_secs,
+            grantee,
+        };
+        let description = capability.describe();
+        let token = self.capabilities.issue(capability)?;
+        self.capabilities
+            .save(self.data_dir.join(CAPABILITIES_FILE))?;
+        Ok((token, description))
+    }
+
+    fn handle_token_issue_command(
+        &mut self,
+        user_id: &str,
+        op: CapabilityOp,
+        path: &str,
+        ttl_secs: u64,
+    ) -> Result<String, String> {
+        let (token, description) = self.issue_capability(user_id, op, path, ttl_secs, None)?;
+        Ok(format!(
+            "Token {} grants {}. Present it as CAP:{} <reply_address> <command>",
+            token, description, token
+        ))
+    }
+
+    fn handle_delegate_command(&mut self, user_id: &str, args: &str) -> Result<String, String> {
+        let [op, target, path, ttl] = &tokenizer::tokenize(args)?[..] else {
+            return Err(
+                "Invalid delegate format. Use: delegate <read|write> <user> <path> <ttl>"
+                    .to_string(),
+            );
+        };
+        let op = CapabilityOp::parse(op)?;
+        let ttl_secs = capability::parse_ttl(ttl)?;
+        let grantee = Some(
+            self.resolve_participant(target)
+                .unwrap_or_else(|| target.to_string()),
+        )
+        .filter(|user| self.verified_users.contains_key(user) && !self.is_banned(user))
+        .ok_or_else(|| format!("Unknown user: {}", target))?;
+        if grantee == user_id {
+            return Err("Invalid delegation: you already have your own access".to_string());
+        }
+
+        let (id, description) =
+            self.issue_capability(user_id, op, path, ttl_secs, Some(grantee.clone()))?;
+        Ok(format!(
+            "Delegation {} grants {}. Revoke it early with token revoke {}",
+            id, description, id
+        ))
+    }
+
+    fn handle_delegations_command(&self, user_id: &str) -> Result<String, String> {
+        let delegations = self.capabilities.delegated_to(user_id);
+        if delegations.is_empty() {
+            return Ok("No access has been delegated to you".to_string());
+        }
+        Ok(delegations
+            .iter()
+            .map(|(id, capability)| {
+                format!(
+                    "{} from {}: {}",
+                    id,
+                    self.get_user_display_name(&capability.issuer),
+                    capability.describe()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    fn authority(&self, user_id: &str, spec: &CommandSpec, args: &str) -> Authority {
+        if !capability::DELEGABLE_COMMANDS.contains(&spec.name.as_str()) {
+            return Authority::Own;
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let Ok(paths) = spec.path_operands(args) else {
+            return Authority::Own;
+        };
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        self.capabilities
+            .delegation_for(user_id, spec.class, &paths, now)
+            .filter(|delegation| !self.is_banned(&delegation.issuer))
+            .map_or(Authority::Own, |delegation| Authority::Delegated {
+                issuer: delegation.issuer.clone(),
+                path: delegation.path.clone(),
+            })
+    }
+
+    fn handle_token_list_command(&self, user_id: &str) -> Result<String, String> {
+        let tokens = self.capabilities.issued_by(user_id);
+        if tokens.is_empty() {
+            return Ok("No capability tokens issued".to_string());
+        }
+        Ok(tokens
+            .iter()
+            .map(|(token, capability)| format!("{} {}", token, capability.describe()))
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    fn handle_token_revoke_command(
+        &mut self,
+        user_id: &str,
+        token: &str,
+    ) -> Result<String, String> {
+        self.capabilities.revoke(user_id, token)?;
+        self.capabilities
+            .save(self.data_dir.join(CAPABILITIES_FILE))?;
+        Ok(format!("Revoked token {}", token))
+    }
+
+    pub fn prune_capabilities(&mut self) -> Result<usize, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expired = self.capabilities.prune(now);
+        if expired > 0 {
+            self.capabilities
+                .save(self.data_dir.join(CAPABILITIES_FILE))?;
+        }
+        Ok(expired)
+    }
+
+    fn shard_redirect(&self, spec: &CommandSpec, args: &str) -> Result<Option<String>, String> {
+        let paths = spec.path_operands(args)?;
+        self.shards
+            .redirect_for(&paths.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    fn handle_shards_command(&self) -> String {
+        if self.shards.is_empty() {
+            return "All paths are served by this coordinator".to_string();
+        }
+        self.shards
+            .routes()
+            .map(|(prefix, address)| format!("{} -> {}", prefix, address))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn handle_batch(&mut self, message: &Message, batch: &[String]) -> Result<String, String> {
+        if batch.len() > commands::MAX_BATCH_COMMANDS {
+            return Err(format!(
+                "Too many commands in batch: {} (max {})",
+                batch.len(),
+                commands::MAX_BATCH_COMMANDS
+            ));
+        }
+
+        let fees: Vec<u64> = batch
+            .iter()
+            .filter_map(|command| self.commands.resolve(command))
+            .map(|(handler, _)| self.fee_for(&message.sender_address, handler.spec()))
+            .collect();
+        let required: u64 = fees.iter().sum();
+        if message.amount_zatoshis < required {
+            return Err(format!(
+                "Payment required: batch of {} commands costs {} zatoshis (received {})",
+                batch.len(),
+                required,
+                message.amount_zatoshis
+            ));
+        }
+
+        let mut output = Vec::new();
+        for (i, (command, fee)) in batch.iter().zip(fees).enumerate() {
+            let step = Message {
+                memo_text: command.clone(),
+                amount_zatoshis: fee,
+                ..message.clone()
+            };
+            let needs_confirmation = !self.confirmed
+                && self.commands.resolve(command).is_some_and(|(handler, _)| {
+                    self.confirmation_config.requires(&handler.spec().name)
                 });
-                warp::reply::json(&response)
+            match self.handle_authenticated_command(&step) {
+                Ok(response) if needs_confirmation => {
+                    output.push(format!("> {}\n{}", command, response));
+                    output.push(format!(
+                        "(batch stopped at command {} of {}: confirm it, then resend the remaining {} commands)",
+                        i + 1,
+                        batch.len(),
+                        batch.len() - i - 1
+                    ));
+                    break;
+                }
+                Ok(response) => output.push(format!("> {}\n{}", command, response)),
+                Err(e) => {
+                    return Err(format!(
+                        "{} (batch stopped at command {} of {}: {})",
+                        e,
+                        i + 1,
+                        batch.len(),
+                        command
+                    ))
+                }
+            }
+        }
+        Ok(output.join("\n"))
+    }
+
+    fn fee_for(&self, user_id: &str, spec: &CommandSpec) -> u64 {
+        if spec.role.is_staff() || self.is_admin(user_id) {
+            0
+        } else {
+            self.fees.required_for(spec.class)
+        }
+    }
+
+    fn check_payment(&self, user_id: &str, spec: &CommandSpec, paid: u64) -> Result<(), String> {
+        let required = self.fee_for(user_id, spec);
+        if paid < required {
+            return Err(format!(
+                "Payment required: {} costs {} zatoshis (received {})",
+                spec.name, required, paid
+            ));
+        }
+        Ok(())
+    }
+
+    fn handle_quota_command(&mut self, user_id: &str) -> Result<String, String> {
+        if self.is_admin(user_id) {
+            return Ok("Admins are not subject to quotas".to_string());
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        Ok(self.quotas.summary(user_id, now))
+    }
+
+    fn handle_report_command(&mut self, user_id: &str, args: &str) -> Result<String, String> {
+        let (path, reason) = tokenizer::split_first(args)?;
+        let (path, reason) = (path.as_str(), tokenizer::text(reason));
+        let node = self
+            .visible_node(user_id, path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        if !node.permissions.can_read(user_id) {
+            return Err("Permission denied: cannot read path".to_string());
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let id = self.reports.file(path, user_id, &reason, now)?;
+        self.save_reports()?;
+
+        Ok(format!("Report #{} filed for {}", id, path))
+    }
+
+    fn resolve_participant(&self, target: &str) -> Option<String> {
+        if let Some(user) = self.conversations.user_for_participant(target) {
+            return Some(user.to_string());
+        }
+        if let Some(reply_address) = self.session_mappings.get(target) {
+            return self
+                .verified_users
+                .iter()
+                .find(|(_, address)| *address == reply_address)
+                .map(|(user, _)| user.clone());
+        }
+        self.profiles.user_by_name(target).map(str::to_string)
+    }
+
+    fn resolve_relay_target(&self, target: &str) -> Option<String> {
+        match self.resolve_participant(target) {
+            Some(user) => self.verified_users.get(&user).cloned(),
+            None => self.verified_users.get(target).cloned(),
+        }
+    }
+
+    fn describe_profile(&self, user: &str) -> String {
+        let mut lines = vec![format!(
+            "Participant: {}",
+            conversation::participant_id(user)
+        )];
+        match self.profiles.get(user) {
+            Some(profile) => {
+                if let Some(name) = &profile.display_name {
+                    lines.push(format!("Name: {}", name));
+                }
+                if let Some(bio) = &profile.bio {
+                    lines.push(format!("Bio: {}", bio));
+                }
+                if let Some(key) = &profile.public_key {
+                    lines.push(format!("Public key: {}", key));
+                }
+            }
+            None => lines.push("No profile set".to_string()),
+        }
+        lines.join("\n")
+    }
+
+    fn handle_profile_command(&self, user_id: &str) -> Result<String, String> {
+        Ok(self.describe_profile(user_id))
+    }
+
+    fn handle_profile_set_command(&mut self, user_id: &str, args: &str) -> Result<String, String> {
+        let (field, value) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.profiles.set(user_id, field, value, now)?;
+        self.profiles.save(self.data_dir.join(PROFILES_FILE))?;
+
+        if value.trim().is_empty() {
+            Ok(format!("Profile {} cleared", field))
+        } else {
+            Ok(format!("Profile {} updated", field))
+        }
+    }
+
+    fn handle_renew_command(&mut self, user_id: &str) -> Result<String, String> {
+        if !self.user_keys.contains_key(user_id) {
+            return Err(
+                "Authentication required: renew must be signed; send REGISTER:<reply_address> <public_key> first."
+                    .to_string(),
+            );
+        }
+        let session_id = match &self.current_session {
+            Some(session_id) => session_id.clone(),
+            None => self
+                .auth_flow
+                .session_manager
+                .get_session(user_id)
+                .map(|session| session.session_id.clone())
+                .unwrap_or_default(),
+        };
+        let expires_at = self
+            .auth_flow
+            .session_manager
+            .renew_session(&session_id)
+            .ok_or_else(|| {
+                "Authentication required: no authenticated session to renew; send AUTH first."
+                    .to_string()
+            })?;
+        self.save_state();
+        Ok(format!("Session renewed until {}", expires_at))
+    }
+
+    fn rekey_session(&self, user_id: &str) -> Result<String, String> {
+        if !self.user_keys.contains_key(user_id) {
+            return Err(
+                "Authentication required: rekey must be signed with your registered key."
+                    .to_string(),
+            );
+        }
+        self.current_session
+            .clone()
+            .or_else(|| {
+                self.auth_flow
+                    .session_manager
+                    .get_session(user_id)
+                    .map(|session| session.session_id.clone())
+            })
+            .filter(|session_id| {
+                self.auth_flow
+                    .session_manager
+                    .session(session_id)
+                    .is_some_and(|session| session.is_authenticated)
+            })
+            .ok_or_else(|| {
+                "Authentication required: no authenticated session to rekey; send AUTH first."
+                    .to_string()
+            })
+    }
+
+    fn handle_rekey_reply_command(
+        &mut self,
+        user_id: &str,
+        new_reply_address: &str,
+    ) -> Result<String, String> {
+        let session_id = self.rekey_session(user_id)?;
+        if self
+            .auth_flow
+            .session_manager
+            .sessions_for(user_id)
+            .iter()
+            .any(|session| session.reply_address == new_reply_address)
+        {
+            return Err(format!(
+                "Invalid reply address: {} already belongs to one of your sessions",
+                new_reply_address
+            ));
+        }
+
+        let Some(session) = self.auth_flow.session_manager.session_mut(&session_id) else {
+            return Err("Authentication required: session ended".to_string());
+        };
+        let old_reply_address =
+            std::mem::replace(&mut session.reply_address, new_reply_address.to_string());
+        self.session_mappings
+            .insert(session_id.clone(), new_reply_address.to_string());
+        if self.verified_users.get(user_id) == Some(&old_reply_address) {
+            self.record_registration(user_id, new_reply_address);
+            self.replicate(FederationOp::Rekey {
+                user: user_id.to_string(),
+                reply: Some(new_reply_address.to_string()),
+                key: None,
             });
+        }
+        self.save_state();
 
-        let routes = status_route
-            .or(filesystem_route)
-            .or(chat_route)
-            .with(warp::cors().allow_any_origin());
+        println!(
+            "🔑 {} moved session {} to a new reply address",
+            Self::truncate_for_log(user_id, 12),
+            session_id
+        );
+        Ok(format!(
+            "Session {} now replies to {}. Sign future commands for that address.",
+            session_id, new_reply_address
+        ))
+    }
+
+    fn handle_rekey_key_command(
+        &mut self,
+        user_id: &str,
+        new_public_key: &str,
+    ) -> Result<String, String> {
+        self.rekey_session(user_id)?;
+        identity::parse_public_key(new_public_key).map_err(|e| format!("Invalid rekey: {}", e))?;
+        if self.user_keys.get(user_id).map(String::as_str) == Some(new_public_key) {
+            return Err("Invalid rekey: that key is already registered".to_string());
+        }
+
+        self.user_keys
+            .insert(user_id.to_string(), new_public_key.to_string());
+        self.replicate(FederationOp::Rekey {
+            user: user_id.to_string(),
+            reply: None,
+            key: Some(new_public_key.to_string()),
+        });
+        self.save_state();
+
+        println!(
+            "🔑 {} rotated their identity key",
+            Self::truncate_for_log(user_id, 12)
+        );
+        Ok(
+            "Public key replaced. Your sessions and files are kept; sign future commands with the new key."
+                .to_string(),
+        )
+    }
+
+    fn request_confirmation(&mut self, message: &Message, command: &str) -> Result<String, String> {
+        let user_id = &message.sender_address;
+        let address = self.verified_users.get(user_id).cloned().ok_or_else(|| {
+            "Authentication required: confirmation codes need a registered reply address"
+                .to_string()
+        })?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let code = self.confirmations.issue(user_id, message, now);
+        self.queue_reply_memos(
+            &address,
+            vec![format!("CONFIRM_CODE:{} for: {}", code, message.memo_text)],
+            None,
+        )?;
+        Ok(format!(
+            "Confirmation required: a code was sent to your registered reply address. Send confirm <code> within {} seconds to run {}.",
+            self.confirmation_config.ttl_secs, command
+        ))
+    }
+
+    fn handle_confirm_command(&mut self, user_id: &str, code: &str) -> Result<String, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let message = self
+            .confirmations
+            .confirm(user_id, code, &self.confirmation_config, now)?;
+        self.confirmed = true;
+        let result = self.handle_authenticated_command(&message);
+        self.confirmed = false;
+        result
+    }
+
+    fn handle_pubkey_command(&self) -> String {
+        if self.signing.enabled {
+            format!("CoordKey: {}", self.signer.public_key_hex())
+        } else {
+            "Reply signing is disabled".to_string()
+        }
+    }
+
+    fn handle_whois_command(&self, target: &str) -> Result<String, String> {
+        let user = self
+            .resolve_participant(target)
+            .filter(|user| !self.is_banned(user))
+            .ok_or_else(|| format!("Unknown user: {}", target))?;
+        Ok(self.describe_profile(&user))
+    }
+
+    fn handle_msg_command(&mut self, user_id: &str, args: &str) -> Result<String, String> {
+        let (target, text) = args
+            .trim()
+            .split_once(' ')
+            .map(|(target, text)| (target, text.trim()))
+            .ok_or_else(|| "Invalid msg format. Use: msg <user_or_session> <text>".to_string())?;
+        if text.chars().count() > relay::MAX_RELAY_LENGTH {
+            return Err(format!(
+                "Message too long (max {} characters)",
+                relay::MAX_RELAY_LENGTH
+            ));
+        }
+        let reply_address = self
+            .resolve_relay_target(target)
+            .ok_or_else(|| format!("Unknown recipient: {}", target))?;
+
+        let memo = format!(
+            "msg from {}: {}",
+            conversation::participant_id(user_id),
+            text
+        );
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let outbound_ids = self.queue_reply_memos(
+            &reply_address,
+            memo_decoder::split_into_memos_within(&memo, self.memo_budget()),
+            None,
+        )?;
+
+        let id = self.relays.record(user_id, target, text, outbound_ids, now);
+        self.relays.save(self.data_dir.join(RELAYS_FILE))?;
+
+        Ok(format!("Message #{} queued for {}", id, target))
+    }
+
+    fn handle_receipts_command(&self, user_id: &str, args: &str) -> Result<String, String> {
+        let limit = match args.trim() {
+            "" => DEFAULT_RECEIPTS_LIMIT,
+            value => value
+                .parse::<usize>()
+                .ok()
+                .filter(|limit| *limit > 0)
+                .ok_or_else(|| "Invalid receipts format. Use: receipts [n]".to_string())?,
+        };
+        let reply_address = self
+            .get_reply_address(user_id)
+            .ok_or_else(|| "No reply address found for user".to_string())?;
+
+        let queue = self.lock_outbound();
+        let receipts = queue.receipts_for(&reply_address);
+        let mut lines: Vec<String> = receipts
+            .iter()
+            .rev()
+            .take(limit)
+            .map(|receipt| {
+                format!(
+                    "#{} {} at {}{}{} fee {}: {}",
+                    receipt.id,
+                    receipt.txid,
+                    receipt.sent_at,
+                    receipt
+                        .reply_to
+                        .as_ref()
+                        .map(|txid| format!(" for {}", txid))
+                        .unwrap_or_default(),
+                    receipt
+                        .status
+                        .map(|status| format!(" [{}]", status.describe()))
+                        .unwrap_or_default(),
+                    receipt.fee_zatoshis,
+                    receipt.preview
+                )
+            })
+            .collect();
+
+        let pending = queue
+            .items()
+            .filter(|item| item.address == reply_address)
+            .count();
+        if pending > 0 {
+            lines.push(format!("{} replies still queued", pending));
+        }
+        if lines.is_empty() {
+            return Ok("No replies sent yet".to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn handle_inbox_command(&self, user_id: &str, args: &str) -> Result<String, String> {
+        let scope = format!("inbox:{}", user_id);
+        let (relays, next) = match cursor::strip_arg(args) {
+            Some(token) => {
+                let after = cursor::decode(&scope, token)?;
+                let page = cursor::page_after(
+                    self.relays
+                        .sent_by(user_id, usize::MAX)
+                        .into_iter()
+                        .map(|relay| (relay.id, relay)),
+                    after,
+                    DEFAULT_INBOX_LIMIT,
+                );
+                (page.items, page.next)
+            }
+            None => {
+                let limit = match args.trim() {
+                    "" => DEFAULT_INBOX_LIMIT,
+                    value => value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|limit| *limit > 0)
+                        .ok_or_else(|| {
+                            "Invalid inbox format. Use: inbox [n|cursor=<cursor>]".to_string()
+                        })?,
+                };
+                (self.relays.sent_by(user_id, limit), None)
+            }
+        };
+        if relays.is_empty() {
+            return Ok("No messages sent".to_string());
+        }
+
+        let queue = self.lock_outbound();
+        let lines = relays
+            .iter()
+            .map(|relay| {
+                let status = queue
+                    .items()
+                    .find(|item| relay.outbound_ids.contains(&item.id))
+                    .map(|item| RelayStatus::Pending {
+                        attempts: item.attempts,
+                        last_error: item.last_error.clone(),
+                    })
+                    .unwrap_or(RelayStatus::Delivered);
+                format!(
+                    "#{} to {} [{}]: {}",
+                    relay.id,
+                    relay.target,
+                    status.describe(),
+                    relay.preview
+                )
+            })
+            .collect();
+        Ok(Self::paged_output(lines, &scope, next))
+    }
+
+    fn paged_output(mut lines: Vec<String>, scope: &str, next: Option<u64>) -> String {
+        if let Some(next) = next {
+            lines.push(cursor::more_line(scope, next));
+        }
+        lines.join("\n")
+    }
+
+    fn handle_hide_command(&mut self, path: &str, hidden: bool) -> Result<String, String> {
+        self.filesystem.set_hidden(path, hidden)?;
+        self.save_filesystem()?;
+        self.response_cache.clear();
+
+        if hidden {
+            let resolved = self.reports.resolve(path);
+            self.save_reports()?;
+            Ok(format!("Hidden: {} ({} reports resolved)", path, resolved))
+        } else {
+            Ok(format!("Unhidden: {}", path))
+        }
+    }
+
+    fn handle_admin_reports_command(&self) -> Result<String, String> {
+        let reports = self.reports.open();
+        if reports.is_empty() {
+            return Ok("No open reports".to_string());
+        }
+
+        Ok(reports
+            .iter()
+            .map(|report| {
+                format!(
+                    "#{} {} by {}: {}",
+                    report.id,
+                    report.path,
+                    Self::truncate_for_log(&report.reporter, 12),
+                    report.reason
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    fn save_reports(&self) -> Result<(), String> {
+        self.reports.save(self.data_dir.join(REPORTS_FILE))
+    }
+
+    fn handle_freeze_command(&mut self, path: &str, frozen: bool) -> Result<String, String> {
+        self.filesystem.set_frozen(path, frozen)?;
+        self.save_filesystem()?;
+
+        if frozen {
+            Ok(format!("Frozen: {} is now read-only", path))
+        } else {
+            let still_frozen = self.filesystem.frozen_paths();
+            if still_frozen.is_empty() {
+                Ok(format!("Unfrozen: {}", path))
+            } else {
+                Ok(format!(
+                    "Unfrozen: {} (still frozen: {})",
+                    path,
+                    still_frozen.join(", ")
+                ))
+            }
+        }
+    }
+
+    fn handle_admin_users_command(&self) -> Result<String, String> {
+        let mut users: Vec<String> = self
+            .verified_users
+            .iter()
+            .map(|(user, reply_address)| {
+                format!(
+                    "{} ({}) {} -> {}",
+                    user,
+                    conversation::participant_id(user),
+                    self.role_of(user).as_str(),
+                    Self::truncate_for_log(reply_address, 16)
+                )
+            })
+            .collect();
+        users.sort();
+
+        if users.is_empty() {
+            Ok("No registered users".to_string())
+        } else {
+            Ok(users.join("\n"))
+        }
+    }
+
+    pub fn session_details(&self, user_id: Option<&str>) -> Vec<UserSession> {
+        self.auth_flow
+            .session_manager
+            .list(user_id)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    fn handle_admin_sessions_command(&self, target: &str) -> Result<String, String> {
+        let user_id = match target {
+            "" => None,
+            target => Some(
+                self.resolve_participant(target)
+                    .unwrap_or_else(|| target.to_string()),
+            ),
+        };
+        let sessions = self.session_details(user_id.as_deref());
+        if sessions.is_empty() {
+            return Ok(match user_id {
+                Some(user_id) => format!("No sessions for {}", user_id),
+                None => "No active sessions".to_string(),
+            });
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        Ok(sessions
+            .iter()
+            .map(|session| {
+                let expiry = match session.state(now) {
+                    "expired" => format!("expired {}s ago", now - session.expires_at),
+                    "active" if session.expires_at > 0 => {
+                        format!("expires in {}s", session.expires_at - now)
+                    }
+                    _ => "awaiting AUTH".to_string(),
+                };
+                format!(
+                    "{} session={} reply={} state={} created={} last_seen={} expires_at={} ({}) commands={}",
+                    session.user_id,
+                    session.session_id,
+                    Self::truncate_for_log(&session.reply_address, 16),
+                    session.state(now),
+                    session.session_start,
+                    session.last_activity,
+                    session.expires_at,
+                    expiry,
+                    session.commands_issued
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    fn handle_ban_command(&mut self, actor: &str, pattern: &str) -> Result<String, String> {
+        if pattern.is_empty() {
+            return Err("Invalid ban format. Use: admin ban <address|prefix*>".to_string());
+        }
+        BanList::parse_pattern(pattern)?;
+        let mut known: HashSet<String> = self.verified_users.keys().cloned().collect();
+        known.extend(self.admin_addresses.iter().cloned());
+        known.extend(self.roles.keys().cloned());
+        known.insert(actor.to_string());
+        known.insert(pattern.to_string());
+        let targets: Vec<String> = known
+            .into_iter()
+            .filter(|address| BanList::pattern_matches(pattern, address))
+            .collect();
+        for address in &targets {
+            if address == actor || self.is_admin(address) {
+                return Err("Cannot ban an admin address".to_string());
+            }
+            if self.role_of(address).is_staff() && !self.is_admin(actor) {
+                return Err("Permission denied: only admins can ban moderators".to_string());
+            }
+        }
+        if !self.bans.insert(pattern)? {
+            return Err(format!("Already banned: {}", pattern));
+        }
+
+        let mut revoked_tokens = 0;
+        for address in &targets {
+            self.revoke_user(address);
+            revoked_tokens += self.capabilities.revoke_issuer(address);
+        }
+        if revoked_tokens > 0 {
+            self.capabilities
+                .save(self.data_dir.join(CAPABILITIES_FILE))?;
+        }
+        self.save_state();
+
+        Ok(format!("Banned: {}", pattern))
+    }
+
+    fn handle_bans_command(&self) -> Result<String, String> {
+        if self.bans.is_empty() {
+            return Ok("No bans".to_string());
+        }
+        Ok(self.bans.sorted().join("\n"))
+    }
+
+    fn revoke_user(&mut self, address: &str) -> bool {
+        let mut revoked = false;
+        if let Some(reply_address) = self.verified_users.remove(address) {
+            self.session_mappings
+                .retain(|_, mapped| mapped != &reply_address);
+            revoked = true;
+        }
+        revoked |= self.conversations.retire_user(address).is_some();
+        self.challenges.remove_user(address);
+        self.confirmations.remove_user(address);
+        for session in self.auth_flow.session_manager.remove_user(address) {
+            self.session_mappings.remove(&session.session_id);
+            revoked = true;
+        }
+        self.response_cache
+            .retain(|key, _| !key.starts_with(&Self::cache_key(address, "")));
+        revoked
+    }
+
+    fn end_session(&mut self, session_id: &str) -> Option<String> {
+        let session = self.auth_flow.session_manager.remove_session(session_id)?;
+        self.session_mappings.remove(session_id);
+
+        let remaining = self
+            .auth_flow
+            .session_manager
+            .get_session(&session.user_id)
+            .map(|latest| latest.reply_address.clone());
+        match remaining {
+            Some(reply_address) => {
+                if self.verified_users.get(&session.user_id) == Some(&session.reply_address) {
+                    self.verified_users
+                        .insert(session.user_id.clone(), reply_address);
+                }
+            }
+            None => {
+                self.revoke_user(&session.user_id);
+            }
+        }
+        Some(session.user_id)
+    }
+
+    fn handle_logout_command(&mut self, user_id: &str, args: &str) -> Result<String, String> {
+        match (args.trim(), self.current_session.clone()) {
+            ("", Some(session_id))
+                if self.auth_flow.session_manager.sessions_for(user_id).len() > 1 =>
+            {
+                self.end_session(&session_id);
+                self.save_state();
+                Ok(format!(
+                    "Logged out of session {}. Your other sessions stay active; send logout all to end them too.",
+                    session_id
+                ))
+            }
+            ("" | "all", _) => {
+                self.revoke_user(user_id);
+                self.save_state();
+                Ok(
+                    "Logged out. Send REGISTER:<reply_address> <public_key> to sign in again."
+                        .to_string(),
+                )
+            }
+            _ => Err("Invalid logout format. Use: logout [all]".to_string()),
+        }
+    }
+
+    fn handle_revoke_command(&mut self, target: &str) -> Result<String, String> {
+        if let Some(user) = self.end_session(target) {
+            self.save_state();
+            return Ok(format!("Revoked session {} of {}", target, user));
+        }
+
+        let users: Vec<String> = match self.session_mappings.get(target) {
+            Some(reply_address) => self
+                .verified_users
+                .iter()
+                .filter(|(_, mapped)| *mapped == reply_address)
+                .map(|(user, _)| user.clone())
+                .collect(),
+            None => vec![target.to_string()],
+        };
+        self.session_mappings.remove(target);
+
+        let revoked: Vec<String> = users
+            .into_iter()
+            .filter(|user| self.revoke_user(user))
+            .collect();
+        if revoked.is_empty() {
+            return Err(format!("No such session: {}", target));
+        }
+        self.save_state();
+        Ok(format!("Revoked: {}", revoked.join(", ")))
+    }
+
+    fn handle_admin_roles_command(&self) -> Result<String, String> {
+        let mut lines: Vec<String> = self
+            .admin_addresses
+            .iter()
+            .map(|user| format!("{} admin (config)", user))
+            .chain(
+                self.roles
+                    .iter()
+                    .filter(|(user, _)| !self.admin_addresses.contains(*user))
+                    .map(|(user, role)| format!("{} {}", user, role.as_str())),
+            )
+            .collect();
+        lines.sort();
+
+        if lines.is_empty() {
+            Ok("Everyone has the member role".to_string())
+        } else {
+            Ok(lines.join("\n"))
+        }
+    }
+
+    fn handle_set_role_command(&mut self, address: &str, role: Role) -> Result<String, String> {
+        if self.admin_addresses.contains(address) {
+            return Err(format!(
+                "{} is an admin via [admin] addresses; edit the config to change its role",
+                address
+            ));
+        }
+        if !self.verified_users.contains_key(address) {
+            return Err(format!("Unknown user: {}", address));
+        }
+
+        if role == Role::Member {
+            self.roles.remove(address);
+        } else {
+            self.roles.insert(address.to_string(), role);
+        }
+        self.response_cache
+            .retain(|key, _| !key.starts_with(&Self::cache_key(address, "")));
+        self.save_state();
+        Ok(format!("{} is now a {}", address, role.as_str()))
+    }
+
+    fn handle_unban_command(&mut self, address: &str) -> Result<String, String> {
+        if !self.bans.remove(address) {
+            return Err(format!("Not banned: {}", address));
+        }
+        self.save_state();
+        Ok(format!("Unbanned: {}", address))
+    }
+
+    fn handle_admin_audit_command(&self, args: &str) -> Result<String, String> {
+        let (entries, next) = match cursor::strip_arg(args) {
+            Some(token) => {
+                let after = cursor::decode("audit", token)?;
+                let page = cursor::page_after(
+                    self.audit_log
+                        .all()
+                        .into_iter()
+                        .map(|entry| (entry.seq, entry)),
+                    after,
+                    DEFAULT_AUDIT_LIMIT,
+                );
+                (page.items, page.next)
+            }
+            None => {
+                let limit = match args.trim() {
+                    "" => DEFAULT_AUDIT_LIMIT,
+                    value => value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|limit| *limit > 0)
+                        .ok_or_else(|| {
+                            "Invalid admin audit format. Use: admin audit [n|cursor=<cursor>]"
+                                .to_string()
+                        })?,
+                };
+                (self.audit_log.recent(limit), None)
+            }
+        };
+        if entries.is_empty() {
+            return Ok("Audit log is empty".to_string());
+        }
+
+        let lines = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "#{} {} {} {} {} -> {}",
+                    entry.seq,
+                    entry.timestamp,
+                    Self::truncate_for_log(&entry.sender, 12),
+                    if entry.ok { "ok" } else { "err" },
+                    entry.command,
+                    entry.result
+                )
+            })
+            .collect();
+        Ok(Self::paged_output(lines, "audit", next))
+    }
+
+    fn handle_admin_authlog_command(&self, args: &str) -> Result<String, String> {
+        let mut filter = None;
+        let mut limit = DEFAULT_AUDIT_LIMIT;
+        for part in args.split_whitespace() {
+            match part.parse::<usize>() {
+                Ok(0) => return Err("Invalid admin authlog limit".to_string()),
+                Ok(value) => limit = value,
+                Err(_) if filter.is_none() => filter = Some(part),
+                Err(_) => {
+                    return Err(
+                        "Invalid admin authlog format. Use: admin authlog [address|event] [n]"
+                            .to_string(),
+                    )
+                }
+            }
+        }
+
+        let mut entries: Vec<_> = self
+            .auth_log
+            .all()
+            .into_iter()
+            .filter(|entry| filter.is_none_or(|f| entry.user == f || entry.kind == f))
+            .collect();
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+        if entries.is_empty() {
+            return Ok("Auth log is empty".to_string());
+        }
+
+        Ok(entries
+            .iter()
+            .map(|entry| {
+                let line = format!(
+                    "#{} {} {} {}",
+                    entry.seq,
+                    entry.timestamp,
+                    entry.kind,
+                    Self::truncate_for_log(&entry.user, 12)
+                );
+                if entry.detail.is_empty() {
+                    line
+                } else {
+                    format!("{} {}", line, entry.detail)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn handle_admin_federation_command(&self) -> Result<String, String> {
+        Ok(self.federation.summary())
+    }
+
+    fn handle_admin_metrics_command(&self) -> Result<String, String> {
+        Ok(self.metrics.render())
+    }
+
+    fn handle_admin_stats_command(&self) -> Result<String, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        Ok(format!(
+            "Users: {}\nSessions: {}\nPending challenges: {}\nLocked out: {}\nBanned: {}\nFilesystem nodes: {}\nChat rooms: {}\nCached responses: {}",
+            self.verified_users.len(),
+            self.session_mappings.len(),
+            self.challenges.len(),
+            self.lockouts.locked(now),
+            self.bans.len(),
+            self.count_filesystem_nodes(),
+            self.chat_rooms.room_names().len(),
+            self.response_cache.len()
+        ))
+    }
+
+    pub fn is_banned(&self, address: &str) -> bool {
+        self.bans.contains(address)
+    }
+
+    fn handle_gc_command(&mut self) -> Result<String, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let report = self.filesystem.gc(now);
+        self.save_filesystem()?;
+
+        Ok(format!(
+            "GC complete: {} expired nodes, {} revisions pruned, {} links dissolved, {} bytes reclaimed",
+            report.expired_nodes,
+            report.pruned_revisions,
+            report.dissolved_links,
+            report.reclaimed_bytes
+        ))
+    }
+
+    fn handle_expire_command(&mut self, path: &str, seconds: u64) -> Result<String, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expiry = if seconds == 0 {
+            None
+        } else {
+            Some(now + seconds)
+        };
+        self.filesystem.set_expiry(path, expiry)?;
+        self.save_filesystem()?;
+
+        match expiry {
+            Some(expires_at) => Ok(format!("{} expires at {}", path, expires_at)),
+            None => Ok(format!("Expiry cleared for {}", path)),
+        }
+    }
+
+    fn exports_dir(&self) -> PathBuf {
+        self.data_dir.join("exports")
+    }
+
+    fn handle_export_command(&self, path: &str) -> Result<String, String> {
+        let archive = self.filesystem.export_archive(path)?;
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let label: String = path
+            .trim_matches('/')
+            .replace('/', "_")
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+            .collect();
+        let label = if label.is_empty() { "root" } else { &label };
+        let archive_name = format!("{}-{}.tar", label, timestamp);
+
+        let exports_dir = self.exports_dir();
+        std::fs::create_dir_all(&exports_dir)
+            .map_err(|e| format!("Failed to create exports directory: {}", e))?;
+        std::fs::write(exports_dir.join(&archive_name), &archive)
+            .map_err(|e| format!("Failed to write archive: {}", e))?;
+
+        Ok(format!(
+            "Exported {} to {} ({} bytes)",
+            path,
+            archive_name,
+            archive.len()
+        ))
+    }
+
+    fn handle_backup_command(&self) -> Result<String, String> {
+        self.flush_state()?;
+
+        let database_file = self
+            .db_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| "Database path has no file name".to_string())?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let report = backup::create(
+            &self.data_dir,
+            &Coordinator::state_files(&database_file),
+            now,
+        )?;
+
+        let location = report
+            .path
+            .strip_prefix(&self.data_dir)
+            .unwrap_or(&report.path)
+            .display()
+            .to_string();
+        Ok(format!(
+            "Backup written to {} ({} files, {} bytes)",
+            location, report.files, report.bytes
+        ))
+    }
+
+    fn handle_import_command(&mut self, archive_name: &str, dest: &str) -> Result<String, String> {
+        if archive_name.contains('/')
+            || archive_name.contains('\\')
+            || archive_name.starts_with('.')
+        {
+            return Err("Invalid archive name".to_string());
+        }
+
+        let archive = std::fs::read(self.exports_dir().join(archive_name))
+            .map_err(|e| format!("Failed to read archive {}: {}", archive_name, e))?;
+        let imported = self
+            .filesystem
+            .import_archive(dest, &archive, "coordinator")?;
+        self.save_filesystem()?;
+
+        Ok(format!(
+            "Imported {} entries from {} into {}",
+            imported, archive_name, dest
+        ))
+    }
+
+    fn handle_permissions_command(&self, user_id: &str, path: &str) -> Result<String, String> {
+        let node = self
+            .visible_node(user_id, path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if !node.permissions.can_read(user_id) {
+            return Err("Permission denied: cannot view permissions".to_string());
+        }
+
+        let mut result = format!("Permissions for {}:\n", path);
+        result.push_str(&format!("Owner: {}\n", node.permissions.owner));
+        result.push_str(&format!("Public read: {}\n", node.permissions.public_read));
+        result.push_str(&format!(
+            "Public write: {}\n",
+            node.permissions.public_write
+        ));
+        result.push_str(&format!("Read users: {:?}\n", node.permissions.read_users));
+        result.push_str(&format!("Write users: {:?}", node.permissions.write_users));
+
+        Ok(result)
+    }
+
+    fn handle_chmod_command(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        permissions: &str,
+    ) -> Result<String, String> {
+        self.filesystem.check_writable(path)?;
+        let node = self
+            .filesystem
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if node.permissions.owner != user_id {
+            return Err("Permission denied: only owner can change permissions".to_string());
+        }
+
+        match permissions {
+            "public" => {
+                node.permissions.public_read = true;
+                node.permissions.public_write = false;
+            }
+            "private" => {
+                node.permissions.public_read = false;
+                node.permissions.public_write = false;
+            }
+            "open" => {
+                node.permissions.public_read = true;
+                node.permissions.public_write = true;
+            }
+            _ => return Err("Invalid permissions. Use: public, private, or open".to_string()),
+        }
+
+        self.filesystem.sync_links(path)?;
+        self.save_filesystem()?;
+        Ok(format!("Permissions updated for {}", path))
+    }
+
+    fn handle_chown_command(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        new_owner: &str,
+    ) -> Result<String, String> {
+        let old_key = self.node_key(user_id);
+        let new_key = self.node_key(new_owner);
+        self.filesystem.check_writable(path)?;
+        let node = self
+            .filesystem
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if node.permissions.owner != user_id {
+            return Err("Permission denied: only owner can change ownership".to_string());
+        }
+
+        if node.encrypted {
+            let not_configured = || "Encryption at rest is not configured".to_string();
+            node.decrypt_content(&old_key.ok_or_else(not_configured)?)?;
+            node.encrypt_content(&new_key.ok_or_else(not_configured)?)?;
+        }
+
+        node.permissions.owner = new_owner.to_string();
+        node.permissions.read_users.clear();
+        node.permissions.write_users.clear();
+        node.permissions.read_users.push(new_owner.to_string());
+        node.permissions.write_users.push(new_owner.to_string());
+
+        self.filesystem.sync_links(path)?;
+        self.save_filesystem()?;
+        Ok(format!(
+            "Ownership of {} transferred to {}",
+            path, new_owner
+        ))
+    }
+
+    fn handle_grant_command(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        target_user: &str,
+        permission_type: &str,
+    ) -> Result<String, String> {
+        self.filesystem.check_writable(path)?;
+        let node = self
+            .filesystem
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if node.permissions.owner != user_id {
+            return Err("Permission denied: only owner can grant permissions".to_string());
+        }
+
+        match permission_type {
+            "read" => {
+                node.permissions
+                    .add_read_permission(target_user.to_string());
+                self.filesystem.sync_links(path)?;
+                self.save_filesystem()?;
+                Ok(format!(
+                    "Read permission granted to {} for {}",
+                    target_user, path
+                ))
+            }
+            "write" => {
+                node.permissions
+                    .add_write_permission(target_user.to_string());
+                self.filesystem.sync_links(path)?;
+                self.save_filesystem()?;
+                Ok(format!(
+                    "Write permission granted to {} for {}",
+                    target_user, path
+                ))
+            }
+            _ => Err("Invalid permission type. Use: read or write".to_string()),
+        }
+    }
+
+    fn handle_ls_command(&self, user_id: &str, options: &LsOptions) -> Result<String, String> {
+        let path = options.path.as_str();
+        let (offset, limit) = (options.offset, options.limit);
+        let node = self
+            .visible_node(user_id, path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if !self
+            .filesystem
+            .principal(user_id)
+            .can_read(&node.permissions)
+        {
+            return Err("Permission denied: cannot read directory".to_string());
+        }
+
+        if node.file_type != crate::filesystem::FileType::Directory {
+            return Err("Not a directory".to_string());
+        }
+
+        let list = ListOptions {
+            include_hidden: self.role_of(user_id).is_staff(),
+            ..options.list.clone()
+        };
+        let (mut listing, more) = node.list_children_with(&list, offset, limit);
+        if options.show_hash {
+            for entry in listing.iter_mut() {
+                if let Some(checksum) = node.get_child(entry).and_then(|child| child.checksum()) {
+                    *entry = format!("{}:{}", entry, &checksum[..16]);
+                }
+            }
+        }
+
+        if listing.is_empty() && offset == 0 {
+            Ok("(empty directory)".to_string())
+        } else if listing.is_empty() {
+            Ok("(no more entries)".to_string())
+        } else if more {
+            Ok(format!(
+                "{}\n(more: ls {}{} {} {})",
+                listing.join("  "),
+                options.flags(),
+                tokenizer::quote(path),
+                offset + listing.len(),
+                limit
+            ))
+        } else {
+            Ok(listing.join("  "))
+        }
+    }
+
+    fn handle_stat_command(&self, user_id: &str, path: &str) -> Result<String, String> {
+        let node = self
+            .visible_node(user_id, path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if !self
+            .filesystem
+            .principal(user_id)
+            .can_read(&node.permissions)
+        {
+            return Err("Permission denied: cannot read path".to_string());
+        }
+
+        let mut result = format!("Path: {}\n", path);
+        match node.file_type {
+            crate::filesystem::FileType::Directory => {
+                result.push_str("Type: directory\n");
+                result.push_str(&format!("Entries: {}\n", node.children.len()));
+            }
+            crate::filesystem::FileType::File => {
+                result.push_str("Type: file\n");
+                result.push_str(&format!("Size: {}\n", node.size()));
+                result.push_str(&format!("Version: {}\n", node.version));
+                result.push_str(&format!("Links: {}\n", self.filesystem.link_count(path)));
+            }
+        }
+        result.push_str(&format!("Owner: {}\n", node.permissions.owner));
+        result.push_str(&format!("Created: {}\n", node.created_at));
+        result.push_str(&format!("Modified: {}", node.modified_at));
+        if node.hidden {
+            result.push_str("\nHidden: yes");
+        }
+        if let Some(checksum) = node.checksum() {
+            result.push_str(&format!("\nSHA256: {}", checksum));
+        }
+
+        Ok(result)
+    }
+
+    fn handle_du_command(&self, user_id: &str, path: &str) -> Result<String, String> {
+        if self.visible_node(user_id, path).is_none() {
+            return Err(format!("Path not found: {}", path));
+        }
+
+        let usage = self.filesystem.disk_usage(path, user_id)?;
+        Ok(usage
+            .iter()
+            .filter(|(dir, _)| self.visible_node(user_id, dir).is_some())
+            .map(|(dir, bytes)| format!("{}\t{}", bytes, dir))
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    fn handle_cat_command(&self, user_id: &str, path: &str) -> Result<String, String> {
+        let node = self.readable_file(user_id, path)?;
+
+        if node.content.is_none() {
+            return Ok("(empty file)".to_string());
+        }
+
+        let key = self.read_key(user_id, node)?;
+        node.read_content(key.as_ref())
+    }
+
+    fn visible_node(&self, user_id: &str, path: &str) -> Option<&FileNode> {
+        if !self.role_of(user_id).is_staff() && self.filesystem.is_hidden(path) {
+            return None;
+        }
+        self.filesystem.resolve_path(path)
+    }
+
+    fn readable_file(&self, user_id: &str, path: &str) -> Result<&FileNode, String> {
+        let node = self
+            .visible_node(user_id, path)
+            .ok_or_else(|| format!("File not found: {}", path))?;
+
+        if !self
+            .filesystem
+            .principal(user_id)
+            .can_read(&node.permissions)
+        {
+            return Err("Permission denied: cannot read file".to_string());
+        }
+
+        if node.file_type != crate::filesystem::FileType::File {
+            return Err("Not a file".to_string());
+        }
+
+        Ok(node)
+    }
+
+    fn handle_cat_range_command(
+        &self,
+        user_id: &str,
+        path: &str,
+        offset: usize,
+        len: usize,
+    ) -> Result<String, String> {
+        if len == 0 {
+            return Err("Length must be greater than zero".to_string());
+        }
+
+        let node = self.readable_file(user_id, path)?;
+        let key = self.read_key(user_id, node)?;
+        let (chunk, more) = node.read_range(offset, len, key.as_ref())?;
+
+        if chunk.is_empty() {
+            Ok("(end of file)".to_string())
+        } else if more {
+            Ok(format!(
+                "{}\n(more: cat {} {} {})",
+                chunk,
+                tokenizer::quote(path),
+                offset + chunk.chars().count(),
+                len
+            ))
+        } else {
+            Ok(chunk)
+        }
+    }
+
+    fn handle_head_command(
+        &self,
+        user_id: &str,
+        args: &str,
+        from_end: bool,
+    ) -> Result<String, String> {
+        let name = if from_end { "tail" } else { "head" };
+        let parts = tokenizer::tokenize(args)?;
+        let (path, count) = match parts.as_slice() {
+            [path] => (path.as_str(), DEFAULT_HEAD_LINES),
+            [path, count] => (
+                path.as_str(),
+                count
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid line count. Use: {} <path> [lines]", name))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "Invalid {} format. Use: {} <path> [lines]",
+                    name, name
+                ))
+            }
+        };
+
+        let node = self.readable_file(user_id, path)?;
+        let key = self.read_key(user_id, node)?;
+        if from_end {
+            node.tail_lines(count, key.as_ref())
+        } else {
+            node.head_lines(count, key.as_ref())
+        }
+    }
+
+    fn handle_diff_command(
+        &self,
+        user_id: &str,
+        path: &str,
+        from: u32,
+        to: u32,
+    ) -> Result<String, String> {
+        if self.visible_node(user_id, path).is_none() {
+            return Err(format!("File not found: {}", path));
+        }
+
+        let key = match self.filesystem.resolve_path(path) {
+            Some(node) => self.read_key(user_id, node)?,
+            None => None,
+        };
+        self.filesystem.diff(path, from, to, user_id, key.as_ref())
+    }
+
+    fn handle_encrypt_command(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        encrypt: bool,
+    ) -> Result<String, String> {
+        let key = self.node_key(user_id).ok_or_else(|| {
+            "Encryption at rest is not configured on this coordinator".to_string()
+        })?;
+
+        self.filesystem.check_writable(path)?;
+        let node = self
+            .filesystem
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("File not found: {}", path))?;
+
+        if node.permissions.owner != user_id {
+            return Err("Permission denied: only owner can change encryption".to_string());
+        }
+
+        if encrypt {
+            node.encrypt_content(&key)?;
+        } else {
+            node.decrypt_content(&key)?;
+        }
+
+        self.filesystem.sync_links(path)?;
+        self.save_filesystem()?;
+        if encrypt {
+            Ok(format!("Encrypted at rest: {}", path))
+        } else {
+            Ok(format!("Decrypted: {}", path))
+        }
+    }
+
+    fn handle_mkdir_command(&mut self, user_id: &str, path: &str) -> Result<String, String> {
+        match self.filesystem.create_directory(path, user_id.to_string()) {
+            Ok(()) => {
+                let response = format!("Directory created: {}", path);
+
+                if let Err(e) = self.save_filesystem() {
+                    eprintln!("Warning: Failed to persist filesystem: {}", e);
+                }
+                self.emit(Event::file_change(user_id, path, WatchEvent::Created));
+
+                Ok(response)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn handle_touch_command(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        content: &str,
+        overwrite: bool,
+    ) -> Result<String, String> {
+        if overwrite && self.filesystem.resolve_path(path).is_some() {
+            self.write_existing_file(user_id, path, content.to_string())?;
+            return Ok(format!("File overwritten: {}", path));
+        }
+
+        match self
+            .filesystem
+            .create_file(path, content.to_string(), user_id.to_string())
+        {
+            Ok(()) => {
+                self.save_filesystem()?;
+                self.emit(Event::file_change(user_id, path, WatchEvent::Created));
+                Ok(format!("File created: {}", path))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn handle_write_command(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        content: &str,
+    ) -> Result<String, String> {
+        self.write_existing_file(user_id, path, content.to_string())?;
+        Ok(format!("File updated: {}", path))
+    }
+
+    fn write_existing_file(
+        &mut self,
+        user_id: &str,
+        path: &str,
+        content: String,
+    ) -> Result<(), String> {
+        let key = self
+            .filesystem
+            .resolve_path(path)
+            .filter(|node| node.encrypted)
+            .and_then(|node| self.node_key(&node.permissions.owner));
+        self.filesystem
+            .write_file(path, content, user_id, key.as_ref())?;
+        self.save_filesystem()?;
+        self.emit(Event::file_change(user_id, path, WatchEvent::Modified));
+        Ok(())
+    }
+
+    fn handle_cp_command(
+        &mut self,
+        user_id: &str,
+        src: &str,
+        dest: &str,
+    ) -> Result<String, String> {
+        self.filesystem.copy(src, dest, user_id)?;
+        self.save_filesystem()?;
+        self.emit(Event::file_change(user_id, dest, WatchEvent::Created));
+        Ok(format!("Copied {} to {}", src, dest))
+    }
+
+    fn handle_watch_command(&mut self, user_id: &str, path: &str) -> Result<String, String> {
+        let node = self
+            .filesystem
+            .resolve_path(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+
+        if !node.permissions.can_read(user_id) {
+            return Err("Permission denied: cannot read path".to_string());
+        }
+
+        if self.watches.watch(user_id, path) {
+            Ok(format!("Watching {}", path))
+        } else {
+            Ok(format!("Already watching {}", path))
+        }
+    }
+
+    fn handle_unwatch_command(&mut self, user_id: &str, path: &str) -> Result<String, String> {
+        if self.watches.unwatch(user_id, path) {
+            Ok(format!("Stopped watching {}", path))
+        } else {
+            Err(format!("Not watching {}", path))
+        }
+    }
+
+    fn handle_ln_command(
+        &mut self,
+        user_id: &str,
+        src: &str,
+        dest: &str,
+    ) -> Result<String, String> {
+        self.filesystem.link(src, dest, user_id)?;
+        self.save_filesystem()?;
+        self.emit(Event::file_change(user_id, dest, WatchEvent::Created));
+        Ok(format!("Linked {} to {}", dest, src))
+    }
+
+    fn handle_rm_command(&mut self, user_id: &str, path: &str) -> Result<String, String> {
+        match self.filesystem.remove(path, user_id) {
+            Ok(()) => {
+                self.save_filesystem()?;
+                self.emit(Event::file_change(user_id, path, WatchEvent::Removed));
+                Ok(format!("Directory removed: {}", path))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn handle_echo_command(&mut self, user_id: &str, command: &str) -> Result<String, String> {
+        let parts: Vec<&str> = command.splitn(2, " > ").collect();
+        if parts.len() != 2 {
+            return Err("Invalid echo format. Use: echo \"content\" > <file>".to_string());
+        }
+
+        let echo_part = parts[0].trim();
+        let file_path = parts[1].trim();
+
+        if !echo_part.starts_with("echo ") {
+            return Err("Command must start with 'echo'".to_string());
+        }
+
+        let content_part = echo_part.strip_prefix("echo ").unwrap().trim();
+        let content = if content_part.starts_with('"') && content_part.ends_with('"') {
+            content_part[1..content_part.len() - 1].to_string()
+        } else {
+            content_part.to_string()
+        };
+
+        if self.filesystem.resolve_path(file_path).is_some() {
+            self.write_existing_file(user_id, file_path, content)?;
+            Ok(format!("File updated: {}", file_path))
+        } else {
+            match self
+                .filesystem
+                .create_file(file_path, content, user_id.to_string())
+            {
+                Ok(()) => {
+                    self.save_filesystem()?;
+                    self.emit(Event::file_change(user_id, file_path, WatchEvent::Created));
+                    Ok(format!("File created: {}", file_path))
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    fn handle_chat_command(
+        &mut self,
+        user_id: &str,
+        folder_path: &str,
+        message: &str,
+    ) -> Result<String, String> {
+        let folder_node = self
+            .filesystem
+            .resolve_path(folder_path)
+            .ok_or_else(|| format!("Folder not found: {}", folder_path))?;
+
+        if folder_node.file_type != crate::filesystem::FileType::Directory {
+            return Err("Can only chat in directories".to_string());
+        }
+
+        if !folder_node.permissions.can_read(user_id) {
+            return Err("Permission denied: cannot access chatroom".to_string());
+        }
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let chat_entry = format!(
+            "[{}] {}: {}",
+            timestamp,
+            self.get_user_display_name(user_id),
+            message
+        );
+
+        let chat_log_path = format!("{}/.chat_log", folder_path.trim_end_matches('/'));
+        self.filesystem.check_writable(&chat_log_path)?;
+
+        let event = if let Some(chat_file) = self.filesystem.resolve_path_mut(&chat_log_path) {
+            let current_content = chat_file.content.clone().unwrap_or_default();
+            let new_content = if current_content.is_empty() {
+                chat_entry
+            } else {
+                format!("{}\n{}", current_content, chat_entry)
+            };
+            chat_file.update_content(new_content)?;
+            self.filesystem.sync_links(&chat_log_path)?;
+            WatchEvent::Modified
+        } else {
+            self.filesystem
+                .create_file(&chat_log_path, chat_entry, "coordinator".to_string())?;
+            WatchEvent::Created
+        };
+
+        self.save_filesystem()?;
+        self.emit(Event::file_change(user_id, &chat_log_path, event));
+
+        Ok(format!("Message sent to chatroom: {}", folder_path))
+    }
+
+    fn handle_chat_rooms_command(&self) -> Result<String, String> {
+        let names = self.chat_rooms.room_names();
+        if names.is_empty() {
+            Ok("No chat rooms yet. Create one with: chat create <room>".to_string())
+        } else {
+            Ok(names.join("  "))
+        }
+    }
+
+    fn handle_chat_create_command(&mut self, user_id: &str, room: &str) -> Result<String, String> {
+        self.chat_rooms.create(room, user_id)?;
+        self.save_chat_rooms()?;
+        Ok(format!("Chat room created: {}", room))
+    }
+
+    fn handle_chat_post_command(
+        &mut self,
+        user_id: &str,
+        room: &str,
+        text: &str,
+    ) -> Result<String, String> {
+        let author = self.get_user_display_name(user_id);
+        let id = self
+            .chat_rooms
+            .post(room, &author, text.trim_matches('"'))?;
+        self.save_chat_rooms()?;
+        Ok(format!("Posted #{} to {}", id, room))
+    }
+
+    fn handle_chat_read_command(&self, room: &str, since: Option<&str>) -> Result<String, String> {
+        let scope = format!("chat:{}", room);
+        let since = match since {
+            Some(value) => Some(match cursor::strip_arg(value) {
+                Some(token) => cursor::decode(&scope, token)?,
+                None => value.trim().parse::<u64>().map_err(|_| {
+                    "Invalid message id. Use: chat read <room> [since|cursor=<cursor>]"
+                })?,
+            }),
+            None => None,
+        };
+        let chat_room = self
+            .chat_rooms
+            .get(room)
+            .ok_or_else(|| format!("Room not found: {}", room))?;
+        let (messages, next) = match since {
+            Some(after) => {
+                let page = cursor::page_after(
+                    chat_room
+                        .messages_since(Some(after), usize::MAX)
+                        .into_iter()
+                        .map(|message| (message.id, message)),
+                    after,
+                    chat::DEFAULT_READ_LIMIT,
+                );
+                (page.items, page.next)
+            }
+            None => (
+                chat_room.messages_since(None, chat::DEFAULT_READ_LIMIT),
+                None,
+            ),
+        };
+        if messages.is_empty() {
+            return Ok(format!("No new messages in {}", room));
+        }
+
+        let lines = messages
+            .iter()
+            .map(|message| {
+                format!(
+                    "#{} [{}] {}: {}",
+                    message.id, message.timestamp, message.author, message.text
+                )
+            })
+            .collect();
+        Ok(Self::paged_output(lines, &scope, next))
+    }
+
+    fn save_chat_rooms(&self) -> Result<(), String> {
+        self.chat_rooms.save(self.data_dir.join(CHAT_ROOMS_FILE))
+    }
+
+    fn handle_history_command(&self, user_id: &str, folder_path: &str) -> Result<String, String> {
+        let folder_node = self
+            .filesystem
+            .resolve_path(folder_path)
+            .ok_or_else(|| format!("Folder not found: {}", folder_path))?;
+
+        if !folder_node.permissions.can_read(user_id) {
+            return Err("Permission denied: cannot access chatroom".to_string());
+        }
+
+        let chat_log_path = format!("{}/.chat_log", folder_path.trim_end_matches('/'));
+
+        if let Some(chat_file) = self.filesystem.resolve_path(&chat_log_path) {
+            Ok(chat_file
+                .content
+                .clone()
+                .unwrap_or_else(|| "No chat history".to_string()))
+        } else {
+            Ok("No chat history in this folder yet. Start chatting!".to_string())
+        }
+    }
+
+    fn get_user_display_name(&self, user_id: &str) -> String {
+        if user_id.len() > 8 {
+            user_id[user_id.len() - 8..].to_string()
+        } else {
+            user_id.to_string()
+        }
+    }
+
+    fn handle_authentication(&mut self, message: &Message) -> Result<String, String> {
+        let rest = message.memo_text.strip_prefix("AUTH:").unwrap_or_default();
+        let mut fields = rest.split_whitespace();
+        let provided_challenge = fields.next().unwrap_or_default();
+        let Some(signature) = fields.next().or(message.signature.as_deref()) else {
+            return Err("Invalid auth format. Use AUTH:<challenge> <signature>".to_string());
+        };
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let consumed = self.challenges.consume(
+            &message.sender_address,
+            provided_challenge,
+            &self.challenge_config,
+            now,
+        );
+        self.save_state();
+        consumed.map_err(|e| format!("Authentication failed. {}", e))?;
+        let Some(public_key) = self.user_keys.get(&message.sender_address) else {
+            return Err(
+                "Authentication failed. No public key registered; send REGISTER:<reply_address> <public_key> first."
+                    .to_string(),
+            );
+        };
+
+        let mut candidates: Vec<String> = self
+            .auth_flow
+            .session_manager
+            .sessions_for(&message.sender_address)
+            .into_iter()
+            .rev()
+            .map(|session| session.reply_address.clone())
+            .collect();
+        if candidates.is_empty() {
+            candidates.push(message.sender_address.clone());
+        }
+        let mut verified = Err(String::new());
+        for candidate in candidates {
+            verified = identity::verify_auth(public_key, &candidate, provided_challenge, signature)
+                .map(|_| candidate);
+            if verified.is_ok() {
+                break;
+            }
+        }
+        let reply_address = verified.map_err(|e| format!("Authentication failed. {}", e))?;
+
+        let sessions = &mut self.auth_flow.session_manager;
+        let session_id = sessions
+            .create_session(message.sender_address.clone(), reply_address.clone())
+            .session_id
+            .clone();
+        sessions.authenticate(&session_id);
+        let expires_at = sessions
+            .session(&session_id)
+            .map_or(0, |session| session.expires_at);
+        self.verified_users
+            .entry(message.sender_address.clone())
+            .or_insert_with(|| reply_address.clone());
+        self.session_mappings
+            .insert(session_id.clone(), reply_address);
+        self.save_state();
+
+        Ok(format!(
+            "Authentication successful. Session ID: {} (expires at {}; send renew to extend)",
+            session_id, expires_at
+        ))
+    }
+
+    pub fn get_reply_address_by_session(&self, session_id: &str) -> Option<String> {
+        self.session_mappings.get(session_id).cloned()
+    }
+
+    pub fn get_all_sessions(&self) -> &HashMap<String, String> {
+        &self.session_mappings
+    }
+
+    pub fn prune_quotas(&mut self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.quotas.prune(now);
+    }
+
+    pub fn retire_stale_conversations(&mut self) -> usize {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let retired = self
+            .conversations
+            .retire_stale(now, self.conversation_config.max_idle_secs);
+        if !retired.is_empty() {
+            self.save_state();
+        }
+        retired.len()
+    }
+
+    pub fn cleanup_expired_sessions(&mut self) {
+        for session in self.auth_flow.cleanup_expired_sessions() {
+            self.emit(Event::SessionExpired {
+                user: session.user_id,
+                session_id: session.session_id,
+            });
+        }
+        let active_addresses: HashSet<String> = self
+            .auth_flow
+            .session_manager
+            .active_reply_addresses()
+            .into_iter()
+            .collect();
+
+        self.session_mappings
+            .retain(|_, reply_address| active_addresses.contains(reply_address));
+
+        let sessions = &self.auth_flow.session_manager;
+        self.verified_users
+            .retain(|user, reply_address| match sessions.get_session(user) {
+                Some(latest) => {
+                    if !active_addresses.contains(reply_address) {
+                        *reply_address = latest.reply_address.clone();
+                    }
+                    true
+                }
+                None => false,
+            });
+        self.challenges
+            .retain_users(|user| sessions.get_session(user).is_some());
+        self.save_state();
+    }
+
+    pub fn prune_lockouts(&mut self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.lockouts.prune(&self.lockout_config, now);
+    }
+
+    pub fn prune_confirmations(&mut self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.confirmations.prune(&self.confirmation_config, now);
+    }
+
+    pub fn prune_fragments(&mut self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for (sender, id, missing) in self.reassembler.prune(&self.fragment_config, now) {
+            println!(
+                "🧩 Dropped fragmented memo {} from {}: {} parts never arrived",
+                id,
+                Self::truncate_for_log(&sender, 12),
+                missing
+            );
+        }
+    }
+
+    pub fn prune_probes(&mut self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.probes.prune(&self.ownership, now);
+    }
+
+    pub fn prune_challenges(&mut self) -> usize {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expired = self.challenges.prune(&self.challenge_config, now);
+        if expired > 0 {
+            self.save_state();
+        }
+        expired
+    }
+
+    pub fn process_incoming_message(&mut self, message: &Message) -> Result<String, String> {
+        let result = self.dispatch_message(message);
+        if let Err(e) = &result {
+            let rejected = !message.memo_text.starts_with("AUTH:")
+                && matches!(
+                    response::Status::classify(e),
+                    response::Status::Unauthenticated
+                        | response::Status::PaymentRequired
+                        | response::Status::Denied
+                        | response::Status::Throttled
+                );
+            if rejected {
+                self.emit(Event::CommandRejected {
+                    user: message.sender_address.clone(),
+                    command: message.memo_text.clone(),
+                    reason: e.clone(),
+                });
+            }
+        }
+        self.emit(Event::MessageProcessed {
+            sender: message.sender_address.clone(),
+            txid: message.txid.clone(),
+            memo: message.memo_text.clone(),
+            result: result.clone(),
+        });
+        result
+    }
+
+    fn dispatch_message(&mut self, message: &Message) -> Result<String, String> {
+        if e2e::is_encrypted(&message.memo_text) {
+            return self.dispatch_message(&self.open_memo(message)?);
+        }
+
+        if let Some(decoded) = message.decode_memo()? {
+            return self.dispatch_message(&decoded);
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if message.is_expired(now) {
+            self.metrics.record_expired();
+            return Err(format!(
+                "Expired: command expired at {}, received at {}; it was not run",
+                message.expires_at.unwrap_or_default(),
+                now
+            ));
+        }
+
+        let sanitized = self.resolve_reply_to(&Message {
+            memo_text: memo_decoder::sanitize_text(&message.memo_text),
+            ..message.clone()
+        });
+        let message = &sanitized;
+
+        if message.memo_text.starts_with(FEDERATION_PREFIX) {
+            return self.handle_federation_message(&message.memo_text);
+        }
+
+        let policy = self.policy.clone();
+        policy::evaluate(&policy, self, message).into_result()?;
+
+        for interceptor in self.interceptors.clone() {
+            if let Some(result) = interceptor.intercept(self, message) {
+                println!("🧩 Message handled by interceptor {}", interceptor.name());
+                return result;
+            }
+        }
+
+        if let Some(request) = guest::parse(&message.memo_text) {
+            return self.handle_guest_command(request?.command, message.amount_zatoshis);
+        }
+
+        if let Some(request) = capability::parse(&message.memo_text) {
+            return self.handle_capability_command(&request?, message.amount_zatoshis);
+        }
+
+        if message.memo_text.starts_with("REGISTER:") {
+            return self.handle_registration(message);
+        }
+
+        let (body, _) = signing::split_signature(&message.memo_text);
+        if let Some(code) = body.strip_prefix(ownership::PROVE_PREFIX) {
+            return self.handle_ownership_proof(&message.sender_address, code.trim());
+        }
+
+        if message.memo_text.starts_with("AUTH:") {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let user = &message.sender_address;
+            self.lockouts
+                .check(user, &self.lockout_config, now, message.amount_zatoshis)?;
+            let result = self.handle_authentication(message);
+            match &result {
+                Ok(_) => self.lockouts.record_success(user),
+                Err(e) if e.starts_with("Invalid auth format") => {}
+                Err(_) => {
+                    if let Some(until) =
+                        self.lockouts
+                            .record_failure(user, &self.lockout_config, now)
+                    {
+                        println!(
+                            "🔒 Locking out {} until {} after repeated AUTH failures",
+                            Self::truncate_for_log(user, 12),
+                            until
+                        );
+                    }
+                }
+            }
+            let user = message.sender_address.clone();
+            self.emit(match &result {
+                Ok(_) => Event::AuthSucceeded { user },
+                Err(e) => Event::AuthFailed {
+                    user,
+                    reason: e.clone(),
+                },
+            });
+            return result;
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let (body, _) = signing::split_signature(&message.memo_text);
+        if let Some(routed) = self.conversations.route(body, now)? {
+            if self.verified_users.contains_key(&routed.user) {
+                self.verify_command_signature(
+                    &routed.user,
+                    &message.memo_text,
+                    message.reply_to.as_deref(),
+                )?;
+                self.current_session =
+                    Some(self.require_session(&routed.user, &message.memo_text)?);
+                let synthetic_message = Message {
+                    sender_address: routed.user,
+                    recipient_address: message.recipient_address.clone(),
+                    memo_text: routed.command,
+                    signature: Some("conv_id_auth".to_string()),
+                    txid: message.txid.clone(),
+                    timestamp: message.timestamp,
+                    amount_zatoshis: message.amount_zatoshis,
+                    output_index: message.output_index,
+                    block_height: message.block_height,
+                    in_reply_to: message.in_reply_to.clone(),
+                    expires_at: message.expires_at,
+                    priority: message.priority,
+                    reply_to: message.reply_to.clone(),
+                };
+                let result = self.handle_authenticated_command(&synthetic_message);
+                self.current_session = None;
+                return result;
+            } else {
+                return Err("Invalid conversation ID - user not registered".to_string());
+            }
+        }
+
+        let signature = if self.verified_users.contains_key(&message.sender_address) {
+            Some(self.verify_command_signature(
+                &message.sender_address,
+                &message.memo_text,
+                message.reply_to.as_deref(),
+            )?)
+        } else {
+            None
+        };
+        if signature.is_none() {
+            return Err(
+                "Authentication required. Send REGISTER:<reply_address> first.".to_string(),
+            );
+        }
+        self.current_session =
+            Some(self.require_session(&message.sender_address, &message.memo_text)?);
+        let result = self.handle_authenticated_command(&Message {
+            memo_text: body.to_string(),
+            signature,
+            ..message.clone()
+        });
+        self.current_session = None;
+        result
+    }
+
+    fn record_registration(&mut self, user: &str, reply_address: &str) -> (String, String) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let conversation = self.conversations.open(user, reply_address, now);
+
+        self.verified_users
+            .insert(user.to_string(), reply_address.to_string());
+
+        (conversation.id, conversation.participant_id)
+    }
+
+    fn replicate(&mut self, op: FederationOp) {
+        if !self.federation.is_enabled() {
+            return;
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        match self.federation.outgoing(&op) {
+            Ok(memos) => self.queue_federation_memos(memos, now),
+            Err(e) => eprintln!("⚠️  Not replicating {:?}: {}", op, e),
+        }
+        self.save_state();
+    }
+
+    fn queue_federation_memos(&self, memos: Vec<(String, String)>, now: u64) {
+        let mut queue = self.lock_outbound();
+        for (address, memo) in memos {
+            if let Err(e) = queue.enqueue(&address, &memo, now) {
+                eprintln!("⚠️  Failed to queue federation delta: {}", e);
+            }
+        }
+    }
+
+    fn handle_federation_message(&mut self, memo: &str) -> Result<String, String> {
+        let received = self.federation.accept(memo)?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.queue_federation_memos(received.replies, now);
+
+        let origin = received.origin;
+        let mut applied = Vec::new();
+        for op in received.ops {
+            match self.apply_federation_op(&origin, op) {
+                Ok(line) => applied.push(line),
+                Err(e) => applied.push(format!("Skipped delta from {}: {}", origin, e)),
+            }
+        }
+        self.save_state();
+
+        if !applied.is_empty() {
+            return Ok(applied.join("\n"));
+        }
+        Ok(match self.federation.waiting_for(&origin) {
+            Some(seq) => format!("Buffered delta from {}; waiting for seq {}", origin, seq),
+            None => format!("Handled federation request from {}", origin),
+        })
+    }
+
+    fn apply_federation_op(&mut self, origin: &str, op: FederationOp) -> Result<String, String> {
+        match op {
+            FederationOp::Register { user, reply, key } => {
+                if !self.verified_users.contains_key(&user) {
+                    self.record_registration(&user, &reply);
+                    if let Some(key) = key {
+                        self.user_keys.insert(user.clone(), key);
+                    }
+                }
+                Ok(format!("Applied registration of {} from {}", user, origin))
+            }
+            FederationOp::Rekey { user, reply, key } => {
+                if !self.verified_users.contains_key(&user) {
+                    return Err(format!("rekey for unknown user {}", user));
+                }
+                if let Some(reply) = reply {
+                    self.record_registration(&user, &reply);
+                }
+                if let Some(key) = key {
+                    self.user_keys.insert(user.clone(), key);
+                }
+                Ok(format!("Applied rekey of {} from {}", user, origin))
+            }
+            FederationOp::Command { user, command } => {
+                let (handler, args) = self
+                    .commands
+                    .resolve(&command)
+                    .ok_or_else(|| format!("Unknown replicated command from {}", origin))?;
+                if handler.spec().role > Role::Member {
+                    return Err(format!("Refusing replicated admin command from {}", origin));
+                }
+                if self.is_banned(&user) {
+                    return Err(format!("Replicated command from banned user {}", user));
+                }
+                handler.execute(self, &user, args)?;
+                Ok(format!(
+                    "Applied '{}' for {} from {}",
+                    command, user, origin
+                ))
+            }
+            FederationOp::Resend { .. } => Err("resend requests carry no state".to_string()),
+        }
+    }
+
+    fn handle_registration(&mut self, message: &Message) -> Result<String, String> {
+        let rest = message
+            .memo_text
+            .strip_prefix("REGISTER:")
+            .unwrap_or_default();
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let (reply_address, public_key) = match fields.as_slice() {
+            [reply_address] => (reply_address.to_string(), None),
+            [reply_address, public_key] => {
+                (reply_address.to_string(), Some(public_key.to_string()))
+            }
+            _ => {
+                return Err(
+                    "Invalid registration format. Use REGISTER:<reply_address> <public_key>"
+                        .to_string(),
+                )
+            }
+        };
+        if let Some(key) = &public_key {
+            identity::parse_public_key(key).map_err(|e| format!("Invalid registration: {}", e))?;
+        }
+        if self.is_banned(&message.sender_address) || self.is_banned(&reply_address) {
+            return Err("Access denied: this address is banned".to_string());
+        }
+
+        if let Some(current) = self.verified_users.get(&message.sender_address).cloned() {
+            let new_key = match (&public_key, self.user_keys.get(&message.sender_address)) {
+                (Some(key), Some(existing)) if key != existing => {
+                    return Err("Already registered with a different public key".to_string());
+                }
+                (Some(key), None) => Some(key.clone()),
+                _ => None,
+            };
+            let challenge_suffix =
+                if new_key.is_some() || self.user_keys.contains_key(&message.sender_address) {
+                    format!(
+                        " AUTH_CHALLENGE:{}",
+                        self.issue_challenge(&message.sender_address, &reply_address)?
+                    )
+                } else {
+                    String::new()
+                };
+            if let Some(key) = new_key {
+                self.user_keys
+                    .insert(message.sender_address.clone(), key.clone());
+                self.replicate(FederationOp::Rekey {
+                    user: message.sender_address.clone(),
+                    reply: None,
+                    key: Some(key),
+                });
+            }
+            let (conversation_id, participant_id) =
+                self.record_registration(&message.sender_address, &current);
+            self.save_state();
+            return Ok(format!(
+                "Already registered! ConvID: {} PartID: {}{}{}",
+                conversation_id,
+                participant_id,
+                challenge_suffix,
+                self.registration_suffix()
+            ));
+        }
+
+        if self.ownership.require_proof {
+            return self.send_ownership_probe(&message.sender_address, &reply_address, public_key);
+        }
+        self.complete_registration(&message.sender_address, &reply_address, public_key)
+    }
+
+    fn send_ownership_probe(
+        &mut self,
+        user: &str,
+        reply_address: &str,
+        public_key: Option<String>,
+    ) -> Result<String, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let code = self.probes.issue(user, reply_address, public_key, now);
+        self.queue_reply_memos(
+            reply_address,
+            vec![format!(
+                "{}{} - send {}{} within {} seconds to finish registering",
+                ownership::PROBE_PREFIX,
+                code,
+                ownership::PROVE_PREFIX,
+                code,
+                self.ownership.ttl_secs
+            )],
+            None,
+        )?;
+        println!(
+            "📨 Ownership probe sent to {} for {}",
+            Self::truncate_for_log(reply_address, 12),
+            Self::truncate_for_log(user, 12)
+        );
+        Ok(format!(
+            "Registration pending: a probe code was sent to {}. Send {}<code> to prove you control it.",
+            reply_address,
+            ownership::PROVE_PREFIX
+        ))
+    }
+
+    fn handle_ownership_proof(&mut self, user: &str, code: &str) -> Result<String, String> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let pending = self.probes.prove(user, code, &self.ownership, now)?;
+        if self.verified_users.contains_key(user) {
+            return Err("Invalid proof: address is already registered".to_string());
+        }
+        self.complete_registration(user, &pending.reply_address, pending.public_key)
+    }
+
+    fn complete_registration(
+        &mut self,
+        user: &str,
+        reply_address: &str,
+        public_key: Option<String>,
+    ) -> Result<String, String> {
+        let (conversation_id, participant_id) = self.record_registration(user, reply_address);
+        if let Some(key) = &public_key {
+            self.user_keys.insert(user.to_string(), key.clone());
+        }
+        self.emit(Event::UserRegistered {
+            user: user.to_string(),
+            reply_address: reply_address.to_string(),
+        });
+        self.replicate(FederationOp::Register {
+            user: user.to_string(),
+            reply: reply_address.to_string(),
+            key: public_key,
+        });
+
+        let challenge_value = self.issue_challenge(user, reply_address)?;
+        self.save_state();
+
+        let sender_preview = Self::truncate_for_log(user, 12);
+        let reply_preview = Self::truncate_for_log(reply_address, 12);
+        println!(
+            "✅ New user registered: {} -> {}",
+            sender_preview, reply_preview
+        );
+
+        println!(
+            "   ConvID: {} | PartID: {}",
+            conversation_id, participant_id
+        );
+
+        Ok(format!(
+            "Registration successful! ConvID: {} PartID: {} AUTH_CHALLENGE:{} - Save these for future commands.{}",
+            conversation_id,
+            participant_id,
+            challenge_value,
+            self.registration_suffix()
+        ))
+    }
+
+    fn issue_challenge(&mut self, user: &str, reply_address: &str) -> Result<String, String> {
+        if self.is_banned(user) {
+            return Err("Access denied: this address is banned".to_string());
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let challenge = self
+            .auth_flow
+            .initiate_authentication(user.to_string(), reply_address.to_string());
+        let challenge_value = challenge
+            .strip_prefix("AUTH_CHALLENGE:")
+            .unwrap_or("")
+            .to_string();
+        self.challenges
+            .issue(user, &challenge_value, &self.challenge_config, now)?;
+        self.emit(Event::ChallengeIssued {
+            user: user.to_string(),
+            reply_address: reply_address.to_string(),
+        });
+        Ok(challenge_value)
+    }
+
+    pub fn features(&self) -> Features {
+        let mut commands = self.commands.top_level_names(Role::Admin);
+        for (alias, _) in self.commands.aliases() {
+            let top = alias.split(' ').next().unwrap_or_default().to_string();
+            if !commands.contains(&top) {
+                commands.push(top);
+            }
+        }
+        Features {
+            protocols: vec![handshake::PROTOCOL_VERSION],
+            max_memo: memo_decoder::MAX_MEMO_SIZE,
+            max_fragments: self.fragment_config.max_fragments,
+            compression: vec![message::COMPRESSION_CODEC.to_string()],
+            encryption: vec![handshake::ENCRYPTION_SCHEME.to_string()],
+            formats: [
+                handshake::FORMAT_COMPACT,
+                handshake::FORMAT_JSON,
+                handshake::FORMAT_BATCH,
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            commands,
+        }
+    }
+
+    fn registration_suffix(&self) -> String {
+        let compression = format!(
+            " {} Compress: {}",
+            self.features().without_commands().encode(),
+            message::COMPRESSION_CODEC
+        );
+        if self.signing.enabled {
+            format!("{} CoordKey: {}", compression, self.signer.public_key_hex())
+        } else {
+            compression
+        }
+    }
+
+    fn verify_command_signature(
+        &self,
+        user: &str,
+        memo: &str,
+        reply_to: Option<&str>,
+    ) -> Result<String, String> {
+        let Some(public_key) = self.user_keys.get(user) else {
+            return Err(
+                "Authentication required: no public key registered for this address; send REGISTER:<reply_address> <public_key> first."
+                    .to_string(),
+            );
+        };
+        let reply_address = self
+            .verified_users
+            .get(user)
+            .map(String::as_str)
+            .unwrap_or(user);
+        let verified = identity::verify_command(public_key, reply_address, memo).or_else(|e| {
+            self.auth_flow
+                .session_manager
+                .sessions_for(user)
+                .into_iter()
+                .filter(|session| session.is_authenticated)
+                .find_map(|session| {
+                    identity::verify_command(public_key, &session.reply_address, memo).ok()
+                })
+                .or_else(|| {
+                    reply_to.and_then(|reply_to| {
+                        identity::verify_command(public_key, reply_to, memo).ok()
+                    })
+                })
+                .ok_or(e)
+        });
+        let (_, signature) = verified.map_err(|e| format!("Authentication required: {}", e))?;
+        Ok(signature.to_string())
+    }
+
+    fn require_session(&mut self, user: &str, memo: &str) -> Result<String, String> {
+        let session_id = self.command_session(user, memo).ok_or_else(|| {
+            "Authentication required: no authenticated session; answer your AUTH_CHALLENGE with AUTH:<challenge> <signature> first."
+                .to_string()
+        })?;
+        self.auth_flow.session_manager.record_command(&session_id);
+        Ok(session_id)
+    }
+
+    fn command_session(&self, user: &str, memo: &str) -> Option<String> {
+        let public_key = self.user_keys.get(user)?;
+        self.auth_flow
+            .session_manager
+            .sessions_for(user)
+            .into_iter()
+            .filter(|session| session.is_authenticated)
+            .find(|session| {
+                identity::verify_command(public_key, &session.reply_address, memo).is_ok()
+            })
+            .map(|session| session.session_id.clone())
+    }
+
+    pub fn get_reply_address(&self, user_id: &str) -> Option<String> {
+        self.verified_users.get(user_id).cloned()
+    }
+
+    pub fn is_user_verified(&self, user_id: &str) -> bool {
+        self.verified_users.contains_key(user_id)
+    }
+
+    pub fn poll_for_new_messages(&mut self) -> Result<Vec<Message>, String> {
+        let all_messages = self.wallet.poll_once().inspect_err(|e| {
+            self.record_poll_error(e);
+        })?;
+        self.record_sync(None);
+        Ok(self.filter_new_messages(all_messages))
+    }
+
+    pub fn filter_new_messages(&mut self, all_messages: Vec<Message>) -> Vec<Message> {
+        self.prune_processed_txids();
+
+        let mut new_messages = Vec::new();
+        let mut _processed_count = 0;
+
+        for msg in all_messages {
+            if let Some(key) = msg.idempotency_key() {
+                let replied = self.processed.get(&key).is_some_and(|entry| entry.replied);
+                if replied || self.processed_txids.contains(&key) {
+                    _processed_count += 1;
+                    continue;
+                } else {
+                    self.processed_txids.insert(key);
+                    new_messages.push(msg);
+                }
+            } else {
+                new_messages.push(msg);
+            }
+        }
+
+        if !new_messages.is_empty() {
+            println!("📨 Found {} new messages", new_messages.len());
+        }
+
+        new_messages
+    }
+
+    pub async fn start_json_rpc_server(
+        &self,
+        bind_address: String,
+        port: u16,
+    ) -> Result<(), String> {
+        let coordinator_data = self.get_coordinator_status();
+
+        let status_route = warp::path("status")
+            .and(warp::get())
+            .map(move || warp::reply::json(&coordinator_data));
+
+        let filesystem_route = warp::path("filesystem")
+            .and(warp::path::param::<String>())
+            .and(warp::get())
+            .map(move |path: String| {
+                let response = json!({
+                    "path": path,
+                    "type": "directory",
+                    "children": ["file1.txt", "folder1/"],
+                    "message": "JSON-RPC filesystem query"
+                });
+                warp::reply::json(&response)
+            });
+
+        let chat_route = warp::path("chat")
+            .and(warp::path::param::<String>())
+            .and(warp::get())
+            .map(move |folder: String| {
+                let response = json!({
+                    "folder": folder,
+                    "history": [
+                        {"timestamp": 1640995200, "user": "user123", "message": "Hello!"},
+                        {"timestamp": 1640995260, "user": "user456", "message": "Hi there!"}
+                    ],
+                    "message": "JSON-RPC chat history"
+                });
+                warp::reply::json(&response)
+            });
+
+        let routes = status_route
+            .or(filesystem_route)
+            .or(chat_route)
+            .with(warp::cors().allow_any_origin());
+
+        println!("JSON-RPC server starting on {}:{}", bind_address, port);
+
+        warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+
+        Ok(())
+    }
+
+    fn get_coordinator_status(&self) -> Value {
+        json!({
+            "status": "running",
+            "verified_users": self.verified_users.len(),
+            "pending_challenges": self.challenges.len(),
+            "filesystem_nodes": self.count_filesystem_nodes(),
+            "uptime": "unknown",
+            "version": "0.1.0"
+        })
+    }
+
+    fn count_filesystem_nodes(&self) -> usize {
+        self.filesystem.walk("/").count()
+    }
+}
+
+impl<W: WalletBackend> PolicyContext for Coordinator<W> {
+    fn is_banned(&self, sender: &str) -> bool {
+        Coordinator::is_banned(self, sender)
+    }
+
+    fn is_exempt(&self, sender: &str) -> bool {
+        self.is_admin(sender)
+    }
+
+    fn check_rate(&mut self, sender: &str) -> Result<(), String> {
+        self.rate_limiter.check(sender)
+    }
+
+    fn check_quota(&mut self, message: &Message) -> Result<(), String> {
+        let class = match self.commands.resolve(&message.memo_text) {
+            Some((handler, _)) if handler.spec().role <= Role::Member => handler.spec().class,
+            _ => return Ok(()),
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.quotas.check(&message.sender_address, class, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::MockWallet;
+
+    fn sign_in<W: WalletBackend>(
+        coordinator: &mut Coordinator<W>,
+        user: &str,
+        reply_address: &str,
+    ) -> identity::IdentityKey {
+        let key_dir = tempfile::tempdir().unwrap();
+        let key =
+            identity::IdentityKey::load_or_create(key_dir.path().join("identity.key")).unwrap();
+        coordinator
+            .verified_users
+            .insert(user.to_string(), reply_address.to_string());
+        coordinator
+            .user_keys
+            .insert(user.to_string(), key.public_key_hex());
+        let sessions = &mut coordinator.auth_flow.session_manager;
+        let session_id = sessions
+            .create_session(user.to_string(), reply_address.to_string())
+            .session_id
+            .clone();
+        sessions.authenticate(&session_id);
+        key
+    }
+
+    fn answer_challenge(
+        coordinator: &mut Coordinator,
+        user: &str,
+        reply_address: &str,
+        identity: &identity::IdentityKey,
+    ) {
+        let challenge = coordinator.challenges.latest(user).unwrap().to_string();
+        coordinator
+            .process_incoming_message(&Message::new(
+                user.to_string(),
+                "coordinator".to_string(),
+                format!(
+                    "AUTH:{} {}",
+                    challenge,
+                    identity.sign_auth(reply_address, &challenge)
+                ),
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_coordinator_registration() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+
+        let register_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator456".to_string(),
+            "REGISTER:zs1reply789".to_string(),
+        );
+
+        let result = coordinator.process_incoming_message(&register_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("Registration successful!"));
+    }
+
+    #[test]
+    fn test_authentication_requires_matching_challenge() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+
+        let register_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator456".to_string(),
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        );
+        coordinator.process_incoming_message(&register_msg).unwrap();
+
+        let auth = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        let bad_result = auth(
+            &mut coordinator,
+            format!("AUTH:wrong {}", identity.sign_auth("zs1reply789", "wrong")),
+        );
+        assert!(bad_result.is_err());
+
+        let expected = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        assert!(auth(&mut coordinator, format!("AUTH:{}", expected))
+            .unwrap_err()
+            .starts_with("Invalid auth format"));
+        assert!(auth(&mut coordinator, format!("AUTH:{} sig", expected))
+            .unwrap_err()
+            .starts_with("Authentication failed"));
+        let signed = format!(
+            "AUTH:{} {}",
+            expected,
+            identity.sign_auth("zs1reply789", &expected)
+        );
+        assert!(auth(&mut coordinator, signed.clone()).is_err());
+
+        coordinator.process_incoming_message(&register_msg).unwrap();
+        let expected = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let forged = format!(
+            "AUTH:{} {}",
+            expected,
+            identity.sign_auth("zs1attacker", &expected)
+        );
+        assert!(auth(&mut coordinator, forged).is_err());
+
+        let reissued = coordinator.process_incoming_message(&register_msg).unwrap();
+        assert!(reissued.contains("AUTH_CHALLENGE:"));
+        let expected = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let signed = format!(
+            "AUTH:{} {}",
+            expected,
+            identity.sign_auth("zs1reply789", &expected)
+        );
+        let good_result = auth(&mut coordinator, signed.clone());
+        assert!(good_result.is_ok());
+        assert!(good_result.unwrap().contains("Authentication successful"));
+        assert!(auth(&mut coordinator, signed).is_err());
+
+        let mut config = CoordinatorConfig::default();
+        config.challenges.ttl_secs = 1;
+        config.challenges.max_outstanding = 2;
+        coordinator.apply_config(&config);
+        coordinator.process_incoming_message(&register_msg).unwrap();
+        coordinator.process_incoming_message(&register_msg).unwrap();
+        assert!(coordinator
+            .process_incoming_message(&register_msg)
+            .unwrap_err()
+            .starts_with("Rate limit"));
+
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert_eq!(coordinator.prune_challenges(), 2);
+        assert!(coordinator.challenges.is_empty());
+    }
+
+    #[test]
+    fn test_consumed_challenges_cannot_be_replayed_after_restart() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let new_coordinator = || {
+            Coordinator::new(
+                3600,
+                temp_dir.path().to_path_buf(),
+                "http://test:9067".to_string(),
+            )
+        };
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        let mut coordinator = new_coordinator();
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let auth = format!(
+            "AUTH:{} {}",
+            challenge,
+            identity.sign_auth("zs1reply789", &challenge)
+        );
+        assert!(send(&mut coordinator, auth.clone()).is_ok());
+        drop(coordinator);
+
+        let mut restarted = new_coordinator();
+        assert!(restarted.is_user_verified("zs1user123"));
+        assert!(send(&mut restarted, auth)
+            .unwrap_err()
+            .starts_with("Authentication failed"));
+    }
+
+    #[test]
+    fn test_authentication_requires_registered_key() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        assert!(
+            send(&mut coordinator, "REGISTER:zs1reply789 abcd".to_string())
+                .unwrap_err()
+                .starts_with("Invalid registration")
+        );
+        send(&mut coordinator, "REGISTER:zs1reply789".to_string()).unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let signed = format!(
+            "AUTH:{} {}",
+            challenge,
+            identity.sign_auth("zs1reply789", &challenge)
+        );
+        assert!(send(&mut coordinator, signed)
+            .unwrap_err()
+            .contains("No public key registered"));
+
+        let upgraded = send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        assert!(upgraded.starts_with("Already registered!"));
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        assert!(upgraded.contains(&format!("AUTH_CHALLENGE:{}", challenge)));
+        let other =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("other.key")).unwrap();
+        assert!(send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", other.public_key_hex())
+        )
+        .is_err());
+
+        let signed = format!(
+            "AUTH:{} {}",
+            challenge,
+            identity.sign_auth("zs1reply789", &challenge)
+        );
+        assert!(send(&mut coordinator, signed).is_ok());
+    }
+
+    #[test]
+    fn test_commands_from_keyed_users_must_be_signed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        let registered = send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        assert!(send(&mut coordinator, "ls /".to_string())
+            .unwrap_err()
+            .contains("must be signed"));
+        let forged = identity
+            .sign_command("zs1reply789", "ls /docs")
+            .replace("/docs", "/");
+        assert!(send(&mut coordinator, forged)
+            .unwrap_err()
+            .starts_with("Authentication required"));
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1reply789", "ls /")
+        )
+        .unwrap_err()
+        .contains("no authenticated session"));
+
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        send(
+            &mut coordinator,
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                identity.sign_auth("zs1reply789", &challenge)
+            ),
+        )
+        .unwrap();
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1reply789", "ls /")
+        )
+        .is_ok());
+
+        let field = |name: &str| {
+            registered
+                .split(name)
+                .nth(1)
+                .unwrap()
+                .split(' ')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        let routed = format!("{}:{}:ls /", field("ConvID: "), field("PartID: "));
+        let mut conv_message = Message::new(
+            "client_anon".to_string(),
+            "zs1coordinator456".to_string(),
+            routed.clone(),
+        );
+        assert!(coordinator.process_incoming_message(&conv_message).is_err());
+        conv_message.memo_text = identity.sign_command("zs1reply789", &routed);
+        coordinator.process_incoming_message(&conv_message).unwrap();
+    }
+
+    #[test]
+    fn test_stale_sessions_must_renew() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1reply789", "renew")
+        )
+        .unwrap_err()
+        .contains("no authenticated session"));
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let authed = send(
+            &mut coordinator,
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                identity.sign_auth("zs1reply789", &challenge)
+            ),
+        )
+        .unwrap();
+        assert!(authed.contains("expires at"));
+
+        coordinator
+            .auth_flow
+            .session_manager
+            .get_session_mut("zs1user123")
+            .unwrap()
+            .expires_at = 1;
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1reply789", "ls /")
+        )
+        .unwrap_err()
+        .contains("session expired"));
+        assert!(send(&mut coordinator, "renew".to_string()).is_err());
+
+        let renewed = send(
+            &mut coordinator,
+            identity.sign_command("zs1reply789", "renew"),
+        )
+        .unwrap();
+        assert!(renewed.starts_with("Session renewed until"));
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1reply789", "ls /")
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_repeated_auth_failures_lock_the_address_out() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.lockout.max_failures = 2;
+        config.lockout.retry_fee_zatoshis = 500;
+        coordinator.apply_config(&config);
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String, amount: u64| {
+            coordinator.process_incoming_message(
+                &Message::new(
+                    "zs1user123".to_string(),
+                    "zs1coordinator456".to_string(),
+                    memo,
+                )
+                .with_amount(amount),
+            )
+        };
+
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+            0,
+        )
+        .unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let signature = identity.sign_auth("zs1reply789", &challenge);
+
+        assert!(
+            send(&mut coordinator, format!("AUTH:{} {}", challenge, "00"), 0)
+                .unwrap_err()
+                .starts_with("Authentication failed")
+        );
+        assert!(send(
+            &mut coordinator,
+            format!("AUTH:{} {}", challenge, signature),
+            0
+        )
+        .unwrap_err()
+        .starts_with("Payment required: AUTH retry after 1 failures costs 500"));
+        assert!(send(&mut coordinator, "AUTH:guess 00".to_string(), 500)
+            .unwrap_err()
+            .starts_with("Authentication failed"));
+        assert!(send(
+            &mut coordinator,
+            format!("AUTH:{} {}", challenge, signature),
+            5000
+        )
+        .unwrap_err()
+        .starts_with("Rate limit: too many failed AUTH attempts"));
+        assert!(coordinator
+            .handle_admin_stats_command()
+            .unwrap()
+            .contains("Locked out: 1"));
+
+        coordinator.apply_config(&CoordinatorConfig::default());
+        coordinator.lockouts.record_success("zs1user123");
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+            0,
+        )
+        .unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        assert!(send(
+            &mut coordinator,
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                identity.sign_auth("zs1reply789", &challenge)
+            ),
+            0
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_rekey_moves_reply_address_and_key_without_losing_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let old_key =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let new_key =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("next.key")).unwrap();
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1oldreply {}", old_key.public_key_hex()),
+        )
+        .unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        send(
+            &mut coordinator,
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                old_key.sign_auth("zs1oldreply", &challenge)
+            ),
+        )
+        .unwrap();
+        send(
+            &mut coordinator,
+            old_key.sign_command("zs1oldreply", "mkdir /mine"),
+        )
+        .unwrap();
+        send(
+            &mut coordinator,
+            old_key.sign_command("zs1oldreply", "touch /mine/notes.txt mine"),
+        )
+        .unwrap();
+
+        assert!(send(&mut coordinator, "rekey reply zs1newreply".to_string()).is_err());
+        assert!(send(
+            &mut coordinator,
+            old_key.sign_command("zs1oldreply", "rekey reply zs1newreply")
+        )
+        .unwrap()
+        .contains("now replies to zs1newreply"));
+        assert_eq!(
+            coordinator.get_reply_address("zs1user123").unwrap(),
+            "zs1newreply"
+        );
+        assert!(send(
+            &mut coordinator,
+            old_key.sign_command("zs1oldreply", "cat /mine/notes.txt")
+        )
+        .is_err());
+        assert!(send(
+            &mut coordinator,
+            old_key.sign_command("zs1newreply", "cat /mine/notes.txt")
+        )
+        .is_ok());
+
+        assert!(send(
+            &mut coordinator,
+            old_key.sign_command("zs1newreply", "rekey key nothex")
+        )
+        .unwrap_err()
+        .starts_with("Invalid rekey"));
+        send(
+            &mut coordinator,
+            old_key.sign_command(
+                "zs1newreply",
+                &format!("rekey key {}", new_key.public_key_hex()),
+            ),
+        )
+        .unwrap();
+        assert!(send(
+            &mut coordinator,
+            old_key.sign_command("zs1newreply", "cat /mine/notes.txt")
+        )
+        .is_err());
+        assert_eq!(
+            send(
+                &mut coordinator,
+                new_key.sign_command("zs1newreply", "cat /mine/notes.txt")
+            )
+            .unwrap(),
+            "mine"
+        );
+        assert!(send(
+            &mut coordinator,
+            new_key.sign_command("zs1newreply", "rm /mine/notes.txt")
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_sqlite_session_store_survives_restarts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let start = || {
+            Coordinator::new(
+                3600,
+                temp_dir.path().to_path_buf(),
+                "http://test:9067".to_string(),
+            )
+        };
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let mut coordinator = start();
+        let register = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator456".to_string(),
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        );
+        coordinator.process_incoming_message(&register).unwrap();
+
+        let mut config = CoordinatorConfig::default();
+        config.sessions.store = SessionBackend::Sqlite;
+        coordinator.apply_config(&config);
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        coordinator
+            .process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                format!(
+                    "AUTH:{} {}",
+                    challenge,
+                    identity.sign_auth("zs1reply789", &challenge)
+                ),
+            ))
+            .unwrap();
+        drop(coordinator);
+
+        let state = CoordinatorState::load(temp_dir.path().join(STATE_FILE)).unwrap();
+        assert!(state.sessions.is_empty());
+        assert!(state.verified_users.is_empty());
+        let stored = Coordinator::stored_sessions(temp_dir.path(), SessionBackend::Sqlite).unwrap();
+        assert_eq!(stored.sessions.len(), 1);
+        assert!(stored.sessions[0].is_authenticated);
+
+        let mut coordinator = start();
+        assert!(!coordinator.is_user_verified("zs1user123"));
+        coordinator.apply_config(&config);
+        assert!(coordinator.is_user_verified("zs1user123"));
+        assert!(coordinator
+            .process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                identity.sign_command("zs1reply789", "ls /"),
+            ))
+            .is_ok());
+
+        coordinator.apply_config(&CoordinatorConfig::default());
+        let state = CoordinatorState::load(temp_dir.path().join(STATE_FILE)).unwrap();
+        assert_eq!(state.sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_sensitive_commands_wait_for_a_confirmation_code() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.confirmations.enabled = true;
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1owner".to_string(), "zs1ownerreply".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1owner".to_string());
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                "zs1owner".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ))
+        };
+        let latest_code = |coordinator: &Coordinator| {
+            let queue = coordinator.lock_outbound();
+            let item = queue.items().last().unwrap();
+            assert_eq!(item.address, "zs1ownerreply");
+            item.memo
+                .strip_prefix("CONFIRM_CODE:")
+                .unwrap()
+                .split(' ')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        run(&mut coordinator, "mkdir /docs").unwrap();
+        assert!(run(&mut coordinator, "rm /docs")
+            .unwrap()
+            .starts_with("Confirmation required"));
+        assert!(coordinator.filesystem.resolve_path("/docs").is_some());
+        let code = latest_code(&coordinator);
+        assert!(coordinator
+            .lock_outbound()
+            .items()
+            .last()
+            .unwrap()
+            .memo
+            .contains("for: rm /docs"));
+
+        assert!(run(&mut coordinator, "confirm 1234567")
+            .unwrap_err()
+            .starts_with("Invalid confirmation"));
+        assert!(run(&mut coordinator, &format!("confirm {}", code)).is_err());
+        assert!(coordinator.filesystem.resolve_path("/docs").is_some());
+
+        run(&mut coordinator, "rm /docs").unwrap();
+        let code = latest_code(&coordinator);
+        run(&mut coordinator, &format!("confirm {}", code)).unwrap();
+        assert!(coordinator.filesystem.resolve_path("/docs").is_none());
+        assert!(run(&mut coordinator, &format!("confirm {}", code)).is_err());
+    }
+
+    #[test]
+    fn test_owners_can_delegate_temporary_access() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        for (user, reply) in [
+            ("zs1owner", "zs1ownerreply"),
+            ("zs1friend", "zs1friendreply"),
+        ] {
+            coordinator
+                .verified_users
+                .insert(user.to_string(), reply.to_string());
+        }
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1owner".to_string());
+        let run = |coordinator: &mut Coordinator, user: &str, command: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                user.to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ))
+        };
+
+        run(&mut coordinator, "zs1owner", "mkdir /projects").unwrap();
+        run(&mut coordinator, "zs1owner", "mkdir /projects/x").unwrap();
+        assert!(
+            run(&mut coordinator, "zs1friend", "touch /projects/x/a.txt hi")
+                .unwrap_err()
+                .starts_with("Permission denied")
+        );
+        assert!(run(
+            &mut coordinator,
+            "zs1friend",
+            "delegate write zs1owner /projects/x 24h"
+        )
+        .unwrap_err()
+        .starts_with("Permission denied"));
+        assert!(run(
+            &mut coordinator,
+            "zs1owner",
+            "delegate write zs1stranger /projects/x 24h"
+        )
+        .unwrap_err()
+        .starts_with("Unknown user"));
+
+        let issued = run(
+            &mut coordinator,
+            "zs1owner",
+            "delegate write zs1friend /projects/x 24h",
+        )
+        .unwrap();
+        assert!(issued.contains("write under /projects/x for zs1friend"));
+        let id = issued.split_whitespace().nth(1).unwrap().to_string();
+
+        run(&mut coordinator, "zs1friend", "touch /projects/x/a.txt hi").unwrap();
+        assert_eq!(
+            run(&mut coordinator, "zs1friend", "cat /projects/x/a.txt").unwrap(),
+            "hi"
+        );
+        let created = coordinator.filesystem.resolve_path("/projects/x/a.txt");
+        assert_eq!(created.unwrap().permissions.owner, "zs1friend");
+        assert!(coordinator.filesystem.delegation.is_none());
+        let audited = coordinator
+            .handle_admin_authlog_command("command_delegated")
+            .unwrap();
+        assert!(audited.contains("zs1friend"));
+        assert!(audited.contains("issuer=zs1owner path=/projects/x ok=true command=cat"));
+        assert!(run(&mut coordinator, "zs1friend", "delegations")
+            .unwrap()
+            .starts_with(&id));
+        assert!(run(&mut coordinator, "zs1friend", "mkdir /elsewhere").is_err());
+        assert!(run(&mut coordinator, "zs1friend", "rm /projects").is_err());
+
+        run(
+            &mut coordinator,
+            "zs1owner",
+            &format!("token revoke {}", id),
+        )
+        .unwrap();
+        assert!(
+            run(&mut coordinator, "zs1friend", "touch /projects/x/b.txt hi")
+                .unwrap_err()
+                .starts_with("Permission denied")
+        );
+    }
+
+    #[test]
+    fn test_users_can_hold_a_session_per_device() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            let message = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            );
+            let result = coordinator.process_incoming_message(&message);
+            let _ = coordinator.respond(&message, None, result.clone(), false);
+            result
+        };
+        let login = |coordinator: &mut Coordinator, reply: &str| {
+            send(
+                coordinator,
+                format!("REGISTER:{} {}", reply, identity.public_key_hex()),
+            )
+            .unwrap();
+            let challenge = coordinator
+                .challenges
+                .latest("zs1user123")
+                .unwrap()
+                .to_string();
+            let authed = send(
+                coordinator,
+                format!(
+                    "AUTH:{} {}",
+                    challenge,
+                    identity.sign_auth(reply, &challenge)
+                ),
+            )
+            .unwrap();
+            authed
+                .split("Session ID: ")
+                .nth(1)
+                .unwrap()
+                .split(' ')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        let phone = login(&mut coordinator, "zs1phone");
+        let laptop = login(&mut coordinator, "zs1laptop");
+        assert_ne!(phone, laptop);
+        assert_eq!(
+            coordinator.get_reply_address("zs1user123").unwrap(),
+            "zs1phone"
+        );
+
+        let queued = coordinator.lock_outbound().items().count();
+        send(&mut coordinator, identity.sign_command("zs1laptop", "ls /")).unwrap();
+        let replies: Vec<String> = coordinator
+            .lock_outbound()
+            .items()
+            .skip(queued)
+            .map(|item| item.address.clone())
+            .collect();
+        assert_eq!(replies, vec!["zs1laptop"]);
+
+        let listing = coordinator
+            .handle_authenticated_command(&Message::new(
+                "zs1admin".to_string(),
+                "zs1coordinator456".to_string(),
+                "admin sessions".to_string(),
+            ))
+            .unwrap();
+        assert!(listing.contains(&format!("session={}", phone)));
+        assert!(listing.contains(&format!("session={}", laptop)));
+        let details = coordinator.session_details(Some("zs1user123"));
+        assert_eq!(details.len(), 2);
+        let laptop_details = details
+            .iter()
+            .find(|session| session.session_id == laptop)
+            .unwrap();
+        assert_eq!(laptop_details.commands_issued, 1);
+        assert!(coordinator.session_details(Some("zs1nobody")).is_empty());
+
+        coordinator
+            .auth_flow
+            .session_manager
+            .session_mut(&phone)
+            .unwrap()
+            .expires_at = 1;
+        assert!(
+            send(&mut coordinator, identity.sign_command("zs1phone", "ls /"))
+                .unwrap_err()
+                .contains("session expired")
+        );
+        let phone_line = coordinator
+            .handle_authenticated_command(&Message::new(
+                "zs1admin".to_string(),
+                "zs1coordinator456".to_string(),
+                "admin sessions zs1user123".to_string(),
+            ))
+            .unwrap()
+            .lines()
+            .find(|line| line.contains(&phone))
+            .unwrap()
+            .to_string();
+        assert!(phone_line.contains("state=expired"));
+        assert!(phone_line.contains("commands=1"));
+        assert!(send(&mut coordinator, identity.sign_command("zs1laptop", "ls /")).is_ok());
+
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1phone", "logout")
+        )
+        .unwrap()
+        .starts_with(&format!("Logged out of session {}", phone)));
+        assert!(coordinator.is_user_verified("zs1user123"));
+        assert_eq!(
+            coordinator.get_reply_address("zs1user123").unwrap(),
+            "zs1laptop"
+        );
+        assert!(send(&mut coordinator, identity.sign_command("zs1phone", "ls /")).is_err());
+
+        assert!(send(
+            &mut coordinator,
+            identity.sign_command("zs1laptop", "logout")
+        )
+        .unwrap()
+        .starts_with("Logged out. Send REGISTER"));
+        assert!(!coordinator.is_user_verified("zs1user123"));
+        assert!(coordinator.get_all_sessions().is_empty());
+    }
+
+    #[test]
+    fn test_logout_and_admin_revoke_end_access() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        let keys = [
+            (
+                "zs1alice",
+                sign_in(&mut coordinator, "zs1alice", "zs1alicereply"),
+            ),
+            ("zs1bob", sign_in(&mut coordinator, "zs1bob", "zs1bobreply")),
+            (
+                "zs1admin",
+                sign_in(&mut coordinator, "zs1admin", "zs1adminreply"),
+            ),
+        ];
+        let send = |coordinator: &mut Coordinator, user: &str, memo: &str| {
+            let (_, key) = keys.iter().find(|(name, _)| *name == user).unwrap();
+            let reply_address = format!("{}reply", user);
+            coordinator.process_incoming_message(&Message::new(
+                user.to_string(),
+                "zs1coordinator456".to_string(),
+                key.sign_command(&reply_address, memo),
+            ))
+        };
+
+        coordinator
+            .session_mappings
+            .insert("abcdef0123456789".to_string(), "zs1bobreply".to_string());
+        assert!(send(&mut coordinator, "zs1alice", "ls /").is_ok());
+
+        assert!(send(&mut coordinator, "zs1alice", "logout")
+            .unwrap()
+            .starts_with("Logged out"));
+        assert!(!coordinator.is_user_verified("zs1alice"));
+        assert!(coordinator
+            .auth_flow
+            .session_manager
+            .get_session("zs1alice")
+            .is_none());
+        assert!(send(&mut coordinator, "zs1alice", "ls /")
+            .unwrap_err()
+            .starts_with("Authentication required"));
+
+        assert!(send(&mut coordinator, "zs1bob", "admin revoke abcdef0123456789").is_err());
+        assert_eq!(
+            send(
+                &mut coordinator,
+                "zs1admin",
+                "admin revoke abcdef0123456789"
+            )
+            .unwrap(),
+            "Revoked: zs1bob"
+        );
+        assert!(coordinator.get_all_sessions().is_empty());
+        assert!(!coordinator.is_user_verified("zs1bob"));
+        assert!(send(&mut coordinator, "zs1admin", "admin revoke zs1bob")
+            .unwrap_err()
+            .starts_with("No such session"));
+    }
+
+    #[test]
+    fn test_cleanup_expired_sessions_removes_mappings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            0,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+
+        let register_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator456".to_string(),
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        );
+        coordinator.process_incoming_message(&register_msg).unwrap();
+
+        let expected = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        let auth_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator456".to_string(),
+            format!(
+                "AUTH:{} {}",
+                expected,
+                identity.sign_auth("zs1reply789", &expected)
+            ),
+        );
+        coordinator.process_incoming_message(&auth_msg).unwrap();
+
+        assert!(!coordinator.get_all_sessions().is_empty());
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        coordinator.cleanup_expired_sessions();
+        assert!(coordinator.get_all_sessions().is_empty());
+        assert!(!coordinator.is_user_verified("zs1user123"));
+    }
+
+    #[test]
+    fn test_registration_waits_for_proof_of_the_reply_address() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.ownership.require_proof = true;
+        coordinator.apply_config(&config);
+        let send = |coordinator: &mut Coordinator, memo: &str| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo.to_string(),
+            ))
+        };
+        let probe_code = |coordinator: &Coordinator| {
+            let memo = coordinator
+                .lock_outbound()
+                .items()
+                .filter(|item| item.address == "zs1reply789")
+                .last()
+                .map(|item| item.memo.clone())
+                .unwrap();
+            memo.split(ownership::PROBE_PREFIX)
+                .nth(1)
+                .and_then(|rest| rest.split_whitespace().next())
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(send(&mut coordinator, "REGISTER:zs1reply789")
+            .unwrap()
+            .starts_with("Registration pending"));
+        assert!(!coordinator.is_user_verified("zs1user123"));
+        let code = probe_code(&coordinator);
+
+        assert!(send(&mut coordinator, "PROVE:0000000000000000")
+            .unwrap_err()
+            .starts_with("Invalid proof"));
+        assert!(send(&mut coordinator, &format!("PROVE:{}", code)).is_err());
+        assert!(!coordinator.is_user_verified("zs1user123"));
+
+        send(&mut coordinator, "REGISTER:zs1reply789").unwrap();
+        let code = probe_code(&coordinator);
+        assert!(send(&mut coordinator, &format!("PROVE:{}", code))
+            .unwrap()
+            .starts_with("Registration successful"));
+        assert_eq!(
+            coordinator.get_reply_address("zs1user123").as_deref(),
+            Some("zs1reply789")
+        );
+    }
+
+    #[test]
+    fn test_secret_backed_challenges_survive_lost_pending_state() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.challenges.secret = Some("operator-secret".to_string());
+        coordinator.apply_config(&config);
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        let registered = send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        assert!(coordinator.challenges.is_empty());
+        let challenge = registered
+            .split("AUTH_CHALLENGE:")
+            .nth(1)
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let auth = format!(
+            "AUTH:{} {}",
+            challenge,
+            identity.sign_auth("zs1reply789", &challenge)
+        );
+        assert!(send(&mut coordinator, auth.clone())
+            .unwrap()
+            .starts_with("Authentication successful"));
+        assert!(send(&mut coordinator, auth.clone())
+            .unwrap_err()
+            .contains("Invalid signature or challenge"));
+        for _ in 0..config.challenges.max_outstanding {
+            send(
+                &mut coordinator,
+                format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+            )
+            .unwrap();
+        }
+        drop(coordinator);
+
+        let mut restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        restarted.apply_config(&config);
+        assert!(send(&mut restarted, auth)
+            .unwrap_err()
+            .contains("Invalid signature or challenge"));
+        assert!(send(
+            &mut restarted,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap_err()
+        .starts_with("Rate limit"));
+    }
+
+    #[test]
+    fn test_auth_log_records_the_login_lifecycle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            0,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator456".to_string(),
+                memo,
+            ))
+        };
+
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        let expected = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        assert!(send(
+            &mut coordinator,
+            format!("AUTH:wrong {}", identity.sign_auth("zs1reply789", "wrong"))
+        )
+        .is_err());
+        send(
+            &mut coordinator,
+            format!(
+                "AUTH:{} {}",
+                expected,
+                identity.sign_auth("zs1reply789", &expected)
+            ),
+        )
+        .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        coordinator.cleanup_expired_sessions();
+
+        let kinds: Vec<String> = coordinator
+            .auth_log
+            .all()
+            .into_iter()
+            .map(|entry| entry.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "user_registered",
+                "challenge_issued",
+                "auth_failed",
+                "auth_succeeded",
+                "session_expired"
+            ]
+        );
+        assert!(coordinator.audit_log.all().len() >= 3);
+
+        let output = coordinator
+            .handle_admin_authlog_command("auth_failed")
+            .unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.contains("#3 "));
+        assert!(output.contains("auth_failed zs1user123"));
+        let output = coordinator
+            .handle_admin_authlog_command("zs1user123 2")
+            .unwrap();
+        assert!(output.starts_with("#4 "));
+        assert!(output.contains("session_expired"));
+        assert_eq!(
+            coordinator
+                .handle_admin_authlog_command("zs1nobody")
+                .unwrap(),
+            "Auth log is empty"
+        );
+        assert!(coordinator.handle_admin_authlog_command("0").is_err());
+        assert!(coordinator.handle_admin_authlog_command("a b").is_err());
+    }
+
+    #[test]
+    fn test_ls_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+
+        coordinator
+            .filesystem
+            .create_directory("/home", "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_file(
+                "/home/readme.txt",
+                "Hello!".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        let ls_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /home".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&ls_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("readme.txt"));
+    }
+
+    #[test]
+    fn test_mkdir_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let mkdir_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "mkdir /test_dir".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&mkdir_msg);
+
+        if let Err(e) = &result {
+            eprintln!("mkdir command failed with error: {}", e);
+        }
+
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("Directory created"));
+
+        let dir = coordinator.filesystem.resolve_path("/test_dir").unwrap();
+        assert_eq!(dir.file_type, crate::filesystem::FileType::Directory);
+    }
+
+    #[test]
+    fn test_rm_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        coordinator
+            .filesystem
+            .create_file("/test.txt", "content".to_string(), "zs1user123".to_string())
+            .unwrap();
+
+        let rm_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "rm /test.txt".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&rm_msg);
+        assert!(result.is_ok());
+        assert!(coordinator.filesystem.resolve_path("/test.txt").is_none());
+    }
+
+    #[test]
+    fn test_touch_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let touch_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "touch /newfile.txt Hello World!".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&touch_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("File created"));
+
+        let file = coordinator.filesystem.resolve_path("/newfile.txt").unwrap();
+        assert_eq!(file.content, Some("Hello World!".to_string()));
+    }
+
+    #[test]
+    fn test_event_hooks_see_lifecycle_events() {
+        struct Recorder(Mutex<Vec<&'static str>>);
+
+        impl EventHook for Recorder {
+            fn name(&self) -> &str {
+                "recorder"
+            }
+
+            fn on_event(&self, event: &Event) {
+                self.0.lock().unwrap().push(event.name());
+            }
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        coordinator.subscribe(recorder.clone());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            let mut message =
+                Message::new("zs1user123".to_string(), "zs1coordinator".to_string(), memo);
+            message.signature = Some("sig".to_string());
+            coordinator.process_incoming_message(&message)
+        };
+
+        send(
+            &mut coordinator,
+            format!("REGISTER:zs1reply456 {}", identity.public_key_hex()),
+        )
+        .unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        send(&mut coordinator, "AUTH:wrong".to_string()).unwrap_err();
+        send(
+            &mut coordinator,
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                identity.sign_auth("zs1reply456", &challenge)
+            ),
+        )
+        .unwrap();
+        send(
+            &mut coordinator,
+            identity.sign_command("zs1reply456", "touch /hello.txt hi"),
+        )
+        .unwrap();
+        send(
+            &mut coordinator,
+            identity.sign_command("zs1reply456", "admin gc"),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "user_registered",
+                "challenge_issued",
+                "message_processed",
+                "auth_failed",
+                "message_processed",
+                "auth_succeeded",
+                "message_processed",
+                "file_created",
+                "command_completed",
+                "message_processed",
+                "command_rejected",
+                "message_processed",
+            ]
+        );
+        assert_eq!(coordinator.metrics().auth_failures, 1);
+        assert_eq!(coordinator.metrics().messages_failed, 2);
+        assert_eq!(coordinator.audit_log.all().len(), 5);
+    }
+
+    #[test]
+    fn test_guest_mode_allows_public_reads_only() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .filesystem
+            .create_file(
+                "/rules.txt",
+                "be nice".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_file(
+                "/secret.txt",
+                "hidden".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+        coordinator
+            .filesystem
+            .resolve_path_mut("/secret.txt")
+            .unwrap()
+            .permissions
+            .public_read = false;
+
+        let guest = |coordinator: &mut Coordinator, memo: &str| {
+            let message = Message::new(
+                "client_abcdef12".to_string(),
+                "zs1coordinator".to_string(),
+                memo.to_string(),
+            );
+            let result = coordinator.process_incoming_message(&message);
+            let _ = coordinator.respond(&message, None, result.clone(), false);
+            result
+        };
+
+        assert!(guest(&mut coordinator, "GUEST:zs1visitor cat /rules.txt")
+            .unwrap_err()
+            .contains("guest access is disabled"));
+        assert!(coordinator.lock_outbound().is_empty());
+
+        let config = CoordinatorConfig {
+            guests: GuestConfig {
+                enabled: true,
+                ..GuestConfig::default()
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+
+        assert_eq!(
+            guest(&mut coordinator, "GUEST:zs1visitor cat /rules.txt").unwrap(),
+            "be nice"
+        );
+        assert!(guest(&mut coordinator, "GUEST:zs1visitor cat /secret.txt").is_err());
+        assert!(
+            guest(&mut coordinator, "GUEST:zs1visitor touch /spam.txt hi")
+                .unwrap_err()
+                .starts_with("Permission denied: guests may only run")
+        );
+        assert!(guest(&mut coordinator, "GUEST:zs1visitor").is_err());
+        assert!(coordinator.filesystem.resolve_path("/spam.txt").is_none());
+
+        let replies: Vec<(String, String)> = coordinator
+            .lock_outbound()
+            .items()
+            .map(|item| (item.address.clone(), item.memo.clone()))
+            .collect();
+        assert_eq!(replies.len(), 3);
+        assert!(replies.iter().all(|(address, _)| address == "zs1visitor"));
+        assert!(replies[0]
+            .1
+            .starts_with("ZB1 200 . cat /rules.txt\nbe nice"));
+    }
+
+    #[test]
+    fn test_capability_tokens_grant_scoped_access_without_a_session() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1owner".to_string(), "zs1ownerreply".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1owner".to_string());
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                "zs1owner".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ))
+        };
+        run(&mut coordinator, "mkdir /board").unwrap();
+        run(&mut coordinator, "mkdir /board/general").unwrap();
+        run(&mut coordinator, "touch /board/rules.txt be nice").unwrap();
+        assert!(run(&mut coordinator, "token issue write / 3600")
+            .unwrap_err()
+            .starts_with("Permission denied"));
+        assert!(run(&mut coordinator, "token issue write /board/general 0").is_err());
+
+        let issued = run(&mut coordinator, "token issue write /board/general/ 3600").unwrap();
+        let token = issued.split_whitespace().nth(1).unwrap().to_string();
+        assert!(issued.contains("grants write under /board/general until"));
+        assert!(run(&mut coordinator, "token list")
+            .unwrap()
+            .starts_with(&token));
+
+        let present = |coordinator: &mut Coordinator, memo: String| {
+            let message = Message::new(
+                "client_abcdef12".to_string(),
+                "zs1coordinator".to_string(),
+                memo,
+            );
+            let result = coordinator.process_incoming_message(&message);
+            let _ = coordinator.respond(&message, None, result.clone(), false);
+            result
+        };
+
+        present(
+            &mut coordinator,
+            format!("CAP:{} zs1guest touch /board/general/hi.txt hello", token),
+        )
+        .unwrap();
+        assert_eq!(
+            coordinator
+                .filesystem
+                .resolve_path("/board/general/hi.txt")
+                .unwrap()
+                .permissions
+                .owner,
+            "zs1owner"
+        );
+        assert!(present(
+            &mut coordinator,
+            format!("CAP:{} zs1guest touch /board/rules.txt spam", token)
+        )
+        .unwrap_err()
+        .starts_with("Permission denied: token only covers /board/general"));
+        assert!(present(
+            &mut coordinator,
+            format!("CAP:{} zs1guest cp /board/general/hi.txt /x.txt", token)
+        )
+        .is_err());
+        assert!(present(
+            &mut coordinator,
+            format!("CAP:{} zs1guest grant write zs1guest /board/general", token)
+        )
+        .unwrap_err()
+        .contains("cannot run grant"));
+        assert!(present(
+            &mut coordinator,
+            "CAP:0000 zs1guest cat /board/general/hi.txt".to_string()
+        )
+        .unwrap_err()
+        .starts_with("Authentication failed"));
+
+        let replies: Vec<String> = coordinator
+            .lock_outbound()
+            .items()
+            .map(|item| item.address.clone())
+            .collect();
+        assert_eq!(replies.len(), 4);
+        assert!(replies.iter().all(|address| address == "zs1guest"));
+
+        run(&mut coordinator, &format!("token revoke {}", token)).unwrap();
+        assert!(present(
+            &mut coordinator,
+            format!("CAP:{} zs1guest cat /board/general/hi.txt", token)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_cat_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        coordinator
+            .filesystem
+            .create_file(
+                "/readme.txt",
+                "Hello from ZatBoard!".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+
+        let cat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "cat /readme.txt".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&cat_msg);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "Hello from ZatBoard!");
+    }
+
+    #[test]
+    fn test_echo_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let echo_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "echo \"Hello ZatBoard!\" > /greeting.txt".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&echo_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("File created"));
+
+        let file = coordinator
+            .filesystem
+            .resolve_path("/greeting.txt")
+            .unwrap();
+        assert_eq!(file.content, Some("Hello ZatBoard!".to_string()));
+    }
+
+    #[test]
+    fn test_echo_update_existing_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        coordinator
+            .filesystem
+            .create_file(
+                "/update.txt",
+                "old content".to_string(),
+                "zs1user123".to_string(),
+            )
+            .unwrap();
+
+        let echo_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "echo \"new content\" > /update.txt".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&echo_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("File updated"));
+
+        let file = coordinator.filesystem.resolve_path("/update.txt").unwrap();
+        assert_eq!(file.content, Some("new content".to_string()));
+    }
+
+    #[test]
+    fn test_chmod_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        coordinator
+            .filesystem
+            .create_file("/test.txt", "content".to_string(), "zs1user123".to_string())
+            .unwrap();
+
+        let chmod_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "chmod private /test.txt".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&chmod_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("Permissions updated"));
+
+        let file = coordinator.filesystem.resolve_path("/test.txt").unwrap();
+        assert!(!file.permissions.public_read);
+    }
+
+    #[test]
+    fn test_grant_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        coordinator
+            .filesystem
+            .create_file(
+                "/shared.txt",
+                "content".to_string(),
+                "zs1user123".to_string(),
+            )
+            .unwrap();
+
+        let grant_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "grant read zs1other456 /shared.txt".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&grant_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("Read permission granted"));
+
+        let file = coordinator.filesystem.resolve_path("/shared.txt").unwrap();
+        assert!(file.permissions.can_read("zs1other456"));
+    }
+
+    #[test]
+    fn test_chat_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        coordinator
+            .filesystem
+            .create_directory("/lobby", "coordinator".to_string())
+            .unwrap();
+
+        let chat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "chat /lobby \"Hello everyone in the lobby!\"".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&chat_msg);
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("Message sent to chatroom"));
+
+        let chat_log = coordinator
+            .filesystem
+            .resolve_path("/lobby/.chat_log")
+            .unwrap();
+        assert!(chat_log
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("Hello everyone in the lobby!"));
+    }
+
+    #[test]
+    fn test_chat_history_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .verified_users
+            .insert("zs1user789".to_string(), "zs1reply000".to_string());
+
+        coordinator
+            .filesystem
+            .create_directory("/general", "coordinator".to_string())
+            .unwrap();
+
+        let chat1 = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "chat /general \"First message\"".to_string(),
+        );
+        let chat2 = Message::new(
+            "zs1user789".to_string(),
+            "zs1coordinator".to_string(),
+            "chat /general \"Second message\"".to_string(),
+        );
+
+        coordinator.handle_authenticated_command(&chat1).unwrap();
+        coordinator.handle_authenticated_command(&chat2).unwrap();
+
+        let history_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "history /general".to_string(),
+        );
+        let result = coordinator.handle_authenticated_command(&history_msg);
+
+        assert!(result.is_ok());
+        let history = result.unwrap();
+        assert!(history.contains("First message"));
+        assert!(history.contains("Second message"));
+        assert!(history.contains("ser123"));
+        assert!(history.contains("ser789"));
+    }
+
+    #[test]
+    fn test_chat_permissions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        coordinator
+            .filesystem
+            .create_directory("/private", "coordinator".to_string())
+            .unwrap();
+        let private_dir = coordinator.filesystem.resolve_path_mut("/private").unwrap();
+        private_dir.permissions.public_read = false;
+
+        let chat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "chat /private \"Secret message\"".to_string(),
+        );
+
+        let result = coordinator.handle_authenticated_command(&chat_msg);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Permission denied"));
+    }
+
+    #[test]
+    fn test_diff_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        coordinator
+            .filesystem
+            .create_file("/todo.txt", "milk".to_string(), "zs1user123".to_string())
+            .unwrap();
+
+        let echo_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "echo \"milk\neggs\" > /todo.txt".to_string(),
+        );
+        coordinator.handle_authenticated_command(&echo_msg).unwrap();
+
+        let diff_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "diff /todo.txt 1 2".to_string(),
+        );
+        let result = coordinator.handle_authenticated_command(&diff_msg);
+        assert_eq!(result.unwrap(), "+eggs");
+
+        let bad_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "diff /todo.txt one two".to_string(),
+        );
+        assert!(coordinator.handle_authenticated_command(&bad_msg).is_err());
+    }
+
+    #[test]
+    fn test_admin_export_and_import() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        coordinator
+            .filesystem
+            .create_directory("/board", "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_file(
+                "/board/post.txt",
+                "hello".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_directory("/mirror", "coordinator".to_string())
+            .unwrap();
+
+        let denied = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "admin export /board".to_string(),
+        );
+        let result = coordinator.handle_authenticated_command(&denied);
+        assert!(result.unwrap_err().contains("admin only"));
+
+        let export_msg = Message::new(
+            "zs1admin".to_string(),
+            "zs1coordinator".to_string(),
+            "admin export /board".to_string(),
+        );
+        let response = coordinator
+            .handle_authenticated_command(&export_msg)
+            .unwrap();
+        let archive_name = response
+            .split(" to ")
+            .nth(1)
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let import_msg = Message::new(
+            "zs1admin".to_string(),
+            "zs1coordinator".to_string(),
+            format!("admin import {} /mirror", archive_name),
+        );
+        let response = coordinator
+            .handle_authenticated_command(&import_msg)
+            .unwrap();
+        assert!(response.contains("Imported 1 entries"));
+
+        let file = coordinator
+            .filesystem
+            .resolve_path("/mirror/post.txt")
+            .unwrap();
+        assert_eq!(file.content, Some("hello".to_string()));
+    }
+
+    #[test]
+    fn test_ls_pagination() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .create_directory("/board", "coordinator".to_string())
+            .unwrap();
+        for i in 0..5 {
+            coordinator
+                .filesystem
+                .create_file(
+                    &format!("/board/post{}.txt", i),
+                    String::new(),
+                    "coordinator".to_string(),
+                )
+                .unwrap();
+        }
+
+        let first_page = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /board 0 2".to_string(),
+        );
+        let response = coordinator
+            .handle_authenticated_command(&first_page)
+            .unwrap();
+        assert_eq!(response, "post0.txt  post1.txt\n(more: ls /board 2 2)");
+
+        let last_page = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /board 4 2".to_string(),
+        );
+        let response = coordinator
+            .handle_authenticated_command(&last_page)
+            .unwrap();
+        assert_eq!(response, "post4.txt");
+
+        let invalid = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /board x".to_string(),
+        );
+        assert!(coordinator.handle_authenticated_command(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_stat_and_ls_hash() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .create_file("/hello.txt", "hello".to_string(), "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_directory("/docs", "coordinator".to_string())
+            .unwrap();
+
+        let stat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "stat /hello.txt".to_string(),
+        );
+        let stat = coordinator.handle_authenticated_command(&stat_msg).unwrap();
+        assert!(stat.contains("Size: 5"));
+        assert!(stat
+            .contains("SHA256: 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"));
+
+        let ls_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls --hash /".to_string(),
+        );
+        let listing = coordinator.handle_authenticated_command(&ls_msg).unwrap();
+        assert_eq!(listing, "docs/  hello.txt:2cf24dba5fb0a30e");
+    }
+
+    #[test]
+    fn test_admin_freeze_rejects_mutations() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        coordinator
+            .filesystem
+            .create_file("/notes.txt", "a".to_string(), "zs1user123".to_string())
+            .unwrap();
+
+        let freeze = Message::new(
+            "zs1admin".to_string(),
+            "zs1coordinator".to_string(),
+            "admin freeze".to_string(),
+        );
+        coordinator.handle_authenticated_command(&freeze).unwrap();
+
+        let echo_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "echo \"b\" > /notes.txt".to_string(),
+        );
+        let result = coordinator.handle_authenticated_command(&echo_msg);
+        assert!(result.unwrap_err().contains("frozen"));
+
+        let cat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "cat /notes.txt".to_string(),
+        );
+        assert_eq!(
+            coordinator.handle_authenticated_command(&cat_msg).unwrap(),
+            "a"
+        );
+
+        let unfreeze = Message::new(
+            "zs1admin".to_string(),
+            "zs1coordinator".to_string(),
+            "admin unfreeze".to_string(),
+        );
+        coordinator.handle_authenticated_command(&unfreeze).unwrap();
+        assert!(coordinator.handle_authenticated_command(&echo_msg).is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_command_and_authorized_cat() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.storage.encryption_secret = Some("operator-secret".to_string());
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .verified_users
+            .insert("zs1other789".to_string(), "zs1reply000".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        coordinator
+            .filesystem
+            .create_file(
+                "/secret.txt",
+                "launch codes".to_string(),
+                "zs1user123".to_string(),
+            )
+            .unwrap();
+
+        let encrypt_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "encrypt /secret.txt".to_string(),
+        );
+        coordinator
+            .handle_authenticated_command(&encrypt_msg)
+            .unwrap();
+
+        let stored = coordinator.filesystem.resolve_path("/secret.txt").unwrap();
+        assert!(stored.encrypted);
+        assert!(!stored.content.as_ref().unwrap().contains("launch"));
+
+        let cat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "cat /secret.txt".to_string(),
+        );
+        assert_eq!(
+            coordinator.handle_authenticated_command(&cat_msg).unwrap(),
+            "launch codes"
+        );
+
+        coordinator
+            .filesystem
+            .resolve_path_mut("/secret.txt")
+            .unwrap()
+            .permissions
+            .public_read = false;
+        let other_cat = Message::new(
+            "zs1other789".to_string(),
+            "zs1coordinator".to_string(),
+            "cat /secret.txt".to_string(),
+        );
+        assert!(coordinator
+            .handle_authenticated_command(&other_cat)
+            .is_err());
+
+        coordinator
+            .filesystem
+            .resolve_path_mut("/secret.txt")
+            .unwrap()
+            .permissions
+            .public_read = true;
+        for command in [
+            "cat /secret.txt",
+            "head /secret.txt 1",
+            "diff /secret.txt 1 1",
+        ] {
+            let reply = coordinator.handle_authenticated_command(&Message::new(
+                "zs1other789".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ));
+            assert!(reply.unwrap_err().starts_with("Permission denied"));
+        }
+
+        coordinator
+            .filesystem
+            .resolve_path_mut("/secret.txt")
+            .unwrap()
+            .permissions
+            .add_read_permission("zs1other789".to_string());
+        assert_eq!(
+            coordinator
+                .handle_authenticated_command(&other_cat)
+                .unwrap(),
+            "launch codes"
+        );
+    }
+
+    #[test]
+    fn test_ls_sort_and_filter_flags() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .create_file("/big.txt", "xxxxxx".to_string(), "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_file("/small.txt", "x".to_string(), "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_directory("/dir", "coordinator".to_string())
+            .unwrap();
+
+        let ls_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls --files --sort=size / 0 1".to_string(),
+        );
+        let response = coordinator.handle_authenticated_command(&ls_msg).unwrap();
+        assert_eq!(response, "small.txt\n(more: ls --sort=size --files / 1 1)");
+
+        let bad_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "ls --sort=color /".to_string(),
+        );
+        assert!(coordinator.handle_authenticated_command(&bad_msg).is_err());
+    }
+
+    #[test]
+    fn test_ln_command_shares_updates() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator.filesystem.root.permissions.public_write = true;
+
+        for command in [
+            "touch /rules.txt be nice",
+            "mkdir /pinned",
+            "ln /rules.txt /pinned/rules.txt",
+            "echo \"be kind\" > /pinned/rules.txt",
+        ] {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg).unwrap();
+        }
+
+        let cat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "cat /rules.txt".to_string(),
+        );
+        assert_eq!(
+            coordinator.handle_authenticated_command(&cat_msg).unwrap(),
+            "be kind"
+        );
+
+        let stat_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "stat /pinned/rules.txt".to_string(),
+        );
+        let stat = coordinator.handle_authenticated_command(&stat_msg).unwrap();
+        assert!(stat.contains("Links: 2"));
+    }
+
+    #[test]
+    fn test_du_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .create_directory("/board", "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .create_file(
+                "/board/a.txt",
+                "hello".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+
+        let du_msg = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            "du /".to_string(),
+        );
+        let response = coordinator.handle_authenticated_command(&du_msg).unwrap();
+        assert_eq!(response, "5\t/board\n5\t/");
+    }
+
+    #[test]
+    fn test_watch_queues_notifications() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1watcher123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .verified_users
+            .insert("zs1writer789".to_string(), "zs1reply789".to_string());
+        coordinator
+            .filesystem
+            .create_directory("/board", "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .resolve_path_mut("/board")
+            .unwrap()
+            .permissions
+            .public_write = true;
+
+        let watch_msg = Message::new(
+            "zs1watcher123".to_string(),
+            "zs1coordinator".to_string(),
+            "watch /board".to_string(),
+        );
+        assert_eq!(
+            coordinator
+                .handle_authenticated_command(&watch_msg)
+                .unwrap(),
+            "Watching /board"
+        );
+
+        for command in [
+            "touch /board/post.txt hi",
+            "echo \"edit\" > /board/post.txt",
+        ] {
+            let msg = Message::new(
+                "zs1writer789".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg).unwrap();
+        }
+
+        let own_msg = Message::new(
+            "zs1watcher123".to_string(),
+            "zs1coordinator".to_string(),
+            "rm /board/post.txt".to_string(),
+        );
+        coordinator.handle_authenticated_command(&own_msg).unwrap();
+
+        let notifications = coordinator.watches.drain();
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0].user_id, "zs1watcher123");
+        assert!(notifications[0]
+            .memo
+            .starts_with("watch: created /board/post.txt"));
+        assert!(notifications[1]
+            .memo
+            .starts_with("watch: modified /board/post.txt"));
+
+        let unwatch_msg = Message::new(
+            "zs1watcher123".to_string(),
+            "zs1coordinator".to_string(),
+            "unwatch /board".to_string(),
+        );
+        coordinator
+            .handle_authenticated_command(&unwatch_msg)
+            .unwrap();
+        assert!(coordinator
+            .handle_authenticated_command(&unwatch_msg)
+            .is_err());
+    }
+
+    #[test]
+    fn test_touch_requires_overwrite_flag_and_write_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator.filesystem.root.permissions.public_write = true;
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        run(&mut coordinator, "touch /notes.txt first").unwrap();
+        assert_eq!(
+            run(&mut coordinator, "touch /notes.txt second").unwrap_err(),
+            "File already exists: /notes.txt"
+        );
+        assert_eq!(
+            run(&mut coordinator, "touch --overwrite /notes.txt second").unwrap(),
+            "File overwritten: /notes.txt"
+        );
+        assert_eq!(
+            run(&mut coordinator, "write /notes.txt \"third draft\"").unwrap(),
+            "File updated: /notes.txt"
+        );
+        assert!(run(&mut coordinator, "write /missing.txt text").is_err());
+
+        let node = coordinator.filesystem.resolve_path("/notes.txt").unwrap();
+        assert_eq!(node.content.as_deref(), Some("third draft"));
+        assert_eq!(node.version, 3);
+    }
+
+    #[test]
+    fn test_ranged_cat_head_and_tail() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .create_file(
+                "/log.txt",
+                "alpha\nbeta\ngamma".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        assert_eq!(
+            run(&mut coordinator, "cat /log.txt 0 5").unwrap(),
+            "alpha\n(more: cat /log.txt 5 5)"
+        );
+        assert_eq!(run(&mut coordinator, "cat /log.txt 11").unwrap(), "gamma");
+        coordinator
+            .filesystem
+            .create_file(
+                "/my log.txt",
+                "alpha\nbeta".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+        let hint = run(&mut coordinator, "cat \"/my log.txt\" 0 5").unwrap();
+        assert_eq!(hint, "alpha\n(more: cat \"/my log.txt\" 5 5)");
+        let more = hint.split_once("(more: ").unwrap().1.trim_end_matches(')');
+        assert_eq!(run(&mut coordinator, more).unwrap(), "\nbeta");
+        assert_eq!(
+            run(&mut coordinator, "cat /log.txt 50 5").unwrap(),
+            "(end of file)"
+        );
+        assert!(run(&mut coordinator, "cat /log.txt x 5").is_err());
+        assert_eq!(run(&mut coordinator, "head /log.txt 1").unwrap(), "alpha");
+        assert_eq!(
+            run(&mut coordinator, "tail /log.txt 2").unwrap(),
+            "beta\ngamma"
+        );
+        assert_eq!(
+            run(&mut coordinator, "cat /log.txt").unwrap(),
+            "alpha\nbeta\ngamma"
+        );
+    }
+
+    #[test]
+    fn test_admin_gc_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+        coordinator
+            .filesystem
+            .create_file("/old.txt", "stale".to_string(), "coordinator".to_string())
+            .unwrap();
+        coordinator
+            .filesystem
+            .set_expiry("/old.txt", Some(1))
+            .unwrap();
+
+        let gc_msg = Message::new(
+            "zs1admin".to_string(),
+            "zs1coordinator".to_string(),
+            "admin gc".to_string(),
+        );
+        let response = coordinator.handle_authenticated_command(&gc_msg).unwrap();
+        assert_eq!(
+            response,
+            "GC complete: 1 expired nodes, 0 revisions pruned, 0 links dissolved, 5 bytes reclaimed"
+        );
+        assert!(coordinator.filesystem.resolve_path("/old.txt").is_none());
+
+        let expire_msg = Message::new(
+            "zs1admin".to_string(),
+            "zs1coordinator".to_string(),
+            "admin expire /missing.txt 60".to_string(),
+        );
+        assert!(coordinator
+            .handle_authenticated_command(&expire_msg)
+            .is_err());
+    }
+
+    #[test]
+    fn test_state_survives_restart() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        let register = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            format!("REGISTER:zs1reply456 {}", identity.public_key_hex()),
+        );
+        coordinator.process_incoming_message(&register).unwrap();
+        let challenge = coordinator
+            .challenges
+            .latest("zs1user123")
+            .unwrap()
+            .to_string();
+        drop(coordinator);
+
+        let mut restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert!(restarted.is_user_verified("zs1user123"));
+        assert_eq!(restarted.conversations.counter(), 1001);
+
+        let auth = Message::new(
+            "zs1user123".to_string(),
+            "zs1coordinator".to_string(),
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                identity.sign_auth("zs1reply456", &challenge)
+            ),
+        );
+        restarted.process_incoming_message(&auth).unwrap();
+        restarted.cleanup_expired_sessions();
+        drop(restarted);
+
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(
+            restarted.get_reply_address("zs1user123"),
+            Some("zs1reply456".to_string())
+        );
+        assert_eq!(restarted.get_all_sessions().len(), 1);
+        assert!(restarted.challenges.is_empty());
+    }
+
+    #[test]
+    fn test_conversation_prefix_is_validated() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let config = CoordinatorConfig {
+            admin: crate::config::AdminConfig {
+                addresses: vec!["zs1admin".to_string()],
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        let alice = sign_in(&mut coordinator, "zs1alice", "zs1alicereply");
+        let (alice_conv, alice_part) = coordinator.record_registration("zs1alice", "zs1alicereply");
+        let (bob_conv, bob_part) = coordinator.record_registration("zs1bob", "zs1bobreply");
+        let send = |coordinator: &mut Coordinator, sender: &str, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                sender.to_string(),
+                "zs1coordinator".to_string(),
+                memo,
+            ))
+        };
+
+        assert!(send(
+            &mut coordinator,
+            "zs1anyone",
+            alice.sign_command(
+                "zs1alicereply",
+                &format!("{}:{}:ls /", alice_conv, alice_part)
+            )
+        )
+        .is_ok());
+        assert!(send(
+            &mut coordinator,
+            "zs1anyone",
+            format!("{}:{}:ls /", alice_conv, alice_part)
+        )
+        .unwrap_err()
+        .starts_with("Authentication required"));
+        assert!(send(
+            &mut coordinator,
+            "zs1anyone",
+            format!("{}:{}:ls /", alice_conv, bob_part)
+        )
+        .unwrap_err()
+        .contains("does not belong"));
+
+        let again = send(
+            &mut coordinator,
+            "zs1alice",
+            "REGISTER:zs1alicereply".to_string(),
+        )
+        .unwrap();
+        assert!(again.contains(&format!("ConvID: {} PartID: {}", alice_conv, alice_part)));
+
+        coordinator
+            .handle_authenticated_command(&Message::new(
+                "zs1admin".to_string(),
+                "zs1coordinator".to_string(),
+                "admin ban zs1bob".to_string(),
+            ))
+            .unwrap();
+        assert!(send(
+            &mut coordinator,
+            "zs1anyone",
+            format!("{}:{}:ls /", bob_conv, bob_part)
+        )
+        .unwrap_err()
+        .contains("retired"));
+
+        let carol =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("carol.key")).unwrap();
+        let mut carol_session = crate::user_session::UserSession::new(
+            "zs1carol".to_string(),
+            "zs1carolreply".to_string(),
+        );
+        carol_session.authenticate(3600);
+        let legacy = CoordinatorState {
+            verified_users: HashMap::from([("zs1carol".to_string(), "zs1carolreply".to_string())]),
+            user_keys: HashMap::from([("zs1carol".to_string(), carol.public_key_hex())]),
+            conversation_mappings: HashMap::from([(
+                "CONV0900".to_string(),
+                "zs1carol".to_string(),
+            )]),
+            pending_challenges: HashMap::from([("zs1carol".to_string(), "c0ffee".to_string())]),
+            sessions: vec![carol_session],
+            ..CoordinatorState::default()
+        };
+        let legacy_dir = tempfile::tempdir().unwrap();
+        legacy.save(legacy_dir.path().join(STATE_FILE)).unwrap();
+        let mut migrated = Coordinator::new(
+            3600,
+            legacy_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let carol_part = conversation::participant_id("zs1carol");
+        assert!(send(
+            &mut migrated,
+            "zs1anyone",
+            carol.sign_command("zs1carolreply", &format!("CONV0900:{}:ls /", carol_part))
+        )
+        .is_ok());
+        migrated.save_state();
+        let saved = CoordinatorState::load(legacy_dir.path().join(STATE_FILE)).unwrap();
+        assert!(saved.conversation_mappings.is_empty());
+        assert_eq!(saved.conversations[0].id, "CONV0900");
+        assert!(saved.pending_challenges.is_empty());
+        assert_eq!(saved.challenges["zs1carol"][0].value, "c0ffee");
+    }
+
+    #[test]
+    fn test_rate_limit_throttles_sender() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.rate_limit.burst = 2;
+        config.rate_limit.refill_per_minute = 1;
+        coordinator.apply_config(&config);
+
+        let register = Message::new(
+            "zs1spam".to_string(),
+            "zs1coordinator".to_string(),
+            "REGISTER:zs1reply456".to_string(),
+        );
+        assert!(coordinator.process_incoming_message(&register).is_ok());
+        assert!(coordinator.process_incoming_message(&register).is_ok());
+
+        let throttled = coordinator.process_incoming_message(&register).unwrap_err();
+        assert!(throttled.starts_with("Rate limit exceeded"));
+        assert!(coordinator.rate_limiter.take_notice("zs1spam"));
+
+        let other = Message::new(
+            "zs1other".to_string(),
+            "zs1coordinator".to_string(),
+            "REGISTER:zs1reply789".to_string(),
+        );
+        assert!(coordinator.process_incoming_message(&other).is_ok());
+
+        let anonymous: Vec<Message> = (0..3)
+            .flat_map(|i| {
+                Message::from_zingo_value(&serde_json::json!({
+                    "txid": format!("{:02}ff1234567890", i),
+                    "memos": ["ls /"]
+                }))
+                .unwrap()
+            })
+            .collect();
+        for message in &anonymous[..2] {
+            assert!(!coordinator
+                .process_incoming_message(message)
+                .unwrap_err()
+                .starts_with("Rate limit exceeded"));
+        }
+        assert!(coordinator
+            .process_incoming_message(&anonymous[2])
+            .unwrap_err()
+            .starts_with("Rate limit exceeded"));
+    }
+
+    #[test]
+    fn test_policy_pipeline_from_config() {
+        use crate::policy::PolicyKind;
+        use crate::quota::QuotaConfig;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let config = CoordinatorConfig {
+            admin: crate::config::AdminConfig {
+                addresses: vec!["zs1admin".to_string()],
+            },
+            policy: PolicyConfig {
+                order: vec![
+                    PolicyKind::BanList,
+                    PolicyKind::MinPayment,
+                    PolicyKind::Quota,
+                ],
+                min_payment_zatoshi: 1000,
+            },
+            quotas: QuotaConfig {
+                enabled: true,
+                reads_per_window: 1,
+                ..QuotaConfig::default()
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        let keys = [
+            (
+                "zs1user123",
+                sign_in(&mut coordinator, "zs1user123", "zs1reply456"),
+            ),
+            (
+                "zs1admin",
+                sign_in(&mut coordinator, "zs1admin", "zs1adminreply"),
+            ),
+        ];
+        let send = |coordinator: &mut Coordinator, sender: &str, memo: &str, paid: u64| {
+            let memo = match keys.iter().find(|(name, _)| *name == sender) {
+                Some((_, key)) => {
+                    key.sign_command(&coordinator.get_reply_address(sender).unwrap(), memo)
+                }
+                None => memo.to_string(),
+            };
+            let mut message = Message::new(sender.to_string(), "zs1coordinator".to_string(), memo);
+            message.amount_zatoshis = paid;
+            coordinator.process_incoming_message(&message)
+        };
+
+        assert!(send(&mut coordinator, "zs1new", "REGISTER:zs1newreply", 10)
+            .unwrap_err()
+            .starts_with("Payment required: memos must carry at least 1000"));
+        assert!(send(&mut coordinator, "zs1new", "REGISTER:zs1newreply", 1000).is_ok());
+
+        assert!(send(&mut coordinator, "zs1user123", "ls /", 1000).is_ok());
+        assert!(send(&mut coordinator, "zs1user123", "ls /", 1000)
+            .unwrap_err()
+            .starts_with("Quota exceeded"));
+
+        let described = send(&mut coordinator, "zs1admin", "admin policy", 0).unwrap();
+        assert_eq!(
+            described,
+            "Policies: ban_list -> min_payment -> quota\nMinimum payment: 1000 zatoshis"
+        );
+        for _ in 0..30 {
+            assert!(send(&mut coordinator, "zs1admin", "ls /", 0).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_chat_room_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        assert_eq!(
+            run(&mut coordinator, "chat create general").unwrap(),
+            "Chat room created: general"
+        );
+        assert!(run(&mut coordinator, "chat create general").is_err());
+        assert_eq!(
+            run(&mut coordinator, "chat post general Hello everyone!").unwrap(),
+            "Posted #1 to general"
+        );
+        run(&mut coordinator, "chat post general \"Second\"").unwrap();
+
+        let all = run(&mut coordinator, "chat read general").unwrap();
+        assert!(all.contains("#1 ["));
+        assert!(all.contains("Hello everyone!"));
+        let newer = run(&mut coordinator, "chat read general 1").unwrap();
+        assert!(newer.starts_with("#2 ["));
+        assert!(newer.ends_with(": Second"));
+        assert!(run(&mut coordinator, "chat read missing").is_err());
+        assert_eq!(run(&mut coordinator, "chat rooms").unwrap(), "general");
+        assert!(coordinator.filesystem.resolve_path("/general").is_none());
+
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(restarted.chat_rooms.get("general").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_command_registry_dispatch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        let unknown = run(&mut coordinator, "frobnicate /").unwrap_err();
+        assert!(unknown.starts_with("Unknown command. Try: help, ls, stat,"));
+        assert!(!unknown.contains("admin"));
+
+        assert_eq!(
+            run(&mut coordinator, "grant read zs1bob").unwrap_err(),
+            "Invalid grant format. Use: grant <read|write> <user> <path>"
+        );
+        assert_eq!(
+            run(&mut coordinator, "admin gc").unwrap_err(),
+            "Permission denied: admin only"
+        );
+        assert_eq!(run(&mut coordinator, "ls").unwrap(), "(empty directory)");
+
+        let spec = coordinator.commands.get("chat create").unwrap();
+        assert_eq!(spec.usage(), "chat create <room>");
+    }
+
+    #[test]
+    fn test_help_command_hides_admin_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+
+        let run = |coordinator: &mut Coordinator, user: &str, command: &str| {
+            let msg = Message::new(
+                user.to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        let user_help = run(&mut coordinator, "zs1user123", "help").unwrap();
+        assert!(user_help.contains("\nstat <path>\n"));
+        assert!(!user_help.contains("admin gc"));
+
+        let admin_help = run(&mut coordinator, "zs1admin", "help").unwrap();
+        assert!(admin_help.contains("\nadmin gc\n"));
+
+        assert_eq!(
+            run(&mut coordinator, "zs1user123", "help mkdir").unwrap(),
+            "mkdir <path> - Create a directory"
+        );
+        let chat_help = run(&mut coordinator, "zs1user123", "help chat").unwrap();
+        assert!(chat_help.contains("chat create <room> - Create a chat room"));
+        assert!(run(&mut coordinator, "zs1user123", "help admin gc").is_err());
+        assert!(run(&mut coordinator, "zs1admin", "help admin gc").is_ok());
+    }
+
+    #[test]
+    fn test_batch_memo_runs_commands_in_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let config = CoordinatorConfig {
+            fees: FeeConfig {
+                enabled: true,
+                per_command_zatoshi: 100,
+                chat_message_zatoshi: 0,
+                file_upload_zatoshi: 100,
+                read_zatoshi: 10,
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        let run = |coordinator: &mut Coordinator, command: &str, paid: u64| {
+            let mut msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            msg.amount_zatoshis = paid;
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        let batch = "batch mkdir /a; touch /a/x hi; ls /a";
+        assert!(run(&mut coordinator, batch, 150)
+            .unwrap_err()
+            .starts_with("Payment required: batch of 3 commands costs 210 zatoshis"));
+        assert!(coordinator.filesystem.resolve_path("/a").is_none());
+
+        let output = run(&mut coordinator, batch, 210).unwrap();
+        assert!(output.starts_with("> mkdir /a\n"));
+        assert!(output.contains("> touch /a/x hi\n"));
+        assert!(output.contains("> ls /a\n"));
+        assert!(output.contains("x"));
+
+        let failed = run(&mut coordinator, "batch mkdir /b; mkdir /a; ls /", 300).unwrap_err();
+        assert!(failed.contains("(batch stopped at command 2 of 3: mkdir /a)"));
+        assert!(coordinator.filesystem.resolve_path("/b").is_some());
+
+        let too_many = format!(
+            "{}{}",
+            commands::BATCH_PREFIX,
+            ["ls /"; commands::MAX_BATCH_COMMANDS + 1].join("; ")
+        );
+        assert!(run(&mut coordinator, &too_many, 1000)
+            .unwrap_err()
+            .starts_with("Too many commands in batch"));
+
+        let unbatched = run(&mut coordinator, "touch /a/z a; ls /", 110).unwrap();
+        assert!(!unbatched.contains("> ls /"));
+        assert_eq!(
+            coordinator
+                .filesystem
+                .resolve_path("/a/z")
+                .unwrap()
+                .content
+                .as_deref(),
+            Some("a; ls /")
+        );
+
+        let frame = memo_decoder::encode_batch(&["mkdir /c", "touch /c/y a; b", "ls /c"]);
+        let output = run(&mut coordinator, &frame, 210).unwrap();
+        assert!(output.contains("> touch /c/y a; b\n"));
+        assert_eq!(
+            coordinator
+                .filesystem
+                .resolve_path("/c/y")
+                .unwrap()
+                .content
+                .as_deref(),
+            Some("a; b")
+        );
+        assert!(run(&mut coordinator, "ZBATCH:2:4:ls /", 210)
+            .unwrap_err()
+            .starts_with("Invalid batch frame"));
+    }
+
+    #[test]
+    fn test_configured_aliases_dispatch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let config = CoordinatorConfig {
+            aliases: [
+                ("dir", "ls"),
+                ("post", "touch"),
+                ("ls", "rm"),
+                ("zap", "nuke"),
+            ]
+            .into_iter()
+            .map(|(alias, target)| (alias.to_string(), target.to_string()))
+            .collect(),
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        run(&mut coordinator, "post /notes.txt hello").unwrap();
+        assert!(run(&mut coordinator, "dir /")
+            .unwrap()
+            .contains("notes.txt"));
+        assert!(run(&mut coordinator, "ls /").unwrap().contains("notes.txt"));
+        assert!(run(&mut coordinator, "zap /notes.txt").is_err());
+
+        let help = run(&mut coordinator, "help").unwrap();
+        assert!(help.contains("Aliases: dir -> ls, post -> touch"));
+        assert!(run(&mut coordinator, "help dir")
+            .unwrap()
+            .starts_with("ls [--hash]"));
+
+        coordinator.apply_config(&CoordinatorConfig::default());
+        assert!(run(&mut coordinator, "dir /").is_err());
+    }
+
+    #[test]
+    fn test_admin_user_management() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+
+        let register = Message::new(
+            "zs1spammer".to_string(),
+            "zs1coordinator".to_string(),
+            "REGISTER:zs1spamreply".to_string(),
+        );
+        coordinator.process_incoming_message(&register).unwrap();
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1admin".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        assert!(run(&mut coordinator, "admin users")
+            .unwrap()
+            .contains("zs1spammer ("));
+        assert!(run(&mut coordinator, "admin sessions")
+            .unwrap()
+            .starts_with("zs1spammer session="));
+        assert!(run(&mut coordinator, "admin ban zs1admin").is_err());
+        assert_eq!(
+            run(&mut coordinator, "admin ban zs1spammer").unwrap(),
+            "Banned: zs1spammer"
+        );
+        assert!(!coordinator.is_user_verified("zs1spammer"));
+
+        let mut retry = register.clone();
+        retry.txid = Some("tx2".to_string());
+        assert!(coordinator
+            .process_incoming_message(&retry)
+            .unwrap_err()
+            .contains("banned"));
+
+        let stats = run(&mut coordinator, "admin stats").unwrap();
+        assert!(stats.contains("Banned: 1"));
+
+        assert_eq!(
+            run(&mut coordinator, "admin unban zs1spammer").unwrap(),
+            "Unbanned: zs1spammer"
+        );
+        assert!(coordinator.process_incoming_message(&register).is_ok());
+    }
+
+    #[test]
+    fn test_prefix_bans_block_matching_addresses_before_registration() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+        let register = |coordinator: &mut Coordinator, sender: &str, reply: &str| {
+            coordinator.process_incoming_message(&Message::new(
+                sender.to_string(),
+                "zs1coordinator".to_string(),
+                format!("REGISTER:{}", reply),
+            ))
+        };
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                "zs1admin".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ))
+        };
+
+        register(&mut coordinator, "zs1spambot1", "zs1spambot1reply").unwrap();
+        assert!(run(&mut coordinator, "admin ban zs1*").is_err());
+        assert!(run(&mut coordinator, "admin ban zs1adm*").is_err());
+        assert_eq!(
+            run(&mut coordinator, "admin ban zs1spam*").unwrap(),
+            "Banned: zs1spam*"
+        );
+        assert!(!coordinator.is_user_verified("zs1spambot1"));
+
+        let challenges_before = coordinator.challenges.len();
+        assert!(register(&mut coordinator, "zs1spambot2", "zs1elsewhere")
+            .unwrap_err()
+            .contains("banned"));
+        assert!(register(&mut coordinator, "zs1honest", "zs1spambot9")
+            .unwrap_err()
+            .contains("banned"));
+        assert_eq!(coordinator.challenges.len(), challenges_before);
+        assert!(register(&mut coordinator, "zs1honest", "zs1honestreply").is_ok());
+
+        assert_eq!(run(&mut coordinator, "admin bans").unwrap(), "zs1spam*");
+        coordinator.save_state();
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert!(restarted.is_banned("zs1spambot3"));
+        assert_eq!(
+            run(&mut coordinator, "admin unban zs1spam*").unwrap(),
+            "Unbanned: zs1spam*"
+        );
+        assert_eq!(run(&mut coordinator, "admin bans").unwrap(), "No bans");
+    }
+
+    #[test]
+    fn test_roles_gate_moderation_and_admin_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        for user in ["zs1admin", "zs1mod", "zs1member", "zs1lurker"] {
+            coordinator
+                .verified_users
+                .insert(user.to_string(), format!("{}reply", user));
+        }
+        coordinator
+            .filesystem
+            .create_file(
+                "/spam.txt",
+                "buy now".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+
+        let run = |coordinator: &mut Coordinator, user: &str, command: &str| {
+            let msg = Message::new(
+                user.to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        assert_eq!(coordinator.role_of("zs1member"), Role::Member);
+        assert_eq!(
+            run(&mut coordinator, "zs1mod", "admin hide /spam.txt").unwrap_err(),
+            "Permission denied: moderators only"
+        );
+        assert_eq!(
+            run(&mut coordinator, "zs1mod", "admin role zs1mod moderator").unwrap_err(),
+            "Permission denied: admin only"
+        );
+        assert_eq!(
+            run(&mut coordinator, "zs1admin", "admin role zs1mod moderator").unwrap(),
+            "zs1mod is now a moderator"
+        );
+        assert!(run(&mut coordinator, "zs1admin", "admin role zs1nobody guest").is_err());
+        assert!(run(&mut coordinator, "zs1admin", "admin role zs1admin guest").is_err());
+        assert!(run(&mut coordinator, "zs1admin", "admin role zs1mod owner")
+            .unwrap_err()
+            .starts_with("Invalid role"));
+
+        assert!(run(&mut coordinator, "zs1mod", "admin hide /spam.txt")
+            .unwrap()
+            .starts_with("Hidden: /spam.txt"));
+        assert!(run(&mut coordinator, "zs1mod", "cat /spam.txt").is_ok());
+        assert!(run(&mut coordinator, "zs1member", "cat /spam.txt").is_err());
+        assert!(run(&mut coordinator, "zs1mod", "help")
+            .unwrap()
+            .contains("admin reports"));
+        assert!(!run(&mut coordinator, "zs1mod", "help")
+            .unwrap()
+            .contains("admin gc"));
+        assert!(run(&mut coordinator, "zs1mod", "admin gc").is_err());
+        assert!(run(&mut coordinator, "zs1mod", "admin ban zs1admin").is_err());
+        assert_eq!(
+            run(&mut coordinator, "zs1mod", "admin ban zs1lurker").unwrap(),
+            "Banned: zs1lurker"
+        );
+
+        run(&mut coordinator, "zs1admin", "admin role zs1member guest").unwrap();
+        assert!(run(&mut coordinator, "zs1member", "ls /").is_ok());
+        assert!(run(&mut coordinator, "zs1member", "mkdir /docs")
+            .unwrap_err()
+            .starts_with("Permission denied: your role (guest)"));
+        assert_eq!(
+            run(&mut coordinator, "zs1admin", "admin roles").unwrap(),
+            "zs1admin admin (config)\nzs1member guest\nzs1mod moderator"
+        );
+
+        let reloaded = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(reloaded.roles.get("zs1mod"), Some(&Role::Moderator));
+    }
+
+    #[test]
+    fn test_responses_are_queued_until_sent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+
+        coordinator
+            .send_response("zs1user123", &"x".repeat(700))
+            .unwrap();
+        assert_eq!(coordinator.pending_outbound(), 2);
+        assert!(coordinator.send_response("zs1unknown", "hi").is_err());
+
+        let report = coordinator.flush_outbound();
+        assert_eq!(report.sent, 0);
+        assert_eq!(report.failed, 2);
+        assert_eq!(coordinator.pending_outbound(), 2);
+        drop(coordinator);
+
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(restarted.pending_outbound(), 2);
+    }
+
+    #[test]
+    fn test_payment_required_per_command_class() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.fees.enabled = true;
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let send = |coordinator: &mut Coordinator, sender: &str, command: &str, amount: u64| {
+            let message = Message::new(
+                sender.to_string(),
+                "coordinator".to_string(),
+                command.to_string(),
+            )
+            .with_amount(amount);
+            coordinator.handle_authenticated_command(&message)
+        };
+
+        assert!(send(&mut coordinator, "zs1user123", "ls /", 0).is_ok());
+
+        let err = send(&mut coordinator, "zs1user123", "mkdir /paid", 999).unwrap_err();
+        assert_eq!(
+            err,
+            "Payment required: mkdir costs 1000 zatoshis (received 999)"
+        );
+        send(&mut coordinator, "zs1user123", "mkdir /paid", 1000).unwrap();
+
+        let err = send(&mut coordinator, "zs1user123", "touch /paid/a.txt hi", 0).unwrap_err();
+        assert!(err.starts_with("Payment required: touch"));
+        let err = send(
+            &mut coordinator,
+            "zs1user123",
+            "echo hi > /paid/b.txt",
+            1000,
+        )
+        .unwrap_err();
+        assert!(err.contains("5000 zatoshis"));
+        let err = send(&mut coordinator, "zs1user123", "chat create lobby", 0).unwrap_err();
+        assert!(err.contains("500 zatoshis"));
+
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
+        send(&mut coordinator, "zs1admin", "chat create lobby", 0).unwrap();
+        send(&mut coordinator, "zs1admin", "admin freeze /paid", 0).unwrap();
+
+        config.fees.read_zatoshi = 10;
+        coordinator.apply_config(&config);
+        let err = send(&mut coordinator, "zs1user123", "ls /", 0).unwrap_err();
+        assert!(err.starts_with("Payment required: ls"));
+    }
+
+    #[test]
+    fn test_admin_audit_lists_processed_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        let admin = sign_in(&mut coordinator, "zs1admin", "zs1adminreply");
+
+        let mut unknown = Message::new(
+            "zs1stranger".to_string(),
+            "coordinator".to_string(),
+            "ls /".to_string(),
+        );
+        unknown.txid = Some("tx-unknown".to_string());
+        assert!(coordinator.process_incoming_message(&unknown).is_err());
+
+        let audit = |memo: &str| {
+            Message::new(
+                "zs1admin".to_string(),
+                "coordinator".to_string(),
+                admin.sign_command("zs1adminreply", memo),
+            )
+        };
+        let output = coordinator
+            .process_incoming_message(&audit("admin audit"))
+            .unwrap();
+        assert!(output.contains("zs1stranger err ls / -> Authentication required"));
+
+        let output = coordinator
+            .process_incoming_message(&audit("admin audit 1"))
+            .unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.contains("zs1admin ok admin audit"));
+
+        assert!(coordinator
+            .process_incoming_message(&audit("admin audit zero"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_metrics_track_messages_and_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        let admin = sign_in(&mut coordinator, "zs1admin", "zs1adminreply");
+
+        let bad_auth = Message::new(
+            "zs1stranger".to_string(),
+            "coordinator".to_string(),
+            "AUTH:bogus".to_string(),
+        );
+        assert!(coordinator.process_incoming_message(&bad_auth).is_err());
+
+        let mut ls = Message::new(
+            "zs1admin".to_string(),
+            "coordinator".to_string(),
+            admin.sign_command("zs1adminreply", "ls /"),
+        );
+        assert!(coordinator.process_incoming_message(&ls).is_ok());
+
+        let metrics = coordinator.metrics();
+        assert_eq!(metrics.messages_processed, 2);
+        assert_eq!(metrics.messages_failed, 1);
+        assert_eq!(metrics.auth_failures, 1);
+        assert_eq!(metrics.commands["ls"].count, 1);
+
+        ls.memo_text = admin.sign_command("zs1adminreply", "admin metrics");
+        let output = coordinator.process_incoming_message(&ls).unwrap();
+        assert!(output.contains("auth_failures: 1"));
+        assert!(output.contains("cmd ls: count=1"));
+
+        coordinator.export_metrics().unwrap();
+        let exported = std::fs::read_to_string(temp_dir.path().join("metrics.prom")).unwrap();
+        assert!(exported.contains("zatboard_messages_processed_total 3"));
+    }
+
+    #[test]
+    fn test_shutdown_flushes_state() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        coordinator.send_response("zs1user123", "pending").unwrap();
+
+        coordinator.shutdown().unwrap();
+        assert!(temp_dir.path().join("coordinator_state.json").exists());
+        assert!(temp_dir.path().join("metrics.prom").exists());
+
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert!(restarted.is_user_verified("zs1user123"));
+        assert_eq!(restarted.pending_outbound(), 1);
+    }
+
+    #[test]
+    fn test_replies_use_response_envelope() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let key = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
+        let send = |coordinator: &mut Coordinator, command: &str| {
+            let message = Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                key.sign_command("zs1reply456", command),
+            );
+            let _ = coordinator.process_and_respond(&message);
+            let memo = coordinator
+                .lock_outbound()
+                .items()
+                .last()
+                .unwrap()
+                .memo
+                .clone();
+            signing::verify(&coordinator.signer.public_key_hex(), "zs1reply456", &memo)
+                .unwrap_or(memo)
+        };
+
+        let listing = response::Envelope::decode(&send(&mut coordinator, "ls /")).unwrap();
+        assert_eq!(listing.status, response::Status::Ok);
+        assert_eq!(listing.command, "ls /");
+        assert_eq!(listing.payload, "(empty directory)");
+
+        assert!(
+            send(&mut coordinator, "admin gc").starts_with("ZB1 403 . admin gc\nPermission denied")
+        );
+        assert!(
+            send(&mut coordinator, "cat /missing.txt").starts_with("ZB1 404 . cat /missing.txt\n")
+        );
+
+        let config = CoordinatorConfig {
+            responses: ResponseConfig {
+                envelope: false,
+                ..ResponseConfig::default()
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        assert_eq!(send(&mut coordinator, "ls /"), "(empty directory)");
+    }
+
+    #[test]
+    fn test_replies_are_signed_with_published_key() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let key = coordinator.signer.public_key_hex();
+        let register = Message::new(
+            "zs1user123".to_string(),
+            "coordinator".to_string(),
+            "REGISTER:zs1reply456".to_string(),
+        );
+        let registered = coordinator.process_incoming_message(&register).unwrap();
+        assert!(registered.ends_with(&format!(" Compress: zstd CoordKey: {}", key)));
+        let features = handshake::Features::parse(&registered).unwrap();
+        assert!(!registered.contains("cmds="));
+        assert_eq!(features, coordinator.features().without_commands());
+        assert_eq!(features.negotiate(), Ok(handshake::PROTOCOL_VERSION));
+
+        let identity = sign_in(&mut coordinator, "zs1caps", "zs1capsreply");
+        let caps = coordinator
+            .process_incoming_message(&Message::new(
+                "zs1caps".to_string(),
+                "coordinator".to_string(),
+                identity.sign_command("zs1capsreply", "caps"),
+            ))
+            .unwrap();
+        let features = handshake::Features::parse(&caps).unwrap();
+        assert_eq!(features, coordinator.features());
+        assert!(features.supports_command("ls") && features.supports_command("caps"));
+        assert!(features.supports_command("admin"));
+        assert!(!features.supports_command("format"));
+
+        coordinator.send_response("zs1user123", "hello").unwrap();
+        let last_memo = |coordinator: &Coordinator| {
+            coordinator
+                .lock_outbound()
+                .items()
+                .last()
+                .unwrap()
+                .memo
+                .clone()
+        };
+        let signed = last_memo(&coordinator);
+        assert_eq!(
+            signing::verify(&key, "zs1reply456", &signed).unwrap(),
+            "hello"
+        );
+        assert!(signing::verify(&key, "zs1attacker", &signed).is_err());
+
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(restarted.signer.public_key_hex(), key);
+        assert!(Coordinator::state_files("fs.db").contains(&SIGNING_KEY_FILE.to_string()));
+
+        let config = CoordinatorConfig {
+            signing: SigningConfig { enabled: false },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        coordinator.send_response("zs1user123", "hello").unwrap();
+        assert_eq!(last_memo(&coordinator), "hello");
+        assert_eq!(
+            coordinator.handle_pubkey_command(),
+            "Reply signing is disabled"
+        );
+    }
+
+    #[test]
+    fn test_processed_messages_are_not_reexecuted_after_restart() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let new_coordinator = || {
+            Coordinator::new(
+                3600,
+                temp_dir.path().to_path_buf(),
+                "http://test:9067".to_string(),
+            )
+        };
+
+        let mut coordinator = new_coordinator();
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
+        coordinator.save_state();
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let mkdir = Message::with_txid(
+            "zs1user123".to_string(),
+            "coordinator".to_string(),
+            identity.sign_command("zs1reply456", "mkdir /once"),
+            "tx-mkdir".to_string(),
+        );
+
+        let result = coordinator.process_incoming_message(&mkdir);
+        let key = mkdir.idempotency_key().unwrap();
+        coordinator
+            .processed
+            .record(ProcessedEntry::new(&key, "zs1user123", &result, 1))
+            .unwrap();
+        drop(coordinator);
+
+        let mut restarted = new_coordinator();
+        assert_eq!(restarted.pending_outbound(), 0);
+        assert_eq!(restarted.filter_new_messages(vec![mkdir.clone()]).len(), 1);
+        restarted.process_and_respond(&mkdir).unwrap();
+        assert_eq!(restarted.pending_outbound(), 1);
+        assert!(restarted
+            .lock_outbound()
+            .items()
+            .all(|item| item.memo.contains("Directory created")));
+        assert!(restarted.processed.get(&key).unwrap().replied);
+
+        assert!(restarted
+            .filter_new_messages(vec![mkdir.clone()])
+            .is_empty());
+        restarted.process_and_respond(&mkdir).unwrap();
+        assert_eq!(restarted.pending_outbound(), 1);
+
+        let second_output = mkdir.clone().with_output_index(1);
+        assert_eq!(restarted.filter_new_messages(vec![second_output]).len(), 1);
+    }
+
+    #[test]
+    fn test_replies_that_fail_to_queue_are_retried() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        let ls = Message::with_txid(
+            "zs1user123".to_string(),
+            "coordinator".to_string(),
+            "ls /".to_string(),
+            "tx-ls".to_string(),
+        );
+        let key = ls.idempotency_key().unwrap();
+
+        let blocker = temp_dir.path().join("outbound_queue.json.tmp");
+        std::fs::create_dir(&blocker).unwrap();
+        assert_eq!(coordinator.filter_new_messages(vec![ls.clone()]).len(), 1);
+        assert!(coordinator
+            .process_and_respond(&ls)
+            .unwrap_err()
+            .starts_with("Failed to write outbound queue"));
+        assert_eq!(coordinator.pending_outbound(), 0);
+        assert!(!coordinator.processed.get(&key).unwrap().replied);
+
+        std::fs::remove_dir(&blocker).unwrap();
+        assert_eq!(coordinator.filter_new_messages(vec![ls.clone()]).len(), 1);
+        assert!(coordinator
+            .process_and_respond(&ls)
+            .unwrap_err()
+            .starts_with("Authentication required"));
+        assert_eq!(coordinator.pending_outbound(), 1);
+        assert!(coordinator.processed.get(&key).unwrap().replied);
+        assert!(coordinator.filter_new_messages(vec![ls]).is_empty());
+    }
+
+    #[test]
+    fn test_quoted_paths_with_spaces() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        coordinator
+            .verified_users
+            .insert("zs1owner".to_string(), "zs1ownerreply".to_string());
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1owner".to_string());
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                "zs1owner".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ))
+        };
+
+        run(&mut coordinator, r#"mkdir "/my docs""#).unwrap();
+        run(
+            &mut coordinator,
+            r#"touch '/my docs/first draft.txt' "hello world""#,
+        )
+        .unwrap();
+        run(
+            &mut coordinator,
+            r#"cp "/my docs/first draft.txt" /my\ docs/copy.txt"#,
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut coordinator, r#"cat "/my docs/copy.txt""#).unwrap(),
+            "hello world"
+        );
+        assert!(run(&mut coordinator, "ls \"/my docs\"")
+            .unwrap()
+            .contains("first draft.txt"));
+
+        assert_eq!(
+            run(&mut coordinator, r#"cat "/my docs/copy.txt"#).unwrap_err(),
+            "Invalid quoting: unterminated \" quote in \"/my docs/copy.txt"
+        );
+        assert!(run(&mut coordinator, "rm /my docs")
+            .unwrap_err()
+            .contains("quote paths that contain spaces"));
+    }
+
+    #[test]
+    fn test_encrypted_commands_get_encrypted_replies() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
+        coordinator
+            .process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                format!("REGISTER:zs1reply789 {}", identity.public_key_hex()),
+            ))
+            .unwrap();
+        answer_challenge(&mut coordinator, "zs1user123", "zs1reply789", &identity);
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let coordinator_key = coordinator.signer.public_key_hex();
+        let sealed = identity
+            .e2e_key()
+            .seal(
+                &coordinator_key,
+                &identity.sign_command("zs1reply789", "touch /diary.txt dear diary"),
+            )
+            .unwrap();
+        let mut message = Message::new("zs1user123".to_string(), "coordinator".to_string(), sealed);
+        message.txid = Some("tx-sealed".to_string());
+        coordinator.process_and_respond(&message).unwrap();
+        assert_eq!(
+            coordinator
+                .filesystem
+                .resolve_path("/diary.txt")
+                .unwrap()
+                .read_content(None)
+                .unwrap(),
+            "dear diary"
+        );
+
+        message.memo_text = identity
+            .e2e_key()
+            .seal(
+                &coordinator_key,
+                &identity.sign_command("zs1reply789", "cat /diary.txt"),
+            )
+            .unwrap();
+        message.txid = Some("tx-sealed-cat".to_string());
+        coordinator.process_and_respond(&message).unwrap();
+        let memo = coordinator
+            .lock_outbound()
+            .items()
+            .last()
+            .unwrap()
+            .memo
+            .clone();
+        let body = signing::verify(&coordinator_key, "zs1reply789", &memo).unwrap();
+        assert!(e2e::is_encrypted(&body));
+        assert!(!body.contains("dear diary"));
+        assert!(identity
+            .e2e_key()
+            .open(&coordinator_key, &body)
+            .unwrap()
+            .contains("dear diary"));
+
+        message.sender_address = "zs1stranger".to_string();
+        message.txid = Some("tx-stranger".to_string());
+        assert!(coordinator
+            .process_and_respond(&message)
+            .unwrap_err()
+            .starts_with("Invalid encrypted memo"));
+    }
+
+    #[test]
+    fn test_fragmented_commands_run_once_all_parts_arrive() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let content = "line of text\n".repeat(100);
+        let touch = Message::new(
+            "zs1user123".to_string(),
+            "coordinator".to_string(),
+            identity.sign_command("zs1reply456", &format!("touch /big.txt {}", content)),
+        );
+        let fragments: Vec<Message> = touch
+            .fragments(memo_decoder::MAX_MEMO_SIZE)
+            .into_iter()
+            .enumerate()
+            .map(|(i, fragment)| Message {
+                txid: Some(format!("tx-frag-{}", i)),
+                ..fragment
+            })
+            .collect();
+        assert_eq!(fragments.len(), 3);
+
+        for fragment in [&fragments[1], &fragments[0], &fragments[1]] {
+            coordinator.process_and_respond(fragment).unwrap();
+        }
+        assert_eq!(coordinator.pending_outbound(), 0);
+        assert!(coordinator.filesystem.resolve_path("/big.txt").is_none());
+
+        coordinator.process_and_respond(&fragments[2]).unwrap();
+        assert_eq!(coordinator.pending_outbound(), 1);
+        assert_eq!(
+            coordinator
+                .filesystem
+                .resolve_path("/big.txt")
+                .unwrap()
+                .read_content(None)
+                .unwrap(),
+            content
+        );
+        assert!(coordinator.reassembler.is_empty());
+
+        let bad = Message {
+            memo_text: "ZBFRAG:zz:9/2:x".to_string(),
+            txid: Some("tx-bad".to_string()),
+            ..touch.clone()
+        };
+        assert!(coordinator
+            .process_and_respond(&bad)
+            .unwrap_err()
+            .starts_with("Invalid fragment"));
+    }
+
+    #[test]
+    fn test_binary_encoded_memos_are_decoded_before_dispatch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+
+        let mut message = Message::new(
+            "zs1user123".to_string(),
+            "coordinator".to_string(),
+            memo_decoder::encode_binary(
+                identity
+                    .sign_command("zs1reply456", "mkdir /encoded")
+                    .as_bytes(),
+            ),
+        );
+        assert!(coordinator.process_incoming_message(&message).is_ok());
+        assert!(coordinator.filesystem.resolve_path("/encoded").is_some());
+
+        message.memo_text = memo_decoder::encode_binary(&[0xff, 0xfe, 0x00]);
+        assert!(coordinator
+            .process_incoming_message(&message)
+            .unwrap_err()
+            .starts_with("Invalid memo"));
+    }
+
+    #[test]
+    fn test_backlogged_messages_are_processed_by_payment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.priority.batch_size = 2;
+        coordinator.apply_config(&config);
+
+        let memo = |txid: &str, amount: u64| {
+            Message::with_txid(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                "ls /".to_string(),
+                txid.to_string(),
+            )
+            .with_amount(amount)
+        };
+        coordinator.enqueue_messages(vec![memo("tx-free", 0), memo("tx-low", 10)]);
+        coordinator.enqueue_messages(vec![memo("tx-free", 0), memo("tx-high", 5000)]);
+        assert_eq!(coordinator.pending_messages(), 3);
+        assert_eq!(coordinator.status_snapshot().inbound_depth, 3);
+
+        let batch = coordinator.next_message_batch();
+        let txids: Vec<_> = batch.iter().filter_map(|m| m.txid.as_deref()).collect();
+        assert_eq!(txids, vec!["tx-high", "tx-low"]);
+        assert_eq!(coordinator.pending_messages(), 1);
+        assert_eq!(
+            coordinator.next_message_batch()[0].txid.as_deref(),
+            Some("tx-free")
+        );
+        assert!(coordinator.next_message_batch().is_empty());
+    }
+
+    #[test]
+    fn test_federation_replicates_registrations_and_writes() {
+        use crate::federation::{FederationConfig, PeerConfig};
+
+        let dir_a = tempfile::tempdir().unwrap();
+        let dir_b = tempfile::tempdir().unwrap();
+        let federated = |dir: &tempfile::TempDir, node_id: &str, peer_id: &str| {
+            let mut coordinator = Coordinator::new(
+                3600,
+                dir.path().to_path_buf(),
+                "http://test:9067".to_string(),
+            );
+            let config = CoordinatorConfig {
+                federation: FederationConfig {
+                    enabled: true,
+                    node_id: node_id.to_string(),
+                    peers: vec![PeerConfig {
+                        node_id: peer_id.to_string(),
+                        address: format!("zs1{}", peer_id),
+                        secret: "board-secret".to_string(),
+                    }],
+                },
+                ..CoordinatorConfig::default()
+            };
+            coordinator.apply_config(&config);
+            coordinator
+                .filesystem
+                .root
+                .permissions
+                .add_write_permission("zs1user123".to_string());
+            coordinator
+        };
+        let mut alpha = federated(&dir_a, "alpha", "beta");
+        let mut beta = federated(&dir_b, "beta", "alpha");
+
+        let take_deltas = |coordinator: &Coordinator| -> Vec<String> {
+            let mut queue = coordinator.lock_outbound();
+            let memos: Vec<(String, String)> = queue
+                .items()
+                .map(|item| (item.address.clone(), item.memo.clone()))
+                .collect();
+            let ids: Vec<(u64, Result<String, String>)> = queue
+                .items()
+                .map(|item| (item.id, Ok(format!("tx-{}", item.id))))
+                .collect();
+            queue.record_results(0, ids);
+            memos
+                .into_iter()
+                .filter(|(address, memo)| address == "zs1beta" && memo.starts_with("FED:"))
+                .map(|(_, memo)| memo)
+                .collect()
+        };
+
+        let key = identity::IdentityKey::load_or_create(dir_a.path().join("identity.key")).unwrap();
+        let next_key =
+            identity::IdentityKey::load_or_create(dir_a.path().join("next.key")).unwrap();
+        let send = |coordinator: &mut Coordinator, memo: String| {
+            coordinator.process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                memo,
+            ))
+        };
+        send(
+            &mut alpha,
+            format!("REGISTER:zs1reply456 {}", key.public_key_hex()),
+        )
+        .unwrap();
+        let challenge = alpha.challenges.latest("zs1user123").unwrap().to_string();
+        send(
+            &mut alpha,
+            format!(
+                "AUTH:{} {}",
+                challenge,
+                key.sign_auth("zs1reply456", &challenge)
+            ),
+        )
+        .unwrap();
+        send(
+            &mut alpha,
+            key.sign_command("zs1reply456", "mkdir /mirrored"),
+        )
+        .unwrap();
+        send(
+            &mut alpha,
+            key.sign_command(
+                "zs1reply456",
+                &format!("rekey key {}", next_key.public_key_hex()),
+            ),
+        )
+        .unwrap();
+
+        let deltas = take_deltas(&alpha);
+        assert_eq!(deltas.len(), 3);
+
+        let deliver = |coordinator: &mut Coordinator, delta: &str| {
+            coordinator.process_and_respond(&Message::new(
+                "client_peer".to_string(),
+                "coordinator".to_string(),
+                delta.to_string(),
+            ))
+        };
+        assert!(beta
+            .process_incoming_message(&Message::new(
+                "client_peer".to_string(),
+                "coordinator".to_string(),
+                deltas[2].clone(),
+            ))
+            .unwrap()
+            .contains("waiting for seq 1"));
+        assert!(!beta.is_user_verified("zs1user123"));
+        let resend_request: Vec<String> = beta
+            .lock_outbound()
+            .items()
+            .filter(|item| item.address == "zs1alpha")
+            .map(|item| item.memo.clone())
+            .collect();
+        assert_eq!(resend_request.len(), 1);
+        deliver(&mut alpha, &resend_request[0]).unwrap();
+        let resent = take_deltas(&alpha);
+        assert_eq!(resent, deltas);
+
+        for delta in &resent[..2] {
+            deliver(&mut beta, delta).unwrap();
+        }
+        assert!(beta.is_user_verified("zs1user123"));
+        assert!(beta.filesystem.resolve_path("/mirrored").is_some());
+        assert_eq!(
+            beta.user_keys.get("zs1user123"),
+            Some(&next_key.public_key_hex())
+        );
+
+        let replay = Message::new(
+            "client_peer".to_string(),
+            "coordinator".to_string(),
+            deltas[1].clone(),
+        );
+        assert!(beta
+            .process_incoming_message(&replay)
+            .unwrap_err()
+            .contains("Stale"));
+        assert!(beta
+            .process_incoming_message(&Message::new(
+                "client_peer".to_string(),
+                "coordinator".to_string(),
+                deltas[1].replace("mirrored", "hijacked"),
+            ))
+            .unwrap_err()
+            .contains("Invalid signature"));
+    }
+
+    #[test]
+    fn test_report_and_hide_content() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        for user in ["zs1admin", "zs1author", "zs1reader"] {
+            coordinator
+                .verified_users
+                .insert(user.to_string(), format!("{}reply", user));
+        }
+        coordinator
+            .filesystem
+            .root
+            .permissions
+            .add_write_permission("zs1author".to_string());
+
+        let run = |coordinator: &mut Coordinator, user: &str, command: &str| {
+            let message = Message::new(
+                user.to_string(),
+                "coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&message)
+        };
 
-        println!("JSON-RPC server starting on {}:{}", bind_address, port);
+        run(&mut coordinator, "zs1author", "mkdir /board").unwrap();
+        run(&mut coordinator, "zs1author", "chmod public /board").unwrap();
+        run(
+            &mut coordinator,
+            "zs1author",
+            "touch /board/spam.txt buy now",
+        )
+        .unwrap();
+        run(
+            &mut coordinator,
+            "zs1author",
+            "chmod public /board/spam.txt",
+        )
+        .unwrap();
+        assert!(run(&mut coordinator, "zs1reader", "ls /board")
+            .unwrap()
+            .contains("spam.txt"));
 
-        warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+        assert_eq!(
+            run(
+                &mut coordinator,
+                "zs1reader",
+                "report /board/spam.txt unsolicited ads"
+            )
+            .unwrap(),
+            "Report #1 filed for /board/spam.txt"
+        );
+        assert!(run(
+            &mut coordinator,
+            "zs1reader",
+            "report /board/spam.txt again"
+        )
+        .is_err());
+        assert!(run(&mut coordinator, "zs1reader", "admin reports").is_err());
+        assert!(run(&mut coordinator, "zs1admin", "admin reports")
+            .unwrap()
+            .contains("#1 /board/spam.txt by zs1reader: unsolicited ads"));
 
-        Ok(())
-    }
+        assert_eq!(
+            run(&mut coordinator, "zs1admin", "admin hide /board/spam.txt").unwrap(),
+            "Hidden: /board/spam.txt (1 reports resolved)"
+        );
+        assert_eq!(
+            run(&mut coordinator, "zs1reader", "ls /board").unwrap(),
+            "(empty directory)"
+        );
+        assert!(run(&mut coordinator, "zs1reader", "cat /board/spam.txt")
+            .unwrap_err()
+            .contains("not found"));
+        assert!(run(&mut coordinator, "zs1author", "stat /board/spam.txt").is_err());
+        assert_eq!(
+            run(&mut coordinator, "zs1admin", "cat /board/spam.txt").unwrap(),
+            "buy now"
+        );
+        assert!(run(&mut coordinator, "zs1admin", "ls /board")
+            .unwrap()
+            .contains("spam.txt"));
+        assert_eq!(
+            run(&mut coordinator, "zs1admin", "admin reports").unwrap(),
+            "No open reports"
+        );
 
-    fn get_coordinator_status(&self) -> Value {
-        json!({
-            "status": "running",
-            "verified_users": self.verified_users.len(),
-            "pending_challenges": self.pending_challenges.len(),
-            "filesystem_nodes": self.count_filesystem_nodes(),
-            "uptime": "unknown",
-            "version": "0.1.0"
-        })
-    }
+        let reloaded = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert!(reloaded.filesystem.is_hidden("/board/spam.txt"));
 
-    fn count_filesystem_nodes(&self) -> usize {
-        1
+        run(&mut coordinator, "zs1admin", "admin unhide /board/spam.txt").unwrap();
+        assert_eq!(
+            run(&mut coordinator, "zs1reader", "cat /board/spam.txt").unwrap(),
+            "buy now"
+        );
+        assert!(run(&mut coordinator, "zs1admin", "admin hide /").is_err());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    fn test_coordinator_registration() {
+    fn test_write_quota_blocks_after_limit() {
+        use crate::quota::QuotaConfig;
+
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -908,20 +9776,61 @@ mod tests {
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        config.quotas = QuotaConfig {
+            writes_per_window: 2,
+            ..QuotaConfig::default()
+        };
+        coordinator.apply_config(&config);
+        for user in ["zs1admin", "zs1user123"] {
+            coordinator
+                .verified_users
+                .insert(user.to_string(), format!("{}reply", user));
+            coordinator
+                .filesystem
+                .root
+                .permissions
+                .add_write_permission(user.to_string());
+        }
 
-        let register_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator456".to_string(),
-            "REGISTER:zs1reply789".to_string(),
-        );
+        let run = |coordinator: &mut Coordinator, user: &str, command: &str| {
+            let message = Message::new(
+                user.to_string(),
+                "coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&message)
+        };
 
-        let result = coordinator.process_incoming_message(&register_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("Registration successful!"));
+        run(&mut coordinator, "zs1user123", "mkdir /a").unwrap();
+        assert!(run(&mut coordinator, "zs1user123", "mkdir /a").is_err());
+        run(&mut coordinator, "zs1user123", "mkdir /b").unwrap();
+        assert!(run(&mut coordinator, "zs1user123", "quota")
+            .unwrap()
+            .contains("write: 0 of 2 left"));
+
+        let err = run(&mut coordinator, "zs1user123", "mkdir /c").unwrap_err();
+        assert!(err.starts_with("Quota exceeded: 2 of 2 write commands used"));
+        assert!(run(&mut coordinator, "zs1user123", "ls /").is_ok());
+        assert!(run(&mut coordinator, "zs1admin", "mkdir /c").is_ok());
+        assert!(run(&mut coordinator, "zs1admin", "mkdir /d").is_ok());
+        assert!(run(&mut coordinator, "zs1admin", "mkdir /e").is_ok());
+
+        coordinator.save_state();
+        let mut restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        restarted.apply_config(&config);
+        assert!(run(&mut restarted, "zs1user123", "mkdir /f")
+            .unwrap_err()
+            .starts_with("Quota exceeded"));
     }
 
     #[test]
-    fn test_authentication_requires_matching_challenge() {
+    fn test_msg_relays_without_revealing_reply_address() {
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -929,79 +9838,181 @@ mod tests {
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
+        let (_, alice_id) = coordinator.record_registration("zs1alice", "zs1alicereply");
+        let (_, bob_id) = coordinator.record_registration("zs1bob", "zs1bobreply");
 
-        let register_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator456".to_string(),
-            "REGISTER:zs1reply789".to_string(),
-        );
-        coordinator.process_incoming_message(&register_msg).unwrap();
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let message = Message::new(
+                "zs1alice".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&message)
+        };
 
-        let mut bad_auth_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator456".to_string(),
-            "AUTH:wrong".to_string(),
+        let queued = run(&mut coordinator, &format!("msg {} meet at /board", bob_id)).unwrap();
+        assert_eq!(queued, format!("Message #1 queued for {}", bob_id));
+        assert!(!queued.contains("zs1bobreply"));
+        assert!(run(&mut coordinator, "msg P000000 hello")
+            .unwrap_err()
+            .starts_with("Unknown recipient"));
+        assert!(run(&mut coordinator, &format!("msg {}", bob_id)).is_err());
+
+        let relayed = coordinator.lock_outbound().items().next().cloned().unwrap();
+        assert_eq!(relayed.address, "zs1bobreply");
+        assert_eq!(
+            signing::verify(
+                &coordinator.signer.public_key_hex(),
+                "zs1bobreply",
+                &relayed.memo
+            )
+            .unwrap(),
+            format!("msg from {}: meet at /board", alice_id)
         );
-        bad_auth_msg.signature = Some("sig".to_string());
-
-        let bad_result = coordinator.process_incoming_message(&bad_auth_msg);
-        assert!(bad_result.is_err());
 
-        let expected = coordinator
-            .pending_challenges
-            .get("zs1user123")
+        assert!(run(&mut coordinator, "inbox")
             .unwrap()
-            .clone();
-        let mut good_auth_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator456".to_string(),
-            format!("AUTH:{}", expected),
+            .contains("[queued]: meet at /board"));
+        coordinator
+            .lock_outbound()
+            .record_results(0, vec![(relayed.id, Ok("tx-relay".to_string()))]);
+        assert_eq!(
+            run(&mut coordinator, "inbox").unwrap(),
+            format!("#1 to {} [delivered]: meet at /board", bob_id)
         );
-        good_auth_msg.signature = Some("sig".to_string());
 
-        let good_result = coordinator.process_incoming_message(&good_auth_msg);
-        assert!(good_result.is_ok());
-        assert!(good_result.unwrap().contains("Authentication successful"));
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(restarted.relays.sent_by("zs1alice", 5).len(), 1);
     }
 
     #[test]
-    fn test_cleanup_expired_sessions_removes_mappings() {
+    fn test_receipts_report_reply_txids() {
         let temp_dir = tempfile::tempdir().unwrap();
+
         let mut coordinator = Coordinator::new(
-            0,
+            3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
+        let send = |coordinator: &mut Coordinator, command: &str, txid: &str| {
+            let mut message = Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                identity.sign_command("zs1reply456", command),
+            );
+            message.txid = Some(txid.to_string());
+            let _ = coordinator.process_and_respond(&message);
+        };
+
+        send(&mut coordinator, "receipts", "tx-in-1");
+        let first = coordinator.lock_outbound().items().next().cloned().unwrap();
+        assert_eq!(first.reply_to.as_deref(), Some("tx-in-1"));
+        assert!(first.memo.starts_with("RE:tx-in-1\nZB1 200 . receipts\n"));
+        assert!(first.memo.contains("No replies sent yet"));
+
+        coordinator
+            .lock_outbound()
+            .record_results(50, vec![(first.id, Ok("tx-out-1".to_string()))]);
+        send(&mut coordinator, "ls /", "tx-in-2");
+        let receipts = |coordinator: &mut Coordinator, args: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                format!("receipts {}", args),
+            ))
+        };
+        assert_eq!(
+            receipts(&mut coordinator, "").unwrap(),
+            format!(
+                "#{} tx-out-1 at 50 for tx-in-1 fee 10000: ZB1 200 . receipts No replies sent yet\n1 replies still queued",
+                first.id
+            )
+        );
+        assert!(receipts(&mut coordinator, "x").is_err());
+    }
 
-        let register_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator456".to_string(),
-            "REGISTER:zs1reply789".to_string(),
+    #[test]
+    fn test_profiles_and_whois() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
         );
-        coordinator.process_incoming_message(&register_msg).unwrap();
+        let (_, alice_id) = coordinator.record_registration("zs1alice", "zs1alicereply");
+        coordinator.record_registration("zs1bob", "zs1bobreply");
+        coordinator
+            .session_mappings
+            .insert("abcdef0123456789".to_string(), "zs1alicereply".to_string());
 
-        let expected = coordinator
-            .pending_challenges
-            .get("zs1user123")
+        let run = |coordinator: &mut Coordinator, user: &str, command: &str| {
+            let message = Message::new(
+                user.to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&message)
+        };
+
+        assert!(run(&mut coordinator, "zs1alice", "profile")
             .unwrap()
-            .clone();
-        let mut auth_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator456".to_string(),
-            format!("AUTH:{}", expected),
+            .ends_with("No profile set"));
+        assert_eq!(
+            run(&mut coordinator, "zs1alice", "profile set name alice").unwrap(),
+            "Profile name updated"
         );
-        auth_msg.signature = Some("sig".to_string());
-        coordinator.process_incoming_message(&auth_msg).unwrap();
+        run(
+            &mut coordinator,
+            "zs1alice",
+            "profile set bio runs the /board",
+        )
+        .unwrap();
+        assert!(run(&mut coordinator, "zs1bob", "profile set name Alice").is_err());
+        assert!(run(&mut coordinator, "zs1bob", "profile set").is_err());
 
-        assert!(!coordinator.get_all_sessions().is_empty());
-        std::thread::sleep(std::time::Duration::from_millis(1100));
-        coordinator.cleanup_expired_sessions();
-        assert!(coordinator.get_all_sessions().is_empty());
-        assert!(!coordinator.is_user_verified("zs1user123"));
+        let expected = format!(
+            "Participant: {}\nName: alice\nBio: runs the /board",
+            alice_id
+        );
+        assert_eq!(
+            run(&mut coordinator, "zs1bob", "whois alice").unwrap(),
+            expected
+        );
+        assert_eq!(
+            run(&mut coordinator, "zs1bob", &format!("whois {}", alice_id)).unwrap(),
+            expected
+        );
+        assert_eq!(
+            run(&mut coordinator, "zs1bob", "whois abcdef0123456789").unwrap(),
+            expected
+        );
+        assert!(run(&mut coordinator, "zs1bob", "whois zs1alice").is_err());
+        assert!(!expected.contains("zs1alice"));
+
+        run(&mut coordinator, "zs1bob", "msg alice hi").unwrap();
+        assert_eq!(
+            coordinator.lock_outbound().items().last().unwrap().address,
+            "zs1alicereply"
+        );
+
+        let restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert_eq!(restarted.profiles.user_by_name("alice"), Some("zs1alice"));
     }
 
     #[test]
-    fn test_ls_command() {
+    fn test_scheduled_jobs_run_maintenance() {
+        use crate::scheduler::SchedulerConfig;
+
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -1009,37 +10020,57 @@ mod tests {
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
-
+        let config = CoordinatorConfig {
+            admin: crate::config::AdminConfig {
+                addresses: vec!["zs1admin".to_string()],
+            },
+            scheduler: SchedulerConfig {
+                session_cleanup_secs: 0,
+                ..SchedulerConfig::default()
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
+        coordinator
+            .verified_users
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
         coordinator
             .filesystem
-            .create_directory("/home", "coordinator".to_string())
+            .create_file("/old.txt", "stale".to_string(), "coordinator".to_string())
             .unwrap();
         coordinator
             .filesystem
-            .create_file(
-                "/home/readme.txt",
-                "Hello!".to_string(),
-                "coordinator".to_string(),
-            )
+            .set_expiry("/old.txt", Some(1))
             .unwrap();
 
-        coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert_eq!(coordinator.run_jobs_at(now), 0);
+        assert!(!temp_dir.path().join(STATE_FILE).exists());
 
-        let ls_msg = Message::new(
-            "zs1user123".to_string(),
+        assert_eq!(coordinator.run_jobs_at(now + 3600), 6);
+        assert!(coordinator.filesystem.resolve_path("/old.txt").is_none());
+        assert!(temp_dir.path().join(STATE_FILE).exists());
+
+        let jobs = Message::new(
+            "zs1admin".to_string(),
             "zs1coordinator".to_string(),
-            "ls /home".to_string(),
+            "admin jobs".to_string(),
         );
-
-        let result = coordinator.handle_authenticated_command(&ls_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("readme.txt"));
+        let summary = coordinator.handle_authenticated_command(&jobs).unwrap();
+        assert!(summary.contains("session_cleanup disabled, never run"));
+        assert!(summary.contains(&format!(
+            "gc every 3600s, last run {}: GC complete: 1 expired nodes",
+            now + 3600
+        )));
+        assert!(summary.contains("state_flush every 60s, last run"));
+        assert!(summary.contains("0 conversations retired"));
     }
 
     #[test]
-    fn test_mkdir_command() {
+    fn test_reload_config_keeps_sessions_and_challenges() {
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -1047,36 +10078,100 @@ mod tests {
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
+        coordinator.apply_config(&CoordinatorConfig::default());
         coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+            .process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                "REGISTER:zs1reply789".to_string(),
+            ))
+            .unwrap();
         coordinator
-            .filesystem
-            .root
-            .permissions
-            .add_write_permission("zs1user123".to_string());
+            .auth_flow
+            .session_manager
+            .create_session("zs1admin".to_string(), "zs1adminreply".to_string());
 
-        let mkdir_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "mkdir /test_dir".to_string(),
-        );
+        let mut config = CoordinatorConfig::default();
+        config.fees.enabled = true;
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        config.rate_limit.burst = 1;
+        config.sessions.timeout_secs = 120;
+        assert!(coordinator.reload_config(&config).is_empty());
 
-        let result = coordinator.handle_authenticated_command(&mkdir_msg);
+        assert!(coordinator.challenges.latest("zs1user123").is_some());
+        assert!(coordinator
+            .auth_flow
+            .session_manager
+            .get_session("zs1admin")
+            .is_some());
+        assert!(coordinator.is_admin("zs1admin"));
+        assert!(coordinator.fees.enabled);
+        assert_eq!(coordinator.auth_flow.session_manager.timeout(), 120);
+
+        config.network.zingo_server = "http://elsewhere:9067".to_string();
+        assert_eq!(coordinator.reload_config(&config), vec!["network"]);
+        assert!(coordinator.reload_config(&config).is_empty());
+    }
 
-        if let Err(e) = &result {
-            eprintln!("mkdir command failed with error: {}", e);
-        }
+    #[test]
+    fn test_sharded_paths_redirect_to_their_coordinator() {
+        let temp_dir = tempfile::tempdir().unwrap();
 
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("Directory created"));
+        let mut coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let mut config = CoordinatorConfig::default();
+        config.fees.enabled = true;
+        config.fees.read_zatoshi = 10;
+        config
+            .shards
+            .routes
+            .insert("/video".to_string(), "zs1shard2".to_string());
+        config
+            .shards
+            .routes
+            .insert("bad".to_string(), "zs1shard3".to_string());
+        coordinator.apply_config(&config);
+
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            coordinator.handle_authenticated_command(&Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            ))
+        };
 
-        let dir = coordinator.filesystem.resolve_path("/test_dir").unwrap();
-        assert_eq!(dir.file_type, crate::filesystem::FileType::Directory);
+        assert_eq!(
+            run(&mut coordinator, "ls /video").unwrap(),
+            "REDIRECT:zs1shard2:/video"
+        );
+        assert_eq!(
+            run(&mut coordinator, "cat /video/clips/a.mp4").unwrap(),
+            "REDIRECT:zs1shard2:/video/clips/a.mp4"
+        );
+        assert_eq!(
+            run(&mut coordinator, "cat \"/video/a b.mp4\"").unwrap(),
+            "REDIRECT:zs1shard2:/video/a b.mp4"
+        );
+        assert_eq!(
+            run(&mut coordinator, "echo hi > /video/x.txt").unwrap(),
+            "REDIRECT:zs1shard2:/video/x.txt"
+        );
+        assert!(!run(&mut coordinator, "echo /video/a > /docs/b.txt")
+            .is_ok_and(|reply| reply.starts_with("REDIRECT")));
+        assert!(run(&mut coordinator, "cp /video/a.mp4 /docs/a.mp4")
+            .unwrap_err()
+            .contains("served by different coordinators"));
+        assert!(run(&mut coordinator, "ls /docs")
+            .unwrap_err()
+            .starts_with("Payment required"));
+        assert_eq!(coordinator.handle_shards_command(), "/video -> zs1shard2");
     }
 
     #[test]
-    fn test_rm_command() {
+    fn test_health_watchdog_holds_replies_while_degraded() {
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -1084,32 +10179,52 @@ mod tests {
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
+        let config = CoordinatorConfig {
+            admin: crate::config::AdminConfig {
+                addresses: vec!["zs1admin".to_string()],
+            },
+            ..CoordinatorConfig::default()
+        };
+        coordinator.apply_config(&config);
         coordinator
             .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
-        coordinator
-            .filesystem
-            .root
-            .permissions
-            .add_write_permission("zs1user123".to_string());
+            .insert("zs1admin".to_string(), "zs1adminreply".to_string());
         coordinator
-            .filesystem
-            .create_file("/test.txt", "content".to_string(), "zs1user123".to_string())
+            .send_response("zs1admin", "queued reply")
             .unwrap();
 
-        let rm_msg = Message::new(
-            "zs1user123".to_string(),
+        let health = Message::new(
+            "zs1admin".to_string(),
             "zs1coordinator".to_string(),
-            "rm /test.txt".to_string(),
+            "admin health".to_string(),
+        );
+        let described = coordinator.handle_authenticated_command(&health).unwrap();
+        assert!(described.starts_with("Health: fail"));
+        assert!(described.contains("zingo: fail, not responding"));
+        assert!(described.ends_with("Outbound: held, 1 replies queued"));
+        assert!(coordinator.flush_outbound() == FlushReport::default());
+        assert_eq!(coordinator.pending_outbound(), 1);
+        assert_eq!(
+            coordinator.status_snapshot().health.as_deref(),
+            Some("fail")
         );
 
-        let result = coordinator.handle_authenticated_command(&rm_msg);
-        assert!(result.is_ok());
-        assert!(coordinator.filesystem.resolve_path("/test.txt").is_none());
+        let recovered = HealthReport::assess(
+            &HealthConfig::default(),
+            &Probe {
+                height: Ok(2_500_000),
+                balance: Ok(1_000_000),
+                last_sync_at: Some(100),
+            },
+            100,
+        );
+        coordinator.apply_health(&recovered);
+        assert_eq!(coordinator.lock_outbound().held(), None);
+        assert_eq!(coordinator.lock_outbound().due(u64::MAX).len(), 1);
     }
 
     #[test]
-    fn test_touch_command() {
+    fn test_admin_backup_restores_on_new_host() {
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -1117,31 +10232,66 @@ mod tests {
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
-        coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        let mut config = CoordinatorConfig::default();
+        config.admin.addresses = vec!["zs1admin".to_string()];
+        coordinator.apply_config(&config);
+        coordinator.record_registration("zs1admin", "zs1adminreply");
+        coordinator.record_registration("zs1bob", "zs1bobreply");
         coordinator
             .filesystem
-            .root
-            .permissions
-            .add_write_permission("zs1user123".to_string());
+            .create_file(
+                "/notes.txt",
+                "keep me".to_string(),
+                "coordinator".to_string(),
+            )
+            .unwrap();
+        coordinator
+            .send_response("zs1bob", "pending reply")
+            .unwrap();
 
-        let touch_msg = Message::new(
-            "zs1user123".to_string(),
+        let backup = Message::new(
+            "zs1admin".to_string(),
             "zs1coordinator".to_string(),
-            "touch /newfile.txt Hello World!".to_string(),
+            "admin backup".to_string(),
         );
+        let response = coordinator.handle_authenticated_command(&backup).unwrap();
+        assert!(response.starts_with("Backup written to backups/backup-"));
+        let archive = std::fs::read_dir(temp_dir.path().join("backups"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
 
-        let result = coordinator.handle_authenticated_command(&touch_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("File created"));
+        let new_host = tempfile::tempdir().unwrap();
+        let manifest = backup::restore(
+            &archive,
+            new_host.path(),
+            &Coordinator::state_files("filesystem.db"),
+        )
+        .unwrap();
+        assert!(manifest.files.contains(&"filesystem.db".to_string()));
 
-        let file = coordinator.filesystem.resolve_path("/newfile.txt").unwrap();
-        assert_eq!(file.content, Some("Hello World!".to_string()));
+        let restored = Coordinator::new(
+            3600,
+            new_host.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        assert!(restored.is_user_verified("zs1bob"));
+        assert_eq!(
+            restored
+                .filesystem
+                .resolve_path("/notes.txt")
+                .unwrap()
+                .content
+                .as_deref(),
+            Some("keep me")
+        );
+        assert_eq!(restored.pending_outbound(), 1);
     }
 
     #[test]
-    fn test_cat_command() {
+    fn test_chat_read_cursor_pagination() {
         let temp_dir = tempfile::tempdir().unwrap();
 
         let mut coordinator = Coordinator::new(
@@ -1153,221 +10303,360 @@ mod tests {
             .verified_users
             .insert("zs1user123".to_string(), "zs1reply456".to_string());
 
-        coordinator
-            .filesystem
-            .create_file(
-                "/readme.txt",
-                "Hello from ZatBoard!".to_string(),
-                "coordinator".to_string(),
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let msg = Message::new(
+                "zs1user123".to_string(),
+                "zs1coordinator".to_string(),
+                command.to_string(),
+            );
+            coordinator.handle_authenticated_command(&msg)
+        };
+
+        run(&mut coordinator, "chat create general").unwrap();
+        run(&mut coordinator, "chat create other").unwrap();
+        for i in 1..=25 {
+            run(
+                &mut coordinator,
+                &format!("chat post general message {}", i),
             )
             .unwrap();
+        }
 
-        let cat_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "cat /readme.txt".to_string(),
-        );
+        let first = run(&mut coordinator, "chat read general 0").unwrap();
+        let lines: Vec<&str> = first.lines().collect();
+        assert_eq!(lines.len(), 21);
+        assert!(lines[0].starts_with("#1 ["));
+        assert!(lines[19].starts_with("#20 ["));
+        let token = lines[20].strip_prefix(cursor::MORE_PREFIX).unwrap();
 
-        let result = coordinator.handle_authenticated_command(&cat_msg);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "Hello from ZatBoard!");
+        let rest = run(
+            &mut coordinator,
+            &format!("chat read general cursor={}", token),
+        )
+        .unwrap();
+        assert_eq!(rest.lines().count(), 5);
+        assert!(rest.starts_with("#21 ["));
+        assert!(!rest.contains(cursor::MORE_PREFIX));
+
+        assert!(run(
+            &mut coordinator,
+            &format!("chat read other cursor={}", token)
+        )
+        .unwrap_err()
+        .starts_with("Invalid cursor"));
+
+        let latest = run(&mut coordinator, "chat read general").unwrap();
+        assert!(latest.starts_with("#6 ["));
+        assert!(!latest.contains(cursor::MORE_PREFIX));
     }
 
     #[test]
-    fn test_echo_command() {
+    fn test_expired_commands_are_skipped_and_priority_orders_the_inbox() {
         let temp_dir = tempfile::tempdir().unwrap();
-
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
-        coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
         coordinator
             .filesystem
             .root
             .permissions
             .add_write_permission("zs1user123".to_string());
+        let compact = |memo: &str, txid: &str, expires_at: Option<u64>, priority: Option<u8>| {
+            let mut command = Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                identity.sign_command("zs1reply456", memo),
+            );
+            command.expires_at = expires_at;
+            command.priority = priority;
+            let mut wire = Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                command.to_compact_memo().unwrap(),
+            );
+            wire.txid = Some(txid.to_string());
+            wire
+        };
 
-        let echo_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "echo \"Hello ZatBoard!\" > /greeting.txt".to_string(),
-        );
-
-        let result = coordinator.handle_authenticated_command(&echo_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("File created"));
-
-        let file = coordinator
-            .filesystem
-            .resolve_path("/greeting.txt")
-            .unwrap();
-        assert_eq!(file.content, Some("Hello ZatBoard!".to_string()));
+        assert!(coordinator
+            .process_and_respond(&compact("touch /stale.txt", "tx-stale", Some(1), None))
+            .unwrap_err()
+            .starts_with("Expired: command expired at 1,"));
+        assert!(coordinator.filesystem.resolve_path("/stale.txt").is_none());
+        assert_eq!(coordinator.metrics().messages_expired, 1);
+        let memo = coordinator
+            .lock_outbound()
+            .items()
+            .last()
+            .unwrap()
+            .memo
+            .clone();
+        let reply =
+            signing::verify(&coordinator.signer.public_key_hex(), "zs1reply456", &memo).unwrap();
+        assert!(reply.contains("ZB1 410"), "{}", reply);
+        assert!(reply.contains("it was not run"));
+
+        coordinator.enqueue_messages(vec![
+            compact("touch /later.txt", "tx-1", None, Some(1)),
+            compact("touch /default.txt", "tx-2", None, None),
+            compact("touch /urgent.txt", "tx-3", Some(u64::MAX), Some(9)),
+        ]);
+        let batch = coordinator.next_message_batch();
+        let txids: Vec<_> = batch.iter().filter_map(|m| m.txid.as_deref()).collect();
+        assert_eq!(txids, vec!["tx-3", "tx-2", "tx-1"]);
+        assert_eq!(batch[0].expires_at, Some(u64::MAX));
+
+        coordinator.process_and_respond(&batch[0]).unwrap();
+        assert!(coordinator.filesystem.resolve_path("/urgent.txt").is_some());
     }
 
     #[test]
-    fn test_echo_update_existing_file() {
+    fn test_memos_with_bad_checksums_are_not_run() {
         let temp_dir = tempfile::tempdir().unwrap();
-
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
-        coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
         coordinator
             .filesystem
             .root
             .permissions
             .add_write_permission("zs1user123".to_string());
+        let send = |coordinator: &mut Coordinator, memo: String, txid: &str| {
+            let mut message =
+                Message::new("zs1user123".to_string(), "coordinator".to_string(), memo);
+            message.txid = Some(txid.to_string());
+            coordinator.process_and_respond(&message)
+        };
 
-        coordinator
-            .filesystem
-            .create_file(
-                "/update.txt",
-                "old content".to_string(),
-                "zs1user123".to_string(),
-            )
-            .unwrap();
-
-        let echo_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "echo \"new content\" > /update.txt".to_string(),
+        let memo = memo_decoder::append_checksum(
+            &identity.sign_command("zs1reply456", "touch /notes.txt hello"),
         );
+        let mangled = memo.replace("hello", "hellp");
+        assert!(send(&mut coordinator, mangled, "tx-bad")
+            .unwrap_err()
+            .starts_with("Corrupted memo"));
+        assert!(coordinator.filesystem.resolve_path("/notes.txt").is_none());
+        let memo_text = coordinator
+            .lock_outbound()
+            .items()
+            .last()
+            .unwrap()
+            .memo
+            .clone();
+        let reply = signing::verify(
+            &coordinator.signer.public_key_hex(),
+            "zs1reply456",
+            &memo_text,
+        )
+        .unwrap();
+        assert!(reply.contains("ZB1 422"), "{}", reply);
 
-        let result = coordinator.handle_authenticated_command(&echo_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("File updated"));
+        send(&mut coordinator, memo, "tx-good").unwrap();
+        assert_eq!(
+            coordinator
+                .filesystem
+                .resolve_path("/notes.txt")
+                .unwrap()
+                .read_content(None)
+                .unwrap(),
+            "hello"
+        );
 
-        let file = coordinator.filesystem.resolve_path("/update.txt").unwrap();
-        assert_eq!(file.content, Some("new content".to_string()));
+        coordinator.checksums.require = true;
+        assert!(send(&mut coordinator, "ls /".to_string(), "tx-bare")
+            .unwrap_err()
+            .contains("checksum missing"));
     }
 
     #[test]
-    fn test_chmod_command() {
+    fn test_inbound_memos_are_sanitized_before_dispatch() {
         let temp_dir = tempfile::tempdir().unwrap();
-
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
-        coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        let identity = sign_in(&mut coordinator, "zs1owner", "zs1ownerreply");
         coordinator
             .filesystem
             .root
             .permissions
-            .add_write_permission("zs1user123".to_string());
-        coordinator
+            .add_write_permission("zs1owner".to_string());
+        let run = |coordinator: &mut Coordinator, command: &str| {
+            let signed =
+                identity.sign_command("zs1ownerreply", &memo_decoder::sanitize_text(command));
+            let marker = signed.rfind(signing::SIGNATURE_MARKER).unwrap();
+            coordinator.process_incoming_message(&Message::new(
+                "zs1owner".to_string(),
+                "zs1coordinator".to_string(),
+                format!("{}{}", command, &signed[marker..]),
+            ))
+        };
+
+        run(&mut coordinator, "touch /cafe\u{301}.txt menu\u{7}\u{202E}").unwrap();
+        let node = coordinator
             .filesystem
-            .create_file("/test.txt", "content".to_string(), "zs1user123".to_string())
+            .resolve_path("/caf\u{e9}.txt")
             .unwrap();
-
-        let chmod_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "chmod private /test.txt".to_string(),
-        );
-
-        let result = coordinator.handle_authenticated_command(&chmod_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("Permissions updated"));
-
-        let file = coordinator.filesystem.resolve_path("/test.txt").unwrap();
-        assert!(!file.permissions.public_read);
+        assert_eq!(node.read_content(None).unwrap(), "menu");
+
+        run(&mut coordinator, "mkdir /pay").unwrap();
+        assert!(run(&mut coordinator, "mkdir /\u{0440}\u{0430}\u{0443}")
+            .unwrap_err()
+            .contains("looks the same as the existing \"pay\""));
+        assert!(run(&mut coordinator, "mkdir /p\u{0430}ypal2")
+            .unwrap_err()
+            .contains("mixes scripts"));
+        assert!(run(&mut coordinator, "touch /a\u{200B}b.txt x")
+            .unwrap_err()
+            .starts_with("Invalid file name"));
+        assert_eq!(coordinator.filesystem.root.list_children().len(), 2);
     }
 
     #[test]
-    fn test_grant_command() {
+    fn test_signed_reply_to_identifies_users_behind_unknown_senders() {
         let temp_dir = tempfile::tempdir().unwrap();
-
+        let identity =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
         coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+            .process_incoming_message(&Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                crate::builder::register_memo("zs1reply789", Some(&identity.public_key_hex())),
+            ))
+            .unwrap();
+        answer_challenge(&mut coordinator, "zs1user123", "zs1reply789", &identity);
         coordinator
             .filesystem
             .root
             .permissions
             .add_write_permission("zs1user123".to_string());
-        coordinator
-            .filesystem
-            .create_file(
-                "/shared.txt",
-                "content".to_string(),
-                "zs1user123".to_string(),
-            )
-            .unwrap();
+        coordinator.save_filesystem().unwrap();
+        drop(coordinator);
 
-        let grant_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "grant read zs1other456 /shared.txt".to_string(),
+        let mut restarted = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let memo = crate::builder::MessageBuilder::new("client_deadbeef", "coordinator")
+            .command("touch /anywhere.txt hi")
+            .signed_by(&identity, "zs1reply789")
+            .build()
+            .plain_memo();
+        assert!(memo.starts_with("REPLY-TO:zs1reply789\n"));
+        let mut message = Message::new(
+            "client_deadbeef".to_string(),
+            "coordinator".to_string(),
+            memo,
+        );
+        message.txid = Some("tx-anywhere".to_string());
+        restarted.process_and_respond(&message).unwrap();
+        assert_eq!(
+            restarted
+                .filesystem
+                .resolve_path("/anywhere.txt")
+                .unwrap()
+                .permissions
+                .owner,
+            "zs1user123"
+        );
+        assert_eq!(
+            restarted.lock_outbound().items().last().unwrap().address,
+            "zs1reply789"
         );
 
-        let result = coordinator.handle_authenticated_command(&grant_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("Read permission granted"));
-
-        let file = coordinator.filesystem.resolve_path("/shared.txt").unwrap();
-        assert!(file.permissions.can_read("zs1other456"));
+        let stranger =
+            identity::IdentityKey::load_or_create(temp_dir.path().join("other.key")).unwrap();
+        let forged = crate::builder::MessageBuilder::new("client_cafe", "coordinator")
+            .command("touch /forged.txt hi")
+            .signed_by(&stranger, "zs1reply789")
+            .build()
+            .plain_memo();
+        assert!(restarted
+            .process_incoming_message(&Message::new(
+                "client_cafe".to_string(),
+                "coordinator".to_string(),
+                forged,
+            ))
+            .unwrap_err()
+            .starts_with("Authentication required"));
     }
 
     #[test]
-    fn test_chat_command() {
+    fn test_acks_tell_seen_done_and_rejected_apart() {
         let temp_dir = tempfile::tempdir().unwrap();
-
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
-        coordinator
-            .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
-
+        coordinator.responses.acks = true;
+        let identity = sign_in(&mut coordinator, "zs1user123", "zs1reply456");
         coordinator
             .filesystem
-            .create_directory("/lobby", "coordinator".to_string())
-            .unwrap();
+            .root
+            .permissions
+            .add_write_permission("zs1user123".to_string());
+        let command = |memo: &str, txid: &str| {
+            let mut message = Message::new(
+                "zs1user123".to_string(),
+                "coordinator".to_string(),
+                identity.sign_command("zs1reply456", memo),
+            );
+            message.txid = Some(txid.to_string());
+            message
+        };
+        let sent = |coordinator: &Coordinator| -> Vec<String> {
+            let public_key = coordinator.signer.public_key_hex();
+            coordinator
+                .lock_outbound()
+                .items()
+                .map(|item| signing::verify(&public_key, "zs1reply456", &item.memo).unwrap())
+                .collect()
+        };
 
-        let chat_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "chat /lobby \"Hello everyone in the lobby!\"".to_string(),
+        coordinator.enqueue_messages(vec![command("touch /a.txt", "tx-touch")]);
+        assert_eq!(sent(&coordinator), vec!["RE:tx-touch\nZBACK seen"]);
+
+        let batch = coordinator.next_message_batch();
+        coordinator.process_and_respond(&batch[0]).unwrap();
+        let memos = sent(&coordinator);
+        assert_eq!(memos[1], "RE:tx-touch\nZBACK done 1");
+        assert!(memos[2].starts_with("RE:tx-touch\nZB1 200"), "{}", memos[2]);
+
+        coordinator.responses.envelope = false;
+        assert!(coordinator
+            .process_and_respond(&command("cat /missing.txt", "tx-cat"))
+            .is_err());
+        assert_eq!(
+            sent(&coordinator).last().unwrap(),
+            "RE:tx-cat\nZBACK nack 404"
         );
 
-        let result = coordinator.handle_authenticated_command(&chat_msg);
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("Message sent to chatroom"));
-
-        let chat_log = coordinator
-            .filesystem
-            .resolve_path("/lobby/.chat_log")
-            .unwrap();
-        assert!(chat_log
-            .content
-            .as_ref()
-            .unwrap()
-            .contains("Hello everyone in the lobby!"));
+        coordinator.responses.acks = false;
+        let before = coordinator.pending_outbound();
+        coordinator.enqueue_messages(vec![command("ls /", "tx-ls")]);
+        assert!(coordinator
+            .process_and_respond(&command("cat /gone.txt", "tx-gone"))
+            .is_err());
+        assert_eq!(coordinator.pending_outbound(), before);
     }
 
     #[test]
-    fn test_chat_history_command() {
+    fn test_keyless_users_are_refused_even_with_a_signature() {
         let temp_dir = tempfile::tempdir().unwrap();
-
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
@@ -1376,72 +10665,168 @@ mod tests {
         coordinator
             .verified_users
             .insert("zs1user123".to_string(), "zs1reply456".to_string());
-        coordinator
-            .verified_users
-            .insert("zs1user789".to_string(), "zs1reply000".to_string());
-
-        coordinator
-            .filesystem
-            .create_directory("/general", "coordinator".to_string())
-            .unwrap();
-
-        let chat1 = Message::new(
+        let mut message = Message::new(
             "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "chat /general \"First message\"".to_string(),
+            "coordinator".to_string(),
+            "ls /".to_string(),
         );
-        let chat2 = Message::new(
-            "zs1user789".to_string(),
-            "zs1coordinator".to_string(),
-            "chat /general \"Second message\"".to_string(),
+        message.signature = Some("sig".to_string());
+        assert!(coordinator
+            .process_incoming_message(&message)
+            .unwrap_err()
+            .starts_with("Authentication required: no public key registered"));
+    }
+
+    #[test]
+    fn test_coordinator_runs_end_to_end_on_a_mock_wallet() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let wallet = MockWallet::new("zs1coordinator456");
+        wallet.set_balance(1_000_000);
+        let mut coordinator = Coordinator::with_wallet(
+            3600,
+            temp_dir.path().to_path_buf(),
+            wallet.clone(),
+            "filesystem.db".to_string(),
+            10,
         );
 
-        coordinator.handle_authenticated_command(&chat1).unwrap();
-        coordinator.handle_authenticated_command(&chat2).unwrap();
+        wallet.receive("zs1user123", 0, "REGISTER:zs1reply789");
+        let messages = coordinator.poll_for_new_messages().unwrap();
+        assert_eq!(messages.len(), 1);
+        for message in &messages {
+            coordinator.process_and_respond(message).unwrap();
+        }
+        assert!(coordinator.poll_for_new_messages().unwrap().is_empty());
+
+        let report = coordinator.flush_outbound();
+        let sent = wallet.sent();
+        assert_eq!((report.sent, report.failed), (sent.len(), 0));
+        assert!(sent.iter().all(|memo| memo.address == "zs1reply789"));
+        assert!(sent.len() > 1 && sent.iter().all(|memo| memo.txid == sent[0].txid));
+        assert!(sent[0].memo.contains("Registration successful!"));
+        assert!(coordinator.is_user_verified("zs1user123"));
+
+        wallet.receive("zs1user123", 0, "REGISTER:zs1reply789");
+        wallet.fail_sends(Some("lightwalletd unreachable"));
+        for message in coordinator.poll_for_new_messages().unwrap() {
+            coordinator.process_and_respond(&message).unwrap();
+        }
+        let report = coordinator.flush_outbound();
+        assert_eq!(report.sent, 0);
+        assert!(report.failed > 0 && coordinator.pending_outbound() > 0);
+        assert_eq!(wallet.sent(), sent);
+    }
 
-        let history_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "history /general".to_string(),
+    #[test]
+    fn test_replies_are_refused_while_the_wallet_cannot_pay_fees() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let wallet = MockWallet::new("zs1coordinator456");
+        wallet.set_balance(outbound::REPLY_FEE_ZATOSHIS - 1);
+        let mut coordinator = Coordinator::with_wallet(
+            3600,
+            temp_dir.path().to_path_buf(),
+            wallet.clone(),
+            "filesystem.db".to_string(),
+            10,
         );
-        let result = coordinator.handle_authenticated_command(&history_msg);
-
-        assert!(result.is_ok());
-        let history = result.unwrap();
-        assert!(history.contains("First message"));
-        assert!(history.contains("Second message"));
-        assert!(history.contains("ser123"));
-        assert!(history.contains("ser789"));
+        coordinator.run_health_check().unwrap();
+
+        wallet.receive("zs1user123", 0, "REGISTER:zs1reply789");
+        let message = coordinator.poll_for_new_messages().unwrap().remove(0);
+        assert!(coordinator
+            .process_and_respond(&message)
+            .unwrap_err()
+            .starts_with("Insufficient funds:"));
+        assert!(!coordinator.is_user_verified("zs1user123"));
+        assert_eq!(coordinator.pending_outbound(), 0);
+        assert_eq!(coordinator.metrics().replies_refused, 1);
+
+        wallet.set_balance(1_000_000);
+        let deferred = coordinator.poll_for_new_messages().unwrap();
+        assert!(coordinator.process_and_respond(&deferred[0]).is_err());
+        coordinator.run_health_check().unwrap();
+        let retried = coordinator.poll_for_new_messages().unwrap();
+        assert_eq!(retried.len(), 1);
+        coordinator.process_and_respond(&retried[0]).unwrap();
+        assert!(coordinator.is_user_verified("zs1user123"));
+        assert!(coordinator.pending_outbound() > 0);
     }
 
     #[test]
-    fn test_chat_permissions() {
+    fn test_receipts_follow_reply_transactions_on_chain() {
         let temp_dir = tempfile::tempdir().unwrap();
+        let wallet = MockWallet::new("zs1coordinator456");
+        wallet.set_balance(1_000_000);
+        let mut coordinator = Coordinator::with_wallet(
+            3600,
+            temp_dir.path().to_path_buf(),
+            wallet.clone(),
+            "filesystem.db".to_string(),
+            10,
+        );
+        coordinator
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply789".to_string());
+        coordinator.send_response("zs1user123", "hello").unwrap();
+        coordinator.flush_outbound();
+        let txid = wallet.sent()[0].txid.clone();
+        let receipts = |coordinator: &mut Coordinator<MockWallet>| {
+            coordinator
+                .handle_receipts_command("zs1user123", "")
+                .unwrap()
+        };
+        assert!(!receipts(&mut coordinator).contains('['));
+
+        assert_eq!(coordinator.refresh_receipts(), Ok(1));
+        assert!(receipts(&mut coordinator).contains(&format!("{} at ", txid)));
+        assert!(receipts(&mut coordinator).contains("[pending] fee 10000: hello"));
+        assert_eq!(coordinator.refresh_receipts(), Ok(0));
+
+        wallet.sync().unwrap();
+        coordinator.refresh_receipts().unwrap();
+        assert!(receipts(&mut coordinator).contains("[mined at 1]"));
+
+        wallet.sync().unwrap();
+        wallet.sync().unwrap();
+        coordinator.refresh_receipts().unwrap();
+        assert!(receipts(&mut coordinator).contains("[confirmed (3)]"));
+        assert!(coordinator.lock_outbound().unconfirmed_txids().is_empty());
+        assert_eq!(coordinator.refresh_receipts(), Ok(0));
+    }
 
+    #[test]
+    fn test_batch_stops_at_a_step_that_needs_confirmation() {
+        let temp_dir = tempfile::tempdir().unwrap();
         let mut coordinator = Coordinator::new(
             3600,
             temp_dir.path().to_path_buf(),
             "http://test:9067".to_string(),
         );
+        let mut config = CoordinatorConfig::default();
+        config.confirmations.enabled = true;
+        coordinator.apply_config(&config);
         coordinator
             .verified_users
-            .insert("zs1user123".to_string(), "zs1reply456".to_string());
-
+            .insert("zs1owner".to_string(), "zs1ownerreply".to_string());
         coordinator
             .filesystem
-            .create_directory("/private", "coordinator".to_string())
+            .root
+            .permissions
+            .add_write_permission("zs1owner".to_string());
+
+        let output = coordinator
+            .handle_authenticated_command(&Message::new(
+                "zs1owner".to_string(),
+                "zs1coordinator".to_string(),
+                "batch mkdir /a; rm /a; mkdir /b".to_string(),
+            ))
             .unwrap();
-        let private_dir = coordinator.filesystem.resolve_path_mut("/private").unwrap();
-        private_dir.permissions.public_read = false;
-
-        let chat_msg = Message::new(
-            "zs1user123".to_string(),
-            "zs1coordinator".to_string(),
-            "chat /private \"Secret message\"".to_string(),
-        );
-
-        let result = coordinator.handle_authenticated_command(&chat_msg);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Permission denied"));
+        assert!(output.contains("> rm /a\nConfirmation required"));
+        assert!(output.ends_with(
+            "(batch stopped at command 2 of 3: confirm it, then resend the remaining 1 commands)"
+        ));
+        assert!(!output.contains("> mkdir /b"));
+        assert!(coordinator.filesystem.resolve_path("/a").is_some());
+        assert!(coordinator.filesystem.resolve_path("/b").is_none());
     }
 }
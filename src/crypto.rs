@@ -0,0 +1,119 @@
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+const NONCE_LEN: usize = 12;
+
+pub type NodeKey = [u8; 32];
+
+pub fn derive_node_key(secret: &str, owner: &str) -> NodeKey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zatboard_node_key");
+    hasher.update(secret.as_bytes());
+    hasher.update(owner.as_bytes());
+    hasher.finalize().into()
+}
+
+pub fn encrypt_text(key: &NodeKey, plaintext: &str) -> Result<String, String> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(to_hex(&sealed))
+}
+
+pub fn decrypt_text(key: &NodeKey, sealed_hex: &str) -> Result<String, String> {
+    let sealed = from_hex(sealed_hex)?;
+    if sealed.len() < NONCE_LEN {
+        return Err("Ciphertext too short".to_string());
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Decryption failed: wrong key or corrupted data".to_string())?;
+
+    String::from_utf8(plaintext).map_err(|e| format!("Decrypted content is not UTF-8: {}", e))
+}
+
+pub fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    to_hex(&mac.finalize().into_bytes())
+}
+
+pub fn verify_hmac_sha256_hex(key: &[u8], data: &[u8], expected_hex: &str) -> bool {
+    let expected = match from_hex(expected_hex) {
+        Ok(bytes) => bytes,
+        Err(_) => return false,
+    };
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.verify_slice(&expected).is_ok()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(value: &str) -> Result<Vec<u8>, String> {
+    if !value.len().is_multiple_of(2) {
+        return Err("Invalid hex: odd length".to_string());
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            value
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("Invalid hex at offset {}", i))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let key = derive_node_key("secret", "zs1owner123");
+        let sealed = encrypt_text(&key, "private notes").unwrap();
+
+        assert_ne!(sealed, "private notes");
+        assert_eq!(decrypt_text(&key, &sealed).unwrap(), "private notes");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key_fails() {
+        let key = derive_node_key("secret", "zs1owner123");
+        let other = derive_node_key("secret", "zs1other456");
+        let sealed = encrypt_text(&key, "private notes").unwrap();
+
+        assert!(decrypt_text(&other, &sealed).is_err());
+        assert!(decrypt_text(&key, "zz").is_err());
+    }
+
+    #[test]
+    fn test_hmac_sign_and_verify() {
+        let tag = hmac_sha256_hex(b"key", b"payload");
+        assert_eq!(tag.len(), 64);
+        assert!(verify_hmac_sha256_hex(b"key", b"payload", &tag));
+        assert!(!verify_hmac_sha256_hex(b"other", b"payload", &tag));
+        assert!(!verify_hmac_sha256_hex(b"key", b"payload", "zz"));
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        assert_eq!(to_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(from_hex("000fff").unwrap(), vec![0, 15, 255]);
+        assert!(from_hex("abc").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::crypto;
+use sha2::{Digest, Sha256};
+
+pub const MORE_PREFIX: &str = "...more cursor=";
+const CURSOR_ARG: &str = "cursor=";
+const TAG_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<u64>,
+}
+
+fn tag(scope: &str, position: u64) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(scope.as_bytes());
+    hasher.update(b":");
+    hasher.update(position.to_string().as_bytes());
+    hasher.update(b"zatboard_cursor");
+    crypto::to_hex(&hasher.finalize())[..TAG_LEN].to_string()
+}
+
+pub fn encode(scope: &str, position: u64) -> String {
+    format!("{:x}{}", position, tag(scope, position))
+}
+
+pub fn decode(scope: &str, token: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid cursor: {}", token);
+    if token.len() <= TAG_LEN || !token.is_ascii() {
+        return Err(invalid());
+    }
+
+    let (position, expected) = token.split_at(token.len() - TAG_LEN);
+    let position = u64::from_str_radix(position, 16).map_err(|_| invalid())?;
+    if tag(scope, position) != expected {
+        return Err(invalid());
+    }
+    Ok(position)
+}
+
+pub fn strip_arg(arg: &str) -> Option<&str> {
+    arg.trim().strip_prefix(CURSOR_ARG)
+}
+
+pub fn more_line(scope: &str, next: u64) -> String {
+    format!("{}{}", MORE_PREFIX, encode(scope, next))
+}
+
+pub fn page_after<T>(
+    items: impl IntoIterator<Item = (u64, T)>,
+    after: u64,
+    limit: usize,
+) -> Page<T> {
+    let mut page = Vec::new();
+    let mut last = None;
+    let mut more = false;
+
+    for (position, item) in items.into_iter().filter(|(position, _)| *position > after) {
+        if page.len() == limit {
+            more = true;
+            break;
+        }
+        last = Some(position);
+        page.push(item);
+    }
+
+    Page {
+        items: page,
+        next: if more { last } else { None },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip_is_scoped() {
+        let token = encode("chat:lobby", 42);
+        assert_eq!(decode("chat:lobby", &token).unwrap(), 42);
+        assert!(decode("chat:other", &token).is_err());
+        assert!(decode("chat:lobby", "zz").is_err());
+        assert!(decode("chat:lobby", "2a0000").is_err());
+        assert_eq!(strip_arg(" cursor=abc123"), Some("abc123"));
+        assert_eq!(strip_arg("12"), None);
+    }
+
+    #[test]
+    fn test_page_after_resumes_without_gaps() {
+        let items: Vec<(u64, u64)> = (1..=5).map(|id| (id, id * 10)).collect();
+
+        let first = page_after(items.clone(), 0, 2);
+        assert_eq!(first.items, vec![10, 20]);
+        assert_eq!(first.next, Some(2));
+
+        let second = page_after(items.clone(), first.next.unwrap(), 2);
+        assert_eq!(second.items, vec![30, 40]);
+
+        let last = page_after(items, second.next.unwrap(), 2);
+        assert_eq!(last.items, vec![50]);
+        assert_eq!(last.next, None);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::config::ConfigWatcher;
+use crate::coordinator::Coordinator;
+use crate::message::Message;
+use crate::outbound::{self, FlushReport, OutboundQueue};
+use crate::wallet::WalletBackend;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+const IDLE_POLL_DELAY: Duration = Duration::from_secs(5);
+const SEND_IDLE_DELAY: Duration = Duration::from_secs(1);
+const METRICS_INTERVAL: Duration = Duration::from_secs(15);
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(5);
+const CONFIG_RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+const PROCESS_INTERVAL: Duration = Duration::from_millis(250);
+
+enum PollEvent {
+    Synced {
+        height: Option<u64>,
+        messages: Vec<Message>,
+    },
+    Failed(String),
+}
+
+enum Step {
+    Poll(PollEvent),
+    Flushed(FlushReport),
+    Metrics,
+    Jobs,
+    Process,
+    Reload,
+    Stop,
+}
+
+async fn off_reactor<W, T, F>(mut coordinator: Coordinator<W>, work: F) -> (Coordinator<W>, T)
+where
+    W: WalletBackend,
+    T: Send + 'static,
+    F: FnOnce(&mut Coordinator<W>) -> T + Send + 'static,
+{
+    let handle = tokio::task::spawn_blocking(move || {
+        let output = work(&mut coordinator);
+        (coordinator, output)
+    });
+    match handle.await {
+        Ok(done) => done,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+async fn sleep_or_stop(duration: Duration, stop: &mut watch::Receiver<bool>) -> bool {
+    if *stop.borrow() {
+        return true;
+    }
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => *stop.borrow(),
+        _ = stop.changed() => true,
+    }
+}
+
+fn spawn_poller<W: WalletBackend>(
+    wallet: W,
+    polling_interval: Duration,
+    events: mpsc::Sender<PollEvent>,
+    mut stop: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let poll_wallet = wallet.clone();
+            let polled = tokio::task::spawn_blocking(move || {
+                let messages = poll_wallet.poll_once()?;
+                Ok((poll_wallet.height().ok(), messages))
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("Poll task failed: {}", e)));
+
+            let delay = match polled {
+                Ok((height, messages)) => {
+                    let delay = if messages.is_empty() {
+                        IDLE_POLL_DELAY
+                    } else {
+                        polling_interval
+                    };
+                    if events
+                        .send(PollEvent::Synced { height, messages })
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                    delay
+                }
+                Err(e) => {
+                    if events.send(PollEvent::Failed(e)).await.is_err() {
+                        break;
+                    }
+                    IDLE_POLL_DELAY
+                }
+            };
+
+            if sleep_or_stop(delay, &mut stop).await {
+                break;
+            }
+        }
+    })
+}
+
+fn spawn_sender<W: WalletBackend>(
+    wallet: W,
+    queue: Arc<Mutex<OutboundQueue>>,
+    reports: mpsc::Sender<FlushReport>,
+    mut stop: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let (batch, max_fee, max_outputs) = {
+                let queue = queue
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                (queue.due(now), queue.max_fee(), queue.max_outputs())
+            };
+
+            if !batch.is_empty() {
+                let send_wallet = wallet.clone();
+                let results = tokio::task::spawn_blocking(move || {
+                    OutboundQueue::send_grouped(&batch, max_outputs, |items| {
+                        outbound::deliver(&send_wallet, items, max_fee)
+                    })
+                })
+                .await
+                .unwrap_or_default();
+
+                let report = queue
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .record_results(now, results);
+                if reports.send(report).await.is_err() {
+                    break;
+                }
+            }
+
+            if sleep_or_stop(SEND_IDLE_DELAY, &mut stop).await {
+                break;
+            }
+        }
+    })
+}
+
+fn reload_config<W: WalletBackend>(coordinator: &mut Coordinator<W>, watcher: &mut ConfigWatcher) {
+    match watcher.poll() {
+        Some(Ok(config)) => {
+            let restart_required = coordinator.reload_config(&config);
+            println!("🔄 Configuration reloaded");
+            if !restart_required.is_empty() {
+                eprintln!(
+                    "⚠️  Changes to [{}] take effect after a restart",
+                    restart_required.join("], [")
+                );
+            }
+        }
+        Some(Err(e)) => eprintln!("⚠️  Keeping previous configuration: {}", e),
+        None => {}
+    }
+}
+
+fn process_batch<W: WalletBackend>(coordinator: &mut Coordinator<W>) {
+    for message in coordinator.next_message_batch() {
+        match coordinator.process_and_respond(&message) {
+            Ok(()) => println!("📤 Message processed successfully"),
+            Err(e) => eprintln!("❌ Error processing message: {}", e),
+        }
+    }
+}
+
+pub async fn run<W: WalletBackend>(
+    coordinator: Coordinator<W>,
+    polling_interval: Duration,
+    stop: watch::Receiver<bool>,
+) -> Result<(), String> {
+    run_with_reload(coordinator, polling_interval, stop, None).await
+}
+
+pub async fn run_with_reload<W: WalletBackend>(
+    mut coordinator: Coordinator<W>,
+    polling_interval: Duration,
+    mut stop: watch::Receiver<bool>,
+    mut config_watcher: Option<ConfigWatcher>,
+) -> Result<(), String> {
+    let (event_tx, mut event_rx) = mpsc::channel(16);
+    let (report_tx, mut report_rx) = mpsc::channel(16);
+
+    let poller = spawn_poller(
+        coordinator.wallet(),
+        polling_interval,
+        event_tx,
+        stop.clone(),
+    );
+    let sender = spawn_sender(
+        coordinator.wallet(),
+        coordinator.outbound_queue(),
+        report_tx,
+        stop.clone(),
+    );
+
+    let mut metrics_tick = tokio::time::interval(METRICS_INTERVAL);
+    let mut scheduler_tick = tokio::time::interval(SCHEDULER_INTERVAL);
+    let mut reload_tick = tokio::time::interval(CONFIG_RELOAD_INTERVAL);
+    let mut process_tick = tokio::time::interval(PROCESS_INTERVAL);
+    coordinator.publish_status();
+
+    loop {
+        let pending = coordinator.pending_messages();
+        let step = tokio::select! {
+            Some(event) = event_rx.recv() => Step::Poll(event),
+            Some(report) = report_rx.recv() => Step::Flushed(report),
+            _ = metrics_tick.tick() => Step::Metrics,
+            _ = scheduler_tick.tick() => Step::Jobs,
+            _ = process_tick.tick(), if pending > 0 => Step::Process,
+            _ = reload_tick.tick(), if config_watcher.is_some() => Step::Reload,
+            _ = stop.changed() => Step::Stop,
+        };
+        match step {
+            Step::Poll(PollEvent::Synced { height, messages }) => {
+                coordinator.record_sync(height);
+                coordinator.enqueue_messages(messages);
+                (coordinator, ()) = off_reactor(coordinator, process_batch).await;
+            }
+            Step::Poll(PollEvent::Failed(e)) => {
+                eprintln!("⚠️  Error polling messages: {}", e);
+                coordinator.record_poll_error(&e);
+            }
+            Step::Flushed(report) => coordinator.record_flush(&report),
+            Step::Metrics => {
+                if let Err(e) = coordinator.export_metrics() {
+                    eprintln!("⚠️  Failed to export metrics: {}", e);
+                }
+            }
+            Step::Jobs => {
+                (coordinator, _) = off_reactor(coordinator, Coordinator::run_due_jobs).await;
+            }
+            Step::Process => {
+                (coordinator, ()) = off_reactor(coordinator, process_batch).await;
+            }
+            Step::Reload => {
+                if let Some(watcher) = config_watcher.as_mut() {
+                    reload_config(&mut coordinator, watcher);
+                }
+            }
+            Step::Stop => break,
+        }
+        coordinator.publish_status();
+        if *stop.borrow() {
+            break;
+        }
+    }
+
+    let _ = poller.await;
+    let _ = sender.await;
+    while let Ok(report) = report_rx.try_recv() {
+        coordinator.record_flush(&report);
+    }
+
+    off_reactor(coordinator, Coordinator::shutdown).await.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_run_stops_on_signal_and_flushes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(
+            3600,
+            temp_dir.path().to_path_buf(),
+            "http://test:9067".to_string(),
+        );
+        let status = coordinator.status_handle();
+
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let handle = tokio::spawn(run(coordinator, Duration::from_millis(10), stop_rx));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        stop_tx.send(true).unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(10), handle)
+            .await
+            .expect("daemon did not stop")
+            .unwrap();
+        assert!(result.is_ok());
+        assert!(temp_dir.path().join("coordinator_state.json").exists());
+        assert!(temp_dir.path().join("metrics.prom").exists());
+
+        let snapshot = status.lock().unwrap().clone();
+        assert!(snapshot.started_at > 0);
+        assert_eq!(snapshot.outbound_depth, 0);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+const MAX_EDIT_SEARCH: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    let mut ids = HashMap::new();
+    let mut intern = |line: &str| {
+        let next = ids.len();
+        *ids.entry(line.to_string()).or_insert(next)
+    };
+    let a: Vec<usize> = old_lines.iter().map(|line| intern(line)).collect();
+    let b: Vec<usize> = new_lines.iter().map(|line| intern(line)).collect();
+
+    let bound = ((a.len() + b.len()).div_ceil(2) + 1).min(MAX_EDIT_SEARCH);
+    let mut diagonals = (Diagonals::new(bound), Diagonals::new(bound));
+    let mut ops = Vec::with_capacity(a.len().max(b.len()));
+    conquer(&a, &b, &mut diagonals, &mut ops);
+    for run in ops.chunk_by_mut(|x, y| (*x == Op::Same) == (*y == Op::Same)) {
+        run.sort_by_key(|op| *op == Op::Added);
+    }
+
+    let (mut i, mut j) = (0, 0);
+    ops.into_iter()
+        .map(|op| match op {
+            Op::Same => {
+                i += 1;
+                j += 1;
+                DiffLine::Same(old_lines[i - 1].to_string())
+            }
+            Op::Removed => {
+                i += 1;
+                DiffLine::Removed(old_lines[i - 1].to_string())
+            }
+            Op::Added => {
+                j += 1;
+                DiffLine::Added(new_lines[j - 1].to_string())
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+struct Diagonals {
+    offset: isize,
+    furthest: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(bound: usize) -> Self {
+        Diagonals {
+            offset: bound as isize,
+            furthest: vec![0; 2 * bound + 1],
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.furthest[(k + self.offset) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.furthest[(k + self.offset) as usize] = x;
+    }
+}
+
+fn conquer(a: &[usize], b: &[usize], diagonals: &mut (Diagonals, Diagonals), ops: &mut Vec<Op>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    ops.extend(std::iter::repeat_n(Op::Same, prefix));
+
+    match middle_snake(a, b, diagonals) {
+        Some((x, y)) if (x, y) != (0, 0) && (x, y) != (a.len(), b.len()) => {
+            conquer(&a[..x], &b[..y], diagonals, ops);
+            conquer(&a[x..], &b[y..], diagonals, ops);
+        }
+        _ => {
+            ops.extend(std::iter::repeat_n(Op::Removed, a.len()));
+            ops.extend(std::iter::repeat_n(Op::Added, b.len()));
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Same, suffix));
+}
+
+// Myers' linear-space search for the middle of a shortest edit script. Past
+// MAX_EDIT_SEARCH edits it gives up and the caller replaces the whole range.
+fn middle_snake(
+    a: &[usize],
+    b: &[usize],
+    (forward, backward): &mut (Diagonals, Diagonals),
+) -> Option<(usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    if n == 0 || m == 0 {
+        return None;
+    }
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    forward.set(1, 0);
+    backward.set(1, 0);
+
+    let limit = ((n + m).div_ceil(2) + 1).min(MAX_EDIT_SEARCH) as isize;
+    for d in 0..limit {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1)) {
+                forward.get(k + 1)
+            } else {
+                forward.get(k - 1) + 1
+            };
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+            if x < n && y < m {
+                x += a[x..]
+                    .iter()
+                    .zip(&b[y..])
+                    .take_while(|(p, q)| p == q)
+                    .count();
+            }
+            forward.set(k, x);
+            if odd && (k - delta).abs() < d && forward.get(k) + backward.get(delta - k) >= n {
+                return Some(start);
+            }
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                backward.get(k + 1)
+            } else {
+                backward.get(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let run = a[..n - x]
+                    .iter()
+                    .rev()
+                    .zip(b[..m - y].iter().rev())
+                    .take_while(|(p, q)| p == q)
+                    .count();
+                x += run;
+                y += run;
+            }
+            backward.set(k, x);
+            if !odd && (k - delta).abs() <= d && backward.get(k) + forward.get(delta - k) >= n {
+                return Some((n - x, m - y));
+            }
+        }
+    }
+    None
+}
+
+pub fn format_changes(diff: &[DiffLine]) -> String {
+    let changes: Vec<String> = diff
+        .iter()
+        .filter_map(|line| match line {
+            DiffLine::Same(_) => None,
+            DiffLine::Added(text) => Some(format!("+{}", text)),
+            DiffLine::Removed(text) => Some(format!("-{}", text)),
+        })
+        .collect();
+
+    if changes.is_empty() {
+        "(no changes)".to_string()
+    } else {
+        changes.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_identical() {
+        let diff = diff_lines("a\nb", "a\nb");
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Same("a".to_string()),
+                DiffLine::Same("b".to_string())
+            ]
+        );
+        assert_eq!(format_changes(&diff), "(no changes)");
+    }
+
+    #[test]
+    fn test_diff_added_and_removed_lines() {
+        let diff = diff_lines("one\ntwo\nthree", "one\n2\nthree\nfour");
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Same("one".to_string()),
+                DiffLine::Removed("two".to_string()),
+                DiffLine::Added("2".to_string()),
+                DiffLine::Same("three".to_string()),
+                DiffLine::Added("four".to_string()),
+            ]
+        );
+        assert_eq!(format_changes(&diff), "-two\n+2\n+four");
+    }
+
+    #[test]
+    fn test_diff_from_empty() {
+        let diff = diff_lines("", "hello");
+        assert_eq!(diff, vec![DiffLine::Added("hello".to_string())]);
+    }
+
+    #[test]
+    fn test_diff_large_revisions_in_linear_space() {
+        let old: Vec<String> = (0..20_000).map(|i| format!("line {}", i)).collect();
+        let mut new = old.clone();
+        for i in (0..new.len()).step_by(1000) {
+            new[i] = format!("changed {}", i);
+        }
+        new.insert(10_500, "inserted".to_string());
+
+        let diff = diff_lines(&old.join("\n"), &new.join("\n"));
+        let count = |kind: fn(&DiffLine) -> bool| diff.iter().filter(|line| kind(line)).count();
+        assert_eq!(count(|line| matches!(line, DiffLine::Removed(_))), 20);
+        assert_eq!(count(|line| matches!(line, DiffLine::Added(_))), 21);
+        assert_eq!(count(|line| matches!(line, DiffLine::Same(_))), 19_980);
+        assert!(format_changes(&diff).starts_with("-line 0\n+changed 0\n-line 1000\n"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::crypto::NodeKey;
+use crate::identity;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::SigningKey;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+pub const ENCRYPTED_PREFIX: &str = "ZBENC:";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+pub fn is_encrypted(memo: &str) -> bool {
+    memo.starts_with(ENCRYPTED_PREFIX)
+}
+
+pub fn plaintext_budget(memo_len: usize) -> usize {
+    (memo_len.saturating_sub(ENCRYPTED_PREFIX.len()) * 3 / 4).saturating_sub(NONCE_LEN + TAG_LEN)
+}
+
+pub struct E2eKey {
+    secret: StaticSecret,
+}
+
+impl E2eKey {
+    pub fn from_signing_key(key: &SigningKey) -> Self {
+        E2eKey {
+            secret: StaticSecret::from(key.to_scalar_bytes()),
+        }
+    }
+
+    fn session_key(&self, peer_public_key_hex: &str) -> Result<NodeKey, String> {
+        let peer = identity::parse_public_key(peer_public_key_hex)?;
+        let shared = self
+            .secret
+            .diffie_hellman(&PublicKey::from(peer.to_montgomery().to_bytes()));
+        if !shared.was_contributory() {
+            return Err("Invalid public key: cannot agree on an encryption key".to_string());
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(b"zatboard_e2e");
+        hasher.update(shared.as_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    pub fn seal(&self, peer_public_key_hex: &str, plaintext: &str) -> Result<String, String> {
+        let key = self.session_key(peer_public_key_hex)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| "Encryption failed".to_string())?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!(
+            "{}{}",
+            ENCRYPTED_PREFIX,
+            URL_SAFE_NO_PAD.encode(sealed)
+        ))
+    }
+
+    pub fn open(&self, peer_public_key_hex: &str, memo: &str) -> Result<String, String> {
+        let encoded = memo
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .ok_or_else(|| "Invalid encrypted memo: missing ZBENC: prefix".to_string())?;
+        let sealed = URL_SAFE_NO_PAD
+            .decode(encoded.trim_end())
+            .map_err(|e| format!("Invalid encrypted memo: {}", e))?;
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err("Invalid encrypted memo: ciphertext too short".to_string());
+        }
+
+        let key = self.session_key(peer_public_key_hex)?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Invalid encrypted memo: wrong key or corrupted data".to_string())?;
+        String::from_utf8(plaintext)
+            .map_err(|e| format!("Invalid encrypted memo: not UTF-8: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto;
+
+    fn keypair() -> (E2eKey, String) {
+        let key = SigningKey::generate(&mut OsRng);
+        (
+            E2eKey::from_signing_key(&key),
+            crypto::to_hex(key.verifying_key().as_bytes()),
+        )
+    }
+
+    #[test]
+    fn test_sealed_memos_open_only_between_the_two_parties() {
+        let (alice, alice_public) = keypair();
+        let (coordinator, coordinator_public) = keypair();
+        let (_, mallory_public) = keypair();
+
+        let sealed = alice.seal(&coordinator_public, "cat /secret.txt").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("secret"));
+        assert_eq!(
+            coordinator.open(&alice_public, &sealed).unwrap(),
+            "cat /secret.txt"
+        );
+        assert_eq!(
+            alice.open(&coordinator_public, &sealed).unwrap(),
+            "cat /secret.txt"
+        );
+        assert!(coordinator.open(&mallory_public, &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered.pop();
+        tampered.push(if sealed.ends_with('A') { 'B' } else { 'A' });
+        assert!(coordinator.open(&alice_public, &tampered).is_err());
+        assert!(coordinator.open(&alice_public, "ZBENC:AAAA").is_err());
+    }
+
+    #[test]
+    fn test_plaintext_budget_keeps_sealed_memo_within_limit() {
+        let (alice, _) = keypair();
+        let (_, coordinator_public) = keypair();
+        for limit in [100, 447, 512] {
+            let plaintext = "x".repeat(plaintext_budget(limit));
+            let sealed = alice.seal(&coordinator_public, &plaintext).unwrap();
+            assert!(sealed.len() <= limit, "{} > {}", sealed.len(), limit);
+        }
+    }
+}
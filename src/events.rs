@@ -0,0 +1,203 @@
+use crate::watch::WatchEvent;
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    MessageProcessed {
+        sender: String,
+        txid: Option<String>,
+        memo: String,
+        result: Result<String, String>,
+    },
+    UserRegistered {
+        user: String,
+        reply_address: String,
+    },
+    AuthSucceeded {
+        user: String,
+    },
+    AuthFailed {
+        user: String,
+        reason: String,
+    },
+    ChallengeIssued {
+        user: String,
+        reply_address: String,
+    },
+    SessionExpired {
+        user: String,
+        session_id: String,
+    },
+    CommandCompleted {
+        user: String,
+        command: String,
+        elapsed_micros: u64,
+        ok: bool,
+    },
+    CommandRejected {
+        user: String,
+        command: String,
+        reason: String,
+    },
+    CommandDelegated {
+        user: String,
+        issuer: String,
+        command: String,
+        path: String,
+        ok: bool,
+    },
+    FileCreated {
+        actor: String,
+        path: String,
+    },
+    FileModified {
+        actor: String,
+        path: String,
+    },
+    FileRemoved {
+        actor: String,
+        path: String,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::MessageProcessed { .. } => "message_processed",
+            Event::UserRegistered { .. } => "user_registered",
+            Event::AuthSucceeded { .. } => "auth_succeeded",
+            Event::AuthFailed { .. } => "auth_failed",
+            Event::ChallengeIssued { .. } => "challenge_issued",
+            Event::SessionExpired { .. } => "session_expired",
+            Event::CommandCompleted { .. } => "command_completed",
+            Event::CommandRejected { .. } => "command_rejected",
+            Event::CommandDelegated { .. } => "command_delegated",
+            Event::FileCreated { .. } => "file_created",
+            Event::FileModified { .. } => "file_modified",
+            Event::FileRemoved { .. } => "file_removed",
+        }
+    }
+
+    pub fn file_change(actor: &str, path: &str, change: WatchEvent) -> Event {
+        let (actor, path) = (actor.to_string(), path.to_string());
+        match change {
+            WatchEvent::Created => Event::FileCreated { actor, path },
+            WatchEvent::Modified => Event::FileModified { actor, path },
+            WatchEvent::Removed => Event::FileRemoved { actor, path },
+        }
+    }
+
+    pub fn as_file_change(&self) -> Option<(&str, &str, WatchEvent)> {
+        match self {
+            Event::FileCreated { actor, path } => Some((actor, path, WatchEvent::Created)),
+            Event::FileModified { actor, path } => Some((actor, path, WatchEvent::Modified)),
+            Event::FileRemoved { actor, path } => Some((actor, path, WatchEvent::Removed)),
+            _ => None,
+        }
+    }
+}
+
+pub trait Subscriber {
+    fn on_event(&mut self, event: &Event);
+}
+
+pub trait EventHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn on_event(&self, event: &Event);
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    hooks: Vec<Arc<dyn EventHook>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, hook: Arc<dyn EventHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn hook_names(&self) -> Vec<&str> {
+        self.hooks.iter().map(|hook| hook.name()).collect()
+    }
+
+    pub fn publish(&self, event: &Event) {
+        for hook in &self.hooks {
+            hook.on_event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Recorder {
+        seen: Mutex<Vec<String>>,
+    }
+
+    impl EventHook for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn on_event(&self, event: &Event) {
+            self.seen
+                .lock()
+                .unwrap()
+                .push(serde_json::to_string(event).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_hooks_receive_published_events_as_json() {
+        let recorder = Arc::new(Recorder {
+            seen: Mutex::new(Vec::new()),
+        });
+        let mut bus = EventBus::new();
+        bus.subscribe(recorder.clone());
+        assert_eq!(bus.hook_names(), vec!["recorder"]);
+
+        bus.publish(&Event::UserRegistered {
+            user: "zs1alice".to_string(),
+            reply_address: "zs1reply".to_string(),
+        });
+        bus.publish(&Event::file_change(
+            "zs1alice",
+            "/a.txt",
+            WatchEvent::Removed,
+        ));
+
+        assert_eq!(
+            *recorder.seen.lock().unwrap(),
+            vec![
+                "{\"event\":\"user_registered\",\"user\":\"zs1alice\",\"reply_address\":\"zs1reply\"}",
+                "{\"event\":\"file_removed\",\"actor\":\"zs1alice\",\"path\":\"/a.txt\"}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_events_map_to_watch_changes() {
+        let event = Event::file_change("zs1bob", "/docs/b.txt", WatchEvent::Modified);
+        assert_eq!(event.name(), "file_modified");
+        assert_eq!(
+            event.as_file_change(),
+            Some(("zs1bob", "/docs/b.txt", WatchEvent::Modified))
+        );
+        assert_eq!(
+            Event::AuthSucceeded {
+                user: "zs1bob".to_string()
+            }
+            .as_file_change(),
+            None
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use crate::crypto;
+use crate::memo_decoder::MAX_MEMO_SIZE;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+pub const FEDERATION_PREFIX: &str = "FED:";
+pub const RESEND_LOG_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PeerConfig {
+    pub node_id: String,
+    pub address: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FederationConfig {
+    pub enabled: bool,
+    pub node_id: String,
+    pub peers: Vec<PeerConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "t")]
+pub enum FederationOp {
+    #[serde(rename = "reg")]
+    Register {
+        user: String,
+        reply: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+    #[serde(rename = "rekey")]
+    Rekey {
+        user: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reply: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+    #[serde(rename = "cmd")]
+    Command { user: String, command: String },
+    #[serde(rename = "resend")]
+    Resend { from: u64 },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Received {
+    pub origin: String,
+    pub ops: Vec<FederationOp>,
+    pub replies: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Delta {
+    o: String,
+    s: u64,
+    op: FederationOp,
+    sig: String,
+}
+
+fn signing_payload(origin: &str, seq: u64, op: &FederationOp) -> String {
+    let op_json = serde_json::to_string(op).unwrap_or_default();
+    format!("{}:{}:{}", origin, seq, op_json)
+}
+
+fn delta_memo(
+    peer: &PeerConfig,
+    origin: &str,
+    seq: u64,
+    op: &FederationOp,
+) -> Result<String, String> {
+    let payload = signing_payload(origin, seq, op);
+    let delta = Delta {
+        o: origin.to_string(),
+        s: seq,
+        op: op.clone(),
+        sig: crypto::hmac_sha256_hex(peer.secret.as_bytes(), payload.as_bytes()),
+    };
+    let json = serde_json::to_string(&delta)
+        .map_err(|e| format!("Failed to encode federation delta: {}", e))?;
+    let memo = format!("{}{}", FEDERATION_PREFIX, json);
+    if memo.len() > MAX_MEMO_SIZE {
+        return Err(format!(
+            "Federation delta too large for one memo ({} bytes)",
+            memo.len()
+        ));
+    }
+    Ok(memo)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Federation {
+    config: FederationConfig,
+    next_seq: u64,
+    last_seen: HashMap<String, u64>,
+    log: BTreeMap<u64, FederationOp>,
+    buffered: HashMap<String, BTreeMap<u64, FederationOp>>,
+    requested: HashMap<String, u64>,
+}
+
+impl Federation {
+    pub fn new(
+        next_seq: u64,
+        last_seen: HashMap<String, u64>,
+        log: BTreeMap<u64, FederationOp>,
+    ) -> Self {
+        Federation {
+            config: FederationConfig::default(),
+            next_seq,
+            last_seen,
+            log,
+            buffered: HashMap::new(),
+            requested: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: FederationConfig) {
+        self.config = config;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && !self.config.node_id.is_empty() && !self.config.peers.is_empty()
+    }
+
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    pub fn last_seen(&self) -> &HashMap<String, u64> {
+        &self.last_seen
+    }
+
+    pub fn log(&self) -> &BTreeMap<u64, FederationOp> {
+        &self.log
+    }
+
+    fn peer(&self, node_id: &str) -> Result<&PeerConfig, String> {
+        self.config
+            .peers
+            .iter()
+            .find(|peer| peer.node_id == node_id)
+            .ok_or_else(|| format!("Unknown federation peer: {}", node_id))
+    }
+
+    pub fn outgoing(&mut self, op: &FederationOp) -> Result<Vec<(String, String)>, String> {
+        if !self.is_enabled() {
+            return Ok(Vec::new());
+        }
+
+        let seq = self.next_seq + 1;
+        let mut memos = Vec::new();
+        for peer in &self.config.peers {
+            let memo = delta_memo(peer, &self.config.node_id, seq, op)?;
+            memos.push((peer.address.clone(), memo));
+        }
+
+        self.next_seq = seq;
+        self.log.insert(seq, op.clone());
+        while self.log.len() > RESEND_LOG_SIZE {
+            self.log.pop_first();
+        }
+        Ok(memos)
+    }
+
+    fn resend(&self, peer: &PeerConfig, from: u64) -> Result<Vec<(String, String)>, String> {
+        if let Some((&oldest, _)) = self.log.first_key_value() {
+            if from < oldest {
+                eprintln!(
+                    "⚠️  Peer {} asked for deltas from seq {} but only {}.. are kept; it must resync",
+                    peer.node_id, from, oldest
+                );
+            }
+        }
+        self.log
+            .range(from..)
+            .map(|(seq, op)| {
+                delta_memo(peer, &self.config.node_id, *seq, op)
+                    .map(|memo| (peer.address.clone(), memo))
+            })
+            .collect()
+    }
+
+    fn request_resend(&mut self, peer: &PeerConfig, from: u64) -> Vec<(String, String)> {
+        if self.requested.get(&peer.node_id) == Some(&from) {
+            return Vec::new();
+        }
+        self.requested.insert(peer.node_id.clone(), from);
+        delta_memo(
+            peer,
+            &self.config.node_id,
+            0,
+            &FederationOp::Resend { from },
+        )
+        .map(|memo| vec![(peer.address.clone(), memo)])
+        .unwrap_or_default()
+    }
+
+    pub fn accept(&mut self, memo: &str) -> Result<Received, String> {
+        if !self.config.enabled {
+            return Err("Federation is disabled".to_string());
+        }
+
+        let json = memo
+            .strip_prefix(FEDERATION_PREFIX)
+            .ok_or_else(|| "Not a federation memo".to_string())?;
+        let delta: Delta =
+            serde_json::from_str(json).map_err(|e| format!("Invalid federation delta: {}", e))?;
+
+        let peer = self.peer(&delta.o)?.clone();
+        let payload = signing_payload(&delta.o, delta.s, &delta.op);
+        if !crypto::verify_hmac_sha256_hex(peer.secret.as_bytes(), payload.as_bytes(), &delta.sig) {
+            return Err(format!("Invalid signature on delta from {}", delta.o));
+        }
+
+        if let FederationOp::Resend { from } = delta.op {
+            return Ok(Received {
+                replies: self.resend(&peer, from)?,
+                origin: delta.o,
+                ops: Vec::new(),
+            });
+        }
+
+        let last = self.last_seen.get(&delta.o).copied().unwrap_or(0);
+        if delta.s <= last {
+            return Err(format!(
+                "Stale federation delta from {} (seq {} <= {})",
+                delta.o, delta.s, last
+            ));
+        }
+
+        let buffered = self.buffered.entry(delta.o.clone()).or_default();
+        buffered.insert(delta.s, delta.op);
+        let mut ops = Vec::new();
+        let mut next = last + 1;
+        while let Some(op) = buffered.remove(&next) {
+            ops.push(op);
+            next += 1;
+        }
+        let waiting = !buffered.is_empty();
+        if waiting && buffered.len() > RESEND_LOG_SIZE {
+            buffered.pop_last();
+        }
+        self.last_seen.insert(delta.o.clone(), next - 1);
+
+        let replies = if waiting {
+            self.request_resend(&peer, next)
+        } else {
+            self.requested.remove(&delta.o);
+            Vec::new()
+        };
+        Ok(Received {
+            origin: delta.o,
+            ops,
+            replies,
+        })
+    }
+
+    pub fn waiting_for(&self, node_id: &str) -> Option<u64> {
+        self.buffered
+            .get(node_id)
+            .filter(|buffered| !buffered.is_empty())
+            .map(|_| self.last_seen.get(node_id).copied().unwrap_or(0) + 1)
+    }
+
+    pub fn summary(&self) -> String {
+        if !self.config.enabled {
+            return "Federation disabled".to_string();
+        }
+
+        let mut lines = vec![format!(
+            "Node: {} (seq {})",
+            self.config.node_id, self.next_seq
+        )];
+        for peer in &self.config.peers {
+            let mut line = format!(
+                "Peer {} at {} last_seen={}",
+                peer.node_id,
+                peer.address,
+                self.last_seen.get(&peer.node_id).copied().unwrap_or(0)
+            );
+            if let Some(missing) = self.waiting_for(&peer.node_id) {
+                line.push_str(&format!(" waiting_for={}", missing));
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, peer_id: &str) -> Federation {
+        let mut federation = Federation::new(0, HashMap::new(), BTreeMap::new());
+        federation.set_config(FederationConfig {
+            enabled: true,
+            node_id: id.to_string(),
+            peers: vec![PeerConfig {
+                node_id: peer_id.to_string(),
+                address: format!("zs1{}", peer_id),
+                secret: "shared-secret".to_string(),
+            }],
+        });
+        federation
+    }
+
+    #[test]
+    fn test_deltas_roundtrip_between_peers() {
+        let mut alpha = node("alpha", "beta");
+        let mut beta = node("beta", "alpha");
+
+        let op = FederationOp::Command {
+            user: "zs1user".to_string(),
+            command: "mkdir /shared".to_string(),
+        };
+        let memos = alpha.outgoing(&op).unwrap();
+        assert_eq!(memos.len(), 1);
+        assert_eq!(memos[0].0, "zs1beta");
+        assert_eq!(alpha.next_seq(), 1);
+
+        let received = beta.accept(&memos[0].1).unwrap();
+        assert_eq!(received.origin, "alpha");
+        assert_eq!(received.ops, vec![op]);
+        assert!(received.replies.is_empty());
+        assert!(beta.accept(&memos[0].1).unwrap_err().contains("Stale"));
+    }
+
+    #[test]
+    fn test_gaps_are_buffered_and_resent() {
+        let mut alpha = node("alpha", "beta");
+        let mut beta = node("beta", "alpha");
+        let command = |n: u32| FederationOp::Command {
+            user: "zs1user".to_string(),
+            command: format!("mkdir /d{}", n),
+        };
+        let deltas: Vec<String> = (1..=3)
+            .map(|n| alpha.outgoing(&command(n)).unwrap().remove(0).1)
+            .collect();
+
+        let early = beta.accept(&deltas[2]).unwrap();
+        assert!(early.ops.is_empty());
+        assert_eq!(early.replies.len(), 1);
+        assert_eq!(early.replies[0].0, "zs1alpha");
+        assert_eq!(beta.waiting_for("alpha"), Some(1));
+        assert!(beta.accept(&deltas[1]).unwrap().replies.is_empty());
+
+        let resent = alpha.accept(&early.replies[0].1).unwrap();
+        assert!(resent.ops.is_empty());
+        assert_eq!(resent.replies.len(), 3);
+        assert_eq!(
+            resent.replies[0],
+            ("zs1beta".to_string(), deltas[0].clone())
+        );
+
+        let caught_up = beta.accept(&resent.replies[0].1).unwrap();
+        assert_eq!(caught_up.ops, vec![command(1), command(2), command(3)]);
+        assert_eq!(beta.last_seen()["alpha"], 3);
+        assert_eq!(beta.waiting_for("alpha"), None);
+        assert!(beta
+            .accept(&resent.replies[2].1)
+            .unwrap_err()
+            .contains("Stale"));
+        assert_eq!(alpha.log().len(), 3);
+    }
+
+    #[test]
+    fn test_tampered_or_unknown_deltas_are_rejected() {
+        let mut alpha = node("alpha", "beta");
+        let mut beta = node("beta", "alpha");
+        let mut mallory = node("mallory", "beta");
+
+        let op = FederationOp::Register {
+            user: "zs1user".to_string(),
+            reply: "zs1reply".to_string(),
+            key: Some("ab".repeat(32)),
+        };
+        let memo = alpha.outgoing(&op).unwrap().remove(0).1;
+        let tampered = memo.replace("zs1reply", "zs1evil");
+        assert!(beta
+            .accept(&tampered)
+            .unwrap_err()
+            .contains("Invalid signature"));
+
+        let forged = mallory.outgoing(&op).unwrap().remove(0).1;
+        assert!(beta
+            .accept(&forged)
+            .unwrap_err()
+            .contains("Unknown federation peer"));
+
+        let oversized = FederationOp::Command {
+            user: "zs1user".to_string(),
+            command: "x".repeat(600),
+        };
+        assert!(alpha.outgoing(&oversized).is_err());
+        assert_eq!(alpha.next_seq(), 1);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::memo_decoder::MAX_MEMO_SIZE;
+
+pub const MARGINAL_FEE_ZATOSHIS: u64 = 5_000;
+pub const GRACE_ACTIONS: u64 = 2;
+
+pub const fn memo_outputs(memo_len: usize) -> u64 {
+    let outputs = memo_len.div_ceil(MAX_MEMO_SIZE) as u64;
+    if outputs == 0 {
+        1
+    } else {
+        outputs
+    }
+}
+
+pub const fn estimate_fee(memo_len: usize, outputs: usize) -> u64 {
+    let memo_outputs = memo_outputs(memo_len);
+    let outputs = if (outputs as u64) > memo_outputs {
+        outputs as u64
+    } else {
+        memo_outputs
+    };
+    let actions = outputs + 1;
+    let actions = if actions > GRACE_ACTIONS {
+        actions
+    } else {
+        GRACE_ACTIONS
+    };
+    MARGINAL_FEE_ZATOSHIS * actions
+}
+
+pub fn estimate_memos_fee(memo_lens: &[usize]) -> u64 {
+    let outputs: u64 = memo_lens.iter().map(|len| memo_outputs(*len)).sum();
+    estimate_fee(0, outputs as usize)
+}
+
+pub fn check_fee(memo_lens: &[usize], max_fee: Option<u64>) -> Result<u64, String> {
+    let fee = estimate_memos_fee(memo_lens);
+    match max_fee {
+        Some(max_fee) if fee > max_fee => Err(format!(
+            "Fee too high: sending needs about {} zatoshis but the limit is {}",
+            fee, max_fee
+        )),
+        _ => Ok(fee),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_fee_follows_zip317_actions() {
+        assert_eq!(estimate_fee(0, 1), 10_000);
+        assert_eq!(estimate_fee(MAX_MEMO_SIZE, 1), 10_000);
+        assert_eq!(estimate_fee(MAX_MEMO_SIZE + 1, 1), 15_000);
+        assert_eq!(estimate_fee(10, 3), 20_000);
+        assert_eq!(memo_outputs(0), 1);
+        assert_eq!(memo_outputs(3 * MAX_MEMO_SIZE), 3);
+
+        assert_eq!(estimate_memos_fee(&[]), 10_000);
+        assert_eq!(estimate_memos_fee(&[10, 20]), 15_000);
+        assert_eq!(estimate_memos_fee(&[MAX_MEMO_SIZE + 1, 20]), 20_000);
+    }
+
+    #[test]
+    fn test_check_fee_enforces_the_limit() {
+        assert_eq!(check_fee(&[100], None), Ok(10_000));
+        assert_eq!(check_fee(&[100], Some(10_000)), Ok(10_000));
+        assert_eq!(
+            check_fee(&[100, 100], Some(10_000)).unwrap_err(),
+            "Fee too high: sending needs about 15000 zatoshis but the limit is 10000"
+        );
+    }
+}
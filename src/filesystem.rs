@@ -1,7 +1,22 @@
+use crate::crypto::{self, NodeKey};
+use crate::diff;
+use crate::memo_decoder;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+const MAX_REVISIONS: usize = 20;
+const ARCHIVE_MANIFEST: &str = ".zatboard_manifest.json";
+
+fn sha256_hex(content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum FileType {
@@ -14,11 +29,40 @@ pub struct FileNode {
     pub name: String,
     pub file_type: FileType,
     pub content: Option<String>,
-    pub children: HashMap<String, FileNode>,
+    pub children: HashMap<String, Arc<FileNode>>,
     pub permissions: Permissions,
     pub created_by: String,
     pub created_at: u64,
     pub modified_at: u64,
+    #[serde(default = "initial_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub revisions: Vec<FileRevision>,
+    #[serde(default)]
+    pub frozen: bool,
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default)]
+    pub link_id: Option<u64>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub plain_size: Option<usize>,
+    #[serde(default)]
+    pub plain_checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileRevision {
+    pub version: u32,
+    pub content: Arc<str>,
+    pub modified_at: u64,
+}
+
+fn initial_version() -> u32 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +93,10 @@ impl Permissions {
         self.public_write || self.owner == user || self.write_users.contains(&user.to_string())
     }
 
+    pub fn can_decrypt(&self, user: &str) -> bool {
+        self.owner == user || self.read_users.contains(&user.to_string())
+    }
+
     pub fn add_read_permission(&mut self, user: String) {
         if !self.read_users.contains(&user) {
             self.read_users.push(user);
@@ -62,6 +110,38 @@ impl Permissions {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub user: String,
+    pub issuer: Option<String>,
+}
+
+impl Principal {
+    pub fn can_read(&self, permissions: &Permissions) -> bool {
+        permissions.can_read(&self.user)
+            || self
+                .issuer
+                .as_deref()
+                .is_some_and(|issuer| permissions.can_read(issuer))
+    }
+
+    pub fn can_write(&self, permissions: &Permissions) -> bool {
+        permissions.can_write(&self.user)
+            || self
+                .issuer
+                .as_deref()
+                .is_some_and(|issuer| permissions.can_write(issuer))
+    }
+
+    pub fn can_decrypt(&self, permissions: &Permissions) -> bool {
+        permissions.can_decrypt(&self.user)
+            || self
+                .issuer
+                .as_deref()
+                .is_some_and(|issuer| permissions.can_decrypt(issuer))
+    }
+}
+
 impl FileNode {
     pub fn new_directory(name: String, owner: String) -> Self {
         let now = std::time::SystemTime::now()
@@ -78,6 +158,15 @@ impl FileNode {
             created_by: owner,
             created_at: now,
             modified_at: now,
+            version: initial_version(),
+            revisions: Vec::new(),
+            frozen: false,
+            encrypted: false,
+            link_id: None,
+            expires_at: None,
+            hidden: false,
+            plain_size: None,
+            plain_checksum: None,
         }
     }
 
@@ -96,6 +185,15 @@ impl FileNode {
             created_by: owner,
             created_at: now,
             modified_at: now,
+            version: initial_version(),
+            revisions: Vec::new(),
+            frozen: false,
+            encrypted: false,
+            link_id: None,
+            expires_at: None,
+            hidden: false,
+            plain_size: None,
+            plain_checksum: None,
         }
     }
 
@@ -104,7 +202,7 @@ impl FileNode {
             return Err("Cannot add children to a file".to_string());
         }
 
-        self.children.insert(child.name.clone(), child);
+        self.children.insert(child.name.clone(), Arc::new(child));
         self.modified_at = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -113,11 +211,11 @@ impl FileNode {
     }
 
     pub fn get_child(&self, name: &str) -> Option<&FileNode> {
-        self.children.get(name)
+        self.children.get(name).map(Arc::as_ref)
     }
 
     pub fn get_child_mut(&mut self, name: &str) -> Option<&mut FileNode> {
-        self.children.get_mut(name)
+        self.children.get_mut(name).map(Arc::make_mut)
     }
 
     pub fn list_children(&self) -> Vec<String> {
@@ -136,29 +234,393 @@ impl FileNode {
         items
     }
 
+    pub fn checksum(&self) -> Option<String> {
+        if self.file_type != FileType::File {
+            return None;
+        }
+
+        if self.encrypted {
+            return self.plain_checksum.clone();
+        }
+        Some(sha256_hex(self.content.as_deref().unwrap_or("")))
+    }
+
+    pub fn size(&self) -> usize {
+        self.plain_size
+            .filter(|_| self.encrypted)
+            .unwrap_or_else(|| self.stored_size())
+    }
+
+    fn stored_size(&self) -> usize {
+        self.content.as_ref().map(|c| c.len()).unwrap_or(0)
+    }
+
+    fn record_plaintext(&mut self, content: &str) {
+        self.plain_size = Some(content.len());
+        self.plain_checksum = Some(sha256_hex(content));
+    }
+
+    pub fn list_children_page(&self, offset: usize, limit: usize) -> (Vec<String>, bool) {
+        self.list_children_with(&ListOptions::default(), offset, limit)
+    }
+
+    pub fn list_children_with(
+        &self,
+        options: &ListOptions,
+        offset: usize,
+        limit: usize,
+    ) -> (Vec<String>, bool) {
+        let mut entries: Vec<&FileNode> = self
+            .children
+            .values()
+            .map(Arc::as_ref)
+            .filter(|child| match options.filter {
+                EntryFilter::All => true,
+                EntryFilter::FilesOnly => child.file_type == FileType::File,
+                EntryFilter::DirsOnly => child.file_type == FileType::Directory,
+            })
+            .filter(|child| {
+                options
+                    .owner
+                    .as_ref()
+                    .is_none_or(|owner| &child.permissions.owner == owner)
+            })
+            .filter(|child| options.include_hidden || !child.hidden)
+            .collect();
+
+        entries.sort_by(|a, b| {
+            let ordering = match options.sort_by {
+                SortKey::Name => std::cmp::Ordering::Equal,
+                SortKey::Modified => a.modified_at.cmp(&b.modified_at),
+                SortKey::Size => a.size().cmp(&b.size()),
+                SortKey::Owner => a.permissions.owner.cmp(&b.permissions.owner),
+            };
+            ordering.then_with(|| a.name.cmp(&b.name))
+        });
+        if options.reverse {
+            entries.reverse();
+        }
+
+        let more = entries.len() > offset.saturating_add(limit);
+        let page = entries
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|child| match child.file_type {
+                FileType::Directory => format!("{}/", child.name),
+                FileType::File => child.name.clone(),
+            })
+            .collect();
+        (page, more)
+    }
+
     pub fn update_content(&mut self, content: String) -> Result<(), String> {
         if self.file_type != FileType::File {
             return Err("Cannot set content on a directory".to_string());
         }
 
+        self.revisions.push(FileRevision {
+            version: self.version,
+            content: Arc::from(self.content.take().unwrap_or_default()),
+            modified_at: self.modified_at,
+        });
+        if self.revisions.len() > MAX_REVISIONS {
+            let excess = self.revisions.len() - MAX_REVISIONS;
+            self.revisions.drain(..excess);
+        }
+
         self.content = Some(content);
+        self.plain_size = None;
+        self.plain_checksum = None;
+        self.version += 1;
         self.modified_at = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
         Ok(())
     }
+
+    pub fn read_content(&self, key: Option<&NodeKey>) -> Result<String, String> {
+        let stored = self.content.as_deref().unwrap_or("");
+        self.open_stored(stored, key)
+    }
+
+    pub fn read_range(
+        &self,
+        offset: usize,
+        len: usize,
+        key: Option<&NodeKey>,
+    ) -> Result<(String, bool), String> {
+        if self.file_type != FileType::File {
+            return Err("Not a file".to_string());
+        }
+
+        let content = self.read_content(key)?;
+        let total = content.chars().count();
+        let chunk: String = content.chars().skip(offset).take(len).collect();
+        Ok((chunk, total > offset.saturating_add(len)))
+    }
+
+    pub fn head_lines(&self, count: usize, key: Option<&NodeKey>) -> Result<String, String> {
+        if self.file_type != FileType::File {
+            return Err("Not a file".to_string());
+        }
+
+        let content = self.read_content(key)?;
+        Ok(content
+            .lines()
+            .take(count)
+            .collect::<Vec<&str>>()
+            .join("\n"))
+    }
+
+    pub fn tail_lines(&self, count: usize, key: Option<&NodeKey>) -> Result<String, String> {
+        if self.file_type != FileType::File {
+            return Err("Not a file".to_string());
+        }
+
+        let content = self.read_content(key)?;
+        let lines: Vec<&str> = content.lines().collect();
+        let start = lines.len().saturating_sub(count);
+        Ok(lines[start..].join("\n"))
+    }
+
+    pub fn read_revision(&self, version: u32, key: Option<&NodeKey>) -> Result<String, String> {
+        let stored = self
+            .revision_content(version)
+            .ok_or_else(|| format!("Version {} not found (latest is {})", version, self.version))?;
+        self.open_stored(stored, key)
+    }
+
+    fn open_stored(&self, stored: &str, key: Option<&NodeKey>) -> Result<String, String> {
+        if !self.encrypted || stored.is_empty() {
+            return Ok(stored.to_string());
+        }
+
+        let key = key.ok_or_else(|| "File is encrypted: key required".to_string())?;
+        crypto::decrypt_text(key, stored)
+    }
+
+    pub fn store_content(&mut self, content: String, key: Option<&NodeKey>) -> Result<(), String> {
+        if !self.encrypted {
+            return self.update_content(content);
+        }
+
+        let key = key.ok_or_else(|| "File is encrypted: key required".to_string())?;
+        let sealed = crypto::encrypt_text(key, &content)?;
+        self.update_content(sealed)?;
+        self.record_plaintext(&content);
+        Ok(())
+    }
+
+    pub fn encrypt_content(&mut self, key: &NodeKey) -> Result<(), String> {
+        if self.file_type != FileType::File {
+            return Err("Only files can be encrypted".to_string());
+        }
+        if self.encrypted {
+            return Err("File is already encrypted".to_string());
+        }
+
+        let content = self.content.clone().unwrap_or_default();
+        let sealed = crypto::encrypt_text(key, &content)?;
+        let mut sealed_revisions = Vec::with_capacity(self.revisions.len());
+        for revision in &self.revisions {
+            let mut sealed_revision = revision.clone();
+            sealed_revision.content = Arc::from(crypto::encrypt_text(key, &revision.content)?);
+            sealed_revisions.push(sealed_revision);
+        }
+
+        self.content = Some(sealed);
+        self.revisions = sealed_revisions;
+        self.encrypted = true;
+        self.record_plaintext(&content);
+        Ok(())
+    }
+
+    pub fn decrypt_content(&mut self, key: &NodeKey) -> Result<(), String> {
+        if !self.encrypted {
+            return Err("File is not encrypted".to_string());
+        }
+
+        let content = self.read_content(Some(key))?;
+        let mut opened_revisions = Vec::with_capacity(self.revisions.len());
+        for revision in &self.revisions {
+            let mut opened = revision.clone();
+            opened.content = Arc::from(self.open_stored(&revision.content, Some(key))?);
+            opened_revisions.push(opened);
+        }
+
+        self.content = Some(content);
+        self.revisions = opened_revisions;
+        self.encrypted = false;
+        self.plain_size = None;
+        self.plain_checksum = None;
+        Ok(())
+    }
+
+    fn stored_bytes(&self) -> usize {
+        self.stored_size()
+            + self
+                .revisions
+                .iter()
+                .map(|revision| revision.content.len())
+                .sum::<usize>()
+            + self
+                .children
+                .values()
+                .map(|child| child.stored_bytes())
+                .sum::<usize>()
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn needs_gc(&self, now: u64) -> bool {
+        !self.frozen
+            && (self.is_expired(now)
+                || self.revisions.len() > MAX_REVISIONS
+                || self.children.values().any(|child| child.needs_gc(now)))
+    }
+
+    pub fn revision_content(&self, version: u32) -> Option<&str> {
+        if version == self.version {
+            return Some(self.content.as_deref().unwrap_or(""));
+        }
+
+        self.revisions
+            .iter()
+            .find(|revision| revision.version == version)
+            .map(|revision| revision.content.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Modified,
+    Size,
+    Owner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EntryFilter {
+    #[default]
+    All,
+    FilesOnly,
+    DirsOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ListOptions {
+    pub sort_by: SortKey,
+    pub reverse: bool,
+    pub filter: EntryFilter,
+    pub owner: Option<String>,
+    pub include_hidden: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcReport {
+    pub expired_nodes: usize,
+    pub pruned_revisions: usize,
+    pub dissolved_links: usize,
+    pub reclaimed_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ArchiveEntry {
+    path: String,
+    permissions: Permissions,
+    created_by: String,
+    created_at: u64,
+    modified_at: u64,
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    plain_size: Option<usize>,
+    #[serde(default)]
+    plain_checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FsLimits {
+    pub max_depth: usize,
+    pub max_children: usize,
+    pub max_name_length: usize,
+}
+
+impl Default for FsLimits {
+    fn default() -> Self {
+        FsLimits {
+            max_depth: 32,
+            max_children: 1000,
+            max_name_length: 255,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileSystem {
     pub root: FileNode,
+    pub limits: FsLimits,
+    pub delegation: Option<Principal>,
+}
+
+pub struct Walk<'a> {
+    stack: Vec<(String, &'a FileNode)>,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (String, &'a FileNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+
+        let mut names: Vec<&String> = node.children.keys().collect();
+        names.sort();
+        for name in names.into_iter().rev() {
+            self.stack.push((
+                join_path(&path, name),
+                node.children[name.as_str()].as_ref(),
+            ));
+        }
+
+        Some((path, node))
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    format!("/{}", parts.join("/"))
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", parent, name)
+    }
 }
 
 impl FileSystem {
     pub fn new(owner: String) -> Self {
         FileSystem {
             root: FileNode::new_directory("/".to_string(), owner),
+            limits: FsLimits::default(),
+            delegation: None,
+        }
+    }
+
+    pub fn principal(&self, user: &str) -> Principal {
+        match &self.delegation {
+            Some(delegation) if delegation.user == user => delegation.clone(),
+            _ => Principal {
+                user: user.to_string(),
+                issuer: None,
+            },
         }
     }
 
@@ -198,95 +660,205 @@ impl FileSystem {
         Some(current)
     }
 
-    pub fn create_directory(&mut self, path: &str, owner: String) -> Result<(), String> {
-        let (parent_path, dir_name) = self.split_path(path)?;
-
-        let parent = self
-            .resolve_path_mut(&parent_path)
-            .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+    pub fn walk(&self, path: &str) -> Walk<'_> {
+        let stack = match self.resolve_path(path) {
+            Some(node) => vec![(normalize_path(path), node)],
+            None => Vec::new(),
+        };
+        Walk { stack }
+    }
 
-        if !parent.permissions.can_write(&owner) {
-            return Err("Permission denied: cannot write to parent directory".to_string());
+    pub fn diff(
+        &self,
+        path: &str,
+        from: u32,
+        to: u32,
+        user: &str,
+        key: Option<&NodeKey>,
+    ) -> Result<String, String> {
+        let node = self
+            .resolve_path(path)
+            .ok_or_else(|| format!("File not found: {}", path))?;
+
+        if !self.principal(user).can_read(&node.permissions) {
+            return Err("Permission denied: cannot read file".to_string());
         }
 
-        if parent.children.contains_key(&dir_name) {
-            return Err("Directory already exists".to_string());
+        if node.file_type != FileType::File {
+            return Err("Not a file".to_string());
         }
 
-        let new_dir = FileNode::new_directory(dir_name.clone(), owner);
-        parent.add_child(new_dir)?;
+        let old = node.read_revision(from, key)?;
+        let new = node.read_revision(to, key)?;
 
-        Ok(())
+        Ok(diff::format_changes(&diff::diff_lines(&old, &new)))
     }
 
-    pub fn create_file(
-        &mut self,
-        path: &str,
-        content: String,
-        owner: String,
-    ) -> Result<(), String> {
-        let (parent_path, file_name) = self.split_path(path)?;
+    pub fn disk_usage(&self, path: &str, user: &str) -> Result<Vec<(String, usize)>, String> {
+        let node = self
+            .resolve_path(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
 
-        let parent = self
-            .resolve_path_mut(&parent_path)
-            .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+        let principal = self.principal(user);
+        if !principal.can_read(&node.permissions) {
+            return Err("Permission denied: cannot read path".to_string());
+        }
 
-        if !parent.permissions.can_write(&owner) {
-            return Err("Permission denied: cannot write to parent directory".to_string());
+        let mut usage = Vec::new();
+        Self::collect_usage(node, &normalize_path(path), &principal, &mut usage);
+        Ok(usage)
+    }
+
+    fn collect_usage(
+        node: &FileNode,
+        path: &str,
+        principal: &Principal,
+        usage: &mut Vec<(String, usize)>,
+    ) -> usize {
+        if node.file_type == FileType::File {
+            return node.size();
         }
 
-        let new_file = FileNode::new_file(file_name.clone(), content, owner);
-        parent.add_child(new_file)?;
+        let mut names: Vec<&String> = node.children.keys().collect();
+        names.sort();
+
+        let mut total = 0;
+        for name in names {
+            let child = &node.children[name];
+            if principal.can_read(&child.permissions) {
+                total += Self::collect_usage(child, &join_path(path, name), principal, usage);
+            }
+        }
+        usage.push((path.to_string(), total));
+        total
+    }
 
+    pub fn set_frozen(&mut self, path: &str, frozen: bool) -> Result<(), String> {
+        let node = self
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        node.frozen = frozen;
         Ok(())
     }
 
-    fn split_path(&self, path: &str) -> Result<(String, String), String> {
-        let path = path.trim_end_matches('/');
-        if path == "/" {
-            return Err("Cannot create root directory".to_string());
+    pub fn set_hidden(&mut self, path: &str, hidden: bool) -> Result<(), String> {
+        if normalize_path(path) == "/" {
+            return Err("Cannot hide the root directory".to_string());
         }
+        let node = self
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        node.hidden = hidden;
+        Ok(())
+    }
 
-        let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
-        if parts.is_empty() || parts[parts.len() - 1].is_empty() {
-            return Err("Invalid path".to_string());
+    pub fn is_hidden(&self, path: &str) -> bool {
+        let mut current = &self.root;
+        for component in normalize_path(path).split('/').filter(|c| !c.is_empty()) {
+            match current.get_child(component) {
+                Some(child) if child.hidden => return true,
+                Some(child) => current = child,
+                None => return false,
+            }
         }
+        false
+    }
 
-        let file_name = parts[parts.len() - 1].to_string();
-        let parent_path = if parts.len() == 1 {
-            "/".to_string()
-        } else {
-            "/".to_string() + &parts[0..parts.len() - 1].join("/")
-        };
+    pub fn hidden_paths(&self) -> Vec<String> {
+        self.walk("/")
+            .filter(|(_, node)| node.hidden)
+            .map(|(path, _)| path)
+            .collect()
+    }
 
-        Ok((parent_path, file_name))
+    pub fn frozen_paths(&self) -> Vec<String> {
+        self.walk("/")
+            .filter(|(_, node)| node.frozen)
+            .map(|(path, _)| path)
+            .collect()
     }
 
-    pub fn remove(&mut self, path: &str, user: &str) -> Result<(), String> {
-        if path == "/" {
-            return Err("Cannot remove root directory".to_string());
+    pub fn check_writable(&self, path: &str) -> Result<(), String> {
+        self.check_path_writable(path)?;
+        if self
+            .resolve_path(path)
+            .is_some_and(|node| node.link_id.is_some())
+        {
+            for link_path in self.link_paths(path) {
+                self.check_path_writable(&link_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_path_writable(&self, path: &str) -> Result<(), String> {
+        let frozen_error = |at: &str| Err(format!("Read-only: {} is frozen", at));
+
+        let mut current = &self.root;
+        if current.frozen {
+            return frozen_error("/");
         }
 
-        let (parent_path, item_name) = self.split_path(path)?;
+        let mut current_path = "/".to_string();
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            match current.get_child(part) {
+                Some(child) => {
+                    current = child;
+                    current_path = join_path(&current_path, part);
+                    if current.frozen {
+                        return frozen_error(&current_path);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> FileSystem {
+        self.clone()
+    }
+
+    pub fn copy(&mut self, src: &str, dest: &str, user: &str) -> Result<(), String> {
+        self.check_writable(dest)?;
+        let principal = self.principal(user);
+        let (src_parent_path, src_name) = self.split_path(src)?;
+        let source = self
+            .resolve_path(&src_parent_path)
+            .and_then(|parent| parent.children.get(&src_name))
+            .ok_or_else(|| format!("File or directory not found: {}", src))?;
+
+        if !principal.can_read(&source.permissions) {
+            return Err("Permission denied: cannot read source".to_string());
+        }
+        let source = Arc::clone(source);
 
+        let (parent_path, name) = self.split_path(dest)?;
+        let limits = self.limits.clone();
         let parent = self
             .resolve_path_mut(&parent_path)
             .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
 
-        if !parent.permissions.can_write(user) {
+        if !principal.can_write(&parent.permissions) {
             return Err("Permission denied: cannot write to parent directory".to_string());
         }
 
-        if !parent.children.contains_key(&item_name) {
-            return Err(format!("File or directory not found: {}", path));
+        if parent.children.contains_key(&name) {
+            return Err(format!("Destination already exists: {}", dest));
         }
 
-        let item = parent.children.get(&item_name).unwrap();
-        if item.permissions.owner != user && !parent.permissions.can_write(user) {
-            return Err("Permission denied: cannot remove item".to_string());
-        }
+        Self::check_limits(&limits, dest, &name, parent)?;
+        Self::check_subtree_limits(&limits, dest, &source)?;
+
+        let mut copy = source;
+        let node = Arc::make_mut(&mut copy);
+        node.name = name.clone();
+        node.permissions = Permissions::new(user.to_string());
+        node.created_by = user.to_string();
+        Self::clear_link_ids(node);
 
-        parent.children.remove(&item_name);
+        parent.children.insert(name, copy);
         parent.modified_at = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -295,37 +867,645 @@ impl FileSystem {
         Ok(())
     }
 
-    pub fn save_to_db<P: AsRef<Path>>(&self, db_path: P) -> Result<(), String> {
-        let conn =
-            Connection::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+    fn clear_link_ids(node: &mut FileNode) {
+        node.link_id = None;
+        for child in node.children.values_mut() {
+            if child.link_id.is_some() || !child.children.is_empty() {
+                Self::clear_link_ids(Arc::make_mut(child));
+            }
+        }
+    }
 
-        self.create_tables(&conn)?;
-        Self::save_node_recursive(&conn, &self.root, "/")?;
+    pub fn link(&mut self, src: &str, dest: &str, user: &str) -> Result<(), String> {
+        self.check_writable(dest)?;
+        let principal = self.principal(user);
+        let source = self
+            .resolve_path(src)
+            .ok_or_else(|| format!("File not found: {}", src))?;
 
-        Ok(())
-    }
+        if source.file_type != FileType::File {
+            return Err("Only files can be linked".to_string());
+        }
 
-    pub fn load_from_db<P: AsRef<Path>>(db_path: P, owner: String) -> Result<Self, String> {
-        if !db_path.as_ref().exists() {
-            return Ok(FileSystem::new(owner));
+        if !principal.can_read(&source.permissions) {
+            return Err("Permission denied: cannot read source".to_string());
         }
 
-        let conn =
-            Connection::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+        let link_id = match source.link_id {
+            Some(link_id) => link_id,
+            None => self.next_link_id(),
+        };
 
-        let root = Self::load_node_recursive(&conn, "/", "/")?
-            .unwrap_or_else(|| FileNode::new_directory("/".to_string(), owner));
+        let (parent_path, name) = self.split_path(dest)?;
+        let limits = self.limits.clone();
+        let parent = self
+            .resolve_path(&parent_path)
+            .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+
+        if !principal.can_write(&parent.permissions) {
+            return Err("Permission denied: cannot write to parent directory".to_string());
+        }
+
+        if parent.children.contains_key(&name) {
+            return Err(format!("Destination already exists: {}", dest));
+        }
+
+        Self::check_limits(&limits, dest, &name, parent)?;
 
-        Ok(FileSystem { root })
+        let source = self.resolve_path_mut(src).unwrap();
+        source.link_id = Some(link_id);
+        let mut linked = source.clone();
+        linked.name = name.clone();
+        linked.frozen = false;
+        linked.hidden = false;
+
+        let parent = self.resolve_path_mut(&parent_path).unwrap();
+        parent.add_child(linked)
     }
 
-    fn create_tables(&self, conn: &Connection) -> Result<(), String> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS files (
-                path TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                file_type TEXT NOT NULL,
-                content TEXT,
+    fn next_link_id(&self) -> u64 {
+        self.walk("/")
+            .filter_map(|(_, node)| node.link_id)
+            .max()
+            .map_or(1, |max| max + 1)
+    }
+
+    pub fn link_paths(&self, path: &str) -> Vec<String> {
+        match self.resolve_path(path).and_then(|node| node.link_id) {
+            Some(link_id) => self
+                .walk("/")
+                .filter(|(_, node)| node.link_id == Some(link_id))
+                .map(|(path, _)| path)
+                .collect(),
+            None => vec![normalize_path(path)],
+        }
+    }
+
+    pub fn link_count(&self, path: &str) -> usize {
+        self.link_paths(path).len()
+    }
+
+    pub fn sync_links(&mut self, path: &str) -> Result<(), String> {
+        let source = self
+            .resolve_path(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        if source.link_id.is_none() {
+            return Ok(());
+        }
+
+        let source = source.clone();
+        let origin = normalize_path(path);
+        for link_path in self.link_paths(path) {
+            if link_path == origin {
+                continue;
+            }
+            if let Some(node) = self.resolve_path_mut(&link_path) {
+                *node = FileNode {
+                    name: node.name.clone(),
+                    frozen: node.frozen,
+                    hidden: node.hidden,
+                    ..source.clone()
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn create_directory(&mut self, path: &str, owner: String) -> Result<(), String> {
+        self.check_writable(path)?;
+        let principal = self.principal(&owner);
+        let (parent_path, dir_name) = self.split_path(path)?;
+        let limits = self.limits.clone();
+
+        let parent = self
+            .resolve_path_mut(&parent_path)
+            .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+
+        if !principal.can_write(&parent.permissions) {
+            return Err("Permission denied: cannot write to parent directory".to_string());
+        }
+
+        if parent.children.contains_key(&dir_name) {
+            return Err("Directory already exists".to_string());
+        }
+
+        Self::check_limits(&limits, path, &dir_name, parent)?;
+
+        let new_dir = FileNode::new_directory(dir_name.clone(), owner);
+        parent.add_child(new_dir)?;
+
+        Ok(())
+    }
+
+    pub fn create_file(
+        &mut self,
+        path: &str,
+        content: String,
+        owner: String,
+    ) -> Result<(), String> {
+        self.check_writable(path)?;
+        let principal = self.principal(&owner);
+        let (parent_path, file_name) = self.split_path(path)?;
+        let limits = self.limits.clone();
+
+        let parent = self
+            .resolve_path_mut(&parent_path)
+            .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+
+        if !principal.can_write(&parent.permissions) {
+            return Err("Permission denied: cannot write to parent directory".to_string());
+        }
+
+        if parent.children.contains_key(&file_name) {
+            return Err(format!("File already exists: {}", path));
+        }
+
+        Self::check_limits(&limits, path, &file_name, parent)?;
+
+        let new_file = FileNode::new_file(file_name.clone(), content, owner);
+        parent.add_child(new_file)?;
+
+        Ok(())
+    }
+
+    pub fn write_file(
+        &mut self,
+        path: &str,
+        content: String,
+        user: &str,
+        key: Option<&NodeKey>,
+    ) -> Result<(), String> {
+        self.check_writable(path)?;
+        let principal = self.principal(user);
+        let node = self
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("File not found: {}", path))?;
+
+        if node.file_type != FileType::File {
+            return Err("Cannot write to directory".to_string());
+        }
+
+        if !principal.can_write(&node.permissions) {
+            return Err("Permission denied: cannot write to file".to_string());
+        }
+
+        node.store_content(content, key)?;
+        self.sync_links(path)
+    }
+
+    pub fn export_archive(&self, path: &str) -> Result<Vec<u8>, String> {
+        let root_path = normalize_path(path);
+        let root = self
+            .resolve_path(&root_path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        if root.file_type != FileType::Directory {
+            return Err("Not a directory".to_string());
+        }
+
+        let prefix = if root_path == "/" {
+            "/".to_string()
+        } else {
+            format!("{}/", root_path)
+        };
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut manifest = Vec::new();
+
+        for (node_path, node) in self.walk(&root_path).skip(1) {
+            let relative = node_path.strip_prefix(&prefix).unwrap_or(&node_path);
+
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(node.modified_at);
+            let append_result = match node.file_type {
+                FileType::Directory => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    builder.append_data(&mut header, format!("{}/", relative), std::io::empty())
+                }
+                FileType::File => {
+                    let content = node.content.as_deref().unwrap_or("").as_bytes();
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_mode(0o644);
+                    header.set_size(content.len() as u64);
+                    builder.append_data(&mut header, relative, content)
+                }
+            };
+            append_result.map_err(|e| format!("Failed to add {} to archive: {}", node_path, e))?;
+
+            manifest.push(ArchiveEntry {
+                path: relative.to_string(),
+                permissions: node.permissions.clone(),
+                created_by: node.created_by.clone(),
+                created_at: node.created_at,
+                modified_at: node.modified_at,
+                encrypted: node.encrypted,
+                plain_size: node.plain_size,
+                plain_checksum: node.plain_checksum.clone(),
+            });
+        }
+
+        let manifest_json = serde_json::to_vec(&manifest)
+            .map_err(|e| format!("Failed to serialize archive manifest: {}", e))?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(manifest_json.len() as u64);
+        builder
+            .append_data(&mut header, ARCHIVE_MANIFEST, manifest_json.as_slice())
+            .map_err(|e| format!("Failed to add manifest to archive: {}", e))?;
+
+        builder
+            .into_inner()
+            .map_err(|e| format!("Failed to finish archive: {}", e))
+    }
+
+    pub fn import_archive(
+        &mut self,
+        dest: &str,
+        data: &[u8],
+        owner: &str,
+    ) -> Result<usize, String> {
+        let dest_path = normalize_path(dest);
+        match self.resolve_path(&dest_path) {
+            Some(node) if node.file_type == FileType::Directory => {}
+            Some(_) => return Err("Not a directory".to_string()),
+            None => return Err(format!("Path not found: {}", dest)),
+        }
+
+        let mut archive = tar::Archive::new(data);
+        let mut items: Vec<(String, FileType, String)> = Vec::new();
+        let mut manifest: HashMap<String, ArchiveEntry> = HashMap::new();
+
+        let entries = archive
+            .entries()
+            .map_err(|e| format!("Invalid archive: {}", e))?;
+        for entry in entries {
+            let mut entry = entry.map_err(|e| format!("Invalid archive entry: {}", e))?;
+            let entry_path = entry
+                .path()
+                .map_err(|e| format!("Invalid archive path: {}", e))?
+                .into_owned();
+
+            let mut parts = Vec::new();
+            for component in entry_path.components() {
+                match component {
+                    Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+                    Component::CurDir => {}
+                    _ => return Err(format!("Unsafe path in archive: {}", entry_path.display())),
+                }
+            }
+            if parts.is_empty() {
+                continue;
+            }
+            let relative = parts.join("/");
+
+            let entry_type = entry.header().entry_type();
+            let mut content = String::new();
+            if entry_type.is_file() {
+                entry
+                    .read_to_string(&mut content)
+                    .map_err(|e| format!("Archive entry {} is not UTF-8: {}", relative, e))?;
+            }
+
+            if relative == ARCHIVE_MANIFEST {
+                let parsed: Vec<ArchiveEntry> = serde_json::from_str(&content)
+                    .map_err(|e| format!("Invalid archive manifest: {}", e))?;
+                manifest.extend(parsed.into_iter().map(|item| (item.path.clone(), item)));
+            } else if entry_type.is_dir() {
+                items.push((relative, FileType::Directory, content));
+            } else if entry_type.is_file() {
+                items.push((relative, FileType::File, content));
+            }
+        }
+
+        items.sort_by_key(|(relative, _, _)| relative.matches('/').count());
+
+        let mut imported = 0;
+        for (relative, file_type, content) in items {
+            let full_path = join_path(&dest_path, &relative);
+            let (parent_path, name) = self.split_path(&full_path)?;
+            let limits = self.limits.clone();
+            let parent = self
+                .resolve_path_mut(&parent_path)
+                .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+            if parent.file_type != FileType::Directory {
+                return Err(format!("Cannot import into a file: {}", parent_path));
+            }
+
+            if let Some(existing) = parent.get_child_mut(&name) {
+                match (&existing.file_type, &file_type) {
+                    (FileType::Directory, FileType::Directory) => continue,
+                    (FileType::File, FileType::File) => {
+                        existing.update_content(content)?;
+                        imported += 1;
+                        continue;
+                    }
+                    _ => return Err(format!("Type conflict while importing {}", full_path)),
+                }
+            }
+
+            Self::check_limits(&limits, &full_path, &name, parent)?;
+
+            let mut node = match file_type {
+                FileType::Directory => FileNode::new_directory(name, owner.to_string()),
+                FileType::File => FileNode::new_file(name, content, owner.to_string()),
+            };
+            if let Some(meta) = manifest.get(&relative) {
+                node.permissions = meta.permissions.clone();
+                node.created_by = meta.created_by.clone();
+                node.created_at = meta.created_at;
+                node.modified_at = meta.modified_at;
+                node.encrypted = meta.encrypted && node.file_type == FileType::File;
+                node.plain_size = meta.plain_size.filter(|_| node.encrypted);
+                node.plain_checksum = meta.plain_checksum.clone().filter(|_| node.encrypted);
+            }
+            parent.add_child(node)?;
+            imported += 1;
+        }
+
+        Ok(imported)
+    }
+
+    fn check_limits(
+        limits: &FsLimits,
+        path: &str,
+        name: &str,
+        parent: &FileNode,
+    ) -> Result<(), String> {
+        memo_decoder::check_file_name(name)?;
+        if let Some(existing) =
+            memo_decoder::confusable_with(name, parent.children.keys().map(String::as_str))
+        {
+            return Err(format!(
+                "Invalid file name {:?}: looks the same as the existing {:?}",
+                name, existing
+            ));
+        }
+
+        if name.chars().count() > limits.max_name_length {
+            return Err(format!(
+                "Name too long: {} characters (max {})",
+                name.chars().count(),
+                limits.max_name_length
+            ));
+        }
+
+        let depth = path.split('/').filter(|part| !part.is_empty()).count();
+        if depth > limits.max_depth {
+            return Err(format!(
+                "Path too deep: {} levels (max {})",
+                depth, limits.max_depth
+            ));
+        }
+
+        if parent.children.len() >= limits.max_children {
+            return Err(format!(
+                "Directory full: {} entries (max {})",
+                parent.children.len(),
+                limits.max_children
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_subtree_limits(limits: &FsLimits, path: &str, node: &FileNode) -> Result<(), String> {
+        if node.children.len() > limits.max_children {
+            return Err(format!(
+                "Directory full: {} has {} entries (max {})",
+                path,
+                node.children.len(),
+                limits.max_children
+            ));
+        }
+        for (name, child) in &node.children {
+            let child_path = format!("{}/{}", path.trim_end_matches('/'), name);
+            if name.chars().count() > limits.max_name_length {
+                return Err(format!(
+                    "Name too long: {} characters (max {})",
+                    name.chars().count(),
+                    limits.max_name_length
+                ));
+            }
+            let depth = child_path
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .count();
+            if depth > limits.max_depth {
+                return Err(format!(
+                    "Path too deep: {} levels (max {})",
+                    depth, limits.max_depth
+                ));
+            }
+            Self::check_subtree_limits(limits, &child_path, child)?;
+        }
+        Ok(())
+    }
+
+    fn split_path(&self, path: &str) -> Result<(String, String), String> {
+        let path = path.trim_end_matches('/');
+        if path == "/" {
+            return Err("Cannot create root directory".to_string());
+        }
+
+        let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        if parts.is_empty() || parts[parts.len() - 1].is_empty() {
+            return Err("Invalid path".to_string());
+        }
+
+        let file_name = parts[parts.len() - 1].to_string();
+        let parent_path = if parts.len() == 1 {
+            "/".to_string()
+        } else {
+            "/".to_string() + &parts[0..parts.len() - 1].join("/")
+        };
+
+        Ok((parent_path, file_name))
+    }
+
+    pub fn remove(&mut self, path: &str, user: &str) -> Result<(), String> {
+        if path == "/" {
+            return Err("Cannot remove root directory".to_string());
+        }
+
+        self.check_writable(path)?;
+        if let Some((frozen_path, _)) = self.walk(path).find(|(_, node)| node.frozen) {
+            return Err(format!("Read-only: {} is frozen", frozen_path));
+        }
+        let principal = self.principal(user);
+
+        let (parent_path, item_name) = self.split_path(path)?;
+
+        let parent = self
+            .resolve_path_mut(&parent_path)
+            .ok_or_else(|| format!("Parent directory not found: {}", parent_path))?;
+
+        if !principal.can_write(&parent.permissions) {
+            return Err("Permission denied: cannot write to parent directory".to_string());
+        }
+
+        if !parent.children.contains_key(&item_name) {
+            return Err(format!("File or directory not found: {}", path));
+        }
+
+        let item = parent.children.get(&item_name).unwrap();
+        if item.permissions.owner != user && !principal.can_write(&parent.permissions) {
+            return Err("Permission denied: cannot remove item".to_string());
+        }
+
+        let removed = parent.children.remove(&item_name).unwrap();
+        parent.modified_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut removed_links: Vec<u64> = Walk {
+            stack: vec![(normalize_path(path), removed.as_ref())],
+        }
+        .filter_map(|(_, node)| node.link_id)
+        .collect();
+        removed_links.sort_unstable();
+        removed_links.dedup();
+        for link_id in removed_links {
+            self.release_link(link_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_expiry(&mut self, path: &str, expires_at: Option<u64>) -> Result<(), String> {
+        if normalize_path(path) == "/" {
+            return Err("Cannot set expiry on root directory".to_string());
+        }
+
+        self.check_writable(path)?;
+        let node = self
+            .resolve_path_mut(path)
+            .ok_or_else(|| format!("Path not found: {}", path))?;
+        node.expires_at = expires_at;
+        self.sync_links(path)
+    }
+
+    pub fn gc(&mut self, now: u64) -> GcReport {
+        let mut report = GcReport::default();
+        if self.root.needs_gc(now) {
+            Self::gc_node(&mut self.root, now, &mut report);
+        }
+
+        let mut link_counts: HashMap<u64, usize> = HashMap::new();
+        for (_, node) in self.walk("/") {
+            if let Some(link_id) = node.link_id {
+                *link_counts.entry(link_id).or_default() += 1;
+            }
+        }
+        for (link_id, count) in link_counts {
+            if count == 1 {
+                self.release_link(link_id);
+                report.dissolved_links += 1;
+            }
+        }
+
+        report
+    }
+
+    fn gc_node(node: &mut FileNode, now: u64, report: &mut GcReport) {
+        if node.revisions.len() > MAX_REVISIONS {
+            let excess = node.revisions.len() - MAX_REVISIONS;
+            report.pruned_revisions += excess;
+            report.reclaimed_bytes += node
+                .revisions
+                .drain(..excess)
+                .map(|revision| revision.content.len())
+                .sum::<usize>();
+        }
+
+        let expired: Vec<String> = node
+            .children
+            .iter()
+            .filter(|(_, child)| !child.frozen && child.is_expired(now))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in expired {
+            if let Some(child) = node.children.remove(&name) {
+                report.expired_nodes += 1;
+                report.reclaimed_bytes += child.stored_bytes();
+            }
+        }
+
+        for child in node.children.values_mut() {
+            if child.needs_gc(now) {
+                Self::gc_node(Arc::make_mut(child), now, report);
+            }
+        }
+    }
+
+    fn release_link(&mut self, link_id: u64) {
+        let remaining: Vec<String> = self
+            .walk("/")
+            .filter(|(_, node)| node.link_id == Some(link_id))
+            .map(|(path, _)| path)
+            .collect();
+
+        if let [last] = remaining.as_slice() {
+            if let Some(node) = self.resolve_path_mut(last) {
+                node.link_id = None;
+            }
+        }
+    }
+
+    pub fn save_to_db<P: AsRef<Path>>(&self, db_path: P) -> Result<(), String> {
+        let mut conn =
+            Connection::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+
+        self.create_tables(&conn)?;
+
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        for table in [
+            "files",
+            "permissions",
+            "public_permissions",
+            "file_revisions",
+            "node_attributes",
+        ] {
+            tx.execute(&format!("DELETE FROM {}", table), [])
+                .map_err(|e| format!("Failed to clear {} table: {}", table, e))?;
+        }
+        Self::save_node_recursive(&tx, &self.root, "/")?;
+        tx.commit()
+            .map_err(|e| format!("Failed to commit filesystem: {}", e))?;
+
+        Ok(())
+    }
+
+    pub fn load_from_db<P: AsRef<Path>>(db_path: P, owner: String) -> Result<Self, String> {
+        if !db_path.as_ref().exists() {
+            return Ok(FileSystem::new(owner));
+        }
+
+        let conn =
+            Connection::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+
+        let root = Self::load_node_recursive(&conn, "/", "/")?
+            .unwrap_or_else(|| FileNode::new_directory("/".to_string(), owner));
+
+        Ok(FileSystem {
+            root,
+            limits: FsLimits::default(),
+            delegation: None,
+        })
+    }
+
+    fn create_tables(&self, conn: &Connection) -> Result<(), String> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS files (
+                path TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                file_type TEXT NOT NULL,
+                content TEXT,
                 owner TEXT NOT NULL,
                 created_by TEXT NOT NULL,
                 created_at INTEGER NOT NULL,
@@ -356,6 +1536,29 @@ impl FileSystem {
         )
         .map_err(|e| format!("Failed to create public_permissions table: {}", e))?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS file_revisions (
+                path TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                modified_at INTEGER NOT NULL,
+                PRIMARY KEY (path, version)
+            )",
+            [],
+        )
+        .map_err(|e| format!("Failed to create file_revisions table: {}", e))?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS node_attributes (
+                path TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (path, key)
+            )",
+            [],
+        )
+        .map_err(|e| format!("Failed to create node_attributes table: {}", e))?;
+
         Ok(())
     }
 
@@ -406,13 +1609,82 @@ impl FileSystem {
             [path, &node.permissions.public_read.to_string(), &node.permissions.public_write.to_string()],
         ).map_err(|e| format!("Failed to save public permissions: {}", e))?;
 
+        if node.file_type == FileType::File {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'version', ?2)",
+                [path, &node.version.to_string()],
+            )
+            .map_err(|e| format!("Failed to save file version: {}", e))?;
+        }
+
+        if node.frozen {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'frozen', 'true')",
+                [path],
+            )
+            .map_err(|e| format!("Failed to save frozen flag: {}", e))?;
+        }
+
+        if node.hidden {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'hidden', 'true')",
+                [path],
+            )
+            .map_err(|e| format!("Failed to save hidden flag: {}", e))?;
+        }
+
+        if node.encrypted {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'encrypted', 'true')",
+                [path],
+            )
+            .map_err(|e| format!("Failed to save encrypted flag: {}", e))?;
+        }
+
+        if let (Some(size), Some(checksum)) = (node.plain_size, &node.plain_checksum) {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'plain_size', ?2)",
+                [path, &size.to_string()],
+            )
+            .map_err(|e| format!("Failed to save plaintext size: {}", e))?;
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'plain_checksum', ?2)",
+                [path, checksum],
+            )
+            .map_err(|e| format!("Failed to save plaintext checksum: {}", e))?;
+        }
+
+        if let Some(link_id) = node.link_id {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'link_id', ?2)",
+                [path, &link_id.to_string()],
+            )
+            .map_err(|e| format!("Failed to save link id: {}", e))?;
+        }
+
+        if let Some(expires_at) = node.expires_at {
+            conn.execute(
+                "INSERT OR REPLACE INTO node_attributes (path, key, value) VALUES (?1, 'expires_at', ?2)",
+                [path, &expires_at.to_string()],
+            )
+            .map_err(|e| format!("Failed to save expiry: {}", e))?;
+        }
+
+        for revision in &node.revisions {
+            conn.execute(
+                "INSERT OR REPLACE INTO file_revisions (path, version, content, modified_at) VALUES (?1, ?2, ?3, ?4)",
+                [
+                    path,
+                    &revision.version.to_string(),
+                    &revision.content,
+                    &revision.modified_at.to_string(),
+                ],
+            )
+            .map_err(|e| format!("Failed to save file revision: {}", e))?;
+        }
+
         for (child_name, child_node) in &node.children {
-            let child_path = if path == "/" {
-                format!("/{}", child_name)
-            } else {
-                format!("{}/{}", path, child_name)
-            };
-            Self::save_node_recursive(conn, child_node, &child_path)?;
+            Self::save_node_recursive(conn, child_node, &join_path(path, child_name))?;
         }
 
         Ok(())
@@ -487,6 +1759,96 @@ impl FileSystem {
             permissions.public_write = public_write;
         }
 
+        let version = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'version'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or_else(initial_version);
+
+        let frozen = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'frozen'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .map(|value| value == "true")
+            .unwrap_or(false);
+
+        let hidden = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'hidden'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .map(|value| value == "true")
+            .unwrap_or(false);
+
+        let encrypted = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'encrypted'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .map(|value| value == "true")
+            .unwrap_or(false);
+
+        let link_id = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'link_id'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok());
+
+        let expires_at = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'expires_at'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok());
+
+        let plain_size = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'plain_size'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok());
+
+        let plain_checksum = conn
+            .query_row(
+                "SELECT value FROM node_attributes WHERE path = ?1 AND key = 'plain_checksum'",
+                [path],
+                |row| row.get::<_, String>(0),
+            )
+            .ok();
+
+        let mut rev_stmt = conn
+            .prepare(
+                "SELECT version, content, modified_at FROM file_revisions WHERE path = ?1 ORDER BY version",
+            )
+            .map_err(|e| format!("Failed to prepare revisions query: {}", e))?;
+
+        let revisions = rev_stmt
+            .query_map([path], |row| {
+                Ok(FileRevision {
+                    version: row.get(0)?,
+                    content: Arc::from(row.get::<_, String>(1)?),
+                    modified_at: row.get(2)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query revisions: {}", e))?
+            .collect::<Result<Vec<FileRevision>, rusqlite::Error>>()
+            .map_err(|e| format!("Revision row error: {}", e))?;
+
         let mut node = FileNode {
             name: name.to_string(),
             file_type,
@@ -500,6 +1862,15 @@ impl FileSystem {
             created_by,
             created_at,
             modified_at,
+            version,
+            revisions,
+            frozen,
+            encrypted,
+            link_id,
+            expires_at,
+            hidden,
+            plain_size,
+            plain_checksum,
         };
 
         if node.file_type == FileType::Directory {
@@ -529,7 +1900,8 @@ impl FileSystem {
                     child_result.map_err(|e| format!("Child row error: {}", e))?;
                 if let Some(child_node) = Self::load_node_recursive(conn, &child_path, &child_name)?
                 {
-                    node.children.insert(child_name.to_string(), child_node);
+                    node.children
+                        .insert(child_name.to_string(), Arc::new(child_node));
                 }
             }
         }
@@ -672,4 +2044,664 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Cannot remove root directory"));
     }
+
+    #[test]
+    fn test_walk_visits_tree_in_sorted_preorder() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+
+        fs.create_directory("/b", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_directory("/a", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file("/a/z.txt", "z".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file("/a/y.txt", "y".to_string(), "zs1owner123".to_string())
+            .unwrap();
+
+        let paths: Vec<String> = fs.walk("/").map(|(path, _)| path).collect();
+        assert_eq!(paths, vec!["/", "/a", "/a/y.txt", "/a/z.txt", "/b"]);
+    }
+
+    #[test]
+    fn test_walk_subtree_and_missing_path() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+
+        fs.create_directory("/home", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/home/readme.txt",
+            "hi".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+
+        let entries: Vec<(String, FileType)> = fs
+            .walk("/home/")
+            .map(|(path, node)| (path, node.file_type.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("/home".to_string(), FileType::Directory),
+                ("/home/readme.txt".to_string(), FileType::File),
+            ]
+        );
+
+        assert_eq!(fs.walk("/missing").count(), 0);
+    }
+
+    #[test]
+    fn test_update_content_records_revisions() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+
+        fs.create_file("/notes.txt", "v1".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        let file = fs.resolve_path_mut("/notes.txt").unwrap();
+        file.update_content("v2".to_string()).unwrap();
+        file.update_content("v3".to_string()).unwrap();
+
+        let file = fs.resolve_path("/notes.txt").unwrap();
+        assert_eq!(file.version, 3);
+        assert_eq!(file.revision_content(1), Some("v1"));
+        assert_eq!(file.revision_content(2), Some("v2"));
+        assert_eq!(file.revision_content(3), Some("v3"));
+        assert_eq!(file.revision_content(4), None);
+    }
+
+    #[test]
+    fn test_diff_between_revisions() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+
+        fs.create_file(
+            "/rules.txt",
+            "be kind\nno spam".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+        fs.resolve_path_mut("/rules.txt")
+            .unwrap()
+            .update_content("be kind\nno ads".to_string())
+            .unwrap();
+
+        let diff = fs.diff("/rules.txt", 1, 2, "zs1other456", None).unwrap();
+        assert_eq!(diff, "-no spam\n+no ads");
+
+        let missing = fs.diff("/rules.txt", 1, 9, "zs1other456", None);
+        assert!(missing.unwrap_err().contains("Version 9 not found"));
+    }
+
+    #[test]
+    fn test_revisions_persist_and_removed_nodes_stay_removed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("fs.db");
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+
+        fs.create_file("/keep.txt", "old".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file("/gone.txt", "bye".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.resolve_path_mut("/keep.txt")
+            .unwrap()
+            .update_content("new".to_string())
+            .unwrap();
+        fs.save_to_db(&db_path).unwrap();
+
+        fs.remove("/gone.txt", "zs1owner123").unwrap();
+        fs.save_to_db(&db_path).unwrap();
+
+        let loaded = FileSystem::load_from_db(&db_path, "zs1owner123".to_string()).unwrap();
+        let file = loaded.resolve_path("/keep.txt").unwrap();
+        assert_eq!(file.version, 2);
+        assert_eq!(file.revision_content(1), Some("old"));
+        assert!(loaded.resolve_path("/gone.txt").is_none());
+    }
+
+    #[test]
+    fn test_structural_limits() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.limits = FsLimits {
+            max_depth: 2,
+            max_children: 2,
+            max_name_length: 8,
+        };
+
+        fs.create_directory("/a", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_directory("/a/b", "zs1owner123".to_string())
+            .unwrap();
+
+        let too_deep = fs.create_directory("/a/b/c", "zs1owner123".to_string());
+        assert!(too_deep.unwrap_err().contains("Path too deep"));
+
+        let too_long = fs.create_file(
+            "/a/very_long_name.txt",
+            "x".to_string(),
+            "zs1owner123".to_string(),
+        );
+        assert!(too_long.unwrap_err().contains("Name too long"));
+
+        fs.create_file("/a/f.txt", "x".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        let full = fs.create_file("/a/g.txt", "x".to_string(), "zs1owner123".to_string());
+        assert!(full.unwrap_err().contains("Directory full"));
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let mut source = FileSystem::new("zs1owner123".to_string());
+        source
+            .create_directory("/board", "zs1owner123".to_string())
+            .unwrap();
+        source
+            .create_directory("/board/general", "zs1owner123".to_string())
+            .unwrap();
+        source
+            .create_file(
+                "/board/general/rules.txt",
+                "be kind".to_string(),
+                "zs1owner123".to_string(),
+            )
+            .unwrap();
+        source
+            .resolve_path_mut("/board/general/rules.txt")
+            .unwrap()
+            .permissions
+            .owner = "zs1author789".to_string();
+
+        let archive = source.export_archive("/board").unwrap();
+
+        let mut target = FileSystem::new("zs1admin".to_string());
+        target
+            .create_directory("/restored", "zs1admin".to_string())
+            .unwrap();
+        let imported = target
+            .import_archive("/restored", &archive, "zs1admin")
+            .unwrap();
+        assert_eq!(imported, 2);
+
+        let rules = target.resolve_path("/restored/general/rules.txt").unwrap();
+        assert_eq!(rules.content, Some("be kind".to_string()));
+        assert_eq!(rules.permissions.owner, "zs1author789");
+    }
+
+    #[test]
+    fn test_import_rejects_unsafe_paths() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(1);
+        header.set_mode(0o644);
+        let name = b"../escape.txt";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_cksum();
+        builder.append(&header, &b"x"[..]).unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        let result = fs.import_archive("/", &archive, "zs1owner123");
+        assert!(result.unwrap_err().contains("Unsafe path"));
+    }
+
+    #[test]
+    fn test_snapshot_shares_structure_until_modified() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_directory("/docs", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/docs/a.txt",
+            "original".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+
+        let snapshot = fs.snapshot();
+        assert!(Arc::ptr_eq(
+            &fs.root.children["docs"],
+            &snapshot.root.children["docs"]
+        ));
+
+        fs.resolve_path_mut("/docs/a.txt")
+            .unwrap()
+            .update_content("changed".to_string())
+            .unwrap();
+
+        assert!(!Arc::ptr_eq(
+            &fs.root.children["docs"],
+            &snapshot.root.children["docs"]
+        ));
+        assert_eq!(
+            snapshot.resolve_path("/docs/a.txt").unwrap().content,
+            Some("original".to_string())
+        );
+        assert_eq!(
+            fs.resolve_path("/docs/a.txt").unwrap().content,
+            Some("changed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_copy_shares_subtree() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_directory("/src", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/src/big.txt",
+            "data".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+
+        fs.copy("/src", "/dst", "zs1owner123").unwrap();
+
+        let src = fs.resolve_path("/src").unwrap();
+        let dst = fs.resolve_path("/dst").unwrap();
+        assert_eq!(dst.name, "dst");
+        assert!(Arc::ptr_eq(
+            &src.children["big.txt"],
+            &dst.children["big.txt"]
+        ));
+
+        let exists = fs.copy("/src", "/dst", "zs1owner123");
+        assert!(exists.unwrap_err().contains("already exists"));
+
+        fs.limits.max_depth = 2;
+        fs.create_directory("/deep", "zs1owner123".to_string())
+            .unwrap();
+        let too_deep = fs.copy("/src", "/deep/src", "zs1owner123");
+        assert!(too_deep.unwrap_err().contains("Path too deep: 3 levels"));
+        assert!(fs.resolve_path("/deep/src").is_none());
+
+        fs.limits.max_depth = 32;
+        fs.create_directory("/wide", "zs1owner123".to_string())
+            .unwrap();
+        for name in ["a", "b", "c"] {
+            fs.create_file(
+                &format!("/wide/{}", name),
+                String::new(),
+                "zs1owner123".to_string(),
+            )
+            .unwrap();
+        }
+        fs.limits.max_children = 2;
+        let full = fs.copy("/wide", "/deep/wide", "zs1owner123");
+        assert!(full
+            .unwrap_err()
+            .contains("Directory full: /deep/wide has 3 entries"));
+    }
+
+    #[test]
+    fn test_directory_listing_pages() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+
+        for name in ["a", "b", "c", "d", "e"] {
+            fs.create_file(
+                &format!("/{}.txt", name),
+                String::new(),
+                "zs1owner123".to_string(),
+            )
+            .unwrap();
+        }
+
+        let (page, more) = fs.root.list_children_page(0, 2);
+        assert_eq!(page, vec!["a.txt", "b.txt"]);
+        assert!(more);
+
+        let (page, more) = fs.root.list_children_page(4, 2);
+        assert_eq!(page, vec!["e.txt"]);
+        assert!(!more);
+
+        let (page, more) = fs.root.list_children_page(10, 2);
+        assert!(page.is_empty());
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_file_checksum() {
+        let file = FileNode::new_file(
+            "hello.txt".to_string(),
+            "hello".to_string(),
+            "zs1owner123".to_string(),
+        );
+        assert_eq!(
+            file.checksum().unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(file.size(), 5);
+
+        let dir = FileNode::new_directory("docs".to_string(), "zs1owner123".to_string());
+        assert!(dir.checksum().is_none());
+    }
+
+    #[test]
+    fn test_freeze_blocks_mutations_but_allows_reads() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("fs.db");
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_directory("/board", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_directory("/other", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/board/post.txt",
+            "hi".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+
+        fs.set_frozen("/board", true).unwrap();
+        assert_eq!(fs.frozen_paths(), vec!["/board"]);
+
+        let create = fs.create_file("/board/new.txt", "x".to_string(), "zs1owner123".to_string());
+        assert!(create.unwrap_err().contains("frozen"));
+        assert!(fs.remove("/board/post.txt", "zs1owner123").is_err());
+        assert!(fs.remove("/board", "zs1owner123").is_err());
+        assert!(fs.resolve_path("/board/post.txt").is_some());
+        assert!(fs
+            .create_directory("/other/ok", "zs1owner123".to_string())
+            .is_ok());
+
+        fs.save_to_db(&db_path).unwrap();
+        let mut loaded = FileSystem::load_from_db(&db_path, "zs1owner123".to_string()).unwrap();
+        assert!(loaded.check_writable("/board/post.txt").is_err());
+
+        loaded.set_frozen("/", true).unwrap();
+        assert!(loaded
+            .create_directory("/other/blocked", "zs1owner123".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn test_encrypted_content_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("fs.db");
+        let key = crypto::derive_node_key("secret", "zs1owner123");
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_file(
+            "/diary.txt",
+            "day one".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+
+        let file = fs.resolve_path_mut("/diary.txt").unwrap();
+        file.encrypt_content(&key).unwrap();
+        file.store_content("day two".to_string(), Some(&key))
+            .unwrap();
+        assert!(!file.content.as_ref().unwrap().contains("day"));
+        assert!(file.read_content(None).is_err());
+
+        fs.save_to_db(&db_path).unwrap();
+        let mut loaded = FileSystem::load_from_db(&db_path, "zs1owner123".to_string()).unwrap();
+        let diff = loaded
+            .diff("/diary.txt", 1, 2, "zs1owner123", Some(&key))
+            .unwrap();
+        assert_eq!(diff, "-day one\n+day two");
+        let plain = FileNode::new_file(
+            "plain.txt".to_string(),
+            "day two".to_string(),
+            "zs1owner123".to_string(),
+        );
+        let sealed = loaded.resolve_path("/diary.txt").unwrap();
+        assert_eq!(sealed.size(), plain.size());
+        assert_eq!(sealed.checksum(), plain.checksum());
+
+        let file = loaded.resolve_path_mut("/diary.txt").unwrap();
+        file.decrypt_content(&key).unwrap();
+        assert_eq!(file.content, Some("day two".to_string()));
+        assert_eq!(file.revision_content(1), Some("day one"));
+    }
+
+    #[test]
+    fn test_directory_listing_sort_and_filter() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.root.permissions.public_write = true;
+
+        fs.create_file("/b.txt", "1234".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file("/a.txt", "12".to_string(), "zs1other456".to_string())
+            .unwrap();
+        fs.create_file("/c.txt", "123".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.create_directory("/d", "zs1owner123".to_string())
+            .unwrap();
+
+        let by_size = ListOptions {
+            sort_by: SortKey::Size,
+            reverse: true,
+            filter: EntryFilter::FilesOnly,
+            owner: None,
+            include_hidden: false,
+        };
+        let (page, _) = fs.root.list_children_with(&by_size, 0, 10);
+        assert_eq!(page, vec!["b.txt", "c.txt", "a.txt"]);
+
+        let dirs = ListOptions {
+            filter: EntryFilter::DirsOnly,
+            ..ListOptions::default()
+        };
+        assert_eq!(fs.root.list_children_with(&dirs, 0, 10).0, vec!["d/"]);
+
+        let by_owner = ListOptions {
+            owner: Some("zs1other456".to_string()),
+            ..ListOptions::default()
+        };
+        assert_eq!(
+            fs.root.list_children_with(&by_owner, 0, 10).0,
+            vec!["a.txt"]
+        );
+    }
+
+    #[test]
+    fn test_links_share_content_until_last_removed() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_directory("/board", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_directory("/pinned", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/board/rules.txt",
+            "be nice".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+
+        fs.link("/board/rules.txt", "/pinned/rules.txt", "zs1owner123")
+            .unwrap();
+        assert_eq!(fs.link_count("/board/rules.txt"), 2);
+        assert!(fs
+            .link("/board/rules.txt", "/pinned/rules.txt", "zs1owner123")
+            .is_err());
+        assert!(fs.link("/board", "/pinned/board", "zs1owner123").is_err());
+
+        fs.resolve_path_mut("/pinned/rules.txt")
+            .unwrap()
+            .update_content("be kind".to_string())
+            .unwrap();
+        fs.sync_links("/pinned/rules.txt").unwrap();
+        let board = fs.resolve_path("/board/rules.txt").unwrap();
+        assert_eq!(board.content.as_deref(), Some("be kind"));
+        assert_eq!(board.version, 2);
+        assert_eq!(board.name, "rules.txt");
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("fs.db");
+        fs.save_to_db(&db_path).unwrap();
+        let mut fs = FileSystem::load_from_db(&db_path, "zs1owner123".to_string()).unwrap();
+        assert_eq!(fs.link_count("/pinned/rules.txt"), 2);
+
+        fs.set_expiry("/pinned/rules.txt", Some(500)).unwrap();
+        assert_eq!(
+            fs.resolve_path("/board/rules.txt").unwrap().expires_at,
+            Some(500)
+        );
+        fs.set_expiry("/board/rules.txt", None).unwrap();
+        assert_eq!(
+            fs.resolve_path("/pinned/rules.txt").unwrap().expires_at,
+            None
+        );
+
+        fs.remove("/board/rules.txt", "zs1owner123").unwrap();
+        let pinned = fs.resolve_path("/pinned/rules.txt").unwrap();
+        assert_eq!(pinned.content.as_deref(), Some("be kind"));
+        assert_eq!(pinned.link_id, None);
+        assert_eq!(fs.link_count("/pinned/rules.txt"), 1);
+    }
+
+    #[test]
+    fn test_disk_usage_respects_read_permissions() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.root.permissions.public_write = true;
+        fs.create_directory("/board", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_directory("/board/private", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/board/a.txt",
+            "1234".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+        fs.create_file(
+            "/board/private/b.txt",
+            "123456".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+        fs.resolve_path_mut("/board/private")
+            .unwrap()
+            .permissions
+            .public_read = false;
+
+        let usage = fs.disk_usage("/", "zs1owner123").unwrap();
+        assert_eq!(
+            usage,
+            vec![
+                ("/board/private".to_string(), 6),
+                ("/board".to_string(), 10),
+                ("/".to_string(), 10),
+            ]
+        );
+
+        let usage = fs.disk_usage("/board", "zs1other456").unwrap();
+        assert_eq!(usage, vec![("/board".to_string(), 4)]);
+        assert!(fs.disk_usage("/board/private", "zs1other456").is_err());
+    }
+
+    #[test]
+    fn test_create_file_rejects_existing_and_write_file_updates() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_file("/notes.txt", "v1".to_string(), "zs1owner123".to_string())
+            .unwrap();
+
+        let duplicate = fs.create_file("/notes.txt", "v2".to_string(), "zs1owner123".to_string());
+        assert_eq!(duplicate.unwrap_err(), "File already exists: /notes.txt");
+        assert_eq!(
+            fs.resolve_path("/notes.txt").unwrap().content.as_deref(),
+            Some("v1")
+        );
+
+        fs.write_file("/notes.txt", "v2".to_string(), "zs1owner123", None)
+            .unwrap();
+        let node = fs.resolve_path("/notes.txt").unwrap();
+        assert_eq!(node.content.as_deref(), Some("v2"));
+        assert_eq!(node.version, 2);
+
+        assert!(fs
+            .write_file("/missing.txt", "x".to_string(), "zs1owner123", None)
+            .is_err());
+        assert!(fs
+            .write_file("/notes.txt", "x".to_string(), "zs1other456", None)
+            .is_err());
+        assert!(fs
+            .write_file("/", "x".to_string(), "zs1owner123", None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_ranged_reads() {
+        let file = FileNode::new_file(
+            "log.txt".to_string(),
+            "one\ntwo\nthree\nfour".to_string(),
+            "zs1owner123".to_string(),
+        );
+
+        assert_eq!(
+            file.read_range(4, 3, None).unwrap(),
+            ("two".to_string(), true)
+        );
+        assert_eq!(
+            file.read_range(14, 10, None).unwrap(),
+            ("four".to_string(), false)
+        );
+        assert_eq!(
+            file.read_range(100, 5, None).unwrap(),
+            (String::new(), false)
+        );
+        assert_eq!(file.head_lines(2, None).unwrap(), "one\ntwo");
+        assert_eq!(file.tail_lines(2, None).unwrap(), "three\nfour");
+        assert_eq!(file.tail_lines(10, None).unwrap(), "one\ntwo\nthree\nfour");
+
+        let unicode = FileNode::new_file(
+            "u.txt".to_string(),
+            "héllo".to_string(),
+            "zs1owner123".to_string(),
+        );
+        assert_eq!(unicode.read_range(1, 2, None).unwrap().0, "él");
+
+        let dir = FileNode::new_directory("d".to_string(), "zs1owner123".to_string());
+        assert!(dir.read_range(0, 1, None).is_err());
+    }
+
+    #[test]
+    fn test_gc_prunes_expired_nodes_histories_and_links() {
+        let mut fs = FileSystem::new("zs1owner123".to_string());
+        fs.create_directory("/tmp", "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file(
+            "/tmp/old.txt",
+            "stale".to_string(),
+            "zs1owner123".to_string(),
+        )
+        .unwrap();
+        fs.create_file("/keep.txt", "v0".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.create_file("/frozen.txt", "ice".to_string(), "zs1owner123".to_string())
+            .unwrap();
+        fs.link("/tmp/old.txt", "/old-link.txt", "zs1owner123")
+            .unwrap();
+
+        fs.set_expiry("/tmp", Some(100)).unwrap();
+        fs.set_expiry("/frozen.txt", Some(100)).unwrap();
+        fs.set_frozen("/frozen.txt", true).unwrap();
+        assert!(fs.set_expiry("/", Some(100)).is_err());
+
+        let keep = fs.resolve_path_mut("/keep.txt").unwrap();
+        for version in 0..MAX_REVISIONS + 3 {
+            keep.revisions.push(FileRevision {
+                version: version as u32,
+                content: Arc::from("xx"),
+                modified_at: 0,
+            });
+        }
+
+        let report = fs.gc(99);
+        assert_eq!(report.expired_nodes, 0);
+        assert_eq!(report.pruned_revisions, 3);
+        assert_eq!(report.reclaimed_bytes, 3 * 2);
+
+        let report = fs.gc(100);
+        assert_eq!(report.expired_nodes, 1);
+        assert_eq!(report.pruned_revisions, 0);
+        assert_eq!(report.dissolved_links, 1);
+        assert_eq!(report.reclaimed_bytes, 5);
+        assert_eq!(fs.gc(100), GcReport::default());
+
+        assert!(fs.resolve_path("/tmp/old.txt").is_none());
+        assert!(fs.resolve_path("/frozen.txt").is_some());
+        assert_eq!(fs.resolve_path("/old-link.txt").unwrap().link_id, None);
+        assert_eq!(
+            fs.resolve_path("/keep.txt").unwrap().revisions.len(),
+            MAX_REVISIONS
+        );
+    }
 }
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+pub const GUEST_PREFIX: &str = "GUEST:";
+pub const GUEST_USER: &str = "guest";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GuestConfig {
+    pub enabled: bool,
+    pub commands: Vec<String>,
+}
+
+impl Default for GuestConfig {
+    fn default() -> Self {
+        GuestConfig {
+            enabled: false,
+            commands: ["help", "ls", "cat", "stat", "head", "tail"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl GuestConfig {
+    pub fn allows(&self, command: &str) -> bool {
+        self.commands.iter().any(|allowed| allowed == command)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuestRequest<'a> {
+    pub reply_address: &'a str,
+    pub command: &'a str,
+}
+
+pub fn parse(memo: &str) -> Option<Result<GuestRequest<'_>, String>> {
+    let rest = memo.strip_prefix(GUEST_PREFIX)?;
+    let parsed = rest
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .map(|(reply_address, command)| GuestRequest {
+            reply_address,
+            command: command.trim(),
+        })
+        .filter(|request| !request.reply_address.is_empty() && !request.command.is_empty())
+        .ok_or_else(|| "Invalid guest format. Use GUEST:<reply_address> <command>".to_string());
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_guest_memo() {
+        assert_eq!(
+            parse("GUEST:zs1me ls /board").unwrap().unwrap(),
+            GuestRequest {
+                reply_address: "zs1me",
+                command: "ls /board",
+            }
+        );
+        assert!(parse("GUEST:zs1me").unwrap().is_err());
+        assert!(parse("GUEST: ").unwrap().is_err());
+        assert!(parse("ls /board").is_none());
+    }
+
+    #[test]
+    fn test_default_commands_are_read_only() {
+        let config = GuestConfig::default();
+        assert!(!config.enabled);
+        assert!(config.allows("cat"));
+        assert!(!config.allows("touch"));
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const CAPS_PREFIX: &str = "Caps:";
+pub const ENCRYPTION_SCHEME: &str = "x25519-chacha20poly1305";
+pub const FORMAT_COMPACT: &str = "zm1";
+pub const FORMAT_JSON: &str = "zbj";
+pub const FORMAT_BATCH: &str = "zbatch";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Features {
+    pub protocols: Vec<u32>,
+    pub max_memo: usize,
+    pub max_fragments: usize,
+    pub compression: Vec<String>,
+    pub encryption: Vec<String>,
+    pub formats: Vec<String>,
+    pub commands: Vec<String>,
+}
+
+fn list<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Features {
+    pub fn encode(&self) -> String {
+        let line = format!(
+            "{} v={} memo={} frags={} zip={} enc={} fmt={}",
+            CAPS_PREFIX,
+            list(&self.protocols),
+            self.max_memo,
+            self.max_fragments,
+            list(&self.compression),
+            list(&self.encryption),
+            list(&self.formats)
+        );
+        if self.commands.is_empty() {
+            line
+        } else {
+            format!("{} cmds={}", line, list(&self.commands))
+        }
+    }
+
+    pub fn without_commands(&self) -> Features {
+        Features {
+            commands: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Features> {
+        let (_, rest) = text.split_once(CAPS_PREFIX)?;
+        let mut features = Features::default();
+        for (key, value) in rest
+            .split_whitespace()
+            .map_while(|token| token.split_once('='))
+        {
+            match key {
+                "v" => {
+                    features.protocols = value.split(',').filter_map(|v| v.parse().ok()).collect()
+                }
+                "memo" => features.max_memo = value.parse().unwrap_or_default(),
+                "frags" => features.max_fragments = value.parse().unwrap_or_default(),
+                "zip" => features.compression = parse_list(value),
+                "enc" => features.encryption = parse_list(value),
+                "fmt" => features.formats = parse_list(value),
+                "cmds" => features.commands = parse_list(value),
+                _ => {}
+            }
+        }
+        Some(features)
+    }
+
+    pub fn negotiate(&self) -> Result<u32, String> {
+        self.protocols
+            .iter()
+            .copied()
+            .filter(|version| *version <= PROTOCOL_VERSION)
+            .max()
+            .ok_or_else(|| {
+                format!(
+                    "The coordinator speaks protocol v{} but this client only speaks up to v{}; upgrade zatboard",
+                    list(&self.protocols),
+                    PROTOCOL_VERSION
+                )
+            })
+    }
+
+    pub fn supports_command(&self, name: &str) -> bool {
+        self.commands.is_empty() || self.commands.iter().any(|command| command == name)
+    }
+
+    pub fn supports_format(&self, format: &str) -> bool {
+        self.formats.iter().any(|f| f == format)
+    }
+
+    pub fn supports_compression(&self, codec: &str) -> bool {
+        self.compression.iter().any(|c| c == codec)
+    }
+
+    pub fn supports_encryption(&self) -> bool {
+        self.encryption
+            .iter()
+            .any(|scheme| scheme == ENCRYPTION_SCHEME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Features {
+        Features {
+            protocols: vec![1],
+            max_memo: 512,
+            max_fragments: 32,
+            compression: vec!["zstd".to_string()],
+            encryption: vec![ENCRYPTION_SCHEME.to_string()],
+            formats: vec![FORMAT_COMPACT.to_string(), FORMAT_JSON.to_string()],
+            commands: vec!["ls".to_string(), "cat".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_features_round_trip_inside_a_registration_reply() {
+        let features = sample();
+        assert_eq!(
+            features.encode(),
+            "Caps: v=1 memo=512 frags=32 zip=zstd enc=x25519-chacha20poly1305 fmt=zm1,zbj cmds=ls,cat"
+        );
+        assert_eq!(Features::parse(&features.encode()), Some(features.clone()));
+        let reply = format!(
+            "Registration successful! ConvID: c PartID: p {} Compress: zstd CoordKey: ab",
+            features.without_commands().encode()
+        );
+        assert!(!reply.contains("cmds="));
+        assert_eq!(Features::parse(&reply), Some(features.without_commands()));
+        assert_eq!(
+            Features::parse("Registration successful! CoordKey: ab"),
+            None
+        );
+
+        let newer = Features::parse("Caps: v=1,2 memo=600 fmt=zm1 extra=yes").unwrap();
+        assert_eq!(newer.protocols, vec![1, 2]);
+        assert_eq!(newer.max_memo, 600);
+        assert!(newer.supports_command("anything"));
+        assert!(!newer.supports_encryption());
+    }
+
+    #[test]
+    fn test_negotiation_picks_a_shared_version() {
+        let features = sample();
+        assert_eq!(features.negotiate(), Ok(1));
+        assert!(features.supports_command("ls"));
+        assert!(!features.supports_command("rm"));
+        assert!(features.supports_format(FORMAT_JSON));
+        assert!(!features.supports_format(FORMAT_BATCH));
+        assert!(features.supports_compression("zstd"));
+
+        let future = Features {
+            protocols: vec![2, 3],
+            ..sample()
+        };
+        assert!(future
+            .negotiate()
+            .unwrap_err()
+            .contains("speaks protocol v2,3 but this client only speaks up to v1"));
+    }
+}
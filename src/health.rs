@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub min_balance_zatoshi: u64,
+    pub max_sync_age_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            enabled: true,
+            min_balance_zatoshi: 100_000,
+            max_sync_age_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckState {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl CheckState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckState::Ok => "ok",
+            CheckState::Warn => "warn",
+            CheckState::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub state: CheckState,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub height: Result<u64, String>,
+    pub balance: Result<u64, String>,
+    pub last_sync_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub checked_at: u64,
+    pub checks: Vec<Check>,
+}
+
+impl HealthReport {
+    pub fn assess(config: &HealthConfig, probe: &Probe, now: u64) -> Self {
+        let zingo = match &probe.height {
+            Ok(height) => Check {
+                name: "zingo",
+                state: CheckState::Ok,
+                detail: format!("responding at height {}", height),
+            },
+            Err(e) => Check {
+                name: "zingo",
+                state: CheckState::Fail,
+                detail: format!("not responding: {}", e),
+            },
+        };
+
+        let balance = match &probe.balance {
+            Ok(balance) if *balance >= config.min_balance_zatoshi => Check {
+                name: "balance",
+                state: CheckState::Ok,
+                detail: format!("{} zatoshis spendable", balance),
+            },
+            Ok(balance) => Check {
+                name: "balance",
+                state: CheckState::Fail,
+                detail: format!(
+                    "{} zatoshis spendable, below the {} needed for replies",
+                    balance, config.min_balance_zatoshi
+                ),
+            },
+            Err(e) => Check {
+                name: "balance",
+                state: CheckState::Warn,
+                detail: format!("unknown: {}", e),
+            },
+        };
+
+        let sync = match probe.last_sync_at {
+            Some(at) if now.saturating_sub(at) <= config.max_sync_age_secs => Check {
+                name: "sync",
+                state: CheckState::Ok,
+                detail: format!("last synced {}s ago", now.saturating_sub(at)),
+            },
+            Some(at) => Check {
+                name: "sync",
+                state: CheckState::Warn,
+                detail: format!("stale, last synced {}s ago", now.saturating_sub(at)),
+            },
+            None => Check {
+                name: "sync",
+                state: CheckState::Warn,
+                detail: "no successful sync yet".to_string(),
+            },
+        };
+
+        HealthReport {
+            checked_at: now,
+            checks: vec![zingo, balance, sync],
+        }
+    }
+
+    pub fn state(&self) -> CheckState {
+        self.checks
+            .iter()
+            .map(|check| check.state)
+            .max()
+            .unwrap_or(CheckState::Ok)
+    }
+
+    pub fn hold_reason(&self) -> Option<String> {
+        self.checks
+            .iter()
+            .find(|check| check.state == CheckState::Fail)
+            .map(|check| format!("{} {}", check.name, check.detail))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "Health: {} (checked at {})",
+            self.state().as_str(),
+            self.checked_at
+        )];
+        lines.extend(
+            self.checks
+                .iter()
+                .map(|check| format!("{}: {}, {}", check.name, check.state.as_str(), check.detail)),
+        );
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_healthy_wallet_passes_every_check() {
+        let probe = Probe {
+            height: Ok(2_500_000),
+            balance: Ok(250_000),
+            last_sync_at: Some(990),
+        };
+        let report = HealthReport::assess(&HealthConfig::default(), &probe, 1000);
+
+        assert_eq!(report.state(), CheckState::Ok);
+        assert_eq!(report.hold_reason(), None);
+        assert_eq!(
+            report.describe(),
+            "Health: ok (checked at 1000)\n\
+             zingo: ok, responding at height 2500000\n\
+             balance: ok, 250000 zatoshis spendable\n\
+             sync: ok, last synced 10s ago"
+        );
+    }
+
+    #[test]
+    fn test_failures_hold_replies_and_warnings_do_not() {
+        let stale = Probe {
+            height: Ok(2_500_000),
+            balance: Err("no JSON".to_string()),
+            last_sync_at: Some(100),
+        };
+        let report = HealthReport::assess(&HealthConfig::default(), &stale, 1000);
+        assert_eq!(report.state(), CheckState::Warn);
+        assert_eq!(report.hold_reason(), None);
+        assert!(report
+            .describe()
+            .contains("sync: warn, stale, last synced 900s ago"));
+
+        let broke = Probe {
+            height: Err("zingo-cli not found".to_string()),
+            balance: Ok(5_000),
+            last_sync_at: None,
+        };
+        let report = HealthReport::assess(&HealthConfig::default(), &broke, 1000);
+        assert_eq!(report.state(), CheckState::Fail);
+        assert_eq!(
+            report.hold_reason().unwrap(),
+            "zingo not responding: zingo-cli not found"
+        );
+        assert!(report
+            .describe()
+            .contains("balance: fail, 5000 zatoshis spendable, below the 100000 needed"));
+    }
+}
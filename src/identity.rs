@@ -0,0 +1,153 @@
+use crate::crypto;
+use crate::e2e::E2eKey;
+use crate::signing;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::path::Path;
+
+pub const IDENTITY_KEY_FILE: &str = "identity.key";
+
+fn auth_bytes(reply_address: &str, challenge: &str) -> Vec<u8> {
+    format!("zatboard_auth\n{}\n{}", reply_address, challenge).into_bytes()
+}
+
+fn command_bytes(reply_address: &str, command: &str) -> Vec<u8> {
+    format!("zatboard_command\n{}\n{}", reply_address, command).into_bytes()
+}
+
+fn verify_bytes(public_key_hex: &str, bytes: &[u8], signature_hex: &str) -> Result<(), String> {
+    let key = parse_public_key(public_key_hex)?;
+    let signature_bytes: [u8; 64] = crypto::from_hex(signature_hex)?
+        .try_into()
+        .map_err(|_| "Invalid signature length".to_string())?;
+    key.verify(bytes, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| "Signature does not match registered key".to_string())
+}
+
+pub fn parse_public_key(public_key_hex: &str) -> Result<VerifyingKey, String> {
+    let key_bytes: [u8; 32] = crypto::from_hex(public_key_hex)?
+        .try_into()
+        .map_err(|_| "Invalid public key length".to_string())?;
+    VerifyingKey::from_bytes(&key_bytes).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+pub fn verify_auth(
+    public_key_hex: &str,
+    reply_address: &str,
+    challenge: &str,
+    signature_hex: &str,
+) -> Result<(), String> {
+    verify_bytes(
+        public_key_hex,
+        &auth_bytes(reply_address, challenge),
+        signature_hex,
+    )
+}
+
+pub fn verify_command<'a>(
+    public_key_hex: &str,
+    reply_address: &str,
+    memo: &'a str,
+) -> Result<(&'a str, &'a str), String> {
+    let (command, signature) = signing::split_signature(memo);
+    let signature =
+        signature.ok_or_else(|| "commands must be signed with your registered key".to_string())?;
+    verify_bytes(
+        public_key_hex,
+        &command_bytes(reply_address, command),
+        signature,
+    )?;
+    Ok((command, signature))
+}
+
+pub struct IdentityKey {
+    key: SigningKey,
+}
+
+impl IdentityKey {
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(IdentityKey {
+            key: signing::load_or_create_key(path.as_ref())?,
+        })
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        crypto::to_hex(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn e2e_key(&self) -> E2eKey {
+        E2eKey::from_signing_key(&self.key)
+    }
+
+    pub fn sign_command(&self, reply_address: &str, command: &str) -> String {
+        let signature = self.key.sign(&command_bytes(reply_address, command));
+        format!(
+            "{}{}{}",
+            command,
+            signing::SIGNATURE_MARKER,
+            crypto::to_hex(&signature.to_bytes())
+        )
+    }
+
+    pub fn sign_auth(&self, reply_address: &str, challenge: &str) -> String {
+        crypto::to_hex(
+            &self
+                .key
+                .sign(&auth_bytes(reply_address, challenge))
+                .to_bytes(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_signature_binds_key_reply_and_challenge() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = IdentityKey::load_or_create(dir.path().join(IDENTITY_KEY_FILE)).unwrap();
+        let key = identity.public_key_hex();
+        let signature = identity.sign_auth("zs1reply", "abc123");
+
+        assert!(verify_auth(&key, "zs1reply", "abc123", &signature).is_ok());
+        assert!(verify_auth(&key, "zs1other", "abc123", &signature).is_err());
+        assert!(verify_auth(&key, "zs1reply", "abc124", &signature).is_err());
+        assert!(verify_auth(&key, "zs1reply", "abc123", "sig").is_err());
+
+        let other = IdentityKey::load_or_create(dir.path().join("other.key")).unwrap();
+        assert!(verify_auth(&other.public_key_hex(), "zs1reply", "abc123", &signature).is_err());
+    }
+
+    #[test]
+    fn test_signed_commands_verify_and_strip_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = IdentityKey::load_or_create(dir.path().join(IDENTITY_KEY_FILE)).unwrap();
+        let key = identity.public_key_hex();
+        let signed = identity.sign_command("zs1reply", "ls /docs");
+        assert_eq!(signed.len(), "ls /docs".len() + signing::SIGNATURE_OVERHEAD);
+
+        let (command, signature) = verify_command(&key, "zs1reply", &signed).unwrap();
+        assert_eq!(command, "ls /docs");
+        assert_eq!(signature.len(), 128);
+        assert!(verify_command(&key, "zs1other", &signed).is_err());
+        assert!(verify_command(&key, "zs1reply", &signed.replace("docs", "etc")).is_err());
+        assert!(verify_command(&key, "zs1reply", "ls /docs")
+            .unwrap_err()
+            .contains("must be signed"));
+        let auth = identity.sign_auth("zs1reply", "ls /docs");
+        assert!(verify_command(&key, "zs1reply", &format!("ls /docs\nSIG:{}", auth)).is_err());
+    }
+
+    #[test]
+    fn test_identity_key_persists_and_rejects_bad_public_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(IDENTITY_KEY_FILE);
+        let first = IdentityKey::load_or_create(&path).unwrap();
+        let second = IdentityKey::load_or_create(&path).unwrap();
+        assert_eq!(first.public_key_hex(), second.public_key_hex());
+
+        assert!(parse_public_key(&first.public_key_hex()).is_ok());
+        assert!(parse_public_key("abcd").is_err());
+        assert!(parse_public_key("not hex").is_err());
+    }
+}
@@ -1,8 +1,54 @@
+pub mod audit;
 pub mod auth;
+pub mod backup;
+pub mod builder;
+pub mod capability;
+pub mod challenge;
+pub mod chat;
+pub mod commands;
 pub mod config;
+pub mod confirm;
+pub mod conversation;
 pub mod coordinator;
+pub mod crypto;
+pub mod cursor;
+pub mod daemon;
+pub mod diff;
+pub mod e2e;
+pub mod events;
+pub mod federation;
+pub mod fee;
 pub mod filesystem;
+pub mod guest;
+pub mod handshake;
+pub mod health;
+pub mod identity;
+pub mod lockout;
 pub mod memo_decoder;
 pub mod message;
+pub mod metrics;
+pub mod moderation;
+pub mod outbound;
+pub mod ownership;
+pub mod policy;
+pub mod priority;
+pub mod processed;
+pub mod profile;
+pub mod quota;
+pub mod rate_limit;
+pub mod relay;
+pub mod response;
+pub mod retry;
+pub mod roles;
+pub mod scheduler;
+pub mod session_store;
+pub mod shard;
+pub mod signing;
+pub mod state;
+pub mod status;
+pub mod tokenizer;
+pub mod tx_status;
 pub mod user_session;
+pub mod wallet;
+pub mod watch;
 pub mod zingo_wrapper;
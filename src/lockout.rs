@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const MAX_FEE_DOUBLINGS: u32 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LockoutConfig {
+    pub enabled: bool,
+    pub max_failures: u32,
+    pub base_lockout_secs: u64,
+    pub max_lockout_secs: u64,
+    pub reset_after_secs: u64,
+    pub retry_fee_zatoshis: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            enabled: true,
+            max_failures: 5,
+            base_lockout_secs: 60,
+            max_lockout_secs: 86400,
+            reset_after_secs: 3600,
+            retry_fee_zatoshis: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FailureRecord {
+    failures: u32,
+    last_failure: u64,
+    locked_until: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct AuthLockouts {
+    records: HashMap<String, FailureRecord>,
+}
+
+impl AuthLockouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn current(&self, user: &str, config: &LockoutConfig, now: u64) -> Option<&FailureRecord> {
+        self.records.get(user).filter(|record| {
+            now < record.locked_until
+                || now.saturating_sub(record.last_failure) < config.reset_after_secs
+        })
+    }
+
+    pub fn failures(&self, user: &str, config: &LockoutConfig, now: u64) -> u32 {
+        self.current(user, config, now)
+            .map_or(0, |record| record.failures)
+    }
+
+    pub fn retry_fee(&self, user: &str, config: &LockoutConfig, now: u64) -> u64 {
+        match self.failures(user, config, now) {
+            0 => 0,
+            failures => config
+                .retry_fee_zatoshis
+                .saturating_mul(1 << (failures - 1).min(MAX_FEE_DOUBLINGS)),
+        }
+    }
+
+    pub fn check(
+        &self,
+        user: &str,
+        config: &LockoutConfig,
+        now: u64,
+        paid: u64,
+    ) -> Result<(), String> {
+        if !config.enabled {
+            return Ok(());
+        }
+        if let Some(record) = self.current(user, config, now) {
+            if now < record.locked_until {
+                return Err(format!(
+                    "Rate limit: too many failed AUTH attempts; try again in {} seconds",
+                    record.locked_until - now
+                ));
+            }
+        }
+
+        let required = self.retry_fee(user, config, now);
+        if paid < required {
+            return Err(format!(
+                "Payment required: AUTH retry after {} failures costs {} zatoshis (received {})",
+                self.failures(user, config, now),
+                required,
+                paid
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn record_failure(&mut self, user: &str, config: &LockoutConfig, now: u64) -> Option<u64> {
+        if !config.enabled {
+            return None;
+        }
+        let failures = self.failures(user, config, now) + 1;
+        let record = self.records.entry(user.to_string()).or_default();
+        record.failures = failures;
+        record.last_failure = now;
+        if failures < config.max_failures.max(1) {
+            return None;
+        }
+
+        let doublings = (failures - config.max_failures.max(1)).min(MAX_FEE_DOUBLINGS);
+        let lockout = config
+            .base_lockout_secs
+            .saturating_mul(1 << doublings)
+            .min(config.max_lockout_secs);
+        record.locked_until = now + lockout;
+        Some(record.locked_until)
+    }
+
+    pub fn record_success(&mut self, user: &str) {
+        self.records.remove(user);
+    }
+
+    pub fn locked(&self, now: u64) -> usize {
+        self.records
+            .values()
+            .filter(|record| now < record.locked_until)
+            .count()
+    }
+
+    pub fn prune(&mut self, config: &LockoutConfig, now: u64) -> usize {
+        let before = self.records.len();
+        self.records.retain(|_, record| {
+            now < record.locked_until
+                || now.saturating_sub(record.last_failure) < config.reset_after_secs
+        });
+        before - self.records.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockouts_escalate_after_repeated_failures() {
+        let config = LockoutConfig {
+            max_failures: 3,
+            base_lockout_secs: 60,
+            max_lockout_secs: 200,
+            ..LockoutConfig::default()
+        };
+        let mut lockouts = AuthLockouts::new();
+        assert_eq!(lockouts.record_failure("zs1mallory", &config, 100), None);
+        assert_eq!(lockouts.record_failure("zs1mallory", &config, 101), None);
+        assert!(lockouts.check("zs1mallory", &config, 102, 0).is_ok());
+
+        assert_eq!(
+            lockouts.record_failure("zs1mallory", &config, 102),
+            Some(162)
+        );
+        assert!(lockouts
+            .check("zs1mallory", &config, 150, 0)
+            .unwrap_err()
+            .starts_with("Rate limit"));
+        assert!(lockouts.check("zs1mallory", &config, 162, 0).is_ok());
+        assert_eq!(
+            lockouts.record_failure("zs1mallory", &config, 162),
+            Some(282)
+        );
+        assert_eq!(
+            lockouts.record_failure("zs1mallory", &config, 300),
+            Some(500)
+        );
+        assert_eq!(lockouts.locked(300), 1);
+        assert!(lockouts.check("zs1alice", &config, 300, 0).is_ok());
+
+        lockouts.record_success("zs1mallory");
+        assert!(lockouts.check("zs1mallory", &config, 300, 0).is_ok());
+    }
+
+    #[test]
+    fn test_retry_fee_doubles_and_failures_reset() {
+        let config = LockoutConfig {
+            max_failures: 10,
+            reset_after_secs: 100,
+            retry_fee_zatoshis: 1000,
+            ..LockoutConfig::default()
+        };
+        let mut lockouts = AuthLockouts::new();
+        assert!(lockouts.check("zs1mallory", &config, 0, 0).is_ok());
+        lockouts.record_failure("zs1mallory", &config, 10);
+        lockouts.record_failure("zs1mallory", &config, 20);
+        assert_eq!(lockouts.retry_fee("zs1mallory", &config, 30), 2000);
+        assert!(lockouts
+            .check("zs1mallory", &config, 30, 1999)
+            .unwrap_err()
+            .starts_with("Payment required"));
+        assert!(lockouts.check("zs1mallory", &config, 30, 2000).is_ok());
+
+        assert_eq!(lockouts.failures("zs1mallory", &config, 120), 0);
+        assert!(lockouts.check("zs1mallory", &config, 120, 0).is_ok());
+        assert_eq!(lockouts.prune(&config, 120), 1);
+    }
+}
@@ -1,6 +1,398 @@
+use crate::crypto;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::str;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{skeleton, RestrictionLevel, RestrictionLevelDetection};
 
-const MAX_MEMO_SIZE: usize = 512;
+pub const MAX_MEMO_SIZE: usize = 512;
+pub const FRAGMENT_PREFIX: &str = "ZBFRAG:";
+const FRAGMENT_ID_BYTES: usize = 4;
+pub const MAX_FRAGMENT_PARTS: usize = 999;
+pub const FRAGMENT_HEADER_MAX: usize =
+    FRAGMENT_PREFIX.len() + 2 * FRAGMENT_ID_BYTES + ":999/999:".len();
+pub const MIN_FRAGMENT_MEMO: usize = FRAGMENT_HEADER_MAX + 4;
+pub const BINARY_PREFIX: &str = "ZB64:";
+pub const BATCH_PREFIX: &str = "ZBATCH:";
+pub const CHECKSUM_MARKER: &str = "\nCRC:";
+pub const HEX_MEMO_PREFIX: &str = "0x";
+const NO_MEMO: u8 = 0xf6;
+const MAX_TEXT_LEAD_BYTE: u8 = 0xf4;
+const CHECKSUM_BYTES: usize = 4;
+pub const CHECKSUM_OVERHEAD: usize = CHECKSUM_MARKER.len() + 2 * CHECKSUM_BYTES;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct ChecksumConfig {
+    pub require: bool,
+}
+
+fn checksum(body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zatboard_memo\n");
+    hasher.update(body.as_bytes());
+    crypto::to_hex(&hasher.finalize()[..CHECKSUM_BYTES])
+}
+
+pub fn append_checksum(memo: &str) -> String {
+    format!("{}{}{}", memo, CHECKSUM_MARKER, checksum(memo))
+}
+
+pub fn verify_checksum<'a>(memo: &'a str, config: &ChecksumConfig) -> Result<&'a str, String> {
+    let Some((body, sum)) = memo.rsplit_once(CHECKSUM_MARKER) else {
+        if config.require {
+            return Err(
+                "Corrupted memo: checksum missing, the memo may have been truncated; resend it"
+                    .to_string(),
+            );
+        }
+        return Ok(memo);
+    };
+    if sum.trim_end_matches(|c: char| c == '\0' || c.is_whitespace()) != checksum(body) {
+        return Err(
+            "Corrupted memo: checksum mismatch, the memo was truncated or altered in transit; resend it"
+                .to_string(),
+        );
+    }
+    Ok(body)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemoPayload {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl MemoPayload {
+    pub fn into_text(self) -> Result<String, String> {
+        match self {
+            MemoPayload::Text(text) => Ok(text),
+            MemoPayload::Binary(bytes) => String::from_utf8(bytes)
+                .map_err(|_| "Invalid memo: binary payload is not UTF-8 text".to_string()),
+        }
+    }
+}
+
+pub fn encode_batch<S: AsRef<str>>(commands: &[S]) -> String {
+    let mut frame = format!("{}{}:", BATCH_PREFIX, commands.len());
+    for command in commands {
+        let command = command.as_ref();
+        frame.push_str(&format!("{}:{}", command.len(), command));
+    }
+    frame
+}
+
+pub fn decode_batch(memo: &str) -> Option<Result<Vec<String>, String>> {
+    let rest = memo.strip_prefix(BATCH_PREFIX)?;
+    let invalid = |reason: &str| Err(format!("Invalid batch frame: {}", reason));
+    let Some((count, mut rest)) = rest.split_once(':') else {
+        return Some(invalid("missing command count"));
+    };
+    let Ok(count) = count.parse::<usize>() else {
+        return Some(invalid("command count is not a number"));
+    };
+    let mut commands = Vec::new();
+    while !rest.is_empty() {
+        let Some((len, tail)) = rest.split_once(':') else {
+            return Some(invalid("missing command length"));
+        };
+        let Some(command) = len.parse::<usize>().ok().and_then(|len| tail.get(..len)) else {
+            return Some(invalid("command length does not match the memo"));
+        };
+        if command.trim().is_empty() {
+            return Some(invalid("empty command"));
+        }
+        commands.push(command.to_string());
+        rest = &tail[command.len()..];
+        if commands.len() > count {
+            break;
+        }
+    }
+    if commands.len() != count {
+        return Some(Err(format!(
+            "Invalid batch frame: expected {} commands, found {}",
+            count,
+            commands.len()
+        )));
+    }
+    Some(Ok(commands))
+}
+
+pub fn encode_binary(bytes: &[u8]) -> String {
+    format!("{}{}", BINARY_PREFIX, URL_SAFE_NO_PAD.encode(bytes))
+}
+
+pub fn decode_payload(memo: &str) -> Result<MemoPayload, String> {
+    match memo.strip_prefix(BINARY_PREFIX) {
+        Some(encoded) => URL_SAFE_NO_PAD
+            .decode(encoded.trim_end())
+            .map(MemoPayload::Binary)
+            .map_err(|e| format!("Invalid memo: bad {} payload: {}", BINARY_PREFIX, e)),
+        None => Ok(MemoPayload::Text(memo.to_string())),
+    }
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+fn is_invisible(c: char) -> bool {
+    is_bidi_control(c)
+        || matches!(
+            c,
+            '\u{00AD}'
+                | '\u{034F}'
+                | '\u{115F}'
+                | '\u{1160}'
+                | '\u{17B4}'
+                | '\u{17B5}'
+                | '\u{180B}'..='\u{180F}'
+                | '\u{200B}'..='\u{200D}'
+                | '\u{2028}'
+                | '\u{2029}'
+                | '\u{2060}'..='\u{2065}'
+                | '\u{206A}'..='\u{206F}'
+                | '\u{3164}'
+                | '\u{FE00}'..='\u{FE0F}'
+                | '\u{FEFF}'
+                | '\u{FFA0}'
+                | '\u{FFF0}'..='\u{FFF8}'
+                | '\u{E0000}'..='\u{E0FFF}'
+        )
+}
+
+pub fn sanitize_text(text: &str) -> String {
+    text.nfc()
+        .filter(|&c| !is_bidi_control(c) && (!c.is_control() || c == '\n' || c == '\t'))
+        .collect()
+}
+
+pub fn check_file_name(name: &str) -> Result<(), String> {
+    if let Some(c) = name.chars().find(|&c| c.is_control() || is_invisible(c)) {
+        return Err(format!(
+            "Invalid file name {:?}: contains the invisible character U+{:04X}",
+            name, c as u32
+        ));
+    }
+    if name.trim() != name {
+        return Err(format!(
+            "Invalid file name {:?}: leading or trailing whitespace",
+            name
+        ));
+    }
+    if !name.nfc().eq(name.chars()) {
+        return Err(format!("Invalid file name {:?}: not NFC normalized", name));
+    }
+    let mixed = name
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| !word.check_restriction_level(RestrictionLevel::ModeratelyRestrictive));
+    if mixed {
+        return Err(format!(
+            "Invalid file name {:?}: mixes scripts that look alike, such as Latin and Cyrillic",
+            name
+        ));
+    }
+    Ok(())
+}
+
+pub fn confusable_with<'a>(
+    name: &str,
+    existing: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let key: String = skeleton(name).collect();
+    existing.into_iter().find(|&other| {
+        other != name && !(name.is_ascii() && other.is_ascii()) && skeleton(other).eq(key.chars())
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FragmentConfig {
+    pub timeout_secs: u64,
+    pub max_fragments: usize,
+    pub max_pending: usize,
+}
+
+impl Default for FragmentConfig {
+    fn default() -> Self {
+        FragmentConfig {
+            timeout_secs: 600,
+            max_fragments: 32,
+            max_pending: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment<'a> {
+    pub id: &'a str,
+    pub index: usize,
+    pub total: usize,
+    pub body: &'a str,
+}
+
+impl<'a> Fragment<'a> {
+    pub fn parse(memo: &'a str) -> Option<Result<Self, String>> {
+        let rest = memo.strip_prefix(FRAGMENT_PREFIX)?;
+        let invalid = || {
+            Err(format!(
+                "Invalid fragment header. Use {}<id>:<n>/<total>:<data>",
+                FRAGMENT_PREFIX
+            ))
+        };
+        let mut parts = rest.splitn(3, ':');
+        let (Some(id), Some(position), Some(body)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Some(invalid());
+        };
+        let Some((index, total)) = position.split_once('/') else {
+            return Some(invalid());
+        };
+        let (Ok(index), Ok(total)) = (index.parse::<usize>(), total.parse::<usize>()) else {
+            return Some(invalid());
+        };
+        if id.is_empty() || index == 0 || index > total {
+            return Some(invalid());
+        }
+        Some(Ok(Fragment {
+            id,
+            index,
+            total,
+            body,
+        }))
+    }
+}
+
+pub fn new_fragment_id() -> String {
+    let mut id = [0u8; FRAGMENT_ID_BYTES];
+    OsRng.fill_bytes(&mut id);
+    crypto::to_hex(&id)
+}
+
+pub fn fragment_payload(payload: &str, id: &str, max_len: usize) -> Vec<String> {
+    if payload.len() <= max_len {
+        return vec![payload.to_string()];
+    }
+
+    let mut total_digits = 1;
+    loop {
+        let header_len = format!(
+            "{}{}:{2}/{2}:",
+            FRAGMENT_PREFIX,
+            id,
+            "9".repeat(total_digits)
+        )
+        .len();
+        let chunks = chunk_text(payload, max_len - header_len);
+        if chunks.len().to_string().len() <= total_digits {
+            let total = chunks.len();
+            return chunks
+                .into_iter()
+                .enumerate()
+                .map(|(i, chunk)| {
+                    format!("{}{}:{}/{}:{}", FRAGMENT_PREFIX, id, i + 1, total, chunk)
+                })
+                .collect();
+        }
+        total_digits += 1;
+    }
+}
+
+#[derive(Debug)]
+struct PartialPayload {
+    total: usize,
+    parts: BTreeMap<usize, String>,
+    first_seen: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    partial: HashMap<(String, String), PartialPayload>,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.partial.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partial.is_empty()
+    }
+
+    pub fn accept(
+        &mut self,
+        sender: &str,
+        fragment: &Fragment,
+        config: &FragmentConfig,
+        now: u64,
+    ) -> Result<Option<String>, String> {
+        if fragment.total > config.max_fragments {
+            return Err(format!(
+                "Invalid fragment: {} parts exceeds the limit of {}",
+                fragment.total, config.max_fragments
+            ));
+        }
+        let key = (sender.to_string(), fragment.id.to_string());
+        if !self.partial.contains_key(&key) {
+            let pending = self
+                .partial
+                .keys()
+                .filter(|(pending_sender, _)| pending_sender == sender)
+                .count();
+            if pending >= config.max_pending {
+                return Err(format!(
+                    "Rate limit: {} fragmented memos already waiting for missing parts",
+                    pending
+                ));
+            }
+        }
+        let partial = self.partial.entry(key.clone()).or_insert(PartialPayload {
+            total: fragment.total,
+            parts: BTreeMap::new(),
+            first_seen: now,
+        });
+        if partial.total != fragment.total {
+            self.partial.remove(&key);
+            return Err(format!(
+                "Invalid fragment: {} changed its part count",
+                fragment.id
+            ));
+        }
+        partial
+            .parts
+            .insert(fragment.index, fragment.body.to_string());
+        if partial.parts.len() < partial.total {
+            return Ok(None);
+        }
+        let partial = self.partial.remove(&key).expect("entry checked above");
+        Ok(Some(partial.parts.into_values().collect()))
+    }
+
+    pub fn prune(&mut self, config: &FragmentConfig, now: u64) -> Vec<(String, String, usize)> {
+        let expired: Vec<(String, String)> = self
+            .partial
+            .iter()
+            .filter(|(_, partial)| now.saturating_sub(partial.first_seen) >= config.timeout_secs)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| {
+                let partial = self.partial.remove(&key)?;
+                Some((key.0, key.1, partial.total - partial.parts.len()))
+            })
+            .collect()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ZcashMemo {
@@ -16,6 +408,43 @@ impl ZcashMemo {
         ZcashMemo { raw_bytes, text }
     }
 
+    pub fn from_wire(memo: &str) -> Self {
+        match Self::parse_hex(memo) {
+            Some(bytes) => Self::new(&bytes),
+            None => Self::new(memo.as_bytes()),
+        }
+    }
+
+    fn parse_hex(memo: &str) -> Option<Vec<u8>> {
+        let digits = memo.trim().trim_end_matches('\0');
+        let (digits, prefixed) = match digits.strip_prefix(HEX_MEMO_PREFIX) {
+            Some(digits) => (digits, true),
+            None => (digits, false),
+        };
+        if !(prefixed || digits.len() == 2 * MAX_MEMO_SIZE)
+            || digits.is_empty()
+            || !digits.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return None;
+        }
+        crypto::from_hex(digits).ok()
+    }
+
+    pub fn wire_text(&self) -> Result<Option<&str>, String> {
+        match (&self.text, Self::trim_padding(&self.raw_bytes)) {
+            (_, []) | (_, [NO_MEMO]) => Ok(None),
+            (Some(text), _) => Ok(Some(text)),
+            (None, [lead, ..]) if *lead > MAX_TEXT_LEAD_BYTE => Err(format!(
+                "Invalid memo: binary memo (type byte 0x{:02x}) carries no text",
+                lead
+            )),
+            (None, bytes) => Err(format!(
+                "Invalid memo: not UTF-8 text (bad byte at offset {})",
+                str::from_utf8(bytes).err().map_or(0, |e| e.valid_up_to())
+            )),
+        }
+    }
+
     pub fn from_string(message: &str) -> Result<Self, String> {
         let bytes = message.as_bytes();
         if bytes.len() > MAX_MEMO_SIZE {
@@ -32,14 +461,27 @@ impl ZcashMemo {
         })
     }
 
-    fn decode_text(bytes: &[u8]) -> Option<String> {
-        let trimmed = bytes
+    fn trim_padding(bytes: &[u8]) -> &[u8] {
+        bytes
             .iter()
             .rposition(|&b| b != 0)
             .map(|pos| &bytes[..=pos])
-            .unwrap_or(&[]);
+            .unwrap_or(&[])
+    }
+
+    fn decode_text(bytes: &[u8]) -> Option<String> {
+        str::from_utf8(Self::trim_padding(bytes))
+            .ok()
+            .map(|s| s.to_string())
+    }
 
-        str::from_utf8(trimmed).ok().map(|s| s.to_string())
+    pub fn payload(&self) -> Result<MemoPayload, String> {
+        match &self.text {
+            Some(text) => decode_payload(text),
+            None => Ok(MemoPayload::Binary(
+                Self::trim_padding(&self.raw_bytes).to_vec(),
+            )),
+        }
     }
 
     pub fn encode_for_transmission(&self) -> Vec<u8> {
@@ -49,6 +491,53 @@ impl ZcashMemo {
     }
 }
 
+pub fn split_into_memos(text: &str) -> Vec<String> {
+    split_into_memos_within(text, MAX_MEMO_SIZE)
+}
+
+pub fn split_into_memos_within(text: &str, max_len: usize) -> Vec<String> {
+    if text.len() <= max_len {
+        return vec![text.to_string()];
+    }
+
+    let mut total_digits = 1;
+    loop {
+        let prefix_len = format!("[{0}/{0}] ", "9".repeat(total_digits)).len();
+        let chunks = chunk_text(text, max_len - prefix_len);
+        if chunks.len().to_string().len() <= total_digits {
+            let total = chunks.len();
+            return chunks
+                .into_iter()
+                .enumerate()
+                .map(|(i, chunk)| format!("[{}/{}] {}", i + 1, total, chunk))
+                .collect();
+        }
+        total_digits += 1;
+    }
+}
+
+pub fn chunk_text(text: &str, budget: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+
+    while rest.len() > budget {
+        let mut end = budget;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let split = match rest[..end].rfind('\n') {
+            Some(newline) if newline > budget / 2 => newline + 1,
+            _ => end,
+        };
+        chunks.push(&rest[..split]);
+        rest = &rest[split..];
+    }
+    chunks.push(rest);
+
+    chunks
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -77,4 +566,291 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_split_short_text_is_untouched() {
+        assert_eq!(split_into_memos("ls /home"), vec!["ls /home".to_string()]);
+    }
+
+    #[test]
+    fn test_split_long_text_into_numbered_memos() {
+        let line = "x".repeat(99);
+        let text = [line.as_str(); 12].join("\n");
+        let memos = split_into_memos(&text);
+
+        assert_eq!(memos.len(), 3);
+        assert!(memos[0].starts_with("[1/3] "));
+        assert!(memos[2].starts_with("[3/3] "));
+        assert!(memos.iter().all(|memo| memo.len() <= MAX_MEMO_SIZE));
+        assert!(memos[0].ends_with('\n'));
+
+        let rejoined: String = memos
+            .iter()
+            .map(|memo| memo.split_once("] ").unwrap().1)
+            .collect();
+        assert_eq!(rejoined, text);
+    }
+
+    #[test]
+    fn test_split_respects_utf8_boundaries() {
+        let text = "é".repeat(1000);
+        let memos = split_into_memos(&text);
+
+        assert!(memos.iter().all(|memo| memo.len() <= MAX_MEMO_SIZE));
+        let rejoined: String = memos
+            .iter()
+            .map(|memo| memo.split_once("] ").unwrap().1)
+            .collect();
+        assert_eq!(rejoined, text);
+    }
+
+    #[test]
+    fn test_fragments_fit_memos_and_reassemble_out_of_order() {
+        let payload = "touch /big.txt ".to_string() + &"é".repeat(700);
+        let memos = fragment_payload(&payload, "ab12cd34", MAX_MEMO_SIZE);
+        assert_eq!(memos.len(), 3);
+        assert!(memos.iter().all(|memo| memo.len() <= MAX_MEMO_SIZE));
+        assert!(memos[0].starts_with("ZBFRAG:ab12cd34:1/3:touch"));
+        assert_eq!(
+            fragment_payload("ls /", "ab12cd34", MAX_MEMO_SIZE),
+            vec!["ls /"]
+        );
+
+        let config = FragmentConfig::default();
+        let mut reassembler = Reassembler::new();
+        for memo in [&memos[2], &memos[0], &memos[0]] {
+            let fragment = Fragment::parse(memo).unwrap().unwrap();
+            assert_eq!(
+                reassembler.accept("zs1alice", &fragment, &config, 100),
+                Ok(None)
+            );
+        }
+        let last = Fragment::parse(&memos[1]).unwrap().unwrap();
+        assert!(reassembler
+            .accept("zs1bob", &last, &config, 100)
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            reassembler.accept("zs1alice", &last, &config, 100),
+            Ok(Some(payload))
+        );
+        assert_eq!(reassembler.len(), 1);
+    }
+
+    #[test]
+    fn test_fragment_headers_limits_and_timeouts() {
+        assert!(Fragment::parse("ls /").is_none());
+        assert!(Fragment::parse("ZBFRAG:id:0/2:x").unwrap().is_err());
+        assert!(Fragment::parse("ZBFRAG:id:3/2:x").unwrap().is_err());
+        assert!(Fragment::parse("ZBFRAG:id:1-2:x").unwrap().is_err());
+        assert_eq!(
+            Fragment::parse("ZBFRAG:id:2/2:a:b").unwrap().unwrap().body,
+            "a:b"
+        );
+
+        let config = FragmentConfig {
+            timeout_secs: 60,
+            max_fragments: 4,
+            max_pending: 2,
+        };
+        let mut reassembler = Reassembler::new();
+        let too_many = Fragment::parse("ZBFRAG:x:1/5:a").unwrap().unwrap();
+        assert!(reassembler
+            .accept("zs1alice", &too_many, &config, 100)
+            .is_err());
+        for id in ["a", "b"] {
+            let memo = format!("ZBFRAG:{}:1/2:x", id);
+            let fragment = Fragment::parse(&memo).unwrap().unwrap();
+            reassembler
+                .accept("zs1alice", &fragment, &config, 100)
+                .unwrap();
+        }
+        let third = Fragment::parse("ZBFRAG:c:1/2:x").unwrap().unwrap();
+        assert!(reassembler
+            .accept("zs1alice", &third, &config, 100)
+            .unwrap_err()
+            .starts_with("Rate limit"));
+
+        let recount = Fragment::parse("ZBFRAG:a:2/3:y").unwrap().unwrap();
+        assert!(reassembler
+            .accept("zs1alice", &recount, &config, 110)
+            .is_err());
+        let dropped = reassembler.prune(&config, 160);
+        assert_eq!(dropped, vec![("zs1alice".to_string(), "b".to_string(), 1)]);
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn test_binary_payloads_round_trip_and_are_detected() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let memo = encode_binary(&bytes);
+        assert!(memo.starts_with(BINARY_PREFIX));
+        assert!(memo.is_ascii() && !memo.contains(['+', '/', '=']));
+        assert_eq!(
+            decode_payload(&memo),
+            Ok(MemoPayload::Binary(bytes.clone()))
+        );
+        assert_eq!(
+            decode_payload("ls /home"),
+            Ok(MemoPayload::Text("ls /home".to_string()))
+        );
+        assert!(decode_payload("ZB64:not*base64").is_err());
+
+        let command = decode_payload(&encode_binary(b"cat /a.txt")).unwrap();
+        assert_eq!(command.into_text().unwrap(), "cat /a.txt");
+        assert!(MemoPayload::Binary(vec![0xff, 0xfe]).into_text().is_err());
+
+        let raw = ZcashMemo::new(&[0xff, 0x00, 0x01, 0x00, 0x00]);
+        assert!(raw.text.is_none());
+        assert_eq!(
+            raw.payload(),
+            Ok(MemoPayload::Binary(vec![0xff, 0x00, 0x01]))
+        );
+        let wrapped = ZcashMemo::from_string(&memo).unwrap();
+        assert_eq!(wrapped.payload(), Ok(MemoPayload::Binary(bytes)));
+    }
+
+    #[test]
+    fn test_batch_frames_round_trip_and_reject_bad_lengths() {
+        let commands = ["mkdir /a", "touch /a/x \"hi; there\"", "ls /a"];
+        let frame = encode_batch(&commands);
+        assert_eq!(
+            frame,
+            "ZBATCH:3:8:mkdir /a22:touch /a/x \"hi; there\"5:ls /a"
+        );
+        assert_eq!(
+            decode_batch(&frame),
+            Some(Ok(commands.map(String::from).to_vec()))
+        );
+        assert_eq!(
+            decode_batch(&encode_batch(&["écrire /é"])),
+            Some(Ok(vec!["écrire /é".to_string()]))
+        );
+        assert!(decode_batch("ls /").is_none());
+
+        for (frame, reason) in [
+            ("ZBATCH:x:", "not a number"),
+            ("ZBATCH:2:5:ls /a", "expected 2 commands, found 1"),
+            ("ZBATCH:1:9:ls /a", "length does not match"),
+            ("ZBATCH:1:5:ls /a4:ls /", "expected 1 commands, found 2"),
+            ("ZBATCH:1:1: ", "empty command"),
+            ("ZBATCH:1:1:é", "length does not match"),
+            ("ZBATCH:1:ls", "missing command length"),
+        ] {
+            let error = decode_batch(frame).unwrap().unwrap_err();
+            assert!(error.contains(reason), "{}: {}", frame, error);
+        }
+    }
+
+    #[test]
+    fn test_hex_and_padded_wire_memos_decode_to_text() {
+        let padded = ZcashMemo::from_string("ls /docs")
+            .unwrap()
+            .encode_for_transmission();
+        let hex = crypto::to_hex(&padded);
+        assert_eq!(ZcashMemo::from_wire(&hex).wire_text(), Ok(Some("ls /docs")));
+        assert_eq!(
+            ZcashMemo::from_wire(&hex.to_uppercase()).wire_text(),
+            Ok(Some("ls /docs"))
+        );
+        assert_eq!(
+            ZcashMemo::from_wire("0x636174202f612e747874").wire_text(),
+            Ok(Some("cat /a.txt"))
+        );
+        assert_eq!(
+            ZcashMemo::from_wire("cat /a.txt\0\0\0").wire_text(),
+            Ok(Some("cat /a.txt"))
+        );
+        assert_eq!(ZcashMemo::from_wire("cafe").wire_text(), Ok(Some("cafe")));
+
+        let mut empty = vec![NO_MEMO];
+        empty.resize(MAX_MEMO_SIZE, 0);
+        assert_eq!(
+            ZcashMemo::from_wire(&crypto::to_hex(&empty)).wire_text(),
+            Ok(None)
+        );
+        assert_eq!(ZcashMemo::from_wire("\0\0").wire_text(), Ok(None));
+
+        assert!(ZcashMemo::from_wire("0xff0001")
+            .wire_text()
+            .unwrap_err()
+            .contains("binary memo"));
+        assert_eq!(
+            ZcashMemo::from_wire("0x6c73c3").wire_text(),
+            Err("Invalid memo: not UTF-8 text (bad byte at offset 2)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sanitize_text_normalizes_and_strips_controls() {
+        assert_eq!(
+            sanitize_text("touch /cafe\u{301}.txt"),
+            "touch /caf\u{e9}.txt"
+        );
+        assert_eq!(
+            sanitize_text("write /a.txt x\u{7}y\r\n\tz\u{202E}txt.exe"),
+            "write /a.txt xy\n\tztxt.exe"
+        );
+        assert_eq!(
+            sanitize_text("chat /lobby 👩\u{200D}💻"),
+            "chat /lobby 👩\u{200D}💻"
+        );
+    }
+
+    #[test]
+    fn test_file_names_reject_invisible_and_confusable_names() {
+        for name in [
+            "notes.txt",
+            "résumé.pdf",
+            "Привет.txt",
+            "報告-2024.md",
+            "my file",
+        ] {
+            assert!(check_file_name(name).is_ok(), "{}", name);
+        }
+        assert!(check_file_name("a\u{200B}b")
+            .unwrap_err()
+            .contains("U+200B"));
+        assert!(check_file_name(" padded").is_err());
+        assert!(check_file_name("cafe\u{301}").is_err());
+        assert!(check_file_name("p\u{0430}ypal")
+            .unwrap_err()
+            .contains("mixes scripts"));
+
+        let existing = ["paypal", "file1", "notes"];
+        assert_eq!(
+            confusable_with("\u{0440}\u{0430}\u{0443}\u{0440}\u{0430}l", existing),
+            Some("paypal")
+        );
+        assert_eq!(confusable_with("filel", existing), None);
+        assert_eq!(confusable_with("notes", existing), None);
+        assert_eq!(confusable_with("other", existing), None);
+    }
+
+    #[test]
+    fn test_checksums_catch_truncated_and_mangled_memos() {
+        let config = ChecksumConfig::default();
+        let memo = append_checksum("ZB64:bHMgL2hvbWU");
+        assert_eq!(memo.len(), "ZB64:bHMgL2hvbWU".len() + CHECKSUM_OVERHEAD);
+        assert_eq!(verify_checksum(&memo, &config), Ok("ZB64:bHMgL2hvbWU"));
+        assert_eq!(
+            verify_checksum(&format!("{}\0\0", memo), &config),
+            Ok("ZB64:bHMgL2hvbWU")
+        );
+        assert!(verify_checksum(&format!("{}x", memo), &config)
+            .unwrap_err()
+            .starts_with("Corrupted memo: checksum mismatch"));
+
+        let mangled = memo.replacen("bHMg", "bHMh", 1);
+        assert!(verify_checksum(&mangled, &config).is_err());
+        let truncated = &memo[..memo.len() - 1];
+        assert!(verify_checksum(truncated, &config).is_err());
+
+        assert_eq!(verify_checksum("ls /home", &config), Ok("ls /home"));
+        let strict = ChecksumConfig { require: true };
+        assert!(verify_checksum("ls /home", &strict)
+            .unwrap_err()
+            .contains("checksum missing"));
+        assert!(verify_checksum(&append_checksum("ls /home"), &strict).is_ok());
+    }
 }
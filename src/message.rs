@@ -1,6 +1,49 @@
+use crate::memo_decoder::{self, MemoPayload, ZcashMemo};
+use crate::{crypto, response, signing};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+pub const COMPACT_MAGIC: &[u8] = b"ZM1";
+pub const REPLY_TO_PREFIX: &str = "REPLY-TO:";
+pub const UNKNOWN_SENDER: &str = "client_unknown";
+pub const JSON_PREFIX: &str = "ZBJ:";
+pub const JSON_VERSION: u8 = 1;
+pub const FLAG_ZSTD: u8 = 0x01;
+pub const COMPRESSION_CODEC: &str = "zstd";
+pub const COMPRESS_THRESHOLD: usize = 128;
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024;
+const ZSTD_LEVEL: i32 = 19;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CompactEnvelope {
+    memo: String,
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+    timestamp: Option<u64>,
+    #[serde(default)]
+    expires_at: Option<u64>,
+    #[serde(default)]
+    priority: Option<u8>,
+    #[serde(default)]
+    reply_to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    memo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reply_to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sig: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    v: u8,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub sender_address: String,
@@ -9,6 +52,20 @@ pub struct Message {
     pub txid: Option<String>,
     pub signature: Option<String>,
     pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub amount_zatoshis: u64,
+    #[serde(default)]
+    pub output_index: Option<u32>,
+    #[serde(default)]
+    pub block_height: Option<u64>,
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub priority: Option<u8>,
+    #[serde(default)]
+    pub reply_to: Option<String>,
 }
 
 impl Message {
@@ -20,6 +77,13 @@ impl Message {
             txid: None,
             signature: None,
             timestamp: None,
+            amount_zatoshis: 0,
+            output_index: None,
+            block_height: None,
+            in_reply_to: None,
+            expires_at: None,
+            priority: None,
+            reply_to: None,
         }
     }
 
@@ -31,6 +95,13 @@ impl Message {
             txid: Some(txid),
             signature: None,
             timestamp: None,
+            amount_zatoshis: 0,
+            output_index: None,
+            block_height: None,
+            in_reply_to: None,
+            expires_at: None,
+            priority: None,
+            reply_to: None,
         }
     }
 
@@ -70,51 +141,354 @@ impl Message {
         format!("{:x}", hasher.finalize())
     }
 
+    pub fn with_output_index(mut self, output_index: u32) -> Self {
+        self.output_index = Some(output_index);
+        self
+    }
+
+    pub fn idempotency_key(&self) -> Option<String> {
+        self.txid
+            .as_ref()
+            .map(|txid| format!("{}:{}", txid, self.output_index.unwrap_or(0)))
+    }
+
+    pub fn fragments(&self, max_len: usize) -> Vec<Message> {
+        memo_decoder::fragment_payload(&self.memo_text, &memo_decoder::new_fragment_id(), max_len)
+            .into_iter()
+            .map(|memo_text| Message {
+                memo_text,
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    pub fn fits_in_memo(&self, overhead: usize) -> bool {
+        self.memo_text.len().saturating_add(overhead) <= memo_decoder::MAX_MEMO_SIZE
+    }
+
+    pub fn split_for_memo(&self, overhead: usize) -> Result<Vec<Message>, String> {
+        if self.fits_in_memo(overhead) {
+            return Ok(vec![self.clone()]);
+        }
+        let max_len = memo_decoder::MAX_MEMO_SIZE.saturating_sub(overhead);
+        if max_len < memo_decoder::MIN_FRAGMENT_MEMO {
+            return Err(format!(
+                "Memo too long: {} bytes, and {} bytes of overhead leave no room to split it",
+                self.memo_text.len(),
+                overhead
+            ));
+        }
+        let body = (max_len - memo_decoder::FRAGMENT_HEADER_MAX) / 2;
+        if self.memo_text.len().div_ceil(body) > memo_decoder::MAX_FRAGMENT_PARTS {
+            return Err(format!(
+                "Memo too long: {} bytes would need more than {} fragments",
+                self.memo_text.len(),
+                memo_decoder::MAX_FRAGMENT_PARTS
+            ));
+        }
+        Ok(self.fragments(max_len))
+    }
+
+    pub fn to_compact_memo(&self) -> Result<String, String> {
+        self.encode_compact(false)
+    }
+
+    pub fn to_compressed_memo(&self) -> Result<String, String> {
+        self.encode_compact(true)
+    }
+
+    fn encode_compact(&self, compress: bool) -> Result<String, String> {
+        let (command, signature) = signing::split_signature(&self.memo_text);
+        let envelope = match signature.map(crypto::from_hex) {
+            Some(Ok(signature)) => CompactEnvelope {
+                memo: command.to_string(),
+                signature,
+                timestamp: self.timestamp,
+                expires_at: self.expires_at,
+                priority: self.priority,
+                reply_to: self.reply_to.clone(),
+            },
+            _ => CompactEnvelope {
+                memo: self.memo_text.clone(),
+                signature: Vec::new(),
+                timestamp: self.timestamp,
+                expires_at: self.expires_at,
+                priority: self.priority,
+                reply_to: self.reply_to.clone(),
+            },
+        };
+        let packed = rmp_serde::to_vec(&envelope)
+            .map_err(|e| format!("Failed to encode compact envelope: {}", e))?;
+        let mut bytes = COMPACT_MAGIC.to_vec();
+        if compress {
+            bytes.push(FLAG_ZSTD);
+            bytes.extend(
+                zstd::bulk::compress(&packed, ZSTD_LEVEL)
+                    .map_err(|e| format!("Failed to compress memo: {}", e))?,
+            );
+        } else {
+            bytes.push(0);
+            bytes.extend(packed);
+        }
+        Ok(memo_decoder::encode_binary(&bytes))
+    }
+
+    pub fn to_json_memo(&self) -> Result<String, String> {
+        let (command, signature) = signing::split_signature(&self.memo_text);
+        let envelope = JsonEnvelope {
+            expires_at: self.expires_at,
+            memo: command.to_string(),
+            priority: self.priority,
+            reply_to: self.reply_to.clone(),
+            sig: signature.map(str::to_string),
+            timestamp: self.timestamp,
+            v: JSON_VERSION,
+        };
+        serde_json::to_string(&envelope)
+            .map(|json| format!("{}{}", JSON_PREFIX, json))
+            .map_err(|e| format!("Failed to encode JSON envelope: {}", e))
+    }
+
+    fn decode_json(&self, json: &str) -> Result<Message, String> {
+        let envelope: JsonEnvelope = serde_json::from_str(json.trim_end())
+            .map_err(|e| format!("Invalid memo: bad JSON envelope: {}", e))?;
+        if envelope.v != JSON_VERSION {
+            return Err(format!(
+                "Invalid memo: unsupported JSON envelope version {}",
+                envelope.v
+            ));
+        }
+        let memo_text = match envelope.sig {
+            Some(sig) => format!("{}{}{}", envelope.memo, signing::SIGNATURE_MARKER, sig),
+            None => envelope.memo,
+        };
+        Ok(Message {
+            memo_text,
+            timestamp: envelope.timestamp.or(self.timestamp),
+            expires_at: envelope.expires_at.or(self.expires_at),
+            priority: envelope.priority.or(self.priority),
+            reply_to: envelope.reply_to.or_else(|| self.reply_to.clone()),
+            ..self.clone()
+        })
+    }
+
+    pub fn needs_envelope(&self) -> bool {
+        self.expires_at.is_some() || self.priority.is_some()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now > expires_at)
+    }
+
+    pub fn with_envelope_fields(self) -> Self {
+        match self.decode_memo() {
+            Ok(Some(decoded)) => Message {
+                expires_at: decoded.expires_at,
+                priority: decoded.priority,
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    pub fn plain_memo(&self) -> String {
+        match &self.reply_to {
+            Some(reply_to) => format!("{}{}\n{}", REPLY_TO_PREFIX, reply_to, self.memo_text),
+            None => self.memo_text.clone(),
+        }
+    }
+
+    pub fn take_reply_to(self) -> Self {
+        let header = self
+            .memo_text
+            .strip_prefix(REPLY_TO_PREFIX)
+            .and_then(|rest| rest.split_once('\n'))
+            .filter(|(reply_to, _)| !reply_to.trim().is_empty())
+            .map(|(reply_to, body)| (reply_to.trim().to_string(), body.to_string()));
+        match header {
+            Some((reply_to, memo_text)) => Message {
+                reply_to: Some(reply_to),
+                memo_text,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    pub fn compact_if_smaller(&self, compress: bool) -> String {
+        let mut candidates = vec![self.to_compact_memo()];
+        if compress && self.memo_text.len() >= COMPRESS_THRESHOLD {
+            candidates.push(self.to_compressed_memo());
+        }
+        let plain = self.plain_memo();
+        candidates
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|memo| memo.len() < plain.len() || self.needs_envelope())
+            .min_by_key(String::len)
+            .unwrap_or(plain)
+    }
+
+    pub fn decode_memo(&self) -> Result<Option<Message>, String> {
+        if let Some(json) = self.memo_text.strip_prefix(JSON_PREFIX) {
+            return self.decode_json(json).map(Some);
+        }
+        if !self.memo_text.starts_with(memo_decoder::BINARY_PREFIX) {
+            return Ok(None);
+        }
+        let bytes = match memo_decoder::decode_payload(&self.memo_text)? {
+            MemoPayload::Binary(bytes) => bytes,
+            MemoPayload::Text(text) => text.into_bytes(),
+        };
+        let Some(framed) = bytes.strip_prefix(COMPACT_MAGIC) else {
+            let memo_text = MemoPayload::Binary(bytes).into_text()?;
+            return Ok(Some(Message {
+                memo_text,
+                ..self.clone()
+            }));
+        };
+        let packed = match framed.split_first() {
+            Some((&0, packed)) => packed.to_vec(),
+            Some((&FLAG_ZSTD, compressed)) => {
+                zstd::bulk::decompress(compressed, MAX_DECOMPRESSED_SIZE)
+                    .map_err(|e| format!("Invalid memo: bad zstd payload: {}", e))?
+            }
+            Some((flags, _)) => {
+                return Err(format!(
+                    "Invalid memo: unsupported compact envelope flags {:#04x}",
+                    flags
+                ))
+            }
+            None => return Err("Invalid memo: empty compact envelope".to_string()),
+        };
+        let envelope: CompactEnvelope = rmp_serde::from_slice(&packed)
+            .map_err(|e| format!("Invalid memo: bad compact envelope: {}", e))?;
+        let memo_text = if envelope.signature.is_empty() {
+            envelope.memo
+        } else {
+            format!(
+                "{}{}{}",
+                envelope.memo,
+                signing::SIGNATURE_MARKER,
+                crypto::to_hex(&envelope.signature)
+            )
+        };
+        Ok(Some(Message {
+            memo_text,
+            timestamp: envelope.timestamp.or(self.timestamp),
+            expires_at: envelope.expires_at.or(self.expires_at),
+            priority: envelope.priority.or(self.priority),
+            reply_to: envelope.reply_to.or_else(|| self.reply_to.clone()),
+            ..self.clone()
+        }))
+    }
+
+    pub fn take_reply_ref(self) -> Self {
+        match response::split_reply_ref(&self.memo_text) {
+            (Some(reference), body) => Message {
+                in_reply_to: Some(reference.to_string()),
+                memo_text: body.to_string(),
+                ..self
+            },
+            (None, _) => self,
+        }
+    }
+
+    pub fn with_amount(mut self, amount_zatoshis: u64) -> Self {
+        self.amount_zatoshis = amount_zatoshis;
+        self
+    }
+
     pub fn from_zingo_transaction(transaction_data: &str) -> Result<Self, String> {
         let value = serde_json::from_str::<serde_json::Value>(transaction_data)
             .map_err(|e| format!("Invalid transaction JSON: {}", e))?;
+        Self::from_zingo_value(&value)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "Missing memo field".to_string())
+    }
 
-        let sender = value
-            .get("sender")
-            .and_then(|v| v.as_str())
-            .or_else(|| value.get("from").and_then(|v| v.as_str()))
-            .ok_or_else(|| "Missing sender field".to_string())?
-            .to_string();
-
-        let recipient = value
-            .get("recipient")
-            .and_then(|v| v.as_str())
-            .or_else(|| value.get("to").and_then(|v| v.as_str()))
-            .ok_or_else(|| "Missing recipient field".to_string())?
-            .to_string();
-
-        let memo = value
-            .get("memo")
-            .and_then(|v| v.as_str())
-            .or_else(|| value.get("memo_text").and_then(|v| v.as_str()))
-            .ok_or_else(|| "Missing memo field".to_string())?
-            .to_string();
-
-        let txid = value
-            .get("txid")
-            .and_then(|v| v.as_str())
-            .map(|v| v.to_string());
-
-        let signature = value
-            .get("signature")
-            .and_then(|v| v.as_str())
-            .map(|v| v.to_string());
-
-        let timestamp = value.get("timestamp").and_then(|v| v.as_u64());
+    pub fn from_zingo_value(value: &serde_json::Value) -> Result<Vec<Self>, String> {
+        if !value.is_object() {
+            return Err("Invalid transaction: expected a JSON object".to_string());
+        }
+        let text = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| value.get(*key).and_then(|v| v.as_str()))
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        let number = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| value.get(*key).and_then(|v| v.as_u64()))
+        };
 
-        Ok(Message {
-            sender_address: sender,
-            recipient_address: recipient,
-            memo_text: memo,
-            txid,
-            signature,
-            timestamp,
-        })
+        let txid = text(&["txid"]);
+        let sender =
+            text(&["sender", "from", "from_address"]).unwrap_or_else(|| UNKNOWN_SENDER.to_string());
+        let recipient = text(&["recipient", "to", "recipient_address", "address"])
+            .unwrap_or_else(|| "coordinator".to_string());
+        let signature = text(&["signature"]);
+        let timestamp = number(&["timestamp", "datetime"]);
+        let confirmed = value.get("unconfirmed").and_then(|v| v.as_bool()) != Some(true)
+            && text(&["status"]).is_none_or(|status| status == "confirmed");
+        let block_height = number(&["block_height", "blockheight", "height"]).filter(|_| confirmed);
+        let amount_zatoshis = number(&["amount", "value"]).unwrap_or(0);
+        let output_index = number(&["output_index", "position"]).map(|v| v as u32);
+
+        let wire_text = |memo: &str| {
+            ZcashMemo::from_wire(memo)
+                .wire_text()
+                .map(|text| text.map(str::to_string))
+        };
+        let memos: Vec<(Option<u32>, String)> = match value.get("memos").and_then(|m| m.as_array())
+        {
+            Some(memos) => {
+                let mut unreadable = None;
+                let memos: Vec<(Option<u32>, String)> = memos
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, memo)| match wire_text(memo.as_str()?) {
+                        Ok(text) => text.map(|text| (Some(index as u32), text)),
+                        Err(e) => {
+                            unreadable.get_or_insert(e);
+                            None
+                        }
+                    })
+                    .collect();
+                match unreadable {
+                    Some(e) if memos.is_empty() => return Err(e),
+                    _ => memos,
+                }
+            }
+            None => {
+                let memo = text(&["memo", "memo_text"])
+                    .map(|memo| wire_text(&memo))
+                    .transpose()?
+                    .flatten()
+                    .ok_or_else(|| "Missing memo field".to_string())?;
+                vec![(output_index, memo)]
+            }
+        };
+
+        Ok(memos
+            .into_iter()
+            .map(|(output_index, memo_text)| Message {
+                sender_address: sender.clone(),
+                recipient_address: recipient.clone(),
+                memo_text,
+                txid: txid.clone(),
+                signature: signature.clone(),
+                timestamp,
+                amount_zatoshis,
+                output_index,
+                block_height,
+                in_reply_to: None,
+                expires_at: None,
+                priority: None,
+                reply_to: None,
+            })
+            .collect())
     }
 }
 
@@ -171,7 +545,9 @@ mod tests {
             "memo": "ls /home",
             "txid": "abc123",
             "signature": "sig",
-            "timestamp": 1700000000
+            "timestamp": 1700000000,
+            "amount": 2500,
+            "output_index": 1
         }"#;
 
         let msg = Message::from_zingo_transaction(raw).unwrap();
@@ -181,6 +557,8 @@ mod tests {
         assert_eq!(msg.txid.as_deref(), Some("abc123"));
         assert_eq!(msg.signature.as_deref(), Some("sig"));
         assert_eq!(msg.timestamp, Some(1700000000));
+        assert_eq!(msg.amount_zatoshis, 2500);
+        assert_eq!(msg.idempotency_key().as_deref(), Some("abc123:1"));
     }
 
     #[test]
@@ -198,6 +576,309 @@ mod tests {
         assert!(msg.txid.is_none());
         assert!(msg.signature.is_none());
         assert!(msg.timestamp.is_none());
+        assert_eq!(msg.amount_zatoshis, 0);
+        assert!(msg.idempotency_key().is_none());
+    }
+
+    #[test]
+    fn test_from_zingo_value_transfer_splits_memos() {
+        let raw = serde_json::json!({
+            "txid": "abcdef1234567890",
+            "datetime": 1700000000,
+            "blockheight": 2947113,
+            "value": 10000,
+            "kind": "received",
+            "memos": ["ls /home", "", "pwd", null]
+        });
+
+        let messages = Message::from_zingo_value(&raw).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].sender_address, UNKNOWN_SENDER);
+        assert_eq!(messages[0].recipient_address, "coordinator");
+        assert_eq!(messages[0].timestamp, Some(1700000000));
+        assert_eq!(messages[0].block_height, Some(2947113));
+        assert_eq!(messages[0].amount_zatoshis, 10000);
+        assert_eq!(
+            messages[1].idempotency_key().as_deref(),
+            Some("abcdef1234567890:2")
+        );
+        assert!(
+            Message::from_zingo_value(&serde_json::json!({"txid": "abc", "memos": []}))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(Message::from_zingo_value(&serde_json::json!(["ls"])).is_err());
+    }
+
+    #[test]
+    fn test_compact_envelope_round_trips_signed_commands() {
+        let mut msg = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            format!("ls /home{}{}", signing::SIGNATURE_MARKER, "ab".repeat(64)),
+        );
+        msg.timestamp = Some(1700000000);
+
+        let compact = msg.to_compact_memo().unwrap();
+        assert!(compact.len() < msg.memo_text.len());
+        assert_eq!(msg.compact_if_smaller(false), compact);
+
+        let wire = Message {
+            memo_text: compact,
+            timestamp: None,
+            ..msg.clone()
+        };
+        let decoded = wire.decode_memo().unwrap().unwrap();
+        assert_eq!(decoded.memo_text, msg.memo_text);
+        assert_eq!(decoded.timestamp, Some(1700000000));
+        assert_eq!(decoded.sender_address, "zs1sender");
+
+        let long = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            "x".repeat(300),
+        );
+        assert_eq!(long.compact_if_smaller(false), long.memo_text);
+        assert!(long.decode_memo().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_split_for_memo_respects_overhead() {
+        let short = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /".to_string(),
+        );
+        assert!(short.fits_in_memo(memo_decoder::CHECKSUM_OVERHEAD));
+        assert_eq!(short.split_for_memo(100).unwrap()[0].memo_text, "ls /");
+
+        let edge = Message {
+            memo_text: "x".repeat(memo_decoder::MAX_MEMO_SIZE - 13),
+            ..short.clone()
+        };
+        assert!(edge.fits_in_memo(13));
+        assert!(!edge.fits_in_memo(14));
+
+        let long = Message {
+            memo_text: "touch /a.txt ".to_string() + &"é".repeat(700),
+            ..short.clone()
+        };
+        let overhead = memo_decoder::CHECKSUM_OVERHEAD + signing::SIGNATURE_OVERHEAD;
+        let parts = long.split_for_memo(overhead).unwrap();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|part| part.fits_in_memo(overhead)));
+        let joined: String = parts
+            .iter()
+            .map(|part| {
+                memo_decoder::Fragment::parse(&part.memo_text)
+                    .unwrap()
+                    .unwrap()
+                    .body
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(joined, long.memo_text);
+
+        assert!(long
+            .split_for_memo(memo_decoder::MAX_MEMO_SIZE - 10)
+            .unwrap_err()
+            .contains("leave no room"));
+        let huge = Message {
+            memo_text: "x".repeat(300_000),
+            ..short
+        };
+        assert!(huge
+            .split_for_memo(0)
+            .unwrap_err()
+            .contains("more than 999 fragments"));
+    }
+
+    #[test]
+    fn test_json_envelope_is_canonical_and_round_trips() {
+        let signature = "ab".repeat(64);
+        let mut msg = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            format!("say \"hi\"\tüber{}{}", signing::SIGNATURE_MARKER, signature),
+        );
+        msg.timestamp = Some(1700000000);
+        msg.priority = Some(7);
+        msg.reply_to = Some("zs1reply".to_string());
+
+        let json = msg.to_json_memo().unwrap();
+        assert_eq!(
+            json,
+            format!(
+                "ZBJ:{{\"memo\":\"say \\\"hi\\\"\\tüber\",\"priority\":7,\"reply_to\":\"zs1reply\",\"sig\":\"{}\",\"timestamp\":1700000000,\"v\":1}}",
+                signature
+            )
+        );
+        let wire = Message {
+            memo_text: json,
+            timestamp: None,
+            priority: None,
+            reply_to: None,
+            ..msg.clone()
+        };
+        let decoded = wire.decode_memo().unwrap().unwrap();
+        assert_eq!(decoded.memo_text, msg.memo_text);
+        assert_eq!(decoded.timestamp, Some(1700000000));
+        assert_eq!(decoded.priority, Some(7));
+        assert_eq!(decoded.reply_to.as_deref(), Some("zs1reply"));
+
+        let hand_written = Message {
+            memo_text: r#"ZBJ:{ "v": 1, "memo": "ls /", "expires_at": 99 }"#.to_string(),
+            ..msg.clone()
+        };
+        let decoded = hand_written.decode_memo().unwrap().unwrap();
+        assert_eq!(decoded.memo_text, "ls /");
+        assert_eq!(decoded.expires_at, Some(99));
+
+        for bad in [
+            r#"ZBJ:{"v":1,"memo":"ls","priority":7.5}"#,
+            r#"ZBJ:{"v":2,"memo":"ls"}"#,
+            r#"ZBJ:{"memo":"ls"}"#,
+            "ZBJ:not json",
+        ] {
+            let wire = Message {
+                memo_text: bad.to_string(),
+                ..msg.clone()
+            };
+            assert!(
+                wire.decode_memo().unwrap_err().starts_with("Invalid memo"),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_envelope_carries_expiry_and_priority() {
+        let mut msg = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            "x".repeat(300),
+        );
+        msg.expires_at = Some(1700000600);
+        msg.priority = Some(7);
+        assert!(msg.is_expired(1700000601));
+        assert!(!msg.is_expired(1700000600));
+
+        let wire = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            msg.compact_if_smaller(false),
+        );
+        assert!(wire.memo_text.starts_with(memo_decoder::BINARY_PREFIX));
+        let queued = wire.with_envelope_fields();
+        assert_eq!(queued.expires_at, Some(1700000600));
+        assert_eq!(queued.priority, Some(7));
+        let decoded = queued.decode_memo().unwrap().unwrap();
+        assert_eq!(decoded.memo_text, msg.memo_text);
+        assert_eq!(decoded.priority, Some(7));
+
+        let mut legacy = COMPACT_MAGIC.to_vec();
+        legacy.push(0);
+        legacy.extend(rmp_serde::to_vec(&("ls /", Vec::<u8>::new(), None::<u64>)).unwrap());
+        let legacy = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            memo_decoder::encode_binary(&legacy),
+        );
+        let decoded = legacy.decode_memo().unwrap().unwrap();
+        assert_eq!(decoded.memo_text, "ls /");
+        assert_eq!((decoded.expires_at, decoded.priority), (None, None));
+    }
+
+    #[test]
+    fn test_reply_to_travels_as_header_or_envelope_field() {
+        let mut msg = Message::new(
+            "client_abcd".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /".to_string(),
+        );
+        msg.reply_to = Some("zs1reply".to_string());
+        let plain = msg.compact_if_smaller(false);
+        assert_eq!(plain, "REPLY-TO:zs1reply\nls /");
+        let received = Message {
+            memo_text: plain,
+            reply_to: None,
+            ..msg.clone()
+        }
+        .take_reply_to();
+        assert_eq!(received.reply_to.as_deref(), Some("zs1reply"));
+        assert_eq!(received.memo_text, "ls /");
+
+        msg.memo_text = format!("ls /home{}{}", signing::SIGNATURE_MARKER, "ab".repeat(64));
+        let compact = msg.compact_if_smaller(false);
+        assert!(compact.starts_with(memo_decoder::BINARY_PREFIX));
+        let decoded = Message::new(
+            "client_abcd".to_string(),
+            "zs1coordinator".to_string(),
+            compact,
+        )
+        .decode_memo()
+        .unwrap()
+        .unwrap();
+        assert_eq!(decoded.reply_to.as_deref(), Some("zs1reply"));
+        assert_eq!(decoded.memo_text, msg.memo_text);
+
+        let unrelated = Message::new(
+            "a".to_string(),
+            "b".to_string(),
+            "REPLY-TO:\nls".to_string(),
+        );
+        assert_eq!(
+            unrelated.clone().take_reply_to().memo_text,
+            unrelated.memo_text
+        );
+    }
+
+    #[test]
+    fn test_compressed_envelope_round_trips_large_commands() {
+        let msg = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            format!("touch /notes.txt {}", "the same line again\n".repeat(60)),
+        );
+
+        let compressed = msg.compact_if_smaller(true);
+        assert_eq!(compressed, msg.to_compressed_memo().unwrap());
+        assert!(compressed.len() < msg.to_compact_memo().unwrap().len());
+        assert!(compressed.len() < memo_decoder::MAX_MEMO_SIZE);
+        let wire = Message {
+            memo_text: compressed,
+            ..msg.clone()
+        };
+        assert_eq!(
+            wire.decode_memo().unwrap().unwrap().memo_text,
+            msg.memo_text
+        );
+
+        let short = Message::new(
+            "zs1sender".to_string(),
+            "zs1coordinator".to_string(),
+            "ls /".to_string(),
+        );
+        assert_eq!(short.compact_if_smaller(true), "ls /");
+
+        let mut bomb = COMPACT_MAGIC.to_vec();
+        bomb.push(FLAG_ZSTD);
+        bomb.extend(zstd::bulk::compress(&vec![0u8; MAX_DECOMPRESSED_SIZE + 1], 3).unwrap());
+        let bomb = Message {
+            memo_text: memo_decoder::encode_binary(&bomb),
+            ..msg.clone()
+        };
+        assert!(bomb
+            .decode_memo()
+            .unwrap_err()
+            .starts_with("Invalid memo: bad zstd payload"));
+
+        let unknown = Message {
+            memo_text: memo_decoder::encode_binary(b"ZM1\x80"),
+            ..msg
+        };
+        assert!(unknown.decode_memo().unwrap_err().contains("flags"));
     }
 
     #[test]
@@ -0,0 +1,220 @@
+use crate::events::{Event, Subscriber};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.count += 1;
+        self.total_micros = self.total_micros.saturating_add(micros);
+        self.max_micros = self.max_micros.max(micros);
+    }
+
+    pub fn average_micros(&self) -> u64 {
+        self.total_micros.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    pub messages_processed: u64,
+    pub messages_failed: u64,
+    pub messages_expired: u64,
+    pub auth_successes: u64,
+    pub auth_failures: u64,
+    pub zingo_errors: u64,
+    pub replies_sent: u64,
+    pub reply_failures: u64,
+    pub replies_refused: u64,
+    pub commands: BTreeMap<String, LatencyStats>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_message(&mut self, ok: bool) {
+        self.messages_processed += 1;
+        if !ok {
+            self.messages_failed += 1;
+        }
+    }
+
+    pub fn record_expired(&mut self) {
+        self.messages_expired += 1;
+    }
+
+    pub fn record_auth(&mut self, ok: bool) {
+        if ok {
+            self.auth_successes += 1;
+        } else {
+            self.auth_failures += 1;
+        }
+    }
+
+    pub fn record_command(&mut self, name: &str, elapsed: Duration) {
+        self.commands
+            .entry(name.to_string())
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn record_replies(&mut self, sent: usize, failed: usize) {
+        self.replies_sent += sent as u64;
+        self.reply_failures += failed as u64;
+        self.zingo_errors += failed as u64;
+    }
+
+    pub fn record_refused(&mut self, replies: usize) {
+        self.replies_refused += replies as u64;
+    }
+
+    pub fn record_zingo_error(&mut self) {
+        self.zingo_errors += 1;
+    }
+
+    fn counters(&self) -> [(&'static str, u64); 9] {
+        [
+            ("messages_processed", self.messages_processed),
+            ("messages_failed", self.messages_failed),
+            ("messages_expired", self.messages_expired),
+            ("auth_successes", self.auth_successes),
+            ("auth_failures", self.auth_failures),
+            ("zingo_errors", self.zingo_errors),
+            ("replies_sent", self.replies_sent),
+            ("reply_failures", self.reply_failures),
+            ("replies_refused", self.replies_refused),
+        ]
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines: Vec<String> = self
+            .counters()
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+
+        for (name, stats) in &self.commands {
+            lines.push(format!(
+                "cmd {}: count={} avg={}us max={}us",
+                name,
+                stats.count,
+                stats.average_micros(),
+                stats.max_micros
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({});
+        for (name, count) in self.counters() {
+            value[name] = json!(count);
+        }
+
+        let mut commands = json!({});
+        for (name, stats) in &self.commands {
+            commands[name] = json!({
+                "count": stats.count,
+                "avg_micros": stats.average_micros(),
+                "max_micros": stats.max_micros,
+            });
+        }
+        value["commands"] = commands;
+        value
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in self.counters() {
+            out.push_str(&format!(
+                "# TYPE zatboard_{name}_total counter\nzatboard_{name}_total {value}\n"
+            ));
+        }
+
+        out.push_str("# TYPE zatboard_command_duration_microseconds summary\n");
+        for (name, stats) in &self.commands {
+            out.push_str(&format!(
+                "zatboard_command_duration_microseconds_count{{command=\"{}\"}} {}\n",
+                name, stats.count
+            ));
+            out.push_str(&format!(
+                "zatboard_command_duration_microseconds_sum{{command=\"{}\"}} {}\n",
+                name, stats.total_micros
+            ));
+        }
+        out
+    }
+}
+
+impl Subscriber for Metrics {
+    fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::MessageProcessed { result, .. } => self.record_message(result.is_ok()),
+            Event::AuthSucceeded { .. } => self.record_auth(true),
+            Event::AuthFailed { .. } => self.record_auth(false),
+            Event::CommandCompleted {
+                command,
+                elapsed_micros,
+                ..
+            } => self.record_command(command, Duration::from_micros(*elapsed_micros)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_latency() {
+        let mut metrics = Metrics::new();
+        metrics.record_message(true);
+        metrics.record_message(false);
+        metrics.record_auth(true);
+        metrics.record_auth(false);
+        metrics.record_replies(3, 1);
+        metrics.record_zingo_error();
+        metrics.record_command("ls", Duration::from_micros(100));
+        metrics.record_command("ls", Duration::from_micros(300));
+
+        assert_eq!(metrics.messages_processed, 2);
+        assert_eq!(metrics.messages_failed, 1);
+        assert_eq!(metrics.zingo_errors, 2);
+        assert_eq!(metrics.commands["ls"].average_micros(), 200);
+        assert_eq!(metrics.commands["ls"].max_micros, 300);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("replies_sent: 3"));
+        assert!(rendered.contains("cmd ls: count=2 avg=200us max=300us"));
+    }
+
+    #[test]
+    fn test_prometheus_export() {
+        let mut metrics = Metrics::new();
+        metrics.record_message(true);
+        metrics.record_command("chat post", Duration::from_micros(50));
+
+        let json = metrics.to_json();
+        assert_eq!(json["messages_processed"], 1);
+        assert_eq!(json["commands"]["chat post"]["max_micros"], 50);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("zatboard_messages_processed_total 1\n"));
+        assert!(text
+            .contains("zatboard_command_duration_microseconds_count{command=\"chat post\"} 1\n"));
+        assert!(
+            text.contains("zatboard_command_duration_microseconds_sum{command=\"chat post\"} 50\n")
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const MAX_REASON_LENGTH: usize = 200;
+const MAX_OPEN_REPORTS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Report {
+    pub id: u64,
+    pub path: String,
+    pub reporter: String,
+    pub reason: String,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Reports {
+    next_id: u64,
+    open: Vec<Report>,
+}
+
+impl Reports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Reports::new());
+        }
+
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read reports: {}", e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse reports: {}", e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize reports: {}", e))?;
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write reports: {}", e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace reports: {}", e))
+    }
+
+    pub fn file(
+        &mut self,
+        path: &str,
+        reporter: &str,
+        reason: &str,
+        now: u64,
+    ) -> Result<u64, String> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err("A reason is required".to_string());
+        }
+        if reason.chars().count() > MAX_REASON_LENGTH {
+            return Err(format!(
+                "Reason too long (max {} characters)",
+                MAX_REASON_LENGTH
+            ));
+        }
+        if self
+            .open
+            .iter()
+            .any(|report| report.path == path && report.reporter == reporter)
+        {
+            return Err(format!("You already reported {}", path));
+        }
+        if self.open.len() >= MAX_OPEN_REPORTS {
+            return Err("Report queue is full, try again later".to_string());
+        }
+
+        self.next_id += 1;
+        self.open.push(Report {
+            id: self.next_id,
+            path: path.to_string(),
+            reporter: reporter.to_string(),
+            reason: reason.to_string(),
+            created_at: now,
+        });
+        Ok(self.next_id)
+    }
+
+    pub fn open(&self) -> &[Report] {
+        &self.open
+    }
+
+    pub fn resolve(&mut self, path: &str) -> usize {
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        let before = self.open.len();
+        self.open
+            .retain(|report| report.path != path && !report.path.starts_with(&prefix));
+        before - self.open.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_and_resolve_reports() {
+        let mut reports = Reports::new();
+        assert_eq!(
+            reports.file("/board/spam.txt", "zs1a", "spam", 1).unwrap(),
+            1
+        );
+        assert!(reports.file("/board/spam.txt", "zs1a", "again", 2).is_err());
+        assert!(reports.file("/board/other.txt", "zs1a", "  ", 2).is_err());
+        assert_eq!(
+            reports.file("/board/spam.txt", "zs1b", "ads", 3).unwrap(),
+            2
+        );
+        assert_eq!(reports.file("/elsewhere", "zs1b", "rude", 3).unwrap(), 3);
+
+        assert_eq!(reports.resolve("/board"), 2);
+        assert_eq!(reports.open().len(), 1);
+        assert_eq!(reports.open()[0].path, "/elsewhere");
+    }
+
+    #[test]
+    fn test_reports_persist() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("reports.json");
+
+        let mut reports = Reports::new();
+        reports.file("/a", "zs1a", "off-topic", 5).unwrap();
+        reports.save(&path).unwrap();
+
+        let loaded = Reports::load(&path).unwrap();
+        assert_eq!(loaded.open(), reports.open());
+    }
+}
@@ -0,0 +1,661 @@
+use crate::fee;
+use crate::memo_decoder::MAX_MEMO_SIZE;
+use crate::response;
+use crate::signing;
+use crate::tx_status::TxStatus;
+use crate::wallet::WalletBackend;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+const BASE_BACKOFF_SECS: u64 = 5;
+const MAX_BACKOFF_SECS: u64 = 900;
+const MAX_RECEIPTS: usize = 1000;
+const RECEIPT_PREVIEW_CHARS: usize = 40;
+const UNKNOWN_TXID: &str = "unknown";
+pub const REPLY_FEE_ZATOSHIS: u64 = fee::estimate_fee(MAX_MEMO_SIZE, 1);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutboundMemo {
+    pub id: u64,
+    pub address: String,
+    pub memo: String,
+    pub attempts: u32,
+    pub enqueued_at: u64,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Receipt {
+    pub id: u64,
+    pub address: String,
+    pub txid: String,
+    pub reply_to: Option<String>,
+    pub preview: String,
+    pub sent_at: u64,
+    #[serde(default)]
+    pub status: Option<TxStatus>,
+    #[serde(default)]
+    pub fee_zatoshis: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlushReport {
+    pub sent: usize,
+    pub failed: usize,
+    pub fee_zatoshis: u64,
+}
+
+pub fn deliver<W: WalletBackend>(
+    wallet: &W,
+    items: &[OutboundMemo],
+    max_fee: Option<u64>,
+) -> Result<String, String> {
+    for item in items {
+        let reply_preview: String = item.address.chars().take(8).collect();
+        let response_preview: String = item.memo.chars().take(50).collect();
+        println!(
+            "📤 Sending response to {}: {}",
+            reply_preview, response_preview
+        );
+    }
+
+    let result = match items {
+        [item] => wallet.send_memo(&item.address, 0, &item.memo, max_fee),
+        _ => {
+            let sends: Vec<(&str, u64, &str)> = items
+                .iter()
+                .map(|item| (item.address.as_str(), 0, item.memo.as_str()))
+                .collect();
+            wallet.send_memos(&sends, max_fee)
+        }
+    };
+    let attempts = items.iter().map(|item| item.attempts).max().unwrap_or(0);
+    let memo_lens: Vec<usize> = items.iter().map(|item| item.memo.len()).collect();
+    let fee_zatoshis = fee::estimate_memos_fee(&memo_lens);
+    match result {
+        Ok(txid) => {
+            match items.len() {
+                1 => println!(
+                    "✅ Response sent successfully: {} (fee ~{} zatoshis)",
+                    txid, fee_zatoshis
+                ),
+                n => println!(
+                    "✅ {} response memos sent in one transaction: {} (fee ~{} zatoshis)",
+                    n, txid, fee_zatoshis
+                ),
+            }
+            Ok(txid)
+        }
+        Err(e) => {
+            println!("❌ Send failed (attempt {}): {}", attempts + 1, e);
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueueFile {
+    next_id: u64,
+    items: VecDeque<OutboundMemo>,
+    #[serde(default)]
+    receipts: VecDeque<Receipt>,
+}
+
+#[derive(Debug)]
+pub struct OutboundQueue {
+    path: PathBuf,
+    next_id: u64,
+    items: VecDeque<OutboundMemo>,
+    receipts: VecDeque<Receipt>,
+    held: Option<String>,
+    max_fee: Option<u64>,
+    max_outputs: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct FundsGate {
+    spendable: Option<u64>,
+    alert: Option<String>,
+}
+
+impl FundsGate {
+    pub fn observe(&mut self, spendable: Result<u64, String>) {
+        self.spendable = spendable.ok();
+    }
+
+    pub fn admit(&mut self, queued: usize, new: usize) -> Result<(), String> {
+        let Some(spendable) = self.spendable else {
+            return Ok(());
+        };
+        let needed = REPLY_FEE_ZATOSHIS.saturating_mul((queued + new) as u64);
+        if spendable >= needed {
+            if self.alert.take().is_some() {
+                println!("✅ Wallet funded again, queueing replies");
+            }
+            return Ok(());
+        }
+
+        let reason = format!(
+            "Insufficient funds: {} replies need about {} zatoshis in fees but the wallet has {} spendable",
+            queued + new,
+            needed,
+            spendable
+        );
+        if self.alert.is_none() {
+            eprintln!(
+                "🚨 Refusing to queue replies until the wallet is topped up: {}",
+                reason
+            );
+        }
+        self.alert = Some(reason.clone());
+        Err(reason)
+    }
+
+    pub fn alert(&self) -> Option<&str> {
+        self.alert.as_deref()
+    }
+}
+
+fn is_known_txid(txid: &str) -> bool {
+    !txid.trim().is_empty() && txid != UNKNOWN_TXID
+}
+
+pub fn backoff_secs(attempts: u32) -> u64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+    (BASE_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS)
+}
+
+impl OutboundQueue {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let file = if path.exists() {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read outbound queue: {}", e))?;
+            serde_json::from_str::<QueueFile>(&contents)
+                .map_err(|e| format!("Failed to parse outbound queue: {}", e))?
+        } else {
+            QueueFile::default()
+        };
+
+        Ok(OutboundQueue {
+            path,
+            next_id: file.next_id,
+            items: file.items,
+            receipts: file
+                .receipts
+                .into_iter()
+                .filter(|receipt| is_known_txid(&receipt.txid))
+                .collect(),
+            held: None,
+            max_fee: None,
+            max_outputs: 1,
+        })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let file = QueueFile {
+            next_id: self.next_id,
+            items: self.items.clone(),
+            receipts: self.receipts.clone(),
+        };
+        let contents = serde_json::to_string(&file)
+            .map_err(|e| format!("Failed to serialize outbound queue: {}", e))?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write outbound queue: {}", e))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| format!("Failed to replace outbound queue: {}", e))
+    }
+
+    pub fn enqueue(&mut self, address: &str, memo: &str, now: u64) -> Result<u64, String> {
+        self.enqueue_reply(address, memo, None, now)
+    }
+
+    pub fn enqueue_reply(
+        &mut self,
+        address: &str,
+        memo: &str,
+        reply_to: Option<&str>,
+        now: u64,
+    ) -> Result<u64, String> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.items.push_back(OutboundMemo {
+            id,
+            address: address.to_string(),
+            memo: memo.to_string(),
+            attempts: 0,
+            enqueued_at: now,
+            next_attempt_at: now,
+            last_error: None,
+            reply_to: reply_to.map(str::to_string),
+        });
+        if let Err(e) = self.save() {
+            self.items.pop_back();
+            self.next_id -= 1;
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    pub fn hold(&mut self, reason: &str) {
+        self.held = Some(reason.to_string());
+    }
+
+    pub fn resume(&mut self) {
+        self.held = None;
+    }
+
+    pub fn held(&self) -> Option<&str> {
+        self.held.as_deref()
+    }
+
+    pub fn set_max_fee(&mut self, max_fee: Option<u64>) {
+        self.max_fee = max_fee;
+    }
+
+    pub fn max_fee(&self) -> Option<u64> {
+        self.max_fee
+    }
+
+    pub fn set_max_outputs(&mut self, max_outputs: usize) {
+        self.max_outputs = max_outputs.max(1);
+    }
+
+    pub fn max_outputs(&self) -> usize {
+        self.max_outputs
+    }
+
+    pub fn due(&self, now: u64) -> Vec<OutboundMemo> {
+        if self.held.is_some() {
+            return Vec::new();
+        }
+
+        let mut blocked: Vec<&str> = Vec::new();
+        let mut due = Vec::new();
+
+        for item in &self.items {
+            if blocked.contains(&item.address.as_str()) {
+                continue;
+            }
+            if item.next_attempt_at > now {
+                blocked.push(&item.address);
+                continue;
+            }
+            due.push(item.clone());
+        }
+        due
+    }
+
+    pub fn send_batch<F>(batch: &[OutboundMemo], mut send: F) -> Vec<(u64, Result<String, String>)>
+    where
+        F: FnMut(&OutboundMemo) -> Result<String, String>,
+    {
+        Self::send_grouped(batch, 1, |items| send(&items[0]))
+    }
+
+    pub fn send_grouped<F>(
+        batch: &[OutboundMemo],
+        max_outputs: usize,
+        mut send: F,
+    ) -> Vec<(u64, Result<String, String>)>
+    where
+        F: FnMut(&[OutboundMemo]) -> Result<String, String>,
+    {
+        let mut failed: Vec<String> = Vec::new();
+        let mut results = Vec::new();
+        let mut group: Vec<OutboundMemo> = Vec::new();
+
+        let mut items = batch.iter().peekable();
+        while let Some(item) = items.next() {
+            if !failed.contains(&item.address) {
+                group.push(item.clone());
+            }
+            if group.is_empty() || (group.len() < max_outputs.max(1) && items.peek().is_some()) {
+                continue;
+            }
+            let result = send(&group);
+            for sent in group.drain(..) {
+                if result.is_err() {
+                    failed.push(sent.address);
+                }
+                results.push((sent.id, result.clone()));
+            }
+        }
+        results
+    }
+
+    pub fn record_results(
+        &mut self,
+        now: u64,
+        results: Vec<(u64, Result<String, String>)>,
+    ) -> FlushReport {
+        let mut report = FlushReport::default();
+        let mut tx_memo_lens: HashMap<String, Vec<usize>> = HashMap::new();
+        for (id, result) in &results {
+            let Some(txid) = result.as_ref().ok().filter(|txid| is_known_txid(txid)) else {
+                continue;
+            };
+            if let Some(item) = self.items.iter().find(|i| i.id == *id) {
+                tx_memo_lens
+                    .entry(txid.clone())
+                    .or_default()
+                    .push(item.memo.len());
+            }
+        }
+        let fees: HashMap<String, u64> = tx_memo_lens
+            .into_iter()
+            .map(|(txid, lens)| (txid, fee::estimate_memos_fee(&lens)))
+            .collect();
+        report.fee_zatoshis = fees.values().sum();
+
+        for (id, result) in results {
+            let index = match self.items.iter().position(|item| item.id == id) {
+                Some(index) => index,
+                None => continue,
+            };
+            match result {
+                Ok(txid) => {
+                    match self.items.remove(index) {
+                        Some(item) if is_known_txid(&txid) => {
+                            let fee_zatoshis = fees.get(&txid).copied().unwrap_or_default();
+                            self.record_receipt(item, txid, fee_zatoshis, now);
+                        }
+                        Some(item) => eprintln!(
+                            "Warning: Sent #{} to {} without a txid; no receipt recorded",
+                            item.id, item.address
+                        ),
+                        None => {}
+                    }
+                    report.sent += 1;
+                }
+                Err(e) => {
+                    let item = &mut self.items[index];
+                    item.attempts += 1;
+                    item.next_attempt_at = now + backoff_secs(item.attempts);
+                    item.last_error = Some(e);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        if report.sent > 0 || report.failed > 0 {
+            if let Err(e) = self.save() {
+                eprintln!("Warning: Failed to persist outbound queue: {}", e);
+            }
+        }
+        report
+    }
+
+    fn record_receipt(&mut self, item: OutboundMemo, txid: String, fee_zatoshis: u64, now: u64) {
+        let (body, _) = signing::split_signature(&item.memo);
+        let (_, body) = response::split_reply_ref(body);
+        let preview = body
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .chars()
+            .take(RECEIPT_PREVIEW_CHARS)
+            .collect();
+        self.receipts.push_back(Receipt {
+            id: item.id,
+            address: item.address,
+            txid,
+            reply_to: item.reply_to,
+            preview,
+            sent_at: now,
+            status: None,
+            fee_zatoshis,
+        });
+        while self.receipts.len() > MAX_RECEIPTS {
+            self.receipts.pop_front();
+        }
+    }
+
+    pub fn receipts_for(&self, address: &str) -> Vec<&Receipt> {
+        self.receipts
+            .iter()
+            .filter(|receipt| receipt.address == address)
+            .collect()
+    }
+
+    pub fn unconfirmed_txids(&self) -> Vec<String> {
+        let mut txids: Vec<String> = self
+            .receipts
+            .iter()
+            .filter(|receipt| !receipt.status.is_some_and(|status| status.is_final()))
+            .map(|receipt| receipt.txid.clone())
+            .collect();
+        txids.dedup();
+        txids
+    }
+
+    pub fn update_status(&mut self, txid: &str, status: TxStatus) -> Result<usize, String> {
+        let mut updated = 0;
+        for receipt in self.receipts.iter_mut().filter(|r| r.txid == txid) {
+            receipt.status = Some(status);
+            updated += 1;
+        }
+        if updated > 0 {
+            self.save()?;
+        }
+        Ok(updated)
+    }
+
+    pub fn flush_with<F>(&mut self, now: u64, send: F) -> FlushReport
+    where
+        F: FnMut(&OutboundMemo) -> Result<String, String>,
+    {
+        let batch = self.due(now);
+        let results = Self::send_batch(&batch, send);
+        self.record_results(now, results)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &OutboundMemo> {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        assert_eq!(backoff_secs(1), 5);
+        assert_eq!(backoff_secs(2), 10);
+        assert_eq!(backoff_secs(4), 40);
+        assert_eq!(backoff_secs(30), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn test_failed_sends_are_retried_in_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("outbound.json");
+        let mut queue = OutboundQueue::load(&path).unwrap();
+
+        queue.enqueue("zs1alice", "[1/2] part one", 100).unwrap();
+        queue.enqueue("zs1alice", "[2/2] part two", 100).unwrap();
+        queue.enqueue("zs1bob", "hello bob", 100).unwrap();
+
+        let mut sent = Vec::new();
+        let report = queue.flush_with(100, |item| {
+            if item.address == "zs1alice" {
+                Err("zingo-cli hiccup".to_string())
+            } else {
+                sent.push(item.memo.clone());
+                Ok("tx-bob".to_string())
+            }
+        });
+        assert_eq!(
+            report,
+            FlushReport {
+                sent: 1,
+                failed: 1,
+                fee_zatoshis: REPLY_FEE_ZATOSHIS,
+            }
+        );
+        assert_eq!(sent, vec!["hello bob"]);
+        assert_eq!(queue.len(), 2);
+
+        let first = queue.items().next().unwrap();
+        assert_eq!(first.attempts, 1);
+        assert_eq!(first.next_attempt_at, 105);
+        assert_eq!(first.last_error.as_deref(), Some("zingo-cli hiccup"));
+
+        let report = queue.flush_with(104, |_| Ok("tx-early".to_string()));
+        assert_eq!(report, FlushReport::default());
+
+        let reloaded = OutboundQueue::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+
+        let mut queue = reloaded;
+        let mut order = Vec::new();
+        let report = queue.flush_with(105, |item| {
+            order.push(item.memo.clone());
+            Ok(format!("tx-{}", item.id))
+        });
+        assert_eq!(report.sent, 2);
+        assert_eq!(order, vec!["[1/2] part one", "[2/2] part two"]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.enqueue("zs1bob", "again", 200).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_held_queue_keeps_replies_until_resumed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut queue = OutboundQueue::load(temp_dir.path().join("outbound.json")).unwrap();
+        queue.enqueue("zs1alice", "pending reply", 10).unwrap();
+
+        queue.hold("balance too low");
+        assert_eq!(queue.held(), Some("balance too low"));
+        let report = queue.flush_with(20, |_| panic!("held queue must not send"));
+        assert_eq!(report, FlushReport::default());
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.items().next().unwrap().attempts, 0);
+
+        queue.resume();
+        assert_eq!(queue.flush_with(30, |_| Ok("tx".to_string())).sent, 1);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_sent_replies_leave_receipts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("outbound.json");
+        let mut queue = OutboundQueue::load(&path).unwrap();
+
+        queue
+            .enqueue_reply(
+                "zs1alice",
+                "ZB1 200 . ls /\nnotes.txt\nSIG:abcd",
+                Some("tx-in"),
+                10,
+            )
+            .unwrap();
+        queue.enqueue("zs1bob", "hello bob", 10).unwrap();
+        queue.flush_with(20, |item| Ok(format!("tx-out-{}", item.id)));
+
+        let reloaded = OutboundQueue::load(&path).unwrap();
+        let receipts = reloaded.receipts_for("zs1alice");
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].txid, "tx-out-1");
+        assert_eq!(receipts[0].reply_to.as_deref(), Some("tx-in"));
+        assert_eq!(receipts[0].preview, "ZB1 200 . ls / notes.txt");
+        assert_eq!(receipts[0].sent_at, 20);
+        assert_eq!(reloaded.receipts_for("zs1bob")[0].txid, "tx-out-2");
+    }
+
+    #[test]
+    fn test_sends_without_a_txid_leave_no_receipt() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("outbound.json");
+        let mut queue = OutboundQueue::load(&path).unwrap();
+
+        queue.enqueue("zs1alice", "hello alice", 10).unwrap();
+        assert_eq!(queue.flush_with(20, |_| Ok(String::new())).sent, 1);
+        assert!(queue.is_empty());
+        assert!(queue.receipts_for("zs1alice").is_empty());
+
+        queue.enqueue("zs1alice", "again", 30).unwrap();
+        queue.flush_with(40, |_| Ok("tx-real".to_string()));
+        queue.receipts[0].txid = UNKNOWN_TXID.to_string();
+        queue.save().unwrap();
+        let reloaded = OutboundQueue::load(&path).unwrap();
+        assert!(reloaded.receipts_for("zs1alice").is_empty());
+        assert!(reloaded.unconfirmed_txids().is_empty());
+    }
+
+    #[test]
+    fn test_funds_gate_refuses_replies_the_wallet_cannot_pay_for() {
+        let mut gate = FundsGate::default();
+        assert!(gate.admit(100, 1).is_ok());
+
+        gate.observe(Err("zingo-cli missing".to_string()));
+        assert!(gate.admit(100, 1).is_ok());
+
+        gate.observe(Ok(3 * REPLY_FEE_ZATOSHIS));
+        assert!(gate.admit(1, 2).is_ok());
+        let refused = gate.admit(2, 2).unwrap_err();
+        assert_eq!(
+            refused,
+            "Insufficient funds: 4 replies need about 40000 zatoshis in fees but the wallet has 30000 spendable"
+        );
+        assert_eq!(gate.alert(), Some(refused.as_str()));
+
+        gate.observe(Ok(10 * REPLY_FEE_ZATOSHIS));
+        assert!(gate.admit(2, 2).is_ok());
+        assert_eq!(gate.alert(), None);
+    }
+
+    #[test]
+    fn test_grouped_sends_share_one_transaction() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut queue = OutboundQueue::load(temp_dir.path().join("outbound.json")).unwrap();
+        for (address, memo) in [
+            ("zs1alice", "part 1"),
+            ("zs1alice", "part 2"),
+            ("zs1bob", "hello"),
+            ("zs1carol", "hi"),
+            ("zs1bob", "again"),
+        ] {
+            queue.enqueue(address, memo, 10).unwrap();
+        }
+
+        let mut groups = Vec::new();
+        let results = OutboundQueue::send_grouped(&queue.due(20), 2, |items| {
+            let addresses: Vec<String> = items.iter().map(|item| item.address.clone()).collect();
+            groups.push(addresses.clone());
+            if addresses.contains(&"zs1bob".to_string()) {
+                Err("bob's group failed".to_string())
+            } else {
+                Ok(format!("tx{}", groups.len()))
+            }
+        });
+        assert_eq!(
+            groups,
+            vec![vec!["zs1alice", "zs1alice"], vec!["zs1bob", "zs1carol"],]
+        );
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].1, Ok("tx1".to_string()));
+        assert_eq!(results[1].1, Ok("tx1".to_string()));
+        assert!(results[2].1.is_err() && results[3].1.is_err());
+
+        let report = queue.record_results(20, results);
+        assert_eq!((report.sent, report.failed), (2, 2));
+        assert_eq!(queue.len(), 3);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::crypto;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const PROBE_PREFIX: &str = "ZATBOARD_PROBE:";
+pub const PROVE_PREFIX: &str = "PROVE:";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct OwnershipConfig {
+    pub require_proof: bool,
+    pub ttl_secs: u64,
+}
+
+impl Default for OwnershipConfig {
+    fn default() -> Self {
+        OwnershipConfig {
+            require_proof: false,
+            ttl_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingRegistration {
+    pub reply_address: String,
+    pub public_key: Option<String>,
+    code: String,
+    issued_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Probes {
+    pending: HashMap<String, PendingRegistration>,
+}
+
+impl Probes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn issue(
+        &mut self,
+        user: &str,
+        reply_address: &str,
+        public_key: Option<String>,
+        now: u64,
+    ) -> String {
+        let mut code = [0u8; 8];
+        OsRng.fill_bytes(&mut code);
+        let code = crypto::to_hex(&code);
+        self.pending.insert(
+            user.to_string(),
+            PendingRegistration {
+                reply_address: reply_address.to_string(),
+                public_key,
+                code: code.clone(),
+                issued_at: now,
+            },
+        );
+        code
+    }
+
+    pub fn prove(
+        &mut self,
+        user: &str,
+        code: &str,
+        config: &OwnershipConfig,
+        now: u64,
+    ) -> Result<PendingRegistration, String> {
+        let pending = self.pending.remove(user).ok_or_else(|| {
+            "Invalid proof: no registration is waiting; send REGISTER:<reply_address> first"
+                .to_string()
+        })?;
+        if now.saturating_sub(pending.issued_at) >= config.ttl_secs {
+            return Err("Invalid proof: probe expired; send REGISTER again".to_string());
+        }
+        if pending.code != code {
+            return Err("Invalid proof: wrong probe code; send REGISTER again".to_string());
+        }
+        Ok(pending)
+    }
+
+    pub fn prune(&mut self, config: &OwnershipConfig, now: u64) -> usize {
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, pending| now.saturating_sub(pending.issued_at) < config.ttl_secs);
+        before - self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_codes_are_single_use() {
+        let config = OwnershipConfig::default();
+        let mut probes = Probes::new();
+        let code = probes.issue("zs1alice", "zs1alicereply", Some("ab".to_string()), 100);
+        assert_eq!(code.len(), 16);
+
+        assert!(probes.prove("zs1bob", &code, &config, 110).is_err());
+        let pending = probes.prove("zs1alice", &code, &config, 110).unwrap();
+        assert_eq!(pending.reply_address, "zs1alicereply");
+        assert_eq!(pending.public_key.as_deref(), Some("ab"));
+        assert!(probes.prove("zs1alice", &code, &config, 110).is_err());
+
+        probes.issue("zs1alice", "zs1alicereply", None, 100);
+        assert!(probes
+            .prove("zs1alice", "0000", &config, 110)
+            .unwrap_err()
+            .contains("wrong probe code"));
+        assert!(probes.is_empty());
+    }
+
+    #[test]
+    fn test_probes_expire() {
+        let config = OwnershipConfig {
+            ttl_secs: 60,
+            ..OwnershipConfig::default()
+        };
+        let mut probes = Probes::new();
+        let code = probes.issue("zs1alice", "zs1alicereply", None, 100);
+        assert!(probes
+            .prove("zs1alice", &code, &config, 160)
+            .unwrap_err()
+            .contains("expired"));
+
+        probes.issue("zs1alice", "zs1alicereply", None, 100);
+        probes.issue("zs1bob", "zs1bobreply", None, 150);
+        assert_eq!(probes.prune(&config, 170), 1);
+        assert_eq!(probes.len(), 1);
+    }
+}
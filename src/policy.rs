@@ -0,0 +1,225 @@
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyKind {
+    RateLimit,
+    BanList,
+    MinPayment,
+    Quota,
+}
+
+impl PolicyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyKind::RateLimit => "rate_limit",
+            PolicyKind::BanList => "ban_list",
+            PolicyKind::MinPayment => "min_payment",
+            PolicyKind::Quota => "quota",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PolicyConfig {
+    pub order: Vec<PolicyKind>,
+    pub min_payment_zatoshi: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            order: vec![
+                PolicyKind::RateLimit,
+                PolicyKind::BanList,
+                PolicyKind::MinPayment,
+                PolicyKind::Quota,
+            ],
+            min_payment_zatoshi: 0,
+        }
+    }
+}
+
+impl PolicyConfig {
+    pub fn describe(&self) -> String {
+        let order: Vec<&str> = self.order.iter().map(PolicyKind::as_str).collect();
+        format!(
+            "Policies: {}\nMinimum payment: {} zatoshis",
+            if order.is_empty() {
+                "none".to_string()
+            } else {
+                order.join(" -> ")
+            },
+            self.min_payment_zatoshi
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Allow,
+    Deny(String),
+}
+
+impl Decision {
+    pub fn into_result(self) -> Result<(), String> {
+        match self {
+            Decision::Allow => Ok(()),
+            Decision::Deny(reason) => Err(reason),
+        }
+    }
+}
+
+pub trait PolicyContext {
+    fn is_banned(&self, sender: &str) -> bool;
+
+    fn is_exempt(&self, sender: &str) -> bool;
+
+    fn check_rate(&mut self, sender: &str) -> Result<(), String>;
+
+    fn check_quota(&mut self, message: &Message) -> Result<(), String>;
+}
+
+pub fn evaluate(
+    config: &PolicyConfig,
+    context: &mut impl PolicyContext,
+    message: &Message,
+) -> Decision {
+    let sender = &message.sender_address;
+    for policy in &config.order {
+        let outcome = match policy {
+            PolicyKind::RateLimit => context.check_rate(sender),
+            PolicyKind::BanList if context.is_banned(sender) => {
+                Err("Access denied: this address is banned".to_string())
+            }
+            PolicyKind::MinPayment
+                if message.amount_zatoshis < config.min_payment_zatoshi
+                    && !context.is_exempt(sender) =>
+            {
+                Err(format!(
+                    "Payment required: memos must carry at least {} zatoshis (received {})",
+                    config.min_payment_zatoshi, message.amount_zatoshis
+                ))
+            }
+            PolicyKind::Quota if !context.is_exempt(sender) => context.check_quota(message),
+            _ => Ok(()),
+        };
+        if let Err(reason) = outcome {
+            return Decision::Deny(reason);
+        }
+    }
+    Decision::Allow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeContext {
+        banned: Vec<String>,
+        admins: Vec<String>,
+        throttled: bool,
+        over_quota: bool,
+        calls: Vec<&'static str>,
+    }
+
+    impl PolicyContext for FakeContext {
+        fn is_banned(&self, sender: &str) -> bool {
+            self.banned.iter().any(|banned| banned == sender)
+        }
+
+        fn is_exempt(&self, sender: &str) -> bool {
+            self.admins.iter().any(|admin| admin == sender)
+        }
+
+        fn check_rate(&mut self, _: &str) -> Result<(), String> {
+            self.calls.push("rate");
+            if self.throttled {
+                Err("Rate limit exceeded".to_string())
+            } else {
+                Ok(())
+            }
+        }
+
+        fn check_quota(&mut self, _: &Message) -> Result<(), String> {
+            self.calls.push("quota");
+            if self.over_quota {
+                Err("Quota exceeded".to_string())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn memo(sender: &str, paid: u64) -> Message {
+        let mut message = Message::new(
+            sender.to_string(),
+            "zs1coordinator".to_string(),
+            "ls /".to_string(),
+        );
+        message.amount_zatoshis = paid;
+        message
+    }
+
+    #[test]
+    fn test_policies_run_in_configured_order() {
+        let config = PolicyConfig {
+            min_payment_zatoshi: 50,
+            ..PolicyConfig::default()
+        };
+        let mut context = FakeContext {
+            banned: vec!["zs1spam".to_string()],
+            admins: vec!["zs1admin".to_string()],
+            ..FakeContext::default()
+        };
+
+        assert_eq!(
+            evaluate(&config, &mut context, &memo("zs1spam", 100)),
+            Decision::Deny("Access denied: this address is banned".to_string())
+        );
+        assert!(evaluate(&config, &mut context, &memo("zs1user", 10))
+            .into_result()
+            .unwrap_err()
+            .starts_with("Payment required"));
+        assert_eq!(
+            evaluate(&config, &mut context, &memo("zs1admin", 0)),
+            Decision::Allow
+        );
+        assert_eq!(
+            evaluate(&config, &mut context, &memo("zs1user", 50)),
+            Decision::Allow
+        );
+        assert_eq!(context.calls, vec!["rate", "rate", "rate", "rate", "quota"]);
+
+        context.over_quota = true;
+        assert_eq!(
+            evaluate(&config, &mut context, &memo("zs1user", 50)),
+            Decision::Deny("Quota exceeded".to_string())
+        );
+    }
+
+    #[test]
+    fn test_policies_can_be_disabled_from_config() {
+        let config: PolicyConfig =
+            toml::from_str("order = [\"ban_list\"]\nmin_payment_zatoshi = 1000").unwrap();
+        let mut context = FakeContext {
+            throttled: true,
+            over_quota: true,
+            ..FakeContext::default()
+        };
+
+        assert_eq!(
+            evaluate(&config, &mut context, &memo("zs1user", 0)),
+            Decision::Allow
+        );
+        assert!(context.calls.is_empty());
+        assert_eq!(
+            config.describe(),
+            "Policies: ban_list\nMinimum payment: 1000 zatoshis"
+        );
+        assert!(toml::from_str::<PolicyConfig>("order = [\"firewall\"]").is_err());
+    }
+}
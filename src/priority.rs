@@ -0,0 +1,199 @@
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PRIORITY: u8 = 4;
+pub const MAX_PRIORITY: u8 = 9;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PriorityConfig {
+    pub enabled: bool,
+    pub batch_size: usize,
+    pub max_wait_secs: u64,
+    pub message_priority: bool,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        PriorityConfig {
+            enabled: true,
+            batch_size: 20,
+            max_wait_secs: 60,
+            message_priority: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    seq: u64,
+    enqueued_at: u64,
+    message: Message,
+}
+
+#[derive(Debug, Default)]
+pub struct MessageQueue {
+    pending: Vec<Pending>,
+    next_seq: u64,
+}
+
+impl MessageQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, message: Message, now: u64) {
+        self.next_seq += 1;
+        self.pending.push(Pending {
+            seq: self.next_seq,
+            enqueued_at: now,
+            message,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn next_batch(&mut self, config: &PriorityConfig, now: u64) -> Vec<Message> {
+        if config.enabled {
+            let starving =
+                |item: &Pending| now.saturating_sub(item.enqueued_at) >= config.max_wait_secs;
+            let priority = |item: &Pending| {
+                if config.message_priority {
+                    item.message
+                        .priority
+                        .unwrap_or(DEFAULT_PRIORITY)
+                        .min(MAX_PRIORITY)
+                } else {
+                    DEFAULT_PRIORITY
+                }
+            };
+            self.pending.sort_by(|a, b| {
+                starving(b).cmp(&starving(a)).then_with(|| {
+                    if starving(a) {
+                        a.seq.cmp(&b.seq)
+                    } else {
+                        b.message
+                            .amount_zatoshis
+                            .cmp(&a.message.amount_zatoshis)
+                            .then_with(|| priority(b).cmp(&priority(a)))
+                            .then_with(|| a.seq.cmp(&b.seq))
+                    }
+                })
+            });
+        } else {
+            self.pending.sort_by_key(|item| item.seq);
+        }
+
+        let take = match config.batch_size {
+            0 => self.pending.len(),
+            size => size.min(self.pending.len()),
+        };
+        self.pending
+            .drain(..take)
+            .map(|item| item.message)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paid(memo: &str, amount: u64) -> Message {
+        Message::new(
+            "zs1user".to_string(),
+            "zs1coordinator".to_string(),
+            memo.to_string(),
+        )
+        .with_amount(amount)
+    }
+
+    fn memos(batch: Vec<Message>) -> Vec<String> {
+        batch.into_iter().map(|message| message.memo_text).collect()
+    }
+
+    #[test]
+    fn test_higher_payments_go_first_within_a_batch() {
+        let config = PriorityConfig {
+            batch_size: 2,
+            ..PriorityConfig::default()
+        };
+        let mut queue = MessageQueue::new();
+        queue.push(paid("spam 1", 0), 100);
+        queue.push(paid("paid 500", 500), 100);
+        queue.push(paid("spam 2", 0), 100);
+        queue.push(paid("paid 1000", 1000), 100);
+
+        assert_eq!(
+            memos(queue.next_batch(&config, 101)),
+            vec!["paid 1000", "paid 500"]
+        );
+        assert_eq!(
+            memos(queue.next_batch(&config, 101)),
+            vec!["spam 1", "spam 2"]
+        );
+        assert!(queue.is_empty());
+
+        let fifo = PriorityConfig {
+            enabled: false,
+            batch_size: 0,
+            ..PriorityConfig::default()
+        };
+        queue.push(paid("first", 0), 100);
+        queue.push(paid("second", 900), 100);
+        assert_eq!(memos(queue.next_batch(&fifo, 100)), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_message_priority_orders_equal_payments() {
+        let prioritized = |memo: &str, amount: u64, priority: Option<u8>| Message {
+            priority,
+            ..paid(memo, amount)
+        };
+        let mut queue = MessageQueue::new();
+        queue.push(prioritized("low", 0, Some(0)), 100);
+        queue.push(prioritized("default", 0, None), 100);
+        queue.push(prioritized("urgent", 0, Some(200)), 100);
+        queue.push(prioritized("high", 0, Some(8)), 100);
+        queue.push(prioritized("paid", 10, Some(0)), 100);
+        assert_eq!(
+            memos(queue.next_batch(&PriorityConfig::default(), 101)),
+            vec!["paid", "urgent", "high", "default", "low"]
+        );
+
+        let ignored = PriorityConfig {
+            message_priority: false,
+            ..PriorityConfig::default()
+        };
+        queue.push(prioritized("first", 0, Some(0)), 100);
+        queue.push(prioritized("second", 0, Some(9)), 100);
+        assert_eq!(
+            memos(queue.next_batch(&ignored, 101)),
+            vec!["first", "second"]
+        );
+    }
+
+    #[test]
+    fn test_starving_messages_jump_the_queue() {
+        let config = PriorityConfig {
+            batch_size: 1,
+            max_wait_secs: 30,
+            ..PriorityConfig::default()
+        };
+        let mut queue = MessageQueue::new();
+        queue.push(paid("free", 0), 100);
+        queue.push(paid("paid", 10), 120);
+        assert_eq!(memos(queue.next_batch(&config, 125)), vec!["paid"]);
+
+        queue.push(paid("paid again", 10), 128);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(memos(queue.next_batch(&config, 130)), vec!["free"]);
+        assert_eq!(memos(queue.next_batch(&config, 130)), vec!["paid again"]);
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+const MAX_PROCESSED_ENTRIES: usize = 5000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessedEntry {
+    pub key: String,
+    pub sender: String,
+    pub ok: bool,
+    pub response: String,
+    pub replied: bool,
+    pub processed_at: u64,
+}
+
+impl ProcessedEntry {
+    pub fn new(key: &str, sender: &str, result: &Result<String, String>, now: u64) -> Self {
+        let (ok, response) = match result {
+            Ok(response) => (true, response.clone()),
+            Err(e) => (false, e.clone()),
+        };
+
+        ProcessedEntry {
+            key: key.to_string(),
+            sender: sender.to_string(),
+            ok,
+            response,
+            replied: false,
+            processed_at: now,
+        }
+    }
+
+    pub fn result(&self) -> Result<String, String> {
+        if self.ok {
+            Ok(self.response.clone())
+        } else {
+            Err(self.response.clone())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessedLog {
+    path: PathBuf,
+    entries: HashMap<String, ProcessedEntry>,
+    order: VecDeque<String>,
+}
+
+impl ProcessedLog {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let stored: Vec<ProcessedEntry> = if path.exists() {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read processed log: {}", e))?;
+            serde_json::from_str(&contents)
+                .map_err(|e| format!("Failed to parse processed log: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        let mut log = ProcessedLog {
+            path,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        };
+        for entry in stored {
+            log.insert(entry);
+        }
+        Ok(log)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let stored: Vec<&ProcessedEntry> = self
+            .order
+            .iter()
+            .filter_map(|key| self.entries.get(key))
+            .collect();
+        let contents = serde_json::to_string(&stored)
+            .map_err(|e| format!("Failed to serialize processed log: {}", e))?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write processed log: {}", e))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| format!("Failed to replace processed log: {}", e))
+    }
+
+    fn insert(&mut self, entry: ProcessedEntry) {
+        if self
+            .entries
+            .insert(entry.key.clone(), entry.clone())
+            .is_none()
+        {
+            self.order.push_back(entry.key);
+        }
+        while self.order.len() > MAX_PROCESSED_ENTRIES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ProcessedEntry> {
+        self.entries.get(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn record(&mut self, entry: ProcessedEntry) -> Result<(), String> {
+        self.insert(entry);
+        self.save()
+    }
+
+    pub fn mark_replied(&mut self, key: &str) -> Result<(), String> {
+        match self.entries.get_mut(key) {
+            Some(entry) if !entry.replied => {
+                entry.replied = true;
+                self.save()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_reload() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("processed.json");
+
+        let mut log = ProcessedLog::load(&path).unwrap();
+        assert!(log.is_empty());
+        log.record(ProcessedEntry::new(
+            "tx1:0",
+            "zs1user",
+            &Ok("done".to_string()),
+            10,
+        ))
+        .unwrap();
+        log.record(ProcessedEntry::new(
+            "tx1:1",
+            "zs1user",
+            &Err("nope".to_string()),
+            11,
+        ))
+        .unwrap();
+        log.mark_replied("tx1:0").unwrap();
+
+        let reloaded = ProcessedLog::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert!(reloaded.get("tx1:0").unwrap().replied);
+        assert!(!reloaded.get("tx1:1").unwrap().replied);
+        assert_eq!(
+            reloaded.get("tx1:1").unwrap().result(),
+            Err("nope".to_string())
+        );
+    }
+
+    #[test]
+    fn test_oldest_entries_are_evicted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut log = ProcessedLog::load(temp_dir.path().join("processed.json")).unwrap();
+
+        for i in 0..MAX_PROCESSED_ENTRIES + 3 {
+            log.insert(ProcessedEntry::new(
+                &format!("tx{}:0", i),
+                "zs1user",
+                &Ok(String::new()),
+                i as u64,
+            ));
+        }
+
+        assert_eq!(log.len(), MAX_PROCESSED_ENTRIES);
+        assert!(!log.contains("tx0:0"));
+        assert!(log.contains(&format!("tx{}:0", MAX_PROCESSED_ENTRIES + 2)));
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+const MIN_NAME_LENGTH: usize = 3;
+const MAX_NAME_LENGTH: usize = 24;
+const MAX_BIO_LENGTH: usize = 160;
+const MAX_KEY_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Profile {
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub bio: Option<String>,
+    #[serde(default)]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+impl Profile {
+    pub fn is_empty(&self) -> bool {
+        self.display_name.is_none() && self.bio.is_none() && self.public_key.is_none()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    profiles: HashMap<String, Profile>,
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    let length = name.chars().count();
+    if !(MIN_NAME_LENGTH..=MAX_NAME_LENGTH).contains(&length) {
+        return Err(format!(
+            "Display name must be {}-{} characters",
+            MIN_NAME_LENGTH, MAX_NAME_LENGTH
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("Display name may only contain letters, digits, '_' and '-'".to_string());
+    }
+    if looks_like_participant_id(name) {
+        return Err("Display name cannot look like a participant ID".to_string());
+    }
+    Ok(())
+}
+
+fn looks_like_participant_id(name: &str) -> bool {
+    name.len() == 7
+        && name.starts_with('P')
+        && name[1..]
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+}
+
+fn bounded(field: &str, value: &str, max: usize) -> Result<(), String> {
+    if value.chars().count() > max {
+        return Err(format!("{} too long (max {} characters)", field, max));
+    }
+    Ok(())
+}
+
+impl Profiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Profiles::new());
+        }
+
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read profiles: {}", e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse profiles: {}", e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize profiles: {}", e))?;
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write profiles: {}", e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace profiles: {}", e))
+    }
+
+    pub fn get(&self, user: &str) -> Option<&Profile> {
+        self.profiles.get(user)
+    }
+
+    pub fn user_by_name(&self, name: &str) -> Option<&str> {
+        self.profiles
+            .iter()
+            .find(|(_, profile)| {
+                profile
+                    .display_name
+                    .as_deref()
+                    .is_some_and(|existing| existing.eq_ignore_ascii_case(name))
+            })
+            .map(|(user, _)| user.as_str())
+    }
+
+    pub fn set(&mut self, user: &str, field: &str, value: &str, now: u64) -> Result<(), String> {
+        let value = value.trim();
+        let value = if value.is_empty() {
+            None
+        } else {
+            Some(value.to_string())
+        };
+
+        match field {
+            "name" => {
+                if let Some(name) = &value {
+                    validate_name(name)?;
+                    if self.user_by_name(name).is_some_and(|owner| owner != user) {
+                        return Err(format!("Display name already taken: {}", name));
+                    }
+                }
+            }
+            "bio" => bounded("Bio", value.as_deref().unwrap_or(""), MAX_BIO_LENGTH)?,
+            "key" => {
+                let key = value.as_deref().unwrap_or("");
+                bounded("Public key", key, MAX_KEY_LENGTH)?;
+                if key.contains(char::is_whitespace) {
+                    return Err("Public key cannot contain spaces".to_string());
+                }
+            }
+            other => {
+                return Err(format!(
+                    "Unknown profile field: {}. Use name, bio or key",
+                    other
+                ))
+            }
+        }
+
+        let profile = self.profiles.entry(user.to_string()).or_default();
+        match field {
+            "name" => profile.display_name = value,
+            "bio" => profile.bio = value,
+            _ => profile.public_key = value,
+        }
+        profile.updated_at = now;
+
+        if profile.is_empty() {
+            self.profiles.remove(user);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, user: &str) -> Option<Profile> {
+        self.profiles.remove(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_lookup_profile() {
+        let mut profiles = Profiles::new();
+        profiles.set("zs1a", "name", "alice", 1).unwrap();
+        profiles.set("zs1a", "bio", "  likes boards ", 2).unwrap();
+        profiles.set("zs1a", "key", "age1xyz", 3).unwrap();
+
+        let profile = profiles.get("zs1a").unwrap();
+        assert_eq!(profile.display_name.as_deref(), Some("alice"));
+        assert_eq!(profile.bio.as_deref(), Some("likes boards"));
+        assert_eq!(profile.updated_at, 3);
+        assert_eq!(profiles.user_by_name("ALICE"), Some("zs1a"));
+
+        assert!(profiles.set("zs1b", "name", "Alice", 4).is_err());
+        assert!(profiles.set("zs1a", "name", "alice", 4).is_ok());
+        assert!(profiles.set("zs1b", "name", "P1A2B3C", 4).is_err());
+        assert!(profiles.set("zs1b", "name", "no spaces", 4).is_err());
+        assert!(profiles.set("zs1b", "key", "a b", 4).is_err());
+        assert!(profiles.set("zs1b", "email", "x", 4).is_err());
+    }
+
+    #[test]
+    fn test_clearing_all_fields_removes_profile() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("profiles.json");
+
+        let mut profiles = Profiles::new();
+        profiles.set("zs1a", "name", "alice", 1).unwrap();
+        profiles.save(&path).unwrap();
+        assert_eq!(
+            Profiles::load(&path).unwrap().get("zs1a"),
+            profiles.get("zs1a")
+        );
+
+        profiles.set("zs1a", "name", "", 2).unwrap();
+        assert!(profiles.get("zs1a").is_none());
+        assert!(profiles.user_by_name("alice").is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::commands::CommandClass;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub enabled: bool,
+    pub window_secs: u64,
+    pub reads_per_window: u32,
+    pub writes_per_window: u32,
+    pub uploads_per_window: u32,
+    pub chat_per_window: u32,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        QuotaConfig {
+            enabled: true,
+            window_secs: 24 * 60 * 60,
+            reads_per_window: 0,
+            writes_per_window: 50,
+            uploads_per_window: 50,
+            chat_per_window: 200,
+        }
+    }
+}
+
+impl QuotaConfig {
+    pub fn limit_for(&self, class: CommandClass) -> u32 {
+        match class {
+            CommandClass::Read => self.reads_per_window,
+            CommandClass::Write => self.writes_per_window,
+            CommandClass::Upload => self.uploads_per_window,
+            CommandClass::Chat => self.chat_per_window,
+        }
+    }
+}
+
+const CLASSES: [CommandClass; 4] = [
+    CommandClass::Read,
+    CommandClass::Write,
+    CommandClass::Upload,
+    CommandClass::Chat,
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuotaUsage {
+    #[serde(default)]
+    used: HashMap<String, VecDeque<u64>>,
+}
+
+#[derive(Debug, Default)]
+pub struct QuotaTracker {
+    config: QuotaConfig,
+    usage: HashMap<String, QuotaUsage>,
+}
+
+impl QuotaTracker {
+    pub fn new(usage: HashMap<String, QuotaUsage>) -> Self {
+        QuotaTracker {
+            config: QuotaConfig::default(),
+            usage,
+        }
+    }
+
+    pub fn set_config(&mut self, config: QuotaConfig) {
+        self.config = config;
+    }
+
+    pub fn usage(&self) -> &HashMap<String, QuotaUsage> {
+        &self.usage
+    }
+
+    fn recent(&mut self, user: &str, class: CommandClass, now: u64) -> Option<&VecDeque<u64>> {
+        let window_secs = self.config.window_secs;
+        let stamps = self.usage.get_mut(user)?.used.get_mut(class.as_str())?;
+        while stamps
+            .front()
+            .is_some_and(|stamp| stamp.saturating_add(window_secs) <= now)
+        {
+            stamps.pop_front();
+        }
+        Some(stamps)
+    }
+
+    pub fn check(&mut self, user: &str, class: CommandClass, now: u64) -> Result<(), String> {
+        let limit = self.config.limit_for(class);
+        if !self.config.enabled || limit == 0 {
+            return Ok(());
+        }
+
+        let window_secs = self.config.window_secs;
+        let stamps = match self.recent(user, class, now) {
+            Some(stamps) => stamps,
+            None => return Ok(()),
+        };
+        if stamps.len() < limit as usize {
+            return Ok(());
+        }
+
+        let frees_in = stamps
+            .front()
+            .map(|oldest| (oldest + window_secs).saturating_sub(now))
+            .unwrap_or(0);
+        Err(format!(
+            "Quota exceeded: {} of {} {} commands used, next one frees up in {}s",
+            stamps.len(),
+            limit,
+            class.as_str(),
+            frees_in
+        ))
+    }
+
+    pub fn record(&mut self, user: &str, class: CommandClass, now: u64) {
+        let limit = self.config.limit_for(class);
+        if !self.config.enabled || limit == 0 {
+            return;
+        }
+
+        self.recent(user, class, now);
+        let stamps = self
+            .usage
+            .entry(user.to_string())
+            .or_default()
+            .used
+            .entry(class.as_str().to_string())
+            .or_default();
+        stamps.push_back(now);
+        while stamps.len() > limit as usize {
+            stamps.pop_front();
+        }
+    }
+
+    pub fn prune(&mut self, now: u64) {
+        let window_secs = self.config.window_secs;
+        for usage in self.usage.values_mut() {
+            for stamps in usage.used.values_mut() {
+                stamps.retain(|stamp| stamp.saturating_add(window_secs) > now);
+            }
+            usage.used.retain(|_, stamps| !stamps.is_empty());
+        }
+        self.usage.retain(|_, usage| !usage.used.is_empty());
+    }
+
+    pub fn summary(&mut self, user: &str, now: u64) -> String {
+        if !self.config.enabled {
+            return "Quotas are disabled".to_string();
+        }
+
+        let window_secs = self.config.window_secs;
+        let mut lines = Vec::new();
+        for class in CLASSES {
+            let limit = self.config.limit_for(class);
+            if limit == 0 {
+                lines.push(format!("{}: unlimited", class.as_str()));
+                continue;
+            }
+
+            let (used, frees_in) = match self.recent(user, class, now) {
+                Some(stamps) => (
+                    stamps.len(),
+                    stamps
+                        .front()
+                        .map(|oldest| (oldest + window_secs).saturating_sub(now)),
+                ),
+                None => (0, None),
+            };
+            let mut line = format!(
+                "{}: {} of {} left",
+                class.as_str(),
+                (limit as usize).saturating_sub(used),
+                limit
+            );
+            if let Some(frees_in) = frees_in {
+                line.push_str(&format!(" (next slot frees in {}s)", frees_in));
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(writes: u32) -> QuotaTracker {
+        let mut tracker = QuotaTracker::default();
+        tracker.set_config(QuotaConfig {
+            window_secs: 100,
+            writes_per_window: writes,
+            ..QuotaConfig::default()
+        });
+        tracker
+    }
+
+    #[test]
+    fn test_rolling_window_quota() {
+        let mut quotas = tracker(2);
+        assert!(quotas.check("zs1a", CommandClass::Write, 0).is_ok());
+        quotas.record("zs1a", CommandClass::Write, 0);
+        quotas.record("zs1a", CommandClass::Write, 50);
+
+        let err = quotas.check("zs1a", CommandClass::Write, 60).unwrap_err();
+        assert!(err.contains("2 of 2 write"));
+        assert!(err.contains("frees up in 40s"));
+        assert!(quotas.check("zs1b", CommandClass::Write, 60).is_ok());
+        assert!(quotas.check("zs1a", CommandClass::Read, 60).is_ok());
+
+        assert!(quotas.check("zs1a", CommandClass::Write, 100).is_ok());
+        assert!(quotas
+            .summary("zs1a", 100)
+            .contains("write: 1 of 2 left (next slot frees in 50s)"));
+        assert!(quotas.summary("zs1a", 100).contains("read: unlimited"));
+    }
+
+    #[test]
+    fn test_prune_drops_expired_usage() {
+        let mut quotas = tracker(5);
+        quotas.record("zs1a", CommandClass::Write, 10);
+        quotas.record("zs1b", CommandClass::Write, 90);
+
+        quotas.prune(150);
+        assert!(!quotas.usage().contains_key("zs1a"));
+        assert!(quotas.usage().contains_key("zs1b"));
+    }
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+const MAX_TRACKED_SENDERS: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub burst: u32,
+    pub refill_per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            burst: 20,
+            refill_per_minute: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    throttled: bool,
+    notice_pending: bool,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: RateLimitConfig) {
+        self.config = config;
+        self.buckets.clear();
+    }
+
+    pub fn check(&mut self, sender: &str) -> Result<(), String> {
+        self.check_at(sender, Instant::now())
+    }
+
+    pub fn check_at(&mut self, sender: &str, now: Instant) -> Result<(), String> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        if !self.buckets.contains_key(sender) && self.buckets.len() >= MAX_TRACKED_SENDERS {
+            self.prune(now);
+        }
+
+        let capacity = self.config.burst.max(1) as f64;
+        let refill_per_sec = self.config.refill_per_minute as f64 / 60.0;
+        let bucket = self
+            .buckets
+            .entry(sender.to_string())
+            .or_insert_with(|| Bucket {
+                tokens: capacity,
+                last_refill: now,
+                throttled: false,
+                notice_pending: false,
+            });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * refill_per_sec).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.throttled = false;
+            return Ok(());
+        }
+
+        if !bucket.throttled {
+            bucket.throttled = true;
+            bucket.notice_pending = true;
+        }
+
+        let retry_after = if refill_per_sec > 0.0 {
+            ((1.0 - bucket.tokens) / refill_per_sec).ceil() as u64
+        } else {
+            0
+        };
+        Err(format!(
+            "Rate limit exceeded: slow down and retry in {}s",
+            retry_after
+        ))
+    }
+
+    pub fn is_throttled(&self, sender: &str) -> bool {
+        self.buckets
+            .get(sender)
+            .is_some_and(|bucket| bucket.throttled)
+    }
+
+    pub fn take_notice(&mut self, sender: &str) -> bool {
+        match self.buckets.get_mut(sender) {
+            Some(bucket) if bucket.notice_pending => {
+                bucket.notice_pending = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let capacity = self.config.burst.max(1) as f64;
+        let refill_per_sec = self.config.refill_per_minute as f64 / 60.0;
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.last_refill);
+            bucket.tokens + elapsed.as_secs_f64() * refill_per_sec < capacity
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            burst: 2,
+            refill_per_minute: 60,
+        });
+        let start = Instant::now();
+
+        assert!(limiter.check_at("zs1spam", start).is_ok());
+        assert!(limiter.check_at("zs1spam", start).is_ok());
+        assert!(limiter.check_at("zs1spam", start).is_err());
+        assert!(limiter.check_at("zs1other", start).is_ok());
+
+        assert!(limiter
+            .check_at("zs1spam", start + Duration::from_secs(1))
+            .is_ok());
+        assert!(limiter
+            .check_at("zs1spam", start + Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_notice_sent_once_per_throttle() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            burst: 1,
+            refill_per_minute: 60,
+        });
+        let start = Instant::now();
+
+        limiter.check_at("zs1spam", start).unwrap();
+        assert!(!limiter.take_notice("zs1spam"));
+        assert!(limiter.check_at("zs1spam", start).is_err());
+        assert!(limiter.take_notice("zs1spam"));
+        assert!(limiter.check_at("zs1spam", start).is_err());
+        assert!(!limiter.take_notice("zs1spam"));
+
+        limiter
+            .check_at("zs1spam", start + Duration::from_secs(2))
+            .unwrap();
+        assert!(limiter
+            .check_at("zs1spam", start + Duration::from_secs(2))
+            .is_err());
+        assert!(limiter.take_notice("zs1spam"));
+    }
+
+    #[test]
+    fn test_disabled_limiter_allows_everything() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            enabled: false,
+            burst: 1,
+            refill_per_minute: 0,
+        });
+        for _ in 0..5 {
+            assert!(limiter.check("zs1spam").is_ok());
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+
+pub const MAX_RELAY_LENGTH: usize = 500;
+const MAX_RELAYS: usize = 2000;
+const PREVIEW_LENGTH: usize = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Relay {
+    pub id: u64,
+    pub sender: String,
+    pub target: String,
+    pub preview: String,
+    pub outbound_ids: Vec<u64>,
+    pub sent_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelayStatus {
+    Delivered,
+    Pending {
+        attempts: u32,
+        last_error: Option<String>,
+    },
+}
+
+impl RelayStatus {
+    pub fn describe(&self) -> String {
+        match self {
+            RelayStatus::Delivered => "delivered".to_string(),
+            RelayStatus::Pending {
+                attempts: 0,
+                last_error: _,
+            } => "queued".to_string(),
+            RelayStatus::Pending {
+                attempts,
+                last_error,
+            } => format!(
+                "retrying ({} attempts, last error: {})",
+                attempts,
+                last_error.as_deref().unwrap_or("unknown")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RelayLog {
+    next_id: u64,
+    relays: VecDeque<Relay>,
+}
+
+impl RelayLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(RelayLog::new());
+        }
+
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read relays: {}", e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse relays: {}", e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize relays: {}", e))?;
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write relays: {}", e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace relays: {}", e))
+    }
+
+    pub fn record(
+        &mut self,
+        sender: &str,
+        target: &str,
+        text: &str,
+        outbound_ids: Vec<u64>,
+        now: u64,
+    ) -> u64 {
+        self.next_id += 1;
+        let mut preview: String = text.chars().take(PREVIEW_LENGTH).collect();
+        if text.chars().count() > PREVIEW_LENGTH {
+            preview.push('…');
+        }
+
+        self.relays.push_back(Relay {
+            id: self.next_id,
+            sender: sender.to_string(),
+            target: target.to_string(),
+            preview,
+            outbound_ids,
+            sent_at: now,
+        });
+        while self.relays.len() > MAX_RELAYS {
+            self.relays.pop_front();
+        }
+        self.next_id
+    }
+
+    pub fn sent_by(&self, sender: &str, limit: usize) -> Vec<&Relay> {
+        let mut relays: Vec<&Relay> = self
+            .relays
+            .iter()
+            .rev()
+            .filter(|relay| relay.sender == sender)
+            .take(limit)
+            .collect();
+        relays.reverse();
+        relays
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sent_by_returns_latest_for_sender() {
+        let mut log = RelayLog::new();
+        log.record("zs1a", "P111111", "first", vec![1], 10);
+        log.record("zs1b", "P222222", "not mine", vec![2], 11);
+        log.record("zs1a", "P333333", &"x".repeat(40), vec![3, 4], 12);
+        log.record("zs1a", "P111111", "third", vec![5], 13);
+
+        let recent = log.sent_by("zs1a", 2);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].target, "P333333");
+        assert!(recent[0].preview.ends_with('…'));
+        assert_eq!(recent[1].id, 4);
+        assert!(log.sent_by("zs1c", 5).is_empty());
+    }
+
+    #[test]
+    fn test_relays_persist() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("relays.json");
+
+        let mut log = RelayLog::new();
+        log.record("zs1a", "P111111", "hello", vec![7], 10);
+        log.save(&path).unwrap();
+
+        let loaded = RelayLog::load(&path).unwrap();
+        assert_eq!(loaded.sent_by("zs1a", 5), log.sent_by("zs1a", 5));
+        assert_eq!(
+            RelayStatus::Pending {
+                attempts: 2,
+                last_error: Some("timeout".to_string())
+            }
+            .describe(),
+            "retrying (2 attempts, last error: timeout)"
+        );
+    }
+}
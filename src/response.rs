@@ -0,0 +1,369 @@
+use crate::memo_decoder;
+use serde::{Deserialize, Serialize};
+
+pub const ENVELOPE_TAG: &str = "ZB1";
+pub const ACK_TAG: &str = "ZBACK";
+pub const REPLY_REF_PREFIX: &str = "RE:";
+pub const REPLY_REF_LEN: usize = 16;
+const MAX_ECHO_CHARS: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ResponseConfig {
+    pub envelope: bool,
+    pub threading: bool,
+    pub acks: bool,
+}
+
+impl Default for ResponseConfig {
+    fn default() -> Self {
+        ResponseConfig {
+            envelope: true,
+            threading: true,
+            acks: false,
+        }
+    }
+}
+
+pub fn reply_ref(txid: &str) -> &str {
+    match txid.char_indices().nth(REPLY_REF_LEN) {
+        Some((end, _)) => &txid[..end],
+        None => txid,
+    }
+}
+
+pub fn reply_ref_header(txid: &str) -> String {
+    format!("{}{}\n", REPLY_REF_PREFIX, reply_ref(txid))
+}
+
+pub fn split_reply_ref(memo: &str) -> (Option<&str>, &str) {
+    memo.strip_prefix(REPLY_REF_PREFIX)
+        .and_then(|rest| rest.split_once('\n'))
+        .filter(|(reference, _)| !reference.is_empty() && !reference.contains(char::is_whitespace))
+        .map_or((None, memo), |(reference, body)| (Some(reference), body))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Invalid,
+    Unauthenticated,
+    PaymentRequired,
+    Denied,
+    NotFound,
+    Conflict,
+    Expired,
+    Corrupted,
+    Throttled,
+    Error,
+}
+
+const STATUSES: [Status; 11] = [
+    Status::Ok,
+    Status::Invalid,
+    Status::Unauthenticated,
+    Status::PaymentRequired,
+    Status::Denied,
+    Status::NotFound,
+    Status::Conflict,
+    Status::Expired,
+    Status::Corrupted,
+    Status::Throttled,
+    Status::Error,
+];
+
+impl Status {
+    pub fn code(&self) -> u16 {
+        match self {
+            Status::Ok => 200,
+            Status::Invalid => 400,
+            Status::Unauthenticated => 401,
+            Status::PaymentRequired => 402,
+            Status::Denied => 403,
+            Status::NotFound => 404,
+            Status::Conflict => 409,
+            Status::Expired => 410,
+            Status::Corrupted => 422,
+            Status::Throttled => 429,
+            Status::Error => 500,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Option<Status> {
+        STATUSES
+            .iter()
+            .copied()
+            .find(|status| status.code() == code)
+    }
+
+    pub fn classify(error: &str) -> Status {
+        let error = error.to_lowercase();
+        let starts = |prefixes: &[&str]| prefixes.iter().any(|prefix| error.starts_with(prefix));
+
+        if starts(&["permission denied", "access denied"]) {
+            Status::Denied
+        } else if starts(&["authentication"]) {
+            Status::Unauthenticated
+        } else if starts(&["payment required"]) {
+            Status::PaymentRequired
+        } else if starts(&["rate limit", "quota exceeded"]) {
+            Status::Throttled
+        } else if starts(&["expired"]) {
+            Status::Expired
+        } else if starts(&["corrupted"]) {
+            Status::Corrupted
+        } else if starts(&["invalid", "unknown command", "usage"]) {
+            Status::Invalid
+        } else if starts(&["unknown", "no such"]) || error.contains("not found") {
+            Status::NotFound
+        } else if error.contains("already") {
+            Status::Conflict
+        } else if starts(&["failed to", "database error"]) {
+            Status::Error
+        } else {
+            Status::Invalid
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub status: Status,
+    pub command: String,
+    pub payload: String,
+    pub more: bool,
+}
+
+fn echo(command: &str) -> String {
+    command
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .take(MAX_ECHO_CHARS)
+        .collect()
+}
+
+impl Envelope {
+    fn header(status: Status, command: &str, more: bool) -> String {
+        format!(
+            "{} {} {} {}\n",
+            ENVELOPE_TAG,
+            status.code(),
+            if more { '+' } else { '.' },
+            command
+        )
+    }
+
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            Self::header(self.status, &self.command, self.more),
+            self.payload
+        )
+    }
+
+    pub fn decode(memo: &str) -> Option<Envelope> {
+        let (header, payload) = memo.split_once('\n').unwrap_or((memo, ""));
+        let mut fields = header.splitn(4, ' ');
+        if fields.next()? != ENVELOPE_TAG {
+            return None;
+        }
+        let status = Status::from_code(fields.next()?.parse().ok()?)?;
+        let more = match fields.next()? {
+            "+" => true,
+            "." => false,
+            _ => return None,
+        };
+
+        Some(Envelope {
+            status,
+            command: fields.next().unwrap_or_default().to_string(),
+            payload: payload.to_string(),
+            more,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ack {
+    Seen,
+    Done(usize),
+    Rejected(Status),
+}
+
+impl Ack {
+    pub fn encode(&self) -> String {
+        match self {
+            Ack::Seen => format!("{} seen", ACK_TAG),
+            Ack::Done(replies) => format!("{} done {}", ACK_TAG, replies),
+            Ack::Rejected(status) => format!("{} nack {}", ACK_TAG, status.code()),
+        }
+    }
+
+    pub fn decode(memo: &str) -> Option<Ack> {
+        let mut fields = memo.trim_end().split(' ');
+        if fields.next()? != ACK_TAG {
+            return None;
+        }
+        let ack = match (fields.next()?, fields.next()) {
+            ("seen", None) => Ack::Seen,
+            ("done", Some(replies)) => Ack::Done(replies.parse().ok()?),
+            ("nack", Some(code)) => Ack::Rejected(Status::from_code(code.parse().ok()?)?),
+            _ => return None,
+        };
+        fields.next().is_none().then_some(ack)
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Ack::Seen => "seen by the coordinator; waiting to run".to_string(),
+            Ack::Done(1) => "ran; 1 reply memo on the way".to_string(),
+            Ack::Done(replies) => format!("ran; {} reply memos on the way", replies),
+            Ack::Rejected(status) => {
+                format!("rejected with error {}; it was not run", status.code())
+            }
+        }
+    }
+}
+
+pub fn envelope_memos(
+    command: &str,
+    result: &Result<String, String>,
+    max_len: usize,
+) -> Vec<String> {
+    let (status, payload) = match result {
+        Ok(response) => (Status::Ok, response.as_str()),
+        Err(e) => (Status::classify(e), e.as_str()),
+    };
+    let command = echo(command);
+    let budget = max_len - Envelope::header(status, &command, true).len();
+
+    let chunks = memo_decoder::chunk_text(payload, budget);
+    let last = chunks.len() - 1;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            Envelope {
+                status,
+                command: command.clone(),
+                payload: chunk.to_string(),
+                more: i < last,
+            }
+            .encode()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memo_decoder::MAX_MEMO_SIZE;
+
+    #[test]
+    fn test_classify_errors() {
+        let cases = [
+            ("Permission denied: admin only", 403),
+            ("Access denied: this address is banned", 403),
+            (
+                "Authentication required. Send REGISTER:<reply_address> first.",
+                401,
+            ),
+            ("Payment required: ls costs 10 zatoshis (received 0)", 402),
+            ("Rate limit exceeded: slow down and retry in 3s", 429),
+            ("Quota exceeded: 5 of 5 write commands used", 429),
+            ("Unknown command. Try: help, ls", 400),
+            (
+                "Invalid grant format. Use: grant <read|write> <user> <path>",
+                400,
+            ),
+            ("File not found: /a.txt", 404),
+            ("Unknown recipient: bob", 404),
+            ("Directory already exists: /docs", 409),
+            (
+                "Expired: command expired at 1700000600, received at 1700000900",
+                410,
+            ),
+            ("Corrupted memo: checksum mismatch; resend it", 422),
+            ("Failed to write state file: disk full", 500),
+            ("Cannot remove root directory", 400),
+        ];
+        for (error, code) in cases {
+            assert_eq!(Status::classify(error).code(), code, "{}", error);
+        }
+        assert_eq!(Status::from_code(429), Some(Status::Throttled));
+        assert_eq!(Status::from_code(418), None);
+    }
+
+    #[test]
+    fn test_envelope_roundtrip_and_continuation() {
+        let memos = envelope_memos("ls /docs", &Ok("a.txt\nb.txt".to_string()), MAX_MEMO_SIZE);
+        assert_eq!(memos, vec!["ZB1 200 . ls /docs\na.txt\nb.txt".to_string()]);
+        let decoded = Envelope::decode(&memos[0]).unwrap();
+        assert_eq!(decoded.status, Status::Ok);
+        assert_eq!(decoded.command, "ls /docs");
+        assert!(!decoded.more);
+
+        let denied = envelope_memos(
+            "admin gc",
+            &Err("Permission denied: admin only".to_string()),
+            MAX_MEMO_SIZE,
+        );
+        assert!(denied[0].starts_with("ZB1 403 . admin gc\n"));
+
+        let long = "line of output\n".repeat(100);
+        let memos = envelope_memos(&"x".repeat(80), &Ok(long.clone()), 300);
+        assert!(memos.len() > 1);
+        assert!(memos.iter().all(|memo| memo.len() <= 300));
+        let decoded: Vec<Envelope> = memos.iter().filter_map(|m| Envelope::decode(m)).collect();
+        assert!(decoded[..decoded.len() - 1].iter().all(|e| e.more));
+        assert!(!decoded.last().unwrap().more);
+        assert_eq!(decoded[0].command.len(), MAX_ECHO_CHARS);
+        let joined: String = decoded.iter().map(|e| e.payload.as_str()).collect();
+        assert_eq!(joined, long);
+
+        assert!(Envelope::decode("hello").is_none());
+        assert!(Envelope::decode("ZB1 999 . ls").is_none());
+    }
+
+    #[test]
+    fn test_reply_refs_round_trip() {
+        let txid = "a1b2c3d4e5f60718293a4b5c6d7e8f90";
+        let header = reply_ref_header(txid);
+        assert_eq!(header, "RE:a1b2c3d4e5f60718\n");
+        let memo = format!("{}ZB1 200 . ls /\na.txt", header);
+        assert_eq!(
+            split_reply_ref(&memo),
+            (Some("a1b2c3d4e5f60718"), "ZB1 200 . ls /\na.txt")
+        );
+        assert_eq!(reply_ref("tx-1"), "tx-1");
+
+        assert_eq!(
+            split_reply_ref("RE: your mail\nhi"),
+            (None, "RE: your mail\nhi")
+        );
+        assert_eq!(split_reply_ref("ZB1 200 . ls"), (None, "ZB1 200 . ls"));
+    }
+
+    #[test]
+    fn test_ack_frames_round_trip() {
+        for ack in [Ack::Seen, Ack::Done(3), Ack::Rejected(Status::Throttled)] {
+            assert_eq!(Ack::decode(&ack.encode()), Some(ack));
+        }
+        assert_eq!(Ack::Rejected(Status::Denied).encode(), "ZBACK nack 403");
+        assert_eq!(Ack::decode("ZBACK done 2\n"), Some(Ack::Done(2)));
+        assert_eq!(Ack::Done(1).describe(), "ran; 1 reply memo on the way");
+
+        for memo in [
+            "ZBACK",
+            "ZBACK seen 1",
+            "ZBACK nack 999",
+            "ZBACK done x",
+            "ZB1 200 . ls",
+        ] {
+            assert_eq!(Ack::decode(memo), None, "{}", memo);
+        }
+    }
+}
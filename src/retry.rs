@@ -0,0 +1,288 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::runtime::RuntimeFlavor;
+
+const TRANSIENT_MARKERS: [&str; 16] = [
+    "connection refused",
+    "connection reset",
+    "connection closed",
+    "broken pipe",
+    "timed out",
+    "timeout",
+    "deadline exceeded",
+    "unavailable",
+    "transport error",
+    "tcp connect error",
+    "dns error",
+    "error trying to connect",
+    "network is unreachable",
+    "failed to connect",
+    "temporarily",
+    "try again",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub jitter: bool,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 8_000,
+            jitter: true,
+            breaker_threshold: 5,
+            breaker_cooldown_secs: 60,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn delay(&self, attempt: u32, roll: u64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let full = self
+            .base_delay_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay_ms);
+        if !self.jitter || full < 2 {
+            return Duration::from_millis(full);
+        }
+        let half = full / 2;
+        Duration::from_millis(half + roll % (full - half + 1))
+    }
+
+    pub fn next_delay(&self, attempt: u32) -> Duration {
+        self.delay(attempt, OsRng.next_u64())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Transient,
+    Fatal,
+}
+
+pub fn run_blocking<T>(work: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(work)
+        }
+        _ => work(),
+    }
+}
+
+pub fn classify(error: &str) -> ErrorClass {
+    let error = error.to_lowercase();
+    if TRANSIENT_MARKERS
+        .iter()
+        .any(|marker| error.contains(marker))
+    {
+        ErrorClass::Transient
+    } else {
+        ErrorClass::Fatal
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZingoError {
+    Transient(String),
+    Fatal(String),
+    CircuitOpen { retry_in_secs: u64 },
+}
+
+impl ZingoError {
+    pub fn from_stderr(stderr: String) -> Self {
+        match classify(&stderr) {
+            ErrorClass::Transient => ZingoError::Transient(stderr),
+            ErrorClass::Fatal => ZingoError::Fatal(stderr),
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            ZingoError::Fatal(_) => ErrorClass::Fatal,
+            ZingoError::Transient(_) | ZingoError::CircuitOpen { .. } => ErrorClass::Transient,
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.class() == ErrorClass::Transient
+    }
+}
+
+impl std::fmt::Display for ZingoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZingoError::Transient(message) => {
+                write!(f, "Wallet server temporarily unavailable: {}", message)
+            }
+            ZingoError::Fatal(message) => write!(f, "{}", message),
+            ZingoError::CircuitOpen { retry_in_secs } => write!(
+                f,
+                "Wallet server unavailable after repeated failures; next attempt in {}s",
+                retry_in_secs
+            ),
+        }
+    }
+}
+
+impl From<ZingoError> for String {
+    fn from(error: ZingoError) -> Self {
+        error.to_string()
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker {
+    state: Arc<Mutex<BreakerState>>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BreakerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn admit(&self, config: &RetryConfig, now: u64) -> Result<(), ZingoError> {
+        let state = self.lock();
+        match state.opened_at {
+            Some(opened_at) if now < opened_at + config.breaker_cooldown_secs => {
+                Err(ZingoError::CircuitOpen {
+                    retry_in_secs: opened_at + config.breaker_cooldown_secs - now,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record<T>(&self, config: &RetryConfig, result: &Result<T, ZingoError>, now: u64) {
+        let mut state = self.lock();
+        match result {
+            Err(error) if error.is_transient() => {
+                state.failures += 1;
+                if config.breaker_threshold > 0 && state.failures >= config.breaker_threshold {
+                    state.opened_at = Some(now);
+                }
+            }
+            _ => *state = BreakerState::default(),
+        }
+    }
+
+    pub fn is_open(&self, config: &RetryConfig, now: u64) -> bool {
+        self.admit(config, now).is_err()
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.lock().failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_with_bounded_jitter() {
+        let config = RetryConfig {
+            jitter: false,
+            ..RetryConfig::default()
+        };
+        assert_eq!(config.delay(1, 0), Duration::from_millis(500));
+        assert_eq!(config.delay(2, 0), Duration::from_millis(1_000));
+        assert_eq!(config.delay(3, 0), Duration::from_millis(2_000));
+        assert_eq!(config.delay(30, 0), Duration::from_millis(8_000));
+
+        let jittered = RetryConfig::default();
+        assert_eq!(jittered.delay(2, 0), Duration::from_millis(500));
+        assert_eq!(jittered.delay(2, 500), Duration::from_millis(1_000));
+        assert!((0..50).all(|_| {
+            let delay = jittered.next_delay(3);
+            delay >= Duration::from_millis(1_000) && delay <= Duration::from_millis(2_000)
+        }));
+    }
+
+    #[test]
+    fn test_errors_are_classified_and_trip_the_breaker() {
+        assert_eq!(
+            classify("error trying to connect: tcp connect error: Connection refused"),
+            ErrorClass::Transient
+        );
+        assert_eq!(classify("status: Unavailable"), ErrorClass::Transient);
+        assert_eq!(classify("Insufficient funds"), ErrorClass::Fatal);
+        assert!(ZingoError::from_stderr("request timed out".to_string()).is_transient());
+        assert_eq!(
+            String::from(ZingoError::from_stderr("invalid address".to_string())),
+            "invalid address"
+        );
+
+        let config = RetryConfig {
+            breaker_threshold: 2,
+            breaker_cooldown_secs: 30,
+            ..RetryConfig::default()
+        };
+        let breaker = CircuitBreaker::new();
+        let down: Result<(), ZingoError> = Err(ZingoError::Transient("timed out".to_string()));
+        breaker.record(&config, &down, 100);
+        assert!(breaker.admit(&config, 100).is_ok());
+        breaker.record(
+            &config,
+            &Err::<(), _>(ZingoError::Fatal("bad".to_string())),
+            100,
+        );
+        assert_eq!(breaker.failures(), 0);
+
+        breaker.record(&config, &down, 100);
+        breaker.record(&config, &down, 101);
+        assert_eq!(
+            breaker.admit(&config, 110),
+            Err(ZingoError::CircuitOpen { retry_in_secs: 21 })
+        );
+        assert!(breaker.clone().is_open(&config, 130));
+        assert!(!breaker.is_open(&config, 131));
+
+        breaker.record(&config, &down, 131);
+        assert!(breaker.is_open(&config, 131));
+        breaker.record(&config, &Ok(()), 200);
+        assert!(!breaker.is_open(&config, 200));
+        assert_eq!(breaker.failures(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_blocking_retries_do_not_stall_the_runtime() {
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+        let blocked_tx = done_tx.clone();
+        let blocked = tokio::spawn(async move {
+            run_blocking(|| std::thread::sleep(Duration::from_millis(300)));
+            blocked_tx.send("blocked").unwrap();
+        });
+        let other = tokio::spawn(async move {
+            done_tx.send("other").unwrap();
+        });
+        blocked.await.unwrap();
+        other.await.unwrap();
+        assert_eq!(done_rx.recv().await, Some("other"));
+        assert_eq!(run_blocking(|| 7), 7);
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Guest,
+    Member,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Role, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "guest" => Ok(Role::Guest),
+            "member" => Ok(Role::Member),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!(
+                "Invalid role: {}. Use guest, member, moderator or admin",
+                other
+            )),
+        }
+    }
+
+    pub fn is_staff(&self) -> bool {
+        *self >= Role::Moderator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_are_ordered_by_privilege() {
+        assert!(Role::Guest < Role::Member);
+        assert!(Role::Member < Role::Moderator);
+        assert!(Role::Moderator < Role::Admin);
+        assert!(Role::Moderator.is_staff());
+        assert!(!Role::Member.is_staff());
+    }
+
+    #[test]
+    fn test_parse_round_trips_names() {
+        for role in [Role::Guest, Role::Member, Role::Moderator, Role::Admin] {
+            assert_eq!(Role::parse(role.as_str()).unwrap(), role);
+        }
+        assert_eq!(Role::parse(" Moderator ").unwrap(), Role::Moderator);
+        assert!(Role::parse("owner")
+            .unwrap_err()
+            .starts_with("Invalid role"));
+        assert_eq!(
+            serde_json::to_string(&Role::Moderator).unwrap(),
+            "\"moderator\""
+        );
+    }
+}
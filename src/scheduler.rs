@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    SessionCleanup,
+    Gc,
+    StateFlush,
+    QuotaPrune,
+    ConversationRetire,
+    HealthCheck,
+    TxWatch,
+}
+
+impl Job {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Job::SessionCleanup => "session_cleanup",
+            Job::Gc => "gc",
+            Job::StateFlush => "state_flush",
+            Job::QuotaPrune => "quota_prune",
+            Job::ConversationRetire => "conversation_retire",
+            Job::HealthCheck => "health_check",
+            Job::TxWatch => "tx_watch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub enabled: bool,
+    pub session_cleanup_secs: u64,
+    pub gc_secs: u64,
+    pub state_flush_secs: u64,
+    pub quota_prune_secs: u64,
+    pub conversation_retire_secs: u64,
+    pub health_check_secs: u64,
+    pub tx_watch_secs: u64,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            enabled: true,
+            session_cleanup_secs: 300,
+            gc_secs: 3600,
+            state_flush_secs: 60,
+            quota_prune_secs: 3600,
+            conversation_retire_secs: 3600,
+            health_check_secs: 60,
+            tx_watch_secs: 120,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    fn interval_for(&self, job: Job) -> u64 {
+        if !self.enabled {
+            return 0;
+        }
+        match job {
+            Job::SessionCleanup => self.session_cleanup_secs,
+            Job::Gc => self.gc_secs,
+            Job::StateFlush => self.state_flush_secs,
+            Job::QuotaPrune => self.quota_prune_secs,
+            Job::ConversationRetire => self.conversation_retire_secs,
+            Job::HealthCheck => self.health_check_secs,
+            Job::TxWatch => self.tx_watch_secs,
+        }
+    }
+}
+
+const JOBS: [Job; 7] = [
+    Job::SessionCleanup,
+    Job::Gc,
+    Job::StateFlush,
+    Job::QuotaPrune,
+    Job::ConversationRetire,
+    Job::HealthCheck,
+    Job::TxWatch,
+];
+
+#[derive(Debug, Clone)]
+struct JobEntry {
+    job: Job,
+    interval_secs: u64,
+    next_run: u64,
+    last_run: Option<u64>,
+    last_result: Option<Result<String, String>>,
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    entries: Vec<JobEntry>,
+}
+
+impl Scheduler {
+    pub fn new(config: &SchedulerConfig, now: u64) -> Self {
+        let entries = JOBS
+            .iter()
+            .map(|job| {
+                let interval_secs = config.interval_for(*job);
+                JobEntry {
+                    job: *job,
+                    interval_secs,
+                    next_run: now + interval_secs,
+                    last_run: None,
+                    last_result: None,
+                }
+            })
+            .collect();
+        Scheduler { entries }
+    }
+
+    pub fn set_config(&mut self, config: &SchedulerConfig, now: u64) {
+        for entry in &mut self.entries {
+            let interval_secs = config.interval_for(entry.job);
+            if interval_secs != entry.interval_secs {
+                entry.interval_secs = interval_secs;
+                entry.next_run = entry.last_run.unwrap_or(now) + interval_secs;
+            }
+        }
+    }
+
+    pub fn due(&mut self, now: u64) -> Vec<Job> {
+        let mut due = Vec::new();
+        for entry in &mut self.entries {
+            if entry.interval_secs == 0 || entry.next_run > now {
+                continue;
+            }
+            entry.next_run = now + entry.interval_secs;
+            due.push(entry.job);
+        }
+        due
+    }
+
+    pub fn record(&mut self, job: Job, now: u64, result: Result<String, String>) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.job == job) {
+            entry.last_run = Some(now);
+            entry.last_result = Some(result);
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let schedule = if entry.interval_secs == 0 {
+                    "disabled".to_string()
+                } else {
+                    format!("every {}s", entry.interval_secs)
+                };
+                let last = match (&entry.last_run, &entry.last_result) {
+                    (Some(at), Some(Ok(outcome))) => format!("last run {}: {}", at, outcome),
+                    (Some(at), Some(Err(e))) => format!("last run {} failed: {}", at, e),
+                    _ => "never run".to_string(),
+                };
+                format!("{} {}, {}", entry.job.as_str(), schedule, last)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs_run_on_their_interval() {
+        let config = SchedulerConfig {
+            session_cleanup_secs: 10,
+            gc_secs: 30,
+            state_flush_secs: 0,
+            quota_prune_secs: 30,
+            conversation_retire_secs: 0,
+            health_check_secs: 0,
+            ..SchedulerConfig::default()
+        };
+        let mut scheduler = Scheduler::new(&config, 100);
+
+        assert!(scheduler.due(105).is_empty());
+        assert_eq!(scheduler.due(110), vec![Job::SessionCleanup]);
+        assert!(scheduler.due(115).is_empty());
+        assert_eq!(
+            scheduler.due(131),
+            vec![Job::SessionCleanup, Job::Gc, Job::QuotaPrune]
+        );
+
+        scheduler.record(Job::Gc, 131, Ok("1 expired nodes".to_string()));
+        scheduler.record(Job::QuotaPrune, 131, Err("disk full".to_string()));
+        let summary = scheduler.summary();
+        assert!(summary.contains("gc every 30s, last run 131: 1 expired nodes"));
+        assert!(summary.contains("quota_prune every 30s, last run 131 failed: disk full"));
+        assert!(summary.contains("state_flush disabled, never run"));
+    }
+
+    #[test]
+    fn test_disabled_scheduler_runs_nothing() {
+        let mut scheduler = Scheduler::new(&SchedulerConfig::default(), 0);
+        scheduler.set_config(
+            &SchedulerConfig {
+                enabled: false,
+                ..SchedulerConfig::default()
+            },
+            0,
+        );
+        assert!(scheduler.due(1_000_000).is_empty());
+    }
+}
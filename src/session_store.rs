@@ -0,0 +1,294 @@
+use crate::challenge::PendingChallenge;
+use crate::user_session::UserSession;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const SESSION_DB_FILE: &str = "sessions.db";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionBackend {
+    #[default]
+    State,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuthSnapshot {
+    pub verified_users: HashMap<String, String>,
+    pub session_mappings: HashMap<String, String>,
+    pub sessions: Vec<UserSession>,
+    pub challenges: HashMap<String, Vec<PendingChallenge>>,
+}
+
+impl AuthSnapshot {
+    pub fn is_empty(&self) -> bool {
+        self.verified_users.is_empty()
+            && self.session_mappings.is_empty()
+            && self.sessions.is_empty()
+            && self.challenges.is_empty()
+    }
+}
+
+pub trait SessionStore: Send + Sync {
+    fn location(&self) -> String;
+    fn load(&self) -> Result<AuthSnapshot, String>;
+    fn save(&self, snapshot: &AuthSnapshot) -> Result<(), String>;
+}
+
+pub struct SqliteSessionStore {
+    path: PathBuf,
+}
+
+impl SqliteSessionStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let store = SqliteSessionStore {
+            path: path.as_ref().to_path_buf(),
+        };
+        store.connect()?;
+        Ok(store)
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        let conn = Connection::open(&self.path)
+            .map_err(|e| format!("Failed to open session database: {}", e))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS verified_users (
+                user_id TEXT PRIMARY KEY,
+                reply_address TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS session_mappings (
+                session_id TEXT PRIMARY KEY,
+                reply_address TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS sessions (
+                session_id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                reply_address TEXT NOT NULL,
+                session_start INTEGER NOT NULL,
+                last_activity INTEGER NOT NULL,
+                is_authenticated BOOLEAN NOT NULL,
+                expires_at INTEGER NOT NULL,
+                commands_issued INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS challenges (
+                user_id TEXT NOT NULL,
+                value TEXT NOT NULL,
+                issued_at INTEGER NOT NULL,
+                PRIMARY KEY (user_id, value)
+            );",
+        )
+        .map_err(|e| format!("Failed to create session tables: {}", e))?;
+
+        let has_commands_issued = conn
+            .prepare("SELECT commands_issued FROM sessions LIMIT 0")
+            .is_ok();
+        if !has_commands_issued {
+            conn.execute(
+                "ALTER TABLE sessions ADD COLUMN commands_issued INTEGER NOT NULL DEFAULT 0",
+                [],
+            )
+            .map_err(|e| format!("Failed to upgrade sessions table: {}", e))?;
+        }
+        Ok(conn)
+    }
+}
+
+impl SessionStore for SqliteSessionStore {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<AuthSnapshot, String> {
+        let conn = self.connect()?;
+        let read_error = |e: rusqlite::Error| format!("Failed to read session database: {}", e);
+        let mut snapshot = AuthSnapshot::default();
+
+        let mut stmt = conn
+            .prepare("SELECT user_id, reply_address FROM verified_users")
+            .map_err(read_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(read_error)?;
+        snapshot.verified_users = rows
+            .collect::<Result<HashMap<String, String>, _>>()
+            .map_err(read_error)?;
+
+        let mut stmt = conn
+            .prepare("SELECT session_id, reply_address FROM session_mappings")
+            .map_err(read_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(read_error)?;
+        snapshot.session_mappings = rows
+            .collect::<Result<HashMap<String, String>, _>>()
+            .map_err(read_error)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT session_id, user_id, reply_address, session_start, last_activity,
+                        is_authenticated, expires_at, commands_issued
+                 FROM sessions ORDER BY session_start",
+            )
+            .map_err(read_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(UserSession {
+                    session_id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    reply_address: row.get(2)?,
+                    session_start: row.get(3)?,
+                    last_activity: row.get(4)?,
+                    is_authenticated: row.get(5)?,
+                    expires_at: row.get(6)?,
+                    commands_issued: row.get(7)?,
+                })
+            })
+            .map_err(read_error)?;
+        snapshot.sessions = rows.collect::<Result<_, _>>().map_err(read_error)?;
+
+        let mut stmt = conn
+            .prepare("SELECT user_id, value, issued_at FROM challenges ORDER BY issued_at")
+            .map_err(read_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    PendingChallenge {
+                        value: row.get(1)?,
+                        issued_at: row.get(2)?,
+                    },
+                ))
+            })
+            .map_err(read_error)?;
+        for row in rows {
+            let (user, challenge) = row.map_err(read_error)?;
+            snapshot.challenges.entry(user).or_default().push(challenge);
+        }
+
+        Ok(snapshot)
+    }
+
+    fn save(&self, snapshot: &AuthSnapshot) -> Result<(), String> {
+        let mut conn = self.connect()?;
+        let write_error = |e: rusqlite::Error| format!("Failed to write session database: {}", e);
+        let tx = conn.transaction().map_err(write_error)?;
+        tx.execute_batch(
+            "DELETE FROM verified_users;
+             DELETE FROM session_mappings;
+             DELETE FROM sessions;
+             DELETE FROM challenges;",
+        )
+        .map_err(write_error)?;
+
+        for (user, reply_address) in &snapshot.verified_users {
+            tx.execute(
+                "INSERT INTO verified_users (user_id, reply_address) VALUES (?1, ?2)",
+                params![user, reply_address],
+            )
+            .map_err(write_error)?;
+        }
+        for (session_id, reply_address) in &snapshot.session_mappings {
+            tx.execute(
+                "INSERT INTO session_mappings (session_id, reply_address) VALUES (?1, ?2)",
+                params![session_id, reply_address],
+            )
+            .map_err(write_error)?;
+        }
+        for session in &snapshot.sessions {
+            tx.execute(
+                "INSERT OR REPLACE INTO sessions (session_id, user_id, reply_address,
+                    session_start, last_activity, is_authenticated, expires_at, commands_issued)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    session.session_id,
+                    session.user_id,
+                    session.reply_address,
+                    session.session_start,
+                    session.last_activity,
+                    session.is_authenticated,
+                    session.expires_at,
+                    session.commands_issued
+                ],
+            )
+            .map_err(write_error)?;
+        }
+        for (user, challenges) in &snapshot.challenges {
+            for challenge in challenges {
+                tx.execute(
+                    "INSERT OR REPLACE INTO challenges (user_id, value, issued_at)
+                     VALUES (?1, ?2, ?3)",
+                    params![user, challenge.value, challenge.issued_at],
+                )
+                .map_err(write_error)?;
+            }
+        }
+
+        tx.commit().map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> AuthSnapshot {
+        let mut snapshot = AuthSnapshot::default();
+        snapshot
+            .verified_users
+            .insert("zs1alice".to_string(), "zs1alicereply".to_string());
+        snapshot
+            .session_mappings
+            .insert("abc123".to_string(), "zs1alicereply".to_string());
+        let mut session = UserSession::new("zs1alice".to_string(), "zs1alicereply".to_string());
+        session.session_id = "abc123".to_string();
+        session.authenticate(3600);
+        session.commands_issued = 7;
+        snapshot.sessions.push(session);
+        snapshot.challenges.insert(
+            "zs1bob".to_string(),
+            vec![
+                PendingChallenge {
+                    value: "c1".to_string(),
+                    issued_at: 100,
+                },
+                PendingChallenge {
+                    value: "c2".to_string(),
+                    issued_at: 200,
+                },
+            ],
+        );
+        snapshot
+    }
+
+    #[test]
+    fn test_sqlite_store_round_trips_auth_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_DB_FILE);
+        let store = SqliteSessionStore::open(&path).unwrap();
+        assert!(store.load().unwrap().is_empty());
+
+        store.save(&snapshot()).unwrap();
+        let reopened = SqliteSessionStore::open(&path).unwrap();
+        assert_eq!(reopened.load().unwrap(), snapshot());
+        assert_eq!(reopened.location(), path.display().to_string());
+    }
+
+    #[test]
+    fn test_save_replaces_previous_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteSessionStore::open(dir.path().join(SESSION_DB_FILE)).unwrap();
+        store.save(&snapshot()).unwrap();
+
+        let mut smaller = snapshot();
+        smaller.sessions.clear();
+        smaller.challenges.remove("zs1bob");
+        store.save(&smaller).unwrap();
+        let loaded = store.load().unwrap();
+        assert!(loaded.sessions.is_empty());
+        assert!(loaded.challenges.is_empty());
+        assert_eq!(loaded.verified_users.len(), 1);
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const REDIRECT_PREFIX: &str = "REDIRECT:";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ShardConfig {
+    pub routes: BTreeMap<String, String>,
+}
+
+pub fn top_level(path: &str) -> Option<&str> {
+    let rest = path.strip_prefix('/')?;
+    let name = rest.split('/').next().unwrap_or_default();
+    if name.is_empty() {
+        None
+    } else {
+        Some(&path[..name.len() + 1])
+    }
+}
+
+pub fn format_redirect(address: &str, path: &str) -> String {
+    format!("{}{}:{}", REDIRECT_PREFIX, address, path)
+}
+
+pub fn parse_redirect(text: &str) -> Option<(String, String)> {
+    let (address, path) = text.trim().strip_prefix(REDIRECT_PREFIX)?.split_once(':')?;
+    if address.is_empty() || !path.starts_with('/') {
+        return None;
+    }
+    Some((address.to_string(), path.to_string()))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ShardMap {
+    routes: BTreeMap<String, String>,
+}
+
+impl ShardMap {
+    pub fn new() -> Self {
+        ShardMap::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.routes.clear();
+    }
+
+    pub fn add_route(&mut self, prefix: &str, address: &str) -> Result<(), String> {
+        if top_level(prefix) != Some(prefix) {
+            return Err(format!(
+                "Invalid shard route {}: must be a top-level directory like /video",
+                prefix
+            ));
+        }
+        if address.trim().is_empty() || address.contains(char::is_whitespace) {
+            return Err(format!("Invalid shard address for {}", prefix));
+        }
+        self.routes.insert(prefix.to_string(), address.to_string());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.routes.iter()
+    }
+
+    pub fn route_for(&self, path: &str) -> Option<&str> {
+        top_level(path).and_then(|prefix| self.routes.get(prefix).map(String::as_str))
+    }
+
+    pub fn redirect_for(&self, paths: &[&str]) -> Result<Option<String>, String> {
+        let Some(first) = paths.first() else {
+            return Ok(None);
+        };
+        let target = self.route_for(first);
+        if let Some(other) = paths[1..]
+            .iter()
+            .find(|path| self.route_for(path) != target)
+        {
+            return Err(format!(
+                "Invalid command: {} and {} are served by different coordinators",
+                first, other
+            ));
+        }
+        Ok(target.map(|address| format_redirect(address, first)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_format_roundtrip() {
+        assert_eq!(top_level("/video/clips/a.mp4"), Some("/video"));
+        assert_eq!(top_level("/video"), Some("/video"));
+        assert_eq!(top_level("/"), None);
+        assert_eq!(top_level("video"), None);
+
+        let redirect = format_redirect("zs1shard2", "/video/a.mp4");
+        assert_eq!(redirect, "REDIRECT:zs1shard2:/video/a.mp4");
+        assert_eq!(
+            parse_redirect(&redirect),
+            Some(("zs1shard2".to_string(), "/video/a.mp4".to_string()))
+        );
+        assert_eq!(parse_redirect("REDIRECT:zs1shard2"), None);
+        assert_eq!(parse_redirect("ls /video"), None);
+    }
+
+    #[test]
+    fn test_paths_route_to_their_shard() {
+        let mut shards = ShardMap::new();
+        shards.add_route("/video", "zs1shard2").unwrap();
+        assert!(shards.add_route("/video/clips", "zs1shard3").is_err());
+        assert!(shards.add_route("/docs", "").is_err());
+
+        assert_eq!(shards.redirect_for(&[]), Ok(None));
+        assert_eq!(shards.redirect_for(&["/docs/a.txt"]), Ok(None));
+        assert_eq!(
+            shards.redirect_for(&["/video/a.mp4", "/video/b.mp4"]),
+            Ok(Some("REDIRECT:zs1shard2:/video/a.mp4".to_string()))
+        );
+        assert!(shards
+            .redirect_for(&["/video/a.mp4", "/docs/a.mp4"])
+            .unwrap_err()
+            .starts_with("Invalid command"));
+    }
+}
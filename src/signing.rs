@@ -0,0 +1,153 @@
+use crate::crypto;
+use crate::e2e::E2eKey;
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const SIGNATURE_MARKER: &str = "\nSIG:";
+pub const SIGNATURE_OVERHEAD: usize = SIGNATURE_MARKER.len() + 2 * ed25519_dalek::SIGNATURE_LENGTH;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SigningConfig {
+    pub enabled: bool,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        SigningConfig { enabled: true }
+    }
+}
+
+fn signed_bytes(recipient: &str, body: &str) -> Vec<u8> {
+    format!("zatboard_reply\n{}\n{}", recipient, body).into_bytes()
+}
+
+pub fn split_signature(memo: &str) -> (&str, Option<&str>) {
+    match memo.rsplit_once(SIGNATURE_MARKER) {
+        Some((body, signature)) => (body, Some(signature)),
+        None => (memo, None),
+    }
+}
+
+pub fn verify(public_key_hex: &str, recipient: &str, memo: &str) -> Result<String, String> {
+    let key_bytes: [u8; 32] = crypto::from_hex(public_key_hex)?
+        .try_into()
+        .map_err(|_| "Invalid coordinator key length".to_string())?;
+    let key = VerifyingKey::from_bytes(&key_bytes)
+        .map_err(|e| format!("Invalid coordinator key: {}", e))?;
+
+    let (body, signature) = split_signature(memo);
+    let signature = signature.ok_or_else(|| "Reply is not signed".to_string())?;
+    let signature_bytes: [u8; 64] = crypto::from_hex(signature)?
+        .try_into()
+        .map_err(|_| "Invalid signature length".to_string())?;
+
+    key.verify(
+        &signed_bytes(recipient, body),
+        &Signature::from_bytes(&signature_bytes),
+    )
+    .map_err(|_| "Signature does not match coordinator key".to_string())?;
+    Ok(body.to_string())
+}
+
+pub fn load_or_create_key(path: &Path) -> Result<SigningKey, String> {
+    if path.exists() {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read signing key: {}", e))?;
+        let seed: [u8; 32] = crypto::from_hex(contents.trim())?
+            .try_into()
+            .map_err(|_| "Signing key file is corrupt".to_string())?;
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+
+    let key = SigningKey::generate(&mut OsRng);
+    let tmp_path = path.with_extension("key.tmp");
+    std::fs::write(&tmp_path, crypto::to_hex(key.as_bytes()))
+        .map_err(|e| format!("Failed to write signing key: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict signing key permissions: {}", e))?;
+    }
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to replace signing key: {}", e))?;
+    Ok(key)
+}
+
+pub struct ResponseSigner {
+    key: SigningKey,
+}
+
+impl ResponseSigner {
+    pub fn generate() -> Self {
+        ResponseSigner {
+            key: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(ResponseSigner {
+            key: load_or_create_key(path.as_ref())?,
+        })
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        crypto::to_hex(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn e2e_key(&self) -> E2eKey {
+        E2eKey::from_signing_key(&self.key)
+    }
+
+    pub fn sign(&self, recipient: &str, memo: &str) -> String {
+        let signature = self.key.sign(&signed_bytes(recipient, memo));
+        format!(
+            "{}{}{}",
+            memo,
+            SIGNATURE_MARKER,
+            crypto::to_hex(&signature.to_bytes())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_reply_verifies_for_recipient_only() {
+        let signer = ResponseSigner::generate();
+        let signed = signer.sign("zs1alice", "ZB1 200 . ls /\nnotes.txt");
+        assert_eq!(
+            signed.len(),
+            "ZB1 200 . ls /\nnotes.txt".len() + SIGNATURE_OVERHEAD
+        );
+
+        let key = signer.public_key_hex();
+        assert_eq!(
+            verify(&key, "zs1alice", &signed).unwrap(),
+            "ZB1 200 . ls /\nnotes.txt"
+        );
+        assert!(verify(&key, "zs1bob", &signed).is_err());
+        assert!(verify(&key, "zs1alice", &signed.replace("notes", "nodes")).is_err());
+        assert!(verify(&key, "zs1alice", "ZB1 200 . ls /").is_err());
+
+        let other = ResponseSigner::generate();
+        assert!(verify(&other.public_key_hex(), "zs1alice", &signed).is_err());
+    }
+
+    #[test]
+    fn test_key_persists_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signing.key");
+        let first = ResponseSigner::load_or_create(&path).unwrap();
+        let second = ResponseSigner::load_or_create(&path).unwrap();
+        assert_eq!(first.public_key_hex(), second.public_key_hex());
+
+        std::fs::write(&path, "not hex").unwrap();
+        assert!(ResponseSigner::load_or_create(&path).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::challenge::PendingChallenge;
+use crate::conversation::Conversation;
+use crate::federation::FederationOp;
+use crate::quota::QuotaUsage;
+use crate::roles::Role;
+use crate::user_session::UserSession;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+fn initial_conversation_counter() -> u32 {
+    1000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CoordinatorState {
+    #[serde(default)]
+    pub verified_users: HashMap<String, String>,
+    #[serde(default)]
+    pub user_keys: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub pending_challenges: HashMap<String, String>,
+    #[serde(default)]
+    pub challenges: HashMap<String, Vec<PendingChallenge>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub spent_challenges: HashMap<String, u64>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub issued_challenges: HashMap<String, Vec<u64>>,
+    #[serde(default)]
+    pub session_mappings: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub conversation_mappings: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub user_conversations: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub participant_mappings: HashMap<String, String>,
+    #[serde(default = "initial_conversation_counter")]
+    pub conversation_counter: u32,
+    #[serde(default)]
+    pub conversations: Vec<Conversation>,
+    #[serde(default)]
+    pub sessions: Vec<UserSession>,
+    #[serde(default)]
+    pub banned_users: HashSet<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub roles: HashMap<String, Role>,
+    #[serde(default)]
+    pub federation_seq: u64,
+    #[serde(default)]
+    pub federation_seen: HashMap<String, u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub federation_log: BTreeMap<u64, FederationOp>,
+    #[serde(default)]
+    pub quota_usage: HashMap<String, QuotaUsage>,
+}
+
+impl Default for CoordinatorState {
+    fn default() -> Self {
+        CoordinatorState {
+            verified_users: HashMap::new(),
+            user_keys: HashMap::new(),
+            pending_challenges: HashMap::new(),
+            challenges: HashMap::new(),
+            spent_challenges: HashMap::new(),
+            issued_challenges: HashMap::new(),
+            session_mappings: HashMap::new(),
+            conversation_mappings: HashMap::new(),
+            user_conversations: HashMap::new(),
+            participant_mappings: HashMap::new(),
+            conversation_counter: initial_conversation_counter(),
+            conversations: Vec::new(),
+            sessions: Vec::new(),
+            banned_users: HashSet::new(),
+            roles: HashMap::new(),
+            federation_seq: 0,
+            federation_seen: HashMap::new(),
+            federation_log: BTreeMap::new(),
+            quota_usage: HashMap::new(),
+        }
+    }
+}
+
+impl CoordinatorState {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(CoordinatorState::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read state file: {}", e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse state file: {}", e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write state file: {}", e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace state file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("state.json");
+
+        let mut state = CoordinatorState::default();
+        state
+            .verified_users
+            .insert("zs1user123".to_string(), "zs1reply456".to_string());
+        state.conversation_counter = 1042;
+        state.sessions.push(UserSession::new(
+            "zs1user123".to_string(),
+            "zs1reply456".to_string(),
+        ));
+        state.save(&path).unwrap();
+
+        let loaded = CoordinatorState::load(&path).unwrap();
+        assert_eq!(loaded.verified_users, state.verified_users);
+        assert_eq!(loaded.conversation_counter, 1042);
+        assert_eq!(loaded.sessions.len(), 1);
+        assert!(!temp_dir.path().join("state.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_missing_or_partial_state() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("state.json");
+        assert_eq!(
+            CoordinatorState::load(&path).unwrap(),
+            CoordinatorState::default()
+        );
+
+        std::fs::write(&path, "{\"verified_users\": {\"zs1a\": \"zs1b\"}}").unwrap();
+        let loaded = CoordinatorState::load(&path).unwrap();
+        assert_eq!(loaded.conversation_counter, 1000);
+        assert_eq!(loaded.verified_users.get("zs1a").unwrap(), "zs1b");
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(CoordinatorState::load(&path).is_err());
+    }
+}
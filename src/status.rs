@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use warp::http::StatusCode;
+use warp::Filter;
+
+const STALE_AFTER_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StatusConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        StatusConfig {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 8081,
+        }
+    }
+}
+
+impl StatusConfig {
+    pub fn socket_addr(&self) -> Result<SocketAddr, String> {
+        let ip: IpAddr = self
+            .bind_address
+            .parse()
+            .map_err(|e| format!("Invalid status bind address: {}", e))?;
+        if !ip.is_loopback() {
+            return Err(format!(
+                "Status server must bind to a loopback address, got {}",
+                ip
+            ));
+        }
+        Ok(SocketAddr::new(ip, self.port))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct StatusSnapshot {
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub sync_height: Option<u64>,
+    pub last_sync_at: Option<u64>,
+    pub last_poll_error: Option<String>,
+    pub inbound_depth: usize,
+    pub outbound_depth: usize,
+    pub health: Option<String>,
+    pub verified_users: usize,
+    pub sessions: usize,
+    pub metrics: Value,
+}
+
+impl StatusSnapshot {
+    pub fn is_healthy(&self, now: u64) -> bool {
+        let since = self.last_sync_at.unwrap_or(self.started_at);
+        now.saturating_sub(since) < STALE_AFTER_SECS
+    }
+
+    pub fn health(&self, now: u64) -> Value {
+        json!({
+            "status": if self.is_healthy(now) { "ok" } else { "stale" },
+            "uptime_secs": now.saturating_sub(self.started_at),
+            "last_sync_at": self.last_sync_at,
+            "last_poll_error": self.last_poll_error,
+        })
+    }
+}
+
+pub type SharedStatus = Arc<Mutex<StatusSnapshot>>;
+
+fn snapshot(status: &SharedStatus) -> StatusSnapshot {
+    status
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn bind(
+    addr: SocketAddr,
+    status: SharedStatus,
+    mut stop: watch::Receiver<bool>,
+) -> Result<(SocketAddr, impl Future<Output = ()>), String> {
+    let health_status = Arc::clone(&status);
+    let health = warp::path("health")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || {
+            let current = snapshot(&health_status);
+            let code = if current.is_healthy(now()) {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            warp::reply::with_status(warp::reply::json(&current.health(now())), code)
+        });
+
+    let status_status = Arc::clone(&status);
+    let full = warp::path("status")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || {
+            let current = snapshot(&status_status);
+            let mut body = serde_json::to_value(&current).unwrap_or_default();
+            body["healthy"] = json!(current.is_healthy(now()));
+            warp::reply::json(&body)
+        });
+
+    let metrics = warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || warp::reply::json(&snapshot(&status).metrics));
+
+    let shutdown = async move {
+        while !*stop.borrow() {
+            if stop.changed().await.is_err() {
+                break;
+            }
+        }
+    };
+
+    warp::serve(health.or(full).or(metrics))
+        .try_bind_with_graceful_shutdown(addr, shutdown)
+        .map_err(|e| format!("Failed to bind status server on {}: {}", addr, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_only_loopback_addresses_are_allowed() {
+        assert!(StatusConfig::default().socket_addr().is_ok());
+        let public = StatusConfig {
+            bind_address: "0.0.0.0".to_string(),
+            ..StatusConfig::default()
+        };
+        assert!(public.socket_addr().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_report_snapshot() {
+        let status: SharedStatus = Arc::new(Mutex::new(StatusSnapshot {
+            started_at: now(),
+            sync_height: Some(2_500_000),
+            last_sync_at: Some(now()),
+            inbound_depth: 0,
+            outbound_depth: 3,
+            metrics: json!({"messages_processed": 7}),
+            ..StatusSnapshot::default()
+        }));
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let (addr, server) =
+            bind("127.0.0.1:0".parse().unwrap(), Arc::clone(&status), stop_rx).unwrap();
+        let handle = tokio::spawn(server);
+
+        let health = get(addr, "/health").await;
+        assert!(health.starts_with("HTTP/1.1 200"));
+        assert!(health.contains("\"status\":\"ok\""));
+
+        let full = get(addr, "/status").await;
+        assert!(full.contains("\"sync_height\":2500000"));
+        assert!(full.contains("\"outbound_depth\":3"));
+        assert!(get(addr, "/metrics")
+            .await
+            .contains("{\"messages_processed\":7}"));
+
+        status.lock().unwrap().last_sync_at = Some(1);
+        status.lock().unwrap().started_at = 1;
+        assert!(get(addr, "/health").await.starts_with("HTTP/1.1 503"));
+
+        stop_tx.send(true).unwrap();
+        handle.await.unwrap();
+    }
+}
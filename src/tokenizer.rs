@@ -0,0 +1,192 @@
+pub fn tokenize(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    while !rest.trim_start().is_empty() {
+        let (token, remaining) = split_first(rest)?;
+        tokens.push(token);
+        rest = remaining;
+    }
+    Ok(tokens)
+}
+
+pub fn split_first(input: &str) -> Result<(String, &str), String> {
+    let input = input.trim_start();
+    let mut token = String::new();
+    let mut chars = input.char_indices();
+    let mut quote: Option<(char, usize)> = None;
+
+    while let Some((at, c)) = chars.next() {
+        match (quote, c) {
+            (Some((open, _)), _) if c == open => quote = None,
+            (Some(('\'', _)), _) => token.push(c),
+            (None, '\'' | '"') => quote = Some((c, at)),
+            (None, _) if c.is_whitespace() => return Ok((token, &input[at + c.len_utf8()..])),
+            (_, '\\') => {
+                let Some((_, escaped)) = chars.next() else {
+                    return Err("Invalid escape: trailing backslash".to_string());
+                };
+                token.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    '\\' | '"' | '\'' => escaped,
+                    _ if escaped.is_whitespace() => escaped,
+                    _ => {
+                        return Err(format!(
+                            "Invalid escape \\{} in {}; use \\\\ for a literal backslash",
+                            escaped,
+                            raw_word(input)
+                        ))
+                    }
+                });
+            }
+            _ => token.push(c),
+        }
+    }
+
+    match quote {
+        Some((open, at)) => Err(format!(
+            "Invalid quoting: unterminated {} quote in {}",
+            open,
+            &input[at..]
+        )),
+        None => Ok((token, "")),
+    }
+}
+
+pub fn split_unquoted(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (at, c) in input.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"') | None, '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, _) if c == separator => {
+                parts.push(&input[start..at]);
+                start = at + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+fn raw_word(input: &str) -> &str {
+    input.split_whitespace().next().unwrap_or(input)
+}
+
+pub fn single(input: &str, usage: &str) -> Result<String, String> {
+    match tokenize(input)?.as_mut_slice() {
+        [token] => Ok(std::mem::take(token)),
+        [] => Err(format!("Invalid format: missing argument. Use: {}", usage)),
+        _ => Err(format!(
+            "Invalid format: expected one argument; quote paths that contain spaces. Use: {}",
+            usage
+        )),
+    }
+}
+
+pub fn text(input: &str) -> String {
+    if !input.trim_start().starts_with(['"', '\'']) {
+        return input.to_string();
+    }
+    match tokenize(input) {
+        Ok(tokens) if tokens.len() == 1 => tokens.into_iter().next().unwrap_or_default(),
+        _ => input.to_string(),
+    }
+}
+
+pub fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'));
+    if plain {
+        return word.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_handles_quotes_and_escapes() {
+        assert_eq!(
+            tokenize(r#"cp "/docs/my notes.txt" '/backup/it''s here' plain\ name"#).unwrap(),
+            vec!["cp", "/docs/my notes.txt", "/backup/its here", "plain name"]
+        );
+        assert_eq!(
+            tokenize(r#"  touch "a\"b" 'raw\n' "line\nbreak"  "#).unwrap(),
+            vec!["touch", "a\"b", "raw\\n", "line\nbreak"]
+        );
+        assert_eq!(tokenize(r#"mkdir """#).unwrap(), vec!["mkdir", ""]);
+        assert!(tokenize("   ").unwrap().is_empty());
+
+        assert_eq!(
+            tokenize(r#"cat "/a b"#).unwrap_err(),
+            "Invalid quoting: unterminated \" quote in \"/a b"
+        );
+        assert_eq!(
+            tokenize(r"cat /a\").unwrap_err(),
+            "Invalid escape: trailing backslash"
+        );
+        assert!(tokenize(r"cat /a\qb")
+            .unwrap_err()
+            .starts_with("Invalid escape \\q in /a\\qb;"));
+    }
+
+    #[test]
+    fn test_split_first_single_text_and_quote() {
+        let (path, rest) = split_first(r#""/my file.txt" hello   world"#).unwrap();
+        assert_eq!((path.as_str(), rest), ("/my file.txt", "hello   world"));
+        assert_eq!(split_first("/a").unwrap(), ("/a".to_string(), ""));
+
+        assert_eq!(single(" '/my dir' ", "mkdir <path>").unwrap(), "/my dir");
+        assert!(single("/my dir", "mkdir <path>")
+            .unwrap_err()
+            .contains("quote paths that contain spaces"));
+        assert!(single("", "mkdir <path>")
+            .unwrap_err()
+            .ends_with("Use: mkdir <path>"));
+
+        assert_eq!(text("\"hello world\""), "hello world");
+        assert_eq!(text("don't stop"), "don't stop");
+        assert_eq!(text("'a' 'b'"), "'a' 'b'");
+
+        for word in [
+            "plain",
+            "my file.txt",
+            "say \"hi\"",
+            "C:\\x",
+            "two\nlines",
+            "",
+        ] {
+            assert_eq!(tokenize(&quote(word)).unwrap(), vec![word]);
+        }
+        assert_eq!(quote("plain"), "plain");
+
+        assert_eq!(
+            split_unquoted(r#"touch "/a;b" x; ls 'c;d'; cat \;e"#, ';'),
+            vec![r#"touch "/a;b" x"#, " ls 'c;d'", r" cat \;e"]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::wallet::WalletBackend;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const CONFIRMATION_DEPTH: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum TxStatus {
+    Unknown,
+    Pending,
+    Mined { height: u64 },
+    Confirmed { height: u64, confirmations: u64 },
+}
+
+impl TxStatus {
+    pub fn at(mined_height: Option<u64>, tip: u64) -> Self {
+        let Some(height) = mined_height.filter(|height| *height > 0) else {
+            return TxStatus::Pending;
+        };
+        let confirmations = tip.saturating_sub(height) + 1;
+        if confirmations >= CONFIRMATION_DEPTH {
+            TxStatus::Confirmed {
+                height,
+                confirmations,
+            }
+        } else {
+            TxStatus::Mined { height }
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, TxStatus::Confirmed { .. })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            TxStatus::Unknown => "unknown".to_string(),
+            TxStatus::Pending => "pending".to_string(),
+            TxStatus::Mined { height } => format!("mined at {}", height),
+            TxStatus::Confirmed { confirmations, .. } => format!("confirmed ({})", confirmations),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TxWatcher {
+    watched: BTreeMap<String, TxStatus>,
+}
+
+impl TxWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch(&mut self, txid: &str) {
+        self.watched
+            .entry(txid.to_string())
+            .or_insert(TxStatus::Unknown);
+    }
+
+    pub fn status(&self, txid: &str) -> Option<TxStatus> {
+        self.watched.get(txid).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.watched.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watched.is_empty()
+    }
+
+    pub fn poll<W: WalletBackend>(
+        &mut self,
+        wallet: &W,
+    ) -> Result<Vec<(String, TxStatus)>, String> {
+        let txids: Vec<String> = self.watched.keys().cloned().collect();
+        if txids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let statuses = wallet.tx_statuses(&txids)?;
+
+        let mut changes = Vec::new();
+        for (txid, status) in txids.into_iter().zip(statuses) {
+            if self.watched.get(&txid) != Some(&status) {
+                changes.push((txid.clone(), status));
+            }
+            if status.is_final() {
+                self.watched.remove(&txid);
+            } else {
+                self.watched.insert(txid, status);
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::MockWallet;
+
+    #[test]
+    fn test_status_from_heights() {
+        assert_eq!(TxStatus::at(None, 100), TxStatus::Pending);
+        assert_eq!(TxStatus::at(Some(0), 100), TxStatus::Pending);
+        assert_eq!(TxStatus::at(Some(99), 100), TxStatus::Mined { height: 99 });
+        assert_eq!(
+            TxStatus::at(Some(98), 100),
+            TxStatus::Confirmed {
+                height: 98,
+                confirmations: 3
+            }
+        );
+        assert_eq!(TxStatus::at(Some(98), 100).describe(), "confirmed (3)");
+        assert_eq!(TxStatus::Mined { height: 7 }.describe(), "mined at 7");
+        assert_eq!(
+            serde_json::to_string(&TxStatus::Mined { height: 7 }).unwrap(),
+            r#"{"state":"mined","height":7}"#
+        );
+    }
+
+    #[test]
+    fn test_watcher_reports_each_transition_until_confirmed() {
+        let wallet = MockWallet::new("zs1coordinator");
+        wallet.set_balance(100_000);
+        let txid = wallet.send_memo("zs1alice", 0, "hi", None).unwrap();
+
+        let mut watcher = TxWatcher::new();
+        watcher.watch(&txid);
+        watcher.watch("ff".repeat(32).as_str());
+        assert_eq!(
+            watcher.poll(&wallet).unwrap(),
+            vec![(txid.clone(), TxStatus::Pending)]
+        );
+        assert!(watcher.poll(&wallet).unwrap().is_empty());
+
+        wallet.sync().unwrap();
+        assert_eq!(
+            watcher.poll(&wallet).unwrap(),
+            vec![(txid.clone(), TxStatus::Mined { height: 1 })]
+        );
+
+        wallet.sync().unwrap();
+        wallet.sync().unwrap();
+        assert_eq!(
+            watcher.poll(&wallet).unwrap(),
+            vec![(
+                txid.clone(),
+                TxStatus::Confirmed {
+                    height: 1,
+                    confirmations: 3
+                }
+            )]
+        );
+        assert_eq!(watcher.status(&txid), None);
+        assert_eq!(watcher.len(), 1);
+    }
+}
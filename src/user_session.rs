@@ -1,13 +1,47 @@
+use crate::crypto;
+use crate::session_store::SessionBackend;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub timeout_secs: u64,
+    pub max_per_user: usize,
+    pub store: SessionBackend,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            timeout_secs: 3600,
+            max_per_user: 5,
+            store: SessionBackend::State,
+        }
+    }
+}
+
+fn new_session_id() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    crypto::to_hex(&bytes)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct UserSession {
+    #[serde(default)]
+    pub session_id: String,
     pub user_id: String,
     pub reply_address: String,
     pub session_start: u64,
     pub last_activity: u64,
     pub is_authenticated: bool,
+    #[serde(default)]
+    pub expires_at: u64,
+    #[serde(default)]
+    pub commands_issued: u64,
 }
 
 impl UserSession {
@@ -18,11 +52,14 @@ impl UserSession {
             .as_secs();
 
         UserSession {
+            session_id: new_session_id(),
             user_id,
             reply_address,
             session_start: now,
             last_activity: now,
             is_authenticated: false,
+            expires_at: 0,
+            commands_issued: 0,
         }
     }
 
@@ -33,9 +70,14 @@ impl UserSession {
             .as_secs();
     }
 
-    pub fn authenticate(&mut self) {
+    pub fn authenticate(&mut self, timeout_secs: u64) {
         self.is_authenticated = true;
+        self.renew(timeout_secs);
+    }
+
+    pub fn renew(&mut self, timeout_secs: u64) {
         self.update_activity();
+        self.expires_at = self.last_activity + timeout_secs;
     }
 
     pub fn is_session_expired(&self, timeout_secs: u64) -> bool {
@@ -43,7 +85,20 @@ impl UserSession {
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        now - self.last_activity > timeout_secs
+        if self.expires_at > 0 {
+            return now >= self.expires_at;
+        }
+        now.saturating_sub(self.last_activity) > timeout_secs
+    }
+
+    pub fn state(&self, now: u64) -> &'static str {
+        if !self.is_authenticated {
+            "pending"
+        } else if self.expires_at > 0 && now >= self.expires_at {
+            "expired"
+        } else {
+            "active"
+        }
     }
 }
 
@@ -51,6 +106,7 @@ impl UserSession {
 pub struct SessionManager {
     sessions: HashMap<String, UserSession>,
     session_timeout: u64,
+    max_per_user: usize,
 }
 
 impl SessionManager {
@@ -58,43 +114,187 @@ impl SessionManager {
         SessionManager {
             sessions: HashMap::new(),
             session_timeout,
+            max_per_user: SessionConfig::default().max_per_user,
         }
     }
 
+    pub fn set_timeout(&mut self, session_timeout: u64) {
+        self.session_timeout = session_timeout;
+    }
+
+    pub fn set_max_per_user(&mut self, max_per_user: usize) {
+        self.max_per_user = max_per_user;
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.session_timeout
+    }
+
     pub fn create_session(&mut self, user_id: String, reply_address: String) -> &UserSession {
-        let session = UserSession::new(user_id.clone(), reply_address);
-        self.sessions.insert(user_id.clone(), session);
-        self.sessions.get(&user_id).unwrap()
+        let existing = self
+            .sessions_for(&user_id)
+            .into_iter()
+            .find(|session| session.reply_address == reply_address)
+            .map(|session| session.session_id.clone());
+        let session_id = match existing {
+            Some(session_id) => session_id,
+            None => {
+                let mut owned = self.sessions_for(&user_id);
+                owned.sort_by_key(|session| (session.is_authenticated, session.last_activity));
+                let excess = (owned.len() + 1).saturating_sub(self.max_per_user.max(1));
+                let evicted: Vec<String> = owned
+                    .iter()
+                    .take(excess)
+                    .map(|session| session.session_id.clone())
+                    .collect();
+                for session_id in evicted {
+                    self.sessions.remove(&session_id);
+                }
+
+                let session = UserSession::new(user_id, reply_address);
+                let session_id = session.session_id.clone();
+                self.sessions.insert(session_id.clone(), session);
+                session_id
+            }
+        };
+        &self.sessions[&session_id]
+    }
+
+    pub fn session(&self, session_id: &str) -> Option<&UserSession> {
+        self.sessions.get(session_id)
+    }
+
+    pub fn session_mut(&mut self, session_id: &str) -> Option<&mut UserSession> {
+        self.sessions.get_mut(session_id)
+    }
+
+    pub fn sessions_for(&self, user_id: &str) -> Vec<&UserSession> {
+        let mut sessions: Vec<&UserSession> = self
+            .sessions
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .collect();
+        sessions.sort_by_key(|session| (session.session_start, session.session_id.clone()));
+        sessions
+    }
+
+    fn latest_id(&self, user_id: &str) -> Option<String> {
+        self.sessions
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .max_by_key(|session| (session.last_activity, session.session_start))
+            .map(|session| session.session_id.clone())
     }
 
     pub fn get_session(&self, user_id: &str) -> Option<&UserSession> {
-        self.sessions.get(user_id)
+        self.latest_id(user_id)
+            .and_then(|session_id| self.sessions.get(&session_id))
     }
 
     pub fn get_session_mut(&mut self, user_id: &str) -> Option<&mut UserSession> {
-        self.sessions.get_mut(user_id)
+        self.latest_id(user_id)
+            .and_then(move |session_id| self.sessions.get_mut(&session_id))
     }
 
-    pub fn authenticate_session(&mut self, user_id: &str) -> bool {
+    pub fn authenticate(&mut self, session_id: &str) -> bool {
         let timeout = self.session_timeout;
-        if let Some(session) = self.get_session_mut(user_id) {
-            if !session.is_session_expired(timeout) {
-                session.authenticate();
-                return true;
+        match self.sessions.get_mut(session_id) {
+            Some(session) => {
+                session.authenticate(timeout);
+                true
             }
+            None => false,
         }
-        false
     }
 
-    pub fn cleanup_expired_sessions(&mut self) {
-        self.sessions
-            .retain(|_, session| !session.is_session_expired(self.session_timeout));
+    pub fn authenticate_session(&mut self, user_id: &str) -> bool {
+        match self.latest_id(user_id) {
+            Some(session_id) => self.authenticate(&session_id),
+            None => false,
+        }
+    }
+
+    pub fn is_stale(&self, session_id: &str) -> bool {
+        self.session(session_id).is_some_and(|session| {
+            session.is_authenticated && session.is_session_expired(self.session_timeout)
+        })
+    }
+
+    pub fn renew_session(&mut self, session_id: &str) -> Option<u64> {
+        let timeout = self.session_timeout;
+        let session = self.session_mut(session_id)?;
+        if !session.is_authenticated {
+            return None;
+        }
+        session.renew(timeout);
+        Some(session.expires_at)
+    }
+
+    pub fn record_command(&mut self, session_id: &str) {
+        if let Some(session) = self.session_mut(session_id) {
+            session.commands_issued += 1;
+            session.update_activity();
+        }
+    }
+
+    pub fn list(&self, user_id: Option<&str>) -> Vec<&UserSession> {
+        let mut sessions: Vec<&UserSession> = self
+            .sessions
+            .values()
+            .filter(|session| user_id.is_none_or(|user_id| session.user_id == user_id))
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_activity));
+        sessions
+    }
+
+    pub fn cleanup_expired_sessions(&mut self) -> Vec<UserSession> {
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.is_session_expired(self.session_timeout))
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|session_id| self.sessions.remove(session_id))
+            .collect()
     }
 
     pub fn get_reply_address(&self, user_id: &str) -> Option<String> {
         self.get_session(user_id).map(|s| s.reply_address.clone())
     }
 
+    pub fn sessions(&self) -> Vec<UserSession> {
+        self.sessions.values().cloned().collect()
+    }
+
+    pub fn remove_session(&mut self, session_id: &str) -> Option<UserSession> {
+        self.sessions.remove(session_id)
+    }
+
+    pub fn remove_user(&mut self, user_id: &str) -> Vec<UserSession> {
+        let session_ids: Vec<String> = self
+            .sessions_for(user_id)
+            .into_iter()
+            .map(|session| session.session_id.clone())
+            .collect();
+        session_ids
+            .iter()
+            .filter_map(|session_id| self.sessions.remove(session_id))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+    }
+
+    pub fn restore_session(&mut self, mut session: UserSession) {
+        if session.session_id.is_empty() {
+            session.session_id = new_session_id();
+        }
+        self.sessions.insert(session.session_id.clone(), session);
+    }
+
     pub fn active_reply_addresses(&self) -> Vec<String> {
         self.sessions
             .values()
@@ -127,6 +327,89 @@ mod tests {
 
         let session = manager.get_session("zs1user123").unwrap();
         assert!(session.is_authenticated);
+        assert_eq!(session.expires_at, session.last_activity + 3600);
+    }
+
+    #[test]
+    fn test_authenticated_sessions_go_stale_until_renewed() {
+        let mut manager = SessionManager::new(60);
+        let session_id = manager
+            .create_session("zs1user123".to_string(), "zs1reply456".to_string())
+            .session_id
+            .clone();
+        assert_eq!(manager.renew_session(&session_id), None);
+        manager.authenticate(&session_id);
+        assert!(!manager.is_stale(&session_id));
+
+        manager.session_mut(&session_id).unwrap().expires_at = 1;
+        assert!(manager.is_stale(&session_id));
+        let renewed = manager.renew_session(&session_id).unwrap();
+        assert!(!manager.is_stale(&session_id));
+        assert_eq!(
+            renewed,
+            manager.session(&session_id).unwrap().last_activity + 60
+        );
+        assert!(!manager.is_stale("unknown"));
+    }
+
+    #[test]
+    fn test_users_keep_one_session_per_reply_address() {
+        let mut manager = SessionManager::new(3600);
+        manager.set_max_per_user(2);
+        let phone = manager
+            .create_session("zs1user123".to_string(), "zs1phone".to_string())
+            .session_id
+            .clone();
+        manager.authenticate(&phone);
+        let again = manager
+            .create_session("zs1user123".to_string(), "zs1phone".to_string())
+            .session_id
+            .clone();
+        assert_eq!(again, phone);
+        assert!(manager.session(&phone).unwrap().is_authenticated);
+
+        let laptop = manager
+            .create_session("zs1user123".to_string(), "zs1laptop".to_string())
+            .session_id
+            .clone();
+        assert_ne!(laptop, phone);
+        assert_eq!(manager.sessions_for("zs1user123").len(), 2);
+
+        manager.create_session("zs1user123".to_string(), "zs1tablet".to_string());
+        let remaining: Vec<&str> = manager
+            .sessions_for("zs1user123")
+            .iter()
+            .map(|session| session.reply_address.as_str())
+            .collect();
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining.contains(&"zs1phone"));
+        assert!(!remaining.contains(&"zs1laptop"));
+
+        manager.create_session("zs1other".to_string(), "zs1otherreply".to_string());
+        assert_eq!(manager.remove_user("zs1user123").len(), 2);
+        assert_eq!(manager.sessions().len(), 1);
+    }
+
+    #[test]
+    fn test_sessions_report_state_and_command_counts() {
+        let mut manager = SessionManager::new(3600);
+        let session_id = manager
+            .create_session("zs1user123".to_string(), "zs1reply456".to_string())
+            .session_id
+            .clone();
+        manager.create_session("zs1other".to_string(), "zs1otherreply".to_string());
+        let now = manager.session(&session_id).unwrap().session_start;
+        assert_eq!(manager.session(&session_id).unwrap().state(now), "pending");
+
+        manager.authenticate(&session_id);
+        manager.record_command(&session_id);
+        manager.record_command(&session_id);
+        let listed = manager.list(Some("zs1user123"));
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].commands_issued, 2);
+        assert_eq!(listed[0].state(now), "active");
+        assert_eq!(listed[0].state(listed[0].expires_at), "expired");
+        assert_eq!(manager.list(None).len(), 2);
     }
 
     #[test]
@@ -0,0 +1,313 @@
+use crate::fee;
+use crate::memo_decoder::MAX_MEMO_SIZE;
+use crate::message::Message;
+use crate::tx_status::TxStatus;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub trait WalletBackend: Clone + Send + Sync + 'static {
+    fn send_memo(
+        &self,
+        address: &str,
+        amount_zatoshis: u64,
+        memo: &str,
+        max_fee: Option<u64>,
+    ) -> Result<String, String>;
+
+    fn send_memos(
+        &self,
+        sends: &[(&str, u64, &str)],
+        max_fee: Option<u64>,
+    ) -> Result<String, String>;
+
+    fn get_messages(&self) -> Result<Vec<Message>, String>;
+
+    fn sync(&self) -> Result<(), String>;
+
+    fn height(&self) -> Result<u64, String>;
+
+    fn addresses(&self) -> Result<Vec<String>, String>;
+
+    fn balance(&self) -> Result<u64, String>;
+
+    fn tx_status(&self, txid: &str) -> Result<TxStatus, String>;
+
+    fn tx_statuses(&self, txids: &[String]) -> Result<Vec<TxStatus>, String> {
+        txids.iter().map(|txid| self.tx_status(txid)).collect()
+    }
+
+    fn poll_once(&self) -> Result<Vec<Message>, String> {
+        self.sync()?;
+        self.get_messages()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentMemo {
+    pub txid: String,
+    pub address: String,
+    pub amount_zatoshis: u64,
+    pub fee_zatoshis: u64,
+    pub memo: String,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    address: String,
+    height: u64,
+    balance: u64,
+    next_tx: u64,
+    mempool: Vec<Message>,
+    received: Vec<Message>,
+    sent: Vec<SentMemo>,
+    mined: HashMap<String, Option<u64>>,
+    send_error: Option<String>,
+}
+
+impl MockState {
+    fn next_txid(&mut self) -> String {
+        self.next_tx += 1;
+        format!("{:064x}", self.next_tx)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockWallet {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockWallet {
+    pub fn new(address: &str) -> Self {
+        MockWallet {
+            state: Arc::new(Mutex::new(MockState {
+                address: address.to_string(),
+                ..MockState::default()
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn receive(&self, sender: &str, amount_zatoshis: u64, memo: &str) -> String {
+        let mut state = self.lock();
+        let txid = state.next_txid();
+        let mut message = Message::with_txid(
+            sender.to_string(),
+            state.address.clone(),
+            memo.to_string(),
+            txid.clone(),
+        );
+        message.amount_zatoshis = amount_zatoshis;
+        message.output_index = Some(0);
+        state.mempool.push(message);
+        txid
+    }
+
+    pub fn sent(&self) -> Vec<SentMemo> {
+        self.lock().sent.clone()
+    }
+
+    pub fn set_balance(&self, zatoshis: u64) {
+        self.lock().balance = zatoshis;
+    }
+
+    pub fn fail_sends(&self, error: Option<&str>) {
+        self.lock().send_error = error.map(str::to_string);
+    }
+}
+
+impl WalletBackend for MockWallet {
+    fn send_memo(
+        &self,
+        address: &str,
+        amount_zatoshis: u64,
+        memo: &str,
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        self.send_memos(&[(address, amount_zatoshis, memo)], max_fee)
+    }
+
+    fn send_memos(
+        &self,
+        sends: &[(&str, u64, &str)],
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        let mut state = self.lock();
+        if let Some(error) = &state.send_error {
+            return Err(error.clone());
+        }
+        if sends.is_empty() {
+            return Err("Nothing to send".to_string());
+        }
+        if let Some((_, _, memo)) = sends.iter().find(|(_, _, memo)| memo.len() > MAX_MEMO_SIZE) {
+            return Err(format!(
+                "Memo too long: {} bytes (max {}); split it with Message::split_for_memo",
+                memo.len(),
+                MAX_MEMO_SIZE
+            ));
+        }
+        let memo_lens: Vec<usize> = sends.iter().map(|(_, _, memo)| memo.len()).collect();
+        let fee_zatoshis = fee::check_fee(&memo_lens, max_fee)?;
+        let total = sends.iter().fold(fee_zatoshis, |total, (_, amount, _)| {
+            total.saturating_add(*amount)
+        });
+        if total > state.balance {
+            return Err(format!(
+                "Insufficient balance: need {} zatoshis, have {}",
+                total, state.balance
+            ));
+        }
+        state.balance -= total;
+        let txid = state.next_txid();
+        state.mined.insert(txid.clone(), None);
+        for (index, (address, amount_zatoshis, memo)) in sends.iter().enumerate() {
+            state.sent.push(SentMemo {
+                txid: txid.clone(),
+                address: address.to_string(),
+                amount_zatoshis: *amount_zatoshis,
+                fee_zatoshis: if index == 0 { fee_zatoshis } else { 0 },
+                memo: memo.to_string(),
+            });
+        }
+        Ok(txid)
+    }
+
+    fn get_messages(&self) -> Result<Vec<Message>, String> {
+        Ok(self.lock().received.clone())
+    }
+
+    fn sync(&self) -> Result<(), String> {
+        let mut state = self.lock();
+        state.height += 1;
+        let height = state.height;
+        for mined in state.mined.values_mut() {
+            mined.get_or_insert(height);
+        }
+        let mempool = std::mem::take(&mut state.mempool);
+        state
+            .received
+            .extend(mempool.into_iter().map(|mut message| {
+                message.block_height = Some(height);
+                message
+            }));
+        Ok(())
+    }
+
+    fn height(&self) -> Result<u64, String> {
+        Ok(self.lock().height)
+    }
+
+    fn addresses(&self) -> Result<Vec<String>, String> {
+        Ok(vec![self.lock().address.clone()])
+    }
+
+    fn balance(&self) -> Result<u64, String> {
+        Ok(self.lock().balance)
+    }
+
+    fn tx_status(&self, txid: &str) -> Result<TxStatus, String> {
+        let state = self.lock();
+        if let Some(mined) = state.mined.get(txid) {
+            return Ok(TxStatus::at(*mined, state.height));
+        }
+        if state
+            .mempool
+            .iter()
+            .any(|m| m.txid.as_deref() == Some(txid))
+        {
+            return Ok(TxStatus::Pending);
+        }
+        Ok(state
+            .received
+            .iter()
+            .find(|m| m.txid.as_deref() == Some(txid))
+            .map_or(TxStatus::Unknown, |m| {
+                TxStatus::at(m.block_height, state.height)
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_wallet_delivers_after_sync() {
+        let wallet = MockWallet::new("zs1coordinator");
+        let txid = wallet.receive("zs1alice", 1000, "ls /");
+        assert!(wallet.get_messages().unwrap().is_empty());
+
+        let messages = wallet.poll_once().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].txid.as_deref(), Some(txid.as_str()));
+        assert_eq!(messages[0].recipient_address, "zs1coordinator");
+        assert_eq!(messages[0].amount_zatoshis, 1000);
+        assert_eq!(messages[0].block_height, Some(1));
+        assert_eq!(wallet.height(), Ok(1));
+        assert_eq!(wallet.addresses().unwrap(), vec!["zs1coordinator"]);
+    }
+
+    #[test]
+    fn test_mock_wallet_records_sends_and_spends_balance() {
+        let wallet = MockWallet::new("zs1coordinator");
+        wallet.set_balance(10_500);
+        let txid = wallet.send_memo("zs1alice", 200, "hello", None).unwrap();
+        assert_eq!(txid.len(), 64);
+        assert_eq!(wallet.balance(), Ok(300));
+        assert_eq!(wallet.sent()[0].memo, "hello");
+        assert_eq!(wallet.sent()[0].fee_zatoshis, 10_000);
+
+        wallet.set_balance(1_000_000);
+        assert!(wallet
+            .send_memo("zs1alice", 990_001, "too much", None)
+            .unwrap_err()
+            .starts_with("Insufficient balance"));
+        assert!(wallet
+            .send_memo("zs1alice", 0, "capped", Some(5_000))
+            .unwrap_err()
+            .starts_with("Fee too high"));
+        assert!(wallet
+            .send_memo("zs1alice", 0, &"x".repeat(513), None)
+            .is_err());
+        wallet.fail_sends(Some("lightwalletd unreachable"));
+        assert_eq!(
+            wallet.send_memo("zs1alice", 0, "hi", None),
+            Err("lightwalletd unreachable".to_string())
+        );
+        assert_eq!(wallet.sent().len(), 1);
+    }
+
+    #[test]
+    fn test_mock_wallet_sends_many_outputs_in_one_transaction() {
+        let wallet = MockWallet::new("zs1coordinator");
+        wallet.set_balance(100_000);
+        let txid = wallet
+            .send_memos(
+                &[
+                    ("zs1alice", 100, "part 1"),
+                    ("zs1alice", 0, "part 2"),
+                    ("zs1bob", 0, "hi"),
+                ],
+                None,
+            )
+            .unwrap();
+        let sent = wallet.sent();
+        assert_eq!(sent.len(), 3);
+        assert!(sent.iter().all(|memo| memo.txid == txid));
+        assert_eq!(
+            sent.iter().map(|memo| memo.fee_zatoshis).sum::<u64>(),
+            20_000
+        );
+        assert_eq!(wallet.balance(), Ok(100_000 - 20_100));
+
+        assert!(wallet.send_memos(&[], None).is_err());
+        assert!(wallet
+            .send_memos(&[("zs1alice", 0, "a"), ("zs1bob", 0, "b")], Some(10_000))
+            .unwrap_err()
+            .starts_with("Fee too high"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchEvent {
+    Created,
+    Modified,
+    Removed,
+}
+
+impl WatchEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchEvent::Created => "created",
+            WatchEvent::Modified => "modified",
+            WatchEvent::Removed => "removed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub user_id: String,
+    pub memo: String,
+}
+
+#[derive(Debug, Default)]
+pub struct WatchRegistry {
+    watches: HashMap<String, HashSet<String>>,
+    queue: VecDeque<Notification>,
+}
+
+fn normalize_watch_path(path: &str) -> String {
+    let trimmed = path.trim().trim_end_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn covers(watched: &str, path: &str) -> bool {
+    watched == "/"
+        || path == watched
+        || path
+            .strip_prefix(watched)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl WatchRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch(&mut self, user_id: &str, path: &str) -> bool {
+        self.watches
+            .entry(normalize_watch_path(path))
+            .or_default()
+            .insert(user_id.to_string())
+    }
+
+    pub fn unwatch(&mut self, user_id: &str, path: &str) -> bool {
+        let path = normalize_watch_path(path);
+        let removed = self
+            .watches
+            .get_mut(&path)
+            .is_some_and(|users| users.remove(user_id));
+        if self
+            .watches
+            .get(&path)
+            .is_some_and(|users| users.is_empty())
+        {
+            self.watches.remove(&path);
+        }
+        removed
+    }
+
+    pub fn watched_paths(&self, user_id: &str) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .watches
+            .iter()
+            .filter(|(_, users)| users.contains(user_id))
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    pub fn subscribers(&self, path: &str) -> Vec<String> {
+        let path = normalize_watch_path(path);
+        let mut users: Vec<String> = self
+            .watches
+            .iter()
+            .filter(|(watched, _)| covers(watched, &path))
+            .flat_map(|(_, users)| users.iter().cloned())
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        users.sort();
+        users
+    }
+
+    pub fn enqueue(&mut self, user_id: &str, memo: String) {
+        self.queue.push_back(Notification {
+            user_id: user_id.to_string(),
+            memo,
+        });
+    }
+
+    pub fn drain(&mut self) -> Vec<Notification> {
+        self.queue.drain(..).collect()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribers_cover_descendants() {
+        let mut registry = WatchRegistry::new();
+        assert!(registry.watch("zs1alice", "/board/"));
+        assert!(!registry.watch("zs1alice", "/board"));
+        registry.watch("zs1bob", "/");
+        registry.watch("zs1carol", "/boardroom");
+
+        assert_eq!(
+            registry.subscribers("/board/post.txt"),
+            vec!["zs1alice", "zs1bob"]
+        );
+        assert_eq!(registry.subscribers("/other"), vec!["zs1bob"]);
+        assert_eq!(registry.watched_paths("zs1alice"), vec!["/board"]);
+
+        assert!(registry.unwatch("zs1alice", "/board"));
+        assert!(!registry.unwatch("zs1alice", "/board"));
+        assert_eq!(registry.subscribers("/board/post.txt"), vec!["zs1bob"]);
+    }
+
+    #[test]
+    fn test_queue_drains_in_order() {
+        let mut registry = WatchRegistry::new();
+        registry.enqueue("zs1alice", "first".to_string());
+        registry.enqueue("zs1bob", "second".to_string());
+        assert_eq!(registry.pending(), 2);
+
+        let drained = registry.drain();
+        assert_eq!(drained[0].memo, "first");
+        assert_eq!(drained[1].user_id, "zs1bob");
+        assert_eq!(registry.pending(), 0);
+    }
+}
@@ -1,38 +1,152 @@
+use std::ffi::OsString;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Output};
+use std::time::Duration;
 
+use crate::fee;
+use crate::memo_decoder::MAX_MEMO_SIZE;
 use crate::message::Message;
+use crate::retry::{self, CircuitBreaker, RetryConfig, ZingoError};
+use crate::tx_status::TxStatus;
+use crate::wallet::WalletBackend;
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalletBalance {
+    pub shielded: u64,
+    pub transparent: u64,
+    pub spendable: u64,
+}
+
+#[derive(Debug, Clone)]
 pub struct ZingoClient {
     pub data_dir: PathBuf,
     server: String,
+    retry: RetryConfig,
+    breaker: CircuitBreaker,
+}
+
+const NON_IDEMPOTENT: [&str; 4] = ["send", "quicksend", "shield", "quickshield"];
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
 impl ZingoClient {
     pub fn new(data_dir: PathBuf, server: String) -> Self {
-        ZingoClient { data_dir, server }
+        ZingoClient {
+            data_dir,
+            server,
+            retry: RetryConfig::default(),
+            breaker: CircuitBreaker::new(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn retry_config(&self) -> &RetryConfig {
+        &self.retry
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    fn base_args(&self) -> Vec<OsString> {
+        vec![
+            "--data-dir".into(),
+            self.data_dir.clone().into_os_string(),
+            "--server".into(),
+            self.server.clone().into(),
+            "--chain".into(),
+            "testnet".into(),
+        ]
     }
 
-    fn execute_args(&self, args: &[String]) -> Result<String, String> {
+    fn max_attempts(&self, args: &[String]) -> u32 {
+        let idempotent = args
+            .first()
+            .is_none_or(|command| !NON_IDEMPOTENT.contains(&command.as_str()));
+        if idempotent {
+            self.retry.attempts.max(1)
+        } else {
+            1
+        }
+    }
+
+    fn spawn_once(&self, args: &[String]) -> Result<String, ZingoError> {
         let output = Command::new("zingo-cli")
-            .arg("--data-dir")
-            .arg(&self.data_dir)
-            .arg("--server")
-            .arg(&self.server)
-            .arg("--chain")
-            .arg("testnet")
+            .args(self.base_args())
+            .args(args)
+            .output()
+            .map_err(|e| ZingoError::Fatal(format!("Failed to execute zingo-cli: {}", e)))?;
+        Self::command_output(output)
+    }
+
+    async fn spawn_once_async(&self, args: &[String]) -> Result<String, ZingoError> {
+        let output = tokio::process::Command::new("zingo-cli")
+            .args(self.base_args())
             .args(args)
+            .kill_on_drop(true)
             .output()
-            .map_err(|e| format!("Failed to execute zingo-cli: {}", e))?;
+            .await
+            .map_err(|e| ZingoError::Fatal(format!("Failed to execute zingo-cli: {}", e)))?;
+        Self::command_output(output)
+    }
+
+    fn should_retry(&self, result: &Result<String, ZingoError>, attempt: u32, max: u32) -> bool {
+        self.breaker.record(&self.retry, result, now_secs());
+        matches!(result, Err(error) if error.is_transient())
+            && attempt < max
+            && !self.breaker.is_open(&self.retry, now_secs())
+    }
 
+    fn execute_args(&self, args: &[String]) -> Result<String, ZingoError> {
+        let max = self.max_attempts(args);
+        retry::run_blocking(|| {
+            let mut attempt = 1;
+            loop {
+                self.breaker.admit(&self.retry, now_secs())?;
+                let result = self.spawn_once(args);
+                if !self.should_retry(&result, attempt, max) {
+                    return result;
+                }
+                std::thread::sleep(self.retry.next_delay(attempt));
+                attempt += 1;
+            }
+        })
+    }
+
+    async fn execute_args_async(&self, args: &[String]) -> Result<String, ZingoError> {
+        let max = self.max_attempts(args);
+        let mut attempt = 1;
+        loop {
+            self.breaker.admit(&self.retry, now_secs())?;
+            let result = self.spawn_once_async(args).await;
+            if !self.should_retry(&result, attempt, max) {
+                return result;
+            }
+            tokio::time::sleep(self.retry.next_delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    fn command_output(output: Output) -> Result<String, ZingoError> {
         if output.status.success() {
             Ok(String::from_utf8_lossy(&output.stdout).to_string())
         } else {
             let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
             if stderr.is_empty() {
-                Err("zingo-cli command failed with empty stderr".to_string())
+                Err(ZingoError::Fatal(
+                    "zingo-cli command failed with empty stderr".to_string(),
+                ))
             } else {
-                Err(stderr)
+                Err(ZingoError::from_stderr(stderr))
             }
         }
     }
@@ -73,29 +187,62 @@ impl ZingoClient {
     }
 
     fn extract_json_payload(raw_data: &str) -> Option<&str> {
-        let object_start = raw_data.find('{');
-        let object_end = raw_data.rfind('}');
-        if let (Some(start), Some(end)) = (object_start, object_end) {
-            return Some(&raw_data[start..=end]);
-        }
+        let start = raw_data.find(['{', '['])?;
+        let close = if raw_data[start..].starts_with('{') {
+            '}'
+        } else {
+            ']'
+        };
+        let end = raw_data.rfind(close).filter(|end| *end > start)?;
+        Some(&raw_data[start..=end])
+    }
 
-        let array_start = raw_data.find('[');
-        let array_end = raw_data.rfind(']');
-        if let (Some(start), Some(end)) = (array_start, array_end) {
-            return Some(&raw_data[start..=end]);
-        }
+    fn transfers(json: &serde_json::Value) -> impl Iterator<Item = &serde_json::Value> {
+        json.as_array()
+            .or_else(|| json.get("value_transfers").and_then(|v| v.as_array()))
+            .or_else(|| json.get("transactions").and_then(|v| v.as_array()))
+            .into_iter()
+            .flatten()
+    }
 
-        None
+    fn is_outgoing(transfer: &serde_json::Value) -> bool {
+        let kind = transfer.get("kind").and_then(|kind| kind.as_str());
+        matches!(
+            kind,
+            Some("sent" | "send-to-self" | "memo-to-self" | "basic" | "shield" | "rejection")
+        ) || (transfer.get("outgoing_metadata").is_some() && transfer.get("memo").is_none())
     }
 
-    pub fn execute_command(&self, cmd: &str) -> Result<String, String> {
-        let args = Self::split_command(cmd)?;
+    pub fn execute(&self, cmd: &str) -> Result<String, ZingoError> {
+        let args = Self::split_command(cmd).map_err(ZingoError::Fatal)?;
         self.execute_args(&args)
     }
 
+    pub async fn execute_async(&self, cmd: &str) -> Result<String, ZingoError> {
+        let args = Self::split_command(cmd).map_err(ZingoError::Fatal)?;
+        self.execute_args_async(&args).await
+    }
+
+    pub fn execute_command(&self, cmd: &str) -> Result<String, String> {
+        self.execute(cmd).map_err(String::from)
+    }
+
+    pub async fn execute_command_async(&self, cmd: &str) -> Result<String, String> {
+        self.execute_async(cmd).await.map_err(String::from)
+    }
+
     pub fn get_addresses(&self) -> Result<Vec<String>, String> {
         let response = self.execute_command("addresses")?;
-        let payload = Self::extract_json_payload(&response).unwrap_or(response.as_str());
+        Ok(Self::parse_addresses(&response))
+    }
+
+    pub async fn get_addresses_async(&self) -> Result<Vec<String>, String> {
+        let response = self.execute_command_async("addresses").await?;
+        Ok(Self::parse_addresses(&response))
+    }
+
+    fn parse_addresses(response: &str) -> Vec<String> {
+        let payload = Self::extract_json_payload(response).unwrap_or(response);
         if let Ok(value) = serde_json::from_str::<serde_json::Value>(payload) {
             if let Some(array) = value.as_array() {
                 let addresses: Vec<String> = array
@@ -103,19 +250,39 @@ impl ZingoClient {
                     .filter_map(|entry| entry.as_str().map(ToString::to_string))
                     .collect();
                 if !addresses.is_empty() {
-                    return Ok(addresses);
+                    return addresses;
                 }
             }
         }
 
-        let addresses: Vec<String> = response
+        response
             .lines()
             .map(str::trim)
             .filter(|line| !line.is_empty())
             .map(ToString::to_string)
-            .collect();
+            .collect()
+    }
 
-        Ok(addresses)
+    fn send_args(
+        address: &str,
+        amount_zatoshis: u64,
+        memo: &str,
+        max_fee: Option<u64>,
+    ) -> Result<Vec<String>, String> {
+        if memo.len() > MAX_MEMO_SIZE {
+            return Err(format!(
+                "Memo too long: {} bytes (max {}); split it with Message::split_for_memo",
+                memo.len(),
+                MAX_MEMO_SIZE
+            ));
+        }
+        fee::check_fee(&[memo.len()], max_fee)?;
+        Ok(vec![
+            "quicksend".to_string(),
+            address.to_string(),
+            amount_zatoshis.to_string(),
+            memo.to_string(),
+        ])
     }
 
     pub fn send_memo(
@@ -123,14 +290,71 @@ impl ZingoClient {
         address: &str,
         amount_zatoshis: u64,
         memo: &str,
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        self.execute_args(&Self::send_args(address, amount_zatoshis, memo, max_fee)?)
+            .map_err(String::from)
+    }
+
+    pub async fn send_memo_async(
+        &self,
+        address: &str,
+        amount_zatoshis: u64,
+        memo: &str,
+        max_fee: Option<u64>,
     ) -> Result<String, String> {
-        let args = vec![
+        self.execute_args_async(&Self::send_args(address, amount_zatoshis, memo, max_fee)?)
+            .await
+            .map_err(String::from)
+    }
+
+    fn send_many_args(
+        sends: &[(&str, u64, &str)],
+        max_fee: Option<u64>,
+    ) -> Result<Vec<String>, String> {
+        if sends.is_empty() {
+            return Err("Nothing to send".to_string());
+        }
+        let mut outputs = Vec::with_capacity(sends.len());
+        for (address, amount_zatoshis, memo) in sends {
+            if memo.len() > MAX_MEMO_SIZE {
+                return Err(format!(
+                    "Memo too long: {} bytes (max {}); split it with Message::split_for_memo",
+                    memo.len(),
+                    MAX_MEMO_SIZE
+                ));
+            }
+            outputs.push(serde_json::json!({
+                "address": address,
+                "amount": amount_zatoshis,
+                "memo": memo,
+            }));
+        }
+        let memo_lens: Vec<usize> = sends.iter().map(|(_, _, memo)| memo.len()).collect();
+        fee::check_fee(&memo_lens, max_fee)?;
+        Ok(vec![
             "quicksend".to_string(),
-            address.to_string(),
-            amount_zatoshis.to_string(),
-            memo.to_string(),
-        ];
-        self.execute_args(&args)
+            serde_json::Value::Array(outputs).to_string(),
+        ])
+    }
+
+    pub fn send_memos(
+        &self,
+        sends: &[(&str, u64, &str)],
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        self.execute_args(&Self::send_many_args(sends, max_fee)?)
+            .map_err(String::from)
+    }
+
+    pub async fn send_memos_async(
+        &self,
+        sends: &[(&str, u64, &str)],
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        self.execute_args_async(&Self::send_many_args(sends, max_fee)?)
+            .await
+            .map_err(String::from)
     }
 
     pub fn send_memo_zec(
@@ -138,9 +362,10 @@ impl ZingoClient {
         address: &str,
         amount_zec: f64,
         memo: &str,
+        max_fee: Option<u64>,
     ) -> Result<String, String> {
         let zatoshis = (amount_zec * 100_000_000.0) as u64;
-        self.send_memo(address, zatoshis, memo)
+        self.send_memo(address, zatoshis, memo, max_fee)
     }
 
     pub fn get_messages(&self) -> Result<Vec<Message>, String> {
@@ -148,6 +373,11 @@ impl ZingoClient {
         self.parse_messages(&response)
     }
 
+    pub async fn get_messages_async(&self) -> Result<Vec<Message>, String> {
+        let response = self.execute_command_async("messages").await?;
+        self.parse_messages(&response)
+    }
+
     fn parse_messages(&self, raw_data: &str) -> Result<Vec<Message>, String> {
         let json_payload = Self::extract_json_payload(raw_data)
             .ok_or_else(|| "No JSON payload found in messages response".to_string())?;
@@ -156,38 +386,23 @@ impl ZingoClient {
             .map_err(|e| format!("Failed to parse messages JSON: {}", e))?;
 
         let mut messages = Vec::new();
-        if let Some(transfers) = json.get("value_transfers").and_then(|v| v.as_array()) {
-            for transfer in transfers {
-                let txid = transfer
-                    .get("txid")
-                    .and_then(|t| t.as_str())
-                    .unwrap_or("unknown_txid")
-                    .to_string();
-
-                if let Some(memos) = transfer.get("memos").and_then(|m| m.as_array()) {
-                    for memo in memos {
-                        if let Some(memo_text) = memo.as_str() {
-                            if memo_text.is_empty() || memo_text.contains("ZecFaucet") {
-                                continue;
-                            }
-
-                            let txid_prefix: String = txid.chars().take(8).collect();
-                            let sender = if txid_prefix.is_empty() {
-                                "client_unknown".to_string()
-                            } else {
-                                format!("client_{}", txid_prefix)
-                            };
-
-                            let message = Message::with_txid(
-                                sender,
-                                "coordinator".to_string(),
-                                memo_text.to_string(),
-                                txid.clone(),
-                            );
-                            messages.push(message);
-                        }
-                    }
-                }
+        for transfer in Self::transfers(&json) {
+            if Self::is_outgoing(transfer) {
+                continue;
+            }
+            if transfer.get("memos").is_none()
+                && transfer.get("memo").is_none()
+                && transfer.get("memo_text").is_none()
+            {
+                continue;
+            }
+            match Message::from_zingo_value(transfer) {
+                Ok(parsed) => messages.extend(
+                    parsed
+                        .into_iter()
+                        .filter(|message| !message.memo_text.contains("ZecFaucet")),
+                ),
+                Err(e) => eprintln!("⚠️  Skipping unreadable transaction: {}", e),
             }
         }
 
@@ -221,10 +436,237 @@ impl ZingoClient {
     //     Ok(new_messages)
     // }
 
+    pub fn get_tx_status(&self, txid: &str) -> Result<TxStatus, String> {
+        self.get_tx_statuses(&[txid.to_string()])
+            .map(|mut statuses| statuses.remove(0))
+    }
+
+    pub fn get_tx_statuses(&self, txids: &[String]) -> Result<Vec<TxStatus>, String> {
+        let tip = self.sync_height()?;
+        let response = self.execute_command("messages")?;
+        Self::parse_tx_statuses(&response, txids, tip)
+    }
+
+    pub async fn get_tx_statuses_async(&self, txids: &[String]) -> Result<Vec<TxStatus>, String> {
+        let tip = self.sync_height_async().await?;
+        let response = self.execute_command_async("messages").await?;
+        Self::parse_tx_statuses(&response, txids, tip)
+    }
+
+    fn parse_tx_statuses(
+        raw_data: &str,
+        txids: &[String],
+        tip: u64,
+    ) -> Result<Vec<TxStatus>, String> {
+        let payload = Self::extract_json_payload(raw_data)
+            .ok_or_else(|| "No JSON payload found in messages response".to_string())?;
+        let json = serde_json::from_str::<serde_json::Value>(payload)
+            .map_err(|e| format!("Failed to parse messages JSON: {}", e))?;
+
+        Ok(txids
+            .iter()
+            .map(|txid| {
+                Self::transfers(&json)
+                    .find(|transfer| {
+                        transfer.get("txid").and_then(|t| t.as_str()) == Some(txid.as_str())
+                    })
+                    .map_or(TxStatus::Unknown, |transfer| {
+                        let pending = transfer.get("unconfirmed").and_then(|u| u.as_bool())
+                            == Some(true)
+                            || transfer
+                                .get("status")
+                                .and_then(|s| s.as_str())
+                                .is_some_and(|status| status != "confirmed");
+                        let height = ["block_height", "blockheight", "height"]
+                            .iter()
+                            .find_map(|key| transfer.get(*key).and_then(|h| h.as_u64()))
+                            .filter(|_| !pending);
+                        TxStatus::at(height, tip)
+                    })
+            })
+            .collect())
+    }
+
+    pub fn sync_height(&self) -> Result<u64, String> {
+        let response = self.execute_command("height")?;
+        Self::parse_height(&response)
+    }
+
+    pub async fn sync_height_async(&self) -> Result<u64, String> {
+        let response = self.execute_command_async("height").await?;
+        Self::parse_height(&response)
+    }
+
+    fn parse_height(raw_data: &str) -> Result<u64, String> {
+        let payload = Self::extract_json_payload(raw_data)
+            .ok_or_else(|| "No JSON payload found in height response".to_string())?;
+        let json = serde_json::from_str::<serde_json::Value>(payload)
+            .map_err(|e| format!("Failed to parse height JSON: {}", e))?;
+
+        json.get("height")
+            .and_then(|h| h.as_u64())
+            .ok_or_else(|| "Height response has no height field".to_string())
+    }
+
+    pub fn get_balance(&self) -> Result<WalletBalance, String> {
+        let response = self.execute_command("balance")?;
+        Self::parse_balance(&response)
+    }
+
+    pub async fn get_balance_async(&self) -> Result<WalletBalance, String> {
+        let response = self.execute_command_async("balance").await?;
+        Self::parse_balance(&response)
+    }
+
+    pub fn spendable_balance(&self) -> Result<u64, String> {
+        self.get_balance().map(|balance| balance.spendable)
+    }
+
+    pub async fn spendable_balance_async(&self) -> Result<u64, String> {
+        self.get_balance_async()
+            .await
+            .map(|balance| balance.spendable)
+    }
+
+    fn parse_balance(raw_data: &str) -> Result<WalletBalance, String> {
+        let payload = Self::extract_json_payload(raw_data)
+            .ok_or_else(|| "No JSON payload found in balance response".to_string())?;
+        let json = serde_json::from_str::<serde_json::Value>(payload)
+            .map_err(|e| format!("Failed to parse balance JSON: {}", e))?;
+
+        let sum = |fields: &[&[&str]]| -> Option<u64> {
+            let pools: Vec<u64> = fields
+                .iter()
+                .filter_map(|names| {
+                    names
+                        .iter()
+                        .find_map(|name| json.get(*name).and_then(|b| b.as_u64()))
+                })
+                .collect();
+            (!pools.is_empty()).then(|| pools.iter().sum())
+        };
+
+        let spendable = sum(&[
+            &["spendable_sapling_balance"],
+            &["spendable_orchard_balance"],
+        ])
+        .ok_or_else(|| "Balance response has no spendable balance".to_string())?;
+        let shielded = sum(&[
+            &["sapling_balance", "total_sapling_balance"],
+            &["orchard_balance", "total_orchard_balance"],
+        ])
+        .unwrap_or(spendable);
+        let transparent = sum(&[&[
+            "transparent_balance",
+            "confirmed_transparent_balance",
+            "total_transparent_balance",
+        ]])
+        .unwrap_or(0);
+
+        Ok(WalletBalance {
+            shielded,
+            transparent,
+            spendable,
+        })
+    }
+
+    pub fn parse_txid(raw_data: &str) -> Result<String, String> {
+        let payload = Self::extract_json_payload(raw_data)
+            .ok_or_else(|| "No JSON payload found in send response".to_string())?;
+        let json = serde_json::from_str::<serde_json::Value>(payload)
+            .map_err(|e| format!("Failed to parse send response JSON: {}", e))?;
+        if let Some(error) = json.get("error").and_then(|e| e.as_str()) {
+            return Err(format!("Send rejected: {}", error));
+        }
+
+        json.get("txids")
+            .and_then(|txids| txids.get(0))
+            .or_else(|| json.get("txid"))
+            .or_else(|| json.get(0))
+            .and_then(|txid| txid.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| "Send response has no txid".to_string())
+    }
+
     pub fn poll_once(&self) -> Result<Vec<Message>, String> {
         self.execute_command("sync run")?;
         self.get_messages()
     }
+
+    pub async fn poll_once_async(&self) -> Result<Vec<Message>, String> {
+        self.execute_command_async("sync run").await?;
+        self.get_messages_async().await
+    }
+
+    pub async fn poll_with_retry_async(
+        &self,
+        attempts: u8,
+        delay: Duration,
+    ) -> Result<Vec<Message>, String> {
+        let mut last_error = None;
+        for attempt in 1..=attempts.max(1) {
+            match self.poll_once_async().await {
+                Ok(messages) => return Ok(messages),
+                Err(e) => {
+                    last_error = Some(e);
+                    if attempt < attempts.max(1) {
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| "Polling failed".to_string()))
+    }
+}
+
+impl WalletBackend for ZingoClient {
+    fn send_memo(
+        &self,
+        address: &str,
+        amount_zatoshis: u64,
+        memo: &str,
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        let output = ZingoClient::send_memo(self, address, amount_zatoshis, memo, max_fee)?;
+        Self::parse_txid(&output)
+    }
+
+    fn send_memos(
+        &self,
+        sends: &[(&str, u64, &str)],
+        max_fee: Option<u64>,
+    ) -> Result<String, String> {
+        let output = ZingoClient::send_memos(self, sends, max_fee)?;
+        Self::parse_txid(&output)
+    }
+
+    fn get_messages(&self) -> Result<Vec<Message>, String> {
+        ZingoClient::get_messages(self)
+    }
+
+    fn tx_status(&self, txid: &str) -> Result<TxStatus, String> {
+        self.get_tx_status(txid)
+    }
+
+    fn tx_statuses(&self, txids: &[String]) -> Result<Vec<TxStatus>, String> {
+        self.get_tx_statuses(txids)
+    }
+
+    fn sync(&self) -> Result<(), String> {
+        self.execute_command("sync run").map(|_| ())
+    }
+
+    fn height(&self) -> Result<u64, String> {
+        self.sync_height()
+    }
+
+    fn addresses(&self) -> Result<Vec<String>, String> {
+        self.get_addresses()
+    }
+
+    fn balance(&self) -> Result<u64, String> {
+        self.spendable_balance()
+    }
 }
 
 #[cfg(test)]
@@ -252,6 +694,95 @@ mod tests {
         assert_eq!(args[3], "ls /home");
     }
 
+    #[test]
+    fn test_send_memo_refuses_oversized_memos() {
+        let client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string());
+        assert_eq!(
+            client
+                .send_memo("zs1coord", 0, &"x".repeat(513), None)
+                .unwrap_err(),
+            "Memo too long: 513 bytes (max 512); split it with Message::split_for_memo"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_variants_share_validation_and_parsing() {
+        let client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string());
+        assert_eq!(
+            client
+                .send_memo_async("zs1coord", 0, &"x".repeat(513), None)
+                .await
+                .unwrap_err(),
+            client
+                .send_memo("zs1coord", 0, &"x".repeat(513), None)
+                .unwrap_err()
+        );
+        assert!(client
+            .execute_command_async("quicksend \"unterminated")
+            .await
+            .is_err());
+        assert_eq!(
+            ZingoClient::parse_addresses("[\"zs1a\", \"zs1b\"]"),
+            vec!["zs1a", "zs1b"]
+        );
+        assert_eq!(
+            ZingoClient::parse_addresses("zs1a\n\nzs1b\n"),
+            vec!["zs1a", "zs1b"]
+        );
+    }
+
+    #[test]
+    fn test_retries_skip_sends_and_the_breaker_fails_fast() {
+        let client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string())
+            .with_retry(RetryConfig {
+                breaker_threshold: 2,
+                ..RetryConfig::default()
+            });
+        let args = |command: &str| vec![command.to_string()];
+        assert_eq!(client.max_attempts(&args("height")), 3);
+        assert_eq!(client.max_attempts(&args("quicksend")), 1);
+        assert_eq!(client.max_attempts(&args("send")), 1);
+
+        let down: Result<(), ZingoError> = Err(ZingoError::Transient("timed out".to_string()));
+        client
+            .breaker()
+            .record(client.retry_config(), &down, now_secs());
+        client
+            .clone()
+            .breaker()
+            .record(client.retry_config(), &down, now_secs());
+        let error = client.execute("height").unwrap_err();
+        assert!(matches!(error, ZingoError::CircuitOpen { .. }));
+        assert!(client
+            .get_balance()
+            .unwrap_err()
+            .starts_with("Wallet server unavailable after repeated failures"));
+    }
+
+    #[test]
+    fn test_send_memos_builds_one_multi_recipient_quicksend() {
+        let args = ZingoClient::send_many_args(
+            &[("zs1alice", 0, "part \"1\""), ("zs1bob", 500, "hi")],
+            None,
+        )
+        .unwrap();
+        assert_eq!(args[0], "quicksend");
+        let outputs: serde_json::Value = serde_json::from_str(&args[1]).unwrap();
+        assert_eq!(
+            outputs,
+            serde_json::json!([
+                {"address": "zs1alice", "amount": 0, "memo": "part \"1\""},
+                {"address": "zs1bob", "amount": 500, "memo": "hi"}
+            ])
+        );
+        assert!(ZingoClient::send_many_args(&[], None).is_err());
+        assert!(
+            ZingoClient::send_many_args(&[("zs1a", 0, "a"), ("zs1b", 0, "b")], Some(1))
+                .unwrap_err()
+                .starts_with("Fee too high")
+        );
+    }
+
     #[test]
     fn test_zatoshi_conversion() {
         let _client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string());
@@ -313,7 +844,7 @@ mod tests {
         let messages = result.unwrap();
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0].memo_text, "ls /home");
-        assert_eq!(messages[0].sender_address, "client_abcdef12");
+        assert_eq!(messages[0].sender_address, crate::message::UNKNOWN_SENDER);
     }
 
     #[test]
@@ -340,7 +871,185 @@ mod tests {
 
         let messages = client.parse_messages(raw).unwrap();
         assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].sender_address, "client_abc");
+        assert_eq!(messages[0].sender_address, crate::message::UNKNOWN_SENDER);
+    }
+
+    #[test]
+    fn test_parse_messages_reads_transaction_metadata() {
+        let client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string());
+
+        let raw = r#"{
+            "transactions": [
+                {"txid": "feed0000", "value": 5000},
+                {
+                    "txid": "beef1234cafe",
+                    "datetime": 1700000000,
+                    "blockheight": 2947113,
+                    "value": 20000,
+                    "memos": ["", "rm /old.txt"]
+                },
+                {"memo": 42}
+            ]
+        }"#;
+
+        let messages = client.parse_messages(raw).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].memo_text, "rm /old.txt");
+        assert_eq!(messages[0].output_index, Some(1));
+        assert_eq!(messages[0].amount_zatoshis, 20000);
+        assert_eq!(messages[0].block_height, Some(2947113));
+        assert_eq!(messages[0].timestamp, Some(1700000000));
+    }
+
+    #[test]
+    fn test_parse_height() {
+        assert_eq!(
+            ZingoClient::parse_height("{\n  \"height\": 2947113\n}").unwrap(),
+            2947113
+        );
+        assert!(ZingoClient::parse_height("{\"error\": \"not synced\"}").is_err());
+        assert!(ZingoClient::parse_height("").is_err());
+    }
+
+    #[test]
+    fn test_parse_balance() {
+        let raw = "{\n  \"sapling_balance\": 90000,\n  \"spendable_sapling_balance\": 40000,\n  \"spendable_orchard_balance\": 25000,\n  \"transparent_balance\": 7\n}";
+        assert_eq!(
+            ZingoClient::parse_balance(raw).unwrap(),
+            WalletBalance {
+                shielded: 90000,
+                transparent: 7,
+                spendable: 65000,
+            }
+        );
+        let newer = "{\"total_orchard_balance\": 80000, \"spendable_orchard_balance\": 70000, \"confirmed_transparent_balance\": 3}";
+        assert_eq!(
+            ZingoClient::parse_balance(newer).unwrap(),
+            WalletBalance {
+                shielded: 80000,
+                transparent: 3,
+                spendable: 70000,
+            }
+        );
+        assert!(ZingoClient::parse_balance("{\"transparent_balance\": 7}").is_err());
+    }
+
+    #[test]
+    fn test_parse_txid() {
+        assert_eq!(
+            ZingoClient::parse_txid("{\n  \"txids\": [\n    \"9f3a\"\n  ]\n}").unwrap(),
+            "9f3a"
+        );
+        assert_eq!(ZingoClient::parse_txid("[\"77aa\"]").unwrap(), "77aa");
+        assert!(
+            ZingoClient::parse_txid("{\"error\": \"insufficient funds\"}")
+                .unwrap_err()
+                .contains("insufficient funds")
+        );
+        assert!(ZingoClient::parse_txid("ok").is_err());
+        assert_eq!(
+            ZingoClient::parse_txid("{\"txids\": []}").unwrap_err(),
+            "Send response has no txid"
+        );
+    }
+
+    #[test]
+    fn test_parse_messages_reads_fixtures_from_each_zingo_version() {
+        let client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string());
+        let summary = |raw: &str| -> Vec<(String, u64, Option<u64>, String)> {
+            client
+                .parse_messages(raw)
+                .unwrap()
+                .into_iter()
+                .map(|m| {
+                    let txid: String = m.txid.unwrap_or_default().chars().take(4).collect();
+                    (m.memo_text, m.amount_zatoshis, m.block_height, txid)
+                })
+                .collect()
+        };
+
+        let list = summary(include_str!("../tests/fixtures/zingo/list_0.x.json"));
+        assert_eq!(
+            list,
+            vec![
+                (
+                    "REGISTER:zs1reply".to_string(),
+                    10000,
+                    Some(2947001),
+                    "5d2a".to_string()
+                ),
+                ("ls /".to_string(), 0, Some(2947003), "7f4c".to_string()),
+            ]
+        );
+
+        let v1 = summary(include_str!("../tests/fixtures/zingo/messages_1.x.json"));
+        assert_eq!(
+            v1,
+            vec![(
+                "cat /rules.txt".to_string(),
+                5000,
+                Some(2947010),
+                "8a5d".to_string()
+            )]
+        );
+
+        let v2 = summary(include_str!("../tests/fixtures/zingo/messages_2.x.json"));
+        assert_eq!(
+            v2,
+            vec![
+                ("touch /a.txt".to_string(), 0, None, "bd80".to_string()),
+                (
+                    "rm /old.txt".to_string(),
+                    1000,
+                    Some(2947021),
+                    "ce91".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_tx_statuses_from_each_zingo_version() {
+        let statuses = |raw: &str, txids: &[&str], tip: u64| {
+            let txids: Vec<String> = txids.iter().map(|txid| txid.to_string()).collect();
+            let found: Vec<String> = ZingoClient::parse_tx_statuses(raw, &txids, tip)
+                .unwrap()
+                .iter()
+                .map(TxStatus::describe)
+                .collect();
+            found
+        };
+        let list = include_str!("../tests/fixtures/zingo/list_0.x.json");
+        assert_eq!(
+            statuses(
+                list,
+                &[
+                    "6e3b0b4c2d1f508899aa1122334455667788990011bbccddeeff001122334455",
+                    "00"
+                ],
+                2947010
+            ),
+            vec!["confirmed (9)", "unknown"]
+        );
+        let v1 = include_str!("../tests/fixtures/zingo/messages_1.x.json");
+        assert_eq!(
+            statuses(
+                v1,
+                &["9b6e3e7f5a4283bb2cdd4455667788990011223344eeff001122334455667788"],
+                2947012
+            ),
+            vec!["mined at 2947011"]
+        );
+        let v2 = include_str!("../tests/fixtures/zingo/messages_2.x.json");
+        assert_eq!(
+            statuses(
+                v2,
+                &["bd80509170c4a5dd4eff66778899001122334455660011223344556677889900"],
+                2947030
+            ),
+            vec!["pending"]
+        );
+        assert!(ZingoClient::parse_tx_statuses("not json", &[], 1).is_err());
     }
 
     #[test]
@@ -360,4 +1069,26 @@ mod tests {
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0].memo_text, "chat /lobby hi");
     }
+
+    #[test]
+    fn test_parse_messages_decodes_hex_memos_and_skips_garbage() {
+        let client = ZingoClient::new(PathBuf::from("/tmp/test"), "http://test:9067".to_string());
+        let mut padded = b"ls /docs".to_vec();
+        padded.resize(512, 0);
+
+        let raw = format!(
+            r#"{{
+            "value_transfers": [
+                {{"txid": "aaaa", "memos": ["{}", "0xf6", "0xff00", "cat /a.txt\u0000\u0000"]}},
+                {{"txid": "bbbb", "memo": "0x6c73c3"}}
+            ]
+        }}"#,
+            crate::crypto::to_hex(&padded)
+        );
+
+        let messages = client.parse_messages(&raw).unwrap();
+        let memos: Vec<&str> = messages.iter().map(|m| m.memo_text.as_str()).collect();
+        assert_eq!(memos, vec!["ls /docs", "cat /a.txt"]);
+        assert_eq!(messages[1].output_index, Some(3));
+    }
 }
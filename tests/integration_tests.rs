@@ -1,8 +1,41 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use zatboard::commands::{CommandHandler, CommandSpec, MessageInterceptor};
 use zatboard::coordinator::Coordinator;
+use zatboard::identity::IdentityKey;
 use zatboard::message::Message;
+use zatboard::signing;
 use zatboard::zingo_wrapper::ZingoClient;
 
+fn sign_in(coordinator: &mut Coordinator, dir: &std::path::Path) -> IdentityKey {
+    let identity = IdentityKey::load_or_create(dir.join("identity.key")).unwrap();
+    let register = Message::new(
+        "zs1sender123".to_string(),
+        "zs1coordinator456".to_string(),
+        format!("REGISTER:zs1reply123 {}", identity.public_key_hex()),
+    );
+    let response = coordinator.process_incoming_message(&register).unwrap();
+    let challenge = response
+        .split("AUTH_CHALLENGE:")
+        .nth(1)
+        .unwrap()
+        .split(' ')
+        .next()
+        .unwrap()
+        .to_string();
+    let auth = Message::new(
+        "zs1sender123".to_string(),
+        "zs1coordinator456".to_string(),
+        format!(
+            "AUTH:{} {}",
+            challenge,
+            identity.sign_auth("zs1reply123", &challenge)
+        ),
+    );
+    coordinator.process_incoming_message(&auth).unwrap();
+    identity
+}
+
 #[test]
 fn test_full_memo_workflow() {
     let _client = ZingoClient::new(
@@ -69,11 +102,12 @@ fn test_registration_and_authentication_flow() {
         temp_dir.path().to_path_buf(),
         "https://example.com:9067".to_string(),
     );
+    let identity = IdentityKey::load_or_create(temp_dir.path().join("identity.key")).unwrap();
 
     let register = Message::new(
         "zs1sender123".to_string(),
         "zs1coordinator456".to_string(),
-        "REGISTER:zs1reply123".to_string(),
+        format!("REGISTER:zs1reply123 {}", identity.public_key_hex()),
     );
     let register_response = coordinator.process_incoming_message(&register).unwrap();
     assert!(register_response.contains("Registration successful!"));
@@ -88,12 +122,15 @@ fn test_registration_and_authentication_flow() {
         .unwrap()
         .to_string();
 
-    let mut auth = Message::new(
+    let auth = Message::new(
         "zs1sender123".to_string(),
         "zs1coordinator456".to_string(),
-        format!("AUTH:{}", challenge),
+        format!(
+            "AUTH:{} {}",
+            challenge,
+            identity.sign_auth("zs1reply123", &challenge)
+        ),
     );
-    auth.signature = Some("sig".to_string());
 
     let auth_response = coordinator.process_incoming_message(&auth).unwrap();
     assert!(auth_response.contains("Authentication successful"));
@@ -108,6 +145,7 @@ fn test_conversation_id_command_flow() {
         "https://example.com:9067".to_string(),
     );
 
+    let identity = sign_in(&mut coordinator, temp_dir.path());
     let register = Message::new(
         "zs1sender123".to_string(),
         "zs1coordinator456".to_string(),
@@ -132,11 +170,155 @@ fn test_conversation_id_command_flow() {
         .unwrap()
         .to_string();
 
-    let command = Message::new(
+    let unsigned = Message::new(
         "zs1sender123".to_string(),
         "zs1coordinator456".to_string(),
         format!("{}:{}:ls /", conv_id, part_id),
     );
+    assert!(coordinator.process_incoming_message(&unsigned).is_err());
+
+    let command = Message::new(
+        "zs1sender123".to_string(),
+        "zs1coordinator456".to_string(),
+        identity.sign_command("zs1reply123", &format!("{}:{}:ls /", conv_id, part_id)),
+    );
     let response = coordinator.process_incoming_message(&command).unwrap();
     assert!(response.contains("(empty directory)"));
 }
+
+#[test]
+fn test_chat_room_flow() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut coordinator = Coordinator::new(
+        3600,
+        temp_dir.path().to_path_buf(),
+        "https://example.com:9067".to_string(),
+    );
+
+    let identity = sign_in(&mut coordinator, temp_dir.path());
+
+    let mut forged = Message::new(
+        "zs1sender123".to_string(),
+        "zs1coordinator456".to_string(),
+        "chat create general".to_string(),
+    );
+    forged.signature = Some("sig".to_string());
+    assert!(coordinator
+        .process_incoming_message(&forged)
+        .unwrap_err()
+        .starts_with("Authentication required"));
+
+    for (command, expected) in [
+        ("chat create general", "Chat room created: general"),
+        ("chat post general Hello everyone!", "Posted #1 to general"),
+    ] {
+        let message = Message::new(
+            "zs1sender123".to_string(),
+            "zs1coordinator456".to_string(),
+            identity.sign_command("zs1reply123", command),
+        );
+        let response = coordinator.process_incoming_message(&message).unwrap();
+        assert_eq!(response, expected);
+    }
+
+    let read = Message::new(
+        "zs1sender123".to_string(),
+        "zs1coordinator456".to_string(),
+        identity.sign_command("zs1reply123", "chat read general"),
+    );
+    let history = coordinator.process_incoming_message(&read).unwrap();
+    assert!(history.contains("Hello everyone!"));
+}
+
+struct DiceCommand {
+    spec: CommandSpec,
+}
+
+impl CommandHandler for DiceCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn execute(
+        &self,
+        _coordinator: &mut Coordinator,
+        user_id: &str,
+        args: &str,
+    ) -> Result<String, String> {
+        let sides: u32 = args
+            .trim()
+            .parse()
+            .map_err(|_| "Use: roll <sides>".to_string())?;
+        Ok(format!("{} rolled {}", user_id, sides))
+    }
+}
+
+struct ShoutFilter;
+
+impl MessageInterceptor for ShoutFilter {
+    fn name(&self) -> &str {
+        "shout-filter"
+    }
+
+    fn intercept(
+        &self,
+        _coordinator: &mut Coordinator,
+        message: &Message,
+    ) -> Option<Result<String, String>> {
+        let (command, _) = signing::split_signature(&message.memo_text);
+        if command.chars().any(|c| c.is_lowercase()) {
+            None
+        } else {
+            Some(Err("Please don't shout".to_string()))
+        }
+    }
+}
+
+#[test]
+fn test_extension_commands_and_interceptors() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut coordinator = Coordinator::new(
+        3600,
+        temp_dir.path().to_path_buf(),
+        "https://example.com:9067".to_string(),
+    );
+    coordinator
+        .register_command(Arc::new(DiceCommand {
+            spec: CommandSpec::new("roll", "<sides>", "Roll a die"),
+        }))
+        .unwrap();
+    coordinator.add_interceptor(Arc::new(ShoutFilter));
+    assert!(coordinator
+        .register_command(Arc::new(DiceCommand {
+            spec: CommandSpec::new("ls", "", "Shadow ls"),
+        }))
+        .is_err());
+
+    let identity = sign_in(&mut coordinator, temp_dir.path());
+    let command = |memo: &str| {
+        Message::new(
+            "zs1sender123".to_string(),
+            "zs1coordinator456".to_string(),
+            identity.sign_command("zs1reply123", memo),
+        )
+    };
+
+    assert_eq!(
+        coordinator
+            .process_incoming_message(&command("roll 6"))
+            .unwrap(),
+        "zs1sender123 rolled 6"
+    );
+
+    assert!(coordinator
+        .process_incoming_message(&command("help"))
+        .unwrap()
+        .contains("roll"));
+
+    assert_eq!(
+        coordinator
+            .process_incoming_message(&command("LS /"))
+            .unwrap_err(),
+        "Please don't shout"
+    );
+}